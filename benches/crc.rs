@@ -0,0 +1,34 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use pngmi::chunk::Chunk;
+use std::hint::black_box;
+
+/// The table-driven CRC this crate used before switching to crc32fast, kept as a baseline
+fn table_crc(chunk_type: &[u8; 4], data: &[u8]) -> u32 {
+    let crc = crc::Crc::<u32>::new(&crc::CRC_32_ISO_HDLC);
+    let mut digest = crc.digest();
+    digest.update(chunk_type);
+    digest.update(data);
+    digest.finalize()
+}
+
+fn bench_crc(c: &mut Criterion) {
+    let mut group = c.benchmark_group("crc");
+    for size in [1 << 10, 1 << 16, 1 << 22] {
+        let data: Vec<u8> = (0..size).map(|i| (i * 31 % 251) as u8).collect();
+        assert_eq!(
+            table_crc(b"IDAT", &data),
+            Chunk::compute_crc(b"IDAT", &data)
+        );
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_with_input(BenchmarkId::new("table", size), &data, |b, data| {
+            b.iter(|| table_crc(black_box(b"IDAT"), black_box(data)))
+        });
+        group.bench_with_input(BenchmarkId::new("crc32fast", size), &data, |b, data| {
+            b.iter(|| Chunk::compute_crc(black_box(b"IDAT"), black_box(data)))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_crc);
+criterion_main!(benches);
@@ -0,0 +1,36 @@
+use std::error::Error;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    #[cfg(feature = "ffi")]
+    generate_header()?;
+    Ok(())
+}
+
+/// Write the C header for the `ffi` module to OUT_DIR, where `ffi`'s tests check that the copy
+/// checked in as include/pngmi.h hasn't drifted from it
+#[cfg(feature = "ffi")]
+fn generate_header() -> Result<(), Box<dyn Error>> {
+    use std::env;
+    use std::path::Path;
+
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=src/ffi.rs");
+    let crate_dir = env::var("CARGO_MANIFEST_DIR")?;
+    let config = cbindgen::Config {
+        language: cbindgen::Language::C,
+        include_guard: Some("PNGMI_H".to_string()),
+        autogen_warning: Some(
+            "/* Generated by build.rs from src/ffi.rs; don't edit by hand */".to_string(),
+        ),
+        cpp_compat: true,
+        usize_is_size_t: true,
+        ..Default::default()
+    };
+    let header = cbindgen::Builder::new()
+        // Only the FFI module, rather than every public item in the crate
+        .with_src(Path::new(&crate_dir).join("src").join("ffi.rs"))
+        .with_config(config)
+        .generate()?;
+    header.write_to_file(Path::new(&env::var("OUT_DIR")?).join("pngmi.h"));
+    Ok(())
+}
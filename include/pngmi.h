@@ -0,0 +1,137 @@
+#ifndef PNGMI_H
+#define PNGMI_H
+
+/* Generated by build.rs from src/ffi.rs; don't edit by hand */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * A PNG parsed from a buffer, owned by the caller until passed to `pngmi_png_free`
+ */
+typedef struct PngmiPng PngmiPng;
+
+/**
+ * A chunk of a `PngmiPng`, borrowing its data from it
+ */
+typedef struct PngmiChunk {
+  /**
+   * The chunk type, e.g. `IHDR`, NUL-terminated
+   */
+  char chunk_type[5];
+  const uint8_t *data;
+  size_t len;
+  uint32_t crc;
+} PngmiChunk;
+
+/**
+ * Bytes allocated by pngmi, which the caller frees with `pngmi_buffer_free`
+ */
+typedef struct PngmiBuffer {
+  uint8_t *data;
+  size_t len;
+} PngmiBuffer;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * The message of the last error on this thread, or null if the last call succeeded. The
+ * string stays valid until the next call into pngmi on the same thread.
+ */
+const char *pngmi_last_error(void);
+
+/**
+ * Parse a PNG from `len` bytes at `data`, which are copied. Returns null on failure.
+ *
+ * # Safety
+ *
+ * `data` must point to `len` readable bytes.
+ */
+struct PngmiPng *pngmi_png_open(const uint8_t *data, size_t len);
+
+/**
+ * Free a PNG opened by `pngmi_png_open`; null is ignored
+ *
+ * # Safety
+ *
+ * `png` must be null or a handle from `pngmi_png_open` which hasn't been freed.
+ */
+void pngmi_png_free(struct PngmiPng *png);
+
+/**
+ * The number of chunks in the PNG, or 0 for a null handle
+ *
+ * # Safety
+ *
+ * `png` must be null or a live handle from `pngmi_png_open`.
+ */
+size_t pngmi_png_chunk_count(const struct PngmiPng *png);
+
+/**
+ * Describe the chunk at `index` in `out`. Its data stays valid until the PNG is changed or
+ * freed. Returns 0, or -1 on failure.
+ *
+ * # Safety
+ *
+ * `png` must be a live handle from `pngmi_png_open` and `out` must be writable.
+ */
+int pngmi_png_chunk(const struct PngmiPng *png, size_t index, struct PngmiChunk *out);
+
+/**
+ * Encode `len` bytes at `data` into new chunks of type `chunk_type` before IEND, encrypted
+ * if `passphrase` isn't null. Returns the number of chunks added, or -1 on failure.
+ *
+ * # Safety
+ *
+ * `png` must be a live handle from `pngmi_png_open`, `chunk_type` and `passphrase` null or
+ * NUL-terminated strings and `data` must point to `len` readable bytes.
+ */
+int pngmi_png_encode(struct PngmiPng *png,
+                     const char *chunk_type,
+                     const uint8_t *data,
+                     size_t len,
+                     const char *passphrase);
+
+/**
+ * Decode the payload in chunks of type `chunk_type` into `out`, decrypting it if
+ * `passphrase` isn't null. Returns 0, or -1 on failure.
+ *
+ * # Safety
+ *
+ * `png` must be a live handle from `pngmi_png_open`, `chunk_type` and `passphrase` null or
+ * NUL-terminated strings and `out` must be writable.
+ */
+int pngmi_png_decode(const struct PngmiPng *png,
+                     const char *chunk_type,
+                     const char *passphrase,
+                     struct PngmiBuffer *out);
+
+/**
+ * Write the bytes of the PNG, with any changes made to it, into `out`. Returns 0, or -1 on
+ * failure.
+ *
+ * # Safety
+ *
+ * `png` must be a live handle from `pngmi_png_open` and `out` must be writable.
+ */
+int pngmi_png_to_bytes(const struct PngmiPng *png, struct PngmiBuffer *out);
+
+/**
+ * Free the bytes of a buffer filled by pngmi, leaving it empty
+ *
+ * # Safety
+ *
+ * `buffer` must be null or a buffer filled by pngmi which hasn't been freed.
+ */
+void pngmi_buffer_free(struct PngmiBuffer *buffer);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* PNGMI_H */
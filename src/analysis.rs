@@ -0,0 +1,400 @@
+use crate::decoder;
+use crate::ihdr::{ColorType, Ihdr};
+use crate::lsb;
+use crate::png::PngRef;
+use crate::scan::{self, Finding, FindingKind, ScanOptions};
+use crate::{PngError, PngResult};
+use std::fmt::{Display, Formatter};
+
+/// The chi-square test is run on the first tenth of the samples, the first two tenths and so
+/// on, as payloads embedded from the start of the image only even out the histogram there
+const CHI_SQUARE_STEPS: usize = 10;
+
+/// Pairs of values seen fewer times than this are left out of the chi-square test, which
+/// isn't reliable for rare values
+const MIN_EXPECTED_COUNT: f64 = 5.0;
+
+/// How much each kind of chunk-level finding adds to the risk, as a probability
+fn finding_weight(kind: FindingKind) -> f64 {
+    match kind {
+        FindingKind::NonStandardChunk => 0.3,
+        FindingKind::LargeTextChunk => 0.2,
+        FindingKind::TrailingData => 0.4,
+        FindingKind::HighEntropyChunk => 0.3,
+        FindingKind::ToolSignature => 0.8,
+        FindingKind::EmbeddedFile => 0.5,
+    }
+}
+
+/// Statistical tests on the low bits of the pixels, which LSB embedding disturbs
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PixelAnalysis {
+    /// The chi-square probability, from 0 to 1, that the low bits of the samples were
+    /// replaced with random data. Embedding evens out the counts of each pair of values which
+    /// differ only in their low bit, which natural images rarely do.
+    pub chi_square: f64,
+    /// The share of samples, from 0 to 1, whose low bits sample pair analysis estimates were
+    /// used for a payload
+    pub sample_pairs: f64,
+}
+
+impl PixelAnalysis {
+    /// How strongly the pixels suggest LSB embedding, from 0 to 1. The chi-square test
+    /// flags some clean images with smooth regions, so it only counts as far as sample pair
+    /// analysis backs it up.
+    pub fn evidence(self) -> f64 {
+        self.sample_pairs
+            .max(self.chi_square.min(self.sample_pairs * 10.0))
+    }
+}
+
+/// How detectable a hidden payload would be in a PNG, or how likely it is to hold one
+#[derive(Debug, Clone)]
+pub struct Analysis {
+    /// The pixel statistics, unless the image's samples can't be analysed
+    pub pixels: Option<PixelAnalysis>,
+    /// Why the pixels weren't analysed
+    pub pixels_skipped: Option<String>,
+    /// Suspicious chunks and trailing data
+    pub findings: Vec<Finding>,
+}
+
+/// How worried to be about a file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RiskLevel {
+    Low,
+    Medium,
+    High,
+}
+
+impl Display for RiskLevel {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RiskLevel::Low => write!(f, "low"),
+            RiskLevel::Medium => write!(f, "medium"),
+            RiskLevel::High => write!(f, "high"),
+        }
+    }
+}
+
+impl Analysis {
+    /// The likelihood, from 0 to 100, that the file carries a hidden payload: each piece of
+    /// evidence is taken as an independent chance of one
+    pub fn risk_score(&self) -> u8 {
+        let pixel_evidence = self.pixels.map(PixelAnalysis::evidence).unwrap_or_default();
+        let clean = self
+            .findings
+            .iter()
+            .map(|finding| 1.0 - finding_weight(finding.kind))
+            .product::<f64>()
+            * (1.0 - pixel_evidence);
+        ((1.0 - clean) * 100.0).round().clamp(0.0, 100.0) as u8
+    }
+
+    pub fn risk_level(&self) -> RiskLevel {
+        match self.risk_score() {
+            0..25 => RiskLevel::Low,
+            25..60 => RiskLevel::Medium,
+            _ => RiskLevel::High,
+        }
+    }
+}
+
+/// The natural log of the gamma function, by the Lanczos approximation
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 6] = [
+        76.180_091_729_471_46,
+        -86.505_320_329_416_77,
+        24.014_098_240_830_91,
+        -1.231_739_572_450_155,
+        0.001_208_650_973_866_179,
+        -0.000_005_395_239_384_953,
+    ];
+    let tmp = x + 5.5 - (x + 0.5) * (x + 5.5).ln();
+    let series = COEFFICIENTS
+        .iter()
+        .enumerate()
+        .fold(1.000_000_000_190_015, |sum, (i, c)| {
+            sum + c / (x + 1.0 + i as f64)
+        });
+    -tmp + (2.506_628_274_631_000_5 * series / x).ln()
+}
+
+/// The regularized upper incomplete gamma function Q(a, x), by its series below a + 1 and
+/// its continued fraction above
+fn gamma_q(a: f64, x: f64) -> f64 {
+    const EPSILON: f64 = 1e-12;
+    const MAX_ITERATIONS: usize = 1000;
+    if x <= 0.0 {
+        return 1.0;
+    }
+    let prefix = (-x + a * x.ln() - ln_gamma(a)).exp();
+    if x < a + 1.0 {
+        let (mut term, mut sum) = (1.0 / a, 1.0 / a);
+        for n in 1..MAX_ITERATIONS {
+            term *= x / (a + n as f64);
+            sum += term;
+            if term.abs() < sum.abs() * EPSILON {
+                break;
+            }
+        }
+        return 1.0 - sum * prefix;
+    }
+    // Lentz's method
+    let tiny = f64::MIN_POSITIVE / EPSILON;
+    let mut b = x + 1.0 - a;
+    let mut c = 1.0 / tiny;
+    let mut d = 1.0 / b;
+    let mut h = d;
+    for n in 1..MAX_ITERATIONS {
+        let an = -(n as f64) * (n as f64 - a);
+        b += 2.0;
+        d = an * d + b;
+        d = if d.abs() < tiny { tiny } else { d };
+        c = b + an / c;
+        c = if c.abs() < tiny { tiny } else { c };
+        d = 1.0 / d;
+        let delta = d * c;
+        h *= delta;
+        if (delta - 1.0).abs() < EPSILON {
+            break;
+        }
+    }
+    prefix * h
+}
+
+/// The probability that the counts of each pair of values 2k and 2k + 1 are as even as
+/// random low bits would make them
+pub fn chi_square(samples: &[u8]) -> f64 {
+    let mut counts = [0u64; 256];
+    for &sample in samples {
+        counts[sample as usize] += 1;
+    }
+    let mut statistic = 0.0;
+    let mut categories = 0;
+    for pair in counts.chunks(2) {
+        let expected = (pair[0] + pair[1]) as f64 / 2.0;
+        if expected < MIN_EXPECTED_COUNT {
+            continue;
+        }
+        statistic += (pair[0] as f64 - expected).powi(2) / expected;
+        categories += 1;
+    }
+    if categories < 2 {
+        return 0.0;
+    }
+    gamma_q((categories - 1) as f64 / 2.0, statistic / 2.0)
+}
+
+/// The highest chi-square probability over growing prefixes of the samples
+fn chi_square_prefixes(samples: &[u8]) -> f64 {
+    (1..=CHI_SQUARE_STEPS)
+        .map(|step| chi_square(&samples[..samples.len() * step / CHI_SQUARE_STEPS]))
+        .fold(0.0, f64::max)
+}
+
+/// Sample pair analysis (Dumitrescu, Wu and Wang): estimate the share of samples whose low
+/// bit was flipped from how pairs of neighbouring samples are spread across the sets that LSB
+/// embedding moves them between
+pub fn sample_pairs(pairs: impl Iterator<Item = (u8, u8)>) -> f64 {
+    let (mut x, mut y, mut z, mut w, mut total) = (0f64, 0f64, 0f64, 0f64, 0f64);
+    for (u, v) in pairs {
+        total += 1.0;
+        if u == v {
+            z += 1.0;
+            continue;
+        }
+        // X holds the pairs with v even and u < v or v odd and u > v, Y the rest. W, the
+        // pairs differing only in the low bit, all fall in Y.
+        if (v % 2 == 0) == (u < v) {
+            x += 1.0;
+        } else {
+            y += 1.0;
+            if u >> 1 == v >> 1 {
+                w += 1.0;
+            }
+        }
+    }
+    // The share is the smaller root of (W + Z) p^2 / 2 + (2X - P) p + Y - X = 0
+    let a = (w + z) / 2.0;
+    let b = 2.0 * x - total;
+    let c = y - x;
+    let estimate = if a == 0.0 {
+        if b == 0.0 {
+            0.0
+        } else {
+            -c / b
+        }
+    } else {
+        // Near full embedding the roots come together, and sampling noise can leave none at
+        // all, in which case the vertex is the closest there is
+        let discriminant = (b * b - 4.0 * a * c).max(0.0);
+        (-b - discriminant.sqrt()) / (2.0 * a)
+    };
+    if estimate.is_finite() {
+        estimate.clamp(0.0, 1.0)
+    } else {
+        0.0
+    }
+}
+
+/// Run the pixel tests, or say why they can't be run on this image
+fn analyse_pixels(png: &PngRef) -> Result<PixelAnalysis, String> {
+    let png = png.to_png();
+    let undecodable = |e: PngError| format!("the image couldn't be decoded: {}", e);
+    let ihdr = Ihdr::try_from(&png).map_err(undecodable)?;
+    if ihdr.color_type == ColorType::Indexed || ihdr.bit_depth != 8 {
+        return Err(format!(
+            "pixel tests need 8-bit greyscale or truecolour samples, not {}-bit {}",
+            ihdr.bit_depth, ihdr.color_type
+        ));
+    }
+    let pixels = decoder::decode_png(&png).map_err(undecodable)?;
+    let data = pixels.data();
+    let samples: Vec<u8> = lsb::sample_offsets(&pixels).map(|i| data[i]).collect();
+
+    // Horizontal neighbours within each colour channel
+    let channels = pixels.color_type.channels() as usize;
+    let colour_channels = lsb::colour_channels(pixels.color_type);
+    let width = pixels.width;
+    let pairs = pixels.rows().flat_map(|row| {
+        (0..colour_channels).flat_map(move |channel| {
+            (1..width).map(move |x| {
+                (
+                    row[(x - 1) * channels + channel],
+                    row[x * channels + channel],
+                )
+            })
+        })
+    });
+    Ok(PixelAnalysis {
+        chi_square: chi_square_prefixes(&samples),
+        sample_pairs: sample_pairs(pairs),
+    })
+}
+
+/// Look for signs of a hidden payload: statistical traces of LSB embedding in the pixels and
+/// suspicious chunks or trailing data. The chunks are still checked when the pixels can't be
+/// decoded.
+pub fn analyse(png: &PngRef) -> PngResult<Analysis> {
+    let (pixels, pixels_skipped) = match analyse_pixels(png) {
+        Ok(pixels) => (Some(pixels), None),
+        Err(reason) => (None, Some(reason)),
+    };
+    Ok(Analysis {
+        pixels,
+        pixels_skipped,
+        findings: scan::scan(png, ScanOptions::default()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::png::Png;
+    use crate::test_util::{chunk, ihdr};
+
+    /// A wandering signal like a row of a photograph, whose values happen to all be even
+    fn natural_samples(len: usize) -> Vec<u8> {
+        let mut seed = 0x2545_f491_u32;
+        let mut value = 128i32;
+        (0..len)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                value = (value + ((seed >> 16) % 5) as i32 * 2 - 4).clamp(10, 244);
+                value as u8
+            })
+            .collect()
+    }
+
+    /// Replace the low bit of every sample with a pseudo-random one
+    fn embed(samples: &[u8]) -> Vec<u8> {
+        let mut seed = 0x9e37_79b9_u32;
+        samples
+            .iter()
+            .map(|sample| {
+                seed ^= seed << 13;
+                seed ^= seed >> 17;
+                seed ^= seed << 5;
+                sample & !1 | (seed & 1) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_gamma_q() {
+        // Q(1, x) = e^-x, and with 2 degrees of freedom the chi-square tail is e^(-x/2)
+        for x in [0.1, 1.0, 5.0, 20.0] {
+            assert!((gamma_q(1.0, x) - (-x).exp()).abs() < 1e-9);
+        }
+        assert!((gamma_q(0.5, 0.0) - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_chi_square() {
+        let clean = natural_samples(20_000);
+        let embedded = embed(&clean);
+        assert!(chi_square(&clean) < 0.05);
+        assert!(chi_square(&embedded) > 0.5);
+        assert_eq!(chi_square(&[]), 0.0);
+    }
+
+    #[test]
+    fn test_sample_pairs() {
+        let pairs = |samples: &[u8]| -> Vec<(u8, u8)> {
+            samples.windows(2).map(|pair| (pair[0], pair[1])).collect()
+        };
+        let clean = natural_samples(20_000);
+        let embedded = embed(&clean);
+        assert!(sample_pairs(pairs(&clean).into_iter()) < 0.1);
+        assert!(sample_pairs(pairs(&embedded).into_iter()) > 0.8);
+        assert_eq!(sample_pairs(std::iter::empty()), 0.0);
+    }
+
+    #[test]
+    fn test_risk_score() {
+        let analysis = |chi_square, sample_pairs, findings: Vec<FindingKind>| Analysis {
+            pixels: Some(PixelAnalysis {
+                chi_square,
+                sample_pairs,
+            }),
+            pixels_skipped: None,
+            findings: findings
+                .into_iter()
+                .map(|kind| Finding {
+                    kind,
+                    chunk_type: None,
+                    chunk_index: None,
+                    size: 0,
+                    entropy: 0.0,
+                    signature: None,
+                    embedded: None,
+                })
+                .collect(),
+        };
+        assert_eq!(analysis(0.0, 0.0, vec![]).risk_score(), 0);
+        assert_eq!(analysis(0.97, 0.01, vec![]).risk_level(), RiskLevel::Low);
+        assert_eq!(analysis(1.0, 0.3, vec![]).risk_level(), RiskLevel::High);
+        let trailing = analysis(0.0, 0.0, vec![FindingKind::TrailingData]);
+        assert_eq!(trailing.risk_score(), 40);
+        assert_eq!(trailing.risk_level(), RiskLevel::Medium);
+    }
+
+    #[test]
+    fn test_undecodable_pixels() {
+        let ihdr = ihdr(8, ColorType::Truecolor);
+        let mut png = Png::from_chunks(vec![
+            chunk("IHDR", &ihdr.as_bytes()),
+            chunk("IDAT", b"not zlib"),
+            chunk("IEND", &[]),
+        ]);
+        png.set_trailing_data(vec![0x5a; 64]);
+        let analysis = analyse(&png.borrowed()).unwrap();
+        assert!(analysis.pixels.is_none());
+        assert!(analysis
+            .pixels_skipped
+            .unwrap()
+            .starts_with("the image couldn't be decoded"));
+        assert_eq!(analysis.findings[0].kind, FindingKind::TrailingData);
+    }
+}
@@ -0,0 +1,212 @@
+use crate::chunk::Chunk;
+use crate::exif::EXIF_CHUNK_TYPE;
+use crate::iccp::IccpChunk;
+use crate::png::Png;
+use crate::strip::{self, StripOptions};
+use crate::PngResult;
+
+/// Standard chunk types which routinely carry identifying data: EXIF, text and timestamps
+pub const IDENTIFYING_CHUNK_TYPES: &[&str] = &["eXIf", "tEXt", "zTXt", "iTXt", "tIME"];
+
+/// The profile name written in place of the original, which often names the author or device
+const ANONYMOUS_ICC_PROFILE_NAME: &str = "ICC profile";
+
+/// Where the profile creator signature and profile ID sit in an ICC profile header
+const ICC_CREATOR_RANGE: std::ops::Range<usize> = 80..84;
+const ICC_PROFILE_ID_RANGE: std::ops::Range<usize> = 84..100;
+
+/// Describe a removed chunk, showing the text keyword or timestamp where there is one
+fn describe(chunk: &Chunk) -> String {
+    let chunk_type = chunk.chunk_type().to_string();
+    let data = chunk.data();
+    match chunk_type.as_str() {
+        "tEXt" | "zTXt" | "iTXt" => {
+            let keyword = data.split(|b| *b == 0).next().unwrap_or_default();
+            format!(
+                "removed {} chunk \"{}\" ({} bytes)",
+                chunk_type,
+                String::from_utf8_lossy(keyword),
+                data.len()
+            )
+        }
+        "tIME" if data.len() == 7 => format!(
+            "removed tIME chunk {:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            u16::from_be_bytes([data[0], data[1]]),
+            data[2],
+            data[3],
+            data[4],
+            data[5],
+            data[6]
+        ),
+        "eXIf" => format!("removed eXIf chunk with EXIF data ({} bytes)", data.len()),
+        _ => format!("removed {} chunk ({} bytes)", chunk_type, data.len()),
+    }
+}
+
+/// Replace the name of an iCCP profile and clear the creator and ID fields of the profile
+/// itself, leaving the colour data alone. Returns a description of what was scrubbed, if any.
+fn scrub_iccp(chunk: &mut Chunk) -> PngResult<Option<String>> {
+    let mut iccp = IccpChunk::try_from(&*chunk)?;
+    let mut scrubbed = vec![];
+    if iccp.name() != ANONYMOUS_ICC_PROFILE_NAME {
+        scrubbed.push(format!("profile name \"{}\"", iccp.name()));
+        iccp.set_name(ANONYMOUS_ICC_PROFILE_NAME)?;
+    }
+    let profile = iccp.profile_mut();
+    if profile.len() >= ICC_PROFILE_ID_RANGE.end {
+        for (range, field) in [
+            (ICC_CREATOR_RANGE, "creator"),
+            (ICC_PROFILE_ID_RANGE, "profile ID"),
+        ] {
+            if profile[range.clone()].iter().any(|b| *b != 0) {
+                profile[range].fill(0);
+                scrubbed.push(field.to_string());
+            }
+        }
+    }
+    if scrubbed.is_empty() {
+        return Ok(None);
+    }
+
+    *chunk = iccp.to_chunk()?;
+    Ok(Some(format!("scrubbed iCCP {}", scrubbed.join(", "))))
+}
+
+/// Where a chunk belongs in the canonical order. Chunks of equal rank keep their relative
+/// order, so IDAT runs and APNG frame sequences are left intact.
+fn order_rank(chunk: &Chunk) -> u8 {
+    match chunk.chunk_type().to_string().as_str() {
+        "IHDR" => 0,
+        "cHRM" | "cICP" | "gAMA" | "iCCP" | "mDCV" | "cLLI" | "sBIT" | "sRGB" => 1,
+        "PLTE" => 2,
+        "IDAT" | "fcTL" | "fdAT" => 4,
+        "IEND" => 5,
+        _ => 3,
+    }
+}
+
+/// Tunables for `anonymize`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AnonymizeOptions {
+    /// Keep EXIF data, removing only its GPS tags
+    pub keep_exif: bool,
+}
+
+/// Scrub identifying data from the PNG: EXIF, text and timestamp chunks, non-standard
+/// ancillary chunks, data after IEND and ICC profile author details. The remaining chunks
+/// are put in a canonical order so the layout doesn't fingerprint the software which wrote
+/// the file. Returns a description of everything removed or changed.
+pub fn anonymize(png: &mut Png, options: AnonymizeOptions) -> PngResult<Vec<String>> {
+    let mut remove: Vec<String> = IDENTIFYING_CHUNK_TYPES
+        .iter()
+        .filter(|chunk_type| !(options.keep_exif && **chunk_type == EXIF_CHUNK_TYPE))
+        .map(|chunk_type| chunk_type.to_string())
+        .collect();
+    for chunk in png.chunks() {
+        let chunk_type = chunk.chunk_type().to_string();
+        if !chunk.chunk_type().is_critical()
+            && !chunk.chunk_type().is_standard()
+            && !remove.contains(&chunk_type)
+        {
+            remove.push(chunk_type);
+        }
+    }
+    let strip_options = StripOptions {
+        remove,
+        ..Default::default()
+    };
+    let report = strip::strip(png, &strip_options)?;
+    let mut changes: Vec<String> = report.removed.iter().map(describe).collect();
+    if report.trailing_data_len > 0 {
+        changes.push(format!(
+            "removed {} bytes of data after IEND",
+            report.trailing_data_len
+        ));
+    }
+
+    if options.keep_exif && strip::strip_gps(png)? {
+        changes.push("removed GPS tags from EXIF data".to_string());
+    }
+
+    for chunk in png.chunks_mut() {
+        if chunk.chunk_type().to_string() == IccpChunk::CHUNK_TYPE {
+            changes.extend(scrub_iccp(chunk)?);
+        }
+    }
+
+    let chunks = png.chunks_mut();
+    if !chunks.is_sorted_by_key(order_rank) {
+        chunks.sort_by_key(order_rank);
+        changes.push("normalized chunk order".to_string());
+    }
+    Ok(changes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compression::Compression;
+    use crate::test_util::chunk;
+
+    fn iccp(name: &str, profile: &[u8]) -> Chunk {
+        let mut data = name.as_bytes().to_vec();
+        data.extend_from_slice(&[0, 0]);
+        data.extend(Compression::Zlib.compress(profile, None).unwrap());
+        chunk("iCCP", &data)
+    }
+
+    #[test]
+    fn test_anonymize() {
+        let mut png = Png::from_chunks(vec![
+            chunk("IHDR", &[0; 13]),
+            chunk("tEXt", b"Author\0Jane"),
+            chunk("IDAT", b"pixels"),
+            chunk("tIME", &[7, 232, 1, 2, 3, 4, 5]),
+            chunk("gAMA", &[0, 0, 177, 143]),
+            chunk("prVt", b"device serial"),
+            chunk("IEND", &[]),
+        ]);
+        let changes = anonymize(&mut png, AnonymizeOptions::default()).unwrap();
+        assert_eq!(
+            changes,
+            vec![
+                "removed tEXt chunk \"Author\" (11 bytes)",
+                "removed tIME chunk 2024-01-02 03:04:05",
+                "removed prVt chunk (13 bytes)",
+                "normalized chunk order",
+            ]
+        );
+        let types: Vec<String> = png
+            .chunks()
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect();
+        assert_eq!(types, vec!["IHDR", "gAMA", "IDAT", "IEND"]);
+        assert!(anonymize(&mut png, AnonymizeOptions::default())
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_scrub_iccp() {
+        let mut profile = vec![1u8; 128];
+        profile[0] = 42;
+        let mut png = Png::from_chunks(vec![
+            chunk("IHDR", &[0; 13]),
+            iccp("Jane's camera", &profile),
+            chunk("IEND", &[]),
+        ]);
+        let changes = anonymize(&mut png, AnonymizeOptions::default()).unwrap();
+        assert_eq!(
+            changes,
+            vec!["scrubbed iCCP profile name \"Jane's camera\", creator, profile ID"]
+        );
+
+        let data = png.chunk_by_type("iCCP").unwrap().data();
+        assert!(data.starts_with(b"ICC profile\0\0"));
+        let scrubbed = Compression::Zlib.decompress(&data[13..]).unwrap();
+        assert_eq!(scrubbed[0], 42);
+        assert!(scrubbed[80..100].iter().all(|b| *b == 0));
+        assert_eq!(scrubbed[100..], profile[100..]);
+    }
+}
@@ -0,0 +1,804 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::decoder::{self, Pixels};
+use crate::error::{error, ErrorKind};
+use crate::ihdr::{ColorType, Ihdr};
+use crate::palette::{self, Palette, Transparency};
+use crate::png::Png;
+use crate::{PngError, PngResult};
+use std::fmt::{Display, Formatter};
+
+/// The denominator assumed for a frame delay whose stored denominator is 0
+const DEFAULT_DELAY_DEN: u16 = 100;
+
+/// The zlib level frames are compressed at when written as still images
+const COMPRESSION_LEVEL: u32 = 9;
+
+/// Read the big-endian u32 starting at `start`, which the caller has checked is in bounds
+fn read_u32(bytes: &[u8], start: usize) -> u32 {
+    u32::from_be_bytes([
+        bytes[start],
+        bytes[start + 1],
+        bytes[start + 2],
+        bytes[start + 3],
+    ])
+}
+
+/// The frame count and loop count of an animated PNG, as stored in its acTL chunk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AnimationControl {
+    pub num_frames: u32,
+    /// How many times to play the animation, or 0 to loop forever
+    pub num_plays: u32,
+}
+
+impl AnimationControl {
+    pub const CHUNK_TYPE: &'static str = "acTL";
+
+    /// The length of the acTL chunk data
+    pub const LEN: usize = 8;
+
+    pub fn as_bytes(&self) -> [u8; Self::LEN] {
+        let mut bytes = [0; Self::LEN];
+        bytes[..4].copy_from_slice(&self.num_frames.to_be_bytes());
+        bytes[4..].copy_from_slice(&self.num_plays.to_be_bytes());
+        bytes
+    }
+
+    pub fn to_chunk(&self) -> Chunk {
+        Chunk::new(ChunkType::ACTL, self.as_bytes().to_vec())
+    }
+}
+
+impl TryFrom<&[u8]> for AnimationControl {
+    type Error = PngError;
+
+    /// Decode acTL chunk data
+    fn try_from(value: &[u8]) -> PngResult<Self> {
+        if value.len() != Self::LEN {
+            return Err(format!(
+                "acTL data is {} bytes long but should be {}",
+                value.len(),
+                Self::LEN
+            )
+            .into());
+        }
+        let control = AnimationControl {
+            num_frames: read_u32(value, 0),
+            num_plays: read_u32(value, 4),
+        };
+        if control.num_frames == 0 {
+            return Err("acTL chunk declares no frames".into());
+        }
+        Ok(control)
+    }
+}
+
+impl Display for AnimationControl {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.num_plays {
+            0 => write!(f, "{} frames, looping forever", self.num_frames),
+            1 => write!(f, "{} frames, played once", self.num_frames),
+            plays => write!(f, "{} frames, played {} times", self.num_frames, plays),
+        }
+    }
+}
+
+/// What happens to a frame's region of the canvas before the next frame is drawn
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisposeOp {
+    /// Leave the frame as it is
+    None,
+    /// Clear the region to fully transparent black
+    Background,
+    /// Restore the region to what it was before the frame was drawn
+    Previous,
+}
+
+impl TryFrom<u8> for DisposeOp {
+    type Error = PngError;
+
+    fn try_from(value: u8) -> PngResult<Self> {
+        match value {
+            0 => Ok(DisposeOp::None),
+            1 => Ok(DisposeOp::Background),
+            2 => Ok(DisposeOp::Previous),
+            _ => Err(format!("Unknown fcTL dispose op {}", value).into()),
+        }
+    }
+}
+
+impl Display for DisposeOp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DisposeOp::None => write!(f, "none"),
+            DisposeOp::Background => write!(f, "background"),
+            DisposeOp::Previous => write!(f, "previous"),
+        }
+    }
+}
+
+/// How a frame is drawn onto the canvas
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlendOp {
+    /// Replace the region, alpha included
+    Source,
+    /// Composite the frame over what's already there
+    Over,
+}
+
+impl TryFrom<u8> for BlendOp {
+    type Error = PngError;
+
+    fn try_from(value: u8) -> PngResult<Self> {
+        match value {
+            0 => Ok(BlendOp::Source),
+            1 => Ok(BlendOp::Over),
+            _ => Err(format!("Unknown fcTL blend op {}", value).into()),
+        }
+    }
+}
+
+impl Display for BlendOp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BlendOp::Source => write!(f, "source"),
+            BlendOp::Over => write!(f, "over"),
+        }
+    }
+}
+
+/// The region, timing and compositing of one frame of an animated PNG, as stored in its fcTL
+/// chunk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameControl {
+    /// The position of the chunk among the fcTL and fdAT chunks, counting from 0
+    pub sequence_number: u32,
+    pub width: u32,
+    pub height: u32,
+    pub x_offset: u32,
+    pub y_offset: u32,
+    pub delay_num: u16,
+    pub delay_den: u16,
+    pub dispose_op: DisposeOp,
+    pub blend_op: BlendOp,
+}
+
+impl FrameControl {
+    pub const CHUNK_TYPE: &'static str = "fcTL";
+
+    /// The length of the fcTL chunk data
+    pub const LEN: usize = 26;
+
+    /// How long the frame is shown for, in seconds
+    pub fn delay(&self) -> f64 {
+        let den = match self.delay_den {
+            0 => DEFAULT_DELAY_DEN,
+            den => den,
+        };
+        self.delay_num as f64 / den as f64
+    }
+
+    pub fn as_bytes(&self) -> [u8; Self::LEN] {
+        let mut bytes = [0; Self::LEN];
+        bytes[..4].copy_from_slice(&self.sequence_number.to_be_bytes());
+        bytes[4..8].copy_from_slice(&self.width.to_be_bytes());
+        bytes[8..12].copy_from_slice(&self.height.to_be_bytes());
+        bytes[12..16].copy_from_slice(&self.x_offset.to_be_bytes());
+        bytes[16..20].copy_from_slice(&self.y_offset.to_be_bytes());
+        bytes[20..22].copy_from_slice(&self.delay_num.to_be_bytes());
+        bytes[22..24].copy_from_slice(&self.delay_den.to_be_bytes());
+        bytes[24] = self.dispose_op as u8;
+        bytes[25] = self.blend_op as u8;
+        bytes
+    }
+
+    pub fn to_chunk(&self) -> Chunk {
+        Chunk::new(ChunkType::FCTL, self.as_bytes().to_vec())
+    }
+}
+
+impl TryFrom<&[u8]> for FrameControl {
+    type Error = PngError;
+
+    /// Decode fcTL chunk data
+    fn try_from(value: &[u8]) -> PngResult<Self> {
+        if value.len() != Self::LEN {
+            return Err(format!(
+                "fcTL data is {} bytes long but should be {}",
+                value.len(),
+                Self::LEN
+            )
+            .into());
+        }
+        let frame = FrameControl {
+            sequence_number: read_u32(value, 0),
+            width: read_u32(value, 4),
+            height: read_u32(value, 8),
+            x_offset: read_u32(value, 12),
+            y_offset: read_u32(value, 16),
+            delay_num: u16::from_be_bytes([value[20], value[21]]),
+            delay_den: u16::from_be_bytes([value[22], value[23]]),
+            dispose_op: DisposeOp::try_from(value[24])?,
+            blend_op: BlendOp::try_from(value[25])?,
+        };
+        if frame.width == 0 || frame.height == 0 {
+            return Err("fcTL chunk has a zero width or height".into());
+        }
+        Ok(frame)
+    }
+}
+
+impl Display for FrameControl {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}x{} at ({}, {}), {:.3}s, dispose {}, blend {}",
+            self.width,
+            self.height,
+            self.x_offset,
+            self.y_offset,
+            self.delay(),
+            self.dispose_op,
+            self.blend_op
+        )
+    }
+}
+
+/// Split fdAT chunk data into its sequence number and the compressed frame data after it
+pub fn split_frame_data(data: &[u8]) -> PngResult<(u32, &[u8])> {
+    if data.len() < 4 {
+        return Err("fdAT chunk is too short to hold a sequence number".into());
+    }
+    Ok((read_u32(data, 0), &data[4..]))
+}
+
+/// The animation of an animated PNG
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Animation {
+    pub control: AnimationControl,
+    /// The frames in the order their fcTL chunks appear
+    pub frames: Vec<FrameControl>,
+    /// Whether the IDAT image is the first frame, rather than a still image shown only by
+    /// decoders which don't support APNG
+    pub default_image_is_frame: bool,
+}
+
+impl Animation {
+    /// The time taken to play the animation through once, in seconds
+    pub fn duration(&self) -> f64 {
+        self.frames.iter().map(FrameControl::delay).sum()
+    }
+}
+
+/// Decode the animation chunks of the PNG, if it's animated
+pub fn get_animation(png: &Png) -> PngResult<Option<Animation>> {
+    let Some(actl) = png.chunk_by_type(AnimationControl::CHUNK_TYPE) else {
+        return Ok(None);
+    };
+    let control = AnimationControl::try_from(actl.data())?;
+    let mut frames = vec![];
+    let mut default_image_is_frame = false;
+    let mut seen_idat = false;
+    for chunk in png.chunks() {
+        match &chunk.chunk_type().bytes() {
+            b"IDAT" => seen_idat = true,
+            b"fcTL" => {
+                frames.push(FrameControl::try_from(chunk.data())?);
+                default_image_is_frame |= !seen_idat;
+            }
+            _ => {}
+        }
+    }
+    Ok(Some(Animation {
+        control,
+        frames,
+        default_image_is_frame,
+    }))
+}
+
+/// The compressed data of each frame with its fcTL: the IDAT chunks for a first frame which
+/// is the default image, and the fdAT chunks otherwise
+fn frame_streams(png: &Png) -> PngResult<Vec<(FrameControl, Vec<u8>)>> {
+    let mut frames: Vec<(FrameControl, Vec<u8>)> = vec![];
+    for chunk in png.chunks() {
+        match &chunk.chunk_type().bytes() {
+            b"fcTL" => frames.push((FrameControl::try_from(chunk.data())?, vec![])),
+            // IDAT data belongs to a frame only if an fcTL came before it
+            b"IDAT" => {
+                if let Some((_, data)) = frames.last_mut() {
+                    data.extend_from_slice(chunk.data());
+                }
+            }
+            b"fdAT" => {
+                let (_, frame_data) = split_frame_data(chunk.data())?;
+                let (_, data) = frames
+                    .last_mut()
+                    .ok_or("fdAT chunk comes before any fcTL chunk")?;
+                data.extend_from_slice(frame_data);
+            }
+            _ => {}
+        }
+    }
+    Ok(frames)
+}
+
+/// Sample `index` of a packed row, counting across pixels and then channels
+fn sample(row: &[u8], index: usize, bit_depth: u8) -> u16 {
+    match bit_depth {
+        16 => u16::from_be_bytes([row[index * 2], row[index * 2 + 1]]),
+        8 => row[index] as u16,
+        _ => {
+            let bits = index * bit_depth as usize;
+            let shift = 8 - bit_depth as usize - bits % 8;
+            (row[bits / 8] >> shift) as u16 & ((1 << bit_depth) - 1)
+        }
+    }
+}
+
+/// Set sample `index` of a packed row, for bit depths of 8 and 16
+fn set_sample(row: &mut [u8], index: usize, bit_depth: u8, value: u16) {
+    match bit_depth {
+        16 => row[index * 2..index * 2 + 2].copy_from_slice(&value.to_be_bytes()),
+        _ => row[index] = value as u8,
+    }
+}
+
+/// Whether pixel `x` of a row is made fully transparent by the tRNS chunk
+fn is_transparent(row: &[u8], x: usize, pixels: &Pixels, transparency: &Transparency) -> bool {
+    let bit_depth = pixels.bit_depth;
+    match transparency {
+        Transparency::Alpha(_) => transparency.alpha(sample(row, x, bit_depth) as usize) == 0,
+        Transparency::Gray(gray) => sample(row, x, bit_depth) == *gray,
+        Transparency::Rgb(red, green, blue) => {
+            [*red, *green, *blue]
+                == [0, 1, 2].map(|channel| sample(row, x * 3 + channel, bit_depth))
+        }
+    }
+}
+
+/// Composite pixel `x` of a row with an alpha channel over pixel `to_x` of the canvas row
+fn blend_over(from: &[u8], x: usize, to: &mut [u8], to_x: usize, pixels: &Pixels) {
+    let bit_depth = pixels.bit_depth;
+    let channels = pixels.color_type.channels() as usize;
+    let max = ((1u32 << bit_depth) - 1) as u64;
+    let alpha_index = |x: usize| x * channels + channels - 1;
+    let source_alpha = sample(from, alpha_index(x), bit_depth) as u64;
+    let canvas_alpha = sample(to, alpha_index(to_x), bit_depth) as u64;
+    // The canvas shows through in proportion to how transparent the frame is
+    let behind = canvas_alpha * (max - source_alpha) / max;
+    let alpha = source_alpha + behind;
+    if alpha == 0 {
+        return;
+    }
+    for channel in 0..channels - 1 {
+        let source = sample(from, x * channels + channel, bit_depth) as u64;
+        let canvas = sample(to, to_x * channels + channel, bit_depth) as u64;
+        let value = (source * source_alpha + canvas * behind + alpha / 2) / alpha;
+        set_sample(to, to_x * channels + channel, bit_depth, value as u16);
+    }
+    set_sample(to, alpha_index(to_x), bit_depth, alpha as u16);
+}
+
+/// Draw a frame onto the canvas at its offset, as its blend op says
+fn draw(
+    canvas: &mut Pixels,
+    frame: &Pixels,
+    control: &FrameControl,
+    transparency: Option<&Transparency>,
+) {
+    let has_alpha = matches!(
+        frame.color_type,
+        ColorType::GrayscaleAlpha | ColorType::TruecolorAlpha
+    );
+    let bits_per_pixel = frame.bits_per_pixel();
+    for y in 0..frame.height {
+        let from = frame.row(y);
+        let to = canvas.row_mut(control.y_offset as usize + y);
+        for x in 0..frame.width {
+            let to_x = control.x_offset as usize + x;
+            match (control.blend_op, transparency) {
+                (BlendOp::Over, _) if has_alpha => blend_over(from, x, to, to_x, frame),
+                // Without an alpha channel, tRNS makes pixels either fully transparent or opaque
+                (BlendOp::Over, Some(transparency))
+                    if is_transparent(from, x, frame, transparency) => {}
+                _ => decoder::copy_pixel(from, x, to, to_x, bits_per_pixel),
+            }
+        }
+    }
+}
+
+/// Clear the frame's region of the canvas to zero, transparent black in formats with alpha
+fn clear(canvas: &mut Pixels, control: &FrameControl) {
+    let zeros = vec![0; canvas.stride()];
+    let bits_per_pixel = canvas.bits_per_pixel();
+    for y in 0..control.height as usize {
+        let to = canvas.row_mut(control.y_offset as usize + y);
+        for x in 0..control.width as usize {
+            decoder::copy_pixel(&zeros, 0, to, control.x_offset as usize + x, bits_per_pixel);
+        }
+    }
+}
+
+/// A still PNG holding the canvas, with the chunks of the original which describe how to show
+/// its pixels, such as PLTE, tRNS and the colour space chunks
+fn still_png(png: &Png, ihdr: &Ihdr, canvas: &Pixels) -> PngResult<Png> {
+    let mut chunks: Vec<Chunk> = png
+        .chunks()
+        .iter()
+        .take_while(|chunk| chunk.chunk_type() != &ChunkType::IDAT)
+        .filter(|chunk| ![ChunkType::ACTL, ChunkType::FCTL].contains(chunk.chunk_type()))
+        .cloned()
+        .collect();
+    chunks.push(Chunk::new(ChunkType::IDAT, vec![]));
+    chunks.push(Chunk::new(ChunkType::IEND, vec![]));
+    let mut still = Png::from_chunks(chunks);
+    still.set_idat_data(
+        &decoder::encode(ihdr, canvas),
+        COMPRESSION_LEVEL,
+        Chunk::MAX_DATA_LEN,
+    )?;
+    Ok(still)
+}
+
+/// Render each frame of an animated PNG as a still PNG the size of the whole image, as a
+/// viewer would show it: drawn over the frames before it at its offset, which are disposed
+/// of as their fcTL chunks say. The pixels keep the image's own format, so a frame blended
+/// over a palette or tRNS image only treats fully transparent pixels as see-through.
+pub fn explode(png: &Png) -> PngResult<Vec<Png>> {
+    if get_animation(png)?.is_none() {
+        return Err(error(ErrorKind::Validation, "The PNG isn't animated"));
+    }
+    let ihdr = Ihdr::try_from(png)?;
+    let transparency = palette::get_transparency(png)?;
+    // Every frame is decoded before the canvas is allocated, so that a header claiming a huge
+    // image can't reserve memory for pixels the file doesn't hold
+    let mut frames = vec![];
+    for (index, (control, data)) in frame_streams(png)?.into_iter().enumerate() {
+        let fits = |offset: u32, len: u32, limit: u32| offset.checked_add(len) <= Some(limit);
+        if !fits(control.x_offset, control.width, ihdr.width)
+            || !fits(control.y_offset, control.height, ihdr.height)
+        {
+            let message = format!("Frame {} extends past the edge of the image", index);
+            return Err(error(ErrorKind::Validation, message));
+        }
+        let frame_ihdr = Ihdr {
+            width: control.width,
+            height: control.height,
+            ..ihdr
+        };
+        let raw = decoder::inflate(&data, frame_ihdr.raw_data_len())
+            .map_err(|e| format!("Frame {}: {}", index, e))?;
+        frames.push((control, decoder::decode(&frame_ihdr, &raw)?));
+    }
+    let covered =
+        |control: &FrameControl| (control.width, control.height) == (ihdr.width, ihdr.height);
+    if !frames.iter().any(|(control, _)| covered(control)) {
+        // No frame shows the file holds a whole image's pixels, so the default image must
+        png.idat_data()?;
+    }
+
+    let mut canvas = Pixels::blank(&ihdr)?;
+    let mut stills = vec![];
+    for (index, (control, frame)) in frames.iter().enumerate() {
+        let before = (control.dispose_op == DisposeOp::Previous).then(|| canvas.clone());
+        draw(&mut canvas, frame, control, transparency.as_ref());
+        stills.push(still_png(png, &ihdr, &canvas)?);
+        match (control.dispose_op, before) {
+            // There's nothing before the first frame to go back to, so it's cleared instead
+            (DisposeOp::Previous, Some(before)) if index > 0 => canvas = before,
+            (DisposeOp::None, _) => {}
+            _ => clear(&mut canvas, control),
+        }
+    }
+    Ok(stills)
+}
+
+/// Put still PNGs together as the frames of an animated PNG, each shown for `delay_ms`
+/// milliseconds and covering the whole image, and the animation played `num_plays` times or
+/// forever if 0. The first frame is also the default image, so its chunks besides the image
+/// data are kept. Every frame must have the same header and palette, as their compressed
+/// image data is moved into fdAT chunks as it is.
+pub fn build(frames: &[Png], delay_ms: u16, num_plays: u32) -> PngResult<Png> {
+    let first = frames
+        .first()
+        .ok_or("An animation needs at least one frame")?;
+    let ihdr = Ihdr::try_from(first)?;
+    for (index, frame) in frames.iter().enumerate() {
+        if frame.chunk_by_type(AnimationControl::CHUNK_TYPE).is_some() {
+            return Err(format!("Frame {} is already animated", index).into());
+        }
+        if Ihdr::try_from(frame)? != ihdr {
+            let message = format!(
+                "Frame {} has a different size or format from the first frame",
+                index
+            );
+            return Err(error(ErrorKind::Validation, message));
+        }
+        for chunk_type in [Palette::CHUNK_TYPE, Transparency::CHUNK_TYPE] {
+            let data = |png: &Png| {
+                png.chunk_by_type(chunk_type)
+                    .map(|chunk| chunk.data().to_vec())
+            };
+            if data(frame) != data(first) {
+                let message = format!(
+                    "Frame {} has a different {} chunk from the first frame",
+                    index, chunk_type
+                );
+                return Err(error(ErrorKind::Validation, message));
+            }
+        }
+    }
+
+    let num_frames = u32::try_from(frames.len()).map_err(|_| "Too many frames")?;
+    // fcTL and fdAT chunks share one sequence, counting up from 0
+    let mut sequence_number = 0;
+    let frame_control = |sequence_number: &mut u32| {
+        let control = FrameControl {
+            sequence_number: *sequence_number,
+            width: ihdr.width,
+            height: ihdr.height,
+            x_offset: 0,
+            y_offset: 0,
+            delay_num: delay_ms,
+            delay_den: 1000,
+            dispose_op: DisposeOp::None,
+            blend_op: BlendOp::Source,
+        };
+        *sequence_number += 1;
+        control.to_chunk()
+    };
+
+    let idat_range = first.idat_range()?;
+    let mut chunks = first.chunks()[..idat_range.start].to_vec();
+    chunks.push(
+        AnimationControl {
+            num_frames,
+            num_plays,
+        }
+        .to_chunk(),
+    );
+    chunks.push(frame_control(&mut sequence_number));
+    chunks.extend_from_slice(&first.chunks()[idat_range.clone()]);
+    for frame in &frames[1..] {
+        chunks.push(frame_control(&mut sequence_number));
+        for idat in &frame.chunks()[frame.idat_range()?] {
+            let mut data = sequence_number.to_be_bytes().to_vec();
+            data.extend_from_slice(idat.data());
+            chunks.push(Chunk::new(ChunkType::FDAT, data));
+            sequence_number += 1;
+        }
+    }
+    chunks.extend_from_slice(&first.chunks()[idat_range.end..]);
+    Ok(Png::from_chunks(chunks))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::chunk;
+    use flate2::write::ZlibEncoder;
+    use std::io::Write;
+
+    fn frame(sequence_number: u32) -> FrameControl {
+        FrameControl {
+            sequence_number,
+            width: 4,
+            height: 2,
+            x_offset: 1,
+            y_offset: 0,
+            delay_num: 1,
+            delay_den: 10,
+            dispose_op: DisposeOp::Background,
+            blend_op: BlendOp::Over,
+        }
+    }
+
+    #[test]
+    fn test_animation_control() {
+        let control = AnimationControl {
+            num_frames: 3,
+            num_plays: 0,
+        };
+        assert_eq!(
+            AnimationControl::try_from(&control.as_bytes()[..]).unwrap(),
+            control
+        );
+        assert_eq!(control.to_string(), "3 frames, looping forever");
+        assert!(AnimationControl::try_from(&[0; 8][..]).is_err());
+        assert!(AnimationControl::try_from(&[0; 7][..]).is_err());
+    }
+
+    #[test]
+    fn test_frame_control() {
+        let frame = frame(1);
+        assert_eq!(
+            FrameControl::try_from(&frame.as_bytes()[..]).unwrap(),
+            frame
+        );
+        assert_eq!(
+            frame.to_string(),
+            "4x2 at (1, 0), 0.100s, dispose background, blend over"
+        );
+        let mut bytes = frame.as_bytes();
+        bytes[24] = 3;
+        assert!(FrameControl::try_from(&bytes[..]).is_err());
+        let unset_den = FrameControl {
+            delay_num: 5,
+            delay_den: 0,
+            ..frame
+        };
+        assert_eq!(unset_den.delay(), 0.05);
+    }
+
+    #[test]
+    fn test_get_animation() {
+        let control = AnimationControl {
+            num_frames: 2,
+            num_plays: 1,
+        };
+        let mut fdat = 2u32.to_be_bytes().to_vec();
+        fdat.extend_from_slice(b"zlib");
+        let png = Png::from_chunks(vec![
+            chunk("IHDR", &[]),
+            control.to_chunk(),
+            frame(0).to_chunk(),
+            chunk("IDAT", &[]),
+            frame(1).to_chunk(),
+            chunk("fdAT", &fdat),
+            chunk("IEND", &[]),
+        ]);
+        let animation = get_animation(&png).unwrap().unwrap();
+        assert_eq!(animation.control, control);
+        assert_eq!(animation.frames, vec![frame(0), frame(1)]);
+        assert!(animation.default_image_is_frame);
+        assert!((animation.duration() - 0.2).abs() < 1e-9);
+        assert_eq!(split_frame_data(&fdat).unwrap(), (2, &b"zlib"[..]));
+        assert!(split_frame_data(b"abc").is_err());
+
+        let still = Png::from_chunks(vec![chunk("IHDR", &[]), chunk("IEND", &[])]);
+        assert_eq!(get_animation(&still).unwrap(), None);
+    }
+
+    /// The header of an 8-bit RGBA image
+    fn rgba_ihdr(width: u32, height: u32) -> Ihdr {
+        Ihdr {
+            width,
+            height,
+            bit_depth: 8,
+            color_type: ColorType::TruecolorAlpha,
+            compression_method: 0,
+            filter_method: 0,
+            interlaced: false,
+        }
+    }
+
+    /// The compressed image data of 8-bit RGBA pixels
+    fn compressed_rgba(width: u32, height: u32, rgba: &[[u8; 4]]) -> Vec<u8> {
+        let ihdr = rgba_ihdr(width, height);
+        let mut pixels = Pixels::blank(&ihdr).unwrap();
+        pixels.data_mut().copy_from_slice(&rgba.concat());
+        let mut encoder = ZlibEncoder::new(vec![], flate2::Compression::default());
+        encoder.write_all(&decoder::encode(&ihdr, &pixels)).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn test_explode() {
+        let ihdr = rgba_ihdr(2, 2);
+        let compressed = compressed_rgba;
+        let fdat = |sequence_number: u32, data: Vec<u8>| {
+            let mut bytes = sequence_number.to_be_bytes().to_vec();
+            bytes.extend(data);
+            Chunk::new(ChunkType::FDAT, bytes)
+        };
+        let red = [255, 0, 0, 255];
+        let frame = |sequence_number, x, y, dispose_op, blend_op| FrameControl {
+            sequence_number,
+            width: 2 - x,
+            height: 2 - y,
+            x_offset: x,
+            y_offset: y,
+            dispose_op,
+            blend_op,
+            ..frame(0)
+        };
+        let png = Png::from_chunks(vec![
+            Chunk::new(ChunkType::IHDR, ihdr.as_bytes()),
+            AnimationControl {
+                num_frames: 3,
+                num_plays: 0,
+            }
+            .to_chunk(),
+            frame(0, 0, 0, DisposeOp::None, BlendOp::Source).to_chunk(),
+            Chunk::new(ChunkType::IDAT, compressed(2, 2, &[red; 4])),
+            // Half-transparent blue over the bottom right, then undone
+            frame(1, 1, 1, DisposeOp::Previous, BlendOp::Over).to_chunk(),
+            fdat(2, compressed(1, 1, &[[0, 0, 255, 128]])),
+            frame(3, 1, 0, DisposeOp::Background, BlendOp::Source).to_chunk(),
+            fdat(4, compressed(1, 2, &[[0, 255, 0, 255]; 2])),
+            Chunk::new(ChunkType::IEND, vec![]),
+        ]);
+
+        let stills: Vec<Vec<u8>> = explode(&png)
+            .unwrap()
+            .iter()
+            .map(|still| decoder::decode_png(still).unwrap().data().to_vec())
+            .collect();
+        let green = [0, 255, 0, 255];
+        assert_eq!(
+            stills,
+            vec![
+                [red; 4].concat(),
+                [red, red, red, [127, 0, 128, 255]].concat(),
+                [red, green, red, green].concat(),
+            ]
+        );
+
+        let still = Png::from_chunks(vec![Chunk::new(ChunkType::IHDR, ihdr.as_bytes())]);
+        assert!(explode(&still).is_err());
+    }
+
+    #[test]
+    fn test_explode_huge_header() {
+        // A 40 GB canvas, with a single 1x1 frame and no default image to back it up
+        let control = FrameControl {
+            sequence_number: 0,
+            width: 1,
+            height: 1,
+            x_offset: 0,
+            y_offset: 0,
+            delay_num: 0,
+            delay_den: 0,
+            dispose_op: DisposeOp::None,
+            blend_op: BlendOp::Source,
+        };
+        let mut fdat = 1u32.to_be_bytes().to_vec();
+        fdat.extend(compressed_rgba(1, 1, &[[0; 4]]));
+        let png = Png::from_chunks(vec![
+            Chunk::new(ChunkType::IHDR, rgba_ihdr(100_000, 100_000).as_bytes()),
+            AnimationControl {
+                num_frames: 1,
+                num_plays: 0,
+            }
+            .to_chunk(),
+            Chunk::new(ChunkType::IDAT, vec![]),
+            control.to_chunk(),
+            Chunk::new(ChunkType::FDAT, fdat),
+            Chunk::new(ChunkType::IEND, vec![]),
+        ]);
+        assert!(explode(&png).is_err());
+    }
+
+    #[test]
+    fn test_build() {
+        let still = |width, height, rgba: [u8; 4]| {
+            let pixels = vec![rgba; (width * height) as usize];
+            Png::from_chunks(vec![
+                Chunk::new(ChunkType::IHDR, rgba_ihdr(width, height).as_bytes()),
+                Chunk::new(ChunkType::IDAT, compressed_rgba(width, height, &pixels)),
+                Chunk::new(ChunkType::IEND, vec![]),
+            ])
+        };
+        let frames = [still(2, 2, [255, 0, 0, 255]), still(2, 2, [0, 0, 255, 64])];
+        let animated = build(&frames, 40, 2).unwrap();
+        assert!(crate::validate::validate(&animated.as_bytes()).is_empty());
+
+        let animation = get_animation(&animated).unwrap().unwrap();
+        assert_eq!(animation.control.num_frames, 2);
+        assert_eq!(animation.control.num_plays, 2);
+        assert!(animation.default_image_is_frame);
+        assert_eq!(animation.frames[1].sequence_number, 1);
+        assert_eq!(animation.frames[1].delay(), 0.04);
+        let pixels = |png: &Png| decoder::decode_png(png).unwrap().data().to_vec();
+        let exploded = explode(&animated).unwrap();
+        assert_eq!(
+            exploded.iter().map(pixels).collect::<Vec<_>>(),
+            frames.iter().map(pixels).collect::<Vec<_>>()
+        );
+
+        assert!(build(&[], 40, 0).is_err());
+        assert!(build(&[still(2, 2, [0; 4]), still(1, 2, [0; 4])], 40, 0).is_err());
+        assert!(build(&[animated], 40, 0).is_err());
+    }
+}
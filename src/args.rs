@@ -1 +1,1739 @@
+use crate::walk;
+use clap::error::ErrorKind;
+use clap::parser::ValueSource;
+use clap::{ArgAction, ArgMatches, Args, CommandFactory, Parser, Subcommand};
+use clap_complete::Shell;
+use globset::GlobSet;
+use pngmi::compression::Compression;
+use pngmi::config::Config;
+use pngmi::copy::MergePolicy;
+use pngmi::crypto::KdfParams;
+use pngmi::lint::LintConfig;
+use pngmi::lsb;
+use pngmi::optimize;
+use pngmi::output::WriteOptions;
+use pngmi::png::{ChunkPosition, ParseOptions};
+use pngmi::recipients::Recipient;
+use pngmi::scan::ScanOptions;
+use pngmi::segment;
+use pngmi::PngResult;
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
+/// Hide secret messages inside PNG files
+#[derive(Parser, Debug)]
+#[command(name = "pngmi", version, about, after_help = EXIT_STATUS_HELP)]
+pub struct Cli {
+    /// The output format of the print, info, validate, scan and decode commands
+    #[arg(long, global = true, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+    /// Log what the command is doing to stderr; repeat for more detail, e.g. -vv to log every
+    /// chunk parsed
+    #[arg(short, long, global = true, action = ArgAction::Count)]
+    pub verbose: u8,
+    /// Only log errors
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    pub quiet: bool,
+    /// Ignore pngmi.toml and the user config file, using only the command line
+    #[arg(long, global = true)]
+    pub no_config: bool,
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+impl Cli {
+    /// Fill in the settings the command line leaves out from the config files. `matches` tells
+    /// values given on the command line apart from clap's defaults, which the config overrides.
+    pub fn apply_config(&mut self, config: Config, matches: &ArgMatches) -> PngResult<()> {
+        let given = |matches: &ArgMatches, id: &str| {
+            matches.value_source(id) == Some(ValueSource::CommandLine)
+        };
+        if let Some(format) = &config.format {
+            if !given(matches, "format") {
+                self.format = format.parse()?;
+            }
+        }
+        let ignore = walk::ignore_set(&config.ignore)?;
+        if let Some(walk) = self.command.walk_mut() {
+            walk.ignore = ignore;
+        }
+        let Some((_, matches)) = matches.subcommand() else {
+            return Ok(());
+        };
+        let encryption = config.encryption;
+        match &mut self.command {
+            Command::Encode(args) => {
+                if let Some(chunk_type) = config.chunk_type {
+                    args.default_chunk_type(chunk_type.to_string());
+                }
+                if args.passphrase.is_none() && args.recipient.is_empty() {
+                    args.recipient = encryption.recipients;
+                }
+                let kdf = [
+                    ("kdf_memory", &mut args.kdf_memory, encryption.kdf_memory),
+                    (
+                        "kdf_iterations",
+                        &mut args.kdf_iterations,
+                        encryption.kdf_iterations,
+                    ),
+                    (
+                        "kdf_parallelism",
+                        &mut args.kdf_parallelism,
+                        encryption.kdf_parallelism,
+                    ),
+                ];
+                for (id, value, default) in kdf {
+                    match default {
+                        Some(default) if !given(matches, id) => *value = default,
+                        _ => {}
+                    }
+                }
+            }
+            Command::Decode(args) => {
+                if args.chunk_type.is_none() && args.method.uses_chunk_type() {
+                    args.chunk_type = config.chunk_type.map(|chunk_type| chunk_type.to_string());
+                }
+                if args.passphrase.is_none() && args.identity.is_empty() {
+                    args.identity = encryption.identities;
+                }
+            }
+            Command::Validate(args) => args.lint.defaults = config.lints,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Reject options given with a value of another which they don't apply to, which clap's own
+    /// conflicts can't express, with the error clap gives for those
+    pub fn check_conflicts(&self, matches: &ArgMatches) -> Result<(), clap::Error> {
+        let (Command::Encode(args), Some((name, matches))) = (&self.command, matches.subcommand())
+        else {
+            return Ok(());
+        };
+        if args.method == EmbedMethod::Chunk {
+            return Ok(());
+        }
+        for id in ["position", "max_chunk_size"] {
+            if matches.value_source(id) == Some(ValueSource::CommandLine) {
+                let message = format!(
+                    "the argument '--{}' cannot be used with '--method {}'",
+                    id.replace('_', "-"),
+                    args.method
+                );
+                let mut command = Cli::command();
+                command.build();
+                let command = command
+                    .find_subcommand_mut(name)
+                    .expect("encode is a subcommand");
+                return Err(command.error(ErrorKind::ArgumentConflict, message));
+            }
+        }
+        Ok(())
+    }
+}
+
+const EXIT_STATUS_HELP: &str = "\
+Exit status:
+  0  success
+  1  bad arguments, or any other failure
+  2  the input isn't a well-formed PNG or payload
+  3  a chunk the command needs isn't in the file
+  4  a CRC mismatch or spec violation
+  5  decryption, key or signature verification failed
+  6  a file couldn't be read or written
+
+Batch commands exit with the status shared by every failed file, or 1 if they differ.
+Like grep(1) and diff(1), grep and diff exit with 1 when nothing matches or the files differ
+and 2 on any failure.";
+
+/// How commands which report on files format their output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Human-readable text
+    Text,
+    /// One JSON object per file, on a line of its own
+    Json,
+    /// Comma-separated values with a header row, one row per chunk
+    Csv,
+    /// Tab-separated values with a header row, one row per chunk
+    Tsv,
+    /// A single SARIF log covering every file, for code scanning dashboards
+    Sarif,
+}
+
+impl OutputFormat {
+    /// The field delimiter of the tabular formats
+    pub fn delimiter(&self) -> Option<char> {
+        match self {
+            OutputFormat::Csv => Some(','),
+            OutputFormat::Tsv => Some('\t'),
+            _ => None,
+        }
+    }
+
+    /// Fail unless this is text or one of the given formats, naming the command
+    pub fn check(&self, command: &str, supported: &[OutputFormat]) -> Result<(), String> {
+        if *self == OutputFormat::Text || supported.contains(self) {
+            return Ok(());
+        }
+        Err(format!("{} doesn't support --format {}", command, self))
+    }
+}
+
+impl Display for OutputFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OutputFormat::Text => write!(f, "text"),
+            OutputFormat::Json => write!(f, "json"),
+            OutputFormat::Csv => write!(f, "csv"),
+            OutputFormat::Tsv => write!(f, "tsv"),
+            OutputFormat::Sarif => write!(f, "sarif"),
+        }
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            "csv" => Ok(OutputFormat::Csv),
+            "tsv" => Ok(OutputFormat::Tsv),
+            "sarif" => Ok(OutputFormat::Sarif),
+            _ => Err(format!(
+                "Unknown format {:?}; expected text, json, csv, tsv or sarif",
+                s
+            )),
+        }
+    }
+}
+
+/// Where `encode` hides a payload in the PNG
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmbedMethod {
+    /// In chunks of its own
+    Chunk,
+    /// In the least significant bits of the pixels
+    Lsb,
+    /// After the IEND chunk, behind a small header
+    Trailer,
+}
+
+impl EmbedMethod {
+    /// Whether the payload is stored under a chunk type given on the command line
+    pub fn uses_chunk_type(&self) -> bool {
+        *self == EmbedMethod::Chunk
+    }
+}
+
+impl Display for EmbedMethod {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EmbedMethod::Chunk => write!(f, "chunk"),
+            EmbedMethod::Lsb => write!(f, "lsb"),
+            EmbedMethod::Trailer => write!(f, "trailer"),
+        }
+    }
+}
+
+impl FromStr for EmbedMethod {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "chunk" => Ok(EmbedMethod::Chunk),
+            "lsb" => Ok(EmbedMethod::Lsb),
+            "trailer" => Ok(EmbedMethod::Trailer),
+            _ => Err(format!(
+                "Unknown method {:?}; expected chunk, lsb or trailer",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Encode a message into a new chunk of the given PNG file
+    Encode(EncodeArgs),
+    /// Decode the message stored in the first chunk of the given type
+    Decode(DecodeArgs),
+    /// Remove the first chunk of the given type
+    Remove(RemoveArgs),
+    /// Write the raw data of a chunk to a file or standard output
+    Extract(ExtractArgs),
+    /// Insert a chunk holding the raw data read from a file
+    Inject(InjectArgs),
+    /// Print a hex and ASCII dump of the data of a chunk
+    Hexdump(HexdumpArgs),
+    /// Search the chunk data of PNG files for a regex or byte pattern
+    Grep(GrepArgs),
+    /// Print the chunks present in the given PNG file
+    Print(PrintArgs),
+    /// Print the image properties recorded in the IHDR chunk
+    Info(InfoArgs),
+    /// Break down the size of a PNG file by chunk type
+    Stats(StatsArgs),
+    /// Compare two PNG files chunk by chunk, exiting with status 1 if they differ
+    Diff(DiffArgs),
+    /// Apply a patch written by `diff --patch` to PNG files in place
+    Apply(ApplyArgs),
+    /// Copy ancillary chunks such as text, colour profiles and pixel density between files
+    CopyMeta(CopyMetaArgs),
+    /// Carry the metadata of an original image over to a regenerated copy of it
+    Merge(MergeArgs),
+    /// Rewrite PNG files with their chunks in a canonical order and every CRC recomputed
+    Normalize(NormalizeArgs),
+    /// Remove duplicate copies of ancillary chunks, such as repeated text or eXIf chunks
+    Dedupe(DedupeArgs),
+    /// Write each chunk's data to a file of its own, with a manifest for `assemble`
+    Split(SplitArgs),
+    /// Rebuild a PNG from a manifest written by `split`, recomputing every CRC
+    Assemble(AssembleArgs),
+    /// Shrink PNG files by removing metadata and recompressing the image data losslessly
+    Optimize(OptimizeArgs),
+    /// Read or write standard text metadata (tEXt, zTXt and iTXt chunks)
+    Text(TextArgs),
+    /// Read, write or remove the XMP metadata packet
+    Xmp(XmpArgs),
+    /// Read or set the last modification time stored in the tIME chunk
+    Time(TimeArgs),
+    /// Read or set the pixel density stored in the pHYs chunk
+    Dpi(DpiArgs),
+    /// Read or set the image gamma stored in the gAMA chunk
+    Gamma(GammaArgs),
+    /// Extract or embed the ICC colour profile stored in the iCCP chunk
+    Icc(IccArgs),
+    /// Work with the frames of an animated PNG
+    Apng(ApngArgs),
+    /// Report the size of a PNG and how much a planned payload would add to it
+    Capacity(CapacityArgs),
+    /// Recursively scan PNG files for signs of hidden data
+    Scan(ScanArgs),
+    /// Run statistical tests for LSB embedding and chunk heuristics, scoring each file's risk
+    /// of carrying a hidden payload
+    Analyze(AnalyzeArgs),
+    /// Find PNGs inside any file, such as a memory dump or disk image, and extract each one
+    Carve(CarveArgs),
+    /// Check a PNG file against the structural rules of the PNG spec
+    Validate(ValidateArgs),
+    /// Recompute and rewrite chunk CRCs which don't match the chunk contents, and optionally
+    /// salvage files cut off mid-chunk
+    Repair(RepairArgs),
+    /// Remove ancillary metadata chunks, e.g. before publishing an image
+    Strip(StripArgs),
+    /// Scrub identifying metadata from PNG files in place, listing what was removed
+    Anonymize(AnonymizeArgs),
+    /// Browse the chunks of a PNG file in the terminal, viewing, deleting and exporting them
+    #[cfg(feature = "tui")]
+    Tui(TuiArgs),
+    /// Serve HTTP endpoints which inspect, validate, decode and encode uploaded PNG files
+    #[cfg(feature = "serve")]
+    Serve(ServeArgs),
+    /// Keep carrier files parsed in memory and answer encode, decode and validate requests on
+    /// a Unix socket
+    #[cfg(all(unix, feature = "daemon"))]
+    Daemon(DaemonArgs),
+    /// Load a PNG file once and edit it with commands typed at a prompt
+    #[cfg(feature = "shell")]
+    Shell(ShellArgs),
+    /// Watch a directory and run a command on each PNG file added to or changed in it
+    #[cfg(feature = "watch")]
+    Watch(WatchArgs),
+    /// Print a shell completion script, e.g. `pngmi completions fish > pngmi.fish`
+    Completions(CompletionsArgs),
+    /// Write man pages for pngmi and each of its commands
+    Man(ManArgs),
+}
+
+impl Command {
+    /// The options for walking directories, for the commands which take them
+    fn walk_mut(&mut self) -> Option<&mut WalkArgs> {
+        match self {
+            Command::Grep(GrepArgs { batch, .. })
+            | Command::Print(PrintArgs { batch, .. })
+            | Command::Info(InfoArgs { batch, .. })
+            | Command::Stats(StatsArgs { batch, .. })
+            | Command::Apply(ApplyArgs { batch, .. })
+            | Command::Normalize(NormalizeArgs { batch, .. })
+            | Command::Dedupe(DedupeArgs { batch, .. })
+            | Command::Optimize(OptimizeArgs { batch, .. })
+            | Command::Analyze(AnalyzeArgs { batch, .. })
+            | Command::Validate(ValidateArgs { batch, .. })
+            | Command::Repair(RepairArgs { batch, .. })
+            | Command::Strip(StripArgs { batch, .. }) => Some(&mut batch.walk),
+            Command::Scan(args) => Some(&mut args.walk),
+            _ => None,
+        }
+    }
+}
+
+/// Options controlling how input PNG files are parsed
+#[derive(Args, Debug)]
+pub struct ParseArgs {
+    /// Skip corrupt chunks and carry on parsing instead of failing, printing a warning for each
+    #[arg(long)]
+    pub lenient: bool,
+    /// Don't check chunk CRCs up front. Only decode and extract still check those of the
+    /// chunks they read, and decode --method lsb those of every chunk; every other command
+    /// doesn't check CRCs at all. For trusted files too large to checksum in full.
+    #[arg(long, conflicts_with = "lenient")]
+    pub no_verify_crc: bool,
+    /// Memory-map the file instead of reading it into memory, for very large images
+    #[cfg(feature = "mmap")]
+    #[arg(long)]
+    pub mmap: bool,
+    /// Read the PNG starting this many bytes into the file, in decimal or 0x-prefixed hex,
+    /// such as one inside an ICO file or firmware image. Only for commands which don't change
+    /// the file.
+    #[arg(long, value_parser = parse_offset)]
+    pub offset: Option<usize>,
+    /// Read the file as several PNGs back to back, such as a captured network stream. `info`
+    /// and `print` show each in turn; other commands need --image to pick one.
+    #[arg(long, conflicts_with = "offset")]
+    pub multi: bool,
+    /// With --multi, read only this PNG, counting from 0
+    #[arg(long, value_name = "INDEX", requires = "multi")]
+    pub image: Option<usize>,
+    /// Refuse chunks declaring more data than this many bytes
+    #[arg(long, value_name = "BYTES", default_value_t = ParseOptions::default().max_chunk_len)]
+    pub max_chunk_len: usize,
+    /// Refuse PNGs with more than this many chunks
+    #[arg(long, value_name = "N", default_value_t = ParseOptions::DEFAULT_MAX_CHUNKS)]
+    pub max_chunks: usize,
+    /// Refuse files larger than this many bytes
+    #[arg(long, value_name = "BYTES")]
+    pub max_size: Option<usize>,
+}
+
+impl ParseArgs {
+    pub fn options(&self) -> ParseOptions {
+        ParseOptions {
+            max_chunk_len: self.max_chunk_len,
+            max_chunks: self.max_chunks,
+            max_total_size: self.max_size.unwrap_or(usize::MAX),
+        }
+    }
+}
+
+/// Parse a byte offset given in decimal or, with a 0x prefix, in hex as `carve` prints them
+fn parse_offset(s: &str) -> Result<usize, String> {
+    let parsed = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => s.parse(),
+    };
+    parsed.map_err(|_| format!("Invalid offset {:?}; expected e.g. 4096 or 0x1000", s))
+}
+
+/// Options for commands which can process every PNG file beneath a directory
+#[derive(Args, Debug)]
+pub struct BatchArgs {
+    /// Accept a directory and process every *.png file beneath it, summarising the results
+    #[arg(short, long)]
+    pub recursive: bool,
+    /// Process this many files at once, or one per CPU core if 0. Output stays in file order.
+    #[arg(short, long, default_value_t = 1, value_name = "N")]
+    pub jobs: usize,
+    #[command(flatten)]
+    pub walk: WalkArgs,
+    #[command(flatten)]
+    pub list: FileListArgs,
+}
+
+/// Options for reading the files to process from a list rather than the command line
+#[derive(Args, Debug)]
+pub struct FileListArgs {
+    /// Process the files listed one per line in this file, or on standard input if "-"
+    #[arg(long, value_name = "LIST")]
+    pub files_from: Option<PathBuf>,
+    /// The --files-from list is NUL-delimited, as written by `find -print0`
+    #[arg(short = '0', long, requires = "files_from")]
+    pub null: bool,
+}
+
+/// Options controlling which files are found when walking directories or expanding globs
+#[derive(Args, Debug)]
+pub struct WalkArgs {
+    /// Include hidden files and directories, whose names start with a dot
+    #[arg(long)]
+    pub hidden: bool,
+    /// Descend into symlinked directories
+    #[arg(long)]
+    pub follow_symlinks: bool,
+    /// Don't leave out the files listed in .pngmiignore files
+    #[arg(long)]
+    pub no_ignore: bool,
+    /// Entries to leave out of every walk, from the `ignore` patterns of the config files
+    #[arg(skip)]
+    pub ignore: GlobSet,
+}
+
+/// Handlers for private chunk types
+#[derive(Args, Debug)]
+pub struct HandlerArgs {
+    /// Decode and check a private chunk type with a built-in handler, given as NAME:TYPE,
+    /// e.g. envelope:ruSt for payloads written by encode. May be repeated.
+    #[arg(long = "handler", value_name = "NAME:TYPE")]
+    pub handlers: Vec<String>,
+}
+
+/// Which validation rules are reported and how seriously. Each takes a rule ID as `validate`
+/// reports them, or `warnings` for every violation left as a warning.
+#[derive(Args, Debug)]
+pub struct LintArgs {
+    /// Read rule levels from the [lints] table of this TOML file; the flags below override it
+    #[arg(long, value_name = "FILE")]
+    pub lint_config: Option<PathBuf>,
+    /// Don't report this rule. May be repeated.
+    #[arg(long, value_name = "RULE")]
+    pub allow: Vec<String>,
+    /// Report this rule as information only. May be repeated.
+    #[arg(long, value_name = "RULE")]
+    pub info: Vec<String>,
+    /// Report this rule as a warning. May be repeated.
+    #[arg(long, value_name = "RULE")]
+    pub warn: Vec<String>,
+    /// Report this rule as an error, failing the check; `--deny warnings` fails on any
+    /// warning. May be repeated.
+    #[arg(long, value_name = "RULE")]
+    pub deny: Vec<String>,
+    /// The levels set by the config files, which everything above overrides
+    #[arg(skip)]
+    pub defaults: LintConfig,
+}
+
+/// Options controlling how modified PNG files are written
+#[derive(Args, Debug)]
+pub struct WriteArgs {
+    /// Set the tIME chunk to the current UTC time, as the spec recommends when editing images
+    #[arg(long)]
+    pub update_time: bool,
+    #[command(flatten)]
+    pub save: SaveArgs,
+}
+
+/// Options for commands which edit files in place
+#[derive(Args, Debug)]
+pub struct SaveArgs {
+    /// Keep the original file alongside the edited one, named with this suffix or `~`
+    #[arg(
+        long,
+        value_name = "SUFFIX",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "~"
+    )]
+    pub backup: Option<String>,
+    /// Keep the original modification time and, on Unix, owner and group
+    #[arg(long)]
+    pub preserve: bool,
+    /// Report which chunks would be added, removed or rewritten and the resulting file size,
+    /// without writing anything
+    #[arg(long)]
+    pub dry_run: bool,
+    /// Edit several files at once without asking first
+    #[arg(short = 'y', long, visible_alias = "yes")]
+    pub force: bool,
+}
+
+impl SaveArgs {
+    pub fn options(&self) -> WriteOptions<'_> {
+        WriteOptions {
+            backup_suffix: self.backup.as_deref(),
+            preserve: self.preserve,
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct EncodeArgs {
+    /// The PNG file to read
+    pub file: PathBuf,
+    /// The chunk type to store the message under, e.g. `ruSt`; left out with other methods
+    pub chunk_type: Option<String>,
+    /// The message to hide
+    #[arg(conflicts_with = "payload_file")]
+    pub message: Option<String>,
+    /// Where to write the resulting PNG; defaults to overwriting the input file
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+    /// Hide the raw bytes of this file instead of a message
+    #[arg(long)]
+    pub payload_file: Option<PathBuf>,
+    /// Where to hide the payload: chunk, in chunks of its own; lsb, in the least significant
+    /// bits of the pixels, which is less conspicuous but holds far less; or trailer, after the
+    /// IEND chunk, where every decoder ignores it but tools which rewrite the file drop it
+    #[arg(long, default_value_t = EmbedMethod::Chunk)]
+    pub method: EmbedMethod,
+    /// With --method lsb, how many low bits of each colour sample to use. More bits hold more
+    /// but change the image more.
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u8).range(1..=lsb::MAX_BITS as i64))]
+    pub bits: u8,
+    /// Compress the payload before embedding it
+    #[arg(long, value_name = "ALGORITHM")]
+    pub compress: Option<Compression>,
+    /// Compression level; defaults to the strongest practical level of the algorithm
+    #[arg(long, requires = "compress", allow_negative_numbers = true)]
+    pub level: Option<i32>,
+    /// Split payloads larger than this many bytes across several chunks; only with --method
+    /// chunk
+    #[arg(long, default_value_t = segment::DEFAULT_MAX_CHUNK_LEN)]
+    pub max_chunk_size: usize,
+    /// Where to put the payload chunks: after-ihdr, before-idat, before-iend, after-iend or
+    /// index:N; only with --method chunk
+    #[arg(long, default_value_t = ChunkPosition::BeforeIend)]
+    pub position: ChunkPosition,
+    /// Encrypt the message with a key derived from this passphrase
+    #[arg(long, conflicts_with = "recipient")]
+    pub passphrase: Option<String>,
+    /// Encrypt the message to this X25519 recipient (`age1...`); may be repeated
+    #[arg(long, value_name = "RECIPIENT")]
+    pub recipient: Vec<Recipient>,
+    /// Argon2id memory cost in KiB, at most 1048576 (1 GiB)
+    #[arg(long, default_value_t = KdfParams::DEFAULT.memory_kib, requires = "passphrase")]
+    pub kdf_memory: u32,
+    /// Argon2id iteration count, at most 16
+    #[arg(long, default_value_t = KdfParams::DEFAULT.iterations, requires = "passphrase")]
+    pub kdf_iterations: u32,
+    /// Argon2id degree of parallelism, at most 16
+    #[arg(long, default_value_t = KdfParams::DEFAULT.parallelism, requires = "passphrase")]
+    pub kdf_parallelism: u32,
+    /// Sign the payload with this Ed25519 private key (PKCS#8 PEM)
+    #[arg(long, value_name = "KEY")]
+    pub sign: Option<PathBuf>,
+    #[command(flatten)]
+    pub parse: ParseArgs,
+    #[command(flatten)]
+    pub write: WriteArgs,
+}
+
+impl EncodeArgs {
+    /// The chunk type and message, followed by any positional left over. Methods which don't
+    /// add a chunk take no chunk type, so a message given with them moves up to take its place.
+    fn positionals(&self) -> [Option<&str>; 3] {
+        let mut given = [self.chunk_type.as_deref(), self.message.as_deref()]
+            .into_iter()
+            .flatten();
+        let mut next_if = |wanted: bool| if wanted { given.next() } else { None };
+        let chunk_type = next_if(self.method.uses_chunk_type());
+        let message = next_if(self.payload_file.is_none());
+        [chunk_type, message, next_if(true)]
+    }
+
+    /// The chunk type to store the payload under
+    pub fn chunk_type(&self) -> Option<&str> {
+        self.positionals()[0]
+    }
+
+    /// The message to hide, unless hiding a file
+    pub fn message(&self) -> Option<&str> {
+        self.positionals()[1]
+    }
+
+    /// Where the resulting PNG should be written
+    pub fn output_path(&self) -> &Path {
+        self.output.as_deref().unwrap_or(&self.file)
+    }
+
+    /// A positional which has no place, e.g. a chunk type given with --method lsb
+    pub fn unexpected_positional(&self) -> Option<&str> {
+        self.positionals()[2]
+    }
+
+    /// Use this chunk type if too few positionals were given to include one, moving the one
+    /// given, if any, to the message
+    fn default_chunk_type(&mut self, chunk_type: String) {
+        if !self.method.uses_chunk_type() {
+            return;
+        }
+        let needed = 1 + usize::from(self.payload_file.is_none());
+        let given = [self.chunk_type.is_some(), self.message.is_some()];
+        if given.iter().filter(|&&given| given).count() < needed {
+            self.message = self.chunk_type.take();
+            self.chunk_type = Some(chunk_type);
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct DecodeArgs {
+    /// The PNG file to read
+    pub file: PathBuf,
+    /// The chunk type holding the message; left out with other methods
+    pub chunk_type: Option<String>,
+    /// Where the payload was hidden: chunk, lsb or trailer
+    #[arg(long, default_value_t = EmbedMethod::Chunk)]
+    pub method: EmbedMethod,
+    /// Passphrase used when the message was encoded
+    #[arg(long)]
+    pub passphrase: Option<String>,
+    /// Decrypt using the identities (`AGE-SECRET-KEY-1...`) in this file; may be repeated
+    #[arg(long, value_name = "FILE")]
+    pub identity: Vec<PathBuf>,
+    /// Write the recovered payload to this file instead of printing it
+    #[arg(long)]
+    pub out: Option<PathBuf>,
+    /// Refuse to decode unless the payload is signed by this Ed25519 public key (PEM)
+    #[arg(long, value_name = "PUBKEY")]
+    pub verify: Option<PathBuf>,
+    #[command(flatten)]
+    pub parse: ParseArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct RemoveArgs {
+    /// The PNG file to modify in place
+    pub file: PathBuf,
+    /// The chunk type to remove
+    pub chunk_type: String,
+    #[command(flatten)]
+    pub parse: ParseArgs,
+    #[command(flatten)]
+    pub write: WriteArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct ExtractArgs {
+    /// The PNG file to read
+    pub file: PathBuf,
+    /// The type of the chunk to extract
+    pub chunk_type: String,
+    /// Extract the Nth chunk of the type rather than the first, counting from 1
+    #[arg(long, default_value_t = 1, value_name = "N")]
+    pub nth: usize,
+    /// Write the chunk data to this file instead of standard output
+    #[arg(long)]
+    pub out: Option<PathBuf>,
+    #[command(flatten)]
+    pub parse: ParseArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct InjectArgs {
+    /// The PNG file to modify in place
+    pub file: PathBuf,
+    /// The type of the chunk to insert
+    pub chunk_type: String,
+    /// The file holding the chunk data, or "-" for standard input
+    #[arg(long, value_name = "FILE")]
+    pub data: PathBuf,
+    /// Where to insert the chunk: after-ihdr, before-idat, before-iend, after-iend or index:N
+    #[arg(long, default_value_t = ChunkPosition::BeforeIend)]
+    pub position: ChunkPosition,
+    #[command(flatten)]
+    pub parse: ParseArgs,
+    #[command(flatten)]
+    pub write: WriteArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct HexdumpArgs {
+    /// The PNG file to read
+    pub file: PathBuf,
+    /// The type of the chunk to dump
+    pub chunk_type: String,
+    /// Dump the Nth chunk of the type rather than the first, counting from 1
+    #[arg(long, default_value_t = 1, value_name = "N")]
+    pub nth: usize,
+    /// Dump the chunk as stored in the file, including its length, type and CRC, with offsets
+    /// from the start of the file rather than of the chunk data
+    #[arg(long)]
+    pub raw: bool,
+    #[command(flatten)]
+    pub parse: ParseArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct GrepArgs {
+    /// The regex to search for, matched against raw bytes
+    pub pattern: String,
+    /// The PNG files to search, directories with --recursive, or quoted glob patterns
+    #[arg(required_unless_present = "files_from", conflicts_with = "files_from")]
+    pub files: Vec<PathBuf>,
+    /// The pattern is hex bytes, e.g. "de ad be ef", where ?? matches any byte
+    #[arg(long)]
+    pub hex: bool,
+    /// Also search the inflated text of zTXt and iTXt chunks and the inflated image data
+    #[arg(short = 'z', long)]
+    pub decompress: bool,
+    #[command(flatten)]
+    pub parse: ParseArgs,
+    #[command(flatten)]
+    pub batch: BatchArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct PrintArgs {
+    /// The PNG file to read, a directory with --recursive, or a quoted glob pattern
+    #[arg(required_unless_present = "files_from")]
+    pub file: Option<PathBuf>,
+    /// Also show the entropy of each chunk and whether its data looks like text or ciphertext
+    #[arg(long)]
+    pub analyze: bool,
+    #[command(flatten)]
+    pub parse: ParseArgs,
+    #[command(flatten)]
+    pub handlers: HandlerArgs,
+    #[command(flatten)]
+    pub batch: BatchArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct InfoArgs {
+    /// The PNG file to read, a directory with --recursive, or a quoted glob pattern
+    #[arg(required_unless_present = "files_from")]
+    pub file: Option<PathBuf>,
+    /// Also list the camera, timestamp and GPS tags of any EXIF data
+    #[arg(long)]
+    pub exif: bool,
+    /// Also list the palette entries and their transparency
+    #[arg(long)]
+    pub palette: bool,
+    #[command(flatten)]
+    pub parse: ParseArgs,
+    #[command(flatten)]
+    pub batch: BatchArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct StatsArgs {
+    /// The PNG file to read, a directory with --recursive, or a quoted glob pattern
+    #[arg(required_unless_present = "files_from")]
+    pub file: Option<PathBuf>,
+    #[command(flatten)]
+    pub parse: ParseArgs,
+    #[command(flatten)]
+    pub batch: BatchArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct DiffArgs {
+    /// The original PNG file
+    pub old: PathBuf,
+    /// The PNG file to compare it with
+    pub new: PathBuf,
+    /// Write a JSON patch which turns the original into the other file, for `apply`
+    #[arg(long, value_name = "FILE")]
+    pub patch: Option<PathBuf>,
+    #[command(flatten)]
+    pub parse: ParseArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct ApplyArgs {
+    /// The patch written by `diff --patch`
+    pub patch: PathBuf,
+    /// The PNG file to patch in place, a directory with --recursive, or a quoted glob pattern
+    #[arg(required_unless_present = "files_from")]
+    pub file: Option<PathBuf>,
+    #[command(flatten)]
+    pub parse: ParseArgs,
+    #[command(flatten)]
+    pub write: WriteArgs,
+    #[command(flatten)]
+    pub batch: BatchArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct CopyMetaArgs {
+    /// The PNG file to copy chunks from
+    #[arg(long)]
+    pub from: PathBuf,
+    /// The PNG file to copy them into, in place
+    #[arg(long)]
+    pub to: PathBuf,
+    /// The chunk types to copy, comma-separated; defaults to every ancillary chunk type
+    #[arg(long, value_delimiter = ',', value_name = "CHUNK_TYPES")]
+    pub types: Vec<String>,
+    /// Copy chunks marked unsafe to copy, such as tRNS, even if the images' headers or
+    /// palettes differ
+    #[arg(long)]
+    pub allow_unsafe: bool,
+    #[command(flatten)]
+    pub parse: ParseArgs,
+    #[command(flatten)]
+    pub write: WriteArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct MergeArgs {
+    /// The regenerated PNG file to merge the metadata into, in place
+    pub file: PathBuf,
+    /// The original PNG file whose ancillary chunks to carry over
+    #[arg(long)]
+    pub from: PathBuf,
+    /// Which chunk wins when both files have a chunk of a type allowed only once: ours (the
+    /// regenerated file's), theirs (the original's), or error
+    #[arg(long, default_value_t = MergePolicy::Error)]
+    pub policy: MergePolicy,
+    #[command(flatten)]
+    pub parse: ParseArgs,
+    #[command(flatten)]
+    pub write: WriteArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct NormalizeArgs {
+    /// The PNG file to normalize, a directory with --recursive, or a quoted glob pattern
+    #[arg(required_unless_present = "files_from")]
+    pub file: Option<PathBuf>,
+    /// Where to write the normalized PNG; defaults to overwriting the input file
+    #[arg(conflicts_with = "recursive")]
+    pub output: Option<PathBuf>,
+    /// Only check that the files are already normalized, failing if any aren't
+    #[arg(long, conflicts_with = "output")]
+    pub check: bool,
+    #[command(flatten)]
+    pub parse: ParseArgs,
+    #[command(flatten)]
+    pub write: WriteArgs,
+    #[command(flatten)]
+    pub batch: BatchArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct DedupeArgs {
+    /// The PNG file to deduplicate, a directory with --recursive, or a quoted glob pattern
+    #[arg(required_unless_present = "files_from")]
+    pub file: Option<PathBuf>,
+    /// Where to write the deduplicated PNG; defaults to overwriting the input file
+    #[arg(conflicts_with = "recursive")]
+    pub output: Option<PathBuf>,
+    /// Also remove later copies of chunks allowed only once even if they differ from the
+    /// first, which is the one decoders use
+    #[arg(long)]
+    pub first_wins: bool,
+    #[command(flatten)]
+    pub parse: ParseArgs,
+    #[command(flatten)]
+    pub write: WriteArgs,
+    #[command(flatten)]
+    pub batch: BatchArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct SplitArgs {
+    /// The PNG file to split
+    pub file: PathBuf,
+    /// The directory to write the chunk files and manifest.json to, created if need be
+    #[arg(long)]
+    pub out_dir: PathBuf,
+    #[command(flatten)]
+    pub parse: ParseArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct AssembleArgs {
+    /// The manifest.json written by `split`; chunk files are read relative to it
+    pub manifest: PathBuf,
+    /// Where to write the rebuilt PNG
+    pub output: PathBuf,
+    #[command(flatten)]
+    pub write: WriteArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct OptimizeArgs {
+    /// The PNG file to optimize, a directory with --recursive, or a quoted glob pattern
+    #[arg(required_unless_present = "files_from")]
+    pub file: Option<PathBuf>,
+    /// Where to write the optimized PNG; defaults to overwriting the input file
+    #[arg(conflicts_with = "recursive")]
+    pub output: Option<PathBuf>,
+    /// The zlib level to recompress the image data at, from 0 to 9
+    #[arg(long, default_value_t = optimize::DEFAULT_LEVEL,
+          value_parser = clap::value_parser!(u32).range(0..=9))]
+    pub level: u32,
+    /// Keep tEXt, zTXt and iTXt chunks
+    #[arg(long)]
+    pub keep_text: bool,
+    #[command(flatten)]
+    pub parse: ParseArgs,
+    #[command(flatten)]
+    pub write: WriteArgs,
+    #[command(flatten)]
+    pub batch: BatchArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct TextArgs {
+    #[command(subcommand)]
+    pub command: TextCommand,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum TextCommand {
+    /// Print the text stored under a keyword, or every keyword and its text
+    Get(TextGetArgs),
+    /// Store text under a keyword, replacing any existing text with that keyword and language.
+    /// Text which isn't Latin-1 is stored as UTF-8 in an iTXt chunk.
+    Set(TextSetArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct TextGetArgs {
+    /// The PNG file to read
+    pub file: PathBuf,
+    /// Only print the text stored under this keyword
+    pub keyword: Option<String>,
+    #[command(flatten)]
+    pub parse: ParseArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct TextSetArgs {
+    /// The PNG file to modify in place
+    pub file: PathBuf,
+    /// The keyword, e.g. `Title` or `Author`
+    pub keyword: String,
+    /// The text to store
+    pub value: String,
+    /// Store the text zlib compressed, in a zTXt or compressed iTXt chunk
+    #[arg(long)]
+    pub compress: bool,
+    /// Tag the text with a language such as `en` or `pt-BR`; stores it in an iTXt chunk
+    #[arg(long)]
+    pub language: Option<String>,
+    /// The keyword translated into the text's language; stores it in an iTXt chunk
+    #[arg(long)]
+    pub translated_keyword: Option<String>,
+    #[command(flatten)]
+    pub parse: ParseArgs,
+    #[command(flatten)]
+    pub write: WriteArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct XmpArgs {
+    #[command(subcommand)]
+    pub command: XmpCommand,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum XmpCommand {
+    /// Print the XMP packet, or write it to a file
+    Get(XmpGetArgs),
+    /// Store the XMP packet read from a file, replacing any existing one
+    Set(XmpSetArgs),
+    /// Remove the XMP packet
+    Remove(XmpRemoveArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct XmpGetArgs {
+    /// The PNG file to read
+    pub file: PathBuf,
+    /// Write the packet to this file instead of printing it
+    #[arg(long)]
+    pub out: Option<PathBuf>,
+    #[command(flatten)]
+    pub parse: ParseArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct XmpSetArgs {
+    /// The PNG file to modify in place
+    pub file: PathBuf,
+    /// A file holding the XMP packet, e.g. one exported by exiftool
+    pub packet: PathBuf,
+    #[command(flatten)]
+    pub parse: ParseArgs,
+    #[command(flatten)]
+    pub write: WriteArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct XmpRemoveArgs {
+    /// The PNG file to modify in place
+    pub file: PathBuf,
+    #[command(flatten)]
+    pub parse: ParseArgs,
+    #[command(flatten)]
+    pub write: WriteArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct TimeArgs {
+    #[command(subcommand)]
+    pub command: TimeCommand,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum TimeCommand {
+    /// Print the last modification time
+    Get(TimeGetArgs),
+    /// Set the last modification time
+    Set(TimeSetArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct TimeGetArgs {
+    /// The PNG file to read
+    pub file: PathBuf,
+    #[command(flatten)]
+    pub parse: ParseArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct TimeSetArgs {
+    /// The PNG file to modify in place
+    pub file: PathBuf,
+    /// The UTC time as `YYYY-MM-DDTHH:MM:SS`; defaults to now
+    pub time: Option<String>,
+    #[command(flatten)]
+    pub parse: ParseArgs,
+    #[command(flatten)]
+    pub save: SaveArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct DpiArgs {
+    #[command(subcommand)]
+    pub command: DpiCommand,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum DpiCommand {
+    /// Print the pixel density
+    Get(DpiGetArgs),
+    /// Set the pixel density in dots per inch
+    Set(DpiSetArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct DpiGetArgs {
+    /// The PNG file to read
+    pub file: PathBuf,
+    #[command(flatten)]
+    pub parse: ParseArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct DpiSetArgs {
+    /// The PNG file to modify in place
+    pub file: PathBuf,
+    /// The horizontal dots per inch, also used vertically unless --vertical is given
+    pub dpi: f64,
+    /// A different vertical dots per inch, for non-square pixels
+    #[arg(long)]
+    pub vertical: Option<f64>,
+    #[command(flatten)]
+    pub parse: ParseArgs,
+    #[command(flatten)]
+    pub write: WriteArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct GammaArgs {
+    #[command(subcommand)]
+    pub command: GammaCommand,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum GammaCommand {
+    /// Print the image gamma and any conflict with the sRGB or iCCP chunks
+    Get(GammaGetArgs),
+    /// Set the image gamma
+    Set(GammaSetArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct GammaGetArgs {
+    /// The PNG file to read
+    pub file: PathBuf,
+    #[command(flatten)]
+    pub parse: ParseArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct GammaSetArgs {
+    /// The PNG file to modify in place
+    pub file: PathBuf,
+    /// The decoding exponent, e.g. 2.2, which is stored as its reciprocal
+    pub exponent: f64,
+    #[command(flatten)]
+    pub parse: ParseArgs,
+    #[command(flatten)]
+    pub write: WriteArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct IccArgs {
+    #[command(subcommand)]
+    pub command: IccCommand,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum IccCommand {
+    /// Write the embedded ICC profile to a file
+    Extract(IccExtractArgs),
+    /// Embed an ICC profile, replacing any existing profile and sRGB chunk
+    Embed(IccEmbedArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct IccExtractArgs {
+    /// The PNG file to read
+    pub file: PathBuf,
+    /// Where to write the uncompressed profile
+    pub profile: PathBuf,
+    #[command(flatten)]
+    pub parse: ParseArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct IccEmbedArgs {
+    /// The PNG file to modify in place
+    pub file: PathBuf,
+    /// The ICC profile to embed
+    pub profile: PathBuf,
+    /// The profile name to store alongside it
+    #[arg(long, default_value = "ICC profile")]
+    pub name: String,
+    #[command(flatten)]
+    pub parse: ParseArgs,
+    #[command(flatten)]
+    pub write: WriteArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct ApngArgs {
+    #[command(subcommand)]
+    pub command: ApngCommand,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ApngCommand {
+    /// Write each frame out as a still PNG, drawn over the frames before it as a viewer would
+    /// show it
+    Explode(ApngExplodeArgs),
+    /// Put still PNGs of the same size and format together as the frames of an animated PNG
+    Build(ApngBuildArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct ApngExplodeArgs {
+    /// The animated PNG to read
+    pub file: PathBuf,
+    /// The directory to write the frames to, created if need be, as frame-000.png onwards
+    #[arg(long)]
+    pub out_dir: PathBuf,
+    #[command(flatten)]
+    pub parse: ParseArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct ApngBuildArgs {
+    /// The frames in order; the first is also shown by viewers which don't support APNG
+    #[arg(required = true)]
+    pub frames: Vec<PathBuf>,
+    /// How long to show each frame, in milliseconds
+    #[arg(long, default_value_t = 100)]
+    pub delay: u16,
+    /// How many times to play the animation, or 0 to loop forever
+    #[arg(long, default_value_t = 0)]
+    pub plays: u32,
+    /// Where to write the animated PNG
+    #[arg(long)]
+    pub out: PathBuf,
+    #[command(flatten)]
+    pub parse: ParseArgs,
+    #[command(flatten)]
+    pub write: WriteArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct AnalyzeArgs {
+    /// The PNG file to analyze, a directory with --recursive, or a quoted glob pattern
+    #[arg(required_unless_present = "files_from")]
+    pub file: Option<PathBuf>,
+    #[command(flatten)]
+    pub parse: ParseArgs,
+    #[command(flatten)]
+    pub batch: BatchArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct CarveArgs {
+    /// The file to search for PNGs
+    pub blob: PathBuf,
+    /// The directory to write the PNGs found to, created if need be, each named after the
+    /// offset it was found at
+    #[arg(long)]
+    pub out_dir: PathBuf,
+    /// Also extract PNGs which were cut short, up to their last whole chunk
+    #[arg(long)]
+    pub truncated: bool,
+    /// Memory-map the file instead of reading it into memory, for disk images
+    #[cfg(feature = "mmap")]
+    #[arg(long)]
+    pub mmap: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct ScanArgs {
+    /// A PNG file, a directory to search for PNG files or a quoted glob pattern such as
+    /// 'assets/**/*.png'
+    #[arg(required_unless_present = "files_from")]
+    pub path: Option<PathBuf>,
+    /// Report text chunks holding more than this many bytes
+    #[arg(long, default_value_t = ScanOptions::default().text_threshold)]
+    pub text_threshold: usize,
+    /// Don't decode the images to look for known tools' payloads in their pixels, which is the
+    /// slowest part of a scan
+    #[arg(long)]
+    pub no_pixels: bool,
+    /// Also write a human-readable audit report to this file, as HTML or Markdown depending on
+    /// its extension
+    #[arg(long, value_name = "FILE")]
+    pub report: Option<PathBuf>,
+    /// Scan this many files at once, or one per CPU core if 0. Output stays in file order.
+    #[arg(short, long, default_value_t = 1, value_name = "N")]
+    pub jobs: usize,
+    #[command(flatten)]
+    pub walk: WalkArgs,
+    #[command(flatten)]
+    pub list: FileListArgs,
+    #[command(flatten)]
+    pub parse: ParseArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct ValidateArgs {
+    /// The PNG file to check, a directory with --recursive, or a quoted glob pattern
+    #[arg(required_unless_present_any = ["files_from", "list_rules"])]
+    pub file: Option<PathBuf>,
+    /// List every rule with the severity it's reported at and what it checks, then exit
+    #[arg(long)]
+    pub list_rules: bool,
+    #[command(flatten)]
+    pub handlers: HandlerArgs,
+    #[command(flatten)]
+    pub lint: LintArgs,
+    #[command(flatten)]
+    pub batch: BatchArgs,
+    #[command(flatten)]
+    pub parse: ParseArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct RepairArgs {
+    /// The PNG file to repair in place, a directory with --recursive, or a quoted glob pattern
+    #[arg(required_unless_present = "files_from")]
+    pub file: Option<PathBuf>,
+    /// Salvage a file cut off mid-chunk by dropping the incomplete tail and appending IEND
+    #[arg(long)]
+    pub truncated: bool,
+    #[command(flatten)]
+    pub save: SaveArgs,
+    #[command(flatten)]
+    pub batch: BatchArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct CapacityArgs {
+    /// The PNG file to read
+    pub file: PathBuf,
+    /// The size in bytes of the payload you plan to hide
+    #[arg(long)]
+    pub payload_size: Option<usize>,
+    /// Split payloads larger than this many bytes across several chunks
+    #[arg(long, default_value_t = segment::DEFAULT_MAX_CHUNK_LEN)]
+    pub max_chunk_size: usize,
+    #[command(flatten)]
+    pub parse: ParseArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct StripArgs {
+    /// The PNG file to read, a directory with --recursive, or a quoted glob pattern
+    #[arg(required_unless_present = "files_from")]
+    pub file: Option<PathBuf>,
+    /// Where to write the stripped PNG; defaults to overwriting the input file
+    #[arg(conflicts_with = "recursive")]
+    pub output: Option<PathBuf>,
+    /// Keep ancillary chunks of this type; may be repeated
+    #[arg(long, value_name = "CHUNK_TYPE")]
+    pub keep: Vec<String>,
+    /// Remove only chunks of this type, leaving other ancillary chunks alone; may be repeated
+    #[arg(long, value_name = "CHUNK_TYPE")]
+    pub remove: Vec<String>,
+    /// Leave any data after the IEND chunk in place
+    #[arg(long)]
+    pub keep_trailing_data: bool,
+    /// Only remove the GPS tags from EXIF data, leaving every chunk in place
+    #[arg(long, conflicts_with_all = ["keep", "remove", "keep_trailing_data"])]
+    pub gps_only: bool,
+    #[command(flatten)]
+    pub parse: ParseArgs,
+    #[command(flatten)]
+    pub write: WriteArgs,
+    #[command(flatten)]
+    pub batch: BatchArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct AnonymizeArgs {
+    /// The PNG files to scrub in place
+    #[arg(required = true)]
+    pub files: Vec<PathBuf>,
+    /// Keep EXIF data, removing only its GPS tags
+    #[arg(long)]
+    pub keep_exif: bool,
+    #[command(flatten)]
+    pub parse: ParseArgs,
+    #[command(flatten)]
+    pub save: SaveArgs,
+}
+
+#[cfg(feature = "tui")]
+#[derive(Args, Debug)]
+pub struct TuiArgs {
+    /// The PNG file to browse; edits are written back to it when saved
+    pub file: PathBuf,
+    /// Keep the original file alongside the edited one, named with this suffix or `~`
+    #[arg(
+        long,
+        value_name = "SUFFIX",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "~"
+    )]
+    pub backup: Option<String>,
+    /// Keep the original modification time and, on Unix, owner and group
+    #[arg(long)]
+    pub preserve: bool,
+    #[command(flatten)]
+    pub parse: ParseArgs,
+}
+
+#[cfg(feature = "serve")]
+#[derive(Args, Debug)]
+#[command(after_help = SERVE_HELP)]
+pub struct ServeArgs {
+    /// The address and port to listen on
+    #[arg(long, default_value = "127.0.0.1:8080", value_name = "ADDR")]
+    pub listen: String,
+    /// Refuse uploads larger than this many bytes
+    #[arg(long, default_value_t = 64 * 1024 * 1024, value_name = "BYTES")]
+    pub max_size: usize,
+    /// Answer this many requests at once, so that a slow upload doesn't hold up the rest
+    #[arg(long, default_value_t = 4, value_name = "N",
+          value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    pub threads: usize,
+    /// Refuse to decode a payload whose key derivation takes more than this much memory, in KiB
+    #[arg(long, default_value_t = 4 * KdfParams::DEFAULT.memory_kib, value_name = "KIB")]
+    pub max_kdf_memory: u32,
+    /// Refuse to decode a payload whose key derivation takes more than this many passes
+    #[arg(long, default_value_t = 4 * KdfParams::DEFAULT.iterations, value_name = "N")]
+    pub max_kdf_iterations: u32,
+    /// Refuse to decode a payload whose key derivation takes more than this many lanes
+    #[arg(long, default_value_t = 4, value_name = "N")]
+    pub max_kdf_parallelism: u32,
+}
+
+#[cfg(feature = "serve")]
+impl ServeArgs {
+    /// The most a request may ask key derivation to cost. Each thread may be deriving a key at
+    /// once, so this is kept well below what a file on the command line may ask for.
+    pub fn kdf_limit(&self) -> KdfParams {
+        KdfParams {
+            memory_kib: self.max_kdf_memory,
+            iterations: self.max_kdf_iterations,
+            parallelism: self.max_kdf_parallelism,
+        }
+    }
+}
+
+#[cfg(feature = "serve")]
+const SERVE_HELP: &str = "\
+Endpoints, each taking a PNG file as the body of a POST request:
+  /chunks                               the chunk inventory, as JSON
+  /validate                             the validation report, as JSON
+  /decode?chunk_type=T                  the payload in chunks of type T, as JSON
+  /encode?chunk_type=T                  a copy of the PNG with the X-Message header encoded
+                                        into chunks of type T
+
+/encode takes its message percent-encoded in the X-Message header, and /decode and /encode
+take a passphrase in the X-Passphrase header.";
+
+#[cfg(all(unix, feature = "daemon"))]
+#[derive(Args, Debug)]
+#[command(after_help = DAEMON_HELP)]
+pub struct DaemonArgs {
+    /// The path of the Unix socket to listen on
+    #[arg(long, value_name = "PATH")]
+    pub socket: PathBuf,
+    /// How many parsed files to keep cached; the least recently used are dropped first
+    #[arg(long, default_value_t = 1024, value_name = "FILES")]
+    pub cache_size: usize,
+    /// Keep the original of each file encoded in place, named with this suffix or `~`
+    #[arg(
+        long,
+        value_name = "SUFFIX",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "~"
+    )]
+    pub backup: Option<String>,
+    /// Keep the original modification time and, on Unix, owner and group
+    #[arg(long)]
+    pub preserve: bool,
+}
+
+#[cfg(all(unix, feature = "daemon"))]
+const DAEMON_HELP: &str = "\
+Each request and response is a JSON object, sent as a 4-byte big-endian length followed by
+that many bytes. A connection may carry any number of requests, answered in order.
+
+Requests:
+  {\"command\": \"validate\", \"file\": F}
+  {\"command\": \"decode\", \"file\": F, \"chunk_type\": T, \"passphrase\": P}
+  {\"command\": \"encode\", \"file\": F, \"chunk_type\": T, \"message\": M, \"passphrase\": P, \"output\": O}
+
+passphrase and output are optional; encode overwrites the file unless given an output.
+file and output must be absolute paths. Responses carry \"ok\": true, or \"ok\": false
+and an \"error\" message.
+
+The socket is only accessible to its owner, and is removed when the daemon is stopped.";
+
+#[cfg(feature = "shell")]
+#[derive(Args, Debug)]
+pub struct ShellArgs {
+    /// The PNG file to edit; `save` with no path writes back to it
+    pub file: PathBuf,
+    /// Keep the original file alongside the edited one, named with this suffix or `~`
+    #[arg(
+        long,
+        value_name = "SUFFIX",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "~"
+    )]
+    pub backup: Option<String>,
+    /// Keep the original modification time and, on Unix, owner and group
+    #[arg(long)]
+    pub preserve: bool,
+    #[command(flatten)]
+    pub parse: ParseArgs,
+}
+
+/// Commands which edit the file loaded by `pngmi shell`
+#[cfg(feature = "shell")]
+#[derive(Parser, Debug)]
+#[command(multicall = true)]
+pub struct ShellLine {
+    #[command(subcommand)]
+    pub command: ShellCommand,
+}
+
+#[cfg(feature = "shell")]
+#[derive(Subcommand, Debug)]
+pub enum ShellCommand {
+    /// List the chunks of the file as it stands
+    Print,
+    /// Remove the Nth chunk of the given type, counting from 1
+    Remove {
+        chunk_type: String,
+        #[arg(default_value_t = 1, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+        nth: usize,
+    },
+    /// Encode a message into new chunks of the given type, before IEND
+    Encode {
+        chunk_type: String,
+        message: String,
+        /// Encrypt the message with a key derived from this passphrase
+        #[arg(long)]
+        passphrase: Option<String>,
+    },
+    /// Print the message stored in chunks of the given type
+    Decode {
+        chunk_type: String,
+        /// Passphrase used when the message was encoded
+        #[arg(long)]
+        passphrase: Option<String>,
+        /// Write the payload to this file instead of printing it
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+    /// Write the edited file, to the given path or back over the file loaded
+    Save { path: Option<PathBuf> },
+    /// Leave the shell; asks again if there are unsaved changes
+    #[command(visible_alias = "exit")]
+    Quit,
+}
+
+#[cfg(feature = "watch")]
+#[derive(Args, Debug)]
+pub struct WatchArgs {
+    /// The directory to watch
+    pub dir: PathBuf,
+    /// The pngmi command to run on each new or changed file, e.g. 'strip' or
+    /// 'encode {} ruSt "hello"'. The file's path replaces `{}`, or is added at the end.
+    #[arg(long, value_name = "COMMAND")]
+    pub on_add: String,
+    /// Watch subdirectories too
+    #[arg(short, long)]
+    pub recursive: bool,
+    /// Wait until a file has gone this many milliseconds without changing before running the
+    /// command on it
+    #[arg(long, default_value_t = 500, value_name = "MS")]
+    pub delay: u64,
+}
+
+#[derive(Args, Debug)]
+pub struct CompletionsArgs {
+    /// The shell to complete for: bash, elvish, fish, powershell or zsh
+    pub shell: Shell,
+}
+
+#[derive(Args, Debug)]
+pub struct ManArgs {
+    /// Write a page for every command into this directory, named like pngmi-encode.1, rather
+    /// than printing the page for pngmi itself
+    #[arg(long, value_name = "DIR")]
+    pub out_dir: Option<PathBuf>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::FromArgMatches;
+
+    fn encode_args(args: &[&str]) -> EncodeArgs {
+        let cli = Cli::try_parse_from([&["pngmi", "encode"], args].concat()).unwrap();
+        match cli.command {
+            Command::Encode(args) => args,
+            command => panic!("Parsed as {:?}", command),
+        }
+    }
+
+    #[test]
+    fn test_encode_positionals() {
+        let args = encode_args(&["in.png", "ruSt", "hello", "-o", "out.png"]);
+        assert_eq!(args.chunk_type(), Some("ruSt"));
+        assert_eq!(args.message(), Some("hello"));
+        assert_eq!(args.output_path(), Path::new("out.png"));
+
+        let args = encode_args(&["in.png", "hello", "--method", "lsb"]);
+        assert_eq!(args.chunk_type(), None);
+        assert_eq!(args.message(), Some("hello"));
+        assert_eq!(args.output_path(), Path::new("in.png"));
+
+        let args = encode_args(&["in.png", "ruSt", "--payload-file", "secret.pdf"]);
+        assert_eq!(args.chunk_type(), Some("ruSt"));
+        assert_eq!(args.message(), None);
+        assert_eq!(args.unexpected_positional(), None);
+    }
+
+    #[test]
+    fn test_encode_message_conflicts_with_payload_file() {
+        let args = [
+            "pngmi",
+            "encode",
+            "in.png",
+            "ruSt",
+            "hi",
+            "--payload-file",
+            "a.pdf",
+        ];
+        assert!(Cli::try_parse_from(args).is_err());
+        let args = encode_args(&[
+            "in.png",
+            "out.png",
+            "--method",
+            "lsb",
+            "--payload-file",
+            "a",
+        ]);
+        assert_eq!(args.unexpected_positional(), Some("out.png"));
+    }
+
+    /// The command line parsed, with settings it leaves out taken from the config
+    fn configured(args: &[&str], config: &str) -> Cli {
+        let matches = Cli::command().get_matches_from([&["pngmi"], args].concat());
+        let mut cli = Cli::from_arg_matches(&matches).unwrap();
+        let config = Config::from_toml(config, Path::new("")).unwrap();
+        cli.apply_config(config, &matches).unwrap();
+        cli
+    }
+
+    #[test]
+    fn test_apply_config() {
+        let config = r#"
+            chunk-type = "ruSt"
+            format = "json"
+            ignore = ["vendor/**"]
+
+            [encryption]
+            kdf-iterations = 4
+        "#;
+        let cli = configured(&["encode", "in.png", "hello"], config);
+        assert_eq!(cli.format, OutputFormat::Json);
+        let Command::Encode(args) = cli.command else {
+            panic!("Parsed as {:?}", cli.command);
+        };
+        assert_eq!(args.chunk_type(), Some("ruSt"));
+        assert_eq!(args.message(), Some("hello"));
+        assert_eq!(args.kdf_iterations, 4);
+
+        let cli = configured(
+            &[
+                "encode",
+                "in.png",
+                "abCd",
+                "hi",
+                "--passphrase",
+                "pw",
+                "--kdf-iterations",
+                "2",
+            ],
+            config,
+        );
+        let Command::Encode(args) = cli.command else {
+            panic!("Parsed as {:?}", cli.command);
+        };
+        assert_eq!(args.chunk_type(), Some("abCd"));
+        assert_eq!(args.kdf_iterations, 2);
+
+        let cli = configured(&["--format", "text", "print", "in.png"], config);
+        assert_eq!(cli.format, OutputFormat::Text);
+        let Command::Print(args) = cli.command else {
+            panic!("Parsed as {:?}", cli.command);
+        };
+        assert!(args.batch.walk.ignore.is_match("vendor/a.png"));
+        assert!(!args.batch.walk.ignore.is_match("a.png"));
+
+        let matches = Cli::command().get_matches_from(["pngmi", "scan", "dir"]);
+        let mut cli = Cli::from_arg_matches(&matches).unwrap();
+        let config = Config::from_toml(r#"ignore = ["[z-a]"]"#, Path::new("")).unwrap();
+        assert!(cli.apply_config(config, &matches).is_err());
+    }
+
+    #[test]
+    fn test_encode_chunk_options_conflict_with_other_methods() {
+        let check = |args: &[&str]| {
+            let matches =
+                Cli::command().try_get_matches_from([&["pngmi", "encode"], args].concat())?;
+            Cli::from_arg_matches(&matches)?.check_conflicts(&matches)
+        };
+        assert!(check(&["in.png", "ruSt", "hi", "--position", "after-ihdr"]).is_ok());
+        assert!(check(&["in.png", "hi", "--method", "lsb"]).is_ok());
+        for method in ["lsb", "trailer"] {
+            let error = check(&["in.png", "hi", "--method", method, "--max-chunk-size", "9"]);
+            assert_eq!(error.unwrap_err().kind(), ErrorKind::ArgumentConflict);
+            let error = check(&[
+                "in.png",
+                "hi",
+                "--position",
+                "after-ihdr",
+                "--method",
+                method,
+            ]);
+            assert_eq!(error.unwrap_err().kind(), ErrorKind::ArgumentConflict);
+        }
+    }
+}
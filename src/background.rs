@@ -0,0 +1,164 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::ihdr::{ColorType, Ihdr};
+use crate::png::Png;
+use crate::PngResult;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// The colour to show the image against, as stored in a bKGD chunk. The layout depends on the
+/// image's colour type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Background {
+    /// A grey level, for greyscale images with or without alpha
+    Gray(u16),
+    /// Red, green and blue samples, for truecolour images with or without alpha
+    Rgb(u16, u16, u16),
+    /// An entry of the palette, for indexed images
+    PaletteIndex(u8),
+}
+
+impl Background {
+    pub const CHUNK_TYPE: &'static str = "bKGD";
+
+    /// Decode bKGD chunk data for an image with the given header and palette size
+    pub fn decode(data: &[u8], ihdr: &Ihdr, palette_len: Option<usize>) -> PngResult<Self> {
+        let background = match (ihdr.color_type, data) {
+            (ColorType::Indexed, [index]) => Background::PaletteIndex(*index),
+            (ColorType::Grayscale | ColorType::GrayscaleAlpha, [high, low]) => {
+                Background::Gray(u16::from_be_bytes([*high, *low]))
+            }
+            (ColorType::Truecolor | ColorType::TruecolorAlpha, [r0, r1, g0, g1, b0, b1]) => {
+                Background::Rgb(
+                    u16::from_be_bytes([*r0, *r1]),
+                    u16::from_be_bytes([*g0, *g1]),
+                    u16::from_be_bytes([*b0, *b1]),
+                )
+            }
+            _ => {
+                return Err(format!(
+                    "bKGD data is {} bytes long, which doesn't suit {} images",
+                    data.len(),
+                    ihdr.color_type
+                )
+                .into())
+            }
+        };
+        background.check(ihdr, palette_len)?;
+        Ok(background)
+    }
+
+    /// Check the background suits the image's colour type, bit depth and palette
+    pub fn check(&self, ihdr: &Ihdr, palette_len: Option<usize>) -> PngResult<()> {
+        let max = (1u32 << ihdr.bit_depth) - 1;
+        let samples = match (self, ihdr.color_type) {
+            (Background::PaletteIndex(index), ColorType::Indexed) => {
+                let palette_len = palette_len.ok_or("bKGD chunk found but no PLTE chunk")?;
+                if *index as usize >= palette_len {
+                    return Err(format!(
+                        "bKGD palette index {} is past the end of the {} entry palette",
+                        index, palette_len
+                    )
+                    .into());
+                }
+                return Ok(());
+            }
+            (Background::Gray(gray), ColorType::Grayscale | ColorType::GrayscaleAlpha) => {
+                vec![*gray]
+            }
+            (
+                Background::Rgb(red, green, blue),
+                ColorType::Truecolor | ColorType::TruecolorAlpha,
+            ) => vec![*red, *green, *blue],
+            _ => {
+                return Err(format!("bKGD {} doesn't suit {} images", self, ihdr.color_type).into())
+            }
+        };
+        match samples.iter().find(|sample| **sample as u32 > max) {
+            Some(sample) => Err(format!(
+                "bKGD sample {} is too large for a bit depth of {}",
+                sample, ihdr.bit_depth
+            )
+            .into()),
+            None => Ok(()),
+        }
+    }
+
+    pub fn as_bytes(&self) -> Vec<u8> {
+        match self {
+            Background::Gray(gray) => gray.to_be_bytes().to_vec(),
+            Background::Rgb(red, green, blue) => [red, green, blue]
+                .iter()
+                .flat_map(|sample| sample.to_be_bytes())
+                .collect(),
+            Background::PaletteIndex(index) => vec![*index],
+        }
+    }
+
+    pub fn to_chunk(&self) -> PngResult<Chunk> {
+        Ok(Chunk::new(
+            ChunkType::from_str(Self::CHUNK_TYPE)?,
+            self.as_bytes(),
+        ))
+    }
+}
+
+impl Display for Background {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Background::Gray(gray) => write!(f, "gray {}", gray),
+            Background::Rgb(red, green, blue) => write!(f, "rgb({}, {}, {})", red, green, blue),
+            Background::PaletteIndex(index) => write!(f, "palette index {}", index),
+        }
+    }
+}
+
+/// Decode the bKGD chunk of the PNG, if it has one
+pub fn get_background(png: &Png) -> PngResult<Option<Background>> {
+    let Some(chunk) = png.chunk_by_type(Background::CHUNK_TYPE) else {
+        return Ok(None);
+    };
+    let ihdr = Ihdr::try_from(png)?;
+    let palette_len = png
+        .chunk_by_type("PLTE")
+        .map(|palette| palette.data().len() / 3);
+    Background::decode(chunk.data(), &ihdr, palette_len).map(Some)
+}
+
+/// Store the background colour, replacing any existing bKGD chunk. It must suit the image,
+/// and comes after PLTE but before the first IDAT chunk.
+pub fn set_background(png: &mut Png, background: Background) -> PngResult<()> {
+    let ihdr = Ihdr::try_from(&*png)?;
+    let palette_len = png
+        .chunk_by_type("PLTE")
+        .map(|palette| palette.data().len() / 3);
+    background.check(&ihdr, palette_len)?;
+    png.set_unique_chunk(background.to_chunk()?, &["IDAT", "IEND"]);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::ihdr;
+
+    #[test]
+    fn test_background() {
+        let truecolor = ihdr(8, ColorType::TruecolorAlpha);
+        let rgb = Background::decode(&[0, 1, 0, 2, 0, 3], &truecolor, None).unwrap();
+        assert_eq!(rgb, Background::Rgb(1, 2, 3));
+        assert_eq!(rgb.as_bytes(), vec![0, 1, 0, 2, 0, 3]);
+        assert!(Background::decode(&[1, 0, 0, 2, 0, 3], &truecolor, None).is_err());
+        assert!(Background::decode(&[0, 1], &truecolor, None).is_err());
+
+        let indexed = ihdr(4, ColorType::Indexed);
+        let index = Background::decode(&[2], &indexed, Some(3)).unwrap();
+        assert_eq!(index.to_string(), "palette index 2");
+        assert!(Background::decode(&[3], &indexed, Some(3)).is_err());
+        assert!(Background::Gray(1).check(&indexed, Some(3)).is_err());
+
+        let gray = ihdr(1, ColorType::Grayscale);
+        assert!(Background::decode(&[0, 1], &gray, None).is_ok());
+        assert!(Background::decode(&[0, 2], &gray, None).is_err());
+    }
+}
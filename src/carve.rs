@@ -0,0 +1,129 @@
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+use crate::raw::RawChunks;
+use std::ops::Range;
+
+/// A PNG found inside some larger file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Carved {
+    /// Where its signature starts
+    pub offset: usize,
+    /// Its length in bytes, up to the end of IEND or, if it was cut short, of its last whole
+    /// chunk
+    pub len: usize,
+    /// The number of whole chunks found
+    pub chunks: usize,
+    /// Whether the chunks run all the way to IEND
+    pub complete: bool,
+    /// The number of chunks whose stored CRC doesn't match their contents
+    pub crc_errors: usize,
+}
+
+impl Carved {
+    /// Where the PNG lies in the data it was carved from
+    pub fn range(&self) -> Range<usize> {
+        self.offset..self.offset + self.len
+    }
+}
+
+/// Follow the chunks from a PNG signature at `offset` for as long as they're well formed.
+/// Returns `None` if the signature isn't followed by an IHDR chunk, as then it's unlikely to
+/// be a PNG at all.
+fn follow(data: &[u8], offset: usize) -> Option<Carved> {
+    let mut carved = Carved {
+        offset,
+        len: Png::HEADER_BYTES_LEN,
+        chunks: 0,
+        complete: false,
+        crc_errors: 0,
+    };
+    for chunk in RawChunks::at(data, offset + Png::HEADER_BYTES_LEN) {
+        // Past the end of a damaged PNG the "chunks" are whatever data follows it
+        let Ok(chunk) = chunk else { break };
+        if ChunkType::try_from(chunk.chunk_type).is_err()
+            || (carved.chunks == 0 && !chunk.is(b"IHDR"))
+        {
+            break;
+        }
+        carved.len = chunk.end() - offset;
+        carved.chunks += 1;
+        carved.complete = chunk.is(b"IEND");
+        if !chunk.crc_matches() {
+            carved.crc_errors += 1;
+        }
+    }
+    (carved.chunks > 0).then_some(carved)
+}
+
+/// Find every PNG in `data`, such as a memory dump or disk image, by looking for the PNG
+/// signature and following the chunks after it to find where each one ends. PNGs inside
+/// other PNGs, such as thumbnails, are found as well.
+pub fn carve(data: &[u8]) -> Vec<Carved> {
+    data.windows(Png::HEADER_BYTES_LEN)
+        .enumerate()
+        .filter(|(_, window)| *window == Png::STANDARD_HEADER)
+        .filter_map(|(offset, _)| follow(data, offset))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+    use std::str::FromStr;
+
+    fn png_bytes(chunk_types: &[&str]) -> Vec<u8> {
+        let chunks = chunk_types
+            .iter()
+            .map(|t| Chunk::new(ChunkType::from_str(t).unwrap(), t.as_bytes().to_vec()))
+            .collect();
+        Png::from_chunks(chunks).as_bytes()
+    }
+
+    #[test]
+    fn test_carve() {
+        let first = png_bytes(&["IHDR", "IDAT", "IEND"]);
+        let second = png_bytes(&["IHDR", "tEXt", "IDAT", "IEND"]);
+        let mut blob = b"memory dump ".to_vec();
+        blob.extend_from_slice(&first);
+        blob.extend_from_slice(b"\0\0\0\0");
+        let second_offset = blob.len();
+        blob.extend_from_slice(&second);
+        // A third is cut off partway through its IDAT chunk
+        let third_offset = blob.len();
+        let third = png_bytes(&["IHDR", "IDAT", "IEND"]);
+        blob.extend_from_slice(&third[..30]);
+
+        let carved = carve(&blob);
+        assert_eq!(carved.len(), 3);
+        assert_eq!(&blob[carved[0].range()], first.as_slice());
+        assert!(carved[0].complete);
+        assert_eq!(carved[1].offset, second_offset);
+        assert_eq!(carved[1].chunks, 4);
+        assert_eq!(&blob[carved[1].range()], second.as_slice());
+        assert_eq!(
+            carved[2],
+            Carved {
+                offset: third_offset,
+                len: Png::HEADER_BYTES_LEN + 16,
+                chunks: 1,
+                complete: false,
+                crc_errors: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn test_damaged_chunks() {
+        let mut blob = png_bytes(&["IHDR", "IDAT", "IEND"]);
+        let len = blob.len();
+        blob[Png::HEADER_BYTES_LEN + 9] ^= 1;
+        blob.extend_from_slice(&Png::STANDARD_HEADER);
+        blob.extend_from_slice(b"not a chunk at all");
+
+        let carved = carve(&blob);
+        assert_eq!(carved.len(), 1);
+        assert_eq!(carved[0].len, len);
+        assert_eq!(carved[0].crc_errors, 1);
+    }
+}
@@ -1,9 +1,10 @@
 use crate::chunk_type::ChunkType;
+use crate::error::{error, with_kind, ErrorKind};
 use crate::{PngError, PngResult};
-use crc::Crc;
 use std::fmt::{Display, Formatter};
 
 /// Chunk represents a PNG chunk as detailed out in the PNG spec
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Chunk {
     length: u32,
     chunk_type: ChunkType,
@@ -18,27 +19,41 @@ impl Chunk {
     /// The number of bytes taken up by the chunk type field
     const CHUNK_TYPE_BYTES_LEN: usize = 4;
 
+    /// The number of bytes each chunk takes up on top of its data: the length, chunk type and
+    /// CRC fields
+    pub const OVERHEAD_BYTES_LEN: usize = 12;
+
+    /// The most data a chunk may hold, as its length field is limited to 2^31-1
+    pub const MAX_DATA_LEN: usize = i32::MAX as usize;
+
     /// Create a new `Chunk` from the given chunk type and payload.
     pub fn new(chunk_type: ChunkType, data: Vec<u8>) -> Self {
-        let crc = Crc::<u32>::new(&crc::CRC_32_ISO_HDLC);
-        let mut digest = crc.digest();
-        digest.update(&chunk_type.bytes());
-        digest.update(&data);
+        let checksum = Self::compute_crc(&chunk_type.bytes(), &data);
         Chunk {
             length: data.len() as u32,
             chunk_type,
             data,
-            checksum: digest.finalize(),
+            checksum,
         }
     }
 
+    /// Compute the CRC the spec requires over the chunk type and data fields. This uses the
+    /// SIMD or hardware CRC instructions where the CPU has them, as CRC checks dominate the
+    /// time taken to parse large files.
+    pub fn compute_crc(chunk_type: &[u8; 4], data: &[u8]) -> u32 {
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(chunk_type);
+        hasher.update(data);
+        hasher.finalize()
+    }
+
     /// The overall size of this chunk including chunk type, crc, data and length field
     pub fn overall_length(&self) -> u32 {
-        self.length + 12 // 4 * 3 -- 4 bytes each for chunk type, crc, length field
+        self.length + Self::OVERHEAD_BYTES_LEN as u32
     }
 
     /// The length of the data/payload held inside this chunk
-    fn length(&self) -> u32 {
+    pub fn length(&self) -> u32 {
         self.length
     }
 
@@ -48,12 +63,12 @@ impl Chunk {
     }
 
     /// Returns the payload for this chunk
-    fn data(&self) -> &[u8] {
+    pub fn data(&self) -> &[u8] {
         &self.data
     }
 
     /// Returns the CRC or checksum for this chunk
-    fn crc(&self) -> u32 {
+    pub fn crc(&self) -> u32 {
         self.checksum
     }
 
@@ -78,7 +93,7 @@ impl Chunk {
 fn read_4_bytes(slice: &[u8], start: usize, end: usize) -> [u8; 4] {
     // If we can't convert a 4 element slice to 4 element array, we panic };
     assert_eq!(end - start, 4);
-    (&slice[start..end]).to_vec().try_into().unwrap()
+    slice[start..end].to_vec().try_into().unwrap()
 }
 
 impl TryFrom<&[u8]> for Chunk {
@@ -86,25 +101,155 @@ impl TryFrom<&[u8]> for Chunk {
 
     /// Attempt to perform conversion from a byte slice to Chunk
     fn try_from(value: &[u8]) -> PngResult<Chunk> {
+        ChunkRef::try_from(value).map(|chunk| chunk.to_chunk())
+    }
+}
+
+/// A chunk whose data is borrowed from the bytes it was parsed from, so that read-only
+/// inspection of large files doesn't copy every IDAT payload
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkRef<'a> {
+    /// Where the chunk's length field starts in the bytes it was parsed from
+    offset: usize,
+    chunk_type: ChunkType,
+    data: &'a [u8],
+    checksum: u32,
+}
+
+impl<'a> ChunkRef<'a> {
+    /// A chunk found at `offset` whose CRC the caller has already checked
+    pub(crate) fn from_parts(
+        offset: usize,
+        chunk_type: ChunkType,
+        data: &'a [u8],
+        checksum: u32,
+    ) -> Self {
+        ChunkRef {
+            offset,
+            chunk_type,
+            data,
+            checksum,
+        }
+    }
+
+    /// The same chunk, found `offset` bytes into the input rather than at its start
+    pub(crate) fn at(self, offset: usize) -> Self {
+        ChunkRef { offset, ..self }
+    }
+
+    /// The offset of the chunk's length field within the bytes it was parsed from, or within
+    /// the file written out for chunks borrowed from a `Png`
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// The overall size of this chunk including chunk type, crc, data and length field
+    pub fn overall_length(&self) -> u32 {
+        self.length() + Chunk::OVERHEAD_BYTES_LEN as u32
+    }
+
+    /// The length of the data/payload held inside this chunk
+    pub fn length(&self) -> u32 {
+        self.data.len() as u32
+    }
+
+    /// Returns the type for this chunk
+    pub fn chunk_type(&self) -> &ChunkType {
+        &self.chunk_type
+    }
+
+    /// Returns the payload for this chunk
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// Returns the CRC or checksum for this chunk
+    pub fn crc(&self) -> u32 {
+        self.checksum
+    }
+
+    /// Check the stored CRC against the chunk type and data, for chunks parsed without
+    /// verification
+    pub fn verify_crc(&self) -> PngResult<()> {
+        if Chunk::compute_crc(&self.chunk_type.bytes(), self.data) != self.checksum {
+            return Err(error(
+                ErrorKind::Validation,
+                format!(
+                    "{} chunk: Incoming check does not match computed checksum",
+                    self.chunk_type
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Parse the chunk at the start of the byte slice without checking its CRC, for trusted
+    /// inputs where checksumming every chunk would be wasted work
+    pub fn try_from_unverified(value: &'a [u8]) -> PngResult<Self> {
+        if value.len() < Chunk::OVERHEAD_BYTES_LEN {
+            return Err(error(
+                ErrorKind::Parse,
+                "Truncated chunk: not enough bytes for the chunk fields",
+            ));
+        }
         // Read the length and chunk type bytes back to back
         let length = u32::from_be_bytes(read_4_bytes(value, 0, Chunk::LENGTH_BYTES_LEN));
         let chunk_type = ChunkType::try_from(read_4_bytes(
             value,
             Chunk::CHUNK_TYPE_BYTES_LEN,
             Chunk::CHUNK_TYPE_BYTES_LEN + Chunk::LENGTH_BYTES_LEN,
-        ))?;
+        ))
+        .map_err(|e| with_kind(ErrorKind::Parse, e))?;
 
         let start = Chunk::CHUNK_TYPE_BYTES_LEN + Chunk::LENGTH_BYTES_LEN;
-        let (data_start, data_end) = (start, start + length as usize);
-        let data = (&value[data_start..data_end]).to_vec();
-        let checksum = u32::from_be_bytes(read_4_bytes(value, data_end, data_end + 4));
-        let chunk = Chunk::new(chunk_type, data);
+        let (data_start, data_end) = (start, start.saturating_add(length as usize));
+        if data_end.saturating_add(4) > value.len() {
+            return Err(error(
+                ErrorKind::Parse,
+                "Truncated chunk: data extends past the end of the input",
+            ));
+        }
+        Ok(ChunkRef {
+            offset: 0,
+            chunk_type,
+            data: &value[data_start..data_end],
+            checksum: u32::from_be_bytes(read_4_bytes(value, data_end, data_end + 4)),
+        })
+    }
 
-        if chunk.checksum != checksum {
-            return Err("Incoming check does not match computed checksum".into());
+    /// Copy the data into an owned `Chunk`
+    pub fn to_chunk(&self) -> Chunk {
+        Chunk {
+            length: self.length(),
+            chunk_type: self.chunk_type,
+            data: self.data.to_vec(),
+            checksum: self.checksum,
         }
-        if chunk.length != length {
-            return Err("Incoming length does not match computed length".into());
+    }
+}
+
+impl<'a> From<&'a Chunk> for ChunkRef<'a> {
+    fn from(chunk: &'a Chunk) -> Self {
+        ChunkRef {
+            offset: 0,
+            chunk_type: chunk.chunk_type,
+            data: &chunk.data,
+            checksum: chunk.checksum,
+        }
+    }
+}
+
+impl<'a> TryFrom<&'a [u8]> for ChunkRef<'a> {
+    type Error = PngError;
+
+    /// Parse the chunk at the start of the byte slice, checking its type and CRC
+    fn try_from(value: &'a [u8]) -> PngResult<Self> {
+        let chunk = ChunkRef::try_from_unverified(value)?;
+        if Chunk::compute_crc(&chunk.chunk_type.bytes(), chunk.data) != chunk.checksum {
+            return Err(error(
+                ErrorKind::Validation,
+                "Incoming check does not match computed checksum",
+            ));
         }
         Ok(chunk)
     }
@@ -227,6 +372,25 @@ mod tests {
         assert!(chunk.is_err());
     }
 
+    #[test]
+    fn test_unverified_chunk_from_bytes() {
+        let mut chunk_data = testing_chunk().as_bytes();
+        let last = chunk_data.len() - 1;
+        chunk_data[last] ^= 1;
+        assert!(ChunkRef::try_from(chunk_data.as_slice()).is_err());
+        let chunk = ChunkRef::try_from_unverified(&chunk_data).unwrap();
+        assert_eq!(chunk.data(), b"This is where your secret message will be!");
+        assert!(chunk.verify_crc().is_err());
+        assert!(ChunkRef::from(&testing_chunk()).verify_crc().is_ok());
+    }
+
+    #[test]
+    fn test_truncated_chunk_from_bytes() {
+        let chunk_data = testing_chunk().as_bytes();
+        assert!(Chunk::try_from(&chunk_data[..chunk_data.len() - 1]).is_err());
+        assert!(Chunk::try_from(&chunk_data[..6]).is_err());
+    }
+
     #[test]
     pub fn test_chunk_trait_impls() {
         let data_length: u32 = 42;
@@ -4,40 +4,164 @@ use std::fmt::{Display, Formatter};
 use std::slice::Iter;
 use std::str::FromStr;
 
-/// ChunkType represents the chunk type as detailed out in the PNG spec
-#[derive(PartialEq, Debug)]
+/// ChunkType represents the chunk type as detailed out in the PNG spec. Ordering compares the
+/// type bytes, so uppercase (critical) types sort before lowercase ones.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
 pub struct ChunkType {
     chunk_type_bytes: [u8; 4],
 }
 
+/// Where a chunk type stands with respect to the PNG spec
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkCategory {
+    /// A standard chunk type which decoders must understand
+    Critical,
+    /// A standard chunk type which decoders may ignore
+    Ancillary,
+    /// A type reserved for private use by applications
+    Private,
+    /// A public type which the spec doesn't define
+    Unregistered,
+}
+
+impl Display for ChunkCategory {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ChunkCategory::Critical => "critical",
+            ChunkCategory::Ancillary => "ancillary",
+            ChunkCategory::Private => "private",
+            ChunkCategory::Unregistered => "unregistered",
+        };
+        f.pad(name)
+    }
+}
+
 impl ChunkType {
+    pub const IHDR: ChunkType = ChunkType::known(*b"IHDR");
+    pub const PLTE: ChunkType = ChunkType::known(*b"PLTE");
+    pub const IDAT: ChunkType = ChunkType::known(*b"IDAT");
+    pub const IEND: ChunkType = ChunkType::known(*b"IEND");
+    pub const CHRM: ChunkType = ChunkType::known(*b"cHRM");
+    pub const CICP: ChunkType = ChunkType::known(*b"cICP");
+    pub const GAMA: ChunkType = ChunkType::known(*b"gAMA");
+    pub const ICCP: ChunkType = ChunkType::known(*b"iCCP");
+    pub const MDCV: ChunkType = ChunkType::known(*b"mDCV");
+    pub const CLLI: ChunkType = ChunkType::known(*b"cLLI");
+    pub const SBIT: ChunkType = ChunkType::known(*b"sBIT");
+    pub const SRGB: ChunkType = ChunkType::known(*b"sRGB");
+    pub const BKGD: ChunkType = ChunkType::known(*b"bKGD");
+    pub const HIST: ChunkType = ChunkType::known(*b"hIST");
+    pub const TRNS: ChunkType = ChunkType::known(*b"tRNS");
+    pub const EXIF: ChunkType = ChunkType::known(*b"eXIf");
+    pub const PHYS: ChunkType = ChunkType::known(*b"pHYs");
+    pub const SPLT: ChunkType = ChunkType::known(*b"sPLT");
+    pub const TIME: ChunkType = ChunkType::known(*b"tIME");
+    pub const ITXT: ChunkType = ChunkType::known(*b"iTXt");
+    pub const TEXT: ChunkType = ChunkType::known(*b"tEXt");
+    pub const ZTXT: ChunkType = ChunkType::known(*b"zTXt");
+    pub const ACTL: ChunkType = ChunkType::known(*b"acTL");
+    pub const FCTL: ChunkType = ChunkType::known(*b"fcTL");
+    pub const FDAT: ChunkType = ChunkType::known(*b"fdAT");
+    pub const OFFS: ChunkType = ChunkType::known(*b"oFFs");
+    pub const PCAL: ChunkType = ChunkType::known(*b"pCAL");
+    pub const SCAL: ChunkType = ChunkType::known(*b"sCAL");
+    pub const GIFG: ChunkType = ChunkType::known(*b"gIFg");
+    pub const GIFX: ChunkType = ChunkType::known(*b"gIFx");
+    pub const GIFT: ChunkType = ChunkType::known(*b"gIFt");
+    pub const STER: ChunkType = ChunkType::known(*b"sTER");
+    pub const FRAC: ChunkType = ChunkType::known(*b"fRAc");
+    pub const DSIG: ChunkType = ChunkType::known(*b"dSIG");
+
+    /// Chunk types defined by the PNG specification and its registered extensions
+    pub const STANDARD: &'static [ChunkType] = &[
+        ChunkType::IHDR,
+        ChunkType::PLTE,
+        ChunkType::IDAT,
+        ChunkType::IEND,
+        ChunkType::CHRM,
+        ChunkType::CICP,
+        ChunkType::GAMA,
+        ChunkType::ICCP,
+        ChunkType::MDCV,
+        ChunkType::CLLI,
+        ChunkType::SBIT,
+        ChunkType::SRGB,
+        ChunkType::BKGD,
+        ChunkType::HIST,
+        ChunkType::TRNS,
+        ChunkType::EXIF,
+        ChunkType::PHYS,
+        ChunkType::SPLT,
+        ChunkType::TIME,
+        ChunkType::ITXT,
+        ChunkType::TEXT,
+        ChunkType::ZTXT,
+        ChunkType::ACTL,
+        ChunkType::FCTL,
+        ChunkType::FDAT,
+        ChunkType::OFFS,
+        ChunkType::PCAL,
+        ChunkType::SCAL,
+        ChunkType::GIFG,
+        ChunkType::GIFX,
+        ChunkType::GIFT,
+        ChunkType::STER,
+        ChunkType::FRAC,
+        ChunkType::DSIG,
+    ];
+
+    /// A chunk type whose bytes are known to be valid
+    const fn known(chunk_type_bytes: [u8; 4]) -> Self {
+        ChunkType { chunk_type_bytes }
+    }
+
+    /// Returns whether the PNG spec or one of its registered extensions defines this type
+    pub fn is_standard(&self) -> bool {
+        Self::STANDARD.contains(self)
+    }
+
+    /// Returns whether this is a standard critical or ancillary, private or unregistered type
+    pub fn category(&self) -> ChunkCategory {
+        if self.is_standard() {
+            if self.is_critical() {
+                ChunkCategory::Critical
+            } else {
+                ChunkCategory::Ancillary
+            }
+        } else if !self.is_public() {
+            ChunkCategory::Private
+        } else {
+            ChunkCategory::Unregistered
+        }
+    }
+
     /// Returns the byte array representation of this chunk type
     pub fn bytes(&self) -> [u8; 4] {
         self.chunk_type_bytes
     }
 
     /// Returns whether the current chunk type is valid
-    fn is_valid(&self) -> bool {
+    pub fn is_valid(&self) -> bool {
         self.is_reserved_bit_valid()
     }
 
     /// Returns if the current chunk type is critical
-    fn is_critical(&self) -> bool {
+    pub fn is_critical(&self) -> bool {
         self.chunk_type_bytes[0] & 32_u8 == 0
     }
 
     /// Return if the current chunk type is public
-    fn is_public(&self) -> bool {
+    pub fn is_public(&self) -> bool {
         self.chunk_type_bytes[1] & 32_u8 == 0
     }
 
     /// Returns if the current chunk type has its reserved bit set
-    fn is_reserved_bit_valid(&self) -> bool {
+    pub fn is_reserved_bit_valid(&self) -> bool {
         self.chunk_type_bytes[2] & 32_u8 == 0
     }
 
     /// Returns if the current chunk type is safe to copy
-    fn is_safe_to_copy(&self) -> bool {
+    pub fn is_safe_to_copy(&self) -> bool {
         // Don't make the mistake of checking for == 1 here given that we are setting the bit
         // at the 5th position so it would be 32 and not 1 -- noob mistake, I know!
         self.chunk_type_bytes[3] & 32_u8 != 0
@@ -78,7 +202,9 @@ impl FromStr for ChunkType {
     type Err = crate::PngError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        ChunkType::try_from(<[u8; 4]>::try_from(s.as_bytes()).unwrap())
+        let bytes = <[u8; 4]>::try_from(s.as_bytes())
+            .map_err(|_| format!("Chunk type {:?} is not 4 bytes long", s))?;
+        ChunkType::try_from(bytes)
     }
 }
 
@@ -89,6 +215,20 @@ mod tests {
     use std::str::FromStr;
     use std::{assert_eq, format};
 
+    #[test]
+    pub fn test_chunk_type_category() {
+        assert_eq!(ChunkType::IHDR.to_string(), "IHDR");
+        assert_eq!(ChunkType::IHDR.category(), ChunkCategory::Critical);
+        assert_eq!(ChunkType::TEXT.category(), ChunkCategory::Ancillary);
+        assert!(ChunkType::from_str("tEXt").unwrap().is_standard());
+        let private = ChunkType::from_str("ruSt").unwrap();
+        assert_eq!(private.category(), ChunkCategory::Private);
+        let unregistered = ChunkType::from_str("aBCd").unwrap();
+        assert!(!unregistered.is_standard());
+        assert_eq!(unregistered.category(), ChunkCategory::Unregistered);
+        assert!(ChunkType::STANDARD.iter().all(ChunkType::is_valid));
+    }
+
     #[test]
     pub fn test_chunk_type_from_bytes() {
         let expected = [82, 117, 83, 116];
@@ -104,6 +244,12 @@ mod tests {
         assert_eq!(expected, actual);
     }
 
+    #[test]
+    pub fn test_chunk_type_from_str_wrong_length() {
+        assert!(ChunkType::from_str("ruS").is_err());
+        assert!(ChunkType::from_str("ruStt").is_err());
+    }
+
     #[test]
     pub fn test_chunk_type_is_critical() {
         let chunk = ChunkType::from_str("RuSt").unwrap();
@@ -181,4 +327,21 @@ mod tests {
         let _chunk_string = format!("{}", chunk_type_1);
         let _are_chunks_equal = chunk_type_1 == chunk_type_2;
     }
+
+    #[test]
+    pub fn test_chunk_type_collections() {
+        let types: Vec<ChunkType> = ["tEXt", "IHDR", "tEXt", "IDAT"]
+            .iter()
+            .map(|t| ChunkType::from_str(t).unwrap())
+            .collect();
+        let unique: std::collections::HashSet<ChunkType> = types.iter().copied().collect();
+        assert_eq!(unique.len(), 3);
+        let mut sorted = types.clone();
+        sorted.sort();
+        sorted.dedup();
+        assert_eq!(
+            sorted,
+            vec![ChunkType::IDAT, ChunkType::IHDR, ChunkType::TEXT]
+        );
+    }
 }
@@ -0,0 +1,351 @@
+use crate::background::Background;
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::error::{error, with_kind, ErrorKind};
+use crate::gamma::GamaChunk;
+use crate::iccp::IccpChunk;
+use crate::ihdr::Ihdr;
+use crate::palette::{Histogram, Palette, Transparency};
+use crate::phys::PhysChunk;
+use crate::png::Png;
+use crate::sbit::SignificantBits;
+use crate::splt::SuggestedPalette;
+use crate::text::TextualChunk;
+use crate::time::TimeChunk;
+use crate::PngResult;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// What, besides its own data, a chunk may need to be decoded. Several chunk layouts depend
+/// on the image's colour type or palette size.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DecodeContext {
+    pub ihdr: Option<Ihdr>,
+    pub palette_len: Option<usize>,
+}
+
+impl DecodeContext {
+    pub fn from_png(png: &Png) -> Self {
+        DecodeContext {
+            ihdr: Ihdr::try_from(png).ok(),
+            palette_len: png
+                .chunk_by_type(Palette::CHUNK_TYPE)
+                .map(|palette| palette.data().len() / 3),
+        }
+    }
+
+    /// The IHDR, for chunks which can't be decoded without it
+    fn require_ihdr(&self, chunk_type: &str) -> PngResult<&Ihdr> {
+        self.ihdr.as_ref().ok_or_else(|| {
+            let message = format!("A valid IHDR is needed to decode {}", chunk_type);
+            error(ErrorKind::Parse, message)
+        })
+    }
+}
+
+/// A typed representation of one or more chunk types
+pub trait ChunkCodec: Sized {
+    /// The chunk types this codec decodes
+    const CHUNK_TYPES: &'static [&'static str];
+
+    fn decode(chunk: &Chunk, context: &DecodeContext) -> PngResult<Self>;
+
+    fn encode(&self) -> PngResult<Chunk>;
+}
+
+/// A chunk decoded by a registered codec, or the raw chunk if its type isn't registered
+pub enum TypedChunk<'a> {
+    Ihdr(Ihdr),
+    Palette(Palette),
+    Transparency(Transparency),
+    Background(Background),
+    SignificantBits(SignificantBits),
+    Histogram(Histogram),
+    Gamma(GamaChunk),
+    Iccp(IccpChunk),
+    Phys(PhysChunk),
+    Time(TimeChunk),
+    Text(TextualChunk),
+    SuggestedPalette(SuggestedPalette),
+    Unknown(&'a Chunk),
+}
+
+type Decoder = Box<dyn Fn(&Chunk, &DecodeContext) -> PngResult<TypedChunk<'static>> + Send + Sync>;
+
+/// Maps chunk types to the codecs which decode them
+pub struct ChunkRegistry {
+    decoders: HashMap<ChunkType, Decoder>,
+}
+
+impl ChunkRegistry {
+    /// A registry which decodes nothing, leaving every chunk raw
+    pub fn empty() -> Self {
+        ChunkRegistry {
+            decoders: HashMap::new(),
+        }
+    }
+
+    /// Decode the codec's chunk types with it, wrapping the result with `wrap`. Replaces any
+    /// codec already registered for those types. Chunks the codec can't decode fail with a
+    /// parse error.
+    pub fn register<C: ChunkCodec + 'static>(
+        &mut self,
+        wrap: fn(C) -> TypedChunk<'static>,
+    ) -> PngResult<()> {
+        for chunk_type in C::CHUNK_TYPES {
+            let decoder: Decoder = Box::new(move |chunk, context| {
+                let decoded =
+                    C::decode(chunk, context).map_err(|e| with_kind(ErrorKind::Parse, e))?;
+                Ok(wrap(decoded))
+            });
+            self.decoders
+                .insert(ChunkType::from_str(chunk_type)?, decoder);
+        }
+        Ok(())
+    }
+
+    pub fn is_registered(&self, chunk_type: &ChunkType) -> bool {
+        self.decoders.contains_key(chunk_type)
+    }
+
+    /// Decode the chunk if its type is registered, or return it raw
+    pub fn decode<'a>(
+        &self,
+        chunk: &'a Chunk,
+        context: &DecodeContext,
+    ) -> PngResult<TypedChunk<'a>> {
+        match self.decoders.get(chunk.chunk_type()) {
+            Some(decoder) => decoder(chunk, context),
+            None => Ok(TypedChunk::Unknown(chunk)),
+        }
+    }
+}
+
+impl Default for ChunkRegistry {
+    /// A registry of every standard chunk type this crate has a codec for
+    fn default() -> Self {
+        let mut registry = ChunkRegistry::empty();
+        let registered = [
+            registry.register(TypedChunk::Ihdr),
+            registry.register(TypedChunk::Palette),
+            registry.register(TypedChunk::Transparency),
+            registry.register(TypedChunk::Background),
+            registry.register(TypedChunk::SignificantBits),
+            registry.register(TypedChunk::Histogram),
+            registry.register(TypedChunk::Gamma),
+            registry.register(TypedChunk::Iccp),
+            registry.register(TypedChunk::Phys),
+            registry.register(TypedChunk::Time),
+            registry.register(TypedChunk::Text),
+            registry.register(TypedChunk::SuggestedPalette),
+        ];
+        for result in registered {
+            result.expect("the standard chunk types are all valid");
+        }
+        registry
+    }
+}
+
+impl ChunkCodec for Ihdr {
+    const CHUNK_TYPES: &'static [&'static str] = &["IHDR"];
+
+    fn decode(chunk: &Chunk, _context: &DecodeContext) -> PngResult<Self> {
+        Ihdr::try_from(chunk.data())
+    }
+
+    fn encode(&self) -> PngResult<Chunk> {
+        Ok(Chunk::new(ChunkType::from_str("IHDR")?, self.as_bytes()))
+    }
+}
+
+impl ChunkCodec for Palette {
+    const CHUNK_TYPES: &'static [&'static str] = &[Palette::CHUNK_TYPE];
+
+    fn decode(chunk: &Chunk, context: &DecodeContext) -> PngResult<Self> {
+        let palette = Palette::try_from(chunk.data())?;
+        if let Some(ihdr) = &context.ihdr {
+            palette.check(ihdr)?;
+        }
+        Ok(palette)
+    }
+
+    fn encode(&self) -> PngResult<Chunk> {
+        self.to_chunk()
+    }
+}
+
+impl ChunkCodec for Transparency {
+    const CHUNK_TYPES: &'static [&'static str] = &[Transparency::CHUNK_TYPE];
+
+    fn decode(chunk: &Chunk, context: &DecodeContext) -> PngResult<Self> {
+        let ihdr = context.require_ihdr(Self::CHUNK_TYPE)?;
+        Transparency::decode(chunk.data(), ihdr, context.palette_len)
+    }
+
+    fn encode(&self) -> PngResult<Chunk> {
+        self.to_chunk()
+    }
+}
+
+impl ChunkCodec for Background {
+    const CHUNK_TYPES: &'static [&'static str] = &[Background::CHUNK_TYPE];
+
+    fn decode(chunk: &Chunk, context: &DecodeContext) -> PngResult<Self> {
+        let ihdr = context.require_ihdr(Self::CHUNK_TYPE)?;
+        Background::decode(chunk.data(), ihdr, context.palette_len)
+    }
+
+    fn encode(&self) -> PngResult<Chunk> {
+        self.to_chunk()
+    }
+}
+
+impl ChunkCodec for SignificantBits {
+    const CHUNK_TYPES: &'static [&'static str] = &[SignificantBits::CHUNK_TYPE];
+
+    fn decode(chunk: &Chunk, context: &DecodeContext) -> PngResult<Self> {
+        let ihdr = context.require_ihdr(Self::CHUNK_TYPE)?;
+        SignificantBits::new(chunk.data().to_vec(), ihdr)
+    }
+
+    fn encode(&self) -> PngResult<Chunk> {
+        self.to_chunk()
+    }
+}
+
+impl ChunkCodec for Histogram {
+    const CHUNK_TYPES: &'static [&'static str] = &[Histogram::CHUNK_TYPE];
+
+    fn decode(chunk: &Chunk, context: &DecodeContext) -> PngResult<Self> {
+        Histogram::decode(chunk.data(), context.palette_len)
+    }
+
+    fn encode(&self) -> PngResult<Chunk> {
+        self.to_chunk()
+    }
+}
+
+impl ChunkCodec for GamaChunk {
+    const CHUNK_TYPES: &'static [&'static str] = &[GamaChunk::CHUNK_TYPE];
+
+    fn decode(chunk: &Chunk, _context: &DecodeContext) -> PngResult<Self> {
+        GamaChunk::try_from(chunk.data())
+    }
+
+    fn encode(&self) -> PngResult<Chunk> {
+        self.to_chunk()
+    }
+}
+
+impl ChunkCodec for IccpChunk {
+    const CHUNK_TYPES: &'static [&'static str] = &[IccpChunk::CHUNK_TYPE];
+
+    fn decode(chunk: &Chunk, _context: &DecodeContext) -> PngResult<Self> {
+        IccpChunk::try_from(chunk)
+    }
+
+    fn encode(&self) -> PngResult<Chunk> {
+        self.to_chunk()
+    }
+}
+
+impl ChunkCodec for PhysChunk {
+    const CHUNK_TYPES: &'static [&'static str] = &[PhysChunk::CHUNK_TYPE];
+
+    fn decode(chunk: &Chunk, _context: &DecodeContext) -> PngResult<Self> {
+        PhysChunk::try_from(chunk.data())
+    }
+
+    fn encode(&self) -> PngResult<Chunk> {
+        self.to_chunk()
+    }
+}
+
+impl ChunkCodec for TimeChunk {
+    const CHUNK_TYPES: &'static [&'static str] = &[TimeChunk::CHUNK_TYPE];
+
+    fn decode(chunk: &Chunk, _context: &DecodeContext) -> PngResult<Self> {
+        TimeChunk::try_from(chunk.data())
+    }
+
+    fn encode(&self) -> PngResult<Chunk> {
+        self.to_chunk()
+    }
+}
+
+impl ChunkCodec for TextualChunk {
+    const CHUNK_TYPES: &'static [&'static str] = &TextualChunk::CHUNK_TYPES;
+
+    fn decode(chunk: &Chunk, _context: &DecodeContext) -> PngResult<Self> {
+        TextualChunk::try_from(chunk)
+    }
+
+    fn encode(&self) -> PngResult<Chunk> {
+        self.to_chunk()
+    }
+}
+
+impl ChunkCodec for SuggestedPalette {
+    const CHUNK_TYPES: &'static [&'static str] = &[SuggestedPalette::CHUNK_TYPE];
+
+    fn decode(chunk: &Chunk, _context: &DecodeContext) -> PngResult<Self> {
+        SuggestedPalette::try_from(chunk.data())
+    }
+
+    fn encode(&self) -> PngResult<Chunk> {
+        self.to_chunk()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::kind_of;
+    use crate::test_util::chunk;
+    use crate::text::TextChunk;
+
+    #[test]
+    fn test_typed_chunks() {
+        let ihdr = [0, 0, 0, 1, 0, 0, 0, 1, 8, 0, 0, 0, 0];
+        let text = TextChunk::new("Title", "Dice").unwrap().to_chunk().unwrap();
+        let png = Png::from_chunks(vec![
+            chunk("IHDR", &ihdr),
+            chunk("tRNS", &[0, 7]),
+            text,
+            chunk("RuSt", b"secret"),
+            chunk("IEND", &[]),
+        ]);
+        let typed: Vec<TypedChunk> = png.typed_chunks().map(Result::unwrap).collect();
+        assert!(matches!(typed[0], TypedChunk::Ihdr(ihdr) if ihdr.width == 1));
+        assert!(matches!(
+            typed[1],
+            TypedChunk::Transparency(Transparency::Gray(7))
+        ));
+        assert!(matches!(&typed[2], TypedChunk::Text(text) if text.text() == "Dice"));
+        assert!(matches!(typed[3], TypedChunk::Unknown(chunk) if chunk.data() == b"secret"));
+        assert!(matches!(typed[4], TypedChunk::Unknown(_)));
+    }
+
+    #[test]
+    fn test_registry() {
+        let empty = ChunkRegistry::empty();
+        let gama = GamaChunk::from_exponent(2.2).unwrap().encode().unwrap();
+        assert!(!empty.is_registered(gama.chunk_type()));
+        let context = DecodeContext::default();
+        assert!(matches!(
+            empty.decode(&gama, &context),
+            Ok(TypedChunk::Unknown(_))
+        ));
+
+        let registry = ChunkRegistry::default();
+        assert!(registry.is_registered(gama.chunk_type()));
+        assert!(
+            matches!(registry.decode(&gama, &context), Ok(TypedChunk::Gamma(g)) if g.value == 45455)
+        );
+        // Chunks whose layout depends on the IHDR can't be decoded without one
+        let error = registry.decode(&chunk("bKGD", &[0, 0]), &context).err();
+        assert_eq!(kind_of(&*error.unwrap()), ErrorKind::Parse);
+        let error = registry.decode(&chunk("gAMA", &[0]), &context).err();
+        assert_eq!(kind_of(&*error.unwrap()), ErrorKind::Parse);
+    }
+}
@@ -1 +1,2826 @@
+#[cfg(feature = "shell")]
+use crate::args::ShellArgs;
+#[cfg(feature = "tui")]
+use crate::args::TuiArgs;
+use crate::args::{
+    AnalyzeArgs, AnonymizeArgs, ApngBuildArgs, ApngExplodeArgs, ApplyArgs, AssembleArgs, BatchArgs,
+    CapacityArgs, CarveArgs, Cli, CompletionsArgs, CopyMetaArgs, DecodeArgs, DedupeArgs, DiffArgs,
+    DpiGetArgs, DpiSetArgs, EmbedMethod, EncodeArgs, ExtractArgs, GammaGetArgs, GammaSetArgs,
+    GrepArgs, HandlerArgs, HexdumpArgs, IccEmbedArgs, IccExtractArgs, InfoArgs, InjectArgs,
+    LintArgs, ManArgs, MergeArgs, NormalizeArgs, OptimizeArgs, OutputFormat, ParseArgs, PrintArgs,
+    RemoveArgs, RepairArgs, SaveArgs, ScanArgs, SplitArgs, StatsArgs, StripArgs, TextGetArgs,
+    TextSetArgs, TimeGetArgs, TimeSetArgs, ValidateArgs, WriteArgs, XmpGetArgs, XmpRemoveArgs,
+    XmpSetArgs,
+};
+use crate::progress;
+use crate::walk;
+use base64::prelude::*;
+use clap::CommandFactory;
+use indicatif::ProgressBar;
+use pngmi::analysis::{self, Analysis};
+use pngmi::anonymize::{self, AnonymizeOptions};
+use pngmi::apng;
+use pngmi::carve;
+use pngmi::chunk::{Chunk, ChunkRef};
+use pngmi::chunk_type::ChunkType;
+use pngmi::compression;
+use pngmi::copy::{self, CopyOptions};
+use pngmi::crypto::{self, KdfParams};
+use pngmi::dedupe::{self, DedupeOptions};
+use pngmi::diff::{self, ChunkChange};
+use pngmi::entropy;
+use pngmi::envelope::{self, Envelope, Flags};
+use pngmi::error::{self, ErrorKind};
+use pngmi::exif::{self, EXIF_CHUNK_TYPE};
+use pngmi::gamma::{self, GamaChunk};
+use pngmi::hexdump;
+use pngmi::iccp::{self, IccpChunk};
+use pngmi::ihdr::Ihdr;
+use pngmi::input::{self, Input};
+use pngmi::lint::{LintConfig, LintLevel};
+use pngmi::lsb;
+use pngmi::normalize;
+use pngmi::optimize::{self, OptimizeOptions};
+use pngmi::output;
+use pngmi::palette::{self, Transparency};
+use pngmi::patch::Patch;
+use pngmi::phys::{self, PhysChunk};
+use pngmi::plugin::{self, ChunkHandlers};
+use pngmi::png::{ChunkPosition, Png, PngRef};
+use pngmi::recipients;
+use pngmi::repair;
+use pngmi::report::{FileReport, Report, ReportFormat};
+use pngmi::sarif::{Level, ReportingRule, SarifLog};
+use pngmi::scan::{self, Finding, FindingKind, ScanOptions};
+use pngmi::search;
+use pngmi::segment;
+use pngmi::signing;
+use pngmi::split;
+use pngmi::splt::{self, SuggestedPalette};
+use pngmi::stats::{Stats, Totals};
+use pngmi::strip::{self, StripOptions};
+use pngmi::text::{self, ItxtChunk, TextChunk, TextualChunk};
+use pngmi::time::{self, TimeChunk};
+use pngmi::trailer;
+use pngmi::validate::{self, Rule, Severity, Violation};
+use pngmi::xmp;
+use pngmi::{PngError, PngResult};
+use regex::bytes::Regex;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::io::{self, BufRead, IsTerminal, Read, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use tracing::{error, info, info_span, warn};
 
+/// Build the lint levels from the config file and flags given on the command line, with the
+/// flags applied from least to most severe
+fn load_lint_config(args: &LintArgs) -> PngResult<LintConfig> {
+    let mut config = args.defaults.clone();
+    if let Some(path) = &args.lint_config {
+        let file = LintConfig::from_toml(&fs::read_to_string(path)?)
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        config.extend(&file);
+    }
+    let flags = [
+        (&args.allow, LintLevel::Allow),
+        (&args.info, LintLevel::Info),
+        (&args.warn, LintLevel::Warn),
+        (&args.deny, LintLevel::Deny),
+    ];
+    for (names, level) in flags {
+        for name in names {
+            config.set(name, level)?;
+        }
+    }
+    Ok(config)
+}
+
+/// Create the built-in chunk handlers selected on the command line
+fn load_handlers(args: &HandlerArgs) -> PngResult<ChunkHandlers> {
+    let mut handlers = ChunkHandlers::default();
+    for spec in &args.handlers {
+        let (name, chunk_type) = spec
+            .split_once(':')
+            .ok_or_else(|| format!("Invalid handler {:?}; expected NAME:TYPE", spec))?;
+        handlers.register(plugin::builtin_handler(name, chunk_type)?)?;
+    }
+    Ok(handlers)
+}
+
+/// Read the file at the given path, or map it if asked to
+fn read_input(path: &Path, parse: &ParseArgs) -> PngResult<Input> {
+    info!("Reading {}", path.display());
+    if let Some(url) = input::url(path) {
+        let limit = parse.max_size.unwrap_or(input::MAX_DOWNLOAD_LEN);
+        return fetch_input(url, limit);
+    }
+    let limit = parse.options().max_total_size;
+    if fs::metadata(path)?.len() > limit as u64 {
+        let message = format!(
+            "{} is larger than the limit of {} bytes",
+            path.display(),
+            limit
+        );
+        return Err(error::error(ErrorKind::Parse, message));
+    }
+    #[cfg(feature = "mmap")]
+    if parse.mmap {
+        return Input::map(path);
+    }
+    Ok(Input::Buffered(progress::read(path)?))
+}
+
+#[cfg(feature = "http")]
+fn fetch_input(url: &str, limit: usize) -> PngResult<Input> {
+    Input::fetch(url, limit)
+}
+
+#[cfg(not(feature = "http"))]
+fn fetch_input(url: &str, _limit: usize) -> PngResult<Input> {
+    Err(format!("Can't read {}: this build has no HTTP support", url).into())
+}
+
+/// Read and parse the PNG at the given path, for commands which may change it. An --offset
+/// is refused, as writing the PNG back would throw away the file around it.
+fn read_png(path: &Path, parse: &ParseArgs) -> PngResult<Png> {
+    if parse.offset.is_some() || parse.multi {
+        let message =
+            "--offset and --multi can only be used with commands which don't change the file";
+        return Err(message.into());
+    }
+    if input::url(path).is_some() {
+        return Err("Only commands which don't change the file can read from a URL".into());
+    }
+    inspect_png(path, parse)
+}
+
+/// Read and parse the PNG at the given path, for commands which only inspect it
+fn inspect_png(path: &Path, parse: &ParseArgs) -> PngResult<Png> {
+    let bytes = read_input(path, parse)?;
+    parse_png(path, &bytes, parse)
+}
+
+/// Parse the bytes read from the given path, reporting anything skipped in lenient mode
+fn parse_png(path: &Path, bytes: &[u8], parse: &ParseArgs) -> PngResult<Png> {
+    parse_png_ref(path, bytes, parse).map(|png| png.to_png())
+}
+
+/// Parse the bytes read from the given path without copying the chunk data, for commands
+/// which only inspect the file
+fn parse_png_ref<'a>(path: &Path, bytes: &'a [u8], parse: &ParseArgs) -> PngResult<PngRef<'a>> {
+    let offset = image_offset(bytes, parse)?;
+    parse_png_ref_at(path, bytes, offset, parse)
+}
+
+/// Where the PNG to read starts in the bytes read from a file: at --offset, or with --multi
+/// where the image picked by --image does
+fn image_offset(bytes: &[u8], parse: &ParseArgs) -> PngResult<Option<usize>> {
+    let offset = match (parse.multi, parse.image) {
+        (true, Some(index)) => {
+            let offsets = Png::stream_offsets(bytes)?;
+            let offset = offsets.get(index).ok_or_else(|| {
+                format!("No image {}; the file holds {} PNGs", index, offsets.len())
+            })?;
+            Some(*offset)
+        }
+        (true, None) => return Err("--multi needs --image to pick a PNG for this command".into()),
+        (false, _) => parse.offset,
+    };
+    Ok(offset)
+}
+
+/// Parse the PNG starting at `offset` in the bytes read from the given path, or the whole
+/// file if there's no offset
+fn parse_png_ref_at<'a>(
+    path: &Path,
+    bytes: &'a [u8],
+    offset: Option<usize>,
+    parse: &ParseArgs,
+) -> PngResult<PngRef<'a>> {
+    let bytes = match offset {
+        Some(offset) => Png::bytes_at(bytes, offset)?,
+        None => bytes,
+    };
+    let options = parse.options();
+    let png = if parse.no_verify_crc {
+        PngRef::try_from_unverified_with(bytes, &options)?
+    } else if !parse.lenient {
+        PngRef::try_from_with(bytes, &options)?
+    } else {
+        let (png, warnings) = PngRef::try_from_lenient_with(bytes, &options)?;
+        for warning in warnings {
+            warn!("{}: {}", path.display(), warning);
+        }
+        png
+    };
+    // Offsets are reported within the whole file
+    Ok(png.offset_by(offset.unwrap_or(0)))
+}
+
+/// Call `f` with each PNG in the bytes read from the given path: every one in turn with
+/// --multi and no --image, along with its index, or otherwise just the one selected
+fn for_each_image<'a>(
+    path: &Path,
+    bytes: &'a [u8],
+    parse: &ParseArgs,
+    format: OutputFormat,
+    out: &mut dyn Write,
+    mut f: impl FnMut(PngRef<'a>, Option<usize>, &mut dyn Write) -> PngResult<()>,
+) -> PngResult<()> {
+    if !parse.multi || parse.image.is_some() {
+        return f(parse_png_ref(path, bytes, parse)?, parse.image, out);
+    }
+    let offsets = Png::stream_offsets(bytes)?;
+    let last = offsets.len() - 1;
+    for (index, &offset) in offsets.iter().enumerate() {
+        if format == OutputFormat::Text {
+            if index > 0 {
+                writeln!(out)?;
+            }
+            writeln!(out, "Image {} at offset {:#x}:", index, offset)?;
+        }
+        // The last PNG keeps whatever follows it, as it would outside a stream
+        let png = match index == last {
+            true => parse_png_ref_at(path, &bytes[offset..], None, parse)?.offset_by(offset),
+            false => parse_png_ref_at(path, bytes, Some(offset), parse)?,
+        };
+        f(png, Some(index), out)?;
+    }
+    Ok(())
+}
+
+/// Write the PNG to the given path, first refreshing its tIME chunk if asked to
+fn write_png(path: &Path, png: &mut Png, write: &WriteArgs, out: &mut dyn Write) -> PngResult<()> {
+    if write.update_time {
+        time::set_time(png, TimeChunk::now()?)?;
+    }
+    save(path, &png.as_bytes(), &write.save, out)
+}
+
+/// Replace the file at the given path without ever leaving it half written, keeping a backup
+/// of the original or its timestamps and owner if asked to
+fn save(path: &Path, bytes: &[u8], save: &SaveArgs, out: &mut dyn Write) -> PngResult<()> {
+    if save.dry_run {
+        return describe_save(path, bytes, out);
+    }
+    info!("Writing {}", path.display());
+    output::write_atomic(path, bytes, &save.options())
+}
+
+/// Report how writing the bytes would change the file at the given path, chunk by chunk
+fn describe_save(path: &Path, bytes: &[u8], out: &mut dyn Write) -> PngResult<()> {
+    let existing = match fs::read(path) {
+        Ok(existing) => existing,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            writeln!(
+                out,
+                "Would create {} ({} bytes)",
+                path.display(),
+                bytes.len()
+            )?;
+            return Ok(());
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let (after, _) = PngRef::try_from_lenient(bytes)?;
+    // Nothing can be said about individual chunks if the file isn't a PNG
+    if let Ok((before, _)) = PngRef::try_from_lenient(&existing) {
+        for change in diff::chunk_changes(&before, &after) {
+            writeln!(out, "Would {}", change)?;
+        }
+        match (before.trailing_data().len(), after.trailing_data().len()) {
+            (old, new) if old == new => {}
+            (old, 0) => writeln!(out, "Would remove {} bytes of data after IEND", old)?,
+            (old, new) => writeln!(
+                out,
+                "Would change the data after IEND ({} -> {} bytes)",
+                old, new
+            )?,
+        }
+    }
+    writeln!(
+        out,
+        "Would write {} bytes to {} (currently {} bytes)",
+        bytes.len(),
+        path.display(),
+        existing.len()
+    )?;
+    Ok(())
+}
+
+/// Run `work` on each path using up to `jobs` worker threads, or one per core if `jobs` is 0.
+/// Results are handed to `emit` in path order as soon as each is ready, so the output doesn't
+/// depend on which file happens to finish first. Batches of more than one file show a progress
+/// bar.
+fn run_jobs<T, W, E>(paths: &[PathBuf], jobs: usize, work: W, mut emit: E)
+where
+    T: Send,
+    W: Fn(&Path) -> T + Sync,
+    E: FnMut(&Path, T),
+{
+    let jobs = match jobs {
+        0 => thread::available_parallelism().map_or(1, usize::from),
+        jobs => jobs,
+    };
+    let bar = match paths.len() {
+        0 | 1 => ProgressBar::hidden(),
+        len => progress::files(len),
+    };
+    let next = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::channel();
+    thread::scope(|scope| {
+        for _ in 0..jobs.min(paths.len()) {
+            let (sender, next, work) = (sender.clone(), &next, &work);
+            scope.spawn(move || loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some(path) = paths.get(index) else {
+                    break;
+                };
+                if sender.send((index, work(path))).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(sender);
+
+        // Hold back results which finish early until everything before them is emitted
+        let mut pending = BTreeMap::new();
+        let mut emitted = 0;
+        for (index, result) in receiver {
+            pending.insert(index, result);
+            while let Some(result) = pending.remove(&emitted) {
+                progress::suspend(|| emit(&paths[emitted], result));
+                bar.inc(1);
+                emitted += 1;
+            }
+        }
+    });
+    bar.finish_and_clear();
+}
+
+/// Run a command on the file at the given path, on every file matching it if it is a glob
+/// pattern, on every file listed by `--files-from` or, with `--recursive`, on every PNG file
+/// beneath the directory, listing the files that failed and failing if any did. Each file's
+/// output is buffered so that files processed in parallel don't interleave.
+fn for_each_png<F>(
+    path: Option<&Path>,
+    batch: &BatchArgs,
+    format: OutputFormat,
+    command: F,
+) -> PngResult<()>
+where
+    F: Fn(&Path, &mut dyn Write) -> PngResult<()> + Sync,
+{
+    let (pngs, single) = batch_files(path, batch)?;
+    for_each_file(&pngs, single, batch.jobs, format, command)
+}
+
+/// Like `for_each_png`, for commands which edit files in place, asking before editing more
+/// than one file as described by `confirm_edits`
+fn edit_each_png<F>(
+    path: Option<&Path>,
+    batch: &BatchArgs,
+    verb: &str,
+    save: &SaveArgs,
+    command: F,
+) -> PngResult<()>
+where
+    F: Fn(&Path, &mut dyn Write) -> PngResult<()> + Sync,
+{
+    let (pngs, single) = batch_files(path, batch)?;
+    let pngs = match single {
+        true => pngs,
+        false => confirm_edits(verb, pngs, save)?,
+    };
+    for_each_file(&pngs, single, batch.jobs, OutputFormat::Text, command)
+}
+
+/// Run a command on each of the files, as described by `for_each_png`
+fn for_each_file<F>(
+    pngs: &[PathBuf],
+    single: bool,
+    jobs: usize,
+    format: OutputFormat,
+    command: F,
+) -> PngResult<()>
+where
+    F: Fn(&Path, &mut dyn Write) -> PngResult<()> + Sync,
+{
+    if single {
+        return command(&pngs[0], &mut io::stdout().lock());
+    }
+    let mut failures = vec![];
+    let work = |png: &Path| {
+        let _span = info_span!("file", path = %png.display()).entered();
+        let mut out = vec![];
+        let result =
+            command(png, &mut out).map_err(|e| (error::kind_of(e.as_ref()), e.to_string()));
+        (out, result)
+    };
+    let mut stdout = io::stdout().lock();
+    run_jobs(pngs, jobs, work, |png, (out, result)| {
+        if format == OutputFormat::Text {
+            let _ = writeln!(stdout, "==> {} <==", png.display());
+        }
+        let _ = stdout.write_all(&out);
+        if let Err((kind, e)) = result {
+            error!("{}: {}", png.display(), e);
+            // Keep one line per file, unless the command already reported on the file
+            if format == OutputFormat::Json && out.is_empty() {
+                let _ = write_json(&mut stdout, &json!({ "file": png, "error": e }));
+            }
+            failures.push((png.to_path_buf(), kind));
+        }
+    });
+    if format != OutputFormat::Text {
+        return batch_result(&failures, pngs.len());
+    }
+    writeln!(stdout)?;
+    for (png, _) in &failures {
+        writeln!(stdout, "FAILED {}", png.display())?;
+    }
+    writeln!(
+        stdout,
+        "Processed {} files: {} succeeded, {} failed",
+        pngs.len(),
+        pngs.len() - failures.len(),
+        failures.len()
+    )?;
+    batch_result(&failures, pngs.len())
+}
+
+/// The files a batch command runs on, and whether it was given a single file rather than a
+/// glob pattern, file list or directory
+fn batch_files(path: Option<&Path>, batch: &BatchArgs) -> PngResult<(Vec<PathBuf>, bool)> {
+    if let (Some(path), None) = (path, &batch.list.files_from) {
+        let is_glob = walk::is_glob(path);
+        if !is_glob && !path.is_dir() {
+            return Ok((vec![path.to_path_buf()], true));
+        }
+        if !is_glob && !batch.recursive {
+            return Err(format!(
+                "{} is a directory; pass --recursive to process the PNG files beneath it",
+                path.display()
+            )
+            .into());
+        }
+    }
+    Ok((walk::select_files(path, &batch.list, &batch.walk)?, false))
+}
+
+/// Ask on the terminal whether to go ahead and `verb` the files in place, once for all of them
+/// or one by one, returning those the user agreed to. Dry runs and `--force` skip the question,
+/// and without a terminal to ask on it has to be given up front.
+fn confirm_edits(verb: &str, paths: Vec<PathBuf>, save: &SaveArgs) -> PngResult<Vec<PathBuf>> {
+    if save.dry_run || save.force || paths.len() < 2 {
+        return Ok(paths);
+    }
+    if !io::stdin().is_terminal() {
+        return Err(format!(
+            "Refusing to {} {} files in place without confirmation; pass --force to go ahead",
+            verb,
+            paths.len()
+        )
+        .into());
+    }
+    let answer = ask(&format!(
+        "{} {} files in place? [y]es, [n]o, [e]ach file: ",
+        capitalize(verb),
+        paths.len()
+    ))?;
+    let paths = match answer.as_str() {
+        "y" | "yes" => paths,
+        "e" | "each" => {
+            let mut confirmed = vec![];
+            for path in paths {
+                let answer = ask(&format!("{} {}? [y/N] ", capitalize(verb), path.display()))?;
+                if matches!(answer.as_str(), "y" | "yes") {
+                    confirmed.push(path);
+                }
+            }
+            confirmed
+        }
+        _ => vec![],
+    };
+    if paths.is_empty() {
+        return Err("Cancelled; no files were changed".into());
+    }
+    Ok(paths)
+}
+
+/// Print the question on stderr and read the answer from stdin, lowercased
+fn ask(question: &str) -> PngResult<String> {
+    let mut stderr = io::stderr().lock();
+    write!(stderr, "{}", question)?;
+    stderr.flush()?;
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    Ok(answer.trim().to_lowercase())
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// Fail if any file in a batch failed, with the kind of failure they all share if there is one
+fn batch_result(failures: &[(PathBuf, ErrorKind)], total: usize) -> PngResult<()> {
+    if !failures.is_empty() {
+        let kind = ErrorKind::common(failures.iter().map(|(_, kind)| *kind));
+        let message = format!("{} of {} files failed", failures.len(), total);
+        return Err(error::error(kind, message));
+    }
+    Ok(())
+}
+
+/// The answer "no" from `grep` or `diff`: nothing matched, or the files differ. Like grep(1) and
+/// diff(1) they exit with status 1 for it, keeping status 2 for failures.
+#[derive(Debug)]
+pub struct Negative(String);
+
+impl Display for Negative {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for Negative {}
+
+/// An error for a chunk the command needs but the file doesn't have
+fn not_found(message: &str) -> PngError {
+    error::error(ErrorKind::ChunkNotFound, message)
+}
+
+/// Write the value as JSON on a line of its own
+fn write_json(out: &mut dyn Write, value: &Value) -> PngResult<()> {
+    serde_json::to_writer(&mut *out, value)?;
+    writeln!(out)?;
+    Ok(())
+}
+
+/// The columns of the chunk tables written by `--format csv` and `--format tsv`. Numbers,
+/// CRCs included, are written in decimal as in the JSON output.
+const CHUNK_COLUMNS: &[&str] = &["path", "index", "type", "offset", "length", "crc", "flags"];
+
+/// Write one row of a CSV or TSV table, quoting any field which needs it
+fn write_row<S: AsRef<str>>(out: &mut dyn Write, delimiter: char, fields: &[S]) -> PngResult<()> {
+    let fields: Vec<String> = fields
+        .iter()
+        .map(|field| {
+            let field = field.as_ref();
+            if field.contains([delimiter, '"', '\n', '\r']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field.to_string()
+            }
+        })
+        .collect();
+    writeln!(out, "{}", fields.join(&delimiter.to_string()))?;
+    Ok(())
+}
+
+/// One table row per chunk, flagged with the properties of its type and any scan findings,
+/// plus a row for any data after IEND
+fn chunk_rows(path: &Path, bytes: &[u8], png: &PngRef, findings: &[Finding]) -> Vec<Vec<String>> {
+    let path = path.display().to_string();
+    let mut rows: Vec<Vec<String>> = png
+        .chunks()
+        .iter()
+        .enumerate()
+        .map(|(index, chunk)| {
+            let chunk_type = chunk.chunk_type();
+            let mut flags = vec![chunk_type.category().to_string()];
+            if chunk_type.is_safe_to_copy() {
+                flags.push("safe-to-copy".to_string());
+            }
+            flags.extend(
+                findings
+                    .iter()
+                    .filter(|finding| finding.chunk_index == Some(index))
+                    .map(|finding| finding.kind.id().to_string()),
+            );
+            vec![
+                path.clone(),
+                index.to_string(),
+                chunk_type.to_string(),
+                chunk.offset().to_string(),
+                chunk.length().to_string(),
+                chunk.crc().to_string(),
+                flags.join(" "),
+            ]
+        })
+        .collect();
+    let trailing_data = png.trailing_data();
+    if !trailing_data.is_empty() {
+        rows.push(vec![
+            path,
+            String::new(),
+            String::new(),
+            (bytes.len() - trailing_data.len()).to_string(),
+            trailing_data.len().to_string(),
+            String::new(),
+            FindingKind::TrailingData.id().to_string(),
+        ]);
+    }
+    rows
+}
+
+/// Encode the message or file into a new chunk, compressing, encrypting and signing it if asked to
+pub fn encode(args: EncodeArgs) -> PngResult<()> {
+    if let Some(unexpected) = args.unexpected_positional() {
+        return Err(format!("Unexpected argument {}", unexpected).into());
+    }
+    let chunk_type = match (args.method, args.chunk_type()) {
+        (EmbedMethod::Chunk, Some(chunk_type)) => Some(ChunkType::from_str(chunk_type)?),
+        (EmbedMethod::Chunk, None) => return Err("A chunk type is required".into()),
+        (_, _) => None,
+    };
+    let mut png = read_png(&args.file, &args.parse)?;
+    let mut envelope = match (&args.payload_file, args.message()) {
+        (Some(path), _) => {
+            let filename = path.file_name().and_then(|name| name.to_str());
+            let mime_type = envelope::guess_mime_type(path);
+            Envelope::new(mime_type, filename, progress::read(path)?)
+        }
+        (None, Some(message)) => Envelope::text(message),
+        (None, None) => return Err("Either a message or --payload-file is required".into()),
+    };
+    if let Some(algorithm) = args.compress {
+        compression::compress(&mut envelope, algorithm, args.level)?;
+    }
+    if args.passphrase.is_some() || !args.recipient.is_empty() {
+        envelope.seal_metadata()?;
+    }
+    if let Some(passphrase) = &args.passphrase {
+        let params = KdfParams {
+            memory_kib: args.kdf_memory,
+            iterations: args.kdf_iterations,
+            parallelism: args.kdf_parallelism,
+        };
+        envelope.set_body(crypto::seal(envelope.body(), passphrase, params)?);
+        envelope.flags_mut().set(Flags::ENCRYPTED, true);
+    }
+    if !args.recipient.is_empty() {
+        envelope.set_body(recipients::encrypt(envelope.body(), &args.recipient)?);
+        envelope.flags_mut().set(Flags::RECIPIENTS, true);
+    }
+    if let Some(key_path) = &args.sign {
+        signing::sign(&mut envelope, &signing::load_signing_key(key_path)?)?;
+    }
+    match (args.method, chunk_type) {
+        (_, Some(chunk_type)) => {
+            embed_in_chunks(&mut png, chunk_type, &envelope.as_bytes()?, &args)?
+        }
+        (EmbedMethod::Trailer, None) => {
+            trailer::embed(&mut png, &envelope.as_bytes()?)?;
+            warn!("Data after IEND is outside the datastream; tools which rewrite the file may drop it");
+        }
+        (_, None) => lsb::embed(&mut png, &envelope.as_bytes()?, args.bits)?,
+    }
+    write_png(args.output_path(), &mut png, &args.write, &mut io::stdout())
+}
+
+/// Add the payload to the PNG in chunks of the given type, split as `--max-chunk-size` asks
+fn embed_in_chunks(
+    png: &mut Png,
+    chunk_type: ChunkType,
+    payload: &[u8],
+    args: &EncodeArgs,
+) -> PngResult<()> {
+    if args.position == ChunkPosition::AfterIend {
+        warn!("Chunks after IEND are outside the datastream; tools which rewrite the file may drop them");
+    }
+    // Each segment goes just after the one before it, keeping them in order
+    let mut position = args.position;
+    for data in segment::split(payload, args.max_chunk_size)? {
+        let index = png.insert_chunk_at(position, Chunk::new(chunk_type, data))?;
+        if position != ChunkPosition::AfterIend {
+            position = ChunkPosition::Index(index + 1);
+        }
+    }
+    Ok(())
+}
+
+/// The payload stored in chunks of the given type, joined back together
+fn chunk_payload(png: &PngRef, chunk_type: &str, parse: &ParseArgs) -> PngResult<Vec<u8>> {
+    // Payloads encoded after IEND are found in the trailing data
+    let trailing = png.trailing_chunks();
+    let chunks: Vec<&ChunkRef> = png
+        .chunks_by_type(chunk_type)
+        .chain(
+            trailing
+                .iter()
+                .filter(|chunk| chunk.chunk_type().to_string() == chunk_type),
+        )
+        .collect();
+    if parse.no_verify_crc {
+        // Only the payload chunks are read, so only they need checking
+        for chunk in &chunks {
+            chunk.verify_crc()?;
+        }
+    }
+    segment::join(chunks.iter().map(|chunk| chunk.data()))
+}
+
+/// Print the message held in the first chunk of the given type, or write it out to a file
+pub fn decode(args: DecodeArgs, format: OutputFormat) -> PngResult<()> {
+    format.check("decode", &[OutputFormat::Json])?;
+    let bytes = read_input(&args.file, &args.parse)?;
+    let png = parse_png_ref(&args.file, &bytes, &args.parse)?;
+    let payload = match (args.method, &args.chunk_type) {
+        (EmbedMethod::Chunk, Some(chunk_type)) => chunk_payload(&png, chunk_type, &args.parse)?,
+        (EmbedMethod::Chunk, None) => return Err("A chunk type is required".into()),
+        (method, Some(_)) => {
+            return Err(format!("--method {} doesn't take a chunk type", method).into());
+        }
+        (EmbedMethod::Lsb, None) => {
+            if args.parse.no_verify_crc {
+                for chunk in png.chunks() {
+                    chunk.verify_crc()?;
+                }
+            }
+            lsb::extract(&png.to_png())?
+        }
+        (EmbedMethod::Trailer, None) => trailer::extract(png.trailing_data())?,
+    };
+
+    let mut envelope = Envelope::from_payload(&payload)?;
+    match &args.verify {
+        Some(key_path) => signing::verify(&envelope, &signing::load_verifying_key(key_path)?)?,
+        None if envelope.signature().is_some() => {
+            warn!("Payload is signed but was not verified; pass --verify to check it")
+        }
+        None => {}
+    }
+    match (
+        &args.passphrase,
+        envelope.flags().contains(Flags::ENCRYPTED),
+    ) {
+        (Some(passphrase), true) => {
+            envelope.set_body(crypto::open(envelope.body(), passphrase)?);
+            envelope.flags_mut().set(Flags::ENCRYPTED, false);
+        }
+        (None, true) => return Err("Message is encrypted; pass --passphrase to decode it".into()),
+        (Some(_), false) => return Err("Message is not encrypted".into()),
+        (None, false) => {}
+    }
+    match (
+        args.identity.is_empty(),
+        envelope.flags().contains(Flags::RECIPIENTS),
+    ) {
+        (false, true) => {
+            let identities = args
+                .identity
+                .iter()
+                .map(|path| recipients::load_identities(path))
+                .collect::<PngResult<Vec<_>>>()?
+                .into_iter()
+                .flatten()
+                .collect::<Vec<_>>();
+            envelope.set_body(recipients::decrypt(envelope.body(), &identities)?);
+            envelope.flags_mut().set(Flags::RECIPIENTS, false);
+        }
+        (true, true) => {
+            return Err("Message is encrypted to recipients; pass --identity to decode it".into())
+        }
+        (false, false) => return Err("Message is not encrypted to any recipients".into()),
+        (true, false) => {}
+    }
+    envelope.open_metadata()?;
+    compression::decompress(&mut envelope)?;
+    if let Some(out) = &args.out {
+        fs::write(out, envelope.body())?;
+    }
+    if format == OutputFormat::Json {
+        return write_json(&mut io::stdout().lock(), &payload_json(&envelope));
+    }
+    if args.out.is_some() {
+        return Ok(());
+    }
+    if !envelope.is_text() {
+        return Err(format!(
+            "Payload is binary ({}, originally {}); pass --out to write it to a file",
+            envelope.mime_type(),
+            envelope.filename().unwrap_or("unnamed")
+        )
+        .into());
+    }
+    let message = String::from_utf8(envelope.into_body())?;
+    println!("{}", message);
+    Ok(())
+}
+
+/// A decoded payload as `decode --format json` prints it, with the body in base64
+pub fn payload_json(envelope: &Envelope) -> Value {
+    json!({
+        "mime_type": envelope.mime_type(),
+        "filename": envelope.filename(),
+        "signed": envelope.signature().is_some(),
+        "size": envelope.body().len(),
+        "payload": BASE64_STANDARD.encode(envelope.body()),
+    })
+}
+
+/// Remove the first chunk of the given type and write the file back
+pub fn remove(args: RemoveArgs) -> PngResult<()> {
+    let mut png = read_png(&args.file, &args.parse)?;
+    png.remove_chunk(&args.chunk_type)?;
+    write_png(&args.file, &mut png, &args.write, &mut io::stdout())
+}
+
+/// The Nth chunk of the given type, counting from 1
+fn nth_chunk<'p, 'a>(
+    png: &'p PngRef<'a>,
+    chunk_type: &str,
+    nth: usize,
+) -> PngResult<&'p ChunkRef<'a>> {
+    let chunk_type = ChunkType::from_str(chunk_type)?;
+    if nth == 0 {
+        return Err("--nth counts from 1".into());
+    }
+    let chunks: Vec<&ChunkRef> = png
+        .chunks()
+        .iter()
+        .filter(|chunk| *chunk.chunk_type() == chunk_type)
+        .collect();
+    match chunks.get(nth - 1) {
+        Some(chunk) => Ok(chunk),
+        None if chunks.is_empty() => Err(not_found(&format!("No {} chunk found", chunk_type))),
+        None => Err(not_found(&format!(
+            "Only {} {} chunks found",
+            chunks.len(),
+            chunk_type
+        ))),
+    }
+}
+
+/// Write the data of the Nth chunk of the given type, byte for byte, to a file or stdout
+pub fn extract(args: ExtractArgs) -> PngResult<()> {
+    let bytes = read_input(&args.file, &args.parse)?;
+    let png = parse_png_ref(&args.file, &bytes, &args.parse)?;
+    let chunk = nth_chunk(&png, &args.chunk_type, args.nth)?;
+    if args.parse.no_verify_crc {
+        chunk.verify_crc()?;
+    }
+    match &args.out {
+        Some(out) => {
+            fs::write(out, chunk.data())?;
+            println!(
+                "Wrote {} bytes of {} chunk data to {}",
+                chunk.data().len(),
+                chunk.chunk_type(),
+                out.display()
+            );
+        }
+        None => io::stdout().lock().write_all(chunk.data())?,
+    }
+    Ok(())
+}
+
+/// Print an offset, hex and ASCII dump of the Nth chunk of the given type
+pub fn hexdump(args: HexdumpArgs) -> PngResult<()> {
+    let bytes = read_input(&args.file, &args.parse)?;
+    let png = parse_png_ref(&args.file, &bytes, &args.parse)?;
+    let chunk = nth_chunk(&png, &args.chunk_type, args.nth)?;
+    let mut stdout = io::stdout().lock();
+    if args.raw {
+        let offset = chunk.offset();
+        let raw = &bytes[offset..offset + chunk.overall_length() as usize];
+        hexdump::write_hexdump(&mut stdout, raw, offset)?;
+    } else {
+        hexdump::write_hexdump(&mut stdout, chunk.data(), 0)?;
+    }
+    Ok(())
+}
+
+/// Insert a chunk holding the data read from a file, with its length and CRC filled in
+pub fn inject(args: InjectArgs) -> PngResult<()> {
+    let chunk_type = ChunkType::from_str(&args.chunk_type)?;
+    let data = match args.data.as_os_str() == "-" {
+        true => {
+            let mut data = vec![];
+            io::stdin().lock().read_to_end(&mut data)?;
+            data
+        }
+        false => progress::read(&args.data)?,
+    };
+    let mut png = read_png(&args.file, &args.parse)?;
+    png.insert_chunk_at(args.position, Chunk::new(chunk_type, data))?;
+    write_png(&args.file, &mut png, &args.write, &mut io::stdout())
+}
+
+/// Matched bytes beyond this many are left out of the output
+const MAX_MATCH_PREVIEW_LEN: usize = 64;
+
+/// Print every match of the pattern in the chunk data of each file, failing if there are none
+pub fn grep(args: GrepArgs, format: OutputFormat) -> PngResult<()> {
+    format.check("grep", &[OutputFormat::Json])?;
+    let pattern = match args.hex {
+        true => search::hex_pattern(&args.pattern)?,
+        false => Regex::new(&args.pattern)?,
+    };
+    let mut pngs = vec![];
+    if args.files.is_empty() {
+        pngs = batch_files(None, &args.batch)?.0;
+    }
+    for file in &args.files {
+        pngs.extend(batch_files(Some(file), &args.batch)?.0);
+    }
+
+    let work = |path: &Path| {
+        let search = || -> PngResult<Vec<search::Match>> {
+            let bytes = read_input(path, &args.parse)?;
+            let png = parse_png_ref(path, &bytes, &args.parse)?;
+            Ok(search::search(&png, &pattern, args.decompress))
+        };
+        search().map_err(|e| (error::kind_of(e.as_ref()), e.to_string()))
+    };
+    let mut stdout = io::stdout().lock();
+    let mut failures = vec![];
+    let mut total = 0;
+    run_jobs(&pngs, args.batch.jobs, work, |path, matches| {
+        let matches = match matches {
+            Ok(matches) => matches,
+            Err((kind, e)) => {
+                error!("{}: {}", path.display(), e);
+                failures.push((path.to_path_buf(), kind));
+                return;
+            }
+        };
+        total += matches.len();
+        for found in matches {
+            let preview = found.bytes[..found.bytes.len().min(MAX_MATCH_PREVIEW_LEN)]
+                .escape_ascii()
+                .to_string();
+            let _ = match format {
+                OutputFormat::Json => write_json(
+                    &mut stdout,
+                    &json!({
+                        "file": path,
+                        "index": found.index,
+                        "type": found.chunk_type.to_string(),
+                        "offset": found.offset,
+                        "decompressed": found.decompressed,
+                        "length": found.bytes.len(),
+                        "match": preview,
+                    }),
+                ),
+                _ => writeln!(
+                    stdout,
+                    "{}: {} chunk {} at {:#x}{}: {}",
+                    path.display(),
+                    found.chunk_type,
+                    found.index,
+                    found.offset,
+                    if found.decompressed {
+                        " (decompressed)"
+                    } else {
+                        ""
+                    },
+                    preview
+                )
+                .map_err(PngError::from),
+            };
+        }
+    });
+    batch_result(&failures, pngs.len())?;
+    if total == 0 {
+        return Err(Negative("No matches found".to_string()).into());
+    }
+    Ok(())
+}
+
+/// Print each chunk of the PNG along with its length, and optionally an entropy analysis
+pub fn print(args: PrintArgs, format: OutputFormat) -> PngResult<()> {
+    format.check(
+        "print",
+        &[OutputFormat::Json, OutputFormat::Csv, OutputFormat::Tsv],
+    )?;
+    let handlers = load_handlers(&args.handlers)?;
+    if let Some(delimiter) = format.delimiter() {
+        write_row(&mut io::stdout().lock(), delimiter, CHUNK_COLUMNS)?;
+    }
+    for_each_png(args.file.as_deref(), &args.batch, format, |path, out| {
+        print_file(path, &args, &handlers, format, out)
+    })
+}
+
+/// A decoded summary of the chunk, for the chunk types there is a decoder for
+fn describe_chunk(chunk: &ChunkRef, handlers: &ChunkHandlers) -> Option<String> {
+    if let Some(handler) = handlers.get(chunk.chunk_type()) {
+        return Some(
+            handler
+                .describe(chunk.data())
+                .unwrap_or_else(|e| e.to_string()),
+        );
+    }
+    if chunk.chunk_type().to_string() == SuggestedPalette::CHUNK_TYPE {
+        return Some(match SuggestedPalette::try_from(chunk.data()) {
+            Ok(palette) => palette.to_string(),
+            Err(e) => e.to_string(),
+        });
+    }
+    None
+}
+
+/// A chunk's entry in the JSON inventory `print` writes
+pub fn chunk_json(index: usize, chunk: &ChunkRef) -> Value {
+    json!({
+        "index": index,
+        "type": chunk.chunk_type().to_string(),
+        "category": chunk.chunk_type().category().to_string(),
+        "offset": chunk.offset(),
+        "length": chunk.length(),
+        "crc": chunk.crc(),
+    })
+}
+
+/// Print the chunks of a single file
+fn print_file(
+    path: &Path,
+    args: &PrintArgs,
+    handlers: &ChunkHandlers,
+    format: OutputFormat,
+    out: &mut dyn Write,
+) -> PngResult<()> {
+    let bytes = read_input(path, &args.parse)?;
+    for_each_image(path, &bytes, &args.parse, format, out, |png, image, out| {
+        if let Some(delimiter) = format.delimiter() {
+            for row in chunk_rows(path, &bytes, &png, &[]) {
+                write_row(out, delimiter, &row)?;
+            }
+            return Ok(());
+        }
+        if format == OutputFormat::Json {
+            let chunks: Vec<Value> = png
+                .chunks()
+                .iter()
+                .enumerate()
+                .map(|(index, chunk)| {
+                    let mut entry = chunk_json(index, chunk);
+                    if args.analyze {
+                        entry["entropy"] = json!(entropy::shannon_entropy(chunk.data()));
+                        entry["class"] = json!(entropy::classify(chunk.data()).to_string());
+                    }
+                    if let Some(description) = describe_chunk(chunk, handlers) {
+                        entry["description"] = json!(description);
+                    }
+                    entry
+                })
+                .collect();
+            let mut file = json!({
+                "file": path,
+                "chunks": chunks,
+                "trailing_data": png.trailing_data().len(),
+            });
+            if let Some(image) = image {
+                file["image"] = json!(image);
+            }
+            return write_json(out, &file);
+        }
+        for (index, chunk) in png.chunks().iter().enumerate() {
+            write!(
+                out,
+                "{:>4} {} {:<12} {:>10} bytes",
+                index,
+                chunk.chunk_type(),
+                chunk.chunk_type().category(),
+                chunk.length()
+            )?;
+            if args.analyze {
+                write!(
+                    out,
+                    "  entropy {:.2}  {}",
+                    entropy::shannon_entropy(chunk.data()),
+                    entropy::classify(chunk.data())
+                )?;
+            }
+            if let Some(description) = describe_chunk(chunk, handlers) {
+                write!(out, "  {}", description)?;
+            }
+            writeln!(out)?;
+        }
+        Ok(())
+    })
+}
+
+/// Print the image properties decoded from the IHDR chunk
+pub fn info(args: InfoArgs, format: OutputFormat) -> PngResult<()> {
+    format.check("info", &[OutputFormat::Json])?;
+    for_each_png(args.file.as_deref(), &args.batch, format, |path, out| {
+        let bytes = read_input(path, &args.parse)?;
+        for_each_image(path, &bytes, &args.parse, format, out, |png, image, out| {
+            let png = png.to_png();
+            if format != OutputFormat::Json {
+                return info_file(&png, &args, out);
+            }
+            let mut info = info_json(path, &png, &args)?;
+            if let Some(image) = image {
+                info["image"] = json!(image);
+            }
+            write_json(out, &info)
+        })
+    })
+}
+
+/// Break down where the bytes of each file go, by chunk type
+pub fn stats(args: StatsArgs, format: OutputFormat) -> PngResult<()> {
+    format.check("stats", &[OutputFormat::Json])?;
+    for_each_png(args.file.as_deref(), &args.batch, format, |path, out| {
+        let bytes = read_input(path, &args.parse)?;
+        let stats = Stats::new(&parse_png_ref(path, &bytes, &args.parse)?);
+        match format {
+            OutputFormat::Json => write_json(out, &stats_json(path, &stats)),
+            _ => stats_table(&stats, out),
+        }
+    })
+}
+
+/// The size breakdown of a single file as JSON
+fn stats_json(path: &Path, stats: &Stats) -> Value {
+    let totals = |totals: Totals| {
+        json!({
+            "count": totals.count,
+            "data_bytes": totals.data_bytes,
+            "bytes": totals.bytes,
+            "percent": stats.percent(totals.bytes),
+        })
+    };
+    let types: Vec<Value> = stats
+        .types
+        .iter()
+        .map(|(chunk_type, type_totals)| {
+            let mut entry = totals(*type_totals);
+            entry["type"] = json!(chunk_type.to_string());
+            entry
+        })
+        .collect();
+    let largest: Vec<Value> = stats
+        .largest
+        .iter()
+        .map(|chunk| {
+            json!({
+                "index": chunk.index,
+                "type": chunk.chunk_type.to_string(),
+                "length": chunk.length,
+            })
+        })
+        .collect();
+    let ancillary = stats.ancillary();
+    let mut ancillary_json = totals(ancillary);
+    ancillary_json["framing_bytes"] = json!(ancillary.framing_bytes());
+    json!({
+        "file": path,
+        "file_bytes": stats.file_bytes,
+        "image_data": totals(stats.image_data()),
+        "other_critical": totals(stats.other_critical()),
+        "ancillary": ancillary_json,
+        "trailing_bytes": stats.trailing_bytes,
+        "types": types,
+        "largest": largest,
+    })
+}
+
+/// The size breakdown of a single file as a table
+fn stats_table(stats: &Stats, out: &mut dyn Write) -> PngResult<()> {
+    let line = |out: &mut dyn Write, label: &str, totals: Totals| {
+        writeln!(
+            out,
+            "{:<18}{} bytes ({:.1}%) in {} chunks",
+            label,
+            totals.bytes,
+            stats.percent(totals.bytes),
+            totals.count
+        )
+    };
+    writeln!(out, "File size:        {} bytes", stats.file_bytes)?;
+    line(out, "Image data:", stats.image_data())?;
+    line(out, "Other critical:", stats.other_critical())?;
+    let ancillary = stats.ancillary();
+    line(out, "Ancillary:", ancillary)?;
+    writeln!(out, "  Chunk framing:  {} bytes", ancillary.framing_bytes())?;
+    writeln!(
+        out,
+        "Trailing data:    {} bytes ({:.1}%)",
+        stats.trailing_bytes,
+        stats.percent(stats.trailing_bytes)
+    )?;
+
+    writeln!(out)?;
+    writeln!(out, "Type  Count       Bytes   Share")?;
+    for (chunk_type, totals) in &stats.types {
+        writeln!(
+            out,
+            "{}  {:>5} {:>11} {:>6.1}%",
+            chunk_type,
+            totals.count,
+            totals.bytes,
+            stats.percent(totals.bytes)
+        )?;
+    }
+
+    writeln!(out)?;
+    writeln!(out, "Largest chunks:")?;
+    for chunk in &stats.largest {
+        writeln!(
+            out,
+            "{:>4} {} {:>11} bytes",
+            chunk.index, chunk.chunk_type, chunk.length
+        )?;
+    }
+    Ok(())
+}
+
+/// List the chunks added, removed and rewritten between two files, failing if there are any
+pub fn diff(args: DiffArgs, format: OutputFormat) -> PngResult<()> {
+    format.check("diff", &[OutputFormat::Json])?;
+    let old_bytes = read_input(&args.old, &args.parse)?;
+    let new_bytes = read_input(&args.new, &args.parse)?;
+    let old = parse_png_ref(&args.old, &old_bytes, &args.parse)?;
+    let new = parse_png_ref(&args.new, &new_bytes, &args.parse)?;
+    let changes = diff::chunk_changes(&old, &new);
+    let (old_trailing, new_trailing) = (old.trailing_data(), new.trailing_data());
+    let identical = changes.is_empty() && old_trailing == new_trailing;
+    if let Some(path) = &args.patch {
+        let patch = Patch::between(&old, &new);
+        fs::write(path, serde_json::to_string_pretty(&patch.to_json())? + "\n")?;
+    }
+
+    let mut stdout = io::stdout().lock();
+    if format == OutputFormat::Json {
+        let changes: Vec<Value> = changes.iter().map(chunk_change_json).collect();
+        let report = json!({
+            "old": args.old,
+            "new": args.new,
+            "identical": identical,
+            "changes": changes,
+            "trailing_data": {
+                "old_length": old_trailing.len(),
+                "new_length": new_trailing.len(),
+                "identical": old_trailing == new_trailing,
+            },
+        });
+        write_json(&mut stdout, &report)?;
+    } else {
+        for change in &changes {
+            writeln!(stdout, "{}", describe_chunk_change(change))?;
+        }
+        if old_trailing != new_trailing {
+            writeln!(
+                stdout,
+                "~ trailing data ({} -> {} bytes)",
+                old_trailing.len(),
+                new_trailing.len()
+            )?;
+        }
+    }
+    if !identical {
+        let message = format!("{} and {} differ", args.old.display(), args.new.display());
+        return Err(Negative(message).into());
+    }
+    Ok(())
+}
+
+/// Apply a patch written by `diff --patch` to each file
+pub fn apply(args: ApplyArgs) -> PngResult<()> {
+    let patch = Patch::from_json(&serde_json::from_slice(&fs::read(&args.patch)?)?)?;
+    edit_each_png(
+        args.file.as_deref(),
+        &args.batch,
+        "patch",
+        &args.write.save,
+        |path, out| {
+            let mut png = read_png(path, &args.parse)?;
+            patch.apply(&mut png)?;
+            write_png(path, &mut png, &args.write, out)
+        },
+    )
+}
+
+/// Copy ancillary chunks from one file into another, listing what was and wasn't copied
+pub fn copy_meta(args: CopyMetaArgs) -> PngResult<()> {
+    let from = read_png(&args.from, &args.parse)?;
+    let mut to = read_png(&args.to, &args.parse)?;
+    let options = CopyOptions {
+        types: args.types.clone(),
+        allow_unsafe: args.allow_unsafe,
+    };
+    let report = copy::copy_metadata(&from, &mut to, &options)?;
+    let mut stdout = io::stdout().lock();
+    for skipped in &report.skipped {
+        writeln!(stdout, "{}", skipped)?;
+    }
+    if report.copied.is_empty() {
+        writeln!(stdout, "Nothing to copy")?;
+        return Ok(());
+    }
+    if !args.write.save.dry_run {
+        for chunk in &report.copied {
+            writeln!(
+                stdout,
+                "Copied {} chunk ({} bytes)",
+                chunk.chunk_type(),
+                chunk.length()
+            )?;
+        }
+    }
+    write_png(&args.to, &mut to, &args.write, &mut stdout)
+}
+
+/// Merge the ancillary chunks of an original file into a regenerated one
+pub fn merge(args: MergeArgs) -> PngResult<()> {
+    let original = read_png(&args.from, &args.parse)?;
+    let mut png = read_png(&args.file, &args.parse)?;
+    let report = png.merge_ancillary(&original, args.policy)?;
+    let mut stdout = io::stdout().lock();
+    for skipped in &report.skipped {
+        writeln!(stdout, "{}", skipped)?;
+    }
+    for chunk_type in &report.kept {
+        writeln!(stdout, "Kept the existing {} chunk", chunk_type)?;
+    }
+    if report.added.is_empty() {
+        writeln!(stdout, "Nothing to merge")?;
+        return Ok(());
+    }
+    if !args.write.save.dry_run {
+        for chunk in &report.added {
+            writeln!(
+                stdout,
+                "Merged {} chunk ({} bytes)",
+                chunk.chunk_type(),
+                chunk.length()
+            )?;
+        }
+    }
+    write_png(&args.file, &mut png, &args.write, &mut stdout)
+}
+
+/// Put the chunks of each PNG into canonical order, or with --check report those which aren't
+pub fn normalize(args: NormalizeArgs) -> PngResult<()> {
+    if args.check {
+        return for_each_png(
+            args.file.as_deref(),
+            &args.batch,
+            OutputFormat::Text,
+            |path, out| check_normalized(path, &args, out),
+        );
+    }
+    edit_each_png(
+        args.file.as_deref(),
+        &args.batch,
+        "normalize",
+        &args.write.save,
+        |path, out| normalize_file(path, &args, out),
+    )
+}
+
+/// Fail unless a single file is already in canonical form
+fn check_normalized(path: &Path, args: &NormalizeArgs, out: &mut dyn Write) -> PngResult<()> {
+    let mut png = read_png(path, &args.parse)?;
+    if normalize::normalize(&mut png) {
+        return Err(error::error(
+            ErrorKind::Validation,
+            "File is not normalized",
+        ));
+    }
+    writeln!(out, "File is normalized")?;
+    Ok(())
+}
+
+/// Normalize a single file
+fn normalize_file(path: &Path, args: &NormalizeArgs, out: &mut dyn Write) -> PngResult<()> {
+    let mut png = read_png(path, &args.parse)?;
+    if !normalize::normalize(&mut png) {
+        writeln!(out, "Already normalized")?;
+        if args.output.is_none() {
+            return Ok(());
+        }
+    }
+    let output = args.output.as_deref().unwrap_or(path);
+    write_png(output, &mut png, &args.write, out)
+}
+
+/// Remove duplicate ancillary chunks from each PNG, listing everything removed
+pub fn dedupe(args: DedupeArgs) -> PngResult<()> {
+    edit_each_png(
+        args.file.as_deref(),
+        &args.batch,
+        "dedupe",
+        &args.write.save,
+        |path, out| dedupe_file(path, &args, out),
+    )
+}
+
+/// Deduplicate a single file
+fn dedupe_file(path: &Path, args: &DedupeArgs, out: &mut dyn Write) -> PngResult<()> {
+    let mut png = read_png(path, &args.parse)?;
+    let options = DedupeOptions {
+        first_wins: args.first_wins,
+    };
+    let report = dedupe::dedupe(&mut png, options);
+    for conflict in &report.conflicts {
+        warn!("{}; pass --first-wins to remove it", conflict);
+    }
+    let output = args.output.as_deref().unwrap_or(path);
+    if args.write.save.dry_run && !report.removed.is_empty() {
+        // Saving lists what would be removed
+        return write_png(output, &mut png, &args.write, out);
+    }
+    for chunk in &report.removed {
+        writeln!(
+            out,
+            "Removed duplicate {} chunk ({} bytes)",
+            chunk.chunk_type(),
+            chunk.length()
+        )?;
+    }
+    if report.removed.is_empty() {
+        writeln!(out, "No duplicate chunks found")?;
+        if args.output.is_none() {
+            return Ok(());
+        }
+    }
+    write_png(output, &mut png, &args.write, out)
+}
+
+/// Write each chunk of the PNG to a numbered file alongside a manifest listing them
+pub fn split(args: SplitArgs) -> PngResult<()> {
+    let png = inspect_png(&args.file, &args.parse)?;
+    let manifest = split::split(&png, &args.out_dir)?;
+    println!(
+        "Wrote {} chunks and {} to {}",
+        manifest.chunks.len(),
+        split::MANIFEST_FILE_NAME,
+        args.out_dir.display()
+    );
+    Ok(())
+}
+
+/// Rebuild a PNG from the chunk files listed in a manifest written by `split`
+pub fn assemble(args: AssembleArgs) -> PngResult<()> {
+    let mut png = split::assemble(&args.manifest)?;
+    let mut stdout = io::stdout().lock();
+    write_png(&args.output, &mut png, &args.write, &mut stdout)?;
+    if !args.write.save.dry_run {
+        writeln!(
+            stdout,
+            "Assembled {} chunks into {}",
+            png.chunks().len(),
+            args.output.display()
+        )?;
+    }
+    Ok(())
+}
+
+/// Shrink each PNG losslessly, reporting how many bytes were saved
+pub fn optimize(args: OptimizeArgs) -> PngResult<()> {
+    edit_each_png(
+        args.file.as_deref(),
+        &args.batch,
+        "optimize",
+        &args.write.save,
+        |path, out| optimize_file(path, &args, out),
+    )
+}
+
+/// Optimize a single file
+fn optimize_file(path: &Path, args: &OptimizeArgs, out: &mut dyn Write) -> PngResult<()> {
+    let mut png = read_png(path, &args.parse)?;
+    let options = OptimizeOptions {
+        level: args.level,
+        keep_text: args.keep_text,
+    };
+    let report = optimize::optimize(&mut png, options)?;
+    let output = args.output.as_deref().unwrap_or(path);
+    if args.write.save.dry_run {
+        return write_png(output, &mut png, &args.write, out);
+    }
+    for chunk in &report.removed {
+        writeln!(
+            out,
+            "Removed {} chunk ({} bytes)",
+            chunk.chunk_type(),
+            chunk.length()
+        )?;
+    }
+    if report.trailing_data_len > 0 {
+        writeln!(
+            out,
+            "Removed {} bytes of data after IEND",
+            report.trailing_data_len
+        )?;
+    }
+    let (before, after) = report.idat_chunks;
+    if before != after {
+        writeln!(out, "Merged {} IDAT chunks into {}", before, after)?;
+    }
+    let (before, after) = report.image_data_len;
+    if before != after {
+        writeln!(
+            out,
+            "Recompressed the image data from {} to {} bytes",
+            before, after
+        )?;
+    }
+    if report.saved() == 0 {
+        writeln!(out, "Already optimized")?;
+        if args.output.is_none() {
+            return Ok(());
+        }
+    } else {
+        let (before, after) = report.file_len;
+        writeln!(
+            out,
+            "Saved {} bytes ({:.1}%), {} -> {} bytes",
+            report.saved(),
+            report.saved() as f64 * 100.0 / before as f64,
+            before,
+            after
+        )?;
+    }
+    write_png(output, &mut png, &args.write, out)
+}
+
+/// A line of `diff` output describing a change, marked +, - or ~ like a unified diff
+fn describe_chunk_change(change: &ChunkChange) -> String {
+    match *change {
+        ChunkChange::Added {
+            index,
+            chunk_type,
+            length,
+            crc,
+        } => format!(
+            "+ {} chunk {} ({} bytes, CRC {:08x})",
+            chunk_type, index, length, crc
+        ),
+        ChunkChange::Removed {
+            index,
+            chunk_type,
+            length,
+            crc,
+        } => format!(
+            "- {} chunk {} ({} bytes, CRC {:08x})",
+            chunk_type, index, length, crc
+        ),
+        ChunkChange::Rewritten {
+            old_index,
+            index,
+            chunk_type,
+            old_length,
+            new_length,
+            old_crc,
+            new_crc,
+        } => format!(
+            "~ {} chunk {} -> {} ({} -> {} bytes, {:+}; CRC {:08x} -> {:08x})",
+            chunk_type,
+            old_index,
+            index,
+            old_length,
+            new_length,
+            i64::from(new_length) - i64::from(old_length),
+            old_crc,
+            new_crc
+        ),
+    }
+}
+
+fn chunk_change_json(change: &ChunkChange) -> Value {
+    match *change {
+        ChunkChange::Added {
+            index,
+            chunk_type,
+            length,
+            crc,
+        } => json!({
+            "change": "added",
+            "type": chunk_type.to_string(),
+            "index": index,
+            "length": length,
+            "crc": crc,
+        }),
+        ChunkChange::Removed {
+            index,
+            chunk_type,
+            length,
+            crc,
+        } => json!({
+            "change": "removed",
+            "type": chunk_type.to_string(),
+            "old_index": index,
+            "length": length,
+            "crc": crc,
+        }),
+        ChunkChange::Rewritten {
+            old_index,
+            index,
+            chunk_type,
+            old_length,
+            new_length,
+            old_crc,
+            new_crc,
+        } => json!({
+            "change": "rewritten",
+            "type": chunk_type.to_string(),
+            "old_index": old_index,
+            "index": index,
+            "old_length": old_length,
+            "length": new_length,
+            "old_crc": old_crc,
+            "crc": new_crc,
+        }),
+    }
+}
+
+/// The image properties of a single file as JSON
+fn info_json(path: &Path, png: &Png, args: &InfoArgs) -> PngResult<Value> {
+    let ihdr = Ihdr::try_from(png)?;
+    let palettes = splt::suggested_palettes(png)
+        .map(|palette| palette.map(|palette| palette.to_string()))
+        .collect::<PngResult<Vec<_>>>()?;
+    let mut info = json!({
+        "file": path,
+        "width": ihdr.width,
+        "height": ihdr.height,
+        "bit_depth": ihdr.bit_depth,
+        "color_type": ihdr.color_type.to_string(),
+        "bits_per_pixel": ihdr.bits_per_pixel(),
+        "interlaced": ihdr.interlaced,
+        "raw_data_size": ihdr.raw_data_len(),
+        "suggested_palettes": palettes,
+    });
+    if let Some(animation) = apng::get_animation(png)? {
+        let frames: Vec<Value> = animation
+            .frames
+            .iter()
+            .map(|frame| {
+                json!({
+                    "width": frame.width,
+                    "height": frame.height,
+                    "x_offset": frame.x_offset,
+                    "y_offset": frame.y_offset,
+                    "delay": frame.delay(),
+                    "dispose": frame.dispose_op.to_string(),
+                    "blend": frame.blend_op.to_string(),
+                })
+            })
+            .collect();
+        info["animation"] = json!({
+            "frames": animation.control.num_frames,
+            "plays": animation.control.num_plays,
+            "duration": animation.duration(),
+            "default_image_is_frame": animation.default_image_is_frame,
+            "frame_controls": frames,
+        });
+    }
+    if args.palette {
+        let transparency = palette::get_transparency(png)?;
+        let entries: Option<Vec<Value>> = palette::get_palette(png)?.map(|palette| {
+            palette
+                .entries()
+                .iter()
+                .enumerate()
+                .map(|(index, rgb)| {
+                    let alpha = transparency.as_ref().map_or(u8::MAX, |t| t.alpha(index));
+                    json!({ "color": rgb.to_string(), "alpha": alpha })
+                })
+                .collect()
+        });
+        info["palette"] = json!(entries);
+        info["transparent"] = match transparency {
+            Some(Transparency::Gray(gray)) => json!({ "gray": gray }),
+            Some(Transparency::Rgb(red, green, blue)) => {
+                json!({ "red": red, "green": green, "blue": blue })
+            }
+            _ => Value::Null,
+        };
+    }
+    if args.exif {
+        info["exif"] = match png.chunk_by_type(EXIF_CHUNK_TYPE) {
+            None => Value::Null,
+            Some(chunk) => {
+                let entries = exif::read_exif(chunk.data())?;
+                let tags: Vec<Value> = entries
+                    .iter()
+                    .map(|entry| {
+                        json!({
+                            "ifd": entry.ifd.to_string(),
+                            "tag": entry.tag,
+                            "name": entry.name(),
+                            "value": entry.value.to_string(),
+                        })
+                    })
+                    .collect();
+                let gps = exif::gps_position(&entries).map(
+                    |(latitude, longitude)| json!({ "latitude": latitude, "longitude": longitude }),
+                );
+                json!({ "tags": tags, "gps": gps })
+            }
+        };
+    }
+    Ok(info)
+}
+
+/// Print the image properties of a single file
+fn info_file(png: &Png, args: &InfoArgs, out: &mut dyn Write) -> PngResult<()> {
+    let ihdr = Ihdr::try_from(png)?;
+    writeln!(out, "Dimensions:       {}x{}", ihdr.width, ihdr.height)?;
+    writeln!(out, "Bit depth:        {}", ihdr.bit_depth)?;
+    writeln!(
+        out,
+        "Color type:       {} ({})",
+        ihdr.color_type,
+        ihdr.color_type.value()
+    )?;
+    writeln!(out, "Bits per pixel:   {}", ihdr.bits_per_pixel())?;
+    writeln!(
+        out,
+        "Compression:      deflate ({})",
+        ihdr.compression_method
+    )?;
+    writeln!(out, "Filter method:    adaptive ({})", ihdr.filter_method)?;
+    writeln!(
+        out,
+        "Interlace:        {}",
+        if ihdr.interlaced { "Adam7" } else { "none" }
+    )?;
+    writeln!(out, "Raw data size:    {} bytes", ihdr.raw_data_len())?;
+    for palette in splt::suggested_palettes(png) {
+        writeln!(out, "sPLT palette:     {}", palette?)?;
+    }
+    if let Some(animation) = apng::get_animation(png)? {
+        writeln!(
+            out,
+            "Animation:        {}, {:.3}s per loop",
+            animation.control,
+            animation.duration()
+        )?;
+        if !animation.default_image_is_frame {
+            writeln!(out, "  Default image:  not part of the animation")?;
+        }
+        for (index, frame) in animation.frames.iter().enumerate() {
+            writeln!(out, "  Frame {:<3}      {}", index, frame)?;
+        }
+    }
+
+    if args.palette {
+        print_palette(png, out)?;
+    }
+    if args.exif {
+        let Some(chunk) = png.chunk_by_type(EXIF_CHUNK_TYPE) else {
+            writeln!(out, "EXIF:             none")?;
+            return Ok(());
+        };
+        let entries = exif::read_exif(chunk.data())?;
+        writeln!(out, "EXIF:             {} tags", entries.len())?;
+        for entry in &entries {
+            writeln!(out, "  {}", entry)?;
+        }
+        if let Some((latitude, longitude)) = exif::gps_position(&entries) {
+            writeln!(out, "  GPS position: {:.6}, {:.6}", latitude, longitude)?;
+        }
+    }
+    Ok(())
+}
+
+/// Print the palette entries with their alpha, and any transparent colour
+fn print_palette(png: &Png, out: &mut dyn Write) -> PngResult<()> {
+    let transparency = palette::get_transparency(png)?;
+    match palette::get_palette(png)? {
+        Some(palette) => {
+            writeln!(out, "Palette:          {} entries", palette.entries().len())?;
+            for (index, rgb) in palette.entries().iter().enumerate() {
+                let alpha = transparency.as_ref().map_or(u8::MAX, |t| t.alpha(index));
+                writeln!(out, "  {:3}: {} alpha {}", index, rgb, alpha)?;
+            }
+        }
+        None => writeln!(out, "Palette:          none")?,
+    }
+    match transparency {
+        Some(Transparency::Gray(gray)) => writeln!(out, "Transparent:      gray {}", gray)?,
+        Some(Transparency::Rgb(red, green, blue)) => {
+            writeln!(out, "Transparent:      rgb({}, {}, {})", red, green, blue)?
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Print the text chunks of the PNG, or just the text stored under one keyword
+pub fn text_get(args: TextGetArgs) -> PngResult<()> {
+    let png = inspect_png(&args.file, &args.parse)?;
+    let mut found = false;
+    for text in text::text_chunks(&png) {
+        let text = match text {
+            Ok(text) => text,
+            Err(e) => {
+                warn!("{}: skipping text chunk: {}", args.file.display(), e);
+                continue;
+            }
+        };
+        match &args.keyword {
+            Some(keyword) if text.keyword() == keyword => println!("{}", text.text()),
+            Some(_) => continue,
+            None => match &text {
+                TextualChunk::International(itxt) if !itxt.language_tag().is_empty() => {
+                    let translated = match itxt.translated_keyword() {
+                        "" => String::new(),
+                        translated => format!(" ({})", translated),
+                    };
+                    println!(
+                        "{} [{}]{}: {}",
+                        itxt.keyword(),
+                        itxt.language_tag(),
+                        translated,
+                        itxt.text()
+                    )
+                }
+                _ => println!("{}: {}", text.keyword(), text.text()),
+            },
+        }
+        found = true;
+    }
+    match &args.keyword {
+        Some(keyword) if !found => Err(error::error(
+            ErrorKind::ChunkNotFound,
+            format!("No text found with keyword {:?}", keyword),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Store text under a keyword and write the file back
+pub fn text_set(args: TextSetArgs) -> PngResult<()> {
+    let mut png = read_png(&args.file, &args.parse)?;
+    let international = args.language.is_some()
+        || args.translated_keyword.is_some()
+        || text::latin1_encode(&args.value).is_err();
+    if international {
+        let mut text = ItxtChunk::new(&args.keyword, &args.value)?;
+        text.set_language_tag(args.language.as_deref().unwrap_or_default())?;
+        text.set_translated_keyword(args.translated_keyword.as_deref().unwrap_or_default())?;
+        text.set_compressed(args.compress);
+        text::set_text(&mut png, text)?;
+    } else {
+        let mut text = TextChunk::new(&args.keyword, &args.value)?;
+        text.set_compressed(args.compress);
+        text::set_text(&mut png, text)?;
+    }
+    write_png(&args.file, &mut png, &args.write, &mut io::stdout())
+}
+
+/// Print the XMP packet or write it to a file
+pub fn xmp_get(args: XmpGetArgs) -> PngResult<()> {
+    let png = inspect_png(&args.file, &args.parse)?;
+    let packet = xmp::get_xmp(&png)?.ok_or_else(|| not_found("No XMP packet found"))?;
+    match &args.out {
+        Some(out) => fs::write(out, packet)?,
+        None => println!("{}", packet),
+    }
+    Ok(())
+}
+
+/// Store the XMP packet from a file and write the PNG back
+pub fn xmp_set(args: XmpSetArgs) -> PngResult<()> {
+    let mut png = read_png(&args.file, &args.parse)?;
+    xmp::set_xmp(&mut png, &fs::read_to_string(&args.packet)?)?;
+    write_png(&args.file, &mut png, &args.write, &mut io::stdout())
+}
+
+/// Remove the XMP packet and write the PNG back
+pub fn xmp_remove(args: XmpRemoveArgs) -> PngResult<()> {
+    let mut png = read_png(&args.file, &args.parse)?;
+    if xmp::remove_xmp(&mut png) == 0 {
+        return Err(not_found("No XMP packet found"));
+    }
+    write_png(&args.file, &mut png, &args.write, &mut io::stdout())
+}
+
+/// Print the last modification time recorded in the tIME chunk
+pub fn time_get(args: TimeGetArgs) -> PngResult<()> {
+    let png = inspect_png(&args.file, &args.parse)?;
+    let time = time::get_time(&png)?.ok_or_else(|| not_found("No tIME chunk found"))?;
+    println!("{}", time);
+    Ok(())
+}
+
+/// Set the tIME chunk to the given time, or to now, and write the file back
+pub fn time_set(args: TimeSetArgs) -> PngResult<()> {
+    let mut png = read_png(&args.file, &args.parse)?;
+    let time = match &args.time {
+        Some(time) => TimeChunk::from_str(time)?,
+        None => TimeChunk::now()?,
+    };
+    time::set_time(&mut png, time)?;
+    save(&args.file, &png.as_bytes(), &args.save, &mut io::stdout())
+}
+
+/// Print the pixel density recorded in the pHYs chunk
+pub fn dpi_get(args: DpiGetArgs) -> PngResult<()> {
+    let png = inspect_png(&args.file, &args.parse)?;
+    let phys = phys::get_phys(&png)?.ok_or_else(|| not_found("No pHYs chunk found"))?;
+    println!("{}", phys);
+    Ok(())
+}
+
+/// Set the pixel density in dots per inch and write the file back
+pub fn dpi_set(args: DpiSetArgs) -> PngResult<()> {
+    let mut png = read_png(&args.file, &args.parse)?;
+    let phys = PhysChunk::from_dpi(args.dpi, args.vertical.unwrap_or(args.dpi))?;
+    phys::set_phys(&mut png, phys)?;
+    write_png(&args.file, &mut png, &args.write, &mut io::stdout())
+}
+
+/// Print the image gamma, warning about any conflict with the sRGB or iCCP chunks
+pub fn gamma_get(args: GammaGetArgs) -> PngResult<()> {
+    let png = inspect_png(&args.file, &args.parse)?;
+    for conflict in gamma::color_space_conflicts(&png)? {
+        warn!("{}: {}", args.file.display(), conflict);
+    }
+    let gamma = gamma::get_gamma(&png)?.ok_or_else(|| not_found("No gAMA chunk found"))?;
+    println!("{}", gamma);
+    Ok(())
+}
+
+/// Set the image gamma and write the file back
+pub fn gamma_set(args: GammaSetArgs) -> PngResult<()> {
+    let mut png = read_png(&args.file, &args.parse)?;
+    gamma::set_gamma(&mut png, GamaChunk::from_exponent(args.exponent)?)?;
+    for conflict in gamma::color_space_conflicts(&png)? {
+        warn!("{}: {}", args.file.display(), conflict);
+    }
+    write_png(&args.file, &mut png, &args.write, &mut io::stdout())
+}
+
+/// Write the embedded ICC profile out to its own file
+pub fn icc_extract(args: IccExtractArgs) -> PngResult<()> {
+    let png = inspect_png(&args.file, &args.parse)?;
+    let iccp = iccp::get_icc_profile(&png)?.ok_or_else(|| not_found("No iCCP chunk found"))?;
+    fs::write(&args.profile, iccp.profile())?;
+    println!(
+        "Wrote {} byte profile \"{}\" to {}",
+        iccp.profile().len(),
+        iccp.name(),
+        args.profile.display()
+    );
+    Ok(())
+}
+
+/// Embed an ICC profile read from a file and write the PNG back
+pub fn icc_embed(args: IccEmbedArgs) -> PngResult<()> {
+    let mut png = read_png(&args.file, &args.parse)?;
+    let iccp = IccpChunk::new(&args.name, fs::read(&args.profile)?)?;
+    if iccp::embed_icc_profile(&mut png, &iccp)? {
+        println!("Removed the sRGB chunk, which the profile replaces");
+    }
+    write_png(&args.file, &mut png, &args.write, &mut io::stdout())
+}
+
+/// Put still PNGs together as the frames of an animated PNG
+pub fn apng_build(args: ApngBuildArgs) -> PngResult<()> {
+    let frames = args
+        .frames
+        .iter()
+        .map(|path| inspect_png(path, &args.parse))
+        .collect::<PngResult<Vec<_>>>()?;
+    let mut png = apng::build(&frames, args.delay, args.plays)?;
+    let mut stdout = io::stdout().lock();
+    write_png(&args.out, &mut png, &args.write, &mut stdout)?;
+    if !args.write.save.dry_run {
+        writeln!(
+            stdout,
+            "Wrote {} frames to {}",
+            frames.len(),
+            args.out.display()
+        )?;
+    }
+    Ok(())
+}
+
+/// Render each frame of an animated PNG as a still image in the output directory
+pub fn apng_explode(args: ApngExplodeArgs, format: OutputFormat) -> PngResult<()> {
+    format.check("apng explode", &[OutputFormat::Json])?;
+    let png = inspect_png(&args.file, &args.parse)?;
+    let animation = apng::get_animation(&png)?
+        .ok_or_else(|| not_found("No acTL chunk found; the PNG isn't animated"))?;
+    let stills = apng::explode(&png)?;
+    fs::create_dir_all(&args.out_dir)?;
+
+    let mut stdout = io::stdout().lock();
+    for (index, (still, frame)) in stills.iter().zip(&animation.frames).enumerate() {
+        let file = args.out_dir.join(format!("frame-{:03}.png", index));
+        fs::write(&file, still.as_bytes())?;
+        match format {
+            OutputFormat::Json => write_json(
+                &mut stdout,
+                &json!({
+                    "index": index,
+                    "width": frame.width,
+                    "height": frame.height,
+                    "x_offset": frame.x_offset,
+                    "y_offset": frame.y_offset,
+                    "delay": frame.delay(),
+                    "dispose": frame.dispose_op.to_string(),
+                    "blend": frame.blend_op.to_string(),
+                    "file": file,
+                }),
+            )?,
+            _ => writeln!(stdout, "Frame {}: {} -> {}", index, frame, file.display())?,
+        }
+    }
+    if format == OutputFormat::Text {
+        writeln!(
+            stdout,
+            "Wrote {} frames to {}",
+            stills.len(),
+            args.out_dir.display()
+        )?;
+    }
+    Ok(())
+}
+
+/// Report the current size of the PNG and the impact of hiding a payload of the given size
+pub fn capacity(args: CapacityArgs) -> PngResult<()> {
+    let bytes = read_input(&args.file, &args.parse)?;
+    let png = parse_png_ref(&args.file, &bytes, &args.parse)?;
+    let ancillary: Vec<&ChunkRef> = png
+        .chunks()
+        .iter()
+        .filter(|chunk| !chunk.chunk_type().is_critical())
+        .collect();
+    let ancillary_len: usize = ancillary
+        .iter()
+        .map(|chunk| chunk.overall_length() as usize)
+        .sum();
+
+    println!("File size:        {} bytes", bytes.len());
+    println!("Chunks:           {}", png.chunks().len());
+    println!(
+        "Ancillary data:   {} bytes in {} chunks",
+        ancillary_len,
+        ancillary.len()
+    );
+
+    if let Some(payload_size) = args.payload_size {
+        // A plain message carries the smallest possible envelope
+        let envelope_len = Envelope::text("").as_bytes()?.len();
+        let plan = segment::plan(payload_size + envelope_len, args.max_chunk_size)?;
+        let chunk_overhead = plan.chunks * Chunk::OVERHEAD_BYTES_LEN;
+        let impact = plan.data_len + chunk_overhead;
+
+        println!("Planned payload:  {} bytes", payload_size);
+        println!("  Envelope:       {} bytes", envelope_len);
+        println!(
+            "  Chunks needed:  {} (at most {} bytes each)",
+            plan.chunks, args.max_chunk_size
+        );
+        println!(
+            "  Chunk overhead: {} bytes",
+            chunk_overhead + plan.data_len - payload_size - envelope_len
+        );
+        println!(
+            "  Size impact:    +{} bytes ({:.1}%)",
+            impact,
+            impact as f64 * 100.0 / bytes.len() as f64
+        );
+        println!("  New file size:  {} bytes", bytes.len() + impact);
+    }
+    Ok(())
+}
+
+/// What scanning a single file found
+struct ScannedFile {
+    findings: Vec<Finding>,
+    /// The bytes each finding covers
+    regions: Vec<Range<usize>>,
+    /// The file's chunks as table rows, if they were asked for
+    rows: Vec<Vec<String>>,
+    /// The file's section of the audit report, if one was asked for
+    report: Option<FileReport>,
+}
+
+/// Read and scan a single file, borrowing the chunk data rather than copying it, and list its
+/// chunks as table rows or summarise them for the audit report if asked to
+fn scan_file(path: &Path, args: &ScanArgs, rows: bool) -> PngResult<ScannedFile> {
+    let options = ScanOptions {
+        text_threshold: args.text_threshold,
+        pixels: !args.no_pixels,
+    };
+    let bytes = read_input(path, &args.parse)?;
+    let png = parse_png_ref(path, &bytes, &args.parse)?;
+    let findings = scan::scan(&png, options);
+    let regions = findings
+        .iter()
+        .map(|finding| match finding.chunk_index {
+            Some(index) => {
+                let chunk = &png.chunks()[index];
+                let offset = chunk.offset();
+                offset..offset + chunk.length() as usize + 12
+            }
+            None => bytes.len() - finding.size..bytes.len(),
+        })
+        .collect();
+    let rows = match rows {
+        true => chunk_rows(path, &bytes, &png, &findings),
+        false => vec![],
+    };
+    let report = args
+        .report
+        .as_ref()
+        .map(|_| FileReport::new(path, &png, findings.clone()));
+    Ok(ScannedFile {
+        findings,
+        regions,
+        rows,
+        report,
+    })
+}
+
+/// Score each file's risk of hiding a payload from its pixel statistics and suspicious chunks
+pub fn analyze(args: AnalyzeArgs, format: OutputFormat) -> PngResult<()> {
+    format.check("analyze", &[OutputFormat::Json])?;
+    for_each_png(args.file.as_deref(), &args.batch, format, |path, out| {
+        let bytes = read_input(path, &args.parse)?;
+        let analysis = analysis::analyse(&parse_png_ref(path, &bytes, &args.parse)?)?;
+        match format {
+            OutputFormat::Json => write_json(out, &analysis_json(path, &analysis)),
+            _ => analysis_text(&analysis, out),
+        }
+    })
+}
+
+/// The statistics and findings behind a file's risk score as JSON
+fn analysis_json(path: &Path, analysis: &Analysis) -> Value {
+    let findings: Vec<Value> = analysis
+        .findings
+        .iter()
+        .map(|finding| {
+            json!({
+                "kind": finding.kind.id(),
+                "chunk_type": finding.chunk_type,
+                "size": finding.size,
+                "message": finding.to_string(),
+            })
+        })
+        .collect();
+    json!({
+        "file": path,
+        "risk_score": analysis.risk_score(),
+        "risk_level": analysis.risk_level().to_string(),
+        "pixels": analysis.pixels.map(|pixels| json!({
+            "chi_square": pixels.chi_square,
+            "sample_pairs": pixels.sample_pairs,
+        })),
+        "pixels_skipped": analysis.pixels_skipped,
+        "findings": findings,
+    })
+}
+
+/// Write a file's risk score followed by the evidence for it
+fn analysis_text(analysis: &Analysis, out: &mut dyn Write) -> PngResult<()> {
+    writeln!(
+        out,
+        "Risk: {}/100 ({})",
+        analysis.risk_score(),
+        analysis.risk_level()
+    )?;
+    match (&analysis.pixels, &analysis.pixels_skipped) {
+        (Some(pixels), _) => {
+            writeln!(out, "Chi-square LSB probability: {:.3}", pixels.chi_square)?;
+            writeln!(
+                out,
+                "Sample pair embedding rate: {:.3}",
+                pixels.sample_pairs
+            )?;
+        }
+        (None, Some(reason)) => writeln!(out, "Pixel analysis skipped: {}", reason)?,
+        (None, None) => {}
+    }
+    for finding in &analysis.findings {
+        writeln!(out, "Found {}", finding)?;
+    }
+    Ok(())
+}
+
+/// Extract every PNG found inside the file, listing where each one was
+pub fn carve(args: CarveArgs, format: OutputFormat) -> PngResult<()> {
+    format.check("carve", &[OutputFormat::Json])?;
+    #[cfg(feature = "mmap")]
+    let data = match args.mmap {
+        true => Input::map(&args.blob)?,
+        false => Input::Buffered(progress::read(&args.blob)?),
+    };
+    #[cfg(not(feature = "mmap"))]
+    let data = Input::Buffered(progress::read(&args.blob)?);
+    let carved = carve::carve(&data);
+    fs::create_dir_all(&args.out_dir)?;
+
+    let mut stdout = io::stdout().lock();
+    let mut written = 0;
+    for png in &carved {
+        let file = match (png.complete, args.truncated) {
+            (true, _) => Some(args.out_dir.join(format!("{:08x}.png", png.offset))),
+            (false, true) => Some(
+                args.out_dir
+                    .join(format!("{:08x}-truncated.png", png.offset)),
+            ),
+            (false, false) => None,
+        };
+        if let Some(file) = &file {
+            fs::write(file, &data[png.range()])?;
+            written += 1;
+        }
+        if png.crc_errors > 0 {
+            warn!(
+                "PNG at {:#010x} has {} chunks with bad CRCs",
+                png.offset, png.crc_errors
+            );
+        }
+        match format {
+            OutputFormat::Json => write_json(
+                &mut stdout,
+                &json!({
+                    "offset": png.offset,
+                    "length": png.len,
+                    "chunks": png.chunks,
+                    "complete": png.complete,
+                    "crc_errors": png.crc_errors,
+                    "file": file,
+                }),
+            )?,
+            _ => match &file {
+                Some(file) => writeln!(
+                    stdout,
+                    "{:#010x}: {} bytes in {} chunks -> {}",
+                    png.offset,
+                    png.len,
+                    png.chunks,
+                    file.display()
+                )?,
+                None => writeln!(
+                    stdout,
+                    "{:#010x}: {} bytes in {} chunks, cut short; pass --truncated to extract it",
+                    png.offset, png.len, png.chunks
+                )?,
+            },
+        }
+    }
+    if format == OutputFormat::Text {
+        writeln!(
+            stdout,
+            "Found {} PNGs, extracted {} to {}",
+            carved.len(),
+            written,
+            args.out_dir.display()
+        )?;
+    }
+    Ok(())
+}
+
+/// Scan PNG files for suspicious chunks and trailing data, summarising the likely carriers
+pub fn scan(args: ScanArgs, format: OutputFormat) -> PngResult<()> {
+    let report_format = args
+        .report
+        .as_deref()
+        .map(ReportFormat::from_path)
+        .transpose()?;
+    let mut report = Report::default();
+    let pngs = walk::select_files(args.path.as_deref(), &args.list, &args.walk)?;
+    let (mut carriers, mut unreadable) = (0, vec![]);
+    let delimiter = format.delimiter();
+    let mut sarif = SarifLog::new(
+        FindingKind::ALL
+            .iter()
+            .copied()
+            .map(ReportingRule::from)
+            .collect(),
+    );
+    let work = |path: &Path| {
+        let _span = info_span!("file", path = %path.display()).entered();
+        scan_file(path, &args, delimiter.is_some())
+            .map_err(|e| (error::kind_of(e.as_ref()), e.to_string()))
+    };
+    let mut stdout = io::stdout().lock();
+    if let Some(delimiter) = delimiter {
+        write_row(&mut stdout, delimiter, CHUNK_COLUMNS)?;
+    }
+    run_jobs(&pngs, args.jobs, work, |path, scanned| {
+        let scanned = match scanned {
+            Ok(scanned) => scanned,
+            Err((kind, e)) => {
+                unreadable.push(kind);
+                error!("{}: unreadable: {}", path.display(), e);
+                if report_format.is_some() {
+                    report.files.push(FileReport::unreadable(path, &e));
+                }
+                match format {
+                    OutputFormat::Json => {
+                        let _ = write_json(&mut stdout, &json!({ "file": path, "error": e }));
+                    }
+                    OutputFormat::Sarif => sarif.add_failure(path, &e),
+                    _ => {}
+                }
+                return;
+            }
+        };
+        if !scanned.findings.is_empty() {
+            carriers += 1;
+        }
+        report.files.extend(scanned.report);
+        match format {
+            OutputFormat::Json => {
+                let findings: Vec<Value> = scanned
+                    .findings
+                    .iter()
+                    .map(|finding| {
+                        json!({
+                            "kind": finding.kind.id(),
+                            "chunk_type": finding.chunk_type,
+                            "size": finding.size,
+                            "entropy": finding.entropy,
+                            "tool": finding.signature.as_ref().map(|signature| signature.tool.id()),
+                            "format": finding.embedded.map(|embedded| embedded.format.id()),
+                            "message": finding.to_string(),
+                        })
+                    })
+                    .collect();
+                let _ = write_json(&mut stdout, &json!({ "file": path, "findings": findings }));
+            }
+            OutputFormat::Csv | OutputFormat::Tsv => {
+                let delimiter = delimiter.unwrap_or(',');
+                for row in scanned.rows {
+                    let _ = write_row(&mut stdout, delimiter, &row);
+                }
+            }
+            OutputFormat::Sarif => {
+                for (finding, region) in scanned.findings.iter().zip(scanned.regions) {
+                    // Every finding kind is one of the log's rules
+                    let _ = sarif.add_result(
+                        finding.kind.id(),
+                        Level::Warning,
+                        &finding.to_string(),
+                        path,
+                        Some(region),
+                    );
+                }
+            }
+            OutputFormat::Text => {
+                for finding in scanned.findings {
+                    let _ = writeln!(stdout, "{}: {}", path.display(), finding);
+                }
+            }
+        }
+    });
+    match format {
+        OutputFormat::Text => writeln!(
+            stdout,
+            "Scanned {} files: {} likely carriers, {} unreadable",
+            pngs.len(),
+            carriers,
+            unreadable.len()
+        )?,
+        OutputFormat::Sarif => write_json(&mut stdout, &sarif.to_json())?,
+        _ => {}
+    }
+    if let (Some(path), Some(report_format)) = (&args.report, report_format) {
+        fs::write(path, report.render(report_format))?;
+    }
+    if !unreadable.is_empty() {
+        let message = format!(
+            "{} of {} files were unreadable",
+            unreadable.len(),
+            pngs.len()
+        );
+        return Err(error::error(ErrorKind::common(unreadable), message));
+    }
+    Ok(())
+}
+
+/// Report every spec violation in the file, failing if any of them is an error
+pub fn validate(args: ValidateArgs, format: OutputFormat) -> PngResult<()> {
+    format.check("validate", &[OutputFormat::Json, OutputFormat::Sarif])?;
+    let handlers = load_handlers(&args.handlers)?;
+    let lints = load_lint_config(&args.lint)?;
+    if args.list_rules {
+        return list_rules(&lints);
+    }
+    if format == OutputFormat::Sarif {
+        return validate_sarif(&args, &handlers, &lints);
+    }
+    for_each_png(args.file.as_deref(), &args.batch, format, |path, out| {
+        validate_file(path, &args.parse, &handlers, &lints, format, out)
+    })
+}
+
+/// Print each rule's ID, the severity it's reported at under the lint levels, and what it
+/// checks
+fn list_rules(lints: &LintConfig) -> PngResult<()> {
+    let mut out = io::stdout().lock();
+    for rule in Rule::ALL {
+        let severity = lints
+            .severity(*rule)
+            .map_or("allow".to_string(), |severity| severity.to_string());
+        writeln!(out, "{:<26} {:<8} {}", rule, severity, rule.description())?;
+    }
+    Ok(())
+}
+
+/// Validate every file, writing all of the violations as a single SARIF log
+fn validate_sarif(
+    args: &ValidateArgs,
+    handlers: &ChunkHandlers,
+    lints: &LintConfig,
+) -> PngResult<()> {
+    let (pngs, _) = batch_files(args.file.as_deref(), &args.batch)?;
+    let mut sarif = SarifLog::new(Rule::ALL.iter().copied().map(ReportingRule::from).collect());
+    let mut failures = vec![];
+    let work = |path: &Path| {
+        validate_input(path, &args.parse, handlers, lints)
+            .map_err(|e| (error::kind_of(e.as_ref()), e.to_string()))
+    };
+    run_jobs(&pngs, args.batch.jobs, work, |path, violations| {
+        let violations = match violations {
+            Ok(violations) => violations,
+            Err((kind, e)) => {
+                error!("{}: {}", path.display(), e);
+                sarif.add_failure(path, &e);
+                failures.push((path.to_path_buf(), kind));
+                return;
+            }
+        };
+        if violations
+            .iter()
+            .any(|violation| violation.severity == Severity::Error)
+        {
+            failures.push((path.to_path_buf(), ErrorKind::Validation));
+        }
+        for violation in violations {
+            // Every rule is one of the log's rules
+            let _ = sarif.add_result(
+                violation.rule.id(),
+                violation.severity.into(),
+                &violation.message,
+                path,
+                Some(violation.offset..violation.offset),
+            );
+        }
+    });
+    write_json(&mut io::stdout().lock(), &sarif.to_json())?;
+    batch_result(&failures, pngs.len())
+}
+
+/// How many of the violations are errors, warnings and information
+fn count_violations(violations: &[Violation]) -> (usize, usize, usize) {
+    let count = |severity| {
+        violations
+            .iter()
+            .filter(|violation| violation.severity == severity)
+            .count()
+    };
+    (
+        count(Severity::Error),
+        count(Severity::Warning),
+        count(Severity::Info),
+    )
+}
+
+/// The report `validate --format json` writes for a file
+pub fn validation_json(path: Option<&Path>, violations: &[Violation]) -> Value {
+    let (errors, warnings, infos) = count_violations(violations);
+    let entries: Vec<Value> = violations
+        .iter()
+        .map(|violation| {
+            json!({
+                "code": violation.rule.id(),
+                "severity": violation.severity.to_string(),
+                "offset": violation.offset,
+                "message": violation.message,
+            })
+        })
+        .collect();
+    let mut report = json!({});
+    if let Some(path) = path {
+        report["file"] = json!(path);
+    }
+    report["valid"] = json!(errors == 0);
+    report["errors"] = json!(errors);
+    report["warnings"] = json!(warnings);
+    report["infos"] = json!(infos);
+    report["violations"] = json!(entries);
+    report
+}
+
+/// Read the file, mapping it with --mmap, and report the violations in the PNG at --offset or
+/// picked by --image, or else the whole file, at the lint levels
+fn validate_input(
+    path: &Path,
+    parse: &ParseArgs,
+    handlers: &ChunkHandlers,
+    lints: &LintConfig,
+) -> PngResult<Vec<Violation>> {
+    let bytes = read_input(path, parse)?;
+    let options = parse.options();
+    let Some(offset) = image_offset(&bytes, parse)? else {
+        return Ok(lints.apply(validate::validate_within(&bytes, handlers, &options)?));
+    };
+    let png = Png::bytes_at(&bytes, offset)?;
+    let mut violations = lints.apply(validate::validate_within(png, handlers, &options)?);
+    // Offsets are reported within the whole file
+    for violation in &mut violations {
+        violation.offset += offset;
+    }
+    Ok(violations)
+}
+
+/// Validate a single file
+fn validate_file(
+    path: &Path,
+    parse: &ParseArgs,
+    handlers: &ChunkHandlers,
+    lints: &LintConfig,
+    format: OutputFormat,
+    out: &mut dyn Write,
+) -> PngResult<()> {
+    let violations = validate_input(path, parse, handlers, lints)?;
+    let (errors, warnings, infos) = count_violations(&violations);
+    match format {
+        OutputFormat::Json => write_json(out, &validation_json(Some(path), &violations))?,
+        _ => {
+            for violation in &violations {
+                writeln!(out, "{}", violation)?;
+            }
+            write!(
+                out,
+                "{}: {} errors, {} warnings",
+                path.display(),
+                errors,
+                warnings
+            )?;
+            if infos > 0 {
+                write!(out, ", {} infos", infos)?;
+            }
+            writeln!(out)?;
+        }
+    }
+    if errors > 0 {
+        return Err(error::error(
+            ErrorKind::Validation,
+            format!("{} is not a valid PNG", path.display()),
+        ));
+    }
+    Ok(())
+}
+
+/// Fix the CRC of every chunk whose stored checksum doesn't match and, if asked to, salvage a
+/// truncated file, writing nothing back on a dry run
+pub fn repair(args: RepairArgs) -> PngResult<()> {
+    edit_each_png(
+        args.file.as_deref(),
+        &args.batch,
+        "repair",
+        &args.save,
+        |path, out| repair_file(path, &args, out),
+    )
+}
+
+/// Repair a single file
+fn repair_file(path: &Path, args: &RepairArgs, out: &mut dyn Write) -> PngResult<()> {
+    // Repairs are made to an in-memory copy, which a dry run simply never writes back
+    let mut bytes = progress::read(path)?;
+    let truncation = if args.truncated {
+        repair::salvage_truncated(&mut bytes)?
+    } else {
+        None
+    };
+    match &truncation {
+        Some(truncation) => writeln!(out, "{}", truncation)?,
+        None if args.truncated => writeln!(out, "File is not truncated")?,
+        None => {}
+    }
+
+    let fixes = repair::repair_crcs(&mut bytes)?;
+    for fix in &fixes {
+        writeln!(out, "{}", fix)?;
+    }
+    match (fixes.len(), args.save.dry_run) {
+        (0, _) => writeln!(out, "No bad CRCs found")?,
+        (count, true) => writeln!(out, "Would fix {} CRCs (dry run)", count)?,
+        (count, false) => writeln!(out, "Fixed {} CRCs", count)?,
+    }
+    if truncation.is_some() || !fixes.is_empty() {
+        save(path, &bytes, &args.save, out)?;
+    }
+    Ok(())
+}
+
+/// Strip metadata chunks and trailing data from the PNG, listing everything removed
+pub fn strip(args: StripArgs) -> PngResult<()> {
+    edit_each_png(
+        args.file.as_deref(),
+        &args.batch,
+        "strip",
+        &args.write.save,
+        |path, out| strip_file(path, &args, out),
+    )
+}
+
+/// Strip a single file
+fn strip_file(path: &Path, args: &StripArgs, out: &mut dyn Write) -> PngResult<()> {
+    let mut png = read_png(path, &args.parse)?;
+    let options = StripOptions {
+        keep: args.keep.clone(),
+        remove: args.remove.clone(),
+        keep_trailing_data: args.keep_trailing_data,
+        gps_only: args.gps_only,
+    };
+    let report = strip::strip(&mut png, &options)?;
+    if args.write.save.dry_run && !report.is_empty() {
+        // Saving lists what would be removed
+        let output = args.output.as_deref().unwrap_or(path);
+        return write_png(output, &mut png, &args.write, out);
+    }
+    for chunk in &report.removed {
+        writeln!(
+            out,
+            "Removed {} chunk ({} bytes)",
+            chunk.chunk_type(),
+            chunk.length()
+        )?;
+    }
+    if report.trailing_data_len > 0 {
+        writeln!(
+            out,
+            "Removed {} bytes of data after IEND",
+            report.trailing_data_len
+        )?;
+    }
+    if report.gps_removed {
+        writeln!(out, "Removed GPS tags from EXIF data")?;
+    }
+    if report.is_empty() {
+        writeln!(out, "Nothing to strip")?;
+        if args.output.is_none() {
+            return Ok(());
+        }
+    }
+    let output = args.output.as_deref().unwrap_or(path);
+    write_png(output, &mut png, &args.write, out)
+}
+
+/// Anonymize each PNG in place, reporting what identifying data was removed from each file
+pub fn anonymize(args: AnonymizeArgs) -> PngResult<()> {
+    let paths = confirm_edits("anonymize", args.files.clone(), &args.save)?;
+    for path in &paths {
+        let mut png = read_png(path, &args.parse)?;
+        let options = AnonymizeOptions {
+            keep_exif: args.keep_exif,
+        };
+        let changes = anonymize::anonymize(&mut png, options)?;
+        if changes.is_empty() {
+            println!("{}: nothing to remove", path.display());
+            continue;
+        }
+        println!("{}:", path.display());
+        for change in &changes {
+            println!("  {}", change);
+        }
+        save(path, &png.as_bytes(), &args.save, &mut io::stdout())?;
+    }
+    Ok(())
+}
+
+/// Browse the chunks of a file interactively
+#[cfg(feature = "tui")]
+pub fn tui(args: TuiArgs) -> PngResult<()> {
+    let png = read_png(&args.file, &args.parse)?;
+    let options = output::WriteOptions {
+        backup_suffix: args.backup.as_deref(),
+        preserve: args.preserve,
+    };
+    crate::tui::run(&args.file, png, options)
+}
+
+/// Edit a file with commands typed at a prompt, parsing it only once
+#[cfg(feature = "shell")]
+pub fn shell(args: ShellArgs) -> PngResult<()> {
+    let png = read_png(&args.file, &args.parse)?;
+    let options = output::WriteOptions {
+        backup_suffix: args.backup.as_deref(),
+        preserve: args.preserve,
+    };
+    crate::shell::run(&args.file, png, options)
+}
+
+/// Print the completion script for a shell, generated from the argument definitions
+pub fn completions(args: CompletionsArgs) -> PngResult<()> {
+    let mut command = Cli::command();
+    let name = command.get_name().to_string();
+    // Generated in memory first, as writing to a closed pipe would panic
+    let mut script = vec![];
+    clap_complete::generate(args.shell, &mut command, name, &mut script);
+    io::stdout().write_all(&script)?;
+    Ok(())
+}
+
+/// Print the man page for pngmi, or write one for every command into a directory
+pub fn man(args: ManArgs) -> PngResult<()> {
+    match args.out_dir {
+        Some(dir) => {
+            fs::create_dir_all(&dir)?;
+            clap_mangen::generate_to(Cli::command(), &dir)?;
+        }
+        None => clap_mangen::Man::new(Cli::command()).render(&mut io::stdout())?,
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::args::Command;
+    use clap::Parser;
+
+    fn png_bytes() -> Vec<u8> {
+        let chunk = |chunk_type: &str, data: &[u8]| {
+            Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec())
+        };
+        let mut png = Png::from_chunks(vec![chunk("IHDR", &[0; 13]), chunk("IEND", &[])]);
+        png.set_trailing_data(b"tail".to_vec());
+        png.as_bytes()
+    }
+
+    #[test]
+    fn test_chunk_rows() {
+        let bytes = png_bytes();
+        let png = PngRef::try_from(&bytes[..]).unwrap();
+        let rows = chunk_rows(Path::new("a,b.png"), &bytes, &png, &[]);
+        let mut out = vec![];
+        write_row(&mut out, ',', CHUNK_COLUMNS).unwrap();
+        for row in &rows {
+            write_row(&mut out, ',', row).unwrap();
+        }
+        let ihdr_crc = png.chunks()[0].crc();
+        let expected = format!(
+            "path,index,type,offset,length,crc,flags\n\
+             \"a,b.png\",0,IHDR,8,13,{},critical\n\
+             \"a,b.png\",1,IEND,33,0,2923585666,critical\n\
+             \"a,b.png\",,,45,4,,trailing-data\n",
+            ihdr_crc
+        );
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_chunk_json_matches_rows() {
+        let bytes = png_bytes();
+        let png = PngRef::try_from(&bytes[..]).unwrap();
+        let rows = chunk_rows(Path::new("x.png"), &bytes, &png, &[]);
+        for (index, chunk) in png.chunks().iter().enumerate() {
+            let json = chunk_json(index, chunk);
+            assert_eq!(json["type"], rows[index][2]);
+            assert_eq!(json["offset"].to_string(), rows[index][3]);
+            assert_eq!(json["length"].to_string(), rows[index][4]);
+            assert_eq!(json["crc"].to_string(), rows[index][5]);
+        }
+        assert_eq!(chunk_json(1, &png.chunks()[1])["crc"], 0xae426082u32);
+    }
+
+    #[test]
+    fn test_validate_at_offset() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("blob.bin");
+        fs::write(&path, [&b"HEAD"[..], &png_bytes()].concat()).unwrap();
+        let parse = |args: &[&str]| {
+            let cli = Cli::try_parse_from([&["pngmi", "validate", "blob.bin"], args].concat());
+            match cli.unwrap().command {
+                Command::Validate(args) => args.parse,
+                command => panic!("Parsed as {:?}", command),
+            }
+        };
+        let (handlers, lints) = (ChunkHandlers::default(), LintConfig::default());
+
+        let violations = validate_input(&path, &parse(&[]), &handlers, &lints).unwrap();
+        assert_eq!(violations[0].rule, Rule::InvalidSignature);
+
+        let violations = validate_input(&path, &parse(&["--offset", "4"]), &handlers, &lints);
+        let violations = violations.unwrap();
+        // The zero width is reported where it sits in the file, and the trailing bytes are
+        // left to the container
+        assert_eq!(violations[0].offset, 4 + 8 + 8);
+        assert!(violations
+            .iter()
+            .all(|violation| violation.rule != Rule::TrailingData));
+
+        assert!(validate_input(&path, &parse(&["--offset", "5"]), &handlers, &lints).is_err());
+    }
+}
@@ -0,0 +1,205 @@
+use crate::envelope::{Envelope, Flags};
+#[cfg(feature = "zstd")]
+use crate::error::{error, ErrorKind};
+use crate::zlib;
+use crate::PngResult;
+use flate2::write::ZlibEncoder;
+use std::fmt::{Display, Formatter};
+#[cfg(feature = "zstd")]
+use std::io::Read;
+use std::io::Write;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
+/// The compression algorithms which can be applied to a payload before it is embedded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    Zlib,
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+impl Compression {
+    /// All the algorithms supported by this build
+    pub const ALL: &'static [Compression] = &[
+        Compression::Zlib,
+        #[cfg(feature = "zstd")]
+        Compression::Zstd,
+    ];
+
+    /// The envelope flag recording that this algorithm was applied
+    pub fn flag(&self) -> Flags {
+        match self {
+            Compression::Zlib => Flags::ZLIB,
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => Flags::ZSTD,
+        }
+    }
+
+    /// The range of compression levels accepted by this algorithm
+    pub fn levels(&self) -> RangeInclusive<i32> {
+        match self {
+            Compression::Zlib => 0..=9,
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => zstd::compression_level_range(),
+        }
+    }
+
+    /// Compress the given data at the given level, or the strongest sensible level if none
+    pub fn compress(&self, data: &[u8], level: Option<i32>) -> PngResult<Vec<u8>> {
+        if let Some(level) = level.filter(|level| !self.levels().contains(level)) {
+            return Err(format!(
+                "Compression level {} is out of range for {} ({:?})",
+                level,
+                self,
+                self.levels()
+            )
+            .into());
+        }
+        match self {
+            Compression::Zlib => {
+                let level = level.map_or(flate2::Compression::best(), |level| {
+                    flate2::Compression::new(level as u32)
+                });
+                let mut encoder = ZlibEncoder::new(vec![], level);
+                encoder.write_all(data)?;
+                Ok(encoder.finish()?)
+            }
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => Ok(zstd::encode_all(data, level.unwrap_or(19))?),
+        }
+    }
+
+    /// Decompress data previously produced by `compress`, refusing to decompress more than
+    /// `zlib::MAX_INFLATED_LEN` bytes with either algorithm
+    pub fn decompress(&self, data: &[u8]) -> PngResult<Vec<u8>> {
+        match self {
+            Compression::Zlib => zlib::inflate(data, zlib::MAX_INFLATED_LEN),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => {
+                let limit = zlib::MAX_INFLATED_LEN;
+                let mut decompressed = vec![];
+                zstd::stream::Decoder::new(data)?
+                    .take(limit + 1)
+                    .read_to_end(&mut decompressed)?;
+                if decompressed.len() as u64 > limit {
+                    return Err(error(
+                        ErrorKind::Validation,
+                        format!("zstd stream decompresses to more than {} bytes", limit),
+                    ));
+                }
+                Ok(decompressed)
+            }
+        }
+    }
+}
+
+impl Display for Compression {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Compression::Zlib => write!(f, "zlib"),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => write!(f, "zstd"),
+        }
+    }
+}
+
+impl FromStr for Compression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Compression::ALL
+            .iter()
+            .find(|compression| compression.to_string() == s)
+            .copied()
+            .ok_or_else(|| format!("Unsupported compression algorithm '{}'", s))
+    }
+}
+
+/// Compress the body of the envelope and record the algorithm in its flags
+pub fn compress(
+    envelope: &mut Envelope,
+    compression: Compression,
+    level: Option<i32>,
+) -> PngResult<()> {
+    envelope.set_body(compression.compress(envelope.body(), level)?);
+    envelope.flags_mut().set(compression.flag(), true);
+    Ok(())
+}
+
+/// Undo `compress` if the envelope flags say its body was compressed
+pub fn decompress(envelope: &mut Envelope) -> PngResult<()> {
+    let flags = envelope.flags();
+    #[cfg(not(feature = "zstd"))]
+    if flags.contains(Flags::ZSTD) {
+        return Err(
+            "Payload is zstd compressed; rebuild with the `zstd` feature to decode it".into(),
+        );
+    }
+    if let Some(compression) = Compression::ALL
+        .iter()
+        .find(|compression| flags.contains(compression.flag()))
+    {
+        envelope.set_body(compression.decompress(envelope.body())?);
+        envelope.flags_mut().set(compression.flag(), false);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_zlib_round_trip() {
+        let message = "all work and no play makes jack a dull boy ".repeat(100);
+        let mut envelope = Envelope::text(&message);
+        compress(&mut envelope, Compression::Zlib, None).unwrap();
+        assert!(envelope.flags().contains(Flags::ZLIB));
+        assert!(envelope.body().len() < message.len());
+
+        decompress(&mut envelope).unwrap();
+        assert_eq!(envelope.flags(), Flags::NONE);
+        assert_eq!(envelope.body(), message.as_bytes());
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_zstd_round_trip() {
+        let message = "all work and no play makes jack a dull boy ".repeat(100);
+        let mut envelope = Envelope::text(&message);
+        compress(&mut envelope, Compression::Zstd, Some(3)).unwrap();
+        assert!(envelope.flags().contains(Flags::ZSTD));
+
+        decompress(&mut envelope).unwrap();
+        assert_eq!(envelope.body(), message.as_bytes());
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_zstd_bomb() {
+        let bomb = vec![0; zlib::MAX_INFLATED_LEN as usize + 1];
+        let compressed = Compression::Zstd.compress(&bomb, Some(1)).unwrap();
+        drop(bomb);
+        let e = Compression::Zstd.decompress(&compressed).unwrap_err();
+        assert_eq!(crate::error::kind_of(&*e), ErrorKind::Validation);
+    }
+
+    #[test]
+    fn test_invalid_level() {
+        assert!(Compression::Zlib.compress(b"data", Some(10)).is_err());
+    }
+
+    #[test]
+    fn test_decompress_uncompressed_envelope() {
+        let mut envelope = Envelope::text("hello");
+        decompress(&mut envelope).unwrap();
+        assert_eq!(envelope.body(), b"hello");
+    }
+
+    #[test]
+    fn test_compression_from_str() {
+        assert_eq!(Compression::from_str("zlib"), Ok(Compression::Zlib));
+        assert!(Compression::from_str("lzma").is_err());
+    }
+}
@@ -0,0 +1,246 @@
+use crate::chunk_type::ChunkType;
+use crate::lint::LintConfig;
+use crate::recipients::Recipient;
+use crate::PngResult;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// The name of the project config file, looked for in the current directory and its parents
+pub const PROJECT_FILE_NAME: &str = "pngmi.toml";
+
+/// Defaults for encryption, for teams which always encode to the same keys
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EncryptionConfig {
+    /// Recipients encode encrypts to when given neither --recipient nor --passphrase
+    pub recipients: Vec<Recipient>,
+    /// Identity files decode tries when given no --identity
+    pub identities: Vec<PathBuf>,
+    pub kdf_memory: Option<u32>,
+    pub kdf_iterations: Option<u32>,
+    pub kdf_parallelism: Option<u32>,
+}
+
+/// Defaults read from config files, which the command line overrides
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Config {
+    /// The chunk type encode and decode use when given none
+    pub chunk_type: Option<ChunkType>,
+    /// The name of the output format to use when --format isn't given
+    pub format: Option<String>,
+    pub encryption: EncryptionConfig,
+    pub lints: LintConfig,
+    /// Glob patterns of files and directories to leave out when walking directories
+    pub ignore: Vec<String>,
+}
+
+impl Config {
+    /// Parse a config file. Relative paths in it are taken from `dir`, the directory holding
+    /// the file.
+    pub fn from_toml(text: &str, dir: &Path) -> PngResult<Self> {
+        let document: toml::Table = text.parse()?;
+        let mut config = Config::default();
+        for (key, value) in &document {
+            match key.as_str() {
+                "chunk-type" => config.chunk_type = Some(ChunkType::from_str(string(key, value)?)?),
+                "format" => config.format = Some(string(key, value)?.to_string()),
+                "ignore" => config.ignore = strings(key, value)?,
+                "encryption" => config.encryption = encryption(table(key, value)?, dir)?,
+                "lints" => config.lints = LintConfig::from_table(table(key, value)?)?,
+                _ => return Err(format!("Unknown config key '{}'", key).into()),
+            }
+        }
+        Ok(config)
+    }
+
+    /// Read a config file, naming it in any error
+    pub fn read(path: &Path) -> PngResult<Self> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let dir = path.parent().unwrap_or(Path::new(""));
+        Config::from_toml(&text, dir).map_err(|e| format!("{}: {}", path.display(), e).into())
+    }
+
+    /// Layer `other` on top of this config: its settings win, its ignore patterns are added
+    /// to these and its lint levels override these rule by rule
+    pub fn merge(mut self, other: Config) -> Self {
+        let encryption = other.encryption;
+        self.chunk_type = other.chunk_type.or(self.chunk_type);
+        self.format = other.format.or(self.format);
+        if !encryption.recipients.is_empty() {
+            self.encryption.recipients = encryption.recipients;
+        }
+        if !encryption.identities.is_empty() {
+            self.encryption.identities = encryption.identities;
+        }
+        self.encryption.kdf_memory = encryption.kdf_memory.or(self.encryption.kdf_memory);
+        self.encryption.kdf_iterations =
+            encryption.kdf_iterations.or(self.encryption.kdf_iterations);
+        self.encryption.kdf_parallelism = encryption
+            .kdf_parallelism
+            .or(self.encryption.kdf_parallelism);
+        self.lints.extend(&other.lints);
+        self.ignore.extend(other.ignore);
+        self
+    }
+
+    /// The nearest `pngmi.toml` in `start` or one of its parents
+    pub fn find_project_file(start: &Path) -> Option<PathBuf> {
+        start
+            .ancestors()
+            .map(|dir| dir.join(PROJECT_FILE_NAME))
+            .find(|path| path.is_file())
+    }
+
+    /// Where the user's own config lives: `pngmi/config.toml` under `$XDG_CONFIG_HOME`, or
+    /// under `~/.config` if that isn't set
+    pub fn user_file() -> Option<PathBuf> {
+        let base = match env::var_os("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty()) {
+            Some(dir) => PathBuf::from(dir),
+            None => PathBuf::from(env::var_os("HOME")?).join(".config"),
+        };
+        Some(base.join("pngmi").join("config.toml"))
+    }
+
+    /// Load the user's config with the project config found from `cwd` layered on top,
+    /// either of which may be missing
+    pub fn load(cwd: &Path) -> PngResult<Self> {
+        let files = [Config::user_file(), Config::find_project_file(cwd)];
+        let mut config = Config::default();
+        for path in files.into_iter().flatten().filter(|path| path.is_file()) {
+            config = config.merge(Config::read(&path)?);
+        }
+        Ok(config)
+    }
+}
+
+fn string<'a>(key: &str, value: &'a toml::Value) -> PngResult<&'a str> {
+    value
+        .as_str()
+        .ok_or_else(|| format!("'{}' must be a string", key).into())
+}
+
+fn strings(key: &str, value: &toml::Value) -> PngResult<Vec<String>> {
+    let array = value
+        .as_array()
+        .ok_or_else(|| format!("'{}' must be an array of strings", key))?;
+    array
+        .iter()
+        .map(|item| string(key, item).map(str::to_string))
+        .collect()
+}
+
+fn table<'a>(key: &str, value: &'a toml::Value) -> PngResult<&'a toml::Table> {
+    value
+        .as_table()
+        .ok_or_else(|| format!("'{}' must be a table", key).into())
+}
+
+fn number(key: &str, value: &toml::Value) -> PngResult<u32> {
+    value
+        .as_integer()
+        .and_then(|value| u32::try_from(value).ok())
+        .ok_or_else(|| format!("'{}' must be a whole number", key).into())
+}
+
+/// Parse the `[encryption]` table
+fn encryption(table: &toml::Table, dir: &Path) -> PngResult<EncryptionConfig> {
+    let mut encryption = EncryptionConfig::default();
+    for (key, value) in table {
+        match key.as_str() {
+            "recipients" => {
+                encryption.recipients = strings(key, value)?
+                    .iter()
+                    .map(|recipient| Recipient::from_str(recipient))
+                    .collect::<Result<_, _>>()?
+            }
+            "identities" => {
+                encryption.identities = strings(key, value)?
+                    .iter()
+                    .map(|path| dir.join(path))
+                    .collect()
+            }
+            "kdf-memory" => encryption.kdf_memory = Some(number(key, value)?),
+            "kdf-iterations" => encryption.kdf_iterations = Some(number(key, value)?),
+            "kdf-parallelism" => encryption.kdf_parallelism = Some(number(key, value)?),
+            _ => return Err(format!("Unknown config key 'encryption.{}'", key).into()),
+        }
+    }
+    Ok(encryption)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::validate::{Rule, Severity};
+
+    const PROJECT: &str = r#"
+        chunk-type = "ruSt"
+        format = "json"
+        ignore = ["vendor/**"]
+
+        [encryption]
+        identities = ["keys/me.txt"]
+        kdf-iterations = 4
+
+        [lints]
+        trailing-data = "allow"
+    "#;
+
+    #[test]
+    fn test_from_toml() {
+        let config = Config::from_toml(PROJECT, Path::new("/project")).unwrap();
+        assert_eq!(
+            config.chunk_type,
+            Some(ChunkType::from_str("ruSt").unwrap())
+        );
+        assert_eq!(config.format.as_deref(), Some("json"));
+        assert_eq!(config.ignore, vec!["vendor/**"]);
+        assert_eq!(
+            config.encryption.identities,
+            vec![PathBuf::from("/project/keys/me.txt")]
+        );
+        assert_eq!(config.encryption.kdf_iterations, Some(4));
+        assert_eq!(config.lints.severity(Rule::TrailingData), None);
+
+        let dir = Path::new("");
+        assert!(Config::from_toml("colour = \"red\"", dir).is_err());
+        assert!(Config::from_toml("chunk-type = \"ru\"", dir).is_err());
+        assert!(Config::from_toml("ignore = \"vendor\"", dir).is_err());
+        assert!(Config::from_toml("[encryption]\nkdf-memory = -1", dir).is_err());
+        assert!(Config::from_toml("[encryption]\nrecipients = [\"age1\"]", dir).is_err());
+    }
+
+    #[test]
+    fn test_merge() {
+        let user = Config::from_toml(
+            "format = \"text\"\nignore = [\"*.tmp.png\"]\n[lints]\nwarnings = \"deny\"\n\
+             trailing-data = \"warn\"\n[encryption]\nkdf-memory = 65536",
+            Path::new(""),
+        )
+        .unwrap();
+        let project = Config::from_toml(PROJECT, Path::new("")).unwrap();
+        let config = user.merge(project);
+        assert_eq!(config.format.as_deref(), Some("json"));
+        assert_eq!(config.ignore, vec!["*.tmp.png", "vendor/**"]);
+        assert_eq!(config.encryption.kdf_memory, Some(65536));
+        assert_eq!(config.encryption.kdf_iterations, Some(4));
+        assert_eq!(config.lints.severity(Rule::TrailingData), None);
+        assert_eq!(
+            config.lints.severity(Rule::EmbeddedFile),
+            Some(Severity::Error)
+        );
+    }
+
+    #[test]
+    fn test_find_project_file() {
+        let root = tempfile::tempdir().unwrap();
+        let nested = root.path().join("a").join("b");
+        fs::create_dir_all(&nested).unwrap();
+        assert_eq!(Config::find_project_file(&nested), None);
+        let path = root.path().join("a").join(PROJECT_FILE_NAME);
+        fs::write(&path, PROJECT).unwrap();
+        assert_eq!(Config::find_project_file(&nested), Some(path.clone()));
+        assert_eq!(Config::read(&path).unwrap().format.as_deref(), Some("json"));
+    }
+}
@@ -0,0 +1,340 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+use crate::PngResult;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// Standard chunks which aren't safe to copy by the naming rules, but which describe the image
+/// in ways that don't depend on its pixel layout, so carry over to a re-encoded image
+const LAYOUT_INDEPENDENT_TYPES: &[ChunkType] = &[
+    ChunkType::CHRM,
+    ChunkType::CICP,
+    ChunkType::GAMA,
+    ChunkType::ICCP,
+    ChunkType::MDCV,
+    ChunkType::CLLI,
+    ChunkType::SRGB,
+    ChunkType::PHYS,
+    ChunkType::SPLT,
+    ChunkType::TIME,
+];
+
+/// Standard ancillary chunks which may appear more than once
+const REPEATABLE_TYPES: &[ChunkType] = &[
+    ChunkType::TEXT,
+    ChunkType::ZTXT,
+    ChunkType::ITXT,
+    ChunkType::SPLT,
+];
+
+const UNSAFE_TO_COPY: &str = "not safe to copy between images with a different header or palette";
+
+/// Returns whether the two images have the same header and palette, so that any chunk
+/// describing one describes the other too
+fn same_layout(a: &Png, b: &Png) -> bool {
+    [ChunkType::IHDR, ChunkType::PLTE].iter().all(|chunk_type| {
+        let data = |png: &Png| {
+            png.chunks()
+                .iter()
+                .find(|chunk| chunk.chunk_type() == chunk_type)
+                .map(|chunk| chunk.data().to_vec())
+        };
+        data(a) == data(b)
+    })
+}
+
+/// Returns whether chunks of the type can be carried over to another image
+fn can_copy(chunk_type: &ChunkType, same_layout: bool) -> bool {
+    chunk_type.is_safe_to_copy() || same_layout || LAYOUT_INDEPENDENT_TYPES.contains(chunk_type)
+}
+
+/// Which chunks `copy_metadata` copies
+#[derive(Debug, Clone, Default)]
+pub struct CopyOptions {
+    /// The chunk types to copy, or every ancillary type if empty
+    pub types: Vec<String>,
+    /// Copy chunks which aren't safe to copy even when the images' layouts differ
+    pub allow_unsafe: bool,
+}
+
+/// A chunk type `copy_metadata` left behind, and why
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Skipped {
+    pub chunk_type: ChunkType,
+    pub reason: &'static str,
+}
+
+impl Display for Skipped {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Skipped {} chunks: {}", self.chunk_type, self.reason)
+    }
+}
+
+/// What `copy_metadata` did
+#[derive(Debug, Default)]
+pub struct CopyReport {
+    /// The copied chunks, grouped by type
+    pub copied: Vec<Chunk>,
+    /// The types which were selected but not copied
+    pub skipped: Vec<Skipped>,
+}
+
+/// Copy the selected ancillary chunks of `from` into `to`, replacing any chunks of the same
+/// types already there and placing each where the spec allows.
+///
+/// Chunks whose type marks them unsafe to copy may depend on the image data, so unless the
+/// two images have the same header and palette, only the standard ones known not to are
+/// copied, e.g. iCCP but not tRNS.
+pub fn copy_metadata(from: &Png, to: &mut Png, options: &CopyOptions) -> PngResult<CopyReport> {
+    let mut types = vec![];
+    for chunk_type in &options.types {
+        let chunk_type = ChunkType::from_str(chunk_type)?;
+        if chunk_type.is_critical() {
+            return Err(format!("Refusing to copy critical {} chunks", chunk_type).into());
+        }
+        types.push(chunk_type);
+    }
+    if types.is_empty() {
+        for chunk in from.chunks() {
+            if !chunk.chunk_type().is_critical() && !types.contains(chunk.chunk_type()) {
+                types.push(*chunk.chunk_type());
+            }
+        }
+    }
+
+    let same_layout = same_layout(from, to);
+    let mut report = CopyReport::default();
+    for chunk_type in types {
+        let chunks: Vec<&Chunk> = from
+            .chunks()
+            .iter()
+            .filter(|chunk| *chunk.chunk_type() == chunk_type)
+            .collect();
+        let reason = if chunks.is_empty() {
+            Some("none in the source file")
+        } else if options.allow_unsafe || can_copy(&chunk_type, same_layout) {
+            None
+        } else {
+            Some(UNSAFE_TO_COPY)
+        };
+        if let Some(reason) = reason {
+            report.skipped.push(Skipped { chunk_type, reason });
+            continue;
+        }
+        to.retain_chunks(|chunk| *chunk.chunk_type() != chunk_type);
+        for chunk in chunks {
+            to.place_chunk(chunk.clone());
+            report.copied.push(chunk.clone());
+        }
+    }
+    Ok(report)
+}
+
+/// What `Png::merge_ancillary` does when both images have a chunk of a type which may only
+/// appear once
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergePolicy {
+    /// Keep the chunk already in the image being merged into
+    Ours,
+    /// Take the chunk from the other image
+    Theirs,
+    /// Fail, leaving the image as it was
+    Error,
+}
+
+impl Display for MergePolicy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MergePolicy::Ours => write!(f, "ours"),
+            MergePolicy::Theirs => write!(f, "theirs"),
+            MergePolicy::Error => write!(f, "error"),
+        }
+    }
+}
+
+impl FromStr for MergePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ours" => Ok(MergePolicy::Ours),
+            "theirs" => Ok(MergePolicy::Theirs),
+            "error" => Ok(MergePolicy::Error),
+            _ => Err(format!(
+                "Unknown merge policy {:?}; expected ours, theirs or error",
+                s
+            )),
+        }
+    }
+}
+
+/// What `Png::merge_ancillary` did
+#[derive(Debug, Default)]
+pub struct MergeReport {
+    /// The chunks taken from the other image, grouped by type
+    pub added: Vec<Chunk>,
+    /// The types both images had, for which this image's chunk was kept
+    pub kept: Vec<ChunkType>,
+    /// The types which couldn't be carried over
+    pub skipped: Vec<Skipped>,
+}
+
+impl Png {
+    /// Carry the ancillary chunks of `other` over to this image, e.g. to restore the metadata
+    /// of an original after regenerating its image data. Chunks of types which may appear more
+    /// than once are added unless this image already has an identical one, while for other
+    /// types the policy settles which image's chunk wins. Chunks which aren't safe to copy
+    /// are only carried over as `copy_metadata` would.
+    pub fn merge_ancillary(&mut self, other: &Png, policy: MergePolicy) -> PngResult<MergeReport> {
+        let same_layout = same_layout(self, other);
+        let mut types: Vec<ChunkType> = vec![];
+        for chunk in other.chunks() {
+            if !chunk.chunk_type().is_critical() && !types.contains(chunk.chunk_type()) {
+                types.push(*chunk.chunk_type());
+            }
+        }
+
+        let mut report = MergeReport::default();
+        let mut merged = self.borrowed().to_png();
+        for chunk_type in types {
+            if !can_copy(&chunk_type, same_layout) {
+                report.skipped.push(Skipped {
+                    chunk_type,
+                    reason: UNSAFE_TO_COPY,
+                });
+                continue;
+            }
+            let theirs = other
+                .chunks()
+                .iter()
+                .filter(|chunk| *chunk.chunk_type() == chunk_type);
+            let repeatable = REPEATABLE_TYPES.contains(&chunk_type) || !chunk_type.is_standard();
+            if repeatable {
+                for chunk in theirs {
+                    if !merged.chunks().contains(chunk) {
+                        merged.place_chunk(chunk.clone());
+                        report.added.push(chunk.clone());
+                    }
+                }
+                continue;
+            }
+            let ours = merged
+                .chunks()
+                .iter()
+                .find(|chunk| *chunk.chunk_type() == chunk_type);
+            match (ours, policy) {
+                (Some(ours), _) if theirs.clone().all(|chunk| chunk == ours) => {}
+                (Some(_), MergePolicy::Ours) => report.kept.push(chunk_type),
+                (Some(_), MergePolicy::Error) => {
+                    return Err(format!("Both images have a different {} chunk", chunk_type).into())
+                }
+                (Some(_), MergePolicy::Theirs) | (None, _) => {
+                    merged.retain_chunks(|chunk| *chunk.chunk_type() != chunk_type);
+                    for chunk in theirs {
+                        merged.place_chunk(chunk.clone());
+                        report.added.push(chunk.clone());
+                    }
+                }
+            }
+        }
+        *self = merged;
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{png, types};
+
+    #[test]
+    fn test_copy_metadata() {
+        let from = png(&[
+            ("IHDR", "big"),
+            ("iCCP", "profile"),
+            ("tRNS", "alpha"),
+            ("IDAT", "pixels"),
+            ("tEXt", "Author\0me"),
+            ("IEND", ""),
+        ]);
+        let mut to = png(&[
+            ("IHDR", "small"),
+            ("IDAT", "other"),
+            ("tEXt", "old"),
+            ("IEND", ""),
+        ]);
+
+        let report = copy_metadata(&from, &mut to, &CopyOptions::default()).unwrap();
+        assert_eq!(types(&to), ["IHDR", "iCCP", "IDAT", "tEXt", "IEND"]);
+        assert_eq!(to.chunks()[3].data(), b"Author\0me");
+        assert_eq!(report.copied.len(), 2);
+        assert_eq!(report.skipped.len(), 1);
+        assert_eq!(report.skipped[0].chunk_type, ChunkType::TRNS);
+
+        // tRNS is copied once the layouts match, or if asked to regardless
+        let mut same = png(&[("IHDR", "big"), ("IDAT", "other"), ("IEND", "")]);
+        copy_metadata(&from, &mut same, &CopyOptions::default()).unwrap();
+        assert_eq!(
+            types(&same),
+            ["IHDR", "iCCP", "tRNS", "IDAT", "tEXt", "IEND"]
+        );
+        let options = CopyOptions {
+            types: vec!["tRNS".to_string(), "gAMA".to_string()],
+            allow_unsafe: true,
+        };
+        let report = copy_metadata(&from, &mut to, &options).unwrap();
+        assert_eq!(report.copied[0].chunk_type(), &ChunkType::TRNS);
+        assert_eq!(report.skipped[0].reason, "none in the source file");
+
+        let options = CopyOptions {
+            types: vec!["IDAT".to_string()],
+            ..CopyOptions::default()
+        };
+        assert!(copy_metadata(&from, &mut to, &options).is_err());
+    }
+
+    #[test]
+    fn test_merge_ancillary() {
+        let original = png(&[
+            ("IHDR", "h"),
+            ("gAMA", "old"),
+            ("tEXt", "a"),
+            ("tEXt", "b"),
+            ("IDAT", "pixels"),
+            ("IEND", ""),
+        ]);
+        let regenerated = png(&[
+            ("IHDR", "h"),
+            ("gAMA", "new"),
+            ("IDAT", "repacked"),
+            ("tEXt", "b"),
+            ("IEND", ""),
+        ]);
+
+        let mut merged = regenerated.borrowed().to_png();
+        let report = merged
+            .merge_ancillary(&original, MergePolicy::Ours)
+            .unwrap();
+        assert_eq!(
+            types(&merged),
+            ["IHDR", "gAMA", "IDAT", "tEXt", "tEXt", "IEND"]
+        );
+        assert_eq!(merged.chunks()[1].data(), b"new");
+        assert_eq!(report.kept, [ChunkType::GAMA]);
+        assert_eq!(report.added.len(), 1);
+
+        let mut merged = regenerated.borrowed().to_png();
+        merged
+            .merge_ancillary(&original, MergePolicy::Theirs)
+            .unwrap();
+        assert_eq!(merged.chunks()[1].data(), b"old");
+
+        let mut merged = regenerated.borrowed().to_png();
+        assert!(merged
+            .merge_ancillary(&original, MergePolicy::Error)
+            .is_err());
+        assert_eq!(merged.as_bytes(), regenerated.as_bytes());
+        assert!(MergePolicy::from_str("mine").is_err());
+    }
+}
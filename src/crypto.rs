@@ -0,0 +1,264 @@
+use crate::error::{error, ErrorKind};
+use crate::PngResult;
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+
+/// Tunable Argon2id cost parameters used to derive an encryption key from a passphrase
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KdfParams {
+    /// Memory cost in KiB
+    pub memory_kib: u32,
+    /// Number of passes over the memory
+    pub iterations: u32,
+    /// Degree of parallelism (lanes)
+    pub parallelism: u32,
+}
+
+impl KdfParams {
+    /// The OWASP recommended baseline for Argon2id (19 MiB, 2 iterations, 1 lane)
+    pub const DEFAULT: KdfParams = KdfParams {
+        memory_kib: 19 * 1024,
+        iterations: 2,
+        parallelism: 1,
+    };
+
+    /// The most a payload may ask key derivation to cost (1 GiB, 16 passes, 16 lanes)
+    pub const MAX: KdfParams = KdfParams {
+        memory_kib: 1024 * 1024,
+        iterations: 16,
+        parallelism: 16,
+    };
+
+    /// Refuse costs beyond those of `limit`. The parameters of a sealed payload come from the
+    /// file, so without this a crafted one could make key derivation exhaust memory.
+    fn check(&self, limit: KdfParams) -> PngResult<()> {
+        let too_costly = |what: &str, value: u32, max: u32| {
+            error(
+                ErrorKind::Crypto,
+                format!(
+                    "Key derivation {} of {} is over the limit of {}",
+                    what, value, max
+                ),
+            )
+        };
+        if self.memory_kib > limit.memory_kib {
+            return Err(too_costly(
+                "memory (KiB)",
+                self.memory_kib,
+                limit.memory_kib,
+            ));
+        }
+        if self.iterations > limit.iterations {
+            return Err(too_costly("iterations", self.iterations, limit.iterations));
+        }
+        if self.parallelism > limit.parallelism {
+            return Err(too_costly(
+                "parallelism",
+                self.parallelism,
+                limit.parallelism,
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Default for KdfParams {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Marks the start of a passphrase-sealed payload
+pub const SEALED_MAGIC: [u8; 4] = *b"pmiK";
+
+/// The only sealed payload layout understood so far
+const SEALED_VERSION: u8 = 1;
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const KEY_LEN: usize = 32;
+
+/// Magic, version and the three 4-byte KDF parameters
+const PARAMS_LEN: usize = SEALED_MAGIC.len() + 1 + 3 * 4;
+const SEALED_HEADER_LEN: usize = PARAMS_LEN + SALT_LEN + NONCE_LEN;
+
+/// Returns whether the given payload looks like the output of `seal`
+pub fn is_sealed(payload: &[u8]) -> bool {
+    payload.len() > SEALED_HEADER_LEN && payload[..SEALED_MAGIC.len()] == SEALED_MAGIC
+}
+
+/// Derive a 256-bit key from the passphrase and salt using Argon2id
+fn derive_key(passphrase: &str, salt: &[u8], params: KdfParams) -> PngResult<[u8; KEY_LEN]> {
+    params.check(KdfParams::MAX)?;
+    let params = Params::new(
+        params.memory_kib,
+        params.iterations,
+        params.parallelism,
+        Some(KEY_LEN),
+    )
+    .map_err(|e| {
+        error(
+            ErrorKind::Crypto,
+            format!("Invalid key derivation parameters: {}", e),
+        )
+    })?;
+    let mut key = [0u8; KEY_LEN];
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| error(ErrorKind::Crypto, format!("Key derivation failed: {}", e)))?;
+    Ok(key)
+}
+
+/// Encrypt the plaintext with ChaCha20-Poly1305 using a key derived from the passphrase.
+///
+/// The returned payload records the KDF parameters, salt and nonce ahead of the ciphertext so
+/// that `open` can reconstruct the same key from the passphrase alone.
+pub fn seal(plaintext: &[u8], passphrase: &str, params: KdfParams) -> PngResult<Vec<u8>> {
+    let mut salt = [0u8; SALT_LEN];
+    let mut nonce = [0u8; NONCE_LEN];
+    OsRng.fill_bytes(&mut salt);
+    OsRng.fill_bytes(&mut nonce);
+
+    let key = derive_key(passphrase, &salt, params)?;
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(&key));
+    let ciphertext = cipher
+        .encrypt(Nonce::from_slice(&nonce), plaintext)
+        .map_err(|_| error(ErrorKind::Crypto, "Encryption failed"))?;
+
+    let mut sealed = Vec::with_capacity(SEALED_HEADER_LEN + ciphertext.len());
+    sealed.extend_from_slice(&SEALED_MAGIC);
+    sealed.push(SEALED_VERSION);
+    sealed.extend_from_slice(&params.memory_kib.to_be_bytes());
+    sealed.extend_from_slice(&params.iterations.to_be_bytes());
+    sealed.extend_from_slice(&params.parallelism.to_be_bytes());
+    sealed.extend_from_slice(&salt);
+    sealed.extend_from_slice(&nonce);
+    sealed.extend_from_slice(&ciphertext);
+    Ok(sealed)
+}
+
+/// Read back the KDF parameters recorded in a sealed payload
+pub fn sealed_params(sealed: &[u8]) -> PngResult<KdfParams> {
+    if !is_sealed(sealed) {
+        return Err(error(
+            ErrorKind::Crypto,
+            "Payload is not passphrase encrypted",
+        ));
+    }
+    if sealed[SEALED_MAGIC.len()] != SEALED_VERSION {
+        return Err(error(
+            ErrorKind::Crypto,
+            "Unsupported encrypted payload version",
+        ));
+    }
+    let field = |index: usize| {
+        let start = SEALED_MAGIC.len() + 1 + index * 4;
+        u32::from_be_bytes(sealed[start..start + 4].try_into().unwrap())
+    };
+    Ok(KdfParams {
+        memory_kib: field(0),
+        iterations: field(1),
+        parallelism: field(2),
+    })
+}
+
+/// Decrypt a payload produced by `seal` with the given passphrase
+pub fn open(sealed: &[u8], passphrase: &str) -> PngResult<Vec<u8>> {
+    open_within(sealed, passphrase, KdfParams::MAX)
+}
+
+/// Like `open`, but refusing a payload which asks key derivation to cost more than `limit`
+pub fn open_within(sealed: &[u8], passphrase: &str, limit: KdfParams) -> PngResult<Vec<u8>> {
+    let params = sealed_params(sealed)?;
+    params.check(limit)?;
+    let salt = &sealed[PARAMS_LEN..PARAMS_LEN + SALT_LEN];
+    let nonce = &sealed[PARAMS_LEN + SALT_LEN..SEALED_HEADER_LEN];
+    let key = derive_key(passphrase, salt, params)?;
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(&key));
+    cipher
+        .decrypt(Nonce::from_slice(nonce), &sealed[SEALED_HEADER_LEN..])
+        .map_err(|_| {
+            error(
+                ErrorKind::Crypto,
+                "Decryption failed: wrong passphrase or corrupted payload",
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Keep the tests quick; the defaults are deliberately expensive
+    const CHEAP: KdfParams = KdfParams {
+        memory_kib: 64,
+        iterations: 1,
+        parallelism: 1,
+    };
+
+    #[test]
+    fn test_seal_and_open() {
+        let sealed = seal(b"attack at dawn", "hunter2", CHEAP).unwrap();
+        assert!(is_sealed(&sealed));
+        assert_eq!(open(&sealed, "hunter2").unwrap(), b"attack at dawn");
+    }
+
+    #[test]
+    fn test_open_with_wrong_passphrase() {
+        let sealed = seal(b"attack at dawn", "hunter2", CHEAP).unwrap();
+        assert!(open(&sealed, "hunter3").is_err());
+    }
+
+    #[test]
+    fn test_params_are_recorded() {
+        let params = KdfParams {
+            memory_kib: 128,
+            iterations: 3,
+            parallelism: 2,
+        };
+        let sealed = seal(b"secret", "pw", params).unwrap();
+        assert_eq!(sealed_params(&sealed).unwrap(), params);
+    }
+
+    #[test]
+    fn test_costly_params_are_refused() {
+        let mut sealed = seal(b"secret", "pw", CHEAP).unwrap();
+        let memory = SEALED_MAGIC.len() + 1;
+        sealed[memory..memory + 4].copy_from_slice(&u32::MAX.to_be_bytes());
+        let e = open(&sealed, "pw").unwrap_err();
+        assert_eq!(crate::error::kind_of(&*e), ErrorKind::Crypto);
+
+        let lanes = KdfParams {
+            parallelism: 17,
+            ..CHEAP
+        };
+        assert!(seal(b"secret", "pw", lanes).is_err());
+
+        let sealed = seal(b"secret", "pw", CHEAP).unwrap();
+        let limit = KdfParams {
+            memory_kib: 32,
+            ..CHEAP
+        };
+        let e = open_within(&sealed, "pw", limit).unwrap_err();
+        assert_eq!(crate::error::kind_of(&*e), ErrorKind::Crypto);
+        assert!(open_within(&sealed, "pw", CHEAP).is_ok());
+    }
+
+    #[test]
+    fn test_invalid_params_are_crypto_errors() {
+        let empty = KdfParams {
+            memory_kib: 0,
+            ..CHEAP
+        };
+        let e = seal(b"secret", "pw", empty).unwrap_err();
+        assert_eq!(crate::error::kind_of(&*e), ErrorKind::Crypto);
+    }
+
+    #[test]
+    fn test_plain_payload_is_not_sealed() {
+        assert!(!is_sealed(b"just a message"));
+        assert!(open(b"just a message", "pw").is_err());
+    }
+}
@@ -0,0 +1,421 @@
+use crate::args::DaemonArgs;
+use crate::commands;
+use pngmi::chunk_type::ChunkType;
+use pngmi::ipc;
+use pngmi::message;
+use pngmi::output::{self, WriteOptions};
+use pngmi::png::Png;
+use pngmi::validate;
+use pngmi::PngResult;
+use serde_json::{json, Value};
+use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM};
+use signal_hook::iterator::Signals;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::SystemTime;
+use tracing::{info, warn};
+
+/// What a file looked like when it was cached, so that changes to it are noticed
+type Stamp = (SystemTime, u64);
+
+/// The bytes of a file and its parsed form, or why it couldn't be parsed
+type Loaded = (Arc<Vec<u8>>, Result<Arc<Png>, String>);
+
+/// A carrier file as last read from disk
+struct Carrier {
+    stamp: Stamp,
+    bytes: Arc<Vec<u8>>,
+    /// The parsed file, or why it couldn't be parsed, which validation still reports on
+    png: Result<Arc<Png>, String>,
+    /// When the carrier was last asked for, to evict the least recently used first
+    last_used: u64,
+}
+
+/// Carriers kept parsed between requests, up to a number of files
+struct Cache {
+    carriers: HashMap<PathBuf, Carrier>,
+    capacity: usize,
+    clock: u64,
+}
+
+impl Cache {
+    fn new(capacity: usize) -> Self {
+        Cache {
+            carriers: HashMap::new(),
+            capacity,
+            clock: 0,
+        }
+    }
+
+    /// The cached carrier for the file, if there is one and the file hasn't changed since
+    fn get(&mut self, path: &Path, stamp: Stamp) -> Option<Loaded> {
+        self.clock += 1;
+        let carrier = self.carriers.get_mut(path).filter(|c| c.stamp == stamp)?;
+        carrier.last_used = self.clock;
+        Some((carrier.bytes.clone(), carrier.png.clone()))
+    }
+
+    fn insert(&mut self, path: PathBuf, carrier: Carrier) {
+        if self.capacity == 0 {
+            return;
+        }
+        if !self.carriers.contains_key(&path) && self.carriers.len() >= self.capacity {
+            let oldest = self
+                .carriers
+                .iter()
+                .min_by_key(|(_, carrier)| carrier.last_used)
+                .map(|(path, _)| path.clone());
+            if let Some(oldest) = oldest {
+                self.carriers.remove(&oldest);
+            }
+        }
+        self.clock += 1;
+        let carrier = Carrier {
+            last_used: self.clock,
+            ..carrier
+        };
+        self.carriers.insert(path, carrier);
+    }
+}
+
+/// State shared by the connections being served
+struct Daemon<'a> {
+    cache: Mutex<Cache>,
+    options: WriteOptions<'a>,
+}
+
+/// Accept connections on the socket until the process is stopped, answering the requests on
+/// each connection in turn. Connections are served on threads of their own and share the cache.
+pub fn run(args: DaemonArgs) -> PngResult<()> {
+    let listener = bind(&args.socket)?;
+    let _socket = SocketFile(&args.socket);
+    remove_on_signal(&args.socket)?;
+    eprintln!("Listening on {}", args.socket.display());
+    let daemon = Daemon {
+        cache: Mutex::new(Cache::new(args.cache_size)),
+        options: WriteOptions {
+            backup_suffix: args.backup.as_deref(),
+            preserve: args.preserve,
+        },
+    };
+    thread::scope(|scope| {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let daemon = &daemon;
+                    scope.spawn(move || daemon.serve(stream));
+                }
+                Err(e) => warn!("Couldn't accept a connection: {}", e),
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Listen on the socket, replacing one left behind by a daemon which is no longer running.
+/// Only the owner may connect, since the daemon reads and writes files on their behalf.
+fn bind(path: &Path) -> PngResult<UnixListener> {
+    if let Ok(metadata) = fs::symlink_metadata(path) {
+        if !metadata.file_type().is_socket() {
+            return Err(format!("{} exists and isn't a socket", path.display()).into());
+        }
+        if UnixStream::connect(path).is_ok() {
+            let message = format!("A daemon is already listening on {}", path.display());
+            return Err(message.into());
+        }
+        fs::remove_file(path)?;
+    }
+    // The socket is created without group or other access, rather than narrowed afterwards,
+    // so that nobody else can connect in between
+    // SAFETY: umask can't fail, and nothing else creating files runs until it is restored
+    let umask = unsafe { libc::umask(0o177) };
+    let listener = UnixListener::bind(path);
+    unsafe { libc::umask(umask) };
+    Ok(listener?)
+}
+
+/// The socket, removed when the daemon stops serving
+struct SocketFile<'a>(&'a Path);
+
+impl Drop for SocketFile<'_> {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(self.0);
+    }
+}
+
+/// Remove the socket and exit when the daemon is told to stop, as it is otherwise stopped
+/// only by a signal and would leave the socket behind
+fn remove_on_signal(path: &Path) -> PngResult<()> {
+    let mut signals = Signals::new([SIGHUP, SIGINT, SIGTERM])?;
+    let path = path.to_path_buf();
+    thread::spawn(move || {
+        if let Some(signal) = signals.forever().next() {
+            let _ = fs::remove_file(&path);
+            process::exit(128 + signal);
+        }
+    });
+    Ok(())
+}
+
+impl Daemon<'_> {
+    fn serve(&self, mut stream: UnixStream) {
+        loop {
+            let frame = match ipc::read_frame(&mut stream, ipc::MAX_FRAME_LEN) {
+                Ok(Some(frame)) => frame,
+                Ok(None) => return,
+                Err(e) => {
+                    // The stream can't be resynchronised, so report the error and hang up
+                    let _ = self.reply(&mut stream, &error_json(&e.to_string()));
+                    return;
+                }
+            };
+            let response = match serde_json::from_slice(&frame) {
+                Ok(request) => self
+                    .handle(&request)
+                    .unwrap_or_else(|e| error_json(&e.to_string())),
+                Err(e) => error_json(&format!("Request isn't valid JSON: {}", e)),
+            };
+            if let Err(e) = self.reply(&mut stream, &response) {
+                warn!("Couldn't send a response: {}", e);
+                return;
+            }
+        }
+    }
+
+    fn reply(&self, stream: &mut UnixStream, response: &Value) -> PngResult<()> {
+        ipc::write_frame(stream, response.to_string().as_bytes())
+    }
+
+    fn handle(&self, request: &Value) -> PngResult<Value> {
+        let command = field(request, "command")?;
+        let path = absolute_path(field(request, "file")?, "file")?;
+        info!("{} {}", command, path.display());
+        let passphrase = optional_field(request, "passphrase")?;
+        let mut response = match command {
+            "validate" => {
+                let (bytes, _) = self.load(&path)?;
+                commands::validation_json(Some(&path), &validate::validate(&bytes))
+            }
+            "decode" => {
+                let png = self.load_png(&path)?;
+                let envelope = message::read(&png, field(request, "chunk_type")?, passphrase)?;
+                commands::payload_json(&envelope)
+            }
+            "encode" => {
+                let chunk_type = ChunkType::from_str(field(request, "chunk_type")?)?;
+                let message = field(request, "message")?;
+                let output = match optional_field(request, "output")? {
+                    Some(output) => absolute_path(output, "output")?,
+                    None => path.clone(),
+                };
+                let mut png = Png::clone(&*self.load_png(&path)?);
+                let chunks = message::embed(&mut png, chunk_type, message, passphrase)?;
+                let bytes = png.as_bytes();
+                output::write_atomic(&output, &bytes, &self.options)?;
+                self.store(&output, bytes, png)?;
+                json!({ "output": output, "chunks": chunks })
+            }
+            _ => return Err(format!("Unknown command '{}'", command).into()),
+        };
+        response["ok"] = json!(true);
+        Ok(response)
+    }
+
+    /// The bytes and parsed form of the file, read from disk only if it isn't cached or has
+    /// changed since it was
+    fn load(&self, path: &Path) -> PngResult<Loaded> {
+        let stamp = stamp(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        if let Some(carrier) = self.lock().get(path, stamp) {
+            return Ok(carrier);
+        }
+        // Read outside the lock, so that other connections aren't held up by the disk
+        let bytes = fs::read(path)?;
+        let png = Png::try_from(&bytes[..])
+            .map(Arc::new)
+            .map_err(|e| e.to_string());
+        let bytes = Arc::new(bytes);
+        let carrier = Carrier {
+            stamp,
+            bytes: bytes.clone(),
+            png: png.clone(),
+            last_used: 0,
+        };
+        self.lock().insert(path.to_path_buf(), carrier);
+        Ok((bytes, png))
+    }
+
+    fn load_png(&self, path: &Path) -> PngResult<Arc<Png>> {
+        let (_, png) = self.load(path)?;
+        Ok(png.map_err(|e| format!("{}: {}", path.display(), e))?)
+    }
+
+    /// Cache a file the daemon has just written, so that it isn't read straight back
+    fn store(&self, path: &Path, bytes: Vec<u8>, png: Png) -> PngResult<()> {
+        let carrier = Carrier {
+            stamp: stamp(path)?,
+            bytes: Arc::new(bytes),
+            png: Ok(Arc::new(png)),
+            last_used: 0,
+        };
+        self.lock().insert(path.to_path_buf(), carrier);
+        Ok(())
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Cache> {
+        // A thread which panicked mid-request leaves nothing half updated in the cache
+        self.cache.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+fn stamp(path: &Path) -> io::Result<Stamp> {
+    let metadata = fs::metadata(path)?;
+    Ok((metadata.modified()?, metadata.len()))
+}
+
+fn field<'a>(request: &'a Value, name: &str) -> PngResult<&'a str> {
+    optional_field(request, name)?.ok_or_else(|| format!("Request is missing '{}'", name).into())
+}
+
+fn optional_field<'a>(request: &'a Value, name: &str) -> PngResult<Option<&'a str>> {
+    match request.get(name) {
+        None | Some(Value::Null) => Ok(None),
+        Some(Value::String(value)) => Ok(Some(value)),
+        Some(_) => Err(format!("'{}' must be a string", name).into()),
+    }
+}
+
+/// A path from a request, which must be absolute since the daemon's working directory is
+/// unlikely to be the client's
+fn absolute_path(value: &str, name: &str) -> PngResult<PathBuf> {
+    let path = PathBuf::from(value);
+    if !path.is_absolute() {
+        return Err(format!("'{}' must be an absolute path", name).into());
+    }
+    Ok(path)
+}
+
+fn error_json(message: &str) -> Value {
+    json!({ "ok": false, "error": message })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pngmi::chunk::Chunk;
+    use std::os::unix::fs::PermissionsExt;
+    use std::time::Duration;
+
+    fn carrier(stamp: Stamp) -> Carrier {
+        Carrier {
+            stamp,
+            bytes: Arc::new(vec![]),
+            png: Err("not a PNG".to_string()),
+            last_used: 0,
+        }
+    }
+
+    fn daemon() -> Daemon<'static> {
+        Daemon {
+            cache: Mutex::new(Cache::new(2)),
+            options: WriteOptions::default(),
+        }
+    }
+
+    #[test]
+    fn test_cache_evicts_least_recently_used() {
+        let stamp = (SystemTime::UNIX_EPOCH, 1);
+        let mut cache = Cache::new(2);
+        cache.insert(PathBuf::from("/a"), carrier(stamp));
+        cache.insert(PathBuf::from("/b"), carrier(stamp));
+        assert!(cache.get(Path::new("/a"), stamp).is_some());
+        cache.insert(PathBuf::from("/c"), carrier(stamp));
+        assert!(cache.get(Path::new("/a"), stamp).is_some());
+        assert!(cache.get(Path::new("/b"), stamp).is_none());
+        assert!(cache.get(Path::new("/c"), stamp).is_some());
+
+        // Replacing a cached file doesn't evict another
+        cache.insert(PathBuf::from("/c"), carrier(stamp));
+        assert_eq!(cache.carriers.len(), 2);
+
+        let mut cache = Cache::new(0);
+        cache.insert(PathBuf::from("/a"), carrier(stamp));
+        assert!(cache.get(Path::new("/a"), stamp).is_none());
+    }
+
+    #[test]
+    fn test_cache_misses_changed_files() {
+        let stamp = (SystemTime::UNIX_EPOCH, 1);
+        let mut cache = Cache::new(1);
+        cache.insert(PathBuf::from("/a"), carrier(stamp));
+        let grown = (SystemTime::UNIX_EPOCH, 2);
+        assert!(cache.get(Path::new("/a"), grown).is_none());
+        let touched = (SystemTime::UNIX_EPOCH + Duration::from_secs(1), 1);
+        assert!(cache.get(Path::new("/a"), touched).is_none());
+    }
+
+    #[test]
+    fn test_load_rereads_changed_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a.png");
+        let chunk = |chunk_type: &str, data: &[u8]| {
+            Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec())
+        };
+        let png = Png::from_chunks(vec![chunk("IHDR", &[0; 13]), chunk("IEND", &[])]);
+        fs::write(&path, png.as_bytes()).unwrap();
+
+        let daemon = daemon();
+        let (first, parsed) = daemon.load(&path).unwrap();
+        assert!(parsed.is_ok());
+        let (again, _) = daemon.load(&path).unwrap();
+        assert!(Arc::ptr_eq(&first, &again));
+
+        fs::write(&path, b"not a png").unwrap();
+        let (changed, parsed) = daemon.load(&path).unwrap();
+        assert_eq!(&changed[..], b"not a png");
+        assert!(parsed.is_err());
+    }
+
+    #[test]
+    fn test_relative_paths_refused() {
+        let daemon = daemon();
+        let request = json!({ "command": "validate", "file": "a.png" });
+        let error = daemon.handle(&request).unwrap_err();
+        assert_eq!(error.to_string(), "'file' must be an absolute path");
+        let request = json!({
+            "command": "encode",
+            "file": "/a.png",
+            "chunk_type": "ruSt",
+            "message": "hi",
+            "output": "b.png",
+        });
+        let error = daemon.handle(&request).unwrap_err();
+        assert_eq!(error.to_string(), "'output' must be an absolute path");
+    }
+
+    #[test]
+    fn test_bind() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("pngmi.sock");
+        fs::write(&path, b"").unwrap();
+        assert!(bind(&path).is_err());
+        assert!(path.exists());
+
+        fs::remove_file(&path).unwrap();
+        let listener = bind(&path).unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        assert!(bind(&path).is_err());
+
+        // A socket left behind by a daemon no longer listening is replaced
+        drop(listener);
+        assert!(bind(&path).is_ok());
+    }
+}
@@ -0,0 +1,440 @@
+use crate::error::{self, error, ErrorKind};
+use crate::ihdr::{ColorType, Ihdr, Pass};
+use crate::png::Png;
+use crate::zlib;
+use crate::PngResult;
+
+/// The filter applied to a scanline before compression, as stored in its leading byte
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterType {
+    None,
+    Sub,
+    Up,
+    Average,
+    Paeth,
+}
+
+impl TryFrom<u8> for FilterType {
+    type Error = crate::PngError;
+
+    fn try_from(value: u8) -> PngResult<Self> {
+        match value {
+            0 => Ok(FilterType::None),
+            1 => Ok(FilterType::Sub),
+            2 => Ok(FilterType::Up),
+            3 => Ok(FilterType::Average),
+            4 => Ok(FilterType::Paeth),
+            _ => Err(error(
+                ErrorKind::Parse,
+                format!("Invalid filter type {}", value),
+            )),
+        }
+    }
+}
+
+/// The pixels of an image, as rows of packed samples at the image's own bit depth: several
+/// pixels to a byte below 8 bits, and big-endian samples at 16 bits
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pixels {
+    pub width: usize,
+    pub height: usize,
+    pub bit_depth: u8,
+    pub color_type: ColorType,
+    data: Vec<u8>,
+}
+
+impl Pixels {
+    /// An image of the given size and format with every byte zero, which is transparent black
+    /// where the format has alpha. Fails if the image has more bytes than can be addressed.
+    pub fn blank(ihdr: &Ihdr) -> PngResult<Self> {
+        let mut pixels = Pixels {
+            width: ihdr.width as usize,
+            height: ihdr.height as usize,
+            bit_depth: ihdr.bit_depth,
+            color_type: ihdr.color_type,
+            data: vec![],
+        };
+        let len = pixels.stride().checked_mul(pixels.height).ok_or_else(|| {
+            let message = format!("A {}x{} image is too large", ihdr.width, ihdr.height);
+            error(ErrorKind::Parse, message)
+        })?;
+        pixels.data = vec![0; len];
+        Ok(pixels)
+    }
+
+    /// The number of bits each pixel takes up
+    pub fn bits_per_pixel(&self) -> usize {
+        self.bit_depth as usize * self.color_type.channels() as usize
+    }
+
+    /// The number of bytes in each row
+    pub fn stride(&self) -> usize {
+        (self.width * self.bits_per_pixel()).div_ceil(8)
+    }
+
+    /// The packed pixels of the given row
+    pub fn row(&self, y: usize) -> &[u8] {
+        &self.data[y * self.stride()..(y + 1) * self.stride()]
+    }
+
+    /// Every row, top to bottom
+    pub fn rows(&self) -> impl Iterator<Item = &[u8]> {
+        self.data.chunks(self.stride().max(1)).take(self.height)
+    }
+
+    /// The rows packed one after another
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// The rows packed one after another, for editing pixels in place
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+
+    /// The packed pixels of the given row, for editing them in place
+    pub fn row_mut(&mut self, y: usize) -> &mut [u8] {
+        let stride = self.stride();
+        &mut self.data[y * stride..(y + 1) * stride]
+    }
+}
+
+/// The predictor the Paeth filter uses: whichever of the neighbours to the left, above and
+/// above left is closest to their linear combination
+fn paeth(left: u8, up: u8, up_left: u8) -> u8 {
+    let estimate = left as i16 + up as i16 - up_left as i16;
+    let (a, b, c) = (
+        (estimate - left as i16).abs(),
+        (estimate - up as i16).abs(),
+        (estimate - up_left as i16).abs(),
+    );
+    if a <= b && a <= c {
+        left
+    } else if b <= c {
+        up
+    } else {
+        up_left
+    }
+}
+
+/// Undo the filter on a scanline in place, given the unfiltered scanline above it (all zeros
+/// for the first) and the number of bytes per complete pixel, at least 1
+pub fn unfilter(filter: FilterType, row: &mut [u8], previous: &[u8], bytes_per_pixel: usize) {
+    match filter {
+        FilterType::None => {}
+        FilterType::Sub => {
+            for i in bytes_per_pixel..row.len() {
+                row[i] = row[i].wrapping_add(row[i - bytes_per_pixel]);
+            }
+        }
+        FilterType::Up => {
+            for (byte, up) in row.iter_mut().zip(previous) {
+                *byte = byte.wrapping_add(*up);
+            }
+        }
+        FilterType::Average => {
+            for i in 0..row.len() {
+                let left = if i >= bytes_per_pixel {
+                    row[i - bytes_per_pixel]
+                } else {
+                    0
+                };
+                let average = ((left as u16 + previous[i] as u16) / 2) as u8;
+                row[i] = row[i].wrapping_add(average);
+            }
+        }
+        FilterType::Paeth => {
+            for i in 0..row.len() {
+                let (left, up_left) = match i >= bytes_per_pixel {
+                    true => (row[i - bytes_per_pixel], previous[i - bytes_per_pixel]),
+                    false => (0, 0),
+                };
+                row[i] = row[i].wrapping_add(paeth(left, previous[i], up_left));
+            }
+        }
+    }
+}
+
+/// Apply a filter to a scanline, given the scanline above it (all zeros for the first) and
+/// the number of bytes per complete pixel, at least 1. The inverse of `unfilter`.
+pub fn filter(filter: FilterType, row: &[u8], previous: &[u8], bytes_per_pixel: usize) -> Vec<u8> {
+    (0..row.len())
+        .map(|i| {
+            let (left, up_left) = match i >= bytes_per_pixel {
+                true => (row[i - bytes_per_pixel], previous[i - bytes_per_pixel]),
+                false => (0, 0),
+            };
+            let predictor = match filter {
+                FilterType::None => 0,
+                FilterType::Sub => left,
+                FilterType::Up => previous[i],
+                FilterType::Average => ((left as u16 + previous[i] as u16) / 2) as u8,
+                FilterType::Paeth => paeth(left, previous[i], up_left),
+            };
+            row[i].wrapping_sub(predictor)
+        })
+        .collect()
+}
+
+/// Unfilter the scanlines of one pass, returning its packed rows and the rest of the data
+fn unfilter_pass<'a>(ihdr: &Ihdr, pass: &Pass, data: &'a [u8]) -> PngResult<(Vec<u8>, &'a [u8])> {
+    let row_len = ihdr.row_len(pass.width);
+    let len = pass.height * (1 + row_len);
+    if pass.is_empty() {
+        return Ok((vec![], data));
+    }
+    if data.len() < len {
+        return Err(error(ErrorKind::Parse, "Image data ends early"));
+    }
+    let bytes_per_pixel = ihdr.bits_per_pixel().div_ceil(8);
+    let mut rows = vec![0; pass.height * row_len];
+    let zeros = vec![0; row_len];
+    for (y, scanline) in data[..len].chunks(1 + row_len).enumerate() {
+        let filter = FilterType::try_from(scanline[0])?;
+        let (done, rest) = rows.split_at_mut(y * row_len);
+        let previous = match y {
+            0 => &zeros[..],
+            _ => &done[(y - 1) * row_len..],
+        };
+        let row = &mut rest[..row_len];
+        row.copy_from_slice(&scanline[1..]);
+        unfilter(filter, row, previous, bytes_per_pixel);
+    }
+    Ok((rows, &data[len..]))
+}
+
+/// Copy the pixel at index `from_x` of one packed row to index `to_x` of another
+pub(crate) fn copy_pixel(
+    from: &[u8],
+    from_x: usize,
+    to: &mut [u8],
+    to_x: usize,
+    bits_per_pixel: usize,
+) {
+    if bits_per_pixel >= 8 {
+        let len = bits_per_pixel / 8;
+        to[to_x * len..(to_x + 1) * len].copy_from_slice(&from[from_x * len..(from_x + 1) * len]);
+        return;
+    }
+    // Pixels narrower than a byte are packed from the most significant bit down
+    let mask = (1u8 << bits_per_pixel) - 1;
+    let shift = |x: usize| 8 - bits_per_pixel - (x * bits_per_pixel) % 8;
+    let value = (from[from_x * bits_per_pixel / 8] >> shift(from_x)) & mask;
+    let byte = &mut to[to_x * bits_per_pixel / 8];
+    *byte = (*byte & !(mask << shift(to_x))) | (value << shift(to_x));
+}
+
+/// Decompress image data, failing unless it inflates to exactly `expected_len` bytes
+pub(crate) fn inflate(compressed: &[u8], expected_len: usize) -> PngResult<Vec<u8>> {
+    // Deflate can't expand data by more than about 1032 times, so a header claiming more than
+    // that doesn't get to reserve memory up front for data which can't be there
+    let raw = Vec::with_capacity(expected_len.min(compressed.len().saturating_mul(1032)));
+    let raw = zlib::inflate_into(compressed, expected_len as u64, raw)
+        .map_err(|e| error(error::kind_of(&*e), format!("Image data: {}", e)))?;
+    if raw.len() != expected_len {
+        return Err(error(
+            ErrorKind::Validation,
+            format!(
+                "Image data decompresses to {} bytes but the header implies {}",
+                raw.len(),
+                expected_len
+            ),
+        ));
+    }
+    Ok(raw)
+}
+
+/// Turn the decompressed image data into rows of pixels, undoing each scanline's filter and
+/// putting the Adam7 passes of an interlaced image back together
+pub fn decode(ihdr: &Ihdr, data: &[u8]) -> PngResult<Pixels> {
+    let mut pixels = Pixels {
+        width: ihdr.width as usize,
+        height: ihdr.height as usize,
+        bit_depth: ihdr.bit_depth,
+        color_type: ihdr.color_type,
+        data: vec![],
+    };
+    let stride = pixels.stride();
+    let passes = ihdr.passes();
+    if !ihdr.interlaced {
+        (pixels.data, _) = unfilter_pass(ihdr, &passes[0], data)?;
+        return Ok(pixels);
+    }
+    pixels.data = vec![0; stride * pixels.height];
+    let bits_per_pixel = ihdr.bits_per_pixel();
+    let mut data = data;
+    for pass in &passes {
+        let rows;
+        (rows, data) = unfilter_pass(ihdr, pass, data)?;
+        let row_len = ihdr.row_len(pass.width);
+        for y in 0..pass.height {
+            let from = &rows[y * row_len..(y + 1) * row_len];
+            let to_y = pass.y + y * pass.y_step;
+            let to = &mut pixels.data[to_y * stride..(to_y + 1) * stride];
+            for x in 0..pass.width {
+                copy_pixel(from, x, to, pass.x + x * pass.x_step, bits_per_pixel);
+            }
+        }
+    }
+    Ok(pixels)
+}
+
+/// Filter the packed rows of one pass, choosing for each the filter which leaves the smallest
+/// sum of bytes taken as signed, the heuristic libpng uses
+fn filter_pass(ihdr: &Ihdr, rows: &[u8], row_len: usize, data: &mut Vec<u8>) {
+    const FILTERS: [FilterType; 5] = [
+        FilterType::None,
+        FilterType::Sub,
+        FilterType::Up,
+        FilterType::Average,
+        FilterType::Paeth,
+    ];
+    let bytes_per_pixel = ihdr.bits_per_pixel().div_ceil(8);
+    let zeros = vec![0; row_len];
+    let mut previous = &zeros[..];
+    for row in rows.chunks(row_len) {
+        let (filter_type, filtered) = FILTERS
+            .iter()
+            .map(|&filter_type| {
+                (
+                    filter_type,
+                    filter(filter_type, row, previous, bytes_per_pixel),
+                )
+            })
+            .min_by_key(|(_, filtered)| {
+                filtered
+                    .iter()
+                    .map(|&byte| (byte as i8).unsigned_abs() as u64)
+                    .sum::<u64>()
+            })
+            .expect("there is always a filter");
+        data.push(filter_type as u8);
+        data.extend(filtered);
+        previous = row;
+    }
+}
+
+/// Turn rows of pixels back into image data ready to be compressed, the inverse of `decode`,
+/// splitting an interlaced image into its Adam7 passes
+pub fn encode(ihdr: &Ihdr, pixels: &Pixels) -> Vec<u8> {
+    let mut data = Vec::with_capacity(ihdr.raw_data_len());
+    if !ihdr.interlaced {
+        filter_pass(ihdr, pixels.data(), pixels.stride(), &mut data);
+        return data;
+    }
+    let bits_per_pixel = ihdr.bits_per_pixel();
+    for pass in ihdr.passes().iter().filter(|pass| !pass.is_empty()) {
+        let row_len = ihdr.row_len(pass.width);
+        let mut rows = vec![0; pass.height * row_len];
+        for (y, row) in rows.chunks_mut(row_len).enumerate() {
+            let from = pixels.row(pass.y + y * pass.y_step);
+            for x in 0..pass.width {
+                copy_pixel(from, pass.x + x * pass.x_step, row, x, bits_per_pixel);
+            }
+        }
+        filter_pass(ihdr, &rows, row_len, &mut data);
+    }
+    data
+}
+
+/// Decode the pixels of the PNG
+pub fn decode_png(png: &Png) -> PngResult<Pixels> {
+    decode(&Ihdr::try_from(png)?, &png.idat_data()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ihdr(
+        width: u32,
+        height: u32,
+        bit_depth: u8,
+        color_type: ColorType,
+        interlaced: bool,
+    ) -> Ihdr {
+        Ihdr {
+            width,
+            height,
+            bit_depth,
+            color_type,
+            compression_method: 0,
+            filter_method: 0,
+            interlaced,
+        }
+    }
+
+    #[test]
+    fn test_unfilter() {
+        let filters = [
+            FilterType::None,
+            FilterType::Sub,
+            FilterType::Up,
+            FilterType::Average,
+            FilterType::Paeth,
+        ];
+        // 5 rows of 3 RGB pixels, each row filtered with a different filter
+        let rows: Vec<Vec<u8>> = (0..5u8)
+            .map(|y| {
+                (0..9u8)
+                    .map(|i| y.wrapping_mul(71) ^ i.wrapping_mul(37))
+                    .collect()
+            })
+            .collect();
+        let mut data = vec![];
+        let mut previous = vec![0; 9];
+        for (row, filter_type) in rows.iter().zip(filters) {
+            data.push(filter_type as u8);
+            data.extend(filter(filter_type, row, &previous, 3));
+            previous = row.clone();
+        }
+        let pixels = decode(&ihdr(3, 5, 8, ColorType::Truecolor, false), &data).unwrap();
+        assert_eq!(pixels.rows().collect::<Vec<_>>(), rows);
+
+        data[10] = 5;
+        assert!(decode(&ihdr(3, 5, 8, ColorType::Truecolor, false), &data).is_err());
+        assert!(decode(&ihdr(3, 5, 8, ColorType::Truecolor, false), &data[..20]).is_err());
+    }
+
+    #[test]
+    fn test_encode_decode() {
+        for (bit_depth, color_type) in [
+            (1, ColorType::Grayscale),
+            (2, ColorType::Indexed),
+            (8, ColorType::GrayscaleAlpha),
+            (16, ColorType::Truecolor),
+        ] {
+            for (width, height, interlaced) in [(1, 1, true), (5, 3, false), (9, 10, true)] {
+                let header = ihdr(width, height, bit_depth, color_type, interlaced);
+                let image: Vec<Vec<u8>> = (0..height as u8)
+                    .map(|y| {
+                        (0..header.row_len(width as usize) as u8)
+                            .map(|i| y.wrapping_mul(53).wrapping_add(i.wrapping_mul(29)))
+                            .collect()
+                    })
+                    .collect();
+                let pixels = Pixels {
+                    width: width as usize,
+                    height: height as usize,
+                    bit_depth,
+                    color_type,
+                    data: image.concat(),
+                };
+                let data = encode(&header, &pixels);
+                assert_eq!(data.len(), header.raw_data_len());
+                let pixels = decode(&header, &data).unwrap();
+                // Padding bits at the end of a row aren't part of any pixel
+                for (row, expected) in pixels.rows().zip(&image) {
+                    let bits = width as usize * header.bits_per_pixel();
+                    let last = bits.div_ceil(8) - 1;
+                    assert_eq!(row[..last], expected[..last]);
+                    let mask = match bits % 8 {
+                        0 => 0xff,
+                        rem => 0xffu8 << (8 - rem),
+                    };
+                    assert_eq!(row[last] & mask, expected[last] & mask);
+                }
+            }
+        }
+    }
+}
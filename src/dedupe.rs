@@ -0,0 +1,184 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+use std::fmt::{Display, Formatter};
+
+/// Standard ancillary chunks which the spec allows at most once per file
+pub(crate) const ONCE_ONLY_TYPES: &[ChunkType] = &[
+    ChunkType::CHRM,
+    ChunkType::CICP,
+    ChunkType::GAMA,
+    ChunkType::ICCP,
+    ChunkType::MDCV,
+    ChunkType::CLLI,
+    ChunkType::SBIT,
+    ChunkType::SRGB,
+    ChunkType::BKGD,
+    ChunkType::HIST,
+    ChunkType::TRNS,
+    ChunkType::EXIF,
+    ChunkType::PHYS,
+    ChunkType::TIME,
+    ChunkType::ACTL,
+    ChunkType::OFFS,
+    ChunkType::PCAL,
+    ChunkType::SCAL,
+    ChunkType::STER,
+];
+
+/// Which duplicates `dedupe` removes
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DedupeOptions {
+    /// Also remove later chunks which differ from the first but may only appear once, such as
+    /// a second gAMA chunk or a second sPLT palette of the same name. Decoders use the first.
+    pub first_wins: bool,
+}
+
+/// A duplicate `dedupe` left in place because it differs from the chunk it repeats
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict {
+    /// The index of the duplicate after deduplication
+    pub index: usize,
+    pub chunk_type: ChunkType,
+}
+
+impl Display for Conflict {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} chunk {} differs from an earlier one but may appear only once",
+            self.chunk_type, self.index
+        )
+    }
+}
+
+/// What `dedupe` did
+#[derive(Debug, Default)]
+pub struct DedupeReport {
+    /// The chunks removed, in file order
+    pub removed: Vec<Chunk>,
+    /// Duplicates which differ from the first copy, so were kept
+    pub conflicts: Vec<Conflict>,
+}
+
+/// The name of an sPLT palette, which the spec requires to be unique
+fn palette_name(chunk: &Chunk) -> &[u8] {
+    chunk.data().split(|&b| b == 0).next().unwrap_or_default()
+}
+
+/// Whether `chunk` repeats `earlier` where the spec allows only one of them
+fn conflicts(earlier: &Chunk, chunk: &Chunk) -> bool {
+    let chunk_type = chunk.chunk_type();
+    if earlier.chunk_type() != chunk_type {
+        return false;
+    }
+    match *chunk_type {
+        ChunkType::SPLT => palette_name(earlier) == palette_name(chunk),
+        _ => ONCE_ONLY_TYPES.contains(chunk_type),
+    }
+}
+
+/// Remove standard ancillary chunks which are exact copies of an earlier chunk, and with
+/// `first_wins` those which repeat one the spec allows only once. Chunks which may legally
+/// repeat, such as text under different keywords or palettes with different names, are kept,
+/// as are unknown chunks and APNG frames, whose repeats may be meaningful.
+pub fn dedupe(png: &mut Png, options: DedupeOptions) -> DedupeReport {
+    let mut report = DedupeReport::default();
+    let mut seen: Vec<&Chunk> = vec![];
+    let mut remove = vec![];
+    for (index, chunk) in png.chunks().iter().enumerate() {
+        let chunk_type = chunk.chunk_type();
+        if chunk_type.is_critical()
+            || !chunk_type.is_standard()
+            || [ChunkType::FCTL, ChunkType::FDAT].contains(chunk_type)
+        {
+            continue;
+        }
+        if seen.contains(&chunk) {
+            remove.push(index);
+        } else if seen.iter().any(|earlier| conflicts(earlier, chunk)) {
+            match options.first_wins {
+                true => remove.push(index),
+                false => {
+                    report.conflicts.push(Conflict {
+                        index: index - remove.len(),
+                        chunk_type: *chunk_type,
+                    });
+                    seen.push(chunk);
+                }
+            }
+        } else {
+            seen.push(chunk);
+        }
+    }
+    for index in remove.into_iter().rev() {
+        report.removed.insert(0, png.remove_chunk_at(index));
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{chunk, types};
+
+    fn png() -> Png {
+        Png::from_chunks(vec![
+            chunk("IHDR", &[0; 13]),
+            chunk("gAMA", &[0, 0, 177, 143]),
+            chunk("tEXt", b"Title\0a"),
+            chunk("tEXt", b"Title\0b"),
+            chunk("sPLT", b"one\0\x08"),
+            chunk("sPLT", b"two\0\x08"),
+            chunk("sPLT", b"one\0\x10"),
+            chunk("IDAT", &[1]),
+            chunk("gAMA", &[0, 0, 177, 143]),
+            chunk("tEXt", b"Title\0a"),
+            chunk("gAMA", &[0, 1, 134, 160]),
+            chunk("gAMA", &[0, 1, 134, 160]),
+            chunk("prVt", &[1]),
+            chunk("prVt", &[1]),
+            chunk("IEND", &[]),
+        ])
+    }
+
+    #[test]
+    fn test_dedupe() {
+        let mut png = png();
+        let report = dedupe(&mut png, DedupeOptions::default());
+        let removed: Vec<_> = report.removed.iter().map(Chunk::data).collect();
+        assert_eq!(
+            removed,
+            [&[0, 0, 177, 143][..], b"Title\0a", &[0, 1, 134, 160]]
+        );
+        assert_eq!(
+            report.conflicts,
+            [
+                Conflict {
+                    index: 6,
+                    chunk_type: ChunkType::SPLT,
+                },
+                Conflict {
+                    index: 8,
+                    chunk_type: ChunkType::GAMA,
+                },
+            ]
+        );
+        assert_eq!(png.chunks().len(), 12);
+        assert!(dedupe(&mut png, DedupeOptions::default())
+            .removed
+            .is_empty());
+    }
+
+    #[test]
+    fn test_dedupe_first_wins() {
+        let mut png = png();
+        let report = dedupe(&mut png, DedupeOptions { first_wins: true });
+        assert_eq!(report.removed.len(), 5);
+        assert!(report.conflicts.is_empty());
+        assert_eq!(
+            types(&png),
+            ["IHDR", "gAMA", "tEXt", "tEXt", "sPLT", "sPLT", "IDAT", "prVt", "prVt", "IEND"]
+        );
+    }
+}
@@ -0,0 +1,246 @@
+use crate::chunk::ChunkRef;
+use crate::chunk_type::ChunkType;
+use crate::png::PngRef;
+use std::fmt::{Display, Formatter};
+use std::ops::Range;
+
+/// Above this many chunk pairs the sequences aren't aligned, as doing so would take too long.
+/// Edits usually touch a few chunks, leaving a long common prefix and suffix, so the part
+/// which needs aligning is small.
+const MAX_ALIGNMENT_CELLS: usize = 4_000_000;
+
+/// A way in which one chunk differs between two versions of a PNG
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkChange {
+    /// A chunk only in the new version, at the given index of it
+    Added {
+        index: usize,
+        chunk_type: ChunkType,
+        length: u32,
+        crc: u32,
+    },
+    /// A chunk only in the old version, at the given index of it
+    Removed {
+        index: usize,
+        chunk_type: ChunkType,
+        length: u32,
+        crc: u32,
+    },
+    /// A chunk whose data changed, at the given indexes of the old and new versions
+    Rewritten {
+        old_index: usize,
+        index: usize,
+        chunk_type: ChunkType,
+        old_length: u32,
+        new_length: u32,
+        old_crc: u32,
+        new_crc: u32,
+    },
+}
+
+impl Display for ChunkChange {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChunkChange::Added {
+                chunk_type, length, ..
+            } => write!(f, "add {} chunk ({} bytes)", chunk_type, length),
+            ChunkChange::Removed {
+                chunk_type, length, ..
+            } => write!(f, "remove {} chunk ({} bytes)", chunk_type, length),
+            ChunkChange::Rewritten {
+                chunk_type,
+                old_length,
+                new_length,
+                ..
+            } => write!(
+                f,
+                "rewrite {} chunk ({} -> {} bytes)",
+                chunk_type, old_length, new_length
+            ),
+        }
+    }
+}
+
+fn same_chunk(a: &ChunkRef, b: &ChunkRef) -> bool {
+    a.chunk_type() == b.chunk_type() && a.crc() == b.crc() && a.data() == b.data()
+}
+
+/// The chunks added, removed and rewritten to get from `before` to `after`, in file order.
+/// Unchanged chunks are matched up even if others were inserted or removed around them, and
+/// a chunk removed and another of the same type added in its place count as one rewrite.
+pub fn chunk_changes(before: &PngRef, after: &PngRef) -> Vec<ChunkChange> {
+    let (old, new) = (before.chunks(), after.chunks());
+    let prefix = old
+        .iter()
+        .zip(new)
+        .take_while(|(a, b)| same_chunk(a, b))
+        .count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| same_chunk(a, b))
+        .count();
+    let old_middle = &old[prefix..old.len() - suffix];
+    let new_middle = &new[prefix..new.len() - suffix];
+
+    // The runs of unmatched chunks between matched ones, as ranges of indexes
+    let mut changes = vec![];
+    let (mut i, mut j) = (0, 0);
+    for (matched_i, matched_j) in align(old_middle, new_middle) {
+        gap_changes(
+            old,
+            new,
+            prefix + i..prefix + matched_i,
+            prefix + j..prefix + matched_j,
+            &mut changes,
+        );
+        (i, j) = (matched_i + 1, matched_j + 1);
+    }
+    gap_changes(
+        old,
+        new,
+        prefix + i..prefix + old_middle.len(),
+        prefix + j..prefix + new_middle.len(),
+        &mut changes,
+    );
+    changes
+}
+
+/// The index pairs of the longest common subsequence of the two chunk lists
+fn align(old: &[ChunkRef], new: &[ChunkRef]) -> Vec<(usize, usize)> {
+    if old.is_empty() || new.is_empty() || old.len() * new.len() > MAX_ALIGNMENT_CELLS {
+        return vec![];
+    }
+    let width = new.len() + 1;
+    let mut lengths = vec![0u32; (old.len() + 1) * width];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lengths[i * width + j] = if same_chunk(&old[i], &new[j]) {
+                lengths[(i + 1) * width + j + 1] + 1
+            } else {
+                lengths[(i + 1) * width + j].max(lengths[i * width + j + 1])
+            };
+        }
+    }
+    let mut pairs = vec![];
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() && j < new.len() {
+        if same_chunk(&old[i], &new[j]) {
+            pairs.push((i, j));
+            (i, j) = (i + 1, j + 1);
+        } else if lengths[(i + 1) * width + j] >= lengths[i * width + j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    pairs
+}
+
+/// Describe a run of removed and added chunks, pairing up chunks of the same type as rewrites
+fn gap_changes(
+    old: &[ChunkRef],
+    new: &[ChunkRef],
+    removed: Range<usize>,
+    added: Range<usize>,
+    changes: &mut Vec<ChunkChange>,
+) {
+    let mut paired = vec![false; added.len()];
+    for i in removed {
+        let chunk_type = *old[i].chunk_type();
+        let partner = added
+            .clone()
+            .zip(paired.iter_mut())
+            .find(|(j, paired)| !**paired && *new[*j].chunk_type() == chunk_type);
+        match partner {
+            Some((j, paired)) => {
+                *paired = true;
+                changes.push(ChunkChange::Rewritten {
+                    old_index: i,
+                    index: j,
+                    chunk_type,
+                    old_length: old[i].length(),
+                    new_length: new[j].length(),
+                    old_crc: old[i].crc(),
+                    new_crc: new[j].crc(),
+                });
+            }
+            None => changes.push(ChunkChange::Removed {
+                index: i,
+                chunk_type,
+                length: old[i].length(),
+                crc: old[i].crc(),
+            }),
+        }
+    }
+    for (j, paired) in added.zip(paired) {
+        if !paired {
+            changes.push(ChunkChange::Added {
+                index: j,
+                chunk_type: *new[j].chunk_type(),
+                length: new[j].length(),
+                crc: new[j].crc(),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::png::Png;
+    use crate::test_util::chunk;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_chunk_changes() {
+        let before = Png::from_chunks(vec![
+            chunk("IHDR", &[0; 13]),
+            chunk("tIME", &[0; 7]),
+            chunk("tEXt", b"a\0b"),
+            chunk("IDAT", &[1, 2]),
+            chunk("IEND", &[]),
+        ]);
+        let after = Png::from_chunks(vec![
+            chunk("IHDR", &[0; 13]),
+            chunk("tEXt", b"a\0bc"),
+            chunk("zTXt", b"c\0\0x"),
+            chunk("IDAT", &[1, 2]),
+            chunk("IEND", &[]),
+        ]);
+        let ty = |chunk_type| ChunkType::from_str(chunk_type).unwrap();
+        let (old, new) = (before.chunks(), after.chunks());
+        assert_eq!(
+            chunk_changes(&before.borrowed(), &after.borrowed()),
+            vec![
+                ChunkChange::Removed {
+                    index: 1,
+                    chunk_type: ty("tIME"),
+                    length: 7,
+                    crc: old[1].crc(),
+                },
+                ChunkChange::Rewritten {
+                    old_index: 2,
+                    index: 1,
+                    chunk_type: ty("tEXt"),
+                    old_length: 3,
+                    new_length: 4,
+                    old_crc: old[2].crc(),
+                    new_crc: new[1].crc(),
+                },
+                ChunkChange::Added {
+                    index: 2,
+                    chunk_type: ty("zTXt"),
+                    length: 4,
+                    crc: new[2].crc(),
+                },
+            ]
+        );
+        assert!(chunk_changes(&before.borrowed(), &before.borrowed()).is_empty());
+        assert_eq!(
+            chunk_changes(&after.borrowed(), &before.borrowed())[0].to_string(),
+            "rewrite tEXt chunk (4 -> 3 bytes)"
+        );
+    }
+}
@@ -0,0 +1,107 @@
+use std::fmt::{Display, Formatter};
+
+/// Returns the Shannon entropy of the data in bits per byte, between 0 and 8
+pub fn shannon_entropy(data: &[u8]) -> f64 {
+    if data.is_empty() {
+        return 0.0;
+    }
+    let mut counts = [0usize; 256];
+    for b in data {
+        counts[*b as usize] += 1;
+    }
+    let len = data.len() as f64;
+    counts
+        .iter()
+        .filter(|count| **count > 0)
+        .map(|count| {
+            let p = *count as f64 / len;
+            p * (1.0 / p).log2()
+        })
+        .sum()
+}
+
+/// A rough guess at what kind of data a chunk holds, based on its byte distribution
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataClass {
+    Empty,
+    /// Mostly printable ASCII
+    Text,
+    /// Neither text nor random looking, e.g. binary headers or tables
+    Structured,
+    /// Close to uniformly distributed bytes, as produced by compression or encryption
+    Random,
+}
+
+impl Display for DataClass {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            DataClass::Empty => "empty",
+            DataClass::Text => "text",
+            DataClass::Structured => "structured",
+            DataClass::Random => "compressed/encrypted",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// The fraction of the maximum achievable entropy above which data is considered random.
+/// Short inputs can't reach 8 bits per byte, so the maximum depends on the length.
+const RANDOM_THRESHOLD: f64 = 0.9;
+
+/// Classify the data by its entropy and the proportion of printable characters
+pub fn classify(data: &[u8]) -> DataClass {
+    if data.is_empty() {
+        return DataClass::Empty;
+    }
+    let printable = data
+        .iter()
+        .filter(|b| b.is_ascii_graphic() || b.is_ascii_whitespace())
+        .count();
+    if printable as f64 / data.len() as f64 > 0.95 {
+        return DataClass::Text;
+    }
+    let max_entropy = (data.len().min(256) as f64).log2();
+    if max_entropy > 0.0 && shannon_entropy(data) / max_entropy >= RANDOM_THRESHOLD {
+        DataClass::Random
+    } else {
+        DataClass::Structured
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entropy_bounds() {
+        assert_eq!(shannon_entropy(&[]), 0.0);
+        assert_eq!(shannon_entropy(&[42; 100]), 0.0);
+        let all_bytes: Vec<u8> = (0..=255).collect();
+        assert!((shannon_entropy(&all_bytes) - 8.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_classify() {
+        assert_eq!(classify(b""), DataClass::Empty);
+        assert_eq!(
+            classify(b"This is where your secret message will be!"),
+            DataClass::Text
+        );
+        assert_eq!(
+            classify(&[0, 0, 0, 13, 1, 0, 0, 0, 0, 2]),
+            DataClass::Structured
+        );
+
+        // A xorshift stream stands in for ciphertext
+        let mut state: u32 = 2463534242;
+        let random: Vec<u8> = (0..4096)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect();
+        assert_eq!(classify(&random), DataClass::Random);
+    }
+}
@@ -0,0 +1,381 @@
+use crate::crypto;
+use crate::error::{error, ErrorKind};
+use crate::{PngError, PngResult};
+use std::ops::BitOr;
+use std::path::Path;
+
+/// Bit flags describing how the body of an `Envelope` has been transformed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Flags(u8);
+
+impl Flags {
+    pub const NONE: Flags = Flags(0);
+    /// The body is sealed with a passphrase, see `crypto::seal`
+    pub const ENCRYPTED: Flags = Flags(1);
+    /// The body was deflated with zlib before any encryption
+    pub const ZLIB: Flags = Flags(1 << 1);
+    /// The body was compressed with zstd before any encryption
+    pub const ZSTD: Flags = Flags(1 << 2);
+    /// An Ed25519 signature over the rest of the envelope follows the body
+    pub const SIGNED: Flags = Flags(1 << 3);
+    /// The body is encrypted to one or more X25519 recipients, see `recipients::encrypt`
+    pub const RECIPIENTS: Flags = Flags(1 << 4);
+    /// The MIME type and filename are stored at the start of the body rather than in the
+    /// header, so that encrypting the body hides them too, see `Envelope::seal_metadata`
+    pub const SEALED_METADATA: Flags = Flags(1 << 5);
+
+    /// Returns the raw flag byte
+    pub fn bits(&self) -> u8 {
+        self.0
+    }
+
+    /// Returns whether all the flags in `other` are set
+    pub fn contains(&self, other: Flags) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Set or clear the flags in `other`
+    pub fn set(&mut self, other: Flags, value: bool) {
+        if value {
+            self.0 |= other.0
+        } else {
+            self.0 &= !other.0
+        }
+    }
+}
+
+impl BitOr for Flags {
+    type Output = Flags;
+
+    fn bitor(self, rhs: Self) -> Self::Output {
+        Flags(self.0 | rhs.0)
+    }
+}
+
+/// A small versioned header written ahead of the user payload inside a chunk.
+///
+/// The on-disk layout is:
+///
+/// ```text
+/// magic (4) | version (1) | flags (1) | mime length (1) | mime | name length (2) | name |
+/// body length (4) | body | [signature (64)]
+/// ```
+///
+/// All integers are big endian. An empty name means the payload has no original filename.
+/// The signature is only present when the `SIGNED` flag is set. With `SEALED_METADATA` set the
+/// header's mime and name are empty and the body starts with them instead, laid out the same
+/// way: `mime length (1) | mime | name length (2) | name`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Envelope {
+    flags: Flags,
+    mime_type: String,
+    filename: Option<String>,
+    body: Vec<u8>,
+    signature: Option<[u8; Envelope::SIGNATURE_LEN]>,
+}
+
+impl Envelope {
+    /// Marks the start of an enveloped payload
+    pub const MAGIC: [u8; 4] = *b"pmiE";
+
+    /// The envelope layout version written by this build
+    pub const VERSION: u8 = 1;
+
+    /// The MIME type used for plain messages
+    pub const TEXT_MIME: &'static str = "text/plain; charset=utf-8";
+
+    /// The MIME type used when nothing more specific is known
+    pub const BINARY_MIME: &'static str = "application/octet-stream";
+
+    /// The length of the trailing Ed25519 signature
+    pub const SIGNATURE_LEN: usize = 64;
+
+    /// Create a new envelope around the given body
+    pub fn new(mime_type: &str, filename: Option<&str>, body: Vec<u8>) -> Self {
+        Envelope {
+            flags: Flags::NONE,
+            mime_type: mime_type.to_string(),
+            filename: filename.map(str::to_string),
+            body,
+            signature: None,
+        }
+    }
+
+    /// Create a new envelope holding a UTF-8 text message
+    pub fn text(message: &str) -> Self {
+        Self::new(Self::TEXT_MIME, None, message.as_bytes().to_vec())
+    }
+
+    /// Interpret the data of a chunk as an envelope.
+    /// Payloads written before the envelope existed don't start with `MAGIC`; these are
+    /// surfaced as a text envelope (flagged as encrypted if they are a sealed payload) so that
+    /// callers don't need to special case them.
+    pub fn from_payload(data: &[u8]) -> PngResult<Self> {
+        if data.starts_with(&Self::MAGIC) {
+            return Envelope::try_from(data);
+        }
+        let mut envelope = Envelope::new(Self::TEXT_MIME, None, data.to_vec());
+        envelope
+            .flags
+            .set(Flags::ENCRYPTED, crypto::is_sealed(data));
+        Ok(envelope)
+    }
+
+    /// The flags describing how the body has been transformed
+    pub fn flags(&self) -> Flags {
+        self.flags
+    }
+
+    /// Mutable access to the flags, used when transforming the body
+    pub fn flags_mut(&mut self) -> &mut Flags {
+        &mut self.flags
+    }
+
+    /// The MIME type of the original payload
+    pub fn mime_type(&self) -> &str {
+        &self.mime_type
+    }
+
+    /// The name of the file the payload originally came from, if any
+    pub fn filename(&self) -> Option<&str> {
+        self.filename.as_deref()
+    }
+
+    /// The (possibly transformed) payload held in this envelope
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    /// Replace the body, e.g. after encrypting or decrypting it
+    pub fn set_body(&mut self, body: Vec<u8>) {
+        self.body = body
+    }
+
+    /// Consume the envelope returning its body
+    pub fn into_body(self) -> Vec<u8> {
+        self.body
+    }
+
+    /// The signature trailing the envelope, if it was signed
+    pub fn signature(&self) -> Option<&[u8; Envelope::SIGNATURE_LEN]> {
+        self.signature.as_ref()
+    }
+
+    /// Attach a signature produced over `signed_bytes`, or drop it with `None`
+    pub fn set_signature(&mut self, signature: Option<[u8; Envelope::SIGNATURE_LEN]>) {
+        self.flags.set(Flags::SIGNED, signature.is_some());
+        self.signature = signature
+    }
+
+    /// Move the MIME type and filename into the body, ahead of what's there, before the body is
+    /// encrypted. Otherwise they'd be left in the clear next to the ciphertext.
+    pub fn seal_metadata(&mut self) -> PngResult<()> {
+        let mut body = self.metadata_bytes()?;
+        body.append(&mut self.body);
+        self.body = body;
+        self.mime_type.clear();
+        self.filename = None;
+        self.flags.set(Flags::SEALED_METADATA, true);
+        Ok(())
+    }
+
+    /// Undo `seal_metadata` once the body has been decrypted; does nothing if it wasn't sealed
+    pub fn open_metadata(&mut self) -> PngResult<()> {
+        if !self.flags.contains(Flags::SEALED_METADATA) {
+            return Ok(());
+        }
+        let mut rest = &self.body[..];
+        let (mime_type, filename) = read_metadata(&mut rest)?;
+        self.body = rest.to_vec();
+        self.mime_type = mime_type;
+        self.filename = filename;
+        self.flags.set(Flags::SEALED_METADATA, false);
+        Ok(())
+    }
+
+    /// Returns whether the payload is text, judging by its MIME type
+    pub fn is_text(&self) -> bool {
+        self.mime_type.starts_with("text/")
+    }
+
+    /// Returns the byte representation for this envelope
+    pub fn as_bytes(&self) -> PngResult<Vec<u8>> {
+        let mut bytes = self.signed_bytes()?;
+        if let Some(signature) = &self.signature {
+            bytes.extend_from_slice(signature);
+        }
+        Ok(bytes)
+    }
+
+    /// Returns the bytes covered by the signature: everything but the signature itself
+    pub fn signed_bytes(&self) -> PngResult<Vec<u8>> {
+        let body_len = u32::try_from(self.body.len()).map_err(|_| "Payload is too large")?;
+
+        let mut bytes = Self::MAGIC.to_vec();
+        bytes.push(Self::VERSION);
+        bytes.push(self.flags.bits());
+        bytes.extend_from_slice(&self.metadata_bytes()?);
+        bytes.extend_from_slice(&body_len.to_be_bytes());
+        bytes.extend_from_slice(&self.body);
+        Ok(bytes)
+    }
+
+    /// The MIME type and filename, each preceded by its length
+    fn metadata_bytes(&self) -> PngResult<Vec<u8>> {
+        let mime = self.mime_type.as_bytes();
+        let name = self.filename.as_deref().unwrap_or_default().as_bytes();
+        let mime_len = u8::try_from(mime.len()).map_err(|_| "MIME type is too long")?;
+        let name_len = u16::try_from(name.len()).map_err(|_| "Filename is too long")?;
+
+        let mut bytes = vec![mime_len];
+        bytes.extend_from_slice(mime);
+        bytes.extend_from_slice(&name_len.to_be_bytes());
+        bytes.extend_from_slice(name);
+        Ok(bytes)
+    }
+}
+
+/// Guess the MIME type of a file from its extension, falling back to `Envelope::BINARY_MIME`
+pub fn guess_mime_type(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_ascii_lowercase);
+    match extension.as_deref() {
+        Some("txt") | Some("md") => Envelope::TEXT_MIME,
+        Some("json") => "application/json",
+        Some("pdf") => "application/pdf",
+        Some("zip") => "application/zip",
+        Some("gz") => "application/gzip",
+        Some("png") => "image/png",
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        _ => Envelope::BINARY_MIME,
+    }
+}
+
+/// Split `len` bytes off the front of `value`, failing if there aren't enough
+fn take<'a>(value: &mut &'a [u8], len: usize) -> PngResult<&'a [u8]> {
+    if value.len() < len {
+        return Err(error(ErrorKind::Parse, "Truncated payload envelope"));
+    }
+    let (head, tail) = value.split_at(len);
+    *value = tail;
+    Ok(head)
+}
+
+/// Read a MIME type and filename as `Envelope::metadata_bytes` writes them
+fn read_metadata(value: &mut &[u8]) -> PngResult<(String, Option<String>)> {
+    let mime_len = take(value, 1)?[0] as usize;
+    let mime_type = String::from_utf8(take(value, mime_len)?.to_vec())?;
+    let name_len = u16::from_be_bytes(take(value, 2)?.try_into()?) as usize;
+    let filename = String::from_utf8(take(value, name_len)?.to_vec())?;
+    Ok((mime_type, (!filename.is_empty()).then_some(filename)))
+}
+
+impl TryFrom<&[u8]> for Envelope {
+    type Error = PngError;
+
+    /// Attempt to parse an envelope out of the given chunk data
+    fn try_from(value: &[u8]) -> PngResult<Self> {
+        let mut rest = value;
+        if take(&mut rest, Self::MAGIC.len())? != Self::MAGIC {
+            return Err(error(ErrorKind::Parse, "Payload is not enveloped"));
+        }
+        let version = take(&mut rest, 1)?[0];
+        if version != Self::VERSION {
+            return Err(error(
+                ErrorKind::Parse,
+                format!("Unsupported payload envelope version {}", version),
+            ));
+        }
+        let flags = Flags(take(&mut rest, 1)?[0]);
+        let (mime_type, filename) = read_metadata(&mut rest)?;
+        let body_len = u32::from_be_bytes(take(&mut rest, 4)?.try_into()?) as usize;
+        let body = take(&mut rest, body_len)?.to_vec();
+        let signature = if flags.contains(Flags::SIGNED) {
+            Some(take(&mut rest, Self::SIGNATURE_LEN)?.try_into()?)
+        } else {
+            None
+        };
+        if !rest.is_empty() {
+            return Err(error(
+                ErrorKind::Parse,
+                "Unexpected trailing bytes after payload envelope",
+            ));
+        }
+
+        Ok(Envelope {
+            flags,
+            mime_type,
+            filename,
+            body,
+            signature,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_envelope_round_trip() {
+        let mut envelope = Envelope::new("application/pdf", Some("secret.pdf"), vec![1, 2, 3]);
+        envelope.flags_mut().set(Flags::ENCRYPTED, true);
+        let bytes = envelope.as_bytes().unwrap();
+        assert!(bytes.starts_with(&Envelope::MAGIC));
+
+        let parsed = Envelope::from_payload(&bytes).unwrap();
+        assert_eq!(parsed, envelope);
+        assert_eq!(parsed.filename(), Some("secret.pdf"));
+        assert!(parsed.flags().contains(Flags::ENCRYPTED));
+        assert!(!parsed.is_text());
+    }
+
+    #[test]
+    fn test_sealed_metadata() {
+        let original = Envelope::new("application/pdf", Some("secret.pdf"), vec![1, 2, 3]);
+        let mut envelope = original.clone();
+        envelope.seal_metadata().unwrap();
+        let bytes = envelope.as_bytes().unwrap();
+
+        let mut parsed = Envelope::from_payload(&bytes).unwrap();
+        assert_eq!(parsed.mime_type(), "");
+        assert_eq!(parsed.filename(), None);
+        assert!(parsed.flags().contains(Flags::SEALED_METADATA));
+        parsed.open_metadata().unwrap();
+        assert_eq!(parsed, original);
+    }
+
+    #[test]
+    fn test_text_envelope() {
+        let envelope = Envelope::text("hello");
+        let parsed = Envelope::try_from(envelope.as_bytes().unwrap().as_slice()).unwrap();
+        assert!(parsed.is_text());
+        assert_eq!(parsed.filename(), None);
+        assert_eq!(parsed.body(), b"hello");
+    }
+
+    #[test]
+    fn test_legacy_payload() {
+        let envelope = Envelope::from_payload(b"This is a legacy message").unwrap();
+        assert!(envelope.is_text());
+        assert_eq!(envelope.flags(), Flags::NONE);
+        assert_eq!(envelope.body(), b"This is a legacy message");
+    }
+
+    #[test]
+    fn test_guess_mime_type() {
+        assert_eq!(guess_mime_type(Path::new("secret.PDF")), "application/pdf");
+        assert_eq!(guess_mime_type(Path::new("notes.txt")), Envelope::TEXT_MIME);
+        assert_eq!(guess_mime_type(Path::new("blob")), Envelope::BINARY_MIME);
+    }
+
+    #[test]
+    fn test_truncated_envelope() {
+        let bytes = Envelope::text("hello").as_bytes().unwrap();
+        let envelope = Envelope::from_payload(&bytes[..bytes.len() - 1]);
+        assert!(envelope.is_err());
+    }
+}
@@ -0,0 +1,115 @@
+use crate::PngError;
+use std::fmt::{Display, Formatter};
+use std::io;
+
+/// Broad classes of failure, so that callers can tell them apart without matching on messages
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    /// The input isn't a well-formed PNG or payload
+    Parse,
+    /// A chunk the operation needs isn't in the file
+    ChunkNotFound,
+    /// A CRC mismatch, or a file which breaks the PNG spec
+    Validation,
+    /// Decryption, key or signature verification failed
+    Crypto,
+    /// Reading or writing a file failed
+    Io,
+    /// Anything else, such as bad arguments
+    Other,
+}
+
+impl ErrorKind {
+    /// The kind shared by all of the given kinds, or `Other` if they differ or there are none
+    pub fn common(kinds: impl IntoIterator<Item = ErrorKind>) -> ErrorKind {
+        let mut kinds = kinds.into_iter();
+        match kinds.next() {
+            Some(first) if kinds.all(|kind| kind == first) => first,
+            _ => ErrorKind::Other,
+        }
+    }
+}
+
+/// An error tagged with the kind of failure it is
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Error {
+    kind: ErrorKind,
+    message: String,
+}
+
+impl Error {
+    pub fn new(kind: ErrorKind, message: impl Into<String>) -> Self {
+        Error {
+            kind,
+            message: message.into(),
+        }
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// A boxed error of the given kind
+pub fn error(kind: ErrorKind, message: impl Into<String>) -> PngError {
+    Box::new(Error::new(kind, message))
+}
+
+/// Tag an error with the given kind, unless it already has one
+pub fn with_kind(kind: ErrorKind, error: PngError) -> PngError {
+    match error.downcast_ref::<Error>() {
+        Some(_) => error,
+        None => Box::new(Error::new(kind, error.to_string())),
+    }
+}
+
+/// The kind of the error, treating I/O errors as `Io` and untagged errors as `Other`
+pub fn kind_of(error: &(dyn std::error::Error + 'static)) -> ErrorKind {
+    if let Some(error) = error.downcast_ref::<Error>() {
+        error.kind
+    } else if error.is::<io::Error>() {
+        ErrorKind::Io
+    } else {
+        ErrorKind::Other
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kind_of() {
+        let parse = error(ErrorKind::Parse, "Invalid header found");
+        assert_eq!(parse.to_string(), "Invalid header found");
+        assert_eq!(kind_of(parse.as_ref()), ErrorKind::Parse);
+
+        let io: PngError = io::Error::new(io::ErrorKind::NotFound, "missing").into();
+        assert_eq!(kind_of(io.as_ref()), ErrorKind::Io);
+        let other: PngError = "something else".into();
+        assert_eq!(kind_of(other.as_ref()), ErrorKind::Other);
+
+        assert_eq!(
+            kind_of(with_kind(ErrorKind::Crypto, other).as_ref()),
+            ErrorKind::Crypto
+        );
+        let parse = with_kind(ErrorKind::Crypto, parse);
+        assert_eq!(kind_of(parse.as_ref()), ErrorKind::Parse);
+    }
+
+    #[test]
+    fn test_common_kind() {
+        let common = |kinds: &[ErrorKind]| ErrorKind::common(kinds.iter().copied());
+        assert_eq!(common(&[ErrorKind::Io, ErrorKind::Io]), ErrorKind::Io);
+        assert_eq!(common(&[ErrorKind::Io, ErrorKind::Parse]), ErrorKind::Other);
+        assert_eq!(common(&[]), ErrorKind::Other);
+    }
+}
@@ -0,0 +1,454 @@
+use crate::PngResult;
+use std::fmt::{Display, Formatter};
+
+/// The chunk type EXIF data is stored under
+pub const EXIF_CHUNK_TYPE: &str = "eXIf";
+
+/// Tags pointing to the EXIF and GPS sub-IFDs from the primary IFD
+const EXIF_IFD_TAG: u16 = 0x8769;
+const GPS_IFD_TAG: u16 = 0x8825;
+
+/// Which image file directory a tag was found in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ifd {
+    /// IFD0, describing the main image and the camera
+    Primary,
+    /// The EXIF sub-IFD with capture settings and timestamps
+    Exif,
+    /// The GPS sub-IFD
+    Gps,
+}
+
+impl Display for Ifd {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Ifd::Primary => "IFD0",
+            Ifd::Exif => "EXIF",
+            Ifd::Gps => "GPS",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// The name of a commonly used tag, if it's one we know about
+pub fn tag_name(ifd: Ifd, tag: u16) -> Option<&'static str> {
+    let name = match (ifd, tag) {
+        (Ifd::Gps, 0x0000) => "GPSVersionID",
+        (Ifd::Gps, 0x0001) => "GPSLatitudeRef",
+        (Ifd::Gps, 0x0002) => "GPSLatitude",
+        (Ifd::Gps, 0x0003) => "GPSLongitudeRef",
+        (Ifd::Gps, 0x0004) => "GPSLongitude",
+        (Ifd::Gps, 0x0005) => "GPSAltitudeRef",
+        (Ifd::Gps, 0x0006) => "GPSAltitude",
+        (Ifd::Gps, 0x0007) => "GPSTimeStamp",
+        (Ifd::Gps, 0x001D) => "GPSDateStamp",
+        (Ifd::Gps, _) => return None,
+        (_, 0x010E) => "ImageDescription",
+        (_, 0x010F) => "Make",
+        (_, 0x0110) => "Model",
+        (_, 0x0112) => "Orientation",
+        (_, 0x0131) => "Software",
+        (_, 0x0132) => "DateTime",
+        (_, 0x013B) => "Artist",
+        (_, 0x8298) => "Copyright",
+        (_, 0x829A) => "ExposureTime",
+        (_, 0x829D) => "FNumber",
+        (_, 0x8827) => "ISOSpeedRatings",
+        (_, 0x9003) => "DateTimeOriginal",
+        (_, 0x9004) => "DateTimeDigitized",
+        (_, 0x9010) => "OffsetTime",
+        (_, 0x920A) => "FocalLength",
+        (_, 0xA431) => "BodySerialNumber",
+        (_, 0xA433) => "LensMake",
+        (_, 0xA434) => "LensModel",
+        _ => return None,
+    };
+    Some(name)
+}
+
+/// A decoded tag value
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExifValue {
+    Ascii(String),
+    Bytes(Vec<u8>),
+    Short(Vec<u16>),
+    Long(Vec<u32>),
+    Rational(Vec<(u32, u32)>),
+    SignedRational(Vec<(i32, i32)>),
+    /// A value of a type this reader doesn't decode
+    Unsupported {
+        field_type: u16,
+        count: u32,
+    },
+}
+
+impl Display for ExifValue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        fn list<T: Display>(values: &[T]) -> String {
+            values
+                .iter()
+                .map(T::to_string)
+                .collect::<Vec<_>>()
+                .join(" ")
+        }
+        match self {
+            ExifValue::Ascii(text) => write!(f, "{}", text),
+            ExifValue::Bytes(bytes) if bytes.len() > 16 => write!(f, "({} bytes)", bytes.len()),
+            ExifValue::Bytes(bytes) => write!(f, "{}", list(bytes)),
+            ExifValue::Short(values) => write!(f, "{}", list(values)),
+            ExifValue::Long(values) => write!(f, "{}", list(values)),
+            ExifValue::Rational(values) => {
+                let values: Vec<String> =
+                    values.iter().map(|(n, d)| format!("{}/{}", n, d)).collect();
+                write!(f, "{}", values.join(" "))
+            }
+            ExifValue::SignedRational(values) => {
+                let values: Vec<String> =
+                    values.iter().map(|(n, d)| format!("{}/{}", n, d)).collect();
+                write!(f, "{}", values.join(" "))
+            }
+            ExifValue::Unsupported { field_type, count } => {
+                write!(f, "({} values of type {})", count, field_type)
+            }
+        }
+    }
+}
+
+/// A single tag read from the EXIF data
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExifEntry {
+    pub ifd: Ifd,
+    pub tag: u16,
+    pub value: ExifValue,
+}
+
+impl ExifEntry {
+    pub fn name(&self) -> Option<&'static str> {
+        tag_name(self.ifd, self.tag)
+    }
+}
+
+impl Display for ExifEntry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.name() {
+            Some(name) => write!(f, "{}: {}", name, self.value),
+            None => write!(f, "{} tag {:#06x}: {}", self.ifd, self.tag, self.value),
+        }
+    }
+}
+
+/// An IFD entry before its value is decoded
+struct RawEntry {
+    /// Where the entry starts
+    offset: usize,
+    tag: u16,
+    field_type: u16,
+    count: u32,
+}
+
+/// Bounds-checked reads from TIFF data in either byte order
+struct Tiff<'a> {
+    data: &'a [u8],
+    little_endian: bool,
+}
+
+impl<'a> Tiff<'a> {
+    fn new(data: &'a [u8]) -> PngResult<Self> {
+        let little_endian = match data.get(0..4) {
+            Some(b"II*\0") => true,
+            Some(b"MM\0*") => false,
+            _ => return Err("EXIF data doesn't start with a TIFF header".into()),
+        };
+        Ok(Tiff {
+            data,
+            little_endian,
+        })
+    }
+
+    fn bytes(&self, offset: usize, len: usize) -> PngResult<&'a [u8]> {
+        offset
+            .checked_add(len)
+            .and_then(|end| self.data.get(offset..end))
+            .ok_or_else(|| format!("EXIF data is truncated at offset {}", offset).into())
+    }
+
+    fn u16(&self, offset: usize) -> PngResult<u16> {
+        let bytes = self.bytes(offset, 2)?.try_into()?;
+        Ok(if self.little_endian {
+            u16::from_le_bytes(bytes)
+        } else {
+            u16::from_be_bytes(bytes)
+        })
+    }
+
+    fn u32(&self, offset: usize) -> PngResult<u32> {
+        let bytes = self.bytes(offset, 4)?.try_into()?;
+        Ok(if self.little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        })
+    }
+
+    /// The offset of IFD0, from the TIFF header
+    fn first_ifd(&self) -> PngResult<usize> {
+        Ok(self.u32(4)? as usize)
+    }
+
+    fn entries(&self, ifd_offset: usize) -> PngResult<Vec<RawEntry>> {
+        let count = self.u16(ifd_offset)? as usize;
+        (0..count)
+            .map(|index| {
+                let offset = ifd_offset + 2 + index * 12;
+                Ok(RawEntry {
+                    offset,
+                    tag: self.u16(offset)?,
+                    field_type: self.u16(offset + 2)?,
+                    count: self.u32(offset + 4)?,
+                })
+            })
+            .collect()
+    }
+
+    /// Where the value of an entry is stored and how many bytes it takes up, or None for
+    /// types this reader doesn't know the size of
+    fn value_location(&self, entry: &RawEntry) -> PngResult<Option<(usize, usize)>> {
+        let size = match entry.field_type {
+            1 | 2 | 6 | 7 => 1,
+            3 | 8 => 2,
+            4 | 9 | 11 => 4,
+            5 | 10 | 12 => 8,
+            _ => return Ok(None),
+        };
+        let len = size * entry.count as usize;
+        let offset = if len <= 4 {
+            entry.offset + 8
+        } else {
+            self.u32(entry.offset + 8)? as usize
+        };
+        self.bytes(offset, len)?;
+        Ok(Some((offset, len)))
+    }
+
+    fn value(&self, entry: &RawEntry) -> PngResult<ExifValue> {
+        let Some((offset, len)) = self.value_location(entry)? else {
+            return Ok(ExifValue::Unsupported {
+                field_type: entry.field_type,
+                count: entry.count,
+            });
+        };
+        let count = entry.count as usize;
+        let value = match entry.field_type {
+            2 => {
+                let bytes = self.bytes(offset, len)?;
+                let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+                ExifValue::Ascii(
+                    String::from_utf8_lossy(&bytes[..end])
+                        .trim_end()
+                        .to_string(),
+                )
+            }
+            1 | 7 => ExifValue::Bytes(self.bytes(offset, len)?.to_vec()),
+            3 => ExifValue::Short(
+                (0..count)
+                    .map(|i| self.u16(offset + i * 2))
+                    .collect::<PngResult<_>>()?,
+            ),
+            4 => ExifValue::Long(
+                (0..count)
+                    .map(|i| self.u32(offset + i * 4))
+                    .collect::<PngResult<_>>()?,
+            ),
+            5 => ExifValue::Rational(
+                (0..count)
+                    .map(|i| Ok((self.u32(offset + i * 8)?, self.u32(offset + i * 8 + 4)?)))
+                    .collect::<PngResult<_>>()?,
+            ),
+            10 => ExifValue::SignedRational(
+                (0..count)
+                    .map(|i| {
+                        let numerator = self.u32(offset + i * 8)? as i32;
+                        Ok((numerator, self.u32(offset + i * 8 + 4)? as i32))
+                    })
+                    .collect::<PngResult<_>>()?,
+            ),
+            field_type => ExifValue::Unsupported {
+                field_type,
+                count: entry.count,
+            },
+        };
+        Ok(value)
+    }
+
+    /// The offset of the sub-IFD the primary IFD points to with the given tag
+    fn sub_ifd(&self, tag: u16) -> PngResult<Option<usize>> {
+        let pointer = self
+            .entries(self.first_ifd()?)?
+            .into_iter()
+            .find(|entry| entry.tag == tag);
+        match pointer {
+            Some(entry) => Ok(Some(self.u32(entry.offset + 8)? as usize)),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Read the tags of the primary, EXIF and GPS IFDs from the data of an eXIf chunk.
+/// Thumbnail IFDs and maker notes are ignored.
+pub fn read_exif(data: &[u8]) -> PngResult<Vec<ExifEntry>> {
+    let tiff = Tiff::new(data)?;
+    let mut ifds = vec![(Ifd::Primary, tiff.first_ifd()?)];
+    for (ifd, tag) in [(Ifd::Exif, EXIF_IFD_TAG), (Ifd::Gps, GPS_IFD_TAG)] {
+        if let Some(offset) = tiff.sub_ifd(tag)? {
+            ifds.push((ifd, offset));
+        }
+    }
+
+    let mut entries = vec![];
+    for (ifd, offset) in ifds {
+        for entry in tiff.entries(offset)? {
+            if ifd == Ifd::Primary && (entry.tag == EXIF_IFD_TAG || entry.tag == GPS_IFD_TAG) {
+                continue;
+            }
+            entries.push(ExifEntry {
+                ifd,
+                tag: entry.tag,
+                value: tiff.value(&entry)?,
+            });
+        }
+    }
+    Ok(entries)
+}
+
+/// Convert GPS coordinates to signed decimal degrees, if both are present
+pub fn gps_position(entries: &[ExifEntry]) -> Option<(f64, f64)> {
+    let find = |tag: u16| {
+        entries
+            .iter()
+            .find(|entry| entry.ifd == Ifd::Gps && entry.tag == tag)
+            .map(|entry| &entry.value)
+    };
+    let coordinate = |reference_tag: u16, tag: u16, negative: &str| {
+        let ExifValue::Rational(parts) = find(tag)? else {
+            return None;
+        };
+        let degrees: f64 = parts
+            .iter()
+            .zip([1.0, 60.0, 3600.0])
+            .map(|((numerator, denominator), scale)| {
+                *numerator as f64 / *denominator as f64 / scale
+            })
+            .sum();
+        match find(reference_tag) {
+            Some(ExifValue::Ascii(reference)) if reference == negative => Some(-degrees),
+            _ => Some(degrees),
+        }
+    };
+    Some((coordinate(1, 2, "S")?, coordinate(3, 4, "W")?))
+}
+
+/// Blank out the GPS IFD of the EXIF data in place, leaving every other tag untouched.
+/// The GPS IFD is left empty rather than removed so no offsets need to change.
+/// Returns whether there was any GPS data.
+pub fn remove_gps(data: &mut [u8]) -> PngResult<bool> {
+    let tiff = Tiff::new(data)?;
+    let Some(offset) = tiff.sub_ifd(GPS_IFD_TAG)? else {
+        return Ok(false);
+    };
+    let entries = tiff.entries(offset)?;
+    if entries.is_empty() {
+        return Ok(false);
+    }
+    let ifd_len = 2 + entries.len() * 12 + 4;
+    tiff.bytes(offset, ifd_len)?;
+    let mut blank = vec![(offset, ifd_len)];
+    for entry in &entries {
+        if let Some(location) = tiff.value_location(entry)? {
+            blank.push(location);
+        }
+    }
+    for (start, len) in blank {
+        data[start..start + len].fill(0);
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Build big-endian EXIF data with a Make tag, a DateTimeOriginal tag in the EXIF IFD and
+    /// a GPS position of 51°30'0" N, 0°7'30" W
+    fn testing_exif() -> Vec<u8> {
+        let mut data = b"MM\0*\0\0\0\x08".to_vec();
+        let entry = |data: &mut Vec<u8>, tag: u16, field_type: u16, count: u32, value: u32| {
+            data.extend_from_slice(&tag.to_be_bytes());
+            data.extend_from_slice(&field_type.to_be_bytes());
+            data.extend_from_slice(&count.to_be_bytes());
+            data.extend_from_slice(&value.to_be_bytes());
+        };
+        // IFD0 at 8: 3 entries, ends at 8 + 2 + 36 + 4 = 50
+        data.extend_from_slice(&3u16.to_be_bytes());
+        entry(&mut data, 0x010F, 2, 4, u32::from_be_bytes(*b"ACM\0"));
+        entry(&mut data, EXIF_IFD_TAG, 4, 1, 50);
+        entry(&mut data, GPS_IFD_TAG, 4, 1, 88);
+        data.extend_from_slice(&[0; 4]);
+        // EXIF IFD at 50: 1 entry whose 20 byte value sits at 68
+        data.extend_from_slice(&1u16.to_be_bytes());
+        entry(&mut data, 0x9003, 2, 20, 68);
+        data.extend_from_slice(&[0; 4]);
+        data.extend_from_slice(b"2024:01:02 03:04:05\0");
+        // GPS IFD at 88: 4 entries ending at 88 + 2 + 48 + 4 = 142, then the rationals
+        data.extend_from_slice(&4u16.to_be_bytes());
+        entry(&mut data, 1, 2, 2, u32::from_be_bytes(*b"N\0\0\0"));
+        entry(&mut data, 2, 5, 3, 142);
+        entry(&mut data, 3, 2, 2, u32::from_be_bytes(*b"W\0\0\0"));
+        entry(&mut data, 4, 5, 3, 166);
+        data.extend_from_slice(&[0; 4]);
+        for value in [51, 1, 30, 1, 0, 1, 0, 1, 7, 1, 30, 1] {
+            data.extend_from_slice(&(value as u32).to_be_bytes());
+        }
+        data
+    }
+
+    #[test]
+    fn test_read_exif() {
+        let entries = read_exif(&testing_exif()).unwrap();
+        let lines: Vec<String> = entries.iter().map(ExifEntry::to_string).collect();
+        assert_eq!(
+            lines,
+            vec![
+                "Make: ACM",
+                "DateTimeOriginal: 2024:01:02 03:04:05",
+                "GPSLatitudeRef: N",
+                "GPSLatitude: 51/1 30/1 0/1",
+                "GPSLongitudeRef: W",
+                "GPSLongitude: 0/1 7/1 30/1",
+            ]
+        );
+        let (latitude, longitude) = gps_position(&entries).unwrap();
+        assert!((latitude - 51.5).abs() < 1e-9);
+        assert!((longitude + 0.125).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_remove_gps() {
+        let mut data = testing_exif();
+        let len = data.len();
+        assert!(remove_gps(&mut data).unwrap());
+        assert_eq!(data.len(), len);
+        assert!(data[88..].iter().all(|b| *b == 0));
+
+        let entries = read_exif(&data).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert!(entries.iter().all(|entry| entry.ifd != Ifd::Gps));
+        assert!(!remove_gps(&mut data).unwrap());
+    }
+
+    #[test]
+    fn test_invalid_exif() {
+        assert!(read_exif(b"Exif\0\0").is_err());
+        let data = testing_exif();
+        assert!(read_exif(&data[..100]).is_err());
+    }
+}
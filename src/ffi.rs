@@ -0,0 +1,359 @@
+use crate::chunk_type::ChunkType;
+use crate::envelope::Envelope;
+use crate::message;
+use crate::png::Png;
+use crate::PngResult;
+use std::cell::RefCell;
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+use std::str::FromStr;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// A PNG parsed from a buffer, owned by the caller until passed to `pngmi_png_free`
+pub struct PngmiPng {
+    png: Png,
+}
+
+/// A chunk of a `PngmiPng`, borrowing its data from it
+#[repr(C)]
+pub struct PngmiChunk {
+    /// The chunk type, e.g. `IHDR`, NUL-terminated
+    pub chunk_type: [c_char; 5],
+    pub data: *const u8,
+    pub len: usize,
+    pub crc: u32,
+}
+
+/// Bytes allocated by pngmi, which the caller frees with `pngmi_buffer_free`
+#[repr(C)]
+pub struct PngmiBuffer {
+    pub data: *mut u8,
+    pub len: usize,
+}
+
+impl PngmiBuffer {
+    fn new(bytes: Vec<u8>) -> Self {
+        let bytes = Box::into_raw(bytes.into_boxed_slice());
+        PngmiBuffer {
+            data: bytes as *mut u8,
+            len: bytes.len(),
+        }
+    }
+}
+
+/// Run the body of an exported function, recording any error or panic for
+/// `pngmi_last_error` and returning `None` in its place
+fn call<T>(body: impl FnOnce() -> PngResult<T>) -> Option<T> {
+    let result = match panic::catch_unwind(AssertUnwindSafe(body)) {
+        Ok(result) => result,
+        Err(_) => Err("pngmi panicked".into()),
+    };
+    let (value, error) = match result {
+        Ok(value) => (Some(value), None),
+        Err(e) => (None, Some(e.to_string())),
+    };
+    let error = error.map(|e| CString::new(e.replace('\0', " ")).unwrap_or_default());
+    LAST_ERROR.with(|last| *last.borrow_mut() = error);
+    value
+}
+
+fn status(result: Option<()>) -> c_int {
+    match result {
+        Some(()) => 0,
+        None => -1,
+    }
+}
+
+/// Borrow the handle, failing on a null pointer
+unsafe fn handle<'a>(png: *const PngmiPng) -> PngResult<&'a PngmiPng> {
+    png.as_ref().ok_or_else(|| "Null PNG handle".into())
+}
+
+unsafe fn string<'a>(text: *const c_char, name: &str) -> PngResult<&'a str> {
+    optional_string(text)?.ok_or_else(|| format!("Null {}", name).into())
+}
+
+/// A string argument which may be null, such as a passphrase left out
+unsafe fn optional_string<'a>(text: *const c_char) -> PngResult<Option<&'a str>> {
+    match text.is_null() {
+        true => Ok(None),
+        false => Ok(Some(CStr::from_ptr(text).to_str()?)),
+    }
+}
+
+/// The message of the last error on this thread, or null if the last call succeeded. The
+/// string stays valid until the next call into pngmi on the same thread.
+#[no_mangle]
+pub extern "C" fn pngmi_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| match &*last.borrow() {
+        Some(message) => message.as_ptr(),
+        None => ptr::null(),
+    })
+}
+
+/// Parse a PNG from `len` bytes at `data`, which are copied. Returns null on failure.
+///
+/// # Safety
+///
+/// `data` must point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn pngmi_png_open(data: *const u8, len: usize) -> *mut PngmiPng {
+    let png = call(|| {
+        if data.is_null() {
+            return Err("Null buffer".into());
+        }
+        Png::try_from(slice::from_raw_parts(data, len))
+    });
+    match png {
+        Some(png) => Box::into_raw(Box::new(PngmiPng { png })),
+        None => ptr::null_mut(),
+    }
+}
+
+/// Free a PNG opened by `pngmi_png_open`; null is ignored
+///
+/// # Safety
+///
+/// `png` must be null or a handle from `pngmi_png_open` which hasn't been freed.
+#[no_mangle]
+pub unsafe extern "C" fn pngmi_png_free(png: *mut PngmiPng) {
+    if !png.is_null() {
+        drop(Box::from_raw(png));
+    }
+}
+
+/// The number of chunks in the PNG, or 0 for a null handle
+///
+/// # Safety
+///
+/// `png` must be null or a live handle from `pngmi_png_open`.
+#[no_mangle]
+pub unsafe extern "C" fn pngmi_png_chunk_count(png: *const PngmiPng) -> usize {
+    call(|| Ok(handle(png)?.png.chunks().len())).unwrap_or(0)
+}
+
+/// Describe the chunk at `index` in `out`. Its data stays valid until the PNG is changed or
+/// freed. Returns 0, or -1 on failure.
+///
+/// # Safety
+///
+/// `png` must be a live handle from `pngmi_png_open` and `out` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn pngmi_png_chunk(
+    png: *const PngmiPng,
+    index: usize,
+    out: *mut PngmiChunk,
+) -> c_int {
+    status(call(|| {
+        let chunks = handle(png)?.png.chunks();
+        let chunk = chunks
+            .get(index)
+            .ok_or_else(|| format!("No chunk {}; there are {}", index, chunks.len()))?;
+        let out = out.as_mut().ok_or("Null chunk to fill")?;
+        let mut chunk_type = [0; 5];
+        for (to, from) in chunk_type.iter_mut().zip(chunk.chunk_type().bytes()) {
+            *to = from as c_char;
+        }
+        *out = PngmiChunk {
+            chunk_type,
+            data: chunk.data().as_ptr(),
+            len: chunk.data().len(),
+            crc: chunk.crc(),
+        };
+        Ok(())
+    }))
+}
+
+/// Encode `len` bytes at `data` into new chunks of type `chunk_type` before IEND, encrypted
+/// if `passphrase` isn't null. Returns the number of chunks added, or -1 on failure.
+///
+/// # Safety
+///
+/// `png` must be a live handle from `pngmi_png_open`, `chunk_type` and `passphrase` null or
+/// NUL-terminated strings and `data` must point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn pngmi_png_encode(
+    png: *mut PngmiPng,
+    chunk_type: *const c_char,
+    data: *const u8,
+    len: usize,
+    passphrase: *const c_char,
+) -> c_int {
+    let added = call(|| {
+        let png = png.as_mut().ok_or("Null PNG handle")?;
+        let chunk_type = ChunkType::from_str(string(chunk_type, "chunk type")?)?;
+        if data.is_null() {
+            return Err("Null payload".into());
+        }
+        let payload = slice::from_raw_parts(data, len).to_vec();
+        let envelope = Envelope::new(Envelope::BINARY_MIME, None, payload);
+        let added = message::embed_envelope(
+            &mut png.png,
+            chunk_type,
+            envelope,
+            optional_string(passphrase)?,
+        )?;
+        Ok(c_int::try_from(added)?)
+    });
+    added.unwrap_or(-1)
+}
+
+/// Decode the payload in chunks of type `chunk_type` into `out`, decrypting it if
+/// `passphrase` isn't null. Returns 0, or -1 on failure.
+///
+/// # Safety
+///
+/// `png` must be a live handle from `pngmi_png_open`, `chunk_type` and `passphrase` null or
+/// NUL-terminated strings and `out` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn pngmi_png_decode(
+    png: *const PngmiPng,
+    chunk_type: *const c_char,
+    passphrase: *const c_char,
+    out: *mut PngmiBuffer,
+) -> c_int {
+    status(call(|| {
+        let png = &handle(png)?.png;
+        let envelope = message::read(
+            png,
+            string(chunk_type, "chunk type")?,
+            optional_string(passphrase)?,
+        )?;
+        *out.as_mut().ok_or("Null buffer to fill")? = PngmiBuffer::new(envelope.into_body());
+        Ok(())
+    }))
+}
+
+/// Write the bytes of the PNG, with any changes made to it, into `out`. Returns 0, or -1 on
+/// failure.
+///
+/// # Safety
+///
+/// `png` must be a live handle from `pngmi_png_open` and `out` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn pngmi_png_to_bytes(png: *const PngmiPng, out: *mut PngmiBuffer) -> c_int {
+    status(call(|| {
+        let bytes = handle(png)?.png.as_bytes();
+        *out.as_mut().ok_or("Null buffer to fill")? = PngmiBuffer::new(bytes);
+        Ok(())
+    }))
+}
+
+/// Free the bytes of a buffer filled by pngmi, leaving it empty
+///
+/// # Safety
+///
+/// `buffer` must be null or a buffer filled by pngmi which hasn't been freed.
+#[no_mangle]
+pub unsafe extern "C" fn pngmi_buffer_free(buffer: *mut PngmiBuffer) {
+    let Some(buffer) = buffer.as_mut() else {
+        return;
+    };
+    if !buffer.data.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(
+            buffer.data,
+            buffer.len,
+        )));
+    }
+    buffer.data = ptr::null_mut();
+    buffer.len = 0;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::minimal_png;
+
+    fn last_error() -> Option<String> {
+        let error = pngmi_last_error();
+        (!error.is_null()).then(|| {
+            unsafe { CStr::from_ptr(error) }
+                .to_string_lossy()
+                .into_owned()
+        })
+    }
+
+    #[test]
+    fn test_header_up_to_date() {
+        let generated = include_str!(concat!(env!("OUT_DIR"), "/pngmi.h"));
+        assert!(
+            include_str!("../include/pngmi.h") == generated,
+            "include/pngmi.h is out of date; copy the header generated by build.rs over it from {}",
+            concat!(env!("OUT_DIR"), "/pngmi.h")
+        );
+    }
+
+    #[test]
+    fn test_chunks() {
+        let bytes = minimal_png().as_bytes();
+        unsafe {
+            let png = pngmi_png_open(bytes.as_ptr(), bytes.len());
+            assert!(!png.is_null());
+            assert_eq!(pngmi_png_chunk_count(png), 2);
+
+            let mut chunk = PngmiChunk {
+                chunk_type: [0; 5],
+                data: ptr::null(),
+                len: 0,
+                crc: 0,
+            };
+            assert_eq!(pngmi_png_chunk(png, 0, &mut chunk), 0);
+            assert_eq!(
+                CStr::from_ptr(chunk.chunk_type.as_ptr()).to_str(),
+                Ok("IHDR")
+            );
+            assert_eq!(chunk.len, 13);
+            assert_eq!(pngmi_png_chunk(png, 2, &mut chunk), -1);
+            assert_eq!(last_error().unwrap(), "No chunk 2; there are 2");
+            pngmi_png_free(png);
+
+            assert!(pngmi_png_open(bytes.as_ptr(), 3).is_null());
+            assert!(last_error().is_some());
+        }
+    }
+
+    #[test]
+    fn test_encode_decode() {
+        let bytes = minimal_png().as_bytes();
+        unsafe {
+            let png = pngmi_png_open(bytes.as_ptr(), bytes.len());
+            let payload = b"\x00\x01binary";
+            let added = pngmi_png_encode(
+                png,
+                c"ruSt".as_ptr(),
+                payload.as_ptr(),
+                payload.len(),
+                ptr::null(),
+            );
+            assert_eq!(added, 1);
+            assert!(last_error().is_none());
+
+            let mut out = PngmiBuffer {
+                data: ptr::null_mut(),
+                len: 0,
+            };
+            assert_eq!(pngmi_png_to_bytes(png, &mut out), 0);
+            let copy = pngmi_png_open(out.data, out.len);
+            pngmi_buffer_free(&mut out);
+            assert!(out.data.is_null());
+
+            assert_eq!(
+                pngmi_png_decode(copy, c"ruSt".as_ptr(), ptr::null(), &mut out),
+                0
+            );
+            assert_eq!(slice::from_raw_parts(out.data, out.len), payload);
+            pngmi_buffer_free(&mut out);
+            assert_eq!(
+                pngmi_png_decode(copy, c"noPe".as_ptr(), ptr::null(), &mut out),
+                -1
+            );
+            pngmi_png_free(copy);
+            pngmi_png_free(png);
+        }
+    }
+}
@@ -0,0 +1,156 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+use crate::{PngError, PngResult};
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// The gAMA value the spec says should accompany an sRGB chunk, i.e. 1/2.2
+pub const SRGB_GAMMA: u32 = 45455;
+
+/// The image gamma as stored in a gAMA chunk: the encoding exponent times 100000
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GamaChunk {
+    pub value: u32,
+}
+
+impl GamaChunk {
+    pub const CHUNK_TYPE: &'static str = "gAMA";
+
+    /// The length of the gAMA chunk data
+    pub const LEN: usize = 4;
+
+    /// The gAMA chunk for a decoding exponent such as 2.2, which PNG stores as its reciprocal
+    pub fn from_exponent(exponent: f64) -> PngResult<Self> {
+        let value = (100000.0 / exponent).round();
+        if !(1.0..=i32::MAX as f64).contains(&value) {
+            return Err(format!("Gamma exponent {} is out of range", exponent).into());
+        }
+        Ok(GamaChunk {
+            value: value as u32,
+        })
+    }
+
+    /// The encoding gamma, e.g. 0.45455
+    pub fn gamma(&self) -> f64 {
+        self.value as f64 / 100000.0
+    }
+
+    /// The decoding exponent, e.g. 2.2
+    pub fn exponent(&self) -> f64 {
+        100000.0 / self.value as f64
+    }
+
+    pub fn as_bytes(&self) -> [u8; Self::LEN] {
+        self.value.to_be_bytes()
+    }
+
+    pub fn to_chunk(&self) -> PngResult<Chunk> {
+        Ok(Chunk::new(
+            ChunkType::from_str(Self::CHUNK_TYPE)?,
+            self.as_bytes().to_vec(),
+        ))
+    }
+}
+
+impl TryFrom<&[u8]> for GamaChunk {
+    type Error = PngError;
+
+    /// Decode gAMA chunk data
+    fn try_from(value: &[u8]) -> PngResult<Self> {
+        let bytes = <[u8; Self::LEN]>::try_from(value).map_err(|_| {
+            format!(
+                "gAMA data is {} bytes long but should be {}",
+                value.len(),
+                Self::LEN
+            )
+        })?;
+        match u32::from_be_bytes(bytes) {
+            0 => Err("gAMA value is zero".into()),
+            value => Ok(GamaChunk { value }),
+        }
+    }
+}
+
+impl Display for GamaChunk {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:.5} (decoding exponent {:.2})",
+            self.gamma(),
+            self.exponent()
+        )
+    }
+}
+
+/// Decode the gAMA chunk of the PNG, if it has one
+pub fn get_gamma(png: &Png) -> PngResult<Option<GamaChunk>> {
+    png.chunk_by_type(GamaChunk::CHUNK_TYPE)
+        .map(|chunk| GamaChunk::try_from(chunk.data()))
+        .transpose()
+}
+
+/// Describe every way the sRGB, iCCP and gAMA chunks of the PNG contradict each other
+pub fn color_space_conflicts(png: &Png) -> PngResult<Vec<String>> {
+    let mut conflicts = vec![];
+    let srgb = png.chunk_by_type("sRGB").is_some();
+    if srgb && png.chunk_by_type("iCCP").is_some() {
+        conflicts.push("Both sRGB and iCCP chunks are present".to_string());
+    }
+    match get_gamma(png)? {
+        Some(gamma) if srgb && gamma.value != SRGB_GAMMA => conflicts.push(format!(
+            "gAMA value {} doesn't match the {} implied by the sRGB chunk",
+            gamma.value, SRGB_GAMMA
+        )),
+        _ => {}
+    }
+    Ok(conflicts)
+}
+
+/// Store the image gamma, replacing any existing gAMA chunk. Refuses files whose colour space
+/// is already ambiguous because they have both sRGB and iCCP chunks.
+pub fn set_gamma(png: &mut Png, gamma: GamaChunk) -> PngResult<()> {
+    if png.chunk_by_type("sRGB").is_some() && png.chunk_by_type("iCCP").is_some() {
+        return Err("Both sRGB and iCCP chunks are present; remove one first".into());
+    }
+    png.set_unique_chunk(gamma.to_chunk()?, &["PLTE", "IDAT", "IEND"]);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testing_png(chunk_types: &[&str]) -> Png {
+        let chunks = chunk_types
+            .iter()
+            .map(|t| Chunk::new(ChunkType::from_str(t).unwrap(), vec![0]))
+            .collect();
+        Png::from_chunks(chunks)
+    }
+
+    #[test]
+    fn test_gamma_conversion() {
+        let gamma = GamaChunk::from_exponent(2.2).unwrap();
+        assert_eq!(gamma.value, SRGB_GAMMA);
+        assert_eq!(gamma.to_string(), "0.45455 (decoding exponent 2.20)");
+        assert_eq!(GamaChunk::try_from(&gamma.as_bytes()[..]).unwrap(), gamma);
+        assert!(GamaChunk::try_from(&[0, 0, 0, 0][..]).is_err());
+        assert!(GamaChunk::from_exponent(0.0).is_err());
+    }
+
+    #[test]
+    fn test_set_gamma() {
+        let mut png = testing_png(&["IHDR", "sRGB", "PLTE", "IDAT", "IEND"]);
+        set_gamma(&mut png, GamaChunk::from_exponent(1.8).unwrap()).unwrap();
+        assert_eq!(png.chunks()[2].chunk_type().to_string(), "gAMA");
+        assert_eq!(color_space_conflicts(&png).unwrap().len(), 1);
+
+        set_gamma(&mut png, GamaChunk::from_exponent(2.2).unwrap()).unwrap();
+        assert!(color_space_conflicts(&png).unwrap().is_empty());
+        assert_eq!(png.chunks_by_type("gAMA").count(), 1);
+
+        let mut ambiguous = testing_png(&["IHDR", "sRGB", "iCCP", "IDAT", "IEND"]);
+        assert!(set_gamma(&mut ambiguous, GamaChunk::from_exponent(2.2).unwrap()).is_err());
+    }
+}
@@ -0,0 +1,52 @@
+use std::io::{self, Write};
+
+/// How many bytes each line of a dump shows
+pub const BYTES_PER_LINE: usize = 16;
+
+/// Write the bytes as lines of offset, hex and ASCII columns, in the style of `hexdump -C`.
+/// `start` is the offset shown for the first byte. Bytes which aren't printable ASCII show
+/// as dots.
+pub fn write_hexdump(out: &mut dyn Write, bytes: &[u8], start: usize) -> io::Result<()> {
+    for (line, chunk) in bytes.chunks(BYTES_PER_LINE).enumerate() {
+        write!(out, "{:08x} ", start + line * BYTES_PER_LINE)?;
+        for i in 0..BYTES_PER_LINE {
+            // An extra space splits each line into two groups of eight
+            if i % 8 == 0 {
+                write!(out, " ")?;
+            }
+            match chunk.get(i) {
+                Some(byte) => write!(out, "{:02x} ", byte)?,
+                None => write!(out, "   ")?,
+            }
+        }
+        let ascii: String = chunk
+            .iter()
+            .map(|&byte| match byte {
+                0x20..=0x7e => byte as char,
+                _ => '.',
+            })
+            .collect();
+        writeln!(out, " |{}|", ascii)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_hexdump() {
+        let mut out = vec![];
+        write_hexdump(&mut out, b"IHDR\x00\x01\x02 hello, world!\n", 0x20).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "00000020  49 48 44 52 00 01 02 20  68 65 6c 6c 6f 2c 20 77  |IHDR... hello, w|\n\
+             00000030  6f 72 6c 64 21 0a                                 |orld!.|\n"
+        );
+
+        let mut out = vec![];
+        write_hexdump(&mut out, b"", 0).unwrap();
+        assert!(out.is_empty());
+    }
+}
@@ -0,0 +1,144 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::compression::Compression;
+use crate::png::Png;
+use crate::text::{latin1_decode, latin1_encode, validate_keyword};
+use crate::{PngError, PngResult};
+use std::str::FromStr;
+
+/// The length of an ICC profile header, which every profile must have
+pub const ICC_HEADER_LEN: usize = 128;
+
+/// Where the `acsp` signature which identifies an ICC profile sits in its header
+const ICC_SIGNATURE_RANGE: std::ops::Range<usize> = 36..40;
+
+/// An embedded ICC colour profile, as stored in an iCCP chunk
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IccpChunk {
+    name: String,
+    profile: Vec<u8>,
+}
+
+impl IccpChunk {
+    pub const CHUNK_TYPE: &'static str = "iCCP";
+
+    /// Wrap an ICC profile under the given name, checking that it looks like a profile
+    pub fn new(name: &str, profile: Vec<u8>) -> PngResult<Self> {
+        validate_keyword(name)?;
+        if profile.len() < ICC_HEADER_LEN || &profile[ICC_SIGNATURE_RANGE] != b"acsp" {
+            return Err("Data is not an ICC profile: no acsp signature in its header".into());
+        }
+        Ok(IccpChunk {
+            name: name.to_string(),
+            profile,
+        })
+    }
+
+    /// The profile name, which is only a label and need not match the profile's description
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The uncompressed ICC profile
+    pub fn profile(&self) -> &[u8] {
+        &self.profile
+    }
+
+    pub fn profile_mut(&mut self) -> &mut [u8] {
+        &mut self.profile
+    }
+
+    pub fn set_name(&mut self, name: &str) -> PngResult<()> {
+        validate_keyword(name)?;
+        self.name = name.to_string();
+        Ok(())
+    }
+
+    /// Encode the profile as an iCCP chunk, compressing it with zlib as the spec requires
+    pub fn to_chunk(&self) -> PngResult<Chunk> {
+        let mut data = latin1_encode(&self.name)?;
+        data.extend_from_slice(&[0, 0]);
+        data.extend(Compression::Zlib.compress(&self.profile, None)?);
+        Ok(Chunk::new(ChunkType::from_str(Self::CHUNK_TYPE)?, data))
+    }
+}
+
+impl TryFrom<&Chunk> for IccpChunk {
+    type Error = PngError;
+
+    /// Decode an iCCP chunk, decompressing the profile
+    fn try_from(chunk: &Chunk) -> PngResult<Self> {
+        let data = chunk.data();
+        let name_len = data
+            .iter()
+            .position(|b| *b == 0)
+            .ok_or("iCCP chunk has no profile name terminator")?;
+        if data.get(name_len + 1) != Some(&0) {
+            return Err("iCCP chunk uses an unknown compression method".into());
+        }
+        Ok(IccpChunk {
+            name: latin1_decode(&data[..name_len]),
+            profile: Compression::Zlib.decompress(&data[name_len + 2..])?,
+        })
+    }
+}
+
+/// Decode the embedded ICC profile of the PNG, if it has one
+pub fn get_icc_profile(png: &Png) -> PngResult<Option<IccpChunk>> {
+    png.chunk_by_type(IccpChunk::CHUNK_TYPE)
+        .map(IccpChunk::try_from)
+        .transpose()
+}
+
+/// Embed an ICC profile, replacing any existing one. An sRGB chunk would contradict the
+/// profile, so it's removed; returns whether there was one.
+pub fn embed_icc_profile(png: &mut Png, iccp: &IccpChunk) -> PngResult<bool> {
+    let srgb_removed = !png
+        .retain_chunks(|chunk| chunk.chunk_type().to_string() != "sRGB")
+        .is_empty();
+    png.set_unique_chunk(iccp.to_chunk()?, &["PLTE", "IDAT", "IEND"]);
+    Ok(srgb_removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testing_profile() -> Vec<u8> {
+        let mut profile = vec![7; 200];
+        profile[ICC_SIGNATURE_RANGE].copy_from_slice(b"acsp");
+        profile
+    }
+
+    #[test]
+    fn test_iccp_round_trip() {
+        let iccp = IccpChunk::new("Display P3", testing_profile()).unwrap();
+        let chunk = iccp.to_chunk().unwrap();
+        assert!(chunk.data().starts_with(b"Display P3\0\0"));
+        assert_eq!(IccpChunk::try_from(&chunk).unwrap(), iccp);
+
+        assert!(IccpChunk::new("Display P3", vec![0; 200]).is_err());
+        assert!(IccpChunk::new("", testing_profile()).is_err());
+    }
+
+    #[test]
+    fn test_embed_replaces_srgb() {
+        let chunks = ["IHDR", "sRGB", "IDAT", "IEND"]
+            .iter()
+            .map(|t| Chunk::new(ChunkType::from_str(t).unwrap(), vec![]))
+            .collect();
+        let mut png = Png::from_chunks(chunks);
+        assert_eq!(get_icc_profile(&png).unwrap(), None);
+
+        let iccp = IccpChunk::new("ICC profile", testing_profile()).unwrap();
+        assert!(embed_icc_profile(&mut png, &iccp).unwrap());
+        assert!(!embed_icc_profile(&mut png, &iccp).unwrap());
+        assert_eq!(get_icc_profile(&png).unwrap(), Some(iccp));
+        let types: Vec<String> = png
+            .chunks()
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect();
+        assert_eq!(types, vec!["IHDR", "iCCP", "IDAT", "IEND"]);
+    }
+}
@@ -0,0 +1,321 @@
+use crate::error::{error, ErrorKind};
+use crate::png::Png;
+use crate::{PngError, PngResult};
+use std::fmt::{Display, Formatter};
+
+/// How pixels are represented, as stored in the IHDR colour type byte
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorType {
+    Grayscale,
+    Truecolor,
+    Indexed,
+    GrayscaleAlpha,
+    TruecolorAlpha,
+}
+
+impl ColorType {
+    /// The value of the IHDR colour type byte
+    pub fn value(&self) -> u8 {
+        match self {
+            ColorType::Grayscale => 0,
+            ColorType::Truecolor => 2,
+            ColorType::Indexed => 3,
+            ColorType::GrayscaleAlpha => 4,
+            ColorType::TruecolorAlpha => 6,
+        }
+    }
+
+    /// The bit depths the spec allows for this colour type
+    pub fn allowed_bit_depths(&self) -> &'static [u8] {
+        match self {
+            ColorType::Grayscale => &[1, 2, 4, 8, 16],
+            ColorType::Indexed => &[1, 2, 4, 8],
+            _ => &[8, 16],
+        }
+    }
+
+    /// The number of samples making up each pixel
+    pub fn channels(&self) -> u8 {
+        match self {
+            ColorType::Grayscale | ColorType::Indexed => 1,
+            ColorType::GrayscaleAlpha => 2,
+            ColorType::Truecolor => 3,
+            ColorType::TruecolorAlpha => 4,
+        }
+    }
+}
+
+impl TryFrom<u8> for ColorType {
+    type Error = PngError;
+
+    fn try_from(value: u8) -> PngResult<Self> {
+        match value {
+            0 => Ok(ColorType::Grayscale),
+            2 => Ok(ColorType::Truecolor),
+            3 => Ok(ColorType::Indexed),
+            4 => Ok(ColorType::GrayscaleAlpha),
+            6 => Ok(ColorType::TruecolorAlpha),
+            _ => Err(error(
+                ErrorKind::Parse,
+                format!("Invalid colour type {}", value),
+            )),
+        }
+    }
+}
+
+impl Display for ColorType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            ColorType::Grayscale => "grayscale",
+            ColorType::Truecolor => "truecolor",
+            ColorType::Indexed => "indexed",
+            ColorType::GrayscaleAlpha => "grayscale with alpha",
+            ColorType::TruecolorAlpha => "truecolor with alpha",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Where each Adam7 pass starts and how far apart its pixels are: (x, y, x step, y step)
+const ADAM7_PASSES: [(usize, usize, usize, usize); 7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+
+/// One of the reduced images making up the image data, whose pixels are those of the full
+/// image starting at (`x`, `y`) and spaced `x_step` and `y_step` apart
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pass {
+    pub x: usize,
+    pub y: usize,
+    pub x_step: usize,
+    pub y_step: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Pass {
+    /// Whether the pass has no pixels, in which case it takes up no image data at all
+    pub fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+}
+
+/// The decoded contents of an IHDR chunk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ihdr {
+    pub width: u32,
+    pub height: u32,
+    /// Bits per sample, or per palette index for indexed images
+    pub bit_depth: u8,
+    pub color_type: ColorType,
+    /// Always 0 (deflate) in a valid file
+    pub compression_method: u8,
+    /// Always 0 (adaptive filtering) in a valid file
+    pub filter_method: u8,
+    /// Whether the image uses Adam7 interlacing
+    pub interlaced: bool,
+}
+
+impl Ihdr {
+    /// The length of the IHDR chunk data
+    pub const LEN: usize = 13;
+
+    /// The number of bits each pixel takes up in the image data
+    pub fn bits_per_pixel(&self) -> usize {
+        self.bit_depth as usize * self.color_type.channels() as usize
+    }
+
+    /// The number of bytes of pixel data in a row of the given width, not counting the filter
+    /// type byte which leads each scanline
+    pub fn row_len(&self, width: usize) -> usize {
+        width.saturating_mul(self.bits_per_pixel()).div_ceil(8)
+    }
+
+    /// The reduced images the image data is made up of: the whole image if it isn't
+    /// interlaced, otherwise the seven Adam7 passes, some of which may be empty
+    pub fn passes(&self) -> Vec<Pass> {
+        let (width, height) = (self.width as usize, self.height as usize);
+        if !self.interlaced {
+            return vec![Pass {
+                x: 0,
+                y: 0,
+                x_step: 1,
+                y_step: 1,
+                width,
+                height,
+            }];
+        }
+        ADAM7_PASSES
+            .iter()
+            .map(|&(x, y, x_step, y_step)| Pass {
+                x,
+                y,
+                x_step,
+                y_step,
+                width: width.saturating_sub(x).div_ceil(x_step),
+                height: height.saturating_sub(y).div_ceil(y_step),
+            })
+            .collect()
+    }
+
+    /// The size of the image data once decompressed, including the filter type bytes. Sizes
+    /// too large to represent saturate at `usize::MAX`.
+    pub fn raw_data_len(&self) -> usize {
+        self.passes()
+            .iter()
+            .filter(|pass| !pass.is_empty())
+            .map(|pass| {
+                pass.height
+                    .saturating_mul(self.row_len(pass.width).saturating_add(1))
+            })
+            .fold(0, usize::saturating_add)
+    }
+
+    /// The bytes of the IHDR chunk data
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(Self::LEN);
+        bytes.extend_from_slice(&self.width.to_be_bytes());
+        bytes.extend_from_slice(&self.height.to_be_bytes());
+        bytes.extend_from_slice(&[
+            self.bit_depth,
+            self.color_type.value(),
+            self.compression_method,
+            self.filter_method,
+            self.interlaced as u8,
+        ]);
+        bytes
+    }
+}
+
+impl TryFrom<&[u8]> for Ihdr {
+    type Error = PngError;
+
+    /// Decode IHDR chunk data, rejecting values the spec doesn't allow
+    fn try_from(value: &[u8]) -> PngResult<Self> {
+        if value.len() != Self::LEN {
+            return Err(error(
+                ErrorKind::Parse,
+                format!(
+                    "IHDR data is {} bytes long but should be {}",
+                    value.len(),
+                    Self::LEN
+                ),
+            ));
+        }
+        let width = u32::from_be_bytes(value[0..4].try_into()?);
+        let height = u32::from_be_bytes(value[4..8].try_into()?);
+        for (name, dimension) in [("width", width), ("height", height)] {
+            if dimension == 0 || dimension > i32::MAX as u32 {
+                let message = format!("Image {} {} is out of range", name, dimension);
+                return Err(error(ErrorKind::Parse, message));
+            }
+        }
+        let bit_depth = value[8];
+        let color_type = ColorType::try_from(value[9])?;
+        if !color_type.allowed_bit_depths().contains(&bit_depth) {
+            return Err(error(
+                ErrorKind::Parse,
+                format!(
+                    "Bit depth {} is not allowed for {} images",
+                    bit_depth, color_type
+                ),
+            ));
+        }
+        if value[10] != 0 {
+            let message = format!("Unknown compression method {}", value[10]);
+            return Err(error(ErrorKind::Parse, message));
+        }
+        if value[11] != 0 {
+            let message = format!("Unknown filter method {}", value[11]);
+            return Err(error(ErrorKind::Parse, message));
+        }
+        let interlaced = match value[12] {
+            0 => false,
+            1 => true,
+            other => {
+                let message = format!("Unknown interlace method {}", other);
+                return Err(error(ErrorKind::Parse, message));
+            }
+        };
+        Ok(Ihdr {
+            width,
+            height,
+            bit_depth,
+            color_type,
+            compression_method: value[10],
+            filter_method: value[11],
+            interlaced,
+        })
+    }
+}
+
+impl TryFrom<&Png> for Ihdr {
+    type Error = PngError;
+
+    /// Decode the IHDR chunk of the PNG
+    fn try_from(png: &Png) -> PngResult<Self> {
+        let chunk = png
+            .chunk_by_type("IHDR")
+            .ok_or_else(|| error(ErrorKind::ChunkNotFound, "No IHDR chunk found"))?;
+        Ihdr::try_from(chunk.data())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::kind_of;
+
+    const IHDR_DATA: [u8; 13] = [0, 0, 0, 100, 0, 0, 0, 50, 8, 6, 0, 0, 0];
+
+    #[test]
+    fn test_ihdr_round_trip() {
+        let ihdr = Ihdr::try_from(&IHDR_DATA[..]).unwrap();
+        assert_eq!((ihdr.width, ihdr.height), (100, 50));
+        assert_eq!(ihdr.color_type, ColorType::TruecolorAlpha);
+        assert_eq!(ihdr.bits_per_pixel(), 32);
+        assert_eq!(ihdr.raw_data_len(), 50 * (1 + 400));
+        assert_eq!(ihdr.as_bytes(), IHDR_DATA);
+    }
+
+    #[test]
+    fn test_interlaced_raw_data_len() {
+        let mut bytes = IHDR_DATA;
+        bytes[3] = 1; // a 1x50 grayscale image only has pixels in passes 1, 3, 5 and 7
+        bytes[8] = 1;
+        bytes[9] = 0;
+        bytes[12] = 1;
+        let ihdr = Ihdr::try_from(&bytes[..]).unwrap();
+        assert_eq!(ihdr.raw_data_len(), (7 + 6 + 12 + 25) * 2);
+    }
+
+    #[test]
+    fn test_invalid_ihdr() {
+        let cases: [(usize, u8); 6] = [
+            (3, 0),  // zero width
+            (8, 3),  // bit depth 3
+            (9, 5),  // colour type 5
+            (10, 1), // compression method
+            (11, 1), // filter method
+            (12, 2), // interlace method
+        ];
+        for (index, value) in cases {
+            let mut bytes = IHDR_DATA;
+            bytes[index] = value;
+            let error = Ihdr::try_from(&bytes[..]).unwrap_err();
+            assert_eq!(kind_of(&*error), ErrorKind::Parse, "byte {}", index);
+        }
+
+        let mut bytes = IHDR_DATA;
+        bytes[8] = 16;
+        bytes[9] = 3; // 16-bit indexed
+        assert!(Ihdr::try_from(&bytes[..]).is_err());
+        assert!(Ihdr::try_from(&IHDR_DATA[..12]).is_err());
+    }
+}
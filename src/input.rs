@@ -0,0 +1,147 @@
+#[cfg(feature = "http")]
+use crate::error::{self, ErrorKind};
+#[cfg(any(feature = "http", feature = "mmap"))]
+use crate::PngResult;
+#[cfg(feature = "mmap")]
+use std::fs;
+#[cfg(feature = "http")]
+use std::io::Read;
+use std::ops::Deref;
+use std::path::Path;
+#[cfg(feature = "http")]
+use std::time::Duration;
+
+/// The most `fetch` downloads unless given a limit of its own
+pub const MAX_DOWNLOAD_LEN: usize = 256 * 1024 * 1024;
+
+/// How long `fetch` waits to connect to the server, including any TLS handshake
+#[cfg(feature = "http")]
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long `fetch` waits for the server to start responding once the request is sent
+#[cfg(feature = "http")]
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long `fetch` gives the whole body to arrive, long enough for a download at the limit
+/// over a slow connection but not for one that has stalled
+#[cfg(feature = "http")]
+const BODY_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// The URL named by an input path, if it is an `http://` or `https://` URL rather than a file
+pub fn url(path: &Path) -> Option<&str> {
+    let text = path.to_str()?;
+    ["http://", "https://"]
+        .iter()
+        .any(|scheme| {
+            text.get(..scheme.len())
+                .is_some_and(|prefix| prefix.eq_ignore_ascii_case(scheme))
+        })
+        .then_some(text)
+}
+
+/// The bytes of an input file, either read into memory up front or, with the `mmap` feature,
+/// mapped so that only the pages the parser actually touches are read from disk
+pub enum Input {
+    Buffered(Vec<u8>),
+    #[cfg(feature = "mmap")]
+    Mapped(memmap2::Mmap),
+}
+
+impl Input {
+    /// Download the body of the URL, failing rather than reading more than `limit` bytes
+    #[cfg(feature = "http")]
+    pub fn fetch(url: &str, limit: usize) -> PngResult<Self> {
+        let too_large = || {
+            let message = format!("{} is larger than the limit of {} bytes", url, limit);
+            error::error(ErrorKind::Parse, message)
+        };
+        let response = ureq::get(url)
+            .config()
+            .timeout_connect(Some(CONNECT_TIMEOUT))
+            .timeout_recv_response(Some(READ_TIMEOUT))
+            .timeout_recv_body(Some(BODY_TIMEOUT))
+            .build()
+            .call()
+            .map_err(|e| error::error(ErrorKind::Io, format!("{}: {}", url, e)))?;
+        let body = response.into_body();
+        if body.content_length().is_some_and(|len| len > limit as u64) {
+            return Err(too_large());
+        }
+        let mut bytes = vec![];
+        // The body is capped here rather than by ureq, whose own limit is far smaller
+        body.into_with_config()
+            .limit(u64::MAX)
+            .reader()
+            .take(limit as u64 + 1)
+            .read_to_end(&mut bytes)?;
+        if bytes.len() > limit {
+            return Err(too_large());
+        }
+        Ok(Input::Buffered(bytes))
+    }
+
+    /// Map the file into memory rather than reading it. Parsing a `PngRef` over the mapping
+    /// walks the chunk headers in place and borrows the chunk data, so nothing is copied until
+    /// a command asks for an owned chunk.
+    #[cfg(feature = "mmap")]
+    pub fn map(path: &Path) -> PngResult<Self> {
+        let file = fs::File::open(path)?;
+        if file.metadata()?.len() == 0 {
+            // Empty files can't be mapped on every platform
+            return Ok(Input::Buffered(vec![]));
+        }
+        // SAFETY: the mapping is only ever read. As with any tool which maps its input, another
+        // process truncating the file while it is mapped would fault the read.
+        let mmap = unsafe { memmap2::Mmap::map(&file)? };
+        Ok(Input::Mapped(mmap))
+    }
+}
+
+impl Deref for Input {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Input::Buffered(bytes) => bytes,
+            #[cfg(feature = "mmap")]
+            Input::Mapped(mmap) => mmap,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::png::PngRef;
+    use std::fs;
+
+    #[test]
+    fn test_input() {
+        let path = std::env::temp_dir().join(format!("pngmi-input-{}.png", std::process::id()));
+        let png = crate::png::Png::from_chunks(vec![]);
+        let mut bytes = png.as_bytes();
+        bytes.extend_from_slice(&[0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xae, 0x42, 0x60, 0x82]);
+        fs::write(&path, &bytes).unwrap();
+
+        let read = Input::Buffered(fs::read(&path).unwrap());
+        assert_eq!(&*read, bytes.as_slice());
+        #[cfg(feature = "mmap")]
+        {
+            let mapped = Input::map(&path).unwrap();
+            assert_eq!(&*mapped, bytes.as_slice());
+            assert_eq!(PngRef::try_from(&*mapped).unwrap().chunks().len(), 1);
+        }
+        assert_eq!(PngRef::try_from(&*read).unwrap().chunks().len(), 1);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_url() {
+        let url = "https://example.com/a.png?size=2";
+        assert_eq!(super::url(Path::new(url)), Some(url));
+        assert!(super::url(Path::new("HTTP://example.com/a.png")).is_some());
+        assert_eq!(super::url(Path::new("ftp://example.com/a.png")), None);
+        assert_eq!(super::url(Path::new("http")), None);
+        assert_eq!(super::url(Path::new("images/https:/a.png")), None);
+    }
+}
@@ -0,0 +1,85 @@
+use crate::error::{self, ErrorKind};
+use crate::PngResult;
+use std::io::{self, Read, Write};
+
+/// The largest frame `read_frame` accepts unless given a limit of its own
+pub const MAX_FRAME_LEN: usize = 64 * 1024 * 1024;
+
+/// Write one frame: the length of the payload as a 4-byte big-endian integer, then the payload
+pub fn write_frame(writer: &mut impl Write, payload: &[u8]) -> PngResult<()> {
+    let len = u32::try_from(payload.len())
+        .map_err(|_| format!("A frame can't hold {} bytes", payload.len()))?;
+    writer.write_all(&len.to_be_bytes())?;
+    writer.write_all(payload)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Read one frame written by `write_frame`, or `None` if the stream ends before a new frame
+/// starts. Frames longer than `limit` are refused without reading their payload.
+pub fn read_frame(reader: &mut impl Read, limit: usize) -> PngResult<Option<Vec<u8>>> {
+    let mut header = [0; 4];
+    let mut filled = 0;
+    while filled < header.len() {
+        match reader.read(&mut header[filled..]) {
+            Ok(0) if filled == 0 => return Ok(None),
+            Ok(0) => return Err(truncated()),
+            Ok(read) => filled += read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    let len = u32::from_be_bytes(header) as usize;
+    if len > limit {
+        let message = format!(
+            "Frame of {} bytes is larger than the limit of {}",
+            len, limit
+        );
+        return Err(error::error(ErrorKind::Parse, message));
+    }
+    let mut payload = vec![0; len];
+    reader
+        .read_exact(&mut payload)
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::UnexpectedEof => truncated(),
+            _ => e.into(),
+        })?;
+    Ok(Some(payload))
+}
+
+fn truncated() -> crate::PngError {
+    error::error(ErrorKind::Parse, "Stream ended part way through a frame")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let mut stream = vec![];
+        write_frame(&mut stream, b"{\"command\":\"validate\"}").unwrap();
+        write_frame(&mut stream, b"").unwrap();
+        assert_eq!(&stream[..4], &[0, 0, 0, 22]);
+
+        let mut reader = &stream[..];
+        assert_eq!(
+            read_frame(&mut reader, MAX_FRAME_LEN).unwrap().unwrap(),
+            b"{\"command\":\"validate\"}"
+        );
+        assert_eq!(
+            read_frame(&mut reader, MAX_FRAME_LEN).unwrap().unwrap(),
+            b""
+        );
+        assert!(read_frame(&mut reader, MAX_FRAME_LEN).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_bad_frames() {
+        let mut stream = vec![];
+        write_frame(&mut stream, b"payload").unwrap();
+        assert!(read_frame(&mut &stream[..], 6).is_err());
+        assert!(read_frame(&mut &stream[..9], MAX_FRAME_LEN).is_err());
+        assert!(read_frame(&mut &stream[..2], MAX_FRAME_LEN).is_err());
+    }
+}
@@ -0,0 +1,71 @@
+pub mod analysis;
+pub mod anonymize;
+pub mod apng;
+pub mod background;
+pub mod carve;
+pub mod chunk;
+pub mod chunk_type;
+pub mod codec;
+pub mod compression;
+pub mod config;
+pub mod copy;
+pub mod crypto;
+pub mod decoder;
+pub mod dedupe;
+pub mod diff;
+pub mod entropy;
+pub mod envelope;
+pub mod error;
+pub mod exif;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod gamma;
+pub mod hexdump;
+pub mod iccp;
+pub mod ihdr;
+pub mod input;
+pub mod ipc;
+pub mod lint;
+pub mod lsb;
+pub mod message;
+pub mod normalize;
+pub mod optimize;
+pub mod output;
+pub mod palette;
+pub mod patch;
+pub mod phys;
+pub mod plugin;
+pub mod png;
+pub mod polyglot;
+#[cfg(feature = "pngmi-py")]
+pub mod python;
+pub mod raw;
+pub mod recipients;
+pub mod repair;
+pub mod report;
+pub mod sarif;
+pub mod sbit;
+pub mod scan;
+pub mod search;
+pub mod segment;
+pub mod signature;
+pub mod signing;
+pub mod split;
+pub mod splt;
+pub mod stats;
+#[cfg(feature = "async")]
+pub mod stream;
+pub mod strip;
+#[cfg(test)]
+pub(crate) mod test_util;
+pub mod text;
+pub mod time;
+pub mod trailer;
+pub mod validate;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+pub mod xmp;
+pub mod zlib;
+
+pub type PngError = Box<dyn std::error::Error>;
+pub type PngResult<T> = std::result::Result<T, PngError>;
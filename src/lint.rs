@@ -0,0 +1,208 @@
+use crate::validate::{Rule, Severity, Violation};
+use crate::PngResult;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// How a rule's violations are reported, named after clippy's lint levels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LintLevel {
+    /// Don't report them at all
+    Allow,
+    Info,
+    Warn,
+    Deny,
+}
+
+impl LintLevel {
+    pub const ALL: &'static [LintLevel] = &[
+        LintLevel::Allow,
+        LintLevel::Info,
+        LintLevel::Warn,
+        LintLevel::Deny,
+    ];
+
+    /// The severity violations are reported at, or `None` if they aren't reported
+    pub fn severity(&self) -> Option<Severity> {
+        match self {
+            LintLevel::Allow => None,
+            LintLevel::Info => Some(Severity::Info),
+            LintLevel::Warn => Some(Severity::Warning),
+            LintLevel::Deny => Some(Severity::Error),
+        }
+    }
+}
+
+impl Display for LintLevel {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LintLevel::Allow => write!(f, "allow"),
+            LintLevel::Info => write!(f, "info"),
+            LintLevel::Warn => write!(f, "warn"),
+            LintLevel::Deny => write!(f, "deny"),
+        }
+    }
+}
+
+impl FromStr for LintLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        LintLevel::ALL
+            .iter()
+            .find(|level| level.to_string() == s)
+            .copied()
+            .ok_or_else(|| {
+                format!(
+                    "Unknown lint level '{}'; expected allow, info, warn or deny",
+                    s
+                )
+            })
+    }
+}
+
+/// Which rules are reported and at what severity. Rules without a level of their own keep
+/// their default severity, and a level for `warnings` then applies to everything left as a
+/// warning, so `deny` on `warnings` fails CI on any warning as `--deny warnings` does.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LintConfig {
+    levels: HashMap<Rule, LintLevel>,
+    warnings: Option<LintLevel>,
+}
+
+impl LintConfig {
+    /// The name which stands for every violation reported as a warning
+    pub const WARNINGS: &'static str = "warnings";
+
+    /// Set the level of a rule, given by its ID, or of all warnings. Later calls for the same
+    /// name win.
+    pub fn set(&mut self, name: &str, level: LintLevel) -> PngResult<()> {
+        if name == Self::WARNINGS {
+            self.warnings = Some(level);
+        } else {
+            self.levels.insert(Rule::from_str(name)?, level);
+        }
+        Ok(())
+    }
+
+    /// Apply the levels set in `other` on top of these
+    pub fn extend(&mut self, other: &LintConfig) {
+        self.levels.extend(&other.levels);
+        self.warnings = other.warnings.or(self.warnings);
+    }
+
+    /// The severity violations of a rule are reported at, or `None` if they're dropped
+    pub fn severity(&self, rule: Rule) -> Option<Severity> {
+        let severity = match self.levels.get(&rule) {
+            Some(level) => level.severity()?,
+            None => rule.default_severity(),
+        };
+        match self.warnings {
+            Some(level) if severity == Severity::Warning => level.severity(),
+            _ => Some(severity),
+        }
+    }
+
+    /// Drop the violations of allowed rules and reclassify the rest
+    pub fn apply(&self, violations: Vec<Violation>) -> Vec<Violation> {
+        violations
+            .into_iter()
+            .filter_map(|violation| {
+                let severity = self.severity(violation.rule)?;
+                Some(Violation {
+                    severity,
+                    ..violation
+                })
+            })
+            .collect()
+    }
+
+    /// Read levels from a TOML table mapping rule IDs, or `warnings`, to levels
+    pub fn from_table(table: &toml::Table) -> PngResult<Self> {
+        let mut config = LintConfig::default();
+        for (name, level) in table {
+            let level = level
+                .as_str()
+                .ok_or_else(|| format!("Lint level for '{}' must be a string", name))?;
+            config.set(name, level.parse()?)?;
+        }
+        Ok(config)
+    }
+
+    /// Read levels from the `[lints]` table of a TOML document
+    pub fn from_toml(text: &str) -> PngResult<Self> {
+        let document: toml::Table = text.parse()?;
+        match document.get("lints") {
+            Some(toml::Value::Table(table)) => Self::from_table(table),
+            Some(_) => Err("'lints' must be a table".into()),
+            None => Ok(LintConfig::default()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn violation(rule: Rule) -> Violation {
+        Violation {
+            rule,
+            severity: rule.default_severity(),
+            offset: 0,
+            message: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_levels() {
+        let mut config = LintConfig::default();
+        assert_eq!(config.severity(Rule::TrailingData), Some(Severity::Warning));
+        config.set("trailing-data", LintLevel::Allow).unwrap();
+        config.set("crc-mismatch", LintLevel::Info).unwrap();
+        config.set("srgb-with-iccp", LintLevel::Deny).unwrap();
+        assert!(config.set("no-such-rule", LintLevel::Deny).is_err());
+
+        let violations = [Rule::TrailingData, Rule::CrcMismatch, Rule::SrgbWithIccp]
+            .into_iter()
+            .chain([Rule::EmbeddedFile])
+            .map(violation)
+            .collect();
+        let severities: Vec<_> = config
+            .apply(violations)
+            .into_iter()
+            .map(|violation| (violation.rule, violation.severity))
+            .collect();
+        assert_eq!(
+            severities,
+            vec![
+                (Rule::CrcMismatch, Severity::Info),
+                (Rule::SrgbWithIccp, Severity::Error),
+                (Rule::EmbeddedFile, Severity::Warning),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_deny_warnings() {
+        let mut config = LintConfig::default();
+        config.set(LintConfig::WARNINGS, LintLevel::Deny).unwrap();
+        config.set("trailing-data", LintLevel::Info).unwrap();
+        config.set("crc-mismatch", LintLevel::Warn).unwrap();
+        assert_eq!(config.severity(Rule::EmbeddedFile), Some(Severity::Error));
+        assert_eq!(config.severity(Rule::CrcMismatch), Some(Severity::Error));
+        assert_eq!(config.severity(Rule::TrailingData), Some(Severity::Info));
+    }
+
+    #[test]
+    fn test_from_toml() {
+        let config =
+            LintConfig::from_toml("[lints]\nwarnings = \"deny\"\ntrailing-data = \"allow\"\n")
+                .unwrap();
+        assert_eq!(config.severity(Rule::TrailingData), None);
+        assert_eq!(config.severity(Rule::EmbeddedFile), Some(Severity::Error));
+        assert_eq!(LintConfig::from_toml("").unwrap(), LintConfig::default());
+        assert!(LintConfig::from_toml("[lints]\ntrailing-data = \"off\"").is_err());
+        assert!(LintConfig::from_toml("[lints]\ntrailing-data = 1").is_err());
+        assert!(LintConfig::from_toml("lints = 1").is_err());
+    }
+}
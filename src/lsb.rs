@@ -0,0 +1,277 @@
+use crate::chunk::Chunk;
+use crate::decoder::{self, Pixels};
+use crate::error::{error, ErrorKind};
+use crate::ihdr::{ColorType, Ihdr};
+use crate::png::Png;
+use crate::PngResult;
+
+/// The most low bits of each sample which may be given over to the payload. Beyond this the
+/// changes to the image become plainly visible.
+pub const MAX_BITS: u8 = 4;
+
+/// The bytes at the start of the hidden data recording the density and the payload length
+const HEADER_LEN: usize = 5;
+
+/// The header is always stored at one bit per sample, so it can be read before the density
+const HEADER_SAMPLES: usize = HEADER_LEN * 8;
+
+/// The zlib level the image data is recompressed at once the payload is in place
+const COMPRESSION_LEVEL: u32 = 9;
+
+/// The offsets into the pixel data of the least significant byte of every colour sample, in
+/// order. Alpha is left alone, as changes to it stand out against a background.
+pub(crate) fn sample_offsets(pixels: &Pixels) -> impl Iterator<Item = usize> {
+    let bytes_per_sample = pixels.bit_depth as usize / 8;
+    let channels = pixels.color_type.channels() as usize;
+    let colour_channels = colour_channels(pixels.color_type);
+    (0..pixels.width * pixels.height).flat_map(move |pixel| {
+        (0..colour_channels)
+            .map(move |channel| (pixel * channels + channel + 1) * bytes_per_sample - 1)
+    })
+}
+
+/// The number of samples in each pixel which aren't alpha
+pub(crate) fn colour_channels(color_type: ColorType) -> usize {
+    let channels = color_type.channels() as usize;
+    match color_type {
+        ColorType::GrayscaleAlpha | ColorType::TruecolorAlpha => channels - 1,
+        _ => channels,
+    }
+}
+
+/// Only images of at least 8 bits per sample whose samples are colours can carry a payload
+/// invisibly: the low bits of a palette index, or of a 1, 2 or 4-bit sample, are no small
+/// change to the pixel
+fn check_supported(ihdr: &Ihdr) -> PngResult<()> {
+    if ihdr.color_type == ColorType::Indexed {
+        return Err("LSB embedding isn't supported for indexed-colour images".into());
+    }
+    if ihdr.bit_depth < 8 {
+        return Err(format!(
+            "LSB embedding needs at least 8 bits per sample, not {}",
+            ihdr.bit_depth
+        )
+        .into());
+    }
+    Ok(())
+}
+
+fn check_bits(bits: u8) -> PngResult<()> {
+    match (1..=MAX_BITS).contains(&bits) {
+        true => Ok(()),
+        false => Err(format!(
+            "Bits per sample must be from 1 to {}, not {}",
+            MAX_BITS, bits
+        )
+        .into()),
+    }
+}
+
+/// The number of payload bytes which fit in an image with this many colour samples
+fn capacity_of(samples: usize, bits: u8) -> usize {
+    samples.saturating_sub(HEADER_SAMPLES) * bits as usize / 8
+}
+
+/// The largest payload in bytes that `embed` can hide in the PNG at the given density
+pub fn capacity(png: &Png, bits: u8) -> PngResult<usize> {
+    check_bits(bits)?;
+    let ihdr = Ihdr::try_from(png)?;
+    check_supported(&ihdr)?;
+    let samples = ihdr.width as usize * ihdr.height as usize * colour_channels(ihdr.color_type);
+    Ok(capacity_of(samples, bits))
+}
+
+/// Write the bits of `data`, most significant first, into the low `bits` bits of successive
+/// samples, failing if the samples run out first
+fn write_bits(
+    pixels: &mut [u8],
+    offsets: &mut impl Iterator<Item = usize>,
+    data: &[u8],
+    bits: u8,
+) -> PngResult<()> {
+    let mask = (1u8 << bits) - 1;
+    let mut stream = data
+        .iter()
+        .flat_map(|byte| (0..8).rev().map(move |i| (byte >> i) & 1));
+    while let Some(first) = stream.next() {
+        let mut value = first;
+        for _ in 1..bits {
+            value = (value << 1) | stream.next().unwrap_or(0);
+        }
+        let offset = offsets
+            .next()
+            .ok_or("The image ran out of samples to hide the payload in")?;
+        pixels[offset] = (pixels[offset] & !mask) | value;
+    }
+    Ok(())
+}
+
+/// Read `len` bytes from the low `bits` bits of successive samples
+fn read_bits(
+    pixels: &[u8],
+    offsets: &mut impl Iterator<Item = usize>,
+    len: usize,
+    bits: u8,
+) -> Vec<u8> {
+    let mask = (1u8 << bits) - 1;
+    let mut stream = offsets.flat_map(|offset| {
+        let value = pixels[offset] & mask;
+        (0..bits).rev().map(move |i| (value >> i) & 1)
+    });
+    (0..len)
+        .map(|_| (0..8).fold(0, |byte, _| (byte << 1) | stream.next().unwrap_or(0)))
+        .collect()
+}
+
+/// Hide the payload in the low `bits` bits of the image's colour samples, rewriting the image
+/// data. A header in the first samples records the density and length, so `extract` needs
+/// neither. The pixels change by at most `2^bits - 1` in each colour sample.
+pub fn embed(png: &mut Png, payload: &[u8], bits: u8) -> PngResult<()> {
+    check_bits(bits)?;
+    let ihdr = Ihdr::try_from(&*png)?;
+    check_supported(&ihdr)?;
+    let mut pixels = decoder::decode_png(png)?;
+    let samples = sample_offsets(&pixels).count();
+    if samples < HEADER_SAMPLES {
+        return Err(format!(
+            "The image has {} colour samples but needs at least {} for the LSB header",
+            samples, HEADER_SAMPLES
+        )
+        .into());
+    }
+    let capacity = capacity_of(samples, bits);
+    let len = u32::try_from(payload.len())
+        .ok()
+        .filter(|_| payload.len() <= capacity)
+        .ok_or_else(|| {
+            format!(
+                "The payload is {} bytes but the image can only hide {} at {} bits per sample",
+                payload.len(),
+                capacity,
+                bits
+            )
+        })?;
+
+    let mut header = vec![bits];
+    header.extend_from_slice(&len.to_be_bytes());
+    let mut offsets = sample_offsets(&pixels);
+    write_bits(pixels.data_mut(), &mut offsets, &header, 1)?;
+    write_bits(pixels.data_mut(), &mut offsets, payload, bits)?;
+
+    // Keep the IDAT chunks no bigger than they were
+    let max_chunk_len = png
+        .chunks_by_type("IDAT")
+        .map(Chunk::length)
+        .max()
+        .unwrap_or_default()
+        .max(1) as usize;
+    png.set_idat_data(
+        &decoder::encode(&ihdr, &pixels),
+        COMPRESSION_LEVEL,
+        max_chunk_len,
+    )
+}
+
+/// The payload `embed` hid in the pixels, or no more than its first `limit` bytes, if the
+/// header at the start of the samples is a plausible one
+pub(crate) fn read_payload(pixels: &Pixels, limit: usize) -> Option<Vec<u8>> {
+    let mut offsets = sample_offsets(pixels);
+    let header = read_bits(
+        pixels.data(),
+        &mut offsets.by_ref().take(HEADER_SAMPLES),
+        HEADER_LEN,
+        1,
+    );
+    let bits = header[0];
+    let len = u32::from_be_bytes([header[1], header[2], header[3], header[4]]) as usize;
+    let capacity = capacity_of(sample_offsets(pixels).count(), bits.max(1));
+    if !(1..=MAX_BITS).contains(&bits) || len > capacity {
+        return None;
+    }
+    Some(read_bits(pixels.data(), &mut offsets, len.min(limit), bits))
+}
+
+/// Recover a payload hidden by `embed`
+pub fn extract(png: &Png) -> PngResult<Vec<u8>> {
+    check_supported(&Ihdr::try_from(png)?)?;
+    let pixels = decoder::decode_png(png)?;
+    read_payload(&pixels, usize::MAX).ok_or_else(|| error(ErrorKind::Parse, "No LSB payload found"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::chunk;
+
+    /// A noisy image of the given size and layout, so that recompression can't hide mistakes
+    fn testing_png(width: u32, height: u32, bit_depth: u8, color_type: ColorType) -> Png {
+        let ihdr = Ihdr {
+            width,
+            height,
+            bit_depth,
+            color_type,
+            compression_method: 0,
+            filter_method: 0,
+            interlaced: false,
+        };
+        let mut png = Png::from_chunks(vec![
+            chunk("IHDR", &ihdr.as_bytes()),
+            chunk("IDAT", &[]),
+            chunk("IEND", &[]),
+        ]);
+        let data: Vec<u8> = (0..ihdr.raw_data_len())
+            .map(|i| {
+                if i % (1 + ihdr.row_len(width as usize)) == 0 {
+                    0
+                } else {
+                    (i * 7919 % 251) as u8
+                }
+            })
+            .collect();
+        png.set_idat_data(&data, 6, 1024).unwrap();
+        png
+    }
+
+    #[test]
+    fn test_embed_extract() {
+        for (bit_depth, color_type) in [(8, ColorType::TruecolorAlpha), (16, ColorType::Grayscale)]
+        {
+            for bits in 1..=MAX_BITS {
+                let mut png = testing_png(20, 10, bit_depth, color_type);
+                let before = decoder::decode_png(&png).unwrap();
+                let payload: Vec<u8> = (0..capacity(&png, bits).unwrap() as u8).collect();
+                embed(&mut png, &payload, bits).unwrap();
+                assert_eq!(extract(&png).unwrap(), payload);
+
+                // Only the low bits of colour samples changed
+                let after = decoder::decode_png(&png).unwrap();
+                let offsets: Vec<usize> = sample_offsets(&before).collect();
+                for (i, (a, b)) in before.data().iter().zip(after.data()).enumerate() {
+                    match offsets.contains(&i) {
+                        true => assert!(a ^ b < 1 << bits),
+                        false => assert_eq!(a, b),
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_embed_limits() {
+        let mut png = testing_png(4, 4, 8, ColorType::Truecolor);
+        assert_eq!(capacity(&png, 1).unwrap(), 1);
+        assert_eq!(capacity(&png, 2).unwrap(), 2);
+        assert!(embed(&mut png, b"abc", 2).is_err());
+        assert!(embed(&mut png, b"a", 0).is_err());
+        assert!(extract(&png).is_err());
+
+        let mut png = testing_png(4, 4, 8, ColorType::Indexed);
+        assert!(embed(&mut png, b"a", 1).is_err());
+        let mut png = testing_png(4, 4, 4, ColorType::Grayscale);
+        assert!(embed(&mut png, b"a", 1).is_err());
+        // 39 samples can't even hold the header, so not even an empty payload fits
+        let mut png = testing_png(13, 1, 8, ColorType::Truecolor);
+        assert!(embed(&mut png, b"", 1).is_err());
+        assert!(extract(&png).is_err());
+    }
+}
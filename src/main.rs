@@ -1,12 +1,186 @@
 mod args;
-mod chunk;
-mod chunk_type;
 mod commands;
-mod png;
+#[cfg(all(unix, feature = "daemon"))]
+mod daemon;
+mod progress;
+#[cfg(feature = "serve")]
+mod serve;
+#[cfg(feature = "shell")]
+mod shell;
+#[cfg(feature = "tui")]
+mod tui;
+mod walk;
+#[cfg(feature = "watch")]
+mod watch;
 
-pub type PngError = Box<dyn std::error::Error>;
-pub type PngResult<T> = std::result::Result<T, PngError>;
+use args::{
+    ApngCommand, Cli, Command, DpiCommand, GammaCommand, IccCommand, OutputFormat, TextCommand,
+    TimeCommand, XmpCommand,
+};
+use clap::{ArgMatches, CommandFactory, FromArgMatches};
+use commands::Negative;
+use pngmi::config::Config;
+use pngmi::error::{self, ErrorKind};
+use pngmi::PngResult;
+use std::env;
+use std::io::{self, IsTerminal};
+use std::process::ExitCode;
+use tracing::level_filters::LevelFilter;
 
-fn main() -> PngResult<()> {
-    todo!()
+/// The exit status for bad arguments and any failure without a status of its own
+const EXIT_USAGE: u8 = 1;
+
+/// The exit status of `grep` when nothing matches and of `diff` when the files differ
+const EXIT_NEGATIVE: u8 = 1;
+
+/// The exit status of `grep` and `diff` for any failure, as grep(1) and diff(1) use
+const EXIT_TROUBLE: u8 = 2;
+
+/// The exit status for each kind of failure, as listed in the `--help` output
+fn exit_code(kind: ErrorKind) -> u8 {
+    match kind {
+        ErrorKind::Other => EXIT_USAGE,
+        ErrorKind::Parse => 2,
+        ErrorKind::ChunkNotFound => 3,
+        ErrorKind::Validation => 4,
+        ErrorKind::Crypto => 5,
+        ErrorKind::Io => 6,
+    }
+}
+
+fn main() -> ExitCode {
+    let parsed = Cli::command().try_get_matches().and_then(|matches| {
+        let cli = Cli::from_arg_matches(&matches)?;
+        cli.check_conflicts(&matches)?;
+        Ok((cli, matches))
+    });
+    let (mut cli, matches) = match parsed {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            let _ = e.print();
+            // Help and version requests are reported as errors too
+            return match e.use_stderr() {
+                true => ExitCode::from(EXIT_USAGE),
+                false => ExitCode::SUCCESS,
+            };
+        }
+    };
+    if let Err(e) = load_config(&mut cli, &matches) {
+        eprintln!("Error: {}", e);
+        return ExitCode::from(EXIT_USAGE);
+    }
+    init_logging(&cli);
+    progress::init(cli.format == OutputFormat::Text && !cli.quiet);
+    let answers_no = matches!(cli.command, Command::Grep(_) | Command::Diff(_));
+    match run(cli) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) if e.is::<Negative>() => ExitCode::from(EXIT_NEGATIVE),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            match answers_no {
+                true => ExitCode::from(EXIT_TROUBLE),
+                false => ExitCode::from(exit_code(error::kind_of(e.as_ref()))),
+            }
+        }
+    }
+}
+
+/// Fill in what the command line leaves out from pngmi.toml and the user config file
+fn load_config(cli: &mut Cli, matches: &ArgMatches) -> PngResult<()> {
+    if cli.no_config {
+        return Ok(());
+    }
+    let config = Config::load(&env::current_dir()?)?;
+    cli.apply_config(config, matches)
+}
+
+/// Log to stderr at the level picked by `--verbose` or `--quiet`, warnings by default
+fn init_logging(cli: &Cli) {
+    let level = match (cli.quiet, cli.verbose) {
+        (true, _) => LevelFilter::ERROR,
+        (false, 0) => LevelFilter::WARN,
+        (false, 1) => LevelFilter::INFO,
+        (false, 2) => LevelFilter::DEBUG,
+        (false, _) => LevelFilter::TRACE,
+    };
+    tracing_subscriber::fmt()
+        .with_max_level(level)
+        .with_writer(io::stderr)
+        .with_ansi(io::stderr().is_terminal())
+        .with_target(false)
+        .without_time()
+        .init();
+}
+
+fn run(cli: Cli) -> PngResult<()> {
+    match cli.command {
+        Command::Encode(args) => commands::encode(args),
+        Command::Decode(args) => commands::decode(args, cli.format),
+        Command::Remove(args) => commands::remove(args),
+        Command::Extract(args) => commands::extract(args),
+        Command::Inject(args) => commands::inject(args),
+        Command::Hexdump(args) => commands::hexdump(args),
+        Command::Grep(args) => commands::grep(args, cli.format),
+        Command::Print(args) => commands::print(args, cli.format),
+        Command::Info(args) => commands::info(args, cli.format),
+        Command::Stats(args) => commands::stats(args, cli.format),
+        Command::Diff(args) => commands::diff(args, cli.format),
+        Command::Apply(args) => commands::apply(args),
+        Command::CopyMeta(args) => commands::copy_meta(args),
+        Command::Merge(args) => commands::merge(args),
+        Command::Normalize(args) => commands::normalize(args),
+        Command::Dedupe(args) => commands::dedupe(args),
+        Command::Split(args) => commands::split(args),
+        Command::Assemble(args) => commands::assemble(args),
+        Command::Optimize(args) => commands::optimize(args),
+        Command::Text(args) => match args.command {
+            TextCommand::Get(args) => commands::text_get(args),
+            TextCommand::Set(args) => commands::text_set(args),
+        },
+        Command::Xmp(args) => match args.command {
+            XmpCommand::Get(args) => commands::xmp_get(args),
+            XmpCommand::Set(args) => commands::xmp_set(args),
+            XmpCommand::Remove(args) => commands::xmp_remove(args),
+        },
+        Command::Time(args) => match args.command {
+            TimeCommand::Get(args) => commands::time_get(args),
+            TimeCommand::Set(args) => commands::time_set(args),
+        },
+        Command::Dpi(args) => match args.command {
+            DpiCommand::Get(args) => commands::dpi_get(args),
+            DpiCommand::Set(args) => commands::dpi_set(args),
+        },
+        Command::Gamma(args) => match args.command {
+            GammaCommand::Get(args) => commands::gamma_get(args),
+            GammaCommand::Set(args) => commands::gamma_set(args),
+        },
+        Command::Icc(args) => match args.command {
+            IccCommand::Extract(args) => commands::icc_extract(args),
+            IccCommand::Embed(args) => commands::icc_embed(args),
+        },
+        Command::Apng(args) => match args.command {
+            ApngCommand::Explode(args) => commands::apng_explode(args, cli.format),
+            ApngCommand::Build(args) => commands::apng_build(args),
+        },
+        Command::Capacity(args) => commands::capacity(args),
+        Command::Scan(args) => commands::scan(args, cli.format),
+        Command::Analyze(args) => commands::analyze(args, cli.format),
+        Command::Carve(args) => commands::carve(args, cli.format),
+        Command::Validate(args) => commands::validate(args, cli.format),
+        Command::Repair(args) => commands::repair(args),
+        Command::Strip(args) => commands::strip(args),
+        Command::Anonymize(args) => commands::anonymize(args),
+        #[cfg(feature = "tui")]
+        Command::Tui(args) => commands::tui(args),
+        #[cfg(feature = "serve")]
+        Command::Serve(args) => serve::serve(args),
+        #[cfg(all(unix, feature = "daemon"))]
+        Command::Daemon(args) => daemon::run(args),
+        #[cfg(feature = "shell")]
+        Command::Shell(args) => commands::shell(args),
+        #[cfg(feature = "watch")]
+        Command::Watch(args) => watch::watch(args),
+        Command::Completions(args) => commands::completions(args),
+        Command::Man(args) => commands::man(args),
+    }
 }
@@ -0,0 +1,117 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::compression;
+use crate::crypto::{self, KdfParams};
+use crate::envelope::{Envelope, Flags};
+use crate::png::{ChunkPosition, Png};
+use crate::segment;
+use crate::PngResult;
+
+/// Encode a text message into new chunks before IEND, encrypted if given a passphrase,
+/// returning how many chunks it took. For callers which edit a file already loaded, such as
+/// the interactive and server modes and the WASM bindings.
+pub fn embed(
+    png: &mut Png,
+    chunk_type: ChunkType,
+    message: &str,
+    passphrase: Option<&str>,
+) -> PngResult<usize> {
+    embed_envelope(png, chunk_type, Envelope::text(message), passphrase)
+}
+
+/// Encode a payload of any type as `embed` does a text message
+pub fn embed_envelope(
+    png: &mut Png,
+    chunk_type: ChunkType,
+    mut envelope: Envelope,
+    passphrase: Option<&str>,
+) -> PngResult<usize> {
+    if let Some(passphrase) = passphrase {
+        envelope.seal_metadata()?;
+        envelope.set_body(crypto::seal(
+            envelope.body(),
+            passphrase,
+            KdfParams::DEFAULT,
+        )?);
+        envelope.flags_mut().set(Flags::ENCRYPTED, true);
+    }
+    let mut position = ChunkPosition::BeforeIend;
+    let segments = segment::split(&envelope.as_bytes()?, segment::DEFAULT_MAX_CHUNK_LEN)?;
+    for data in &segments {
+        let index = png.insert_chunk_at(position, Chunk::new(chunk_type, data.clone()))?;
+        position = ChunkPosition::Index(index + 1);
+    }
+    Ok(segments.len())
+}
+
+/// The payload stored in chunks of the given type, decrypted and decompressed. Payloads
+/// encrypted to recipients are refused, as only `pngmi decode` takes identities.
+pub fn read(png: &Png, chunk_type: &str, passphrase: Option<&str>) -> PngResult<Envelope> {
+    read_within(png, chunk_type, passphrase, KdfParams::MAX)
+}
+
+/// Like `read`, but refusing a payload which asks key derivation to cost more than `limit`,
+/// for callers answering requests from others such as `pngmi serve`
+pub fn read_within(
+    png: &Png,
+    chunk_type: &str,
+    passphrase: Option<&str>,
+    limit: KdfParams,
+) -> PngResult<Envelope> {
+    let chunks = png.chunks_by_type(chunk_type).map(Chunk::data);
+    let mut envelope = Envelope::from_payload(&segment::join(chunks)?)?;
+    if envelope.flags().contains(Flags::RECIPIENTS) {
+        return Err("Message is encrypted to recipients; use `pngmi decode --identity`".into());
+    }
+    match (passphrase, envelope.flags().contains(Flags::ENCRYPTED)) {
+        (Some(passphrase), true) => {
+            envelope.set_body(crypto::open_within(envelope.body(), passphrase, limit)?);
+            envelope.flags_mut().set(Flags::ENCRYPTED, false);
+        }
+        (None, true) => return Err("Message is encrypted; pass a passphrase".into()),
+        (Some(_), false) => return Err("Message is not encrypted".into()),
+        (None, false) => {}
+    }
+    envelope.open_metadata()?;
+    compression::decompress(&mut envelope)?;
+    Ok(envelope)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::minimal_png;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_round_trip() {
+        let mut png = minimal_png();
+        let chunk_type = ChunkType::from_str("ruSt").unwrap();
+        assert_eq!(embed(&mut png, chunk_type, "Hello there", None).unwrap(), 1);
+        let types: Vec<_> = png
+            .chunks()
+            .iter()
+            .map(|c| c.chunk_type().to_string())
+            .collect();
+        assert_eq!(types, ["IHDR", "ruSt", "IEND"]);
+
+        let envelope = read(&png, "ruSt", None).unwrap();
+        assert!(envelope.is_text());
+        assert_eq!(envelope.body(), b"Hello there");
+        assert!(read(&png, "ruSt", Some("passphrase")).is_err());
+        assert!(read(&png, "noPe", None).is_err());
+    }
+
+    #[test]
+    fn test_encrypted_round_trip() {
+        let mut png = minimal_png();
+        let chunk_type = ChunkType::from_str("ruSt").unwrap();
+        embed(&mut png, chunk_type, "Hello there", Some("hunter2")).unwrap();
+        let data = png.chunk_by_type("ruSt").unwrap().data();
+        assert!(!data.windows(5).any(|window| window == b"text/"));
+
+        let envelope = read(&png, "ruSt", Some("hunter2")).unwrap();
+        assert_eq!(envelope.mime_type(), Envelope::TEXT_MIME);
+        assert_eq!(envelope.body(), b"Hello there");
+    }
+}
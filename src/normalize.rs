@@ -0,0 +1,120 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+
+/// Standard chunks which must come before PLTE
+const BEFORE_PLTE_TYPES: &[ChunkType] = &[
+    ChunkType::CHRM,
+    ChunkType::CICP,
+    ChunkType::GAMA,
+    ChunkType::ICCP,
+    ChunkType::MDCV,
+    ChunkType::CLLI,
+    ChunkType::SBIT,
+    ChunkType::SRGB,
+];
+
+/// APNG frame chunks, whose order among themselves and the image data is their meaning
+const FRAME_TYPES: &[ChunkType] = &[ChunkType::FCTL, ChunkType::FDAT];
+
+/// The part of the file a chunk belongs in, in file order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Section {
+    Header,
+    BeforePalette,
+    Palette,
+    BeforeImageData,
+    ImageData,
+    AfterImageData,
+    End,
+}
+
+/// Where a chunk goes in the canonical order: its section, then, for chunks whose position
+/// carries no meaning, their type, and otherwise their original place. Chunks of the same
+/// type keep their relative order.
+fn sort_key(
+    chunk: &Chunk,
+    index: usize,
+    after_image_data: bool,
+) -> (Section, bool, [u8; 4], usize) {
+    let chunk_type = chunk.chunk_type();
+    let section = match *chunk_type {
+        ChunkType::IHDR => Section::Header,
+        ChunkType::PLTE => Section::Palette,
+        ChunkType::IDAT => Section::ImageData,
+        ChunkType::IEND => Section::End,
+        _ if BEFORE_PLTE_TYPES.contains(chunk_type) => Section::BeforePalette,
+        // Unknown chunks may refer to the image data, so stay on their side of it
+        _ if (!chunk_type.is_standard() || FRAME_TYPES.contains(chunk_type))
+            && after_image_data =>
+        {
+            Section::AfterImageData
+        }
+        _ => Section::BeforeImageData,
+    };
+    let positional = !chunk_type.is_standard() || FRAME_TYPES.contains(chunk_type);
+    match positional {
+        true => (section, true, [0; 4], index),
+        false => (section, false, chunk_type.bytes(), index),
+    }
+}
+
+/// Put the chunks into a canonical order, recomputing every CRC, so that files with the same
+/// chunks come out byte for byte the same. Critical chunks go where the spec requires, with
+/// every IDAT chunk together, and standard ancillary chunks go before the image data, sorted
+/// by type. Unknown chunks and APNG frames keep their order and their side of the image data.
+/// Returns whether anything changed.
+pub fn normalize(png: &mut Png) -> bool {
+    let before = png.as_bytes();
+    let first_idat = png
+        .chunks()
+        .iter()
+        .position(|chunk| *chunk.chunk_type() == ChunkType::IDAT)
+        .unwrap_or(usize::MAX);
+    let mut chunks: Vec<(_, Chunk)> = png
+        .chunks()
+        .iter()
+        .enumerate()
+        .map(|(index, chunk)| {
+            let key = sort_key(chunk, index, index > first_idat);
+            (key, Chunk::new(*chunk.chunk_type(), chunk.data().to_vec()))
+        })
+        .collect();
+    chunks.sort_by_key(|(key, _)| *key);
+
+    let trailing_data = png.take_trailing_data();
+    *png = Png::from_chunks(chunks.into_iter().map(|(_, chunk)| chunk).collect());
+    png.set_trailing_data(trailing_data);
+    png.as_bytes() != before
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{png, types};
+
+    #[test]
+    fn test_normalize() {
+        let mut png = png(&[
+            ("IHDR", "h"),
+            ("tEXt", "b"),
+            ("PLTE", "p"),
+            ("gAMA", "g"),
+            ("IDAT", "1"),
+            ("tIME", "t"),
+            ("IDAT", "2"),
+            ("tEXt", "a"),
+            ("prVt", "x"),
+            ("IEND", ""),
+        ]);
+        assert!(normalize(&mut png));
+        assert_eq!(
+            types(&png),
+            ["IHDR", "gAMA", "PLTE", "tEXt", "tEXt", "tIME", "IDAT", "IDAT", "prVt", "IEND"]
+        );
+        // Chunks of the same type stay in order
+        assert_eq!(png.chunks()[3].data(), b"b");
+        assert_eq!(png.chunks()[6].data(), b"1");
+        assert!(!normalize(&mut png));
+    }
+}
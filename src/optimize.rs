@@ -0,0 +1,147 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+use crate::PngResult;
+
+/// The zlib level `optimize` recompresses at unless told otherwise
+pub const DEFAULT_LEVEL: u32 = 9;
+
+/// Metadata chunks which don't affect how the image looks
+const TEXT_TYPES: &[ChunkType] = &[ChunkType::TEXT, ChunkType::ZTXT, ChunkType::ITXT];
+
+/// How `optimize` shrinks a PNG
+#[derive(Debug, Clone, Copy)]
+pub struct OptimizeOptions {
+    /// The zlib level to recompress the image data at, from 0 to 9
+    pub level: u32,
+    /// Keep tEXt, zTXt and iTXt chunks
+    pub keep_text: bool,
+}
+
+impl Default for OptimizeOptions {
+    fn default() -> Self {
+        OptimizeOptions {
+            level: DEFAULT_LEVEL,
+            keep_text: false,
+        }
+    }
+}
+
+/// What `optimize` did to a PNG
+#[derive(Debug, Default)]
+pub struct OptimizeReport {
+    /// The metadata chunks removed, in file order
+    pub removed: Vec<Chunk>,
+    /// The number of bytes removed from after IEND
+    pub trailing_data_len: usize,
+    /// The number of IDAT chunks before and after
+    pub idat_chunks: (usize, usize),
+    /// The compressed size of the image data before and after
+    pub image_data_len: (usize, usize),
+    /// The size of the file before and after
+    pub file_len: (usize, usize),
+}
+
+impl OptimizeReport {
+    /// The number of bytes saved
+    pub fn saved(&self) -> usize {
+        self.file_len.0.saturating_sub(self.file_len.1)
+    }
+}
+
+/// Whether the chunk is metadata which can go without changing how the image looks: text,
+/// the modification time, signatures which the other changes would break anyway, and
+/// unknown ancillary chunks
+fn is_removable(chunk: &Chunk, keep_text: bool) -> bool {
+    let chunk_type = chunk.chunk_type();
+    if chunk_type.is_critical() {
+        return false;
+    }
+    if TEXT_TYPES.contains(chunk_type) {
+        return !keep_text;
+    }
+    [ChunkType::TIME, ChunkType::DSIG].contains(chunk_type) || !chunk_type.is_standard()
+}
+
+/// Shrink the PNG without changing its pixels: remove metadata and any data after IEND, then
+/// recompress the image data into a single IDAT chunk, keeping the old compressed data if it
+/// was already smaller
+pub fn optimize(png: &mut Png, options: OptimizeOptions) -> PngResult<OptimizeReport> {
+    let mut report = OptimizeReport {
+        file_len: (png.as_bytes().len(), 0),
+        ..OptimizeReport::default()
+    };
+    report.removed = png.retain_chunks(|chunk| !is_removable(chunk, options.keep_text));
+    report.trailing_data_len = png.take_trailing_data().len();
+
+    let compressed = png.compressed_idat_data()?;
+    let idat_chunks = png.chunks_by_type("IDAT").count();
+    let raw = png.idat_data()?;
+    png.set_idat_data(&raw, options.level, Chunk::MAX_DATA_LEN)?;
+    let data = png.compressed_idat_data()?;
+    if data.len() >= compressed.len() {
+        png.set_compressed_idat_data(&compressed, Chunk::MAX_DATA_LEN)?;
+    }
+    report.idat_chunks = (idat_chunks, png.chunks_by_type("IDAT").count());
+    report.image_data_len = (compressed.len(), data.len().min(compressed.len()));
+    report.file_len.1 = png.as_bytes().len();
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{chunk, types};
+    use flate2::write::ZlibEncoder;
+    use std::io::Write;
+
+    /// A 4x4 8-bit greyscale image whose data is stored uncompressed across two IDAT chunks
+    fn testing_png() -> Png {
+        let mut encoder = ZlibEncoder::new(vec![], flate2::Compression::none());
+        encoder.write_all(&[0; 20]).unwrap();
+        let data = encoder.finish().unwrap();
+        let mut png = Png::from_chunks(vec![
+            chunk("IHDR", &[0, 0, 0, 4, 0, 0, 0, 4, 8, 0, 0, 0, 0]),
+            chunk("gAMA", &[0, 0, 177, 143]),
+            chunk("tEXt", b"Title\0Test"),
+            chunk("IDAT", &data[..10]),
+            chunk("IDAT", &data[10..]),
+            chunk("tIME", &[7, 234, 1, 1, 0, 0, 0]),
+            chunk("prVt", &[1]),
+            chunk("IEND", &[]),
+        ]);
+        png.set_trailing_data(b"extra".to_vec());
+        png
+    }
+
+    #[test]
+    fn test_optimize() {
+        let mut png = testing_png();
+        let report = optimize(&mut png, OptimizeOptions::default()).unwrap();
+        assert_eq!(types(&png), ["IHDR", "gAMA", "IDAT", "IEND"]);
+        assert_eq!(report.removed.len(), 3);
+        assert_eq!(report.trailing_data_len, 5);
+        assert_eq!(report.idat_chunks, (2, 1));
+        assert!(report.image_data_len.1 < report.image_data_len.0);
+        assert_eq!(report.saved(), report.file_len.0 - png.as_bytes().len());
+        assert_eq!(png.idat_data().unwrap(), [0; 20]);
+    }
+
+    #[test]
+    fn test_optimize_keep_text() {
+        let mut png = testing_png();
+        let options = OptimizeOptions {
+            keep_text: true,
+            ..OptimizeOptions::default()
+        };
+        optimize(&mut png, options).unwrap();
+        assert_eq!(types(&png), ["IHDR", "gAMA", "tEXt", "IDAT", "IEND"]);
+    }
+
+    #[test]
+    fn test_optimize_rejects_corrupt_image_data() {
+        let mut png = testing_png();
+        png.chunks_mut()[4] = chunk("IDAT", &[]);
+        assert!(optimize(&mut png, OptimizeOptions::default()).is_err());
+    }
+}
@@ -0,0 +1,176 @@
+use crate::PngResult;
+use std::ffi::OsString;
+use std::fs::{self, File, FileTimes, Metadata};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use tempfile::NamedTempFile;
+use tracing::warn;
+
+/// How `write_atomic` treats the file it replaces
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WriteOptions<'a> {
+    /// Keep the original alongside the new file, named with this suffix
+    pub backup_suffix: Option<&'a str>,
+    /// Give the new file the original's modification and access times and, on Unix, its
+    /// owner and group. Permissions are always kept.
+    pub preserve: bool,
+}
+
+/// Replace the file at the given path with the bytes so that, even if the process dies part
+/// way through, the path holds either the old contents or the new ones and never a mix.
+///
+/// The bytes go to a temporary file in the same directory, which is synced to disk and then
+/// renamed over the original. With a backup suffix the original is first kept alongside it,
+/// e.g. `image.png~`.
+pub fn write_atomic(path: &Path, bytes: &[u8], options: &WriteOptions) -> PngResult<()> {
+    // Write through symlinks rather than replacing them with a regular file
+    let path = match fs::canonicalize(path) {
+        Ok(target) => target,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => path.to_path_buf(),
+        Err(e) => return Err(e.into()),
+    };
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+
+    let mut temp = NamedTempFile::new_in(dir)?;
+    temp.write_all(bytes)?;
+    if let Ok(metadata) = fs::metadata(&path) {
+        temp.as_file().set_permissions(metadata.permissions())?;
+        if options.preserve {
+            preserve(temp.as_file(), &metadata)?;
+        }
+    }
+    temp.as_file().sync_all()?;
+
+    if let Some(suffix) = options.backup_suffix {
+        if path.exists() {
+            backup(&path, suffix)?;
+        }
+    }
+    temp.persist(&path).map_err(|e| e.error)?;
+    sync_dir(dir)?;
+    Ok(())
+}
+
+/// Copy the timestamps and ownership of the original file to the new one
+fn preserve(file: &File, original: &Metadata) -> io::Result<()> {
+    file.set_times(
+        FileTimes::new()
+            .set_accessed(original.accessed()?)
+            .set_modified(original.modified()?),
+    )?;
+    preserve_owner(file, original)
+}
+
+/// Only privileged users can give files away, so failing to is a warning rather than an error
+#[cfg(unix)]
+fn preserve_owner(file: &File, original: &Metadata) -> io::Result<()> {
+    use std::os::unix::fs::{fchown, MetadataExt};
+
+    let current = file.metadata()?;
+    if (current.uid(), current.gid()) == (original.uid(), original.gid()) {
+        return Ok(());
+    }
+    if let Err(e) = fchown(file, Some(original.uid()), Some(original.gid())) {
+        warn!(
+            "Couldn't preserve the owner {}:{}: {}",
+            original.uid(),
+            original.gid(),
+            e
+        );
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn preserve_owner(_file: &File, _original: &Metadata) -> io::Result<()> {
+    Ok(())
+}
+
+/// The path a backup of the file is kept at
+pub fn backup_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+/// Keep a copy of the file as it is now, replacing any earlier backup. A hard link is enough,
+/// as the original is about to be replaced rather than modified.
+fn backup(path: &Path, suffix: &str) -> io::Result<()> {
+    let backup = backup_path(path, suffix);
+    match fs::remove_file(&backup) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    if fs::hard_link(path, &backup).is_err() {
+        fs::copy(path, &backup)?;
+    }
+    Ok(())
+}
+
+/// Make the rename durable. Only some platforms need, or allow, directories to be synced.
+#[cfg(unix)]
+fn sync_dir(dir: &Path) -> io::Result<()> {
+    File::open(dir)?.sync_all()
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_dir: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn test_write_atomic() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("image.png");
+
+        let backup = |suffix| WriteOptions {
+            backup_suffix: Some(suffix),
+            preserve: false,
+        };
+        write_atomic(&path, b"first", &backup("~")).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"first");
+        assert!(!backup_path(&path, "~").exists());
+
+        write_atomic(&path, b"second", &backup(".bak")).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"second");
+        assert_eq!(fs::read(backup_path(&path, ".bak")).unwrap(), b"first");
+
+        write_atomic(&path, b"third", &WriteOptions::default()).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"third");
+        assert_eq!(fs::read(backup_path(&path, ".bak")).unwrap(), b"first");
+        // Nothing but the file and its backup is left behind
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn test_preserve() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("image.png");
+        fs::write(&path, b"original").unwrap();
+        let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+
+        let options = WriteOptions {
+            preserve: true,
+            ..WriteOptions::default()
+        };
+        write_atomic(&path, b"edited", &options).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().modified().unwrap(), modified);
+
+        write_atomic(&path, b"edited again", &WriteOptions::default()).unwrap();
+        assert_ne!(fs::metadata(&path).unwrap().modified().unwrap(), modified);
+    }
+}
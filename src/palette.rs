@@ -0,0 +1,330 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::ihdr::{ColorType, Ihdr};
+use crate::png::Png;
+use crate::{PngError, PngResult};
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// The most entries a palette can have, as indexes are at most 8 bits
+pub const MAX_PALETTE_ENTRIES: usize = 256;
+
+/// An RGB colour from a palette
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rgb {
+    pub red: u8,
+    pub green: u8,
+    pub blue: u8,
+}
+
+impl Display for Rgb {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}", self.red, self.green, self.blue)
+    }
+}
+
+/// The colours of an indexed image, as stored in a PLTE chunk
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Palette {
+    entries: Vec<Rgb>,
+}
+
+impl Palette {
+    pub const CHUNK_TYPE: &'static str = "PLTE";
+
+    pub fn new(entries: Vec<Rgb>) -> PngResult<Self> {
+        if entries.is_empty() || entries.len() > MAX_PALETTE_ENTRIES {
+            return Err(format!(
+                "Palette has {} entries but must have 1 to {}",
+                entries.len(),
+                MAX_PALETTE_ENTRIES
+            )
+            .into());
+        }
+        Ok(Palette { entries })
+    }
+
+    pub fn entries(&self) -> &[Rgb] {
+        &self.entries
+    }
+
+    /// Check the palette suits the image: only colour images may have one, and an indexed
+    /// image's palette can't have more entries than its bit depth can index
+    pub fn check(&self, ihdr: &Ihdr) -> PngResult<()> {
+        match ihdr.color_type {
+            ColorType::Grayscale | ColorType::GrayscaleAlpha => {
+                Err(format!("PLTE chunk is not allowed in {} images", ihdr.color_type).into())
+            }
+            ColorType::Indexed if self.entries.len() > 1 << ihdr.bit_depth => Err(format!(
+                "Palette has {} entries but a bit depth of {} can only index {}",
+                self.entries.len(),
+                ihdr.bit_depth,
+                1 << ihdr.bit_depth
+            )
+            .into()),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn as_bytes(&self) -> Vec<u8> {
+        self.entries
+            .iter()
+            .flat_map(|rgb| [rgb.red, rgb.green, rgb.blue])
+            .collect()
+    }
+
+    pub fn to_chunk(&self) -> PngResult<Chunk> {
+        Ok(Chunk::new(
+            ChunkType::from_str(Self::CHUNK_TYPE)?,
+            self.as_bytes(),
+        ))
+    }
+}
+
+impl TryFrom<&[u8]> for Palette {
+    type Error = PngError;
+
+    /// Decode PLTE chunk data
+    fn try_from(value: &[u8]) -> PngResult<Self> {
+        if !value.len().is_multiple_of(3) {
+            return Err(format!(
+                "PLTE data is {} bytes long, which is not a multiple of 3",
+                value.len()
+            )
+            .into());
+        }
+        Palette::new(
+            value
+                .chunks_exact(3)
+                .map(|rgb| Rgb {
+                    red: rgb[0],
+                    green: rgb[1],
+                    blue: rgb[2],
+                })
+                .collect(),
+        )
+    }
+}
+
+/// Which pixels are transparent, as stored in a tRNS chunk. The layout depends on the
+/// image's colour type.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Transparency {
+    /// The grey level which is fully transparent
+    Gray(u16),
+    /// The colour which is fully transparent, as red, green and blue samples
+    Rgb(u16, u16, u16),
+    /// The alpha of each palette entry in order; entries past the end are opaque
+    Alpha(Vec<u8>),
+}
+
+impl Transparency {
+    pub const CHUNK_TYPE: &'static str = "tRNS";
+
+    /// Decode tRNS chunk data for an image with the given header and palette size
+    pub fn decode(data: &[u8], ihdr: &Ihdr, palette_len: Option<usize>) -> PngResult<Self> {
+        let samples = |count: usize| -> PngResult<Vec<u16>> {
+            if data.len() != count * 2 {
+                return Err(format!(
+                    "tRNS data is {} bytes long but should be {} for {} images",
+                    data.len(),
+                    count * 2,
+                    ihdr.color_type
+                )
+                .into());
+            }
+            let samples: Vec<u16> = data
+                .chunks_exact(2)
+                .map(|sample| u16::from_be_bytes([sample[0], sample[1]]))
+                .collect();
+            let max = (1u32 << ihdr.bit_depth) - 1;
+            match samples.iter().find(|sample| **sample as u32 > max) {
+                Some(sample) => Err(format!(
+                    "tRNS sample {} is too large for a bit depth of {}",
+                    sample, ihdr.bit_depth
+                )
+                .into()),
+                None => Ok(samples),
+            }
+        };
+        match ihdr.color_type {
+            ColorType::Grayscale => Ok(Transparency::Gray(samples(1)?[0])),
+            ColorType::Truecolor => {
+                let rgb = samples(3)?;
+                Ok(Transparency::Rgb(rgb[0], rgb[1], rgb[2]))
+            }
+            ColorType::Indexed => {
+                let palette_len = palette_len.ok_or("tRNS chunk found but no PLTE chunk")?;
+                if data.len() > palette_len {
+                    return Err(format!(
+                        "tRNS has {} entries but the palette only has {}",
+                        data.len(),
+                        palette_len
+                    )
+                    .into());
+                }
+                Ok(Transparency::Alpha(data.to_vec()))
+            }
+            ColorType::GrayscaleAlpha | ColorType::TruecolorAlpha => {
+                Err(format!("tRNS chunk is not allowed in {} images", ihdr.color_type).into())
+            }
+        }
+    }
+
+    pub fn as_bytes(&self) -> Vec<u8> {
+        match self {
+            Transparency::Gray(gray) => gray.to_be_bytes().to_vec(),
+            Transparency::Rgb(red, green, blue) => [red, green, blue]
+                .iter()
+                .flat_map(|sample| sample.to_be_bytes())
+                .collect(),
+            Transparency::Alpha(alphas) => alphas.clone(),
+        }
+    }
+
+    pub fn to_chunk(&self) -> PngResult<Chunk> {
+        Ok(Chunk::new(
+            ChunkType::from_str(Self::CHUNK_TYPE)?,
+            self.as_bytes(),
+        ))
+    }
+
+    /// The alpha of the given palette entry, which is opaque unless listed
+    pub fn alpha(&self, index: usize) -> u8 {
+        match self {
+            Transparency::Alpha(alphas) => alphas.get(index).copied().unwrap_or(u8::MAX),
+            _ => u8::MAX,
+        }
+    }
+}
+
+/// How often each palette entry is used in the image, as stored in a hIST chunk
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Histogram {
+    frequencies: Vec<u16>,
+}
+
+impl Histogram {
+    pub const CHUNK_TYPE: &'static str = "hIST";
+
+    /// Decode hIST chunk data, which needs one frequency for every palette entry
+    pub fn decode(data: &[u8], palette_len: Option<usize>) -> PngResult<Self> {
+        let palette_len = palette_len.ok_or("hIST chunk found but no PLTE chunk")?;
+        if data.len() != palette_len * 2 {
+            return Err(format!(
+                "hIST data is {} bytes long but should be {} for a {} entry palette",
+                data.len(),
+                palette_len * 2,
+                palette_len
+            )
+            .into());
+        }
+        Ok(Histogram {
+            frequencies: data
+                .chunks_exact(2)
+                .map(|frequency| u16::from_be_bytes([frequency[0], frequency[1]]))
+                .collect(),
+        })
+    }
+
+    /// The approximate usage of each palette entry, scaled to fit 16 bits
+    pub fn frequencies(&self) -> &[u16] {
+        &self.frequencies
+    }
+
+    pub fn as_bytes(&self) -> Vec<u8> {
+        self.frequencies
+            .iter()
+            .flat_map(|frequency| frequency.to_be_bytes())
+            .collect()
+    }
+
+    pub fn to_chunk(&self) -> PngResult<Chunk> {
+        Ok(Chunk::new(
+            ChunkType::from_str(Self::CHUNK_TYPE)?,
+            self.as_bytes(),
+        ))
+    }
+}
+
+/// Decode the palette of the PNG, if it has one, checking it against the IHDR chunk
+pub fn get_palette(png: &Png) -> PngResult<Option<Palette>> {
+    let ihdr = Ihdr::try_from(png)?;
+    let Some(chunk) = png.chunk_by_type(Palette::CHUNK_TYPE) else {
+        return match ihdr.color_type {
+            ColorType::Indexed => Err("Indexed image has no PLTE chunk".into()),
+            _ => Ok(None),
+        };
+    };
+    let palette = Palette::try_from(chunk.data())?;
+    palette.check(&ihdr)?;
+    Ok(Some(palette))
+}
+
+/// Decode the hIST chunk of the PNG, if it has one
+pub fn get_histogram(png: &Png) -> PngResult<Option<Histogram>> {
+    let Some(chunk) = png.chunk_by_type(Histogram::CHUNK_TYPE) else {
+        return Ok(None);
+    };
+    let palette_len = get_palette(png)?.map(|palette| palette.entries().len());
+    Histogram::decode(chunk.data(), palette_len).map(Some)
+}
+
+/// Decode the tRNS chunk of the PNG, if it has one
+pub fn get_transparency(png: &Png) -> PngResult<Option<Transparency>> {
+    let Some(chunk) = png.chunk_by_type(Transparency::CHUNK_TYPE) else {
+        return Ok(None);
+    };
+    let ihdr = Ihdr::try_from(png)?;
+    let palette_len = get_palette(png)?.map(|palette| palette.entries().len());
+    Transparency::decode(chunk.data(), &ihdr, palette_len).map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::ihdr;
+
+    #[test]
+    fn test_palette() {
+        let palette = Palette::try_from(&[255, 0, 0, 0, 0, 255, 1, 2, 3][..]).unwrap();
+        assert_eq!(palette.entries().len(), 3);
+        assert_eq!(palette.entries()[1].to_string(), "#0000ff");
+        assert_eq!(palette.as_bytes(), vec![255, 0, 0, 0, 0, 255, 1, 2, 3]);
+
+        assert!(palette.check(&ihdr(2, ColorType::Indexed)).is_ok());
+        assert!(palette.check(&ihdr(1, ColorType::Indexed)).is_err());
+        assert!(palette.check(&ihdr(8, ColorType::Grayscale)).is_err());
+        assert!(palette.check(&ihdr(8, ColorType::Truecolor)).is_ok());
+
+        assert!(Palette::try_from(&[0, 0][..]).is_err());
+        assert!(Palette::try_from(&[][..]).is_err());
+        assert!(Palette::try_from(&[0; 257 * 3][..]).is_err());
+    }
+
+    #[test]
+    fn test_histogram() {
+        let histogram = Histogram::decode(&[0, 1, 1, 0], Some(2)).unwrap();
+        assert_eq!(histogram.frequencies(), &[1, 256]);
+        assert_eq!(histogram.as_bytes(), vec![0, 1, 1, 0]);
+        assert!(Histogram::decode(&[0, 1], Some(2)).is_err());
+        assert!(Histogram::decode(&[0, 1], None).is_err());
+    }
+
+    #[test]
+    fn test_transparency() {
+        let gray = Transparency::decode(&[0, 3], &ihdr(2, ColorType::Grayscale), None).unwrap();
+        assert_eq!(gray, Transparency::Gray(3));
+        assert!(Transparency::decode(&[0, 4], &ihdr(2, ColorType::Grayscale), None).is_err());
+
+        let rgb = Transparency::decode(&[0, 1, 0, 2, 0, 3], &ihdr(8, ColorType::Truecolor), None);
+        assert_eq!(rgb.unwrap(), Transparency::Rgb(1, 2, 3));
+
+        let indexed = ihdr(8, ColorType::Indexed);
+        let alpha = Transparency::decode(&[0, 128], &indexed, Some(3)).unwrap();
+        assert_eq!((alpha.alpha(1), alpha.alpha(2)), (128, 255));
+        assert!(Transparency::decode(&[0; 4], &indexed, Some(3)).is_err());
+        assert!(Transparency::decode(&[0], &indexed, None).is_err());
+        assert!(Transparency::decode(&[0, 0], &ihdr(8, ColorType::GrayscaleAlpha), None).is_err());
+    }
+}
@@ -0,0 +1,364 @@
+use crate::chunk::{Chunk, ChunkRef};
+use crate::chunk_type::ChunkType;
+use crate::diff::{self, ChunkChange};
+use crate::error::{error, ErrorKind};
+use crate::png::{Png, PngRef};
+use crate::PngResult;
+use base64::prelude::*;
+use serde_json::{json, Value};
+use std::str::FromStr;
+
+/// The version of the patch format written by `Patch::to_json`
+pub const PATCH_VERSION: u64 = 1;
+
+/// A chunk picked out by its type and which occurrence of that type it is, counting from 1,
+/// so that a patch applies to any file with the same chunk layout rather than one exact file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkSelector {
+    pub chunk_type: ChunkType,
+    pub nth: usize,
+}
+
+impl ChunkSelector {
+    /// The selector for the chunk at the given index
+    fn of(chunk_types: &[&ChunkType], index: usize) -> Self {
+        let chunk_type = *chunk_types[index];
+        let nth = chunk_types[..=index]
+            .iter()
+            .filter(|other| ***other == chunk_type)
+            .count();
+        ChunkSelector { chunk_type, nth }
+    }
+
+    /// The index of the selected chunk
+    fn find(&self, png: &Png) -> PngResult<usize> {
+        png.chunks()
+            .iter()
+            .enumerate()
+            .filter(|(_, chunk)| *chunk.chunk_type() == self.chunk_type)
+            .nth(self.nth - 1)
+            .map(|(index, _)| index)
+            .ok_or_else(|| {
+                error(
+                    ErrorKind::ChunkNotFound,
+                    format!(
+                        "The patch needs {} chunk {}, which isn't in the file",
+                        self.chunk_type, self.nth
+                    ),
+                )
+            })
+    }
+
+    fn to_json(self) -> Value {
+        json!({ "type": self.chunk_type.to_string(), "nth": self.nth })
+    }
+
+    fn from_json(value: &Value) -> PngResult<Self> {
+        let nth = value["nth"]
+            .as_u64()
+            .filter(|nth| *nth > 0)
+            .ok_or("Patch chunk is missing its nth")?;
+        Ok(ChunkSelector {
+            chunk_type: chunk_type_from_json(&value["type"])?,
+            nth: nth as usize,
+        })
+    }
+}
+
+/// One step of a patch
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Operation {
+    /// Replace the data of a chunk
+    Replace { chunk: ChunkSelector, data: Vec<u8> },
+    /// Remove a chunk
+    Remove { chunk: ChunkSelector },
+    /// Insert a new chunk just after the given one, or first if there is none
+    Insert {
+        after: Option<ChunkSelector>,
+        chunk_type: ChunkType,
+        data: Vec<u8>,
+    },
+    /// Replace the bytes following the IEND chunk
+    SetTrailingData { data: Vec<u8> },
+}
+
+/// The chunk insertions, removals and replacements which turn one PNG into another, in the
+/// order they are applied
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Patch {
+    pub operations: Vec<Operation>,
+}
+
+impl Patch {
+    /// The patch which turns `old` into `new`
+    pub fn between(old: &PngRef, new: &PngRef) -> Self {
+        let changes = diff::chunk_changes(old, new);
+        let patch = Self::from_changes(old, new, &changes, true);
+        // Chunks rewritten in place can end up in a different order to `new` when several
+        // types change at once, in which case they are removed and inserted afresh instead
+        let mut patched = old.to_png();
+        match patch.apply(&mut patched) {
+            Ok(()) if patched.as_bytes() == new.to_png().as_bytes() => patch,
+            _ => Self::from_changes(old, new, &changes, false),
+        }
+    }
+
+    fn from_changes(
+        old: &PngRef,
+        new: &PngRef,
+        changes: &[ChunkChange],
+        rewrite_in_place: bool,
+    ) -> Self {
+        let old_types: Vec<&ChunkType> = old.chunks().iter().map(ChunkRef::chunk_type).collect();
+        let new_types: Vec<&ChunkType> = new.chunks().iter().map(ChunkRef::chunk_type).collect();
+        let mut replaced = vec![];
+        let mut removed = vec![];
+        let mut added = vec![];
+        for change in changes {
+            match *change {
+                ChunkChange::Rewritten {
+                    old_index, index, ..
+                } if rewrite_in_place => replaced.push((old_index, index)),
+                ChunkChange::Rewritten {
+                    old_index, index, ..
+                } => {
+                    removed.push(old_index);
+                    added.push(index);
+                }
+                ChunkChange::Removed { index, .. } => removed.push(index),
+                ChunkChange::Added { index, .. } => added.push(index),
+            }
+        }
+        // Removing from the end first leaves the occurrence counts of earlier chunks alone,
+        // and inserting from the start means every chunk an insertion follows is in place
+        removed.sort_unstable_by(|a, b| b.cmp(a));
+        added.sort_unstable();
+
+        let mut operations = vec![];
+        for (old_index, index) in replaced {
+            operations.push(Operation::Replace {
+                chunk: ChunkSelector::of(&old_types, old_index),
+                data: new.chunks()[index].data().to_vec(),
+            });
+        }
+        for index in removed {
+            operations.push(Operation::Remove {
+                chunk: ChunkSelector::of(&old_types, index),
+            });
+        }
+        for index in added {
+            operations.push(Operation::Insert {
+                after: index
+                    .checked_sub(1)
+                    .map(|after| ChunkSelector::of(&new_types, after)),
+                chunk_type: *new_types[index],
+                data: new.chunks()[index].data().to_vec(),
+            });
+        }
+        if old.trailing_data() != new.trailing_data() {
+            operations.push(Operation::SetTrailingData {
+                data: new.trailing_data().to_vec(),
+            });
+        }
+        Patch { operations }
+    }
+
+    /// Apply each operation in turn, failing if a chunk one refers to isn't in the file
+    pub fn apply(&self, png: &mut Png) -> PngResult<()> {
+        for operation in &self.operations {
+            match operation {
+                Operation::Replace { chunk, data } => {
+                    let index = chunk.find(png)?;
+                    png.chunks_mut()[index] = Chunk::new(chunk.chunk_type, data.clone());
+                }
+                Operation::Remove { chunk } => {
+                    let index = chunk.find(png)?;
+                    png.remove_chunk_at(index);
+                }
+                Operation::Insert {
+                    after,
+                    chunk_type,
+                    data,
+                } => {
+                    let index = match after {
+                        Some(after) => after.find(png)? + 1,
+                        None => 0,
+                    };
+                    png.insert_chunk(index, Chunk::new(*chunk_type, data.clone()));
+                }
+                Operation::SetTrailingData { data } => png.set_trailing_data(data.clone()),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn to_json(&self) -> Value {
+        let operations: Vec<Value> = self
+            .operations
+            .iter()
+            .map(|operation| match operation {
+                Operation::Replace { chunk, data } => json!({
+                    "op": "replace",
+                    "chunk": chunk.to_json(),
+                    "data": BASE64_STANDARD.encode(data),
+                }),
+                Operation::Remove { chunk } => json!({ "op": "remove", "chunk": chunk.to_json() }),
+                Operation::Insert {
+                    after,
+                    chunk_type,
+                    data,
+                } => json!({
+                    "op": "insert",
+                    "after": after.map(ChunkSelector::to_json),
+                    "type": chunk_type.to_string(),
+                    "data": BASE64_STANDARD.encode(data),
+                }),
+                Operation::SetTrailingData { data } => json!({
+                    "op": "set_trailing_data",
+                    "data": BASE64_STANDARD.encode(data),
+                }),
+            })
+            .collect();
+        json!({ "version": PATCH_VERSION, "operations": operations })
+    }
+
+    pub fn from_json(value: &Value) -> PngResult<Self> {
+        match value["version"].as_u64() {
+            Some(PATCH_VERSION) => {}
+            Some(version) => return Err(format!("Unsupported patch version {}", version).into()),
+            None => return Err("Patch is missing its version".into()),
+        }
+        let operations = value["operations"]
+            .as_array()
+            .ok_or("Patch is missing its operations")?
+            .iter()
+            .map(|operation| {
+                let data = || -> PngResult<Vec<u8>> {
+                    let data = operation["data"]
+                        .as_str()
+                        .ok_or("Patch operation is missing its data")?;
+                    Ok(BASE64_STANDARD.decode(data)?)
+                };
+                Ok(match operation["op"].as_str() {
+                    Some("replace") => Operation::Replace {
+                        chunk: ChunkSelector::from_json(&operation["chunk"])?,
+                        data: data()?,
+                    },
+                    Some("remove") => Operation::Remove {
+                        chunk: ChunkSelector::from_json(&operation["chunk"])?,
+                    },
+                    Some("insert") => Operation::Insert {
+                        after: match &operation["after"] {
+                            Value::Null => None,
+                            after => Some(ChunkSelector::from_json(after)?),
+                        },
+                        chunk_type: chunk_type_from_json(&operation["type"])?,
+                        data: data()?,
+                    },
+                    Some("set_trailing_data") => Operation::SetTrailingData { data: data()? },
+                    other => return Err(format!("Unknown patch operation {:?}", other).into()),
+                })
+            })
+            .collect::<PngResult<Vec<_>>>()?;
+        Ok(Patch { operations })
+    }
+}
+
+fn chunk_type_from_json(value: &Value) -> PngResult<ChunkType> {
+    ChunkType::from_str(value.as_str().ok_or("Patch chunk is missing its type")?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+
+    fn png(chunks: &[(&str, &str)], trailing_data: &[u8]) -> Png {
+        let mut png = test_util::png(chunks);
+        png.set_trailing_data(trailing_data.to_vec());
+        png
+    }
+
+    /// Check that the patch between the two turns the first into the second
+    fn round_trip(old: &Png, new: &Png) -> Patch {
+        let patch = Patch::between(&old.borrowed(), &new.borrowed());
+        let patch = Patch::from_json(&patch.to_json()).unwrap();
+        let mut patched = old.borrowed().to_png();
+        patch.apply(&mut patched).unwrap();
+        assert_eq!(patched.as_bytes(), new.as_bytes());
+        patch
+    }
+
+    #[test]
+    fn test_patch() {
+        let old = png(
+            &[
+                ("IHDR", "h"),
+                ("tEXt", "a"),
+                ("tIME", "t"),
+                ("tEXt", "b"),
+                ("IDAT", "i"),
+                ("IEND", ""),
+            ],
+            b"",
+        );
+        let new = png(
+            &[
+                ("IHDR", "h"),
+                ("tEXt", "a"),
+                ("tEXt", "c"),
+                ("zTXt", "z"),
+                ("IDAT", "i"),
+                ("IEND", ""),
+            ],
+            b"tail",
+        );
+        let patch = round_trip(&old, &new);
+        let text = ChunkType::from_str("tEXt").unwrap();
+        assert_eq!(
+            patch.operations[0],
+            Operation::Replace {
+                chunk: ChunkSelector {
+                    chunk_type: text,
+                    nth: 2
+                },
+                data: b"c".to_vec()
+            }
+        );
+        assert_eq!(patch.operations.len(), 4);
+
+        // The same patch applies to another file with the same layout
+        let mut other = png(
+            &[
+                ("IHDR", "x"),
+                ("tEXt", "y"),
+                ("tIME", "u"),
+                ("tEXt", "w"),
+                ("IDAT", "v"),
+                ("IEND", ""),
+            ],
+            b"",
+        );
+        patch.apply(&mut other).unwrap();
+        assert_eq!(other.chunks()[2].data(), b"c");
+        assert_eq!(other.chunks()[3].chunk_type().to_string(), "zTXt");
+
+        // Rewriting two types in swapped places falls back to removing and inserting them
+        let swapped = png(
+            &[
+                ("IHDR", "h"),
+                ("tIME", "s"),
+                ("tEXt", "d"),
+                ("IDAT", "i"),
+                ("IEND", ""),
+            ],
+            b"",
+        );
+        round_trip(&old, &swapped);
+        assert!(round_trip(&old, &old).operations.is_empty());
+
+        let mut missing = png(&[("IHDR", "h"), ("IEND", "")], b"");
+        assert!(patch.apply(&mut missing).is_err());
+        assert!(Patch::from_json(&json!({ "version": 2, "operations": [] })).is_err());
+    }
+}
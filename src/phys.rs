@@ -0,0 +1,180 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+use crate::{PngError, PngResult};
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// The number of metres in an inch, for converting between DPI and pixels per metre
+const METRES_PER_INCH: f64 = 0.0254;
+
+/// The unit of a pHYs chunk's pixel densities
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PhysUnit {
+    /// Only the pixel aspect ratio is known
+    Unknown,
+    Metre,
+}
+
+/// The intended pixel size or aspect ratio of the image, as stored in a pHYs chunk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PhysChunk {
+    pub x_pixels_per_unit: u32,
+    pub y_pixels_per_unit: u32,
+    pub unit: PhysUnit,
+}
+
+impl PhysChunk {
+    pub const CHUNK_TYPE: &'static str = "pHYs";
+
+    /// The length of the pHYs chunk data
+    pub const LEN: usize = 9;
+
+    /// A pixel density given in dots per inch, converted to the pixels per metre PNG stores
+    pub fn from_dpi(x_dpi: f64, y_dpi: f64) -> PngResult<Self> {
+        let to_ppm = |dpi: f64| -> PngResult<u32> {
+            let ppm = (dpi / METRES_PER_INCH).round();
+            if !(1.0..=i32::MAX as f64).contains(&ppm) {
+                return Err(format!("{} DPI is out of range", dpi).into());
+            }
+            Ok(ppm as u32)
+        };
+        Ok(PhysChunk {
+            x_pixels_per_unit: to_ppm(x_dpi)?,
+            y_pixels_per_unit: to_ppm(y_dpi)?,
+            unit: PhysUnit::Metre,
+        })
+    }
+
+    /// The horizontal and vertical dots per inch, if the unit is known
+    pub fn dpi(&self) -> Option<(f64, f64)> {
+        match self.unit {
+            PhysUnit::Unknown => None,
+            PhysUnit::Metre => Some((
+                self.x_pixels_per_unit as f64 * METRES_PER_INCH,
+                self.y_pixels_per_unit as f64 * METRES_PER_INCH,
+            )),
+        }
+    }
+
+    pub fn as_bytes(&self) -> [u8; Self::LEN] {
+        let mut bytes = [0; Self::LEN];
+        bytes[..4].copy_from_slice(&self.x_pixels_per_unit.to_be_bytes());
+        bytes[4..8].copy_from_slice(&self.y_pixels_per_unit.to_be_bytes());
+        bytes[8] = match self.unit {
+            PhysUnit::Unknown => 0,
+            PhysUnit::Metre => 1,
+        };
+        bytes
+    }
+
+    pub fn to_chunk(&self) -> PngResult<Chunk> {
+        Ok(Chunk::new(
+            ChunkType::from_str(Self::CHUNK_TYPE)?,
+            self.as_bytes().to_vec(),
+        ))
+    }
+}
+
+impl TryFrom<&[u8]> for PhysChunk {
+    type Error = PngError;
+
+    /// Decode pHYs chunk data
+    fn try_from(value: &[u8]) -> PngResult<Self> {
+        let bytes = <[u8; Self::LEN]>::try_from(value).map_err(|_| {
+            format!(
+                "pHYs data is {} bytes long but should be {}",
+                value.len(),
+                Self::LEN
+            )
+        })?;
+        let unit = match bytes[8] {
+            0 => PhysUnit::Unknown,
+            1 => PhysUnit::Metre,
+            unit => return Err(format!("Unknown pHYs unit {}", unit).into()),
+        };
+        let read_u32 = |start: usize| {
+            u32::from_be_bytes([
+                bytes[start],
+                bytes[start + 1],
+                bytes[start + 2],
+                bytes[start + 3],
+            ])
+        };
+        Ok(PhysChunk {
+            x_pixels_per_unit: read_u32(0),
+            y_pixels_per_unit: read_u32(4),
+            unit,
+        })
+    }
+}
+
+impl Display for PhysChunk {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.dpi() {
+            Some((x_dpi, y_dpi)) => write!(
+                f,
+                "{:.0} x {:.0} dpi ({} x {} pixels per metre)",
+                x_dpi, y_dpi, self.x_pixels_per_unit, self.y_pixels_per_unit
+            ),
+            None => write!(
+                f,
+                "aspect ratio {}:{} (no physical unit)",
+                self.x_pixels_per_unit, self.y_pixels_per_unit
+            ),
+        }
+    }
+}
+
+/// Decode the pHYs chunk of the PNG, if it has one
+pub fn get_phys(png: &Png) -> PngResult<Option<PhysChunk>> {
+    png.chunk_by_type(PhysChunk::CHUNK_TYPE)
+        .map(|chunk| PhysChunk::try_from(chunk.data()))
+        .transpose()
+}
+
+/// Store the pixel density, replacing any existing pHYs chunk. The spec requires pHYs to come
+/// before the first IDAT chunk.
+pub fn set_phys(png: &mut Png, phys: PhysChunk) -> PngResult<()> {
+    png.set_unique_chunk(phys.to_chunk()?, &["IDAT", "IEND"]);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dpi_conversion() {
+        let phys = PhysChunk::from_dpi(300.0, 72.0).unwrap();
+        assert_eq!(phys.x_pixels_per_unit, 11811);
+        assert_eq!(phys.y_pixels_per_unit, 2835);
+        assert_eq!(
+            phys.to_string(),
+            "300 x 72 dpi (11811 x 2835 pixels per metre)"
+        );
+        assert_eq!(PhysChunk::try_from(&phys.as_bytes()[..]).unwrap(), phys);
+
+        assert!(PhysChunk::from_dpi(0.0, 72.0).is_err());
+        assert!(PhysChunk::try_from(&[0, 0, 0, 1, 0, 0, 0, 1, 2][..]).is_err());
+        let aspect = PhysChunk::try_from(&[0, 0, 0, 1, 0, 0, 0, 2, 0][..]).unwrap();
+        assert_eq!(aspect.dpi(), None);
+    }
+
+    #[test]
+    fn test_set_phys_before_idat() {
+        let chunks = ["IHDR", "IDAT", "IEND"]
+            .iter()
+            .map(|t| Chunk::new(ChunkType::from_str(t).unwrap(), vec![]))
+            .collect();
+        let mut png = Png::from_chunks(chunks);
+        assert_eq!(get_phys(&png).unwrap(), None);
+
+        let phys = PhysChunk::from_dpi(96.0, 96.0).unwrap();
+        set_phys(&mut png, PhysChunk::from_dpi(300.0, 300.0).unwrap()).unwrap();
+        set_phys(&mut png, phys).unwrap();
+        assert_eq!(get_phys(&png).unwrap(), Some(phys));
+        assert_eq!(png.chunks().len(), 4);
+        assert_eq!(png.chunks()[1].chunk_type().to_string(), "pHYs");
+    }
+}
@@ -0,0 +1,173 @@
+use crate::chunk_type::ChunkType;
+use crate::envelope::{Envelope, Flags};
+use crate::segment;
+use crate::PngResult;
+use std::str::FromStr;
+
+/// Decoding, validation and pretty-printing for a private chunk type, so that tools built on
+/// this crate can understand proprietary chunks without changes to the crate itself
+pub trait ChunkHandler: Send + Sync {
+    /// The private chunk type this handles
+    fn chunk_type(&self) -> &ChunkType;
+
+    /// Decode the chunk data into a one-line summary, as shown by `print`
+    fn describe(&self, data: &[u8]) -> PngResult<String>;
+
+    /// Every problem with the chunk data, as reported by `validate`
+    fn validate(&self, data: &[u8]) -> Vec<String> {
+        match self.describe(data) {
+            Ok(_) => vec![],
+            Err(e) => vec![e.to_string()],
+        }
+    }
+}
+
+/// The handlers registered for private chunk types
+#[derive(Default)]
+pub struct ChunkHandlers {
+    handlers: Vec<Box<dyn ChunkHandler>>,
+}
+
+impl ChunkHandlers {
+    /// Add a handler. Only private chunk types can be handled, and only by one handler each.
+    pub fn register(&mut self, handler: Box<dyn ChunkHandler>) -> PngResult<()> {
+        let chunk_type = handler.chunk_type();
+        if chunk_type.is_public() {
+            return Err(format!(
+                "Can't register a handler for {}: only private chunk types can have handlers",
+                chunk_type
+            )
+            .into());
+        }
+        if self.get(chunk_type).is_some() {
+            return Err(format!("A handler is already registered for {}", chunk_type).into());
+        }
+        self.handlers.push(handler);
+        Ok(())
+    }
+
+    /// The handler for the given chunk type, if there is one
+    pub fn get(&self, chunk_type: &ChunkType) -> Option<&dyn ChunkHandler> {
+        self.handlers
+            .iter()
+            .find(|handler| handler.chunk_type() == chunk_type)
+            .map(|handler| handler.as_ref())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.handlers.is_empty()
+    }
+}
+
+/// The names of the handlers built into this crate, which can be selected at run time
+pub const BUILTIN_HANDLERS: &[&str] = &["envelope"];
+
+/// Create a built-in handler by name for the given chunk type
+pub fn builtin_handler(name: &str, chunk_type: &str) -> PngResult<Box<dyn ChunkHandler>> {
+    let chunk_type = ChunkType::from_str(chunk_type)?;
+    match name {
+        "envelope" => Ok(Box::new(EnvelopeHandler { chunk_type })),
+        _ => Err(format!(
+            "Unknown handler {:?}; expected one of {}",
+            name,
+            BUILTIN_HANDLERS.join(", ")
+        )
+        .into()),
+    }
+}
+
+/// Describes the payload chunks written by `encode`
+struct EnvelopeHandler {
+    chunk_type: ChunkType,
+}
+
+impl ChunkHandler for EnvelopeHandler {
+    fn chunk_type(&self) -> &ChunkType {
+        &self.chunk_type
+    }
+
+    fn describe(&self, data: &[u8]) -> PngResult<String> {
+        if segment::is_segment(data) {
+            return Ok(format!("pngmi payload segment ({} bytes)", data.len()));
+        }
+        let envelope = Envelope::from_payload(data)?;
+        let flags: Vec<&str> = [
+            (Flags::ENCRYPTED, "encrypted"),
+            (Flags::ZLIB, "zlib"),
+            (Flags::ZSTD, "zstd"),
+            (Flags::SIGNED, "signed"),
+            (Flags::RECIPIENTS, "recipients"),
+            (Flags::SEALED_METADATA, "sealed metadata"),
+        ]
+        .into_iter()
+        .filter(|(flag, _)| envelope.flags().contains(*flag))
+        .map(|(_, name)| name)
+        .collect();
+        let mime_type = match envelope.mime_type() {
+            "" => "unknown type",
+            mime_type => mime_type,
+        };
+        let mut description = format!(
+            "pngmi payload: {}, {} byte body",
+            mime_type,
+            envelope.body().len()
+        );
+        if let Some(filename) = envelope.filename() {
+            description.push_str(&format!(", file {:?}", filename));
+        }
+        if !flags.is_empty() {
+            description.push_str(&format!(" [{}]", flags.join(", ")));
+        }
+        Ok(description)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Rejecting(ChunkType);
+
+    impl ChunkHandler for Rejecting {
+        fn chunk_type(&self) -> &ChunkType {
+            &self.0
+        }
+
+        fn describe(&self, _data: &[u8]) -> PngResult<String> {
+            Err("always invalid".into())
+        }
+    }
+
+    #[test]
+    fn test_register() {
+        let mut handlers = ChunkHandlers::default();
+        let private = ChunkType::from_str("ruSt").unwrap();
+        handlers
+            .register(Box::new(Rejecting(ChunkType::from_str("ruSt").unwrap())))
+            .unwrap();
+        let handler = handlers.get(&private).unwrap();
+        assert_eq!(handler.validate(b""), vec!["always invalid"]);
+
+        assert!(handlers
+            .register(builtin_handler("envelope", "ruSt").unwrap())
+            .is_err());
+        assert!(handlers
+            .register(builtin_handler("envelope", "tEXt").unwrap())
+            .is_err());
+        assert!(handlers
+            .get(&ChunkType::from_str("abCd").unwrap())
+            .is_none());
+        assert!(builtin_handler("unknown", "abCd").is_err());
+    }
+
+    #[test]
+    fn test_envelope_handler() {
+        let handler = builtin_handler("envelope", "ruSt").unwrap();
+        let envelope = Envelope::new("image/png", Some("a.png"), vec![1, 2, 3]);
+        assert_eq!(
+            handler.describe(&envelope.as_bytes().unwrap()).unwrap(),
+            "pngmi payload: image/png, 3 byte body, file \"a.png\""
+        );
+        assert!(handler.validate(&Envelope::MAGIC).len() == 1);
+    }
+}
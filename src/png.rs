@@ -1,13 +1,162 @@
-use crate::chunk::Chunk;
+use crate::chunk::{Chunk, ChunkRef};
 use crate::chunk_type::ChunkType;
+use crate::codec::{ChunkRegistry, DecodeContext, TypedChunk};
+use crate::decoder;
+use crate::error::{error, ErrorKind};
+use crate::ihdr::Ihdr;
+use crate::raw::{RawChunks, RawError};
 use crate::{PngError, PngResult};
+use flate2::write::ZlibEncoder;
 use std::fmt::{Display, Formatter};
+use std::io::Write;
+use std::ops::Range;
 use std::str::FromStr;
+use tracing::debug;
+
+/// A problem `Png::try_from_lenient` skipped over rather than failing on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseWarning {
+    /// The offset at which the problem was found
+    pub offset: usize,
+    pub message: String,
+}
+
+impl Display for ParseWarning {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:#010x}: {}", self.offset, self.message)
+    }
+}
+
+/// Limits on what the parser accepts, so that a hostile file is refused up front rather than
+/// making the tool walk millions of chunks or trust a length field it can't back up
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseOptions {
+    /// The longest chunk data accepted; the spec allows at most 2^31-1 bytes
+    pub max_chunk_len: usize,
+    /// The most chunks accepted in one PNG
+    pub max_chunks: usize,
+    /// The largest input accepted, in bytes
+    pub max_total_size: usize,
+}
+
+impl ParseOptions {
+    pub const DEFAULT_MAX_CHUNKS: usize = 1 << 20;
+
+    pub(crate) fn check_size(&self, value: &[u8]) -> PngResult<()> {
+        if value.len() > self.max_total_size {
+            let message = format!(
+                "Input is {} bytes, more than the limit of {}",
+                value.len(),
+                self.max_total_size
+            );
+            return Err(error(ErrorKind::Parse, message));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn check_count(&self, count: usize, offset: usize) -> PngResult<()> {
+        if count >= self.max_chunks {
+            let message = format!(
+                "More than {} chunks; stopped at offset {:#010x}",
+                self.max_chunks, offset
+            );
+            return Err(error(ErrorKind::Parse, message));
+        }
+        Ok(())
+    }
+
+    /// The error for a chunk at `offset` declaring more data than `max_chunk_len`, if it does
+    pub(crate) fn check_length(&self, value: &[u8], offset: usize) -> Option<String> {
+        let length = value.get(offset..offset + 4)?;
+        let length = u32::from_be_bytes(length.try_into().ok()?) as usize;
+        (length > self.max_chunk_len).then(|| {
+            format!(
+                "declares {} bytes, more than the limit of {}",
+                length, self.max_chunk_len
+            )
+        })
+    }
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        ParseOptions {
+            max_chunk_len: Chunk::MAX_DATA_LEN,
+            max_chunks: ParseOptions::DEFAULT_MAX_CHUNKS,
+            max_total_size: usize::MAX,
+        }
+    }
+}
+
+/// Where `Png::insert_chunk_at` places a new chunk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkPosition {
+    /// Just after the IHDR chunk
+    AfterIhdr,
+    /// Just before the first IDAT chunk
+    BeforeIdat,
+    /// Just before the IEND chunk, or at the end if there is none
+    BeforeIend,
+    /// After the IEND chunk, outside the datastream proper. Decoders ignore such chunks, and
+    /// on reading the file back they are part of its trailing data.
+    AfterIend,
+    /// At this index among the existing chunks, shifting the chunks after it along
+    Index(usize),
+}
+
+impl Display for ChunkPosition {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChunkPosition::AfterIhdr => write!(f, "after-ihdr"),
+            ChunkPosition::BeforeIdat => write!(f, "before-idat"),
+            ChunkPosition::BeforeIend => write!(f, "before-iend"),
+            ChunkPosition::AfterIend => write!(f, "after-iend"),
+            ChunkPosition::Index(index) => write!(f, "index:{}", index),
+        }
+    }
+}
+
+impl FromStr for ChunkPosition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "after-ihdr" => Ok(ChunkPosition::AfterIhdr),
+            "before-idat" => Ok(ChunkPosition::BeforeIdat),
+            "before-iend" => Ok(ChunkPosition::BeforeIend),
+            "after-iend" => Ok(ChunkPosition::AfterIend),
+            _ => match s.strip_prefix("index:").unwrap_or(s).parse() {
+                Ok(index) => Ok(ChunkPosition::Index(index)),
+                Err(_) => Err(format!(
+                    "Unknown position {:?}; expected after-ihdr, before-idat, before-iend, \
+                     after-iend or index:N",
+                    s
+                )),
+            },
+        }
+    }
+}
+
+/// The chunk types which the spec says a chunk of the given type must come before
+pub(crate) fn required_before(chunk_type: &ChunkType) -> &'static [&'static str] {
+    match &chunk_type.bytes() {
+        b"IHDR" => &["PLTE", "IDAT", "IEND"],
+        b"cHRM" | b"gAMA" | b"iCCP" | b"sBIT" | b"sRGB" | b"cICP" | b"mDCV" | b"cLLI" => {
+            &["PLTE", "IDAT"]
+        }
+        b"PLTE" | b"bKGD" | b"hIST" | b"tRNS" | b"pHYs" | b"sPLT" | b"eXIf" | b"acTL" => &["IDAT"],
+        b"IDAT" => &["IEND"],
+        _ => &[],
+    }
+}
 
 /// The structure which represents a PNG file
+#[derive(Clone)]
 pub struct Png {
     /// The chunks present in this PNG file
     chunks: Vec<Chunk>,
+    /// Any bytes found after the IEND chunk
+    trailing_data: Vec<u8>,
 }
 
 impl Png {
@@ -16,61 +165,401 @@ impl Png {
 
     pub const HEADER_BYTES_LEN: usize = Self::STANDARD_HEADER.len();
 
+    /// The chunk type which marks the end of the PNG datastream
+    const IEND: [u8; 4] = *b"IEND";
+
     /// Create a PNG from existing chunks
-    fn from_chunks(chunks: Vec<Chunk>) -> Self {
-        Png { chunks }
+    pub fn from_chunks(chunks: Vec<Chunk>) -> Self {
+        Png {
+            chunks,
+            trailing_data: vec![],
+        }
+    }
+
+    /// Append a new chunk to the PNG.
+    /// If the PNG ends with an IEND chunk, the new chunk is placed just before it so that IEND
+    /// remains the last chunk as required by the spec.
+    pub fn append_chunk(&mut self, chunk: Chunk) {
+        match self.chunks.last() {
+            Some(last) if last.chunk_type().bytes() == Self::IEND => {
+                let index = self.chunks.len() - 1;
+                self.chunks.insert(index, chunk)
+            }
+            _ => self.chunks.push(chunk),
+        }
+    }
+
+    /// Insert a chunk at the given index, shifting the chunks after it along
+    pub fn insert_chunk(&mut self, index: usize, chunk: Chunk) {
+        self.chunks.insert(index, chunk)
+    }
+
+    /// Insert a chunk at the given position, returning the index it ended up at. Fails rather
+    /// than break the chunk ordering the spec requires, e.g. by placing a chunk before IHDR,
+    /// between two IDAT chunks or a gAMA chunk after the image data. Only `AfterIend` may
+    /// place a chunk after IEND.
+    pub fn insert_chunk_at(&mut self, position: ChunkPosition, chunk: Chunk) -> PngResult<usize> {
+        let index = self.resolve_position(position)?;
+        let is = |index: usize, chunk_type: &[u8; 4]| {
+            self.chunks
+                .get(index)
+                .is_some_and(|chunk| chunk.chunk_type().bytes() == *chunk_type)
+        };
+        let misplaced = |message: String| Err(format!("{} {}", chunk.chunk_type(), message).into());
+        if index == 0 && is(0, b"IHDR") {
+            return misplaced("can't come before IHDR".to_string());
+        }
+        let after_iend = (0..index).any(|earlier| is(earlier, &Self::IEND));
+        if after_iend && position != ChunkPosition::AfterIend {
+            return misplaced("can't come after IEND".to_string());
+        }
+        if index > 0 && is(index - 1, b"IDAT") && is(index, b"IDAT") {
+            return misplaced("can't go between IDAT chunks".to_string());
+        }
+        for earlier in &self.chunks[..index] {
+            let earlier = earlier.chunk_type().to_string();
+            if required_before(chunk.chunk_type()).contains(&earlier.as_str()) {
+                return misplaced(format!("must come before {}", earlier));
+            }
+        }
+        self.insert_chunk(index, chunk);
+        Ok(index)
+    }
+
+    /// Insert an ancillary chunk at the last place the spec allows it, just before the image
+    /// data for chunks which must precede it and otherwise just before IEND, returning its
+    /// index. Chunks placed one after another stay in that order.
+    pub fn place_chunk(&mut self, chunk: Chunk) -> usize {
+        let before = required_before(chunk.chunk_type());
+        let index = match self
+            .chunks
+            .iter()
+            .position(|existing| before.contains(&existing.chunk_type().to_string().as_str()))
+        {
+            Some(index) => index,
+            None => self
+                .resolve_position(ChunkPosition::BeforeIend)
+                .unwrap_or(self.chunks.len()),
+        };
+        self.insert_chunk(index, chunk);
+        index
+    }
+
+    /// The index a chunk inserted at the given position would have
+    fn resolve_position(&self, position: ChunkPosition) -> PngResult<usize> {
+        let find = |chunk_type: &str| {
+            self.chunks
+                .iter()
+                .position(|chunk| chunk.chunk_type().to_string() == chunk_type)
+                .ok_or_else(|| {
+                    error(
+                        ErrorKind::ChunkNotFound,
+                        format!("No {} chunk found", chunk_type),
+                    )
+                })
+        };
+        let len = self.chunks.len();
+        match position {
+            ChunkPosition::AfterIhdr => find("IHDR").map(|index| index + 1),
+            ChunkPosition::BeforeIdat => find("IDAT"),
+            ChunkPosition::BeforeIend => match self.chunks.last() {
+                Some(last) if last.chunk_type().bytes() == Self::IEND => Ok(len - 1),
+                _ => Ok(len),
+            },
+            ChunkPosition::AfterIend => Ok(len),
+            ChunkPosition::Index(index) if index > len => {
+                Err(format!("Index {} is past the end of the {} chunks", index, len).into())
+            }
+            ChunkPosition::Index(index) => Ok(index),
+        }
     }
 
-    /// Append a new chunk to the PNG
-    fn append_chunk(&mut self, chunk: Chunk) {
-        self.chunks.push(chunk)
+    /// Replace every chunk of the same type as `chunk` with this one, placed just before the
+    /// first chunk of any of the `before` types, or appended if there is none. This suits
+    /// chunks which may only appear once and must come before e.g. PLTE or IDAT.
+    pub fn set_unique_chunk(&mut self, chunk: Chunk, before: &[&str]) {
+        self.chunks
+            .retain(|existing| existing.chunk_type() != chunk.chunk_type());
+        let index = self
+            .chunks
+            .iter()
+            .position(|existing| before.contains(&existing.chunk_type().to_string().as_str()));
+        match index {
+            Some(index) => self.insert_chunk(index, chunk),
+            None => self.append_chunk(chunk),
+        }
     }
 
     /// Remove the first chunk matching the given `chunk_type`.
     /// If such a chunk is found, return it.
     /// If not found, return an error to signify that an attempt was made to remove a
     /// non-existing chunk.
-    fn remove_chunk(&mut self, chunk_type: &str) -> PngResult<Chunk> {
+    pub fn remove_chunk(&mut self, chunk_type: &str) -> PngResult<Chunk> {
         let wanted_chunk_type = ChunkType::from_str(chunk_type)?;
         match self
             .chunks
             .iter()
             .position(|chunk| *chunk.chunk_type() == wanted_chunk_type)
         {
-            None => Err("No chunk with the given chunk_type found".into()),
+            None => Err(error(
+                ErrorKind::ChunkNotFound,
+                "No chunk with the given chunk_type found",
+            )),
             Some(index) => Ok(self.chunks.remove(index)),
         }
     }
 
+    /// Remove and return the chunk at the given index
+    pub fn remove_chunk_at(&mut self, index: usize) -> Chunk {
+        self.chunks.remove(index)
+    }
+
+    /// The chunks belonging to this PNG object, for editing or reordering in place
+    pub fn chunks_mut(&mut self) -> &mut [Chunk] {
+        &mut self.chunks
+    }
+
+    /// Remove every chunk for which `keep` returns false, returning the removed chunks in order
+    pub fn retain_chunks<F: FnMut(&Chunk) -> bool>(&mut self, mut keep: F) -> Vec<Chunk> {
+        let (kept, removed) = self.chunks.drain(..).partition(|chunk| keep(chunk));
+        self.chunks = kept;
+        removed
+    }
+
+    /// Remove and return the bytes following the IEND chunk
+    pub fn take_trailing_data(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.trailing_data)
+    }
+
+    /// Replace the bytes following the IEND chunk
+    pub fn set_trailing_data(&mut self, trailing_data: Vec<u8>) {
+        self.trailing_data = trailing_data;
+    }
+
+    /// The indexes of the IDAT chunks, which the spec requires to be consecutive
+    pub(crate) fn idat_range(&self) -> PngResult<Range<usize>> {
+        let is_idat = |chunk: &Chunk| *chunk.chunk_type() == ChunkType::IDAT;
+        let start = self
+            .chunks
+            .iter()
+            .position(is_idat)
+            .ok_or_else(|| error(ErrorKind::ChunkNotFound, "No IDAT chunk found"))?;
+        let end = start
+            + self.chunks[start..]
+                .iter()
+                .take_while(|c| is_idat(c))
+                .count();
+        if self.chunks[end..].iter().any(is_idat) {
+            return Err(error(
+                ErrorKind::Validation,
+                "IDAT chunks are not consecutive",
+            ));
+        }
+        Ok(start..end)
+    }
+
+    /// The compressed image data: the data of every IDAT chunk, concatenated
+    pub fn compressed_idat_data(&self) -> PngResult<Vec<u8>> {
+        Ok(self.chunks[self.idat_range()?]
+            .iter()
+            .flat_map(|chunk| chunk.data().iter().copied())
+            .collect())
+    }
+
+    /// Replace the IDAT chunks with the given compressed image data, split into chunks of at
+    /// most `max_chunk_len` bytes where the first IDAT chunk was
+    pub fn set_compressed_idat_data(&mut self, data: &[u8], max_chunk_len: usize) -> PngResult<()> {
+        if !(1..=Chunk::MAX_DATA_LEN).contains(&max_chunk_len) {
+            return Err(format!("IDAT chunk size {} is out of range", max_chunk_len).into());
+        }
+        let range = self.idat_range()?;
+        let idats: Vec<Chunk> = match data.is_empty() {
+            true => vec![Chunk::new(ChunkType::IDAT, vec![])],
+            false => data
+                .chunks(max_chunk_len)
+                .map(|data| Chunk::new(ChunkType::IDAT, data.to_vec()))
+                .collect(),
+        };
+        self.chunks.splice(range, idats);
+        Ok(())
+    }
+
+    /// The decompressed image data: scanlines of filtered pixels, each led by its filter
+    /// type. Fails unless the data inflates to exactly the size the header implies.
+    pub fn idat_data(&self) -> PngResult<Vec<u8>> {
+        let expected_len = Ihdr::try_from(self)?.raw_data_len();
+        decoder::inflate(&self.compressed_idat_data()?, expected_len)
+    }
+
+    /// Compress the image data at the given zlib level, from 0 to 9, and store it in place of
+    /// the IDAT chunks, split into chunks of at most `max_chunk_len` bytes
+    pub fn set_idat_data(&mut self, raw: &[u8], level: u32, max_chunk_len: usize) -> PngResult<()> {
+        if level > 9 {
+            return Err(format!("Compression level {} is out of range (0..=9)", level).into());
+        }
+        let mut encoder = ZlibEncoder::new(vec![], flate2::Compression::new(level));
+        encoder.write_all(raw)?;
+        self.set_compressed_idat_data(&encoder.finish()?, max_chunk_len)
+    }
+
     /// The header for this PNG object
-    fn header(&self) -> &[u8; 8] {
+    pub fn header(&self) -> &[u8; 8] {
         &Self::STANDARD_HEADER
     }
 
     /// The chunks belonging to this PNG object
-    fn chunks(&self) -> &[Chunk] {
+    pub fn chunks(&self) -> &[Chunk] {
         &self.chunks
     }
 
     /// Retrieve the first chunk which matches the given `chunk_type`.
     /// If not found, return None
-    fn chunk_by_type(&self, chunk_type: &str) -> Option<&Chunk> {
+    pub fn chunk_by_type(&self, chunk_type: &str) -> Option<&Chunk> {
         let chunk_type = ChunkType::from_str(chunk_type).ok()?;
         self.chunks
             .iter()
             .find(|chunk| *chunk.chunk_type() == chunk_type)
     }
 
+    /// Iterate over all the chunks which match the given `chunk_type`, in file order
+    pub fn chunks_by_type(&self, chunk_type: &str) -> impl Iterator<Item = &Chunk> {
+        let chunk_type = ChunkType::from_str(chunk_type).ok();
+        self.chunks
+            .iter()
+            .filter(move |chunk| Some(chunk.chunk_type()) == chunk_type.as_ref())
+    }
+
+    /// The bytes following the IEND chunk, which PNG decoders ignore
+    pub fn trailing_data(&self) -> &[u8] {
+        &self.trailing_data
+    }
+
+    /// Decode every chunk of a standard type this crate has a codec for, returning the rest
+    /// as raw chunks
+    pub fn typed_chunks(&self) -> impl Iterator<Item = PngResult<TypedChunk<'_>>> {
+        let registry = ChunkRegistry::default();
+        let context = DecodeContext::from_png(self);
+        self.chunks
+            .iter()
+            .map(move |chunk| registry.decode(chunk, &context))
+    }
+
     /// The bytes which represents the current Png object
-    fn as_bytes(&self) -> Vec<u8> {
+    pub fn as_bytes(&self) -> Vec<u8> {
         // FIXME Is there a more functional way to do this?
         let mut bytes = self.header().to_vec();
         for chunk in &self.chunks {
             let chunk_bytes = &mut chunk.as_bytes().to_vec();
             bytes.append(chunk_bytes)
         }
+        bytes.extend_from_slice(&self.trailing_data);
         bytes
     }
+
+    /// Parse a PNG, skipping chunks with bad CRCs, invalid types or bogus lengths instead of
+    /// failing. After each corrupt chunk, parsing resumes at the next offset holding a
+    /// well-formed chunk, and a warning describes what was dropped.
+    pub fn try_from_lenient(value: &[u8]) -> PngResult<(Png, Vec<ParseWarning>)> {
+        Png::try_from_lenient_with(value, &ParseOptions::default())
+    }
+
+    /// Like `Png::try_from_lenient`, with chunks longer than `options` allow skipped as corrupt
+    pub fn try_from_lenient_with(
+        value: &[u8],
+        options: &ParseOptions,
+    ) -> PngResult<(Png, Vec<ParseWarning>)> {
+        let (png, warnings) = PngRef::try_from_lenient_with(value, options)?;
+        Ok((png.to_png(), warnings))
+    }
+
+    /// Parse a PNG, failing if it goes beyond any of the limits in `options`
+    pub fn try_from_with(value: &[u8], options: &ParseOptions) -> PngResult<Png> {
+        PngRef::try_from_with(value, options).map(|png| png.to_png())
+    }
+
+    /// The bytes of the PNG which starts `offset` bytes into `value`, such as one inside an ICO
+    /// file or a firmware image, up to the end of its IEND chunk. Whatever follows belongs to
+    /// the container rather than the PNG. Without an IEND the PNG runs to the end of `value`.
+    pub fn bytes_at(value: &[u8], offset: usize) -> PngResult<&[u8]> {
+        let rest = value.get(offset..).ok_or_else(|| {
+            error(
+                ErrorKind::Parse,
+                format!(
+                    "Offset {} is past the end of the {}-byte input",
+                    offset,
+                    value.len()
+                ),
+            )
+        })?;
+        if !rest.starts_with(&Png::STANDARD_HEADER) {
+            let message = format!("No PNG signature at offset {:#x}", offset);
+            return Err(error(ErrorKind::Parse, message));
+        }
+        let mut chunks = RawChunks::new(rest);
+        let last = chunks.by_ref().map_while(Result::ok).last();
+        match last {
+            Some(chunk) if chunk.is(&Png::IEND) => Ok(&rest[..chunks.offset()]),
+            _ => Ok(rest),
+        }
+    }
+
+    /// Parse a PNG from an async reader as it arrives, e.g. an upload still being received,
+    /// without blocking the thread while waiting for it
+    #[cfg(feature = "async")]
+    pub async fn from_async_reader<R>(reader: R) -> PngResult<Png>
+    where
+        R: tokio::io::AsyncRead + Unpin,
+    {
+        crate::stream::ChunkReader::new(reader).read_png().await
+    }
+
+    /// Parse the PNG which starts `offset` bytes into `value`, leaving out whatever follows its
+    /// IEND chunk as `bytes_at` does
+    pub fn try_from_at(value: &[u8], offset: usize) -> PngResult<Png> {
+        Png::try_from(Png::bytes_at(value, offset)?)
+    }
+
+    /// Where each PNG starts in a stream of PNGs written back to back, such as a captured
+    /// network stream. Each one ends at its IEND chunk and the next must start straight after;
+    /// anything else there is taken as trailing data of the last.
+    pub fn stream_offsets(value: &[u8]) -> PngResult<Vec<usize>> {
+        let mut offsets = vec![];
+        let mut offset = 0;
+        while offset == 0 || value[offset..].starts_with(&Png::STANDARD_HEADER) {
+            offsets.push(offset);
+            offset += Png::bytes_at(value, offset)?.len();
+        }
+        Ok(offsets)
+    }
+
+    /// Parse every PNG in a stream of PNGs written back to back, in order. Data after the last
+    /// IEND which isn't another PNG is kept as that PNG's trailing data.
+    pub fn parse_all(value: &[u8]) -> PngResult<Vec<Png>> {
+        let offsets = Png::stream_offsets(value)?;
+        let last = offsets.len() - 1;
+        offsets
+            .iter()
+            .enumerate()
+            .map(|(index, &offset)| match index == last {
+                true => Png::try_from(&value[offset..]),
+                false => Png::try_from_at(value, offset),
+            })
+            .collect()
+    }
+
+    /// Borrow the chunks of this PNG, e.g. to pass to functions which take a `PngRef`
+    pub fn borrowed(&self) -> PngRef<'_> {
+        let mut offset = Png::HEADER_BYTES_LEN;
+        let chunks = self.chunks.iter().map(|chunk| {
+            let chunk = ChunkRef::from(chunk).at(offset);
+            offset += chunk.overall_length() as usize;
+            chunk
+        });
+        PngRef {
+            chunks: chunks.collect(),
+            trailing_data: &self.trailing_data,
+        }
+    }
 }
 
 impl Display for Png {
@@ -84,29 +573,259 @@ impl TryFrom<&[u8]> for Png {
 
     /// Attempt to perform the conversion from a byte slice to Png
     fn try_from(value: &[u8]) -> PngResult<Self> {
+        PngRef::try_from(value).map(|png| png.to_png())
+    }
+}
+
+/// A parsed PNG whose chunk data is borrowed from the input bytes rather than copied, for
+/// commands which only inspect a file
+pub struct PngRef<'a> {
+    chunks: Vec<ChunkRef<'a>>,
+    trailing_data: &'a [u8],
+}
+
+impl<'a> PngRef<'a> {
+    pub fn chunks(&self) -> &[ChunkRef<'a>] {
+        &self.chunks
+    }
+
+    /// Searches for the first chunk with the given type
+    pub fn chunk_by_type(&self, chunk_type: &str) -> Option<&ChunkRef<'a>> {
+        self.chunks
+            .iter()
+            .find(|chunk| chunk.chunk_type().to_string() == chunk_type)
+    }
+
+    /// Iterate over all the chunks which match the given `chunk_type`, in file order
+    pub fn chunks_by_type<'s>(
+        &'s self,
+        chunk_type: &'s str,
+    ) -> impl Iterator<Item = &'s ChunkRef<'a>> + 's {
+        self.chunks
+            .iter()
+            .filter(move |chunk| chunk.chunk_type().to_string() == chunk_type)
+    }
+
+    /// The bytes following the IEND chunk, which PNG decoders ignore
+    pub fn trailing_data(&self) -> &'a [u8] {
+        self.trailing_data
+    }
+
+    /// The well-formed chunks at the start of the trailing data, such as those placed after
+    /// IEND with `ChunkPosition::AfterIend`, up to the first bytes which aren't a valid chunk
+    pub fn trailing_chunks(&self) -> Vec<ChunkRef<'a>> {
+        // The trailing data starts straight after IEND
+        let start = self
+            .chunks
+            .last()
+            .map_or(0, |iend| iend.offset() + iend.overall_length() as usize);
+        let mut chunks = vec![];
+        for raw in RawChunks::at(self.trailing_data, 0) {
+            let Ok(raw) = raw else { break };
+            match ChunkType::try_from(raw.chunk_type) {
+                Ok(chunk_type) if raw.crc_matches() => chunks.push(ChunkRef::from_parts(
+                    start + raw.offset,
+                    chunk_type,
+                    raw.data,
+                    raw.stored_crc,
+                )),
+                _ => break,
+            }
+        }
+        chunks
+    }
+
+    /// Count the chunk offsets from `base` bytes earlier, for a PNG parsed out of a larger
+    /// file starting at that offset
+    pub fn offset_by(mut self, base: usize) -> Self {
+        for chunk in &mut self.chunks {
+            *chunk = chunk.at(base + chunk.offset());
+        }
+        self
+    }
+
+    /// Copy the chunks into an owned `Png`
+    pub fn to_png(&self) -> Png {
+        Png {
+            chunks: self.chunks.iter().map(ChunkRef::to_chunk).collect(),
+            trailing_data: self.trailing_data.to_vec(),
+        }
+    }
+
+    /// Like `Png::try_from_lenient`, but borrowing the chunk data
+    pub fn try_from_lenient(value: &'a [u8]) -> PngResult<(PngRef<'a>, Vec<ParseWarning>)> {
+        Self::try_from_lenient_with(value, &ParseOptions::default())
+    }
+
+    /// Like `Png::try_from_lenient_with`, but borrowing the chunk data
+    pub fn try_from_lenient_with(
+        value: &'a [u8],
+        options: &ParseOptions,
+    ) -> PngResult<(PngRef<'a>, Vec<ParseWarning>)> {
+        if !value.starts_with(&Png::STANDARD_HEADER) {
+            return Err(error(ErrorKind::Parse, "Invalid header found"));
+        }
+        options.check_size(value)?;
+
+        let mut chunks = vec![];
+        let mut warnings = vec![];
+        let mut offset = Png::HEADER_BYTES_LEN;
+        while offset < value.len() {
+            options.check_count(chunks.len(), offset)?;
+            let raw = match options.check_length(value, offset) {
+                Some(message) => Some(Err(RawError {
+                    offset,
+                    message: format!("Chunk {}", message),
+                })),
+                None => RawChunks::at(value, offset).next(),
+            };
+            let problem = match raw {
+                Some(Ok(raw)) if raw.crc_matches() => match ChunkType::try_from(raw.chunk_type) {
+                    Ok(chunk_type) => {
+                        debug!(
+                            offset,
+                            chunk_type = %chunk_type,
+                            length = raw.data.len(),
+                            "Parsed chunk"
+                        );
+                        chunks.push(ChunkRef::from_parts(
+                            offset,
+                            chunk_type,
+                            raw.data,
+                            raw.stored_crc,
+                        ));
+                        offset = raw.end();
+                        if raw.is(&Png::IEND) {
+                            let png = PngRef {
+                                chunks,
+                                trailing_data: &value[offset..],
+                            };
+                            return Ok((png, warnings));
+                        }
+                        continue;
+                    }
+                    Err(_) => format!("Invalid chunk type bytes {:?}", raw.chunk_type),
+                },
+                Some(Ok(raw)) => format!("{} chunk has a bad CRC", raw.type_name()),
+                Some(Err(e)) => e.message,
+                None => break,
+            };
+            let next = Self::resync(value, offset + 1).unwrap_or(value.len());
+            warnings.push(ParseWarning {
+                offset,
+                message: format!("{}; skipped {} bytes", problem, next - offset),
+            });
+            offset = next;
+        }
+
+        warnings.push(ParseWarning {
+            offset: value.len(),
+            message: "No IEND chunk found".to_string(),
+        });
+        let png = PngRef {
+            chunks,
+            trailing_data: &[],
+        };
+        Ok((png, warnings))
+    }
+
+    /// Find the first offset from `start` onwards holding a chunk with a type made of letters
+    /// and a matching CRC, which is very unlikely to happen by chance
+    fn resync(value: &[u8], start: usize) -> Option<usize> {
+        (start..value.len()).find(|offset| {
+            matches!(
+                RawChunks::at(value, *offset).next(),
+                Some(Ok(raw)) if raw.chunk_type.iter().all(u8::is_ascii_alphabetic) && raw.crc_matches()
+            )
+        })
+    }
+
+    /// Parse a PNG without checking any chunk CRCs, for trusted inputs. Callers can check the
+    /// chunks they go on to read with `ChunkRef::verify_crc`.
+    pub fn try_from_unverified(value: &'a [u8]) -> PngResult<Self> {
+        Self::try_from_unverified_with(value, &ParseOptions::default())
+    }
+
+    /// Like `PngRef::try_from_unverified`, failing if the PNG goes beyond any of the limits in
+    /// `options`
+    pub fn try_from_unverified_with(value: &'a [u8], options: &ParseOptions) -> PngResult<Self> {
+        Self::parse(value, options, ChunkRef::try_from_unverified)
+    }
+
+    /// Parse a PNG, borrowing the chunk data, failing if it goes beyond any of the limits in
+    /// `options`
+    pub fn try_from_with(value: &'a [u8], options: &ParseOptions) -> PngResult<Self> {
+        Self::parse(value, options, ChunkRef::try_from)
+    }
+
+    /// Parse the PNG which starts `offset` bytes into `value`, borrowing the chunk data, as
+    /// `Png::try_from_at` does
+    pub fn try_from_at(value: &'a [u8], offset: usize) -> PngResult<Self> {
+        PngRef::try_from(Png::bytes_at(value, offset)?)
+    }
+
+    /// Split the datastream into chunks with `parse_chunk`, stopping at IEND
+    fn parse(
+        value: &'a [u8],
+        options: &ParseOptions,
+        parse_chunk: fn(&'a [u8]) -> PngResult<ChunkRef<'a>>,
+    ) -> PngResult<Self> {
         // First parse out the header from the incoming slice and compare it against the expected
         // header value -- fail if it doesn't match.
-        let header = &value[..Png::HEADER_BYTES_LEN];
-        if header != Png::STANDARD_HEADER {
-            return Err("Invalid header found".into());
+        if !value.starts_with(&Png::STANDARD_HEADER) {
+            return Err(error(ErrorKind::Parse, "Invalid header found"));
         }
+        options.check_size(value)?;
 
         // Now incrementally build up the list of chunks by parsing the remaining bytes from the
         // incoming slice. We loop over the slice till we run out of bytes to parse. In case of
         // an invalid payload, we would fail when creating a chunk and return back that failure.
+        // Anything after the IEND chunk isn't part of the datastream and is kept aside as is.
         let mut chunks = vec![];
+        let mut trailing_data: &[u8] = &[];
         let mut start = Png::HEADER_BYTES_LEN;
         let len = value.len();
         loop {
-            let slice = &value[start..];
-            let chunk = Chunk::try_from(slice)?;
+            options.check_count(chunks.len(), start)?;
+            if let Some(message) = options.check_length(value, start) {
+                let message = format!("Chunk at offset {:#010x} {}", start, message);
+                return Err(error(ErrorKind::Parse, message));
+            }
+            let chunk = parse_chunk(&value[start..])
+                .inspect_err(|e| {
+                    debug!(offset = start, "Failed to parse chunk: {}", e);
+                })?
+                .at(start);
+            debug!(
+                offset = start,
+                chunk_type = %chunk.chunk_type(),
+                length = chunk.length(),
+                "Parsed chunk"
+            );
             start += chunk.overall_length() as usize;
+            let is_iend = chunk.chunk_type().bytes() == Png::IEND;
             chunks.push(chunk);
+            if is_iend {
+                trailing_data = &value[start..];
+                break;
+            }
             if start >= len {
                 break;
             }
         }
-        Ok(Png::from_chunks(chunks))
+        Ok(PngRef {
+            chunks,
+            trailing_data,
+        })
+    }
+}
+
+impl<'a> TryFrom<&'a [u8]> for PngRef<'a> {
+    type Error = PngError;
+
+    /// Parse a PNG, borrowing the chunk data from the byte slice
+    fn try_from(value: &'a [u8]) -> PngResult<Self> {
+        Self::try_from_with(value, &ParseOptions::default())
     }
 }
 
@@ -115,18 +834,17 @@ mod tests {
     use super::*;
     use crate::chunk::Chunk;
     use crate::chunk_type::ChunkType;
+    use crate::test_util::chunk;
     use std::convert::TryFrom;
     use std::str::FromStr;
     use std::{assert_eq, format, vec};
 
     fn testing_chunks() -> Vec<Chunk> {
-        let mut chunks = Vec::new();
-
-        chunks.push(chunk_from_strings("FrSt", "I am the first chunk").unwrap());
-        chunks.push(chunk_from_strings("miDl", "I am another chunk").unwrap());
-        chunks.push(chunk_from_strings("LASt", "I am the last chunk").unwrap());
-
-        chunks
+        vec![
+            chunk_from_strings("FrSt", "I am the first chunk").unwrap(),
+            chunk_from_strings("miDl", "I am another chunk").unwrap(),
+            chunk_from_strings("LASt", "I am the last chunk").unwrap(),
+        ]
     }
 
     fn testing_png() -> Png {
@@ -222,6 +940,16 @@ mod tests {
         assert_eq!(&chunk.data_as_string().unwrap(), "I am the first chunk");
     }
 
+    #[test]
+    fn test_chunks_by_type() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("FrSt", "I am the second FrSt").unwrap());
+        let chunks: Vec<_> = png.chunks_by_type("FrSt").collect();
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(&chunks[1].data_as_string().unwrap(), "I am the second FrSt");
+        assert_eq!(png.chunks_by_type("NoPe").count(), 0);
+    }
+
     #[test]
     fn test_append_chunk() {
         let mut png = testing_png();
@@ -240,6 +968,119 @@ mod tests {
         assert!(chunk.is_none());
     }
 
+    #[test]
+    fn test_png_ref_borrows_input() {
+        let bytes = testing_png().as_bytes();
+        let png = PngRef::try_from(bytes.as_slice()).unwrap();
+        let data = png.chunk_by_type("miDl").unwrap().data();
+        assert!(bytes.as_ptr_range().contains(&data.as_ptr()));
+        assert_eq!(png.to_png().as_bytes(), bytes);
+        assert_eq!(testing_png().borrowed().chunks().len(), 3);
+    }
+
+    #[test]
+    fn test_png_ref_unverified() {
+        let mut bytes = testing_png().as_bytes();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+        assert!(PngRef::try_from(bytes.as_slice()).is_err());
+        let png = PngRef::try_from_unverified(&bytes).unwrap();
+        let mut chunks = png.chunks_by_type("LASt");
+        assert!(chunks.next().unwrap().verify_crc().is_err());
+        assert!(chunks.next().is_none());
+        assert!(png.chunk_by_type("FrSt").unwrap().verify_crc().is_ok());
+    }
+
+    #[test]
+    fn test_set_unique_chunk() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("miDl", "A duplicate").unwrap());
+        png.set_unique_chunk(chunk_from_strings("miDl", "Unique").unwrap(), &["LASt"]);
+        assert_eq!(png.chunks().len(), 3);
+        assert_eq!(png.chunks()[1].data_as_string().unwrap(), "Unique");
+
+        png.set_unique_chunk(chunk_from_strings("NeWe", "Appended").unwrap(), &["IDAT"]);
+        assert_eq!(png.chunks()[3].chunk_type().to_string(), "NeWe");
+    }
+
+    #[test]
+    fn test_insert_chunk_at() {
+        let types = |png: &Png| -> Vec<String> {
+            png.chunks()
+                .iter()
+                .map(|chunk| chunk.chunk_type().to_string())
+                .collect()
+        };
+        let mut png = Png::from_chunks(
+            ["IHDR", "IDAT", "IDAT", "IEND"]
+                .iter()
+                .map(|chunk_type| chunk_from_strings(chunk_type, "").unwrap())
+                .collect(),
+        );
+        let chunk = |chunk_type| chunk_from_strings(chunk_type, "data").unwrap();
+        let position = |s| ChunkPosition::from_str(s).unwrap();
+
+        assert_eq!(
+            png.insert_chunk_at(position("before-iend"), chunk("ruSt"))
+                .unwrap(),
+            3
+        );
+        assert_eq!(
+            png.insert_chunk_at(position("before-idat"), chunk("gAMA"))
+                .unwrap(),
+            1
+        );
+        assert_eq!(
+            png.insert_chunk_at(position("index:1"), chunk("tEXt"))
+                .unwrap(),
+            1
+        );
+        assert_eq!(
+            types(&png),
+            ["IHDR", "tEXt", "gAMA", "IDAT", "IDAT", "ruSt", "IEND"]
+        );
+
+        for bad in ["0", "4", "7", "8"] {
+            assert!(png.insert_chunk_at(position(bad), chunk("tEXt")).is_err());
+        }
+        assert!(png
+            .insert_chunk_at(position("before-iend"), chunk("pHYs"))
+            .is_err());
+        assert!(ChunkPosition::from_str("after-idat").is_err());
+        assert_eq!(png.chunks().len(), 7);
+
+        png.insert_chunk_at(position("after-ihdr"), chunk("frSt"))
+            .unwrap();
+        png.insert_chunk_at(position("after-iend"), chunk("laSt"))
+            .unwrap();
+        assert_eq!(types(&png)[1], "frSt");
+        let bytes = png.as_bytes();
+        let reparsed = PngRef::try_from(&bytes[..]).unwrap();
+        assert_eq!(reparsed.chunks().len(), 8);
+        let trailing = reparsed.trailing_chunks();
+        assert_eq!(trailing.len(), 1);
+        assert_eq!(trailing[0].chunk_type().to_string(), "laSt");
+        assert_eq!(trailing[0].data(), b"data");
+    }
+
+    #[test]
+    fn test_chunk_offsets() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("IEND", "").unwrap());
+        let mut bytes = png.as_bytes();
+        bytes.extend(chunk_from_strings("laSt", "data").unwrap().as_bytes());
+        let offsets =
+            |png: &PngRef| -> Vec<usize> { png.chunks().iter().map(ChunkRef::offset).collect() };
+
+        let parsed = PngRef::try_from(&bytes[..]).unwrap();
+        assert_eq!(offsets(&parsed), [8, 40, 70, 101]);
+        assert_eq!(offsets(&png.borrowed()), offsets(&parsed));
+        assert_eq!(parsed.trailing_chunks()[0].offset(), 113);
+        let (lenient, _) = PngRef::try_from_lenient(&bytes).unwrap();
+        assert_eq!(offsets(&lenient), offsets(&parsed));
+        assert_eq!(offsets(&parsed.offset_by(100)), [108, 140, 170, 201]);
+    }
+
     #[test]
     fn test_png_from_image_file() {
         let png = Png::try_from(&PNG_FILE[..]);
@@ -250,10 +1091,149 @@ mod tests {
     fn test_as_bytes() {
         let png = Png::try_from(&PNG_FILE[..]).unwrap();
         let actual = png.as_bytes();
-        let expected: Vec<u8> = PNG_FILE.iter().copied().collect();
+        let expected: Vec<u8> = PNG_FILE.to_vec();
         assert_eq!(actual, expected);
     }
 
+    #[test]
+    fn test_trailing_data() {
+        let mut bytes = PNG_FILE.to_vec();
+        bytes.extend_from_slice(b"hidden after IEND");
+        let png = Png::try_from(bytes.as_slice()).unwrap();
+        assert_eq!(png.trailing_data(), b"hidden after IEND");
+        assert_eq!(png.as_bytes(), bytes);
+    }
+
+    #[test]
+    fn test_try_from_at() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("IEND", "").unwrap());
+        let mut container = b"ICO header".to_vec();
+        container.extend_from_slice(&png.as_bytes());
+        container.extend_from_slice(b"next image");
+
+        let embedded = Png::try_from_at(&container, 10).unwrap();
+        assert_eq!(embedded.as_bytes(), png.as_bytes());
+        assert!(embedded.trailing_data().is_empty());
+        let borrowed = PngRef::try_from_at(&container, 10).unwrap();
+        assert_eq!(borrowed.chunks().len(), png.chunks().len());
+
+        assert!(Png::try_from_at(&container, 0).is_err());
+        assert!(Png::try_from_at(&container, container.len() + 1).is_err());
+        // Without IEND the PNG runs on to the end, where parsing fails
+        let truncated = &container[..container.len() - 16];
+        assert!(Png::try_from_at(truncated, 10).is_err());
+    }
+
+    #[test]
+    fn test_parse_all() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("IEND", "").unwrap());
+        let mut stream = png.as_bytes();
+        let second = stream.len();
+        stream.extend_from_slice(&png.as_bytes());
+        stream.extend_from_slice(b"end of capture");
+
+        assert_eq!(Png::stream_offsets(&stream).unwrap(), vec![0, second]);
+        let pngs = Png::parse_all(&stream).unwrap();
+        assert_eq!(pngs.len(), 2);
+        assert!(pngs[0].trailing_data().is_empty());
+        assert_eq!(pngs[1].trailing_data(), b"end of capture");
+        assert_eq!(Png::parse_all(&png.as_bytes()).unwrap().len(), 1);
+        assert!(Png::parse_all(b"not a png").is_err());
+        // A broken second PNG fails the whole stream rather than being dropped
+        stream[second + 20] ^= 1;
+        assert!(Png::parse_all(&stream).is_err());
+    }
+
+    #[test]
+    fn test_parse_options() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("IEND", "").unwrap());
+        let bytes = png.as_bytes();
+        let defaults = ParseOptions::default();
+        assert!(Png::try_from_with(&bytes, &defaults).is_ok());
+
+        let options = ParseOptions {
+            max_chunks: 3,
+            ..defaults
+        };
+        assert!(Png::try_from_with(&bytes, &options).is_err());
+        assert!(Png::try_from_lenient_with(&bytes, &options).is_err());
+        let options = ParseOptions {
+            max_total_size: bytes.len() - 1,
+            ..defaults
+        };
+        assert!(PngRef::try_from_unverified_with(&bytes, &options).is_err());
+
+        // The first chunk holds the most data, so it's refused without reading any further,
+        // or skipped as corrupt by the lenient parser
+        let longest = png.chunks().iter().map(Chunk::length).max().unwrap() as usize;
+        let options = ParseOptions {
+            max_chunk_len: longest - 1,
+            ..defaults
+        };
+        let message = Png::try_from_with(&bytes, &options)
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(message.contains("more than the limit"), "{}", message);
+        let (lenient, warnings) = Png::try_from_lenient_with(&bytes, &options).unwrap();
+        assert!(lenient.chunks().len() < png.chunks().len());
+        assert!(!warnings.is_empty());
+
+        // A length field far beyond the spec's maximum is refused up front
+        let mut huge = bytes.clone();
+        huge[Png::HEADER_BYTES_LEN..Png::HEADER_BYTES_LEN + 4].copy_from_slice(&[0xff; 4]);
+        let message = Png::try_from(&huge[..]).err().unwrap().to_string();
+        assert!(message.contains("more than the limit"), "{}", message);
+    }
+
+    #[test]
+    fn test_truncated_png() {
+        assert!(Png::try_from(&PNG_FILE[..4]).is_err());
+        assert!(Png::try_from(&PNG_FILE[..100]).is_err());
+    }
+
+    #[test]
+    fn test_lenient_skips_corrupt_chunks() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("IEND", "").unwrap());
+        let mut bytes = png.as_bytes();
+        // Corrupt the CRC of FrSt and give miDl a length running past the end of the file
+        let first_len = png.chunks()[0].overall_length() as usize;
+        bytes[8 + first_len - 1] ^= 1;
+        bytes[8 + first_len..8 + first_len + 4].copy_from_slice(&u32::MAX.to_be_bytes());
+
+        assert!(Png::try_from(bytes.as_slice()).is_err());
+        let (lenient, warnings) = Png::try_from_lenient(&bytes).unwrap();
+        let types: Vec<String> = lenient
+            .chunks()
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect();
+        assert_eq!(types, vec!["LASt", "IEND"]);
+        // Resynchronising after FrSt skips straight past the broken miDl as well
+        let skipped = first_len + png.chunks()[1].overall_length() as usize;
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].offset, 8);
+        assert_eq!(
+            warnings[0].message,
+            format!("FrSt chunk has a bad CRC; skipped {} bytes", skipped)
+        );
+    }
+
+    #[test]
+    fn test_lenient_matches_strict_on_valid_input() {
+        let (png, warnings) = Png::try_from_lenient(&PNG_FILE).unwrap();
+        assert!(warnings.is_empty());
+        assert_eq!(png.as_bytes(), PNG_FILE);
+
+        let (png, warnings) = Png::try_from_lenient(&PNG_FILE[..100]).unwrap();
+        assert!(!png.chunks().is_empty());
+        assert_eq!(warnings.last().unwrap().message, "No IEND chunk found");
+    }
+
     #[test]
     fn test_png_trait_impls() {
         let chunk_bytes: Vec<u8> = testing_chunks()
@@ -518,4 +1498,32 @@ mod tests {
         202, 28, 31, 66, 176, 235, 16, 0, 0, 0, 3, 82, 117, 83, 116, 104, 101, 121, 158, 176, 245,
         160, 0, 0, 0, 0, 73, 69, 78, 68, 174, 66, 96, 130,
     ];
+
+    #[test]
+    fn test_idat_data() {
+        // A 2x2 8-bit greyscale image: two scanlines of a filter type byte and two pixels
+        let raw = [0, 1, 2, 0, 3, 4];
+        let mut png = Png::from_chunks(vec![
+            chunk("IHDR", &[0, 0, 0, 2, 0, 0, 0, 2, 8, 0, 0, 0, 0]),
+            chunk("IDAT", &[]),
+            chunk("IEND", &[]),
+        ]);
+        png.set_idat_data(&raw, 0, 4).unwrap();
+        let idats = png.chunks_by_type("IDAT").count();
+        assert!(idats > 1);
+        assert!(png.chunks_by_type("IDAT").all(|chunk| chunk.length() <= 4));
+        assert_eq!(png.idat_data().unwrap(), raw);
+
+        let compressed = png.compressed_idat_data().unwrap();
+        png.set_compressed_idat_data(&compressed, Chunk::MAX_DATA_LEN)
+            .unwrap();
+        assert_eq!(png.chunks().len(), 3);
+        assert_eq!(png.idat_data().unwrap(), raw);
+
+        assert!(png.set_idat_data(&raw[..3], 9, 4).is_ok());
+        assert!(png.idat_data().is_err());
+        png.insert_chunk(1, chunk("IDAT", &[]));
+        png.insert_chunk(2, chunk("tEXt", b"a\0b"));
+        assert!(png.compressed_idat_data().is_err());
+    }
 }
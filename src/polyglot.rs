@@ -0,0 +1,247 @@
+use std::fmt::{Display, Formatter};
+
+/// Chunks whose data is compressed image data, where short magic numbers turn up by chance
+const IMAGE_DATA_CHUNK_TYPES: &[&[u8; 4]] = &[b"IDAT", b"fdAT"];
+
+/// The offset within a PE file of the pointer to its `PE\0\0` header
+const PE_HEADER_POINTER_OFFSET: usize = 0x3c;
+
+/// File formats which may be hidden inside a PNG, making it valid as both
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Png,
+    Jpeg,
+    Gif,
+    Zip,
+    Gzip,
+    SevenZip,
+    Rar,
+    Pdf,
+    Html,
+    PortableExecutable,
+    Elf,
+    MachO,
+    Script,
+    Armour,
+}
+
+impl Format {
+    pub const ALL: &'static [Format] = &[
+        Format::Png,
+        Format::Jpeg,
+        Format::Gif,
+        Format::Zip,
+        Format::Gzip,
+        Format::SevenZip,
+        Format::Rar,
+        Format::Pdf,
+        Format::Html,
+        Format::PortableExecutable,
+        Format::Elf,
+        Format::MachO,
+        Format::Script,
+        Format::Armour,
+    ];
+
+    /// A stable ID for the format, for machine-readable output
+    pub fn id(&self) -> &'static str {
+        match self {
+            Format::Png => "png",
+            Format::Jpeg => "jpeg",
+            Format::Gif => "gif",
+            Format::Zip => "zip",
+            Format::Gzip => "gzip",
+            Format::SevenZip => "7z",
+            Format::Rar => "rar",
+            Format::Pdf => "pdf",
+            Format::Html => "html",
+            Format::PortableExecutable => "pe",
+            Format::Elf => "elf",
+            Format::MachO => "mach-o",
+            Format::Script => "script",
+            Format::Armour => "armour",
+        }
+    }
+
+    /// What the format is, e.g. "ZIP archive"
+    pub fn name(&self) -> &'static str {
+        match self {
+            Format::Png => "PNG image",
+            Format::Jpeg => "JPEG image",
+            Format::Gif => "GIF image",
+            Format::Zip => "ZIP archive",
+            Format::Gzip => "gzip stream",
+            Format::SevenZip => "7-Zip archive",
+            Format::Rar => "RAR archive",
+            Format::Pdf => "PDF document",
+            Format::Html => "HTML document",
+            Format::PortableExecutable => "Windows executable",
+            Format::Elf => "ELF executable",
+            Format::MachO => "Mach-O executable",
+            Format::Script => "script",
+            Format::Armour => "PEM or PGP armoured data",
+        }
+    }
+
+    /// Whether the format is an image, which some chunks legitimately hold, such as the
+    /// thumbnail in an eXIf chunk
+    pub fn is_image(&self) -> bool {
+        matches!(self, Format::Png | Format::Jpeg | Format::Gif)
+    }
+
+    /// Whether `data` starts with this format's magic number. Formats with short magic
+    /// numbers also need the bytes after it to make sense, so that they rarely match by chance.
+    pub fn matches(&self, data: &[u8]) -> bool {
+        let starts_with_any = |magics: &[&[u8]]| magics.iter().any(|magic| data.starts_with(magic));
+        match self {
+            Format::Png => data.starts_with(b"\x89PNG\r\n\x1a\n"),
+            Format::Jpeg => data.starts_with(b"\xff\xd8\xff") && data.get(3) >= Some(&0xc0),
+            Format::Gif => starts_with_any(&[b"GIF87a", b"GIF89a"]),
+            Format::Zip => starts_with_any(&[b"PK\x03\x04", b"PK\x05\x06"]),
+            Format::Gzip => data.starts_with(b"\x1f\x8b\x08") && data.get(3) < Some(&0x20),
+            Format::SevenZip => data.starts_with(b"7z\xbc\xaf\x27\x1c"),
+            Format::Rar => data.starts_with(b"Rar!\x1a\x07"),
+            Format::Pdf => data.starts_with(b"%PDF-"),
+            Format::Html => [&b"<!doctype html"[..], b"<html", b"<script"]
+                .iter()
+                .any(|tag| data.len() >= tag.len() && data[..tag.len()].eq_ignore_ascii_case(tag)),
+            Format::PortableExecutable => {
+                let Some(pointer) =
+                    data.get(PE_HEADER_POINTER_OFFSET..PE_HEADER_POINTER_OFFSET + 4)
+                else {
+                    return false;
+                };
+                let pe_offset =
+                    u32::from_le_bytes([pointer[0], pointer[1], pointer[2], pointer[3]]);
+                data.starts_with(b"MZ")
+                    && data
+                        .get(pe_offset as usize..)
+                        .is_some_and(|header| header.starts_with(b"PE\0\0"))
+            }
+            Format::Elf => data.starts_with(b"\x7fELF") && matches!(data.get(4), Some(1 | 2)),
+            Format::MachO => starts_with_any(&[
+                b"\xfe\xed\xfa\xce",
+                b"\xfe\xed\xfa\xcf",
+                b"\xce\xfa\xed\xfe",
+                b"\xcf\xfa\xed\xfe",
+            ]),
+            Format::Script => data.starts_with(b"#!/"),
+            Format::Armour => data.starts_with(b"-----BEGIN "),
+        }
+    }
+}
+
+impl Display for Format {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Another file's data found inside a PNG
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Embedded {
+    pub format: Format,
+    /// Where the format's magic number starts, within the chunk data or the data after IEND
+    pub offset: usize,
+}
+
+impl Display for Embedded {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at offset {}", self.format, self.offset)
+    }
+}
+
+/// The format `bytes` starts with, if it's one of the known formats
+pub fn identify(bytes: &[u8]) -> Option<Format> {
+    Format::ALL
+        .iter()
+        .copied()
+        .find(|format| format.matches(bytes))
+}
+
+/// The first occurrence of each known format anywhere in `data`, in order of offset
+pub fn find(data: &[u8]) -> Vec<Embedded> {
+    let mut found: Vec<Embedded> = vec![];
+    for offset in 0..data.len() {
+        for format in Format::ALL {
+            if format.matches(&data[offset..]) && !found.iter().any(|e| e.format == *format) {
+                found.push(Embedded {
+                    format: *format,
+                    offset,
+                });
+            }
+        }
+    }
+    found
+}
+
+/// The files hidden in a chunk's data. Image data is skipped, as it's compressed and so turns
+/// up short magic numbers by chance, and so are images in eXIf chunks, which hold thumbnails.
+pub fn find_in_chunk(chunk_type: &[u8; 4], data: &[u8]) -> Vec<Embedded> {
+    if IMAGE_DATA_CHUNK_TYPES.contains(&chunk_type) {
+        return vec![];
+    }
+    let mut found = find(data);
+    if chunk_type == b"eXIf" {
+        found.retain(|embedded| !embedded.format.is_image());
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_identify() {
+        assert_eq!(identify(b"PK\x03\x04\x14\x00"), Some(Format::Zip));
+        assert_eq!(identify(b"%PDF-1.7\n"), Some(Format::Pdf));
+        assert_eq!(identify(b"<!DOCTYPE html><p>"), Some(Format::Html));
+        assert_eq!(identify(b"\x7fELF\x02\x01\x01"), Some(Format::Elf));
+        assert_eq!(identify(b"\x1f\x8b\x08\x00"), Some(Format::Gzip));
+        assert_eq!(identify(b"\x1f\x8b\x08\xff"), None);
+        assert_eq!(identify(b"\xff\xd8\xff\x00"), None);
+        assert_eq!(identify(b"plain text"), None);
+    }
+
+    #[test]
+    fn test_portable_executable() {
+        let mut exe = b"MZ".to_vec();
+        exe.resize(0x80, 0);
+        exe[PE_HEADER_POINTER_OFFSET] = 0x80;
+        assert_eq!(identify(&exe), None);
+        exe.extend_from_slice(b"PE\0\0");
+        assert_eq!(identify(&exe), Some(Format::PortableExecutable));
+        assert_eq!(identify(b"MZ"), None);
+    }
+
+    #[test]
+    fn test_find() {
+        let mut data = b"Comment\0see attached ".to_vec();
+        data.extend_from_slice(b"PK\x03\x04 first entry PK\x03\x04 second entry ");
+        data.extend_from_slice(b"%PDF-1.4 and the end PK\x05\x06");
+        let found = find(&data);
+        assert_eq!(
+            found,
+            vec![
+                Embedded {
+                    format: Format::Zip,
+                    offset: 21
+                },
+                Embedded {
+                    format: Format::Pdf,
+                    offset: 56
+                },
+            ]
+        );
+        assert_eq!(found[1].to_string(), "PDF document at offset 56");
+    }
+
+    #[test]
+    fn test_find_in_chunk() {
+        let data = b"Exif\0\0\xff\xd8\xff\xdb thumbnail GIF89a";
+        assert!(find_in_chunk(b"eXIf", data).is_empty());
+        assert!(find_in_chunk(b"IDAT", b"%PDF-1.4").is_empty());
+        assert_eq!(find_in_chunk(b"tEXt", data).len(), 2);
+    }
+}
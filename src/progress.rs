@@ -0,0 +1,92 @@
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use std::fs::File;
+use std::io::{self, IsTerminal, Read};
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// Files at least this big get a progress bar of their own while they are read
+const LARGE_FILE_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Every bar is drawn through this, so that batch and per-file bars stack rather than fight
+static BARS: OnceLock<MultiProgress> = OnceLock::new();
+
+/// Draw progress bars on stderr from now on, unless output isn't going to a terminal. Until
+/// this is called every bar is hidden.
+pub fn init(enabled: bool) {
+    let enabled = enabled && io::stdout().is_terminal() && io::stderr().is_terminal();
+    let target = match enabled {
+        true => ProgressDrawTarget::stderr(),
+        false => ProgressDrawTarget::hidden(),
+    };
+    let _ = BARS.set(MultiProgress::with_draw_target(target));
+}
+
+fn add(bar: ProgressBar) -> ProgressBar {
+    match BARS.get() {
+        Some(bars) if !bars.is_hidden() => bars.add(bar),
+        _ => ProgressBar::hidden(),
+    }
+}
+
+/// A bar counting off the files of a batch
+pub fn files(len: usize) -> ProgressBar {
+    let style = ProgressStyle::with_template("{bar:40} {pos}/{len} files {elapsed_precise}")
+        .expect("the template is valid");
+    add(ProgressBar::new(len as u64).with_style(style))
+}
+
+/// Run `f`, which writes to stdout, with the bars cleared so that they don't garble its output
+pub fn suspend<R>(f: impl FnOnce() -> R) -> R {
+    match BARS.get() {
+        Some(bars) => bars.suspend(f),
+        None => f(),
+    }
+}
+
+/// Read the whole file, showing how many bytes have been read if it is large
+pub fn read(path: &Path) -> io::Result<Vec<u8>> {
+    let file = File::open(path)?;
+    let len = file.metadata()?.len();
+    if len < LARGE_FILE_BYTES {
+        return read_to_end(file, len);
+    }
+    let style =
+        ProgressStyle::with_template("{bar:40} {bytes}/{total_bytes} {bytes_per_sec} {wide_msg}")
+            .expect("the template is valid");
+    let bar = add(ProgressBar::new(len).with_style(style)).with_message(path.display().to_string());
+    let bytes = read_to_end(bar.wrap_read(file), len);
+    bar.finish_and_clear();
+    bytes
+}
+
+fn read_to_end(mut reader: impl Read, len: u64) -> io::Result<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(len as usize);
+    reader.read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a.png");
+        fs::write(&path, b"some bytes").unwrap();
+        assert_eq!(read(&path).unwrap(), b"some bytes");
+        assert!(read(&dir.path().join("missing.png")).is_err());
+        // The length is only a hint for the buffer
+        assert_eq!(read_to_end(&b"more"[..], 0).unwrap(), b"more");
+    }
+
+    #[test]
+    fn test_bars_hidden_off_a_terminal() {
+        init(true);
+        let bar = files(3);
+        assert!(bar.is_hidden());
+        bar.inc(1);
+        assert_eq!(suspend(|| 7), 7);
+    }
+}
@@ -0,0 +1,247 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::message;
+use crate::png::Png;
+use crate::PngError;
+use pyo3::create_exception;
+use pyo3::exceptions::PyException;
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+use std::str::FromStr;
+
+create_exception!(
+    pngmi,
+    PngmiError,
+    PyException,
+    "Raised when pngmi can't parse or edit a file"
+);
+
+fn py_error(e: PngError) -> PyErr {
+    PngmiError::new_err(e.to_string())
+}
+
+/// The message of an error raised with the interpreter released, which can only hand back
+/// what is safe to send between threads
+fn detached_error(e: PngError) -> String {
+    e.to_string()
+}
+
+/// A PNG file parsed from bytes and edited in memory
+#[pyclass(name = "Png", module = "pngmi")]
+pub struct PyPng {
+    png: Png,
+}
+
+#[pymethods]
+impl PyPng {
+    /// Parse the bytes of a PNG file, e.g. `Png(open("image.png", "rb").read())`
+    #[new]
+    fn new(data: &[u8]) -> PyResult<Self> {
+        let png = Png::try_from(data).map_err(py_error)?;
+        Ok(PyPng { png })
+    }
+
+    /// Copies of the chunks, in file order
+    fn chunks(&self) -> Vec<PyChunk> {
+        self.png
+            .chunks()
+            .iter()
+            .cloned()
+            .map(PyChunk::from)
+            .collect()
+    }
+
+    /// The first chunk of the given type, or `None`
+    fn chunk_by_type(&self, chunk_type: &str) -> Option<PyChunk> {
+        self.png
+            .chunk_by_type(chunk_type)
+            .cloned()
+            .map(PyChunk::from)
+    }
+
+    fn append_chunk(&mut self, chunk: PyChunk) {
+        self.png.append_chunk(chunk.chunk);
+    }
+
+    /// Remove and return the first chunk of the given type
+    fn remove_chunk(&mut self, chunk_type: &str) -> PyResult<PyChunk> {
+        let chunk = self.png.remove_chunk(chunk_type).map_err(py_error)?;
+        Ok(chunk.into())
+    }
+
+    /// Encode a text message into new chunks of the given type before IEND, encrypted if given
+    /// a passphrase, returning how many chunks it took. Other Python threads run meanwhile.
+    #[pyo3(signature = (chunk_type, message, passphrase=None))]
+    fn encode(
+        &mut self,
+        py: Python<'_>,
+        chunk_type: &str,
+        message: &str,
+        passphrase: Option<&str>,
+    ) -> PyResult<usize> {
+        let chunk_type = ChunkType::from_str(chunk_type).map_err(py_error)?;
+        let png = &mut self.png;
+        // Key derivation and compression can take a while, so the interpreter is released
+        py.detach(|| message::embed(png, chunk_type, message, passphrase).map_err(detached_error))
+            .map_err(PngmiError::new_err)
+    }
+
+    /// The payload stored in chunks of the given type, decrypted and decompressed. Other Python
+    /// threads run meanwhile.
+    #[pyo3(signature = (chunk_type, passphrase=None))]
+    fn decode<'py>(
+        &self,
+        py: Python<'py>,
+        chunk_type: &str,
+        passphrase: Option<&str>,
+    ) -> PyResult<Bound<'py, PyBytes>> {
+        let png = &self.png;
+        let envelope = py
+            .detach(|| message::read(png, chunk_type, passphrase).map_err(detached_error))
+            .map_err(PngmiError::new_err)?;
+        Ok(PyBytes::new(py, envelope.body()))
+    }
+
+    /// Any bytes after the IEND chunk
+    #[getter]
+    fn trailing_data<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, self.png.trailing_data())
+    }
+
+    fn __bytes__<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, &self.png.as_bytes())
+    }
+
+    fn __len__(&self) -> usize {
+        self.png.chunks().len()
+    }
+
+    fn __repr__(&self) -> String {
+        self.png.to_string()
+    }
+}
+
+/// A single chunk, holding a copy of its data
+#[pyclass(name = "Chunk", module = "pngmi", from_py_object)]
+#[derive(Clone)]
+pub struct PyChunk {
+    chunk: Chunk,
+}
+
+impl From<Chunk> for PyChunk {
+    fn from(chunk: Chunk) -> Self {
+        PyChunk { chunk }
+    }
+}
+
+#[pymethods]
+impl PyChunk {
+    /// A new chunk of the given type, e.g. `Chunk("ruSt", b"data")`, with its CRC computed
+    #[new]
+    fn new(chunk_type: &str, data: &[u8]) -> PyResult<Self> {
+        let chunk_type = ChunkType::from_str(chunk_type).map_err(py_error)?;
+        Ok(Chunk::new(chunk_type, data.to_vec()).into())
+    }
+
+    /// Parse a chunk from its bytes as laid out in a file: length, type, data and CRC
+    #[staticmethod]
+    fn from_bytes(data: &[u8]) -> PyResult<Self> {
+        let chunk = Chunk::try_from(data).map_err(py_error)?;
+        Ok(chunk.into())
+    }
+
+    #[getter]
+    fn chunk_type(&self) -> String {
+        self.chunk.chunk_type().to_string()
+    }
+
+    #[getter]
+    fn data<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, self.chunk.data())
+    }
+
+    #[getter]
+    fn length(&self) -> u32 {
+        self.chunk.length()
+    }
+
+    #[getter]
+    fn crc(&self) -> u32 {
+        self.chunk.crc()
+    }
+
+    fn __bytes__<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, &self.chunk.as_bytes())
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "Chunk(chunk_type={:?}, length={})",
+            self.chunk.chunk_type().to_string(),
+            self.chunk.length()
+        )
+    }
+}
+
+/// A copy of the PNG file in `data` with a text message encoded into chunks of the given type
+#[pyfunction]
+#[pyo3(signature = (data, chunk_type, message, passphrase=None))]
+fn encode<'py>(
+    py: Python<'py>,
+    data: &[u8],
+    chunk_type: &str,
+    message: &str,
+    passphrase: Option<&str>,
+) -> PyResult<Bound<'py, PyBytes>> {
+    let mut png = PyPng::new(data)?;
+    png.encode(py, chunk_type, message, passphrase)?;
+    Ok(png.__bytes__(py))
+}
+
+/// The payload stored in chunks of the given type in the PNG file in `data`
+#[pyfunction]
+#[pyo3(signature = (data, chunk_type, passphrase=None))]
+fn decode<'py>(
+    py: Python<'py>,
+    data: &[u8],
+    chunk_type: &str,
+    passphrase: Option<&str>,
+) -> PyResult<Bound<'py, PyBytes>> {
+    PyPng::new(data)?.decode(py, chunk_type, passphrase)
+}
+
+/// The `pngmi` Python module, built with `maturin build` using the settings in pyproject.toml
+#[pymodule]
+fn pngmi(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<PyPng>()?;
+    module.add_class::<PyChunk>()?;
+    module.add_function(wrap_pyfunction!(encode, module)?)?;
+    module.add_function(wrap_pyfunction!(decode, module)?)?;
+    module.add("PngmiError", module.py().get_type::<PngmiError>())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::minimal_png;
+
+    #[test]
+    fn test_round_trip() {
+        Python::initialize();
+        Python::attach(|py| {
+            let mut png = PyPng::new(&minimal_png().as_bytes()).unwrap();
+            assert_eq!(png.__len__(), 2);
+            assert_eq!(png.encode(py, "ruSt", "Hello there", None).unwrap(), 1);
+            let types: Vec<_> = png.chunks().iter().map(PyChunk::chunk_type).collect();
+            assert_eq!(types, ["IHDR", "ruSt", "IEND"]);
+
+            let bytes = png.__bytes__(py);
+            let payload = decode(py, bytes.as_bytes(), "ruSt", None).unwrap();
+            assert_eq!(payload.as_bytes(), b"Hello there");
+            let error = decode(py, bytes.as_bytes(), "ruSt", Some("passphrase")).unwrap_err();
+            assert!(error.is_instance_of::<PngmiError>(py));
+            assert!(PyPng::new(b"not a png").is_err());
+        });
+    }
+}
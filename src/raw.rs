@@ -0,0 +1,209 @@
+use crate::chunk::Chunk;
+use crate::png::Png;
+use std::fmt::{Display, Formatter};
+
+/// A chunk read straight off the bytes of a PNG file without any validation, so that tools
+/// which inspect or fix broken files can see chunks `Chunk::try_from` would reject
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RawChunk<'a> {
+    /// The offset of the chunk's length field from the start of the file
+    pub offset: usize,
+    /// The chunk type bytes, which may not form a valid `ChunkType`
+    pub chunk_type: [u8; 4],
+    /// The chunk data
+    pub data: &'a [u8],
+    /// The CRC stored in the file
+    pub stored_crc: u32,
+}
+
+impl RawChunk<'_> {
+    /// The CRC the chunk should have given its type and data
+    pub fn computed_crc(&self) -> u32 {
+        Chunk::compute_crc(&self.chunk_type, self.data)
+    }
+
+    /// Returns whether the stored CRC matches the chunk type and data
+    pub fn crc_matches(&self) -> bool {
+        self.stored_crc == self.computed_crc()
+    }
+
+    /// The chunk type as text, with any bytes that aren't letters replaced
+    pub fn type_name(&self) -> String {
+        String::from_utf8_lossy(&self.chunk_type).into_owned()
+    }
+
+    /// Returns whether this chunk has the given type
+    pub fn is(&self, chunk_type: &[u8; 4]) -> bool {
+        &self.chunk_type == chunk_type
+    }
+
+    /// The overall size of this chunk including the length, chunk type and CRC fields
+    pub fn overall_length(&self) -> usize {
+        self.data.len() + Chunk::OVERHEAD_BYTES_LEN
+    }
+
+    /// The offset just past the end of this chunk
+    pub fn end(&self) -> usize {
+        self.offset + self.overall_length()
+    }
+}
+
+/// Every chunk whose stored CRC doesn't match, paired with the CRC it should have, in file
+/// order. With the `parallel` feature the CRCs are computed across all available cores.
+pub fn crc_mismatches<'c, 'a>(chunks: &'c [RawChunk<'a>]) -> Vec<(&'c RawChunk<'a>, u32)> {
+    let check = |chunk: &'c RawChunk<'a>| {
+        let computed = chunk.computed_crc();
+        (computed != chunk.stored_crc).then_some((chunk, computed))
+    };
+    #[cfg(feature = "parallel")]
+    {
+        use rayon::prelude::*;
+        chunks.par_iter().filter_map(check).collect()
+    }
+    #[cfg(not(feature = "parallel"))]
+    chunks.iter().filter_map(check).collect()
+}
+
+/// A structural problem which stops `RawChunks` from reading any further
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawError {
+    /// The offset at which the problem was found
+    pub offset: usize,
+    pub message: String,
+}
+
+impl Display for RawError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at offset {}", self.message, self.offset)
+    }
+}
+
+impl std::error::Error for RawError {}
+
+/// Iterates over the chunks of a PNG file, starting right after the signature and stopping
+/// after IEND, at the end of the input or at the first truncated chunk
+pub struct RawChunks<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+    done: bool,
+}
+
+impl<'a> RawChunks<'a> {
+    /// Walk the chunks of the given file. The signature itself is not checked.
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self::at(bytes, Png::HEADER_BYTES_LEN)
+    }
+
+    /// Walk the chunks starting at the given offset
+    pub fn at(bytes: &'a [u8], offset: usize) -> Self {
+        RawChunks {
+            bytes,
+            offset,
+            done: false,
+        }
+    }
+
+    /// The offset the next chunk would be read from; after iteration finishes this is where
+    /// the datastream ended, e.g. just past IEND
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    fn fail(&mut self, message: &str) -> Option<Result<RawChunk<'a>, RawError>> {
+        self.done = true;
+        Some(Err(RawError {
+            offset: self.offset,
+            message: message.to_string(),
+        }))
+    }
+}
+
+impl<'a> Iterator for RawChunks<'a> {
+    type Item = Result<RawChunk<'a>, RawError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done || self.offset >= self.bytes.len() {
+            return None;
+        }
+        let rest = &self.bytes[self.offset..];
+        if rest.len() < Chunk::OVERHEAD_BYTES_LEN {
+            return self.fail("Truncated chunk header");
+        }
+        let length = u32::from_be_bytes(rest[0..4].try_into().unwrap()) as usize;
+        if length > i32::MAX as usize {
+            return self.fail("Chunk length exceeds the 2^31-1 limit");
+        }
+        if rest.len() < length + Chunk::OVERHEAD_BYTES_LEN {
+            return self.fail("Truncated chunk data");
+        }
+        let chunk = RawChunk {
+            offset: self.offset,
+            chunk_type: rest[4..8].try_into().unwrap(),
+            data: &rest[8..8 + length],
+            stored_crc: u32::from_be_bytes(rest[8 + length..12 + length].try_into().unwrap()),
+        };
+        self.offset = chunk.end();
+        self.done = chunk.is(b"IEND");
+        Some(Ok(chunk))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk_type::ChunkType;
+    use std::str::FromStr;
+
+    fn testing_bytes() -> Vec<u8> {
+        let chunks = ["IHDR", "IDAT", "IEND"]
+            .iter()
+            .map(|t| Chunk::new(ChunkType::from_str(t).unwrap(), t.as_bytes().to_vec()))
+            .collect();
+        Png::from_chunks(chunks).as_bytes()
+    }
+
+    #[test]
+    fn test_raw_chunks() {
+        let bytes = testing_bytes();
+        let chunks: Vec<_> = RawChunks::new(&bytes).map(Result::unwrap).collect();
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks[0].offset, 8);
+        assert_eq!(chunks[1].offset, 8 + 16);
+        assert!(chunks.iter().all(RawChunk::crc_matches));
+        assert_eq!(chunks[2].type_name(), "IEND");
+    }
+
+    #[test]
+    fn test_raw_chunks_stop_after_iend() {
+        let mut bytes = testing_bytes();
+        let len = bytes.len();
+        bytes.extend_from_slice(b"trailing");
+        let mut chunks = RawChunks::new(&bytes);
+        assert_eq!(chunks.by_ref().count(), 3);
+        assert_eq!(chunks.offset(), len);
+    }
+
+    #[test]
+    fn test_crc_mismatches() {
+        let mut bytes = testing_bytes();
+        let chunks: Vec<_> = RawChunks::new(&bytes).map(Result::unwrap).collect();
+        assert!(crc_mismatches(&chunks).is_empty());
+        let (offset, crc_end) = (chunks[1].offset, chunks[1].end());
+        bytes[crc_end - 1] ^= 1;
+        let chunks: Vec<_> = RawChunks::new(&bytes).map(Result::unwrap).collect();
+        let mismatches = crc_mismatches(&chunks);
+        assert_eq!(mismatches.len(), 1);
+        assert_eq!(mismatches[0].0.offset, offset);
+        assert_eq!(mismatches[0].1, chunks[1].stored_crc ^ 1);
+    }
+
+    #[test]
+    fn test_raw_chunks_bad_crc_and_truncation() {
+        let mut bytes = testing_bytes();
+        bytes[8 + 12] ^= 1; // corrupt the IHDR CRC
+        let truncated = &bytes[..bytes.len() - 3];
+        let chunks: Vec<_> = RawChunks::new(truncated).collect();
+        assert!(!chunks[0].as_ref().unwrap().crc_matches());
+        assert_eq!(chunks[2].as_ref().unwrap_err().offset, 8 + 32);
+    }
+}
@@ -0,0 +1,268 @@
+use crate::error::{error, ErrorKind};
+use crate::PngResult;
+use bech32::{Bech32, Hrp};
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use hkdf::Hkdf;
+use sha2::Sha256;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use x25519_dalek::{EphemeralSecret, PublicKey, StaticSecret};
+
+/// Marks the start of a payload encrypted to one or more X25519 recipients
+pub const RECIPIENTS_MAGIC: [u8; 4] = *b"pmiR";
+
+/// The only recipient payload layout understood so far
+const RECIPIENTS_VERSION: u8 = 1;
+
+const KEY_LEN: usize = 32;
+const TAG_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+/// An ephemeral share followed by the wrapped file key
+const STANZA_LEN: usize = KEY_LEN + KEY_LEN + TAG_LEN;
+
+/// The info string binding derived wrapping keys to this tool
+const WRAP_INFO: &[u8] = b"pngmi/X25519";
+
+/// The human readable parts used by age for its bech32 encoded keys
+const RECIPIENT_HRP: &str = "age";
+const IDENTITY_HRP: &str = "age-secret-key-";
+
+/// A public key which can decrypt payloads addressed to it, encoded like an age recipient
+/// (`age1...`) so that keys made with `age-keygen` can be used as is
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Recipient(PublicKey);
+
+/// The private half of a `Recipient`, encoded like an age identity (`AGE-SECRET-KEY-1...`)
+pub struct Identity(StaticSecret);
+
+impl Identity {
+    /// Generate a new random identity
+    pub fn generate() -> Self {
+        Identity(StaticSecret::random_from_rng(OsRng))
+    }
+
+    /// The recipient which payloads for this identity should be encrypted to
+    pub fn recipient(&self) -> Recipient {
+        Recipient(PublicKey::from(&self.0))
+    }
+}
+
+/// Decode a bech32 string with the expected human readable part into a 32-byte key
+fn decode_key(s: &str, hrp: &str) -> Result<[u8; KEY_LEN], String> {
+    let (found, data) = bech32::decode(s).map_err(|e| format!("Invalid key '{}': {}", s, e))?;
+    if !found.as_str().eq_ignore_ascii_case(hrp) {
+        return Err(format!("Expected a key starting with '{}1'", hrp));
+    }
+    data.try_into()
+        .map_err(|_| format!("Key '{}' has the wrong length", s))
+}
+
+/// Encode a 32-byte key as bech32 with the given human readable part
+fn encode_key(key: &[u8], hrp: &str) -> String {
+    let hrp = Hrp::parse(hrp).expect("valid human readable part");
+    bech32::encode::<Bech32>(hrp, key).expect("keys fit in a bech32 string")
+}
+
+impl FromStr for Recipient {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        decode_key(s, RECIPIENT_HRP).map(|key| Recipient(PublicKey::from(key)))
+    }
+}
+
+impl Display for Recipient {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", encode_key(self.0.as_bytes(), RECIPIENT_HRP))
+    }
+}
+
+impl FromStr for Identity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        decode_key(s, IDENTITY_HRP).map(|key| Identity(StaticSecret::from(key)))
+    }
+}
+
+impl Display for Identity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let key = encode_key(self.0.as_bytes(), IDENTITY_HRP);
+        write!(f, "{}", key.to_uppercase())
+    }
+}
+
+/// Load the identities from an age style identity file, one per line with `#` comments
+pub fn load_identities(path: &Path) -> PngResult<Vec<Identity>> {
+    let identities = fs::read_to_string(path)?
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(Identity::from_str)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| error(ErrorKind::Crypto, e))?;
+    if identities.is_empty() {
+        return Err(error(
+            ErrorKind::Crypto,
+            format!("No identities found in {}", path.display()),
+        ));
+    }
+    Ok(identities)
+}
+
+/// Returns whether the given payload looks like the output of `encrypt`
+pub fn is_encrypted(payload: &[u8]) -> bool {
+    payload.starts_with(&RECIPIENTS_MAGIC)
+}
+
+/// Derive the key wrapping the file key for one recipient from the X25519 shared secret
+fn wrapping_key(shared: &[u8], share: &PublicKey, recipient: &PublicKey) -> [u8; KEY_LEN] {
+    let salt = [share.as_bytes().as_slice(), recipient.as_bytes()].concat();
+    let mut key = [0u8; KEY_LEN];
+    Hkdf::<Sha256>::new(Some(&salt), shared)
+        .expand(WRAP_INFO, &mut key)
+        .expect("32 bytes is a valid HKDF output length");
+    key
+}
+
+/// Encrypt the plaintext so that any one of the recipients can decrypt it.
+///
+/// A random file key encrypts the payload with ChaCha20-Poly1305. For every recipient the
+/// file key is wrapped with a key derived from an ephemeral X25519 exchange, as age does.
+pub fn encrypt(plaintext: &[u8], recipients: &[Recipient]) -> PngResult<Vec<u8>> {
+    let count = u8::try_from(recipients.len())
+        .ok()
+        .filter(|count| *count > 0)
+        .ok_or("Between 1 and 255 recipients are required")?;
+
+    let mut file_key = [0u8; KEY_LEN];
+    let mut nonce = [0u8; NONCE_LEN];
+    OsRng.fill_bytes(&mut file_key);
+    OsRng.fill_bytes(&mut nonce);
+
+    let mut encrypted = RECIPIENTS_MAGIC.to_vec();
+    encrypted.push(RECIPIENTS_VERSION);
+    encrypted.push(count);
+    for Recipient(recipient) in recipients {
+        let ephemeral = EphemeralSecret::random_from_rng(OsRng);
+        let share = PublicKey::from(&ephemeral);
+        let shared = ephemeral.diffie_hellman(recipient);
+        let key = wrapping_key(shared.as_bytes(), &share, recipient);
+        // Each wrapping key is used exactly once, so a fixed nonce is fine
+        let wrapped = ChaCha20Poly1305::new(Key::from_slice(&key))
+            .encrypt(&Nonce::default(), file_key.as_slice())
+            .map_err(|_| "Failed to wrap the file key")?;
+        encrypted.extend_from_slice(share.as_bytes());
+        encrypted.extend_from_slice(&wrapped);
+    }
+
+    let ciphertext = ChaCha20Poly1305::new(Key::from_slice(&file_key))
+        .encrypt(Nonce::from_slice(&nonce), plaintext)
+        .map_err(|_| "Encryption failed")?;
+    encrypted.extend_from_slice(&nonce);
+    encrypted.extend_from_slice(&ciphertext);
+    Ok(encrypted)
+}
+
+/// Decrypt a payload produced by `encrypt` with whichever of the identities it was sent to
+pub fn decrypt(encrypted: &[u8], identities: &[Identity]) -> PngResult<Vec<u8>> {
+    const PREAMBLE_LEN: usize = RECIPIENTS_MAGIC.len() + 2;
+    if !is_encrypted(encrypted) || encrypted.len() < PREAMBLE_LEN {
+        return Err(error(
+            ErrorKind::Crypto,
+            "Payload is not encrypted to any recipients",
+        ));
+    }
+    if encrypted[RECIPIENTS_MAGIC.len()] != RECIPIENTS_VERSION {
+        return Err(error(
+            ErrorKind::Crypto,
+            "Unsupported recipient payload version",
+        ));
+    }
+    let count = encrypted[RECIPIENTS_MAGIC.len() + 1] as usize;
+    let body_start = PREAMBLE_LEN + count * STANZA_LEN;
+    if encrypted.len() < body_start + NONCE_LEN + TAG_LEN {
+        return Err(error(ErrorKind::Crypto, "Truncated recipient payload"));
+    }
+
+    let stanzas = encrypted[PREAMBLE_LEN..body_start].chunks(STANZA_LEN);
+    let file_key = stanzas
+        .flat_map(|stanza| identities.iter().map(move |identity| (stanza, identity)))
+        .find_map(|(stanza, Identity(secret))| {
+            let share = PublicKey::from(<[u8; KEY_LEN]>::try_from(&stanza[..KEY_LEN]).ok()?);
+            let shared = secret.diffie_hellman(&share);
+            if !shared.was_contributory() {
+                return None;
+            }
+            let key = wrapping_key(shared.as_bytes(), &share, &PublicKey::from(secret));
+            ChaCha20Poly1305::new(Key::from_slice(&key))
+                .decrypt(&Nonce::default(), &stanza[KEY_LEN..])
+                .ok()
+        })
+        .ok_or_else(|| {
+            error(
+                ErrorKind::Crypto,
+                "None of the given identities can decrypt this payload",
+            )
+        })?;
+
+    let (nonce, ciphertext) = encrypted[body_start..].split_at(NONCE_LEN);
+    ChaCha20Poly1305::new(Key::from_slice(&file_key))
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| error(ErrorKind::Crypto, "Decryption failed: corrupted payload"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encrypt_to_multiple_recipients() {
+        let (alice, bob) = (Identity::generate(), Identity::generate());
+        let encrypted = encrypt(b"for both", &[alice.recipient(), bob.recipient()]).unwrap();
+        assert!(is_encrypted(&encrypted));
+        assert_eq!(decrypt(&encrypted, &[alice]).unwrap(), b"for both");
+        assert_eq!(decrypt(&encrypted, &[bob]).unwrap(), b"for both");
+    }
+
+    #[test]
+    fn test_decrypt_with_other_identity() {
+        let encrypted = encrypt(b"not for eve", &[Identity::generate().recipient()]).unwrap();
+        assert!(decrypt(&encrypted, &[Identity::generate()]).is_err());
+    }
+
+    #[test]
+    fn test_no_recipients() {
+        assert!(encrypt(b"nobody", &[]).is_err());
+    }
+
+    #[test]
+    fn test_key_encoding_round_trip() {
+        let identity = Identity::generate();
+        let encoded = identity.to_string();
+        assert!(encoded.starts_with("AGE-SECRET-KEY-1"));
+        let decoded = Identity::from_str(&encoded).unwrap();
+        assert_eq!(decoded.recipient(), identity.recipient());
+
+        let recipient = identity.recipient().to_string();
+        assert!(recipient.starts_with("age1"));
+        assert_eq!(
+            Recipient::from_str(&recipient).unwrap(),
+            identity.recipient()
+        );
+    }
+
+    #[test]
+    fn test_invalid_keys() {
+        assert!(Recipient::from_str("age1invalid").is_err());
+        let identity = Identity::generate();
+        // A secret key is not a recipient and vice versa
+        assert!(Recipient::from_str(&identity.to_string()).is_err());
+        assert!(Identity::from_str(&identity.recipient().to_string()).is_err());
+    }
+}
@@ -0,0 +1,180 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+use crate::raw::{self, RawChunks};
+use crate::PngResult;
+use std::fmt::{Display, Formatter};
+
+/// A chunk whose stored CRC didn't match its contents
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CrcFix {
+    /// The offset of the chunk from the start of the file
+    pub offset: usize,
+    pub chunk_type: String,
+    /// The CRC found in the file
+    pub stored: u32,
+    /// The CRC recomputed from the chunk type and data
+    pub computed: u32,
+}
+
+impl Display for CrcFix {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:#010x}: {} chunk CRC {:#010x} -> {:#010x}",
+            self.offset, self.chunk_type, self.stored, self.computed
+        )
+    }
+}
+
+/// Find every chunk with a stale CRC, without changing anything
+pub fn find_bad_crcs(bytes: &[u8]) -> PngResult<Vec<CrcFix>> {
+    if !bytes.starts_with(&Png::STANDARD_HEADER) {
+        return Err("Invalid header found".into());
+    }
+    let chunks = RawChunks::new(bytes).collect::<Result<Vec<_>, _>>()?;
+    let fixes = raw::crc_mismatches(&chunks)
+        .into_iter()
+        .map(|(chunk, computed)| CrcFix {
+            offset: chunk.offset,
+            chunk_type: chunk.type_name(),
+            stored: chunk.stored_crc,
+            computed,
+        })
+        .collect();
+    Ok(fixes)
+}
+
+/// Rewrite the CRC of every chunk whose stored checksum doesn't match, in place.
+/// Returns what was fixed. Everything else, including data after IEND, is left untouched.
+pub fn repair_crcs(bytes: &mut [u8]) -> PngResult<Vec<CrcFix>> {
+    let fixes = find_bad_crcs(bytes)?;
+    for fix in &fixes {
+        let length = u32::from_be_bytes(bytes[fix.offset..fix.offset + 4].try_into()?) as usize;
+        let crc_start = fix.offset + 8 + length;
+        bytes[crc_start..crc_start + 4].copy_from_slice(&fix.computed.to_be_bytes());
+    }
+    Ok(fixes)
+}
+
+/// Where a file which ends before its IEND chunk stops being usable
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Truncation {
+    /// The offset just past the last complete chunk
+    pub offset: usize,
+    /// The number of bytes of incomplete chunk data after that offset
+    pub dropped: usize,
+}
+
+impl Display for Truncation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:#010x}: file is truncated; dropping {} bytes of incomplete chunk data and appending IEND",
+            self.offset, self.dropped
+        )
+    }
+}
+
+/// Find where a file cut off before its IEND chunk ends, or None if the datastream is complete
+pub fn find_truncation(bytes: &[u8]) -> PngResult<Option<Truncation>> {
+    if !bytes.starts_with(&Png::STANDARD_HEADER) {
+        return Err("Invalid header found".into());
+    }
+    let mut walker = RawChunks::new(bytes);
+    let complete = walker
+        .by_ref()
+        .filter_map(Result::ok)
+        .any(|chunk| chunk.is(b"IEND"));
+    if complete {
+        return Ok(None);
+    }
+    Ok(Some(Truncation {
+        offset: walker.offset(),
+        dropped: bytes.len() - walker.offset(),
+    }))
+}
+
+/// Drop the incomplete tail of a truncated file and terminate what is left with an IEND chunk,
+/// so that viewers can at least show the part of the image which survived
+pub fn salvage_truncated(bytes: &mut Vec<u8>) -> PngResult<Option<Truncation>> {
+    let truncation = find_truncation(bytes)?;
+    if let Some(truncation) = &truncation {
+        bytes.truncate(truncation.offset);
+        let iend = Chunk::new(ChunkType::try_from(*b"IEND")?, vec![]);
+        bytes.extend_from_slice(&iend.as_bytes());
+    }
+    Ok(truncation)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn png_bytes() -> Vec<u8> {
+        let chunks = ["IHDR", "tEXt", "IDAT", "IEND"]
+            .iter()
+            .map(|t| Chunk::new(ChunkType::from_str(t).unwrap(), t.as_bytes().to_vec()))
+            .collect();
+        Png::from_chunks(chunks).as_bytes()
+    }
+
+    #[test]
+    fn test_repair_crcs() {
+        let original = png_bytes();
+        let mut bytes = original.clone();
+        // Edit the tEXt data without updating its CRC
+        bytes[8 + 16 + 8] = b'T';
+        let mut expected = bytes.clone();
+
+        let fixes = repair_crcs(&mut bytes).unwrap();
+        assert_eq!(fixes.len(), 1);
+        assert_eq!(fixes[0].offset, 8 + 16);
+        assert_eq!(fixes[0].chunk_type, "tEXt");
+        assert!(Png::try_from(bytes.as_slice()).is_ok());
+
+        // Only the CRC changed
+        expected[8 + 16 + 12..8 + 16 + 16].copy_from_slice(&fixes[0].computed.to_be_bytes());
+        assert_eq!(bytes, expected);
+    }
+
+    #[test]
+    fn test_nothing_to_repair() {
+        let mut bytes = png_bytes();
+        assert!(repair_crcs(&mut bytes).unwrap().is_empty());
+        assert_eq!(bytes, png_bytes());
+    }
+
+    #[test]
+    fn test_repair_truncated_file() {
+        let mut bytes = png_bytes();
+        bytes.truncate(bytes.len() - 2);
+        assert!(repair_crcs(&mut bytes).is_err());
+    }
+
+    #[test]
+    fn test_salvage_truncated() {
+        let original = png_bytes();
+        let mut bytes = original[..original.len() - 16 - 2].to_vec();
+        let truncation = salvage_truncated(&mut bytes).unwrap().unwrap();
+        // Every chunk is 16 bytes long, and IDAT lost the last two bytes of its CRC
+        assert_eq!(truncation.offset, 8 + 16 + 16);
+        assert_eq!(truncation.dropped, 14);
+
+        let png = Png::try_from(bytes.as_slice()).unwrap();
+        let types: Vec<String> = png
+            .chunks()
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect();
+        assert_eq!(types, vec!["IHDR", "tEXt", "IEND"]);
+    }
+
+    #[test]
+    fn test_salvage_complete_file() {
+        let mut bytes = png_bytes();
+        assert_eq!(salvage_truncated(&mut bytes).unwrap(), None);
+        assert_eq!(bytes, png_bytes());
+    }
+}
@@ -0,0 +1,387 @@
+use crate::entropy::{self, DataClass};
+use crate::png::PngRef;
+use crate::scan::{Finding, FindingKind};
+use crate::PngResult;
+use std::fmt::Write;
+use std::path::Path;
+
+/// Chunk entropy is charted out of this many bits per byte
+const MAX_ENTROPY: f64 = 8.0;
+
+/// The width of the entropy bars in Markdown reports, in characters
+const MARKDOWN_BAR_WIDTH: usize = 16;
+
+/// The document formats an audit report can be rendered as
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    Markdown,
+    Html,
+}
+
+impl ReportFormat {
+    /// The format implied by the extension of the file the report is written to
+    pub fn from_path(path: &Path) -> PngResult<Self> {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_ascii_lowercase);
+        match extension.as_deref() {
+            Some("html") | Some("htm") => Ok(ReportFormat::Html),
+            Some("md") | Some("markdown") => Ok(ReportFormat::Markdown),
+            _ => Err(format!(
+                "Can't tell the report format of {}; use a .html or .md extension",
+                path.display()
+            )
+            .into()),
+        }
+    }
+}
+
+/// A chunk's row in the per-file breakdown
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChunkSummary {
+    pub chunk_type: String,
+    pub length: usize,
+    /// The Shannon entropy of the chunk data, in bits per byte
+    pub entropy: f64,
+    pub class: DataClass,
+}
+
+/// What a scan found in a single file
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileReport {
+    pub path: String,
+    pub chunks: Vec<ChunkSummary>,
+    pub trailing_data: usize,
+    pub findings: Vec<Finding>,
+    /// Why the file couldn't be scanned, if it couldn't
+    pub error: Option<String>,
+}
+
+impl FileReport {
+    pub fn new(path: &Path, png: &PngRef, findings: Vec<Finding>) -> Self {
+        let chunks = png
+            .chunks()
+            .iter()
+            .map(|chunk| ChunkSummary {
+                chunk_type: chunk.chunk_type().to_string(),
+                length: chunk.length() as usize,
+                entropy: entropy::shannon_entropy(chunk.data()),
+                class: entropy::classify(chunk.data()),
+            })
+            .collect();
+        FileReport {
+            path: path.display().to_string(),
+            chunks,
+            trailing_data: png.trailing_data().len(),
+            findings,
+            error: None,
+        }
+    }
+
+    pub fn unreadable(path: &Path, error: &str) -> Self {
+        FileReport {
+            path: path.display().to_string(),
+            chunks: vec![],
+            trailing_data: 0,
+            findings: vec![],
+            error: Some(error.to_string()),
+        }
+    }
+
+    /// Whether any finding relates to the chunk at the given index
+    fn is_flagged(&self, index: usize) -> bool {
+        self.findings
+            .iter()
+            .any(|finding| finding.chunk_index == Some(index))
+    }
+}
+
+/// A human-readable summary of a scan across many files, for handing on to people who won't
+/// read the raw findings
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Report {
+    pub files: Vec<FileReport>,
+}
+
+impl Report {
+    pub fn render(&self, format: ReportFormat) -> String {
+        match format {
+            ReportFormat::Markdown => self.to_markdown(),
+            ReportFormat::Html => self.to_html(),
+        }
+    }
+
+    /// The number of files with findings, the number which couldn't be read, the number of
+    /// chunks and the number of bytes of chunk data across every file
+    fn totals(&self) -> (usize, usize, usize, usize) {
+        let carriers = self
+            .files
+            .iter()
+            .filter(|file| !file.findings.is_empty())
+            .count();
+        let unreadable = self
+            .files
+            .iter()
+            .filter(|file| file.error.is_some())
+            .count();
+        let chunks = self.files.iter().map(|file| file.chunks.len()).sum();
+        let bytes = self
+            .files
+            .iter()
+            .flat_map(|file| &file.chunks)
+            .map(|chunk| chunk.length)
+            .sum();
+        (carriers, unreadable, chunks, bytes)
+    }
+
+    /// How many findings of each kind there are, leaving out the kinds which weren't found
+    fn finding_counts(&self) -> Vec<(FindingKind, usize)> {
+        FindingKind::ALL
+            .iter()
+            .map(|kind| {
+                let count = self
+                    .files
+                    .iter()
+                    .flat_map(|file| &file.findings)
+                    .filter(|finding| finding.kind == *kind)
+                    .count();
+                (*kind, count)
+            })
+            .filter(|(_, count)| *count > 0)
+            .collect()
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let (carriers, unreadable, chunks, bytes) = self.totals();
+        let mut out = String::new();
+        let _ = writeln!(out, "# PNG scan report\n");
+        let _ = writeln!(out, "## Summary\n");
+        let _ = writeln!(out, "| | |\n|---|---|");
+        let _ = writeln!(out, "| Files scanned | {} |", self.files.len());
+        let _ = writeln!(out, "| Likely carriers | {} |", carriers);
+        let _ = writeln!(out, "| Unreadable files | {} |", unreadable);
+        let _ = writeln!(out, "| Chunks | {} |", chunks);
+        let _ = writeln!(out, "| Chunk data | {} bytes |", bytes);
+        for (kind, count) in self.finding_counts() {
+            let _ = writeln!(out, "| {} | {} |", kind.description(), count);
+        }
+
+        for file in &self.files {
+            let _ = writeln!(out, "\n## {}\n", escape_markdown(&file.path));
+            if let Some(error) = &file.error {
+                let _ = writeln!(out, "**Unreadable:** {}", escape_markdown(error));
+                continue;
+            }
+            if file.findings.is_empty() {
+                let _ = writeln!(out, "No suspicious findings.\n");
+            } else {
+                let _ = writeln!(out, "**Findings:**\n");
+                for finding in &file.findings {
+                    let _ = writeln!(out, "- {}", escape_markdown(&finding.to_string()));
+                }
+                let _ = writeln!(out);
+            }
+            let _ = writeln!(out, "| # | Type | Length | Entropy | | Class |");
+            let _ = writeln!(out, "|---:|---|---:|---:|---|---|");
+            for (index, chunk) in file.chunks.iter().enumerate() {
+                let filled = (chunk.entropy / MAX_ENTROPY * MARKDOWN_BAR_WIDTH as f64).round();
+                let filled = (filled as usize).min(MARKDOWN_BAR_WIDTH);
+                let flag = if file.is_flagged(index) { " ⚠" } else { "" };
+                let _ = writeln!(
+                    out,
+                    "| {} | {}{} | {} | {:.2} | `{}{}` | {} |",
+                    index,
+                    escape_markdown(&chunk.chunk_type),
+                    flag,
+                    chunk.length,
+                    chunk.entropy,
+                    "█".repeat(filled),
+                    "░".repeat(MARKDOWN_BAR_WIDTH - filled),
+                    chunk.class
+                );
+            }
+            if file.trailing_data > 0 {
+                let _ = writeln!(out, "\n{} bytes of data after IEND.", file.trailing_data);
+            }
+        }
+        out
+    }
+
+    pub fn to_html(&self) -> String {
+        let (carriers, unreadable, chunks, bytes) = self.totals();
+        let mut out = String::new();
+        out.push_str(concat!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n",
+            "<title>PNG scan report</title>\n<style>\n",
+            "body { font-family: sans-serif; margin: 2em; }\n",
+            "table { border-collapse: collapse; margin-bottom: 1em; }\n",
+            "th, td { border: 1px solid #ccc; padding: 0.2em 0.6em; text-align: left; }\n",
+            ".bar { background: #eee; width: 12em; height: 0.8em; }\n",
+            ".bar div { background: #4a90d9; height: 100%; }\n",
+            ".flagged td { background: #fde8e8; }\n",
+            ".flagged .bar div { background: #d94a4a; }\n",
+            ".error { color: #b00; }\n",
+            "</style>\n</head>\n<body>\n<h1>PNG scan report</h1>\n",
+        ));
+        out.push_str("<h2>Summary</h2>\n<table>\n");
+        let mut totals = vec![
+            ("Files scanned".to_string(), self.files.len().to_string()),
+            ("Likely carriers".to_string(), carriers.to_string()),
+            ("Unreadable files".to_string(), unreadable.to_string()),
+            ("Chunks".to_string(), chunks.to_string()),
+            ("Chunk data".to_string(), format!("{} bytes", bytes)),
+        ];
+        totals.extend(
+            self.finding_counts()
+                .into_iter()
+                .map(|(kind, count)| (kind.description().to_string(), count.to_string())),
+        );
+        for (name, value) in totals {
+            let _ = writeln!(
+                out,
+                "<tr><th>{}</th><td>{}</td></tr>",
+                escape_html(&name),
+                value
+            );
+        }
+        out.push_str("</table>\n");
+
+        for file in &self.files {
+            let _ = writeln!(out, "<h2>{}</h2>", escape_html(&file.path));
+            if let Some(error) = &file.error {
+                let _ = writeln!(
+                    out,
+                    "<p class=\"error\">Unreadable: {}</p>",
+                    escape_html(error)
+                );
+                continue;
+            }
+            if file.findings.is_empty() {
+                out.push_str("<p>No suspicious findings.</p>\n");
+            } else {
+                out.push_str("<ul>\n");
+                for finding in &file.findings {
+                    let _ = writeln!(out, "<li>{}</li>", escape_html(&finding.to_string()));
+                }
+                out.push_str("</ul>\n");
+            }
+            out.push_str(concat!(
+                "<table>\n<tr><th>#</th><th>Type</th><th>Length</th>",
+                "<th colspan=\"2\">Entropy</th><th>Class</th></tr>\n",
+            ));
+            for (index, chunk) in file.chunks.iter().enumerate() {
+                let class = if file.is_flagged(index) {
+                    " class=\"flagged\""
+                } else {
+                    ""
+                };
+                let _ = writeln!(
+                    out,
+                    "<tr{}><td>{}</td><td>{}</td><td>{}</td><td>{:.2}</td>\
+                     <td><div class=\"bar\"><div style=\"width: {:.0}%\"></div></div></td>\
+                     <td>{}</td></tr>",
+                    class,
+                    index,
+                    escape_html(&chunk.chunk_type),
+                    chunk.length,
+                    chunk.entropy,
+                    (chunk.entropy / MAX_ENTROPY * 100.0).min(100.0),
+                    chunk.class
+                );
+            }
+            out.push_str("</table>\n");
+            if file.trailing_data > 0 {
+                let _ = writeln!(
+                    out,
+                    "<p>{} bytes of data after IEND.</p>",
+                    file.trailing_data
+                );
+            }
+        }
+        let _ = writeln!(
+            out,
+            "<footer><p>Generated by {} {}</p></footer>\n</body>\n</html>",
+            env!("CARGO_PKG_NAME"),
+            env!("CARGO_PKG_VERSION")
+        );
+        out
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Escape the characters which would break out of a table cell or start inline formatting
+fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(
+            c,
+            '\\' | '|' | '*' | '_' | '`' | '[' | ']' | '<' | '>' | '#'
+        ) {
+            escaped.push('\\');
+        }
+        escaped.push(if c == '\n' { ' ' } else { c });
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+    use crate::chunk_type::ChunkType;
+    use crate::png::Png;
+    use crate::scan::{self, ScanOptions};
+    use std::str::FromStr;
+
+    fn report() -> Report {
+        let png = Png::from_chunks(vec![
+            Chunk::new(ChunkType::from_str("IHDR").unwrap(), vec![0; 13]),
+            Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"<secret>".to_vec()),
+            Chunk::new(ChunkType::from_str("IEND").unwrap(), vec![]),
+        ]);
+        let png = png.borrowed();
+        let findings = scan::scan(&png, ScanOptions::default());
+        Report {
+            files: vec![
+                FileReport::new(Path::new("a|b.png"), &png, findings),
+                FileReport::unreadable(Path::new("c&d.png"), "Invalid header found"),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_report_format() {
+        let format = |path: &str| ReportFormat::from_path(Path::new(path));
+        assert_eq!(format("out/report.HTML").unwrap(), ReportFormat::Html);
+        assert_eq!(format("report.md").unwrap(), ReportFormat::Markdown);
+        assert!(format("report.pdf").is_err());
+        assert!(format("report").is_err());
+    }
+
+    #[test]
+    fn test_markdown_report() {
+        let markdown = report().to_markdown();
+        assert!(markdown.contains("| Files scanned | 2 |"));
+        assert!(markdown.contains("| Likely carriers | 1 |"));
+        assert!(markdown.contains("## a\\|b.png"));
+        assert!(markdown.contains("| 1 | ruSt ⚠ | 8 |"));
+        assert!(markdown.contains("**Unreadable:** Invalid header found"));
+    }
+
+    #[test]
+    fn test_html_report() {
+        let html = report().to_html();
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<li>non-standard ruSt chunk"));
+        assert!(html.contains("<tr class=\"flagged\"><td>1</td><td>ruSt</td>"));
+        assert!(html.contains("<h2>c&amp;d.png</h2>"));
+        assert!(html.ends_with("</html>\n"));
+    }
+}
@@ -0,0 +1,213 @@
+use crate::scan::FindingKind;
+use crate::validate::{Rule, Severity};
+use crate::PngResult;
+use serde_json::{json, Value};
+use std::fmt::{Display, Formatter};
+use std::ops::Range;
+use std::path::Path;
+
+/// The version of the SARIF format written
+pub const SARIF_VERSION: &str = "2.1.0";
+
+const SARIF_SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+
+/// How serious a SARIF result is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Note,
+    Warning,
+    Error,
+}
+
+impl Display for Level {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Level::Note => write!(f, "note"),
+            Level::Warning => write!(f, "warning"),
+            Level::Error => write!(f, "error"),
+        }
+    }
+}
+
+impl From<Severity> for Level {
+    fn from(severity: Severity) -> Self {
+        match severity {
+            Severity::Info => Level::Note,
+            Severity::Warning => Level::Warning,
+            Severity::Error => Level::Error,
+        }
+    }
+}
+
+/// A check which results can be reported against, identified by a stable ID
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReportingRule {
+    pub id: &'static str,
+    pub description: &'static str,
+}
+
+impl From<Rule> for ReportingRule {
+    fn from(rule: Rule) -> Self {
+        ReportingRule {
+            id: rule.id(),
+            description: rule.description(),
+        }
+    }
+}
+
+impl From<FindingKind> for ReportingRule {
+    fn from(kind: FindingKind) -> Self {
+        ReportingRule {
+            id: kind.id(),
+            description: kind.description(),
+        }
+    }
+}
+
+/// Collects results from a run over one or more files into a SARIF log, which code scanning
+/// dashboards can show as annotations
+pub struct SarifLog {
+    rules: Vec<ReportingRule>,
+    results: Vec<Value>,
+    notifications: Vec<Value>,
+}
+
+impl SarifLog {
+    pub fn new(rules: Vec<ReportingRule>) -> Self {
+        SarifLog {
+            rules,
+            results: vec![],
+            notifications: vec![],
+        }
+    }
+
+    /// Report a result for the file at the given path, optionally pointing at a byte range. An
+    /// empty range points at just its start.
+    pub fn add_result(
+        &mut self,
+        rule_id: &str,
+        level: Level,
+        message: &str,
+        path: &Path,
+        region: Option<Range<usize>>,
+    ) -> PngResult<()> {
+        let rule_index = self
+            .rules
+            .iter()
+            .position(|rule| rule.id == rule_id)
+            .ok_or_else(|| format!("Unknown SARIF rule {:?}", rule_id))?;
+        let mut location = json!({ "artifactLocation": { "uri": artifact_uri(path) } });
+        if let Some(region) = region {
+            location["region"] = json!({ "byteOffset": region.start });
+            if !region.is_empty() {
+                location["region"]["byteLength"] = json!(region.len());
+            }
+        }
+        self.results.push(json!({
+            "ruleId": rule_id,
+            "ruleIndex": rule_index,
+            "level": level.to_string(),
+            "message": { "text": message },
+            "locations": [{ "physicalLocation": location }],
+        }));
+        Ok(())
+    }
+
+    /// Record that the file at the given path couldn't be checked
+    pub fn add_failure(&mut self, path: &Path, message: &str) {
+        self.notifications.push(json!({
+            "level": Level::Error.to_string(),
+            "message": { "text": message },
+            "locations": [{
+                "physicalLocation": { "artifactLocation": { "uri": artifact_uri(path) } }
+            }],
+        }));
+    }
+
+    pub fn to_json(&self) -> Value {
+        let rules: Vec<Value> = self
+            .rules
+            .iter()
+            .map(|rule| {
+                json!({
+                    "id": rule.id,
+                    "shortDescription": { "text": rule.description },
+                })
+            })
+            .collect();
+        json!({
+            "$schema": SARIF_SCHEMA,
+            "version": SARIF_VERSION,
+            "runs": [{
+                "tool": {
+                    "driver": {
+                        "name": env!("CARGO_PKG_NAME"),
+                        "version": env!("CARGO_PKG_VERSION"),
+                        "rules": rules,
+                    }
+                },
+                "invocations": [{
+                    "executionSuccessful": self.notifications.is_empty(),
+                    "toolExecutionNotifications": self.notifications,
+                }],
+                "results": self.results,
+            }],
+        })
+    }
+}
+
+/// A relative URI reference for the path, with forward slashes and any characters which
+/// aren't allowed in a URI percent-encoded
+fn artifact_uri(path: &Path) -> String {
+    let path = path.to_string_lossy().replace('\\', "/");
+    let path = path.strip_prefix("./").unwrap_or(&path);
+    let mut uri = String::with_capacity(path.len());
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                uri.push(byte as char)
+            }
+            _ => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    uri
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sarif_log() {
+        let mut log = SarifLog::new(Rule::ALL.iter().copied().map(ReportingRule::from).collect());
+        log.add_result(
+            "crc-mismatch",
+            Severity::Error.into(),
+            "IDAT chunk CRC is wrong",
+            Path::new("./images/a b.png"),
+            Some(33..45),
+        )
+        .unwrap();
+        assert!(log
+            .add_result("no-such-rule", Level::Note, "", Path::new("a.png"), None)
+            .is_err());
+
+        let sarif = log.to_json();
+        assert_eq!(sarif["version"], SARIF_VERSION);
+        let run = &sarif["runs"][0];
+        assert_eq!(run["tool"]["driver"]["rules"][4]["id"], "crc-mismatch");
+        assert_eq!(run["invocations"][0]["executionSuccessful"], true);
+        let result = &run["results"][0];
+        assert_eq!(result["ruleIndex"], 4);
+        assert_eq!(result["level"], "error");
+        let location = &result["locations"][0]["physicalLocation"];
+        assert_eq!(location["artifactLocation"]["uri"], "images/a%20b.png");
+        assert_eq!(location["region"]["byteLength"], 12);
+
+        log.add_failure(Path::new("c.png"), "unreadable");
+        assert_eq!(
+            log.to_json()["runs"][0]["invocations"][0]["executionSuccessful"],
+            false
+        );
+    }
+}
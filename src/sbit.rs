@@ -0,0 +1,107 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::ihdr::{ColorType, Ihdr};
+use crate::png::Png;
+use crate::PngResult;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// How many bits of each sample were significant in the original image, as stored in an sBIT
+/// chunk. There's one value per channel, with indexed images described as RGB.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignificantBits {
+    bits: Vec<u8>,
+}
+
+impl SignificantBits {
+    pub const CHUNK_TYPE: &'static str = "sBIT";
+
+    /// The number of values an sBIT chunk holds for the given colour type
+    pub fn channels(color_type: ColorType) -> usize {
+        match color_type {
+            ColorType::Indexed => 3,
+            color_type => color_type.channels() as usize,
+        }
+    }
+
+    /// The significant bits of each channel, checked against the image's colour type and
+    /// bit depth
+    pub fn new(bits: Vec<u8>, ihdr: &Ihdr) -> PngResult<Self> {
+        let channels = Self::channels(ihdr.color_type);
+        if bits.len() != channels {
+            return Err(format!(
+                "sBIT has {} values but {} images need {}",
+                bits.len(),
+                ihdr.color_type,
+                channels
+            )
+            .into());
+        }
+        // Palette entries are always 8 bits, whatever the bit depth of the indexes
+        let sample_depth = match ihdr.color_type {
+            ColorType::Indexed => 8,
+            _ => ihdr.bit_depth,
+        };
+        if let Some(invalid) = bits.iter().find(|bits| !(1..=sample_depth).contains(bits)) {
+            return Err(format!(
+                "sBIT value {} must be from 1 to the sample depth of {}",
+                invalid, sample_depth
+            )
+            .into());
+        }
+        Ok(SignificantBits { bits })
+    }
+
+    pub fn bits(&self) -> &[u8] {
+        &self.bits
+    }
+
+    pub fn to_chunk(&self) -> PngResult<Chunk> {
+        Ok(Chunk::new(
+            ChunkType::from_str(Self::CHUNK_TYPE)?,
+            self.bits.clone(),
+        ))
+    }
+}
+
+impl Display for SignificantBits {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let bits: Vec<String> = self.bits.iter().map(|bits| bits.to_string()).collect();
+        write!(f, "{} significant bits", bits.join("/"))
+    }
+}
+
+/// Decode the sBIT chunk of the PNG, if it has one
+pub fn get_significant_bits(png: &Png) -> PngResult<Option<SignificantBits>> {
+    let Some(chunk) = png.chunk_by_type(SignificantBits::CHUNK_TYPE) else {
+        return Ok(None);
+    };
+    SignificantBits::new(chunk.data().to_vec(), &Ihdr::try_from(png)?).map(Some)
+}
+
+/// Store the significant bits, replacing any existing sBIT chunk. The spec requires sBIT to
+/// come before PLTE and the first IDAT chunk.
+pub fn set_significant_bits(png: &mut Png, bits: &SignificantBits) -> PngResult<()> {
+    png.set_unique_chunk(bits.to_chunk()?, &["PLTE", "IDAT", "IEND"]);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::ihdr;
+
+    #[test]
+    fn test_significant_bits() {
+        let rgba = ihdr(16, ColorType::TruecolorAlpha);
+        let bits = SignificantBits::new(vec![10, 10, 10, 16], &rgba).unwrap();
+        assert_eq!(bits.to_string(), "10/10/10/16 significant bits");
+        assert!(SignificantBits::new(vec![10, 10, 10], &rgba).is_err());
+        assert!(SignificantBits::new(vec![0, 10, 10, 10], &rgba).is_err());
+
+        let indexed = ihdr(2, ColorType::Indexed);
+        assert!(SignificantBits::new(vec![5, 6, 5], &indexed).is_ok());
+        assert!(SignificantBits::new(vec![9, 6, 5], &indexed).is_err());
+        assert!(SignificantBits::new(vec![2], &ihdr(1, ColorType::Grayscale)).is_err());
+    }
+}
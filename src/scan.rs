@@ -0,0 +1,335 @@
+use crate::entropy::{self, DataClass};
+use crate::png::PngRef;
+use crate::polyglot::{self, Embedded};
+use crate::signature::{self, Location, Signature};
+use std::fmt::{Display, Formatter};
+
+/// The textual chunk types, which are a common hiding place for large payloads
+const TEXT_CHUNK_TYPES: &[&str] = &["tEXt", "zTXt", "iTXt"];
+
+/// Standard chunk types whose data is expected to be compressed, and so to look random
+const COMPRESSED_CHUNK_TYPES: &[&str] = &["IDAT", "fdAT", "zTXt", "iTXt", "iCCP"];
+
+/// Chunks smaller than this don't carry enough data for a meaningful entropy estimate
+const MIN_ENTROPY_SAMPLE_LEN: usize = 32;
+
+/// Tunables for `scan`
+#[derive(Debug, Clone, Copy)]
+pub struct ScanOptions {
+    /// Text chunks with more data than this many bytes are reported
+    pub text_threshold: usize,
+    /// Decode the image to look for known tools' payloads in the low bits of its pixels
+    pub pixels: bool,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        ScanOptions {
+            text_threshold: 4096,
+            pixels: true,
+        }
+    }
+}
+
+/// The kinds of suspicious structure `scan` looks for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FindingKind {
+    /// A chunk type which isn't defined by the spec or a registered extension
+    NonStandardChunk,
+    /// A text chunk holding far more data than metadata usually needs
+    LargeTextChunk,
+    /// Bytes following the IEND chunk
+    TrailingData,
+    /// A standard chunk which normally holds plain data but looks compressed or encrypted
+    HighEntropyChunk,
+    /// Data laid out the way a known steganography tool writes it
+    ToolSignature,
+    /// Another file format inside a chunk or after IEND, so that the file is a polyglot
+    EmbeddedFile,
+}
+
+impl FindingKind {
+    pub const ALL: &'static [FindingKind] = &[
+        FindingKind::NonStandardChunk,
+        FindingKind::LargeTextChunk,
+        FindingKind::TrailingData,
+        FindingKind::HighEntropyChunk,
+        FindingKind::ToolSignature,
+        FindingKind::EmbeddedFile,
+    ];
+
+    /// A stable ID for this kind of finding, for machine-readable output
+    pub fn id(&self) -> &'static str {
+        match self {
+            FindingKind::NonStandardChunk => "non-standard-chunk",
+            FindingKind::LargeTextChunk => "large-text-chunk",
+            FindingKind::TrailingData => "trailing-data",
+            FindingKind::HighEntropyChunk => "high-entropy-chunk",
+            FindingKind::ToolSignature => "tool-signature",
+            FindingKind::EmbeddedFile => "embedded-file",
+        }
+    }
+
+    /// A one-line description of what this kind of finding means
+    pub fn description(&self) -> &'static str {
+        match self {
+            FindingKind::NonStandardChunk => {
+                "A chunk type not defined by the PNG spec or a registered extension"
+            }
+            FindingKind::LargeTextChunk => {
+                "A text chunk holding far more data than metadata usually needs"
+            }
+            FindingKind::TrailingData => "Data hidden after the IEND chunk",
+            FindingKind::HighEntropyChunk => {
+                "A chunk which normally holds plain data but looks compressed or encrypted"
+            }
+            FindingKind::ToolSignature => "The fingerprint of a known steganography tool",
+            FindingKind::EmbeddedFile => {
+                "Another file format hidden in a chunk or after IEND, making a polyglot"
+            }
+        }
+    }
+}
+
+/// Something suspicious spotted while scanning a PNG
+#[derive(Debug, Clone, PartialEq)]
+pub struct Finding {
+    pub kind: FindingKind,
+    /// The chunk type the finding relates to, if any
+    pub chunk_type: Option<String>,
+    /// The position of that chunk in the PNG
+    pub chunk_index: Option<usize>,
+    /// The number of data bytes involved
+    pub size: usize,
+    /// The Shannon entropy of the data involved, in bits per byte
+    pub entropy: f64,
+    /// The tool fingerprint behind a `ToolSignature` finding
+    pub signature: Option<Signature>,
+    /// The file found by an `EmbeddedFile` finding
+    pub embedded: Option<Embedded>,
+}
+
+impl Display for Finding {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let chunk_type = self.chunk_type.as_deref().unwrap_or_default();
+        match self.kind {
+            FindingKind::NonStandardChunk => {
+                write!(f, "non-standard {} chunk ({} bytes", chunk_type, self.size)?
+            }
+            FindingKind::LargeTextChunk => {
+                write!(f, "large {} chunk ({} bytes", chunk_type, self.size)?
+            }
+            FindingKind::TrailingData => write!(f, "data after IEND ({} bytes", self.size)?,
+            FindingKind::HighEntropyChunk => write!(
+                f,
+                "{} chunk looks compressed or encrypted ({} bytes",
+                chunk_type, self.size
+            )?,
+            FindingKind::ToolSignature => match &self.signature {
+                Some(signature) => write!(f, "{} ({} bytes", signature, self.size)?,
+                None => write!(f, "tool signature ({} bytes", self.size)?,
+            },
+            FindingKind::EmbeddedFile => {
+                let embedded = self.embedded.map(|embedded| embedded.to_string());
+                let embedded = embedded.as_deref().unwrap_or("embedded file");
+                match self.chunk_type.as_deref() {
+                    Some(chunk_type) => write!(f, "{} in {} chunk", embedded, chunk_type)?,
+                    None => write!(f, "{} after IEND", embedded)?,
+                }
+                write!(f, " ({} bytes", self.size)?
+            }
+        }
+        write!(f, ", entropy {:.2})", self.entropy)
+    }
+}
+
+/// Look for chunks and data which suggest the PNG is carrying a hidden payload
+pub fn scan(png: &PngRef, options: ScanOptions) -> Vec<Finding> {
+    let mut findings = vec![];
+    for (index, chunk) in png.chunks().iter().enumerate() {
+        let chunk_type = chunk.chunk_type().to_string();
+        let size = chunk.length() as usize;
+        let kind = if !chunk.chunk_type().is_standard() {
+            FindingKind::NonStandardChunk
+        } else if TEXT_CHUNK_TYPES.contains(&chunk_type.as_str()) && size > options.text_threshold {
+            FindingKind::LargeTextChunk
+        } else if !COMPRESSED_CHUNK_TYPES.contains(&chunk_type.as_str())
+            && size >= MIN_ENTROPY_SAMPLE_LEN
+            && entropy::classify(chunk.data()) == DataClass::Random
+        {
+            FindingKind::HighEntropyChunk
+        } else {
+            continue;
+        };
+        findings.push(Finding {
+            kind,
+            chunk_type: Some(chunk_type),
+            chunk_index: Some(index),
+            size,
+            entropy: entropy::shannon_entropy(chunk.data()),
+            signature: None,
+            embedded: None,
+        });
+    }
+    if !png.trailing_data().is_empty() {
+        findings.push(Finding {
+            kind: FindingKind::TrailingData,
+            chunk_type: None,
+            chunk_index: None,
+            size: png.trailing_data().len(),
+            entropy: entropy::shannon_entropy(png.trailing_data()),
+            signature: None,
+            embedded: None,
+        });
+    }
+    for (index, chunk) in png.chunks().iter().enumerate() {
+        let data = chunk.data();
+        for embedded in polyglot::find_in_chunk(&chunk.chunk_type().bytes(), data) {
+            findings.push(Finding {
+                kind: FindingKind::EmbeddedFile,
+                chunk_type: Some(chunk.chunk_type().to_string()),
+                chunk_index: Some(index),
+                size: data.len() - embedded.offset,
+                entropy: entropy::shannon_entropy(&data[embedded.offset..]),
+                signature: None,
+                embedded: Some(embedded),
+            });
+        }
+    }
+    let trailing_data = png.trailing_data();
+    for embedded in polyglot::find(trailing_data) {
+        // An appended file runs on to the end of the data after IEND
+        findings.push(Finding {
+            kind: FindingKind::EmbeddedFile,
+            chunk_type: None,
+            chunk_index: None,
+            size: trailing_data.len() - embedded.offset,
+            entropy: entropy::shannon_entropy(&trailing_data[embedded.offset..]),
+            signature: None,
+            embedded: Some(embedded),
+        });
+    }
+    for (signature, data) in signature::detect(png, options.pixels) {
+        // Pixel signatures are pinned to the image data, so they have a chunk to point at
+        let (chunk_type, chunk_index) = match &signature.location {
+            Location::Chunk { index, chunk_type } => (Some(chunk_type.clone()), Some(*index)),
+            Location::Pixels(_) => (
+                Some("IDAT".to_string()),
+                png.chunks()
+                    .iter()
+                    .position(|chunk| chunk.chunk_type().to_string() == "IDAT"),
+            ),
+            Location::TrailingChunk { .. } | Location::TrailingData => (None, None),
+        };
+        findings.push(Finding {
+            kind: FindingKind::ToolSignature,
+            chunk_type,
+            chunk_index,
+            size: data.len(),
+            entropy: entropy::shannon_entropy(&data),
+            signature: Some(signature),
+            embedded: None,
+        });
+    }
+    findings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+    use crate::chunk_type::ChunkType;
+    use crate::png::Png;
+    use std::str::FromStr;
+
+    fn chunk(chunk_type: &str, len: usize) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), vec![b'a'; len])
+    }
+
+    #[test]
+    fn test_clean_png() {
+        let png = Png::from_chunks(vec![chunk("IHDR", 13), chunk("tEXt", 20), chunk("IEND", 0)]);
+        assert!(scan(&png.borrowed(), ScanOptions::default()).is_empty());
+    }
+
+    #[test]
+    fn test_suspicious_png() {
+        let png = Png::from_chunks(vec![
+            chunk("IHDR", 13),
+            chunk("ruSt", 42),
+            chunk("tEXt", 100),
+            chunk("IEND", 0),
+        ]);
+        let mut bytes = png.as_bytes();
+        bytes.extend_from_slice(b"trailer");
+        let png = Png::try_from(bytes.as_slice()).unwrap();
+
+        let findings = scan(
+            &png.borrowed(),
+            ScanOptions {
+                text_threshold: 50,
+                pixels: false,
+            },
+        );
+        let kinds: Vec<_> = findings.iter().map(|finding| finding.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                FindingKind::NonStandardChunk,
+                FindingKind::LargeTextChunk,
+                FindingKind::TrailingData,
+                FindingKind::ToolSignature
+            ]
+        );
+        assert_eq!(
+            findings[0].to_string(),
+            "non-standard ruSt chunk (42 bytes, entropy 0.00)"
+        );
+        assert_eq!(findings[1].chunk_index, Some(2));
+        assert_eq!(findings[2].size, 7);
+        assert_eq!(
+            findings[3].to_string(),
+            "message chunk in ruSt chunk 1, likely from pngme (42 bytes, entropy 0.00)"
+        );
+    }
+
+    #[test]
+    fn test_embedded_files() {
+        let png = Png::from_chunks(vec![
+            chunk("IHDR", 13),
+            Chunk::new(ChunkType::from_str("tEXt").unwrap(), b"a\0<html>".to_vec()),
+            chunk("IEND", 0),
+        ]);
+        let mut bytes = png.as_bytes();
+        bytes.extend_from_slice(b"padding PK\x03\x04 archive");
+        let png = Png::try_from(bytes.as_slice()).unwrap();
+
+        let findings: Vec<String> = scan(&png.borrowed(), ScanOptions::default())
+            .iter()
+            .filter(|finding| finding.kind == FindingKind::EmbeddedFile)
+            .map(Finding::to_string)
+            .collect();
+        assert_eq!(
+            findings,
+            vec![
+                "HTML document at offset 2 in tEXt chunk (6 bytes, entropy 2.58)",
+                "ZIP archive at offset 8 after IEND (12 bytes, entropy 3.58)",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_high_entropy_chunk() {
+        let noise: Vec<u8> = (0..=255).collect();
+        let png = Png::from_chunks(vec![
+            chunk("IHDR", 13),
+            Chunk::new(ChunkType::from_str("tEXt").unwrap(), noise.clone()),
+            Chunk::new(ChunkType::from_str("IDAT").unwrap(), noise),
+            chunk("IEND", 0),
+        ]);
+        let findings = scan(&png.borrowed(), ScanOptions::default());
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].kind, FindingKind::HighEntropyChunk);
+        assert_eq!(findings[0].chunk_type.as_deref(), Some("tEXt"));
+    }
+}
@@ -0,0 +1,158 @@
+use crate::chunk::ChunkRef;
+use crate::chunk_type::ChunkType;
+use crate::png::PngRef;
+use crate::zlib;
+use crate::PngResult;
+use regex::bytes::Regex;
+
+/// Where in a chunk a match was found
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Match {
+    /// The index of the chunk, or of the first IDAT chunk for a match in the image data
+    pub index: usize,
+    pub chunk_type: ChunkType,
+    /// The offset of the match within the chunk data, or within the decompressed text or
+    /// image data
+    pub offset: usize,
+    /// Whether the match is in decompressed data rather than the stored bytes
+    pub decompressed: bool,
+    /// The bytes which matched
+    pub bytes: Vec<u8>,
+}
+
+/// A regex which matches the given bytes, written in hex with optional spaces and `0x` prefix.
+/// `??` matches any byte, e.g. `89 50 4e 47 ?? 0a`.
+pub fn hex_pattern(hex: &str) -> PngResult<Regex> {
+    let hex: String = hex.split_whitespace().collect();
+    let hex = hex.strip_prefix("0x").unwrap_or(&hex);
+    if hex.is_empty() || !hex.len().is_multiple_of(2) {
+        return Err(format!("{:?} isn't an even number of hex digits", hex).into());
+    }
+    let mut pattern = String::from("(?s-u)");
+    for pair in hex.as_bytes().chunks(2) {
+        let pair = std::str::from_utf8(pair)?;
+        if pair == "??" {
+            pattern.push('.');
+            continue;
+        }
+        let byte =
+            u8::from_str_radix(pair, 16).map_err(|_| format!("{:?} isn't a hex byte", pair))?;
+        pattern.push_str(&format!("\\x{:02x}", byte));
+    }
+    Ok(Regex::new(&pattern)?)
+}
+
+/// Every match of the pattern in the chunk data of the PNG, in file order. With `decompress`
+/// the text of zTXt and compressed iTXt chunks and the image data split across the IDAT chunks
+/// are searched too, once inflated.
+pub fn search(png: &PngRef, pattern: &Regex, decompress: bool) -> Vec<Match> {
+    let mut matches = vec![];
+    let mut find = |index: usize, chunk: &ChunkRef, data: &[u8], decompressed: bool| {
+        for found in pattern.find_iter(data) {
+            matches.push(Match {
+                index,
+                chunk_type: *chunk.chunk_type(),
+                offset: found.start(),
+                decompressed,
+                bytes: found.as_bytes().to_vec(),
+            });
+        }
+    };
+    for (index, chunk) in png.chunks().iter().enumerate() {
+        find(index, chunk, chunk.data(), false);
+        if !decompress {
+            continue;
+        }
+        if let Some(text) = compressed_text(chunk) {
+            find(
+                index,
+                chunk,
+                &zlib::inflate_lossy(text, zlib::MAX_INFLATED_LEN),
+                true,
+            );
+        }
+    }
+
+    let first_idat = png
+        .chunks()
+        .iter()
+        .position(|chunk| chunk.chunk_type().bytes() == *b"IDAT");
+    if let (true, Some(index)) = (decompress, first_idat) {
+        let idat: Vec<u8> = png
+            .chunks_by_type("IDAT")
+            .flat_map(|chunk| chunk.data())
+            .copied()
+            .collect();
+        let inflated = zlib::inflate_lossy(&idat, zlib::MAX_INFLATED_LEN);
+        find(index, &png.chunks()[index], &inflated, true);
+    }
+    matches.sort_by_key(|found| found.index);
+    matches
+}
+
+/// The zlib stream holding the text of a zTXt or compressed iTXt chunk
+fn compressed_text<'a>(chunk: &ChunkRef<'a>) -> Option<&'a [u8]> {
+    match &chunk.chunk_type().bytes() {
+        chunk_type @ (b"zTXt" | b"iTXt") => zlib::compressed_stream(chunk_type, chunk.data()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+    use crate::png::Png;
+    use crate::text::{ItxtChunk, TextChunk};
+    use std::str::FromStr;
+
+    #[test]
+    fn test_hex_pattern() {
+        let pattern = hex_pattern("0xDE ad ?? ef").unwrap();
+        assert!(pattern.is_match(b"\x00\xde\xad\x00\xef"));
+        assert!(!pattern.is_match(b"\xde\xad\xef"));
+        assert!(hex_pattern("abc").is_err());
+        assert!(hex_pattern("zz").is_err());
+        assert!(hex_pattern("").is_err());
+    }
+
+    #[test]
+    fn test_search() {
+        let mut ztxt = TextChunk::new("Comment", "password=hunter2").unwrap();
+        ztxt.set_compressed(true);
+        let mut itxt = ItxtChunk::new("Comment", "token=hunter3").unwrap();
+        itxt.set_compressed(true);
+        let png = Png::from_chunks(vec![
+            Chunk::new(ChunkType::from_str("IHDR").unwrap(), vec![0; 13]),
+            TextChunk::new("Author", "hunter1")
+                .unwrap()
+                .to_chunk()
+                .unwrap(),
+            ztxt.to_chunk().unwrap(),
+            itxt.to_chunk().unwrap(),
+            Chunk::new(ChunkType::from_str("IEND").unwrap(), vec![]),
+        ]);
+        let pattern = Regex::new("hunter[0-9]").unwrap();
+
+        let matches = search(&png.borrowed(), &pattern, false);
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].index, 1);
+        assert_eq!(matches[0].offset, 7);
+        assert_eq!(matches[0].bytes, b"hunter1");
+
+        let matches = search(&png.borrowed(), &pattern, true);
+        let found: Vec<(usize, &[u8], bool)> = matches
+            .iter()
+            .map(|found| (found.index, &found.bytes[..], found.decompressed))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (1, &b"hunter1"[..], false),
+                (2, &b"hunter2"[..], true),
+                (3, &b"hunter3"[..], true)
+            ]
+        );
+        assert_eq!(matches[1].offset, 9);
+    }
+}
@@ -0,0 +1,164 @@
+use crate::error::{error, ErrorKind};
+use crate::PngResult;
+
+/// Marks chunk data holding one segment of a payload which was split across several chunks
+pub const SEGMENT_MAGIC: [u8; 4] = *b"pmiS";
+
+/// Magic, segment index and segment count
+const SEGMENT_HEADER_LEN: usize = SEGMENT_MAGIC.len() + 4 + 4;
+
+/// The default upper bound on the data held by a single chunk written by this tool
+pub const DEFAULT_MAX_CHUNK_LEN: usize = 1024 * 1024;
+
+/// Returns whether the given chunk data is one segment of a split payload
+pub fn is_segment(data: &[u8]) -> bool {
+    data.len() >= SEGMENT_HEADER_LEN && data[..SEGMENT_MAGIC.len()] == SEGMENT_MAGIC
+}
+
+/// How a payload of a given size would be laid out across chunks by `split`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SplitPlan {
+    /// The number of chunks needed
+    pub chunks: usize,
+    /// The total chunk data, i.e. the payload plus any segment headers
+    pub data_len: usize,
+}
+
+/// Work out how `split` would lay out a payload of `payload_len` bytes without splitting it
+pub fn plan(payload_len: usize, max_chunk_len: usize) -> PngResult<SplitPlan> {
+    if payload_len <= max_chunk_len {
+        return Ok(SplitPlan {
+            chunks: 1,
+            data_len: payload_len,
+        });
+    }
+    if max_chunk_len <= SEGMENT_HEADER_LEN {
+        return Err(format!(
+            "Maximum chunk size must be larger than {} bytes",
+            SEGMENT_HEADER_LEN
+        )
+        .into());
+    }
+    let chunks = payload_len.div_ceil(max_chunk_len - SEGMENT_HEADER_LEN);
+    if u32::try_from(chunks).is_err() {
+        return Err("Payload needs too many chunks".into());
+    }
+    Ok(SplitPlan {
+        chunks,
+        data_len: payload_len + chunks * SEGMENT_HEADER_LEN,
+    })
+}
+
+/// Split a payload into chunk-sized pieces no longer than `max_chunk_len` bytes each.
+/// Payloads which already fit are returned as is, so small payloads don't pay for the
+/// segment header. Every piece of a split payload records its index and the total count.
+pub fn split(payload: &[u8], max_chunk_len: usize) -> PngResult<Vec<Vec<u8>>> {
+    let count = plan(payload.len(), max_chunk_len)?.chunks as u32;
+    if count == 1 {
+        return Ok(vec![payload.to_vec()]);
+    }
+
+    let parts = payload.chunks(max_chunk_len - SEGMENT_HEADER_LEN);
+    Ok(parts
+        .enumerate()
+        .map(|(index, part)| {
+            let mut segment = Vec::with_capacity(SEGMENT_HEADER_LEN + part.len());
+            segment.extend_from_slice(&SEGMENT_MAGIC);
+            segment.extend_from_slice(&(index as u32).to_be_bytes());
+            segment.extend_from_slice(&count.to_be_bytes());
+            segment.extend_from_slice(part);
+            segment
+        })
+        .collect())
+}
+
+/// Reassemble a payload from the data of the chunks it was stored in.
+/// A single non-segment chunk is returned unchanged; segments may appear in any order but
+/// must all be present exactly once.
+pub fn join<'a>(chunks: impl IntoIterator<Item = &'a [u8]>) -> PngResult<Vec<u8>> {
+    let chunks: Vec<&[u8]> = chunks.into_iter().collect();
+    match chunks.first() {
+        None => {
+            return Err(error(
+                ErrorKind::ChunkNotFound,
+                "No chunk with the given chunk_type found",
+            ))
+        }
+        Some(first) if !is_segment(first) => return Ok(first.to_vec()),
+        Some(_) => {}
+    }
+
+    let field = |data: &[u8], index: usize| {
+        let start = SEGMENT_MAGIC.len() + index * 4;
+        u32::from_be_bytes(data[start..start + 4].try_into().unwrap()) as usize
+    };
+    let count = field(chunks[0], 1);
+    if count > chunks.len() {
+        return Err(format!("Missing payload segments, expected {}", count).into());
+    }
+    let mut parts: Vec<Option<&[u8]>> = vec![None; count];
+    for data in chunks.iter().filter(|data| is_segment(data)) {
+        let (index, segment_count) = (field(data, 0), field(data, 1));
+        if segment_count != count || index >= count {
+            return Err("Inconsistent payload segments".into());
+        }
+        if parts[index].replace(&data[SEGMENT_HEADER_LEN..]).is_some() {
+            return Err(format!("Duplicate payload segment {}", index).into());
+        }
+    }
+
+    let mut payload = vec![];
+    for (index, part) in parts.into_iter().enumerate() {
+        let part = part.ok_or_else(|| format!("Missing payload segment {} of {}", index, count))?;
+        payload.extend_from_slice(part);
+    }
+    Ok(payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_small_payload_is_not_split() {
+        let parts = split(b"hello", 64).unwrap();
+        assert_eq!(parts, vec![b"hello".to_vec()]);
+        assert!(!is_segment(&parts[0]));
+    }
+
+    #[test]
+    fn test_split_and_join() {
+        let payload: Vec<u8> = (0..=255).cycle().take(1000).collect();
+        let parts = split(&payload, 100).unwrap();
+        assert_eq!(parts.len(), 12);
+        assert!(parts
+            .iter()
+            .all(|part| part.len() <= 100 && is_segment(part)));
+
+        // Segments may be stored out of order
+        let joined = join(parts.iter().rev().map(Vec::as_slice)).unwrap();
+        assert_eq!(joined, payload);
+    }
+
+    #[test]
+    fn test_plan_matches_split() {
+        let payload = vec![0u8; 1000];
+        let parts = split(&payload, 100).unwrap();
+        let plan = plan(payload.len(), 100).unwrap();
+        assert_eq!(plan.chunks, parts.len());
+        assert_eq!(plan.data_len, parts.iter().map(Vec::len).sum::<usize>());
+    }
+
+    #[test]
+    fn test_join_with_missing_segment() {
+        let payload = vec![7u8; 500];
+        let parts = split(&payload, 100).unwrap();
+        let result = join(parts.iter().skip(1).map(Vec::as_slice));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_split_with_tiny_chunks() {
+        assert!(split(&[0u8; 100], SEGMENT_HEADER_LEN).is_err());
+    }
+}
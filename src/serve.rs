@@ -0,0 +1,343 @@
+use crate::args::ServeArgs;
+use crate::commands;
+use percent_encoding::percent_decode_str;
+use pngmi::chunk_type::ChunkType;
+use pngmi::crypto::KdfParams;
+use pngmi::message;
+use pngmi::png::{Png, PngRef};
+use pngmi::validate;
+use pngmi::PngResult;
+use serde_json::{json, Value};
+use std::io::Read;
+use std::str::FromStr;
+use std::thread;
+use tiny_http::{Header, Method, Request, Response, Server};
+use tracing::{info, warn};
+
+/// The header carrying the passphrase for /encode and /decode, kept out of the URL so that it
+/// doesn't end up in access logs
+const PASSPHRASE_HEADER: &str = "X-Passphrase";
+
+/// The header carrying the message for /encode, percent-encoded as UTF-8, kept out of the URL
+/// for the same reason as the passphrase
+const MESSAGE_HEADER: &str = "X-Message";
+
+/// The routes, each taking a PNG as the body of a POST request
+const ROUTES: &[&str] = &["/chunks", "/validate", "/decode", "/encode"];
+
+/// A response to send back
+struct Reply {
+    status: u16,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+impl Reply {
+    fn json(value: &Value) -> Self {
+        Reply {
+            status: 200,
+            content_type: "application/json",
+            body: value.to_string().into_bytes(),
+        }
+    }
+
+    fn error(status: u16, message: &str) -> Self {
+        Reply {
+            status,
+            ..Reply::json(&json!({ "error": message }))
+        }
+    }
+}
+
+/// Answer requests until the process is stopped, on `args.threads` threads so that a client
+/// slow to send its upload only holds up one of them
+pub fn serve(args: ServeArgs) -> PngResult<()> {
+    let server = Server::http(&args.listen).map_err(|e| format!("{}: {}", args.listen, e))?;
+    eprintln!("Listening on http://{}", server.server_addr());
+    thread::scope(|scope| {
+        for _ in 0..args.threads {
+            scope.spawn(|| {
+                for request in server.incoming_requests() {
+                    respond(request, args.max_size, args.kdf_limit());
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+fn respond(mut request: Request, max_size: usize, kdf_limit: KdfParams) {
+    let (method, url) = (request.method().clone(), request.url().to_string());
+    let headers = request.headers().to_vec();
+    let length = request.body_length();
+    let reply = route(
+        &method,
+        &url,
+        &headers,
+        request.as_reader(),
+        length,
+        max_size,
+        kdf_limit,
+    );
+    // The query is left out of the log, as it may hold what the client would rather keep to
+    // itself
+    info!("{} {} {}", method, path(&url), reply.status);
+    let content_type = Header::from_bytes("Content-Type", reply.content_type)
+        .expect("content types are valid header values");
+    let response = Response::from_data(reply.body)
+        .with_status_code(reply.status)
+        .with_header(content_type);
+    if let Err(e) = request.respond(response) {
+        warn!("Couldn't send a response: {}", e);
+    }
+}
+
+/// The URL without its query
+fn path(url: &str) -> &str {
+    url.split_once('?').map_or(url, |(path, _)| path)
+}
+
+/// Answer a request, given its body and the length the client declared for it, if any
+fn route(
+    method: &Method,
+    url: &str,
+    headers: &[Header],
+    body: impl Read,
+    length: Option<usize>,
+    max_size: usize,
+    kdf_limit: KdfParams,
+) -> Reply {
+    let (path, query) = url.split_once('?').unwrap_or((url, ""));
+    if !ROUTES.contains(&path) {
+        return Reply::error(404, &format!("No such endpoint {}", path));
+    }
+    if *method != Method::Post {
+        return Reply::error(405, "POST a PNG file as the request body");
+    }
+    let body = match read_body(body, length, max_size) {
+        Ok(body) => body,
+        Err(reply) => return reply,
+    };
+    let passphrase = header(headers, PASSPHRASE_HEADER);
+    let result = match path {
+        "/chunks" => chunks(&body),
+        "/validate" => Ok(Reply::json(&commands::validation_json(
+            None,
+            &validate::validate(&body),
+        ))),
+        "/decode" => decode(&body, query, passphrase, kdf_limit),
+        _ => encode(&body, query, header(headers, MESSAGE_HEADER), passphrase),
+    };
+    result.unwrap_or_else(|e| Reply::error(400, &e.to_string()))
+}
+
+/// The value of the named header, if the request has one
+fn header<'a>(headers: &'a [Header], name: &'static str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|header| header.field.equiv(name))
+        .map(|header| header.value.as_str())
+}
+
+/// Read the whole request body, refusing one larger than `max_size` bytes
+fn read_body(body: impl Read, length: Option<usize>, max_size: usize) -> Result<Vec<u8>, Reply> {
+    let too_large = || Reply::error(413, &format!("Bodies are limited to {} bytes", max_size));
+    if length.is_some_and(|len| len > max_size) {
+        return Err(too_large());
+    }
+    let mut bytes = vec![];
+    body.take(max_size as u64 + 1)
+        .read_to_end(&mut bytes)
+        .map_err(|e| Reply::error(400, &e.to_string()))?;
+    if bytes.len() > max_size {
+        return Err(too_large());
+    }
+    Ok(bytes)
+}
+
+/// A query parameter, which the endpoint can't do without
+fn param(query: &str, name: &str) -> PngResult<String> {
+    form_urlencoded::parse(query.as_bytes())
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.into_owned())
+        .ok_or_else(|| format!("Missing the '{}' query parameter", name).into())
+}
+
+/// The chunk inventory, as `print --format json` lists it
+fn chunks(body: &[u8]) -> PngResult<Reply> {
+    let png = PngRef::try_from(body)?;
+    let chunks: Vec<Value> = png
+        .chunks()
+        .iter()
+        .enumerate()
+        .map(|(index, chunk)| commands::chunk_json(index, chunk))
+        .collect();
+    Ok(Reply::json(&json!({
+        "chunks": chunks,
+        "trailing_data": png.trailing_data().len(),
+    })))
+}
+
+/// The payload in chunks of the type given by `chunk_type`, as `decode --format json` prints it,
+/// refusing one which asks key derivation to cost more than `kdf_limit`
+fn decode(
+    body: &[u8],
+    query: &str,
+    passphrase: Option<&str>,
+    kdf_limit: KdfParams,
+) -> PngResult<Reply> {
+    let png = Png::try_from(body)?;
+    let chunk_type = param(query, "chunk_type")?;
+    let envelope = message::read_within(&png, &chunk_type, passphrase, kdf_limit)?;
+    Ok(Reply::json(&commands::payload_json(&envelope)))
+}
+
+/// A copy of the PNG with the message from the `X-Message` header encoded into chunks of type
+/// `chunk_type`
+fn encode(
+    body: &[u8],
+    query: &str,
+    message: Option<&str>,
+    passphrase: Option<&str>,
+) -> PngResult<Reply> {
+    let mut png = Png::try_from(body)?;
+    let chunk_type = ChunkType::from_str(&param(query, "chunk_type")?)?;
+    let message = message.ok_or_else(|| format!("Missing the {} header", MESSAGE_HEADER))?;
+    let message = percent_decode_str(message)
+        .decode_utf8()
+        .map_err(|e| format!("The {} header isn't valid UTF-8: {}", MESSAGE_HEADER, e))?;
+    message::embed(&mut png, chunk_type, &message, passphrase)?;
+    Ok(Reply {
+        status: 200,
+        content_type: "image/png",
+        body: png.as_bytes(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::prelude::*;
+    use pngmi::chunk::Chunk;
+
+    fn png_bytes() -> Vec<u8> {
+        let chunk = |chunk_type: &str, data: &[u8]| {
+            Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec())
+        };
+        Png::from_chunks(vec![chunk("IHDR", &[0; 13]), chunk("IEND", &[])]).as_bytes()
+    }
+
+    fn post(url: &str, headers: &[(&str, &str)], body: &[u8]) -> Reply {
+        let headers: Vec<Header> = headers
+            .iter()
+            .map(|(field, value)| Header::from_bytes(*field, *value).unwrap())
+            .collect();
+        route(
+            &Method::Post,
+            url,
+            &headers,
+            body,
+            Some(body.len()),
+            1024,
+            KdfParams::DEFAULT,
+        )
+    }
+
+    fn json(reply: &Reply) -> Value {
+        serde_json::from_slice(&reply.body).unwrap()
+    }
+
+    #[test]
+    fn test_routes() {
+        let png = png_bytes();
+        let reply = post("/chunks", &[], &png);
+        assert_eq!(reply.status, 200);
+        assert_eq!(json(&reply)["chunks"][1]["type"], "IEND");
+        assert_eq!(post("/validate", &[], &png).status, 200);
+        assert_eq!(post("/decode", &[], &png).status, 400);
+        assert_eq!(post("/nowhere", &[], &png).status, 404);
+        assert_eq!(path("/decode?chunk_type=ruSt"), "/decode");
+
+        let reply = route(
+            &Method::Get,
+            "/chunks",
+            &[],
+            &png[..],
+            None,
+            1024,
+            KdfParams::DEFAULT,
+        );
+        assert_eq!(reply.status, 405);
+    }
+
+    #[test]
+    fn test_body_limit() {
+        let body = vec![0; 1025];
+        assert_eq!(post("/chunks", &[], &body).status, 413);
+        // A body longer than it claimed to be is still cut off at the limit
+        let reply = route(
+            &Method::Post,
+            "/chunks",
+            &[],
+            &body[..],
+            Some(1),
+            1024,
+            KdfParams::DEFAULT,
+        );
+        assert_eq!(reply.status, 413);
+        let reply = route(
+            &Method::Post,
+            "/chunks",
+            &[],
+            &body[..],
+            None,
+            1024,
+            KdfParams::DEFAULT,
+        );
+        assert_eq!(reply.status, 413);
+    }
+
+    #[test]
+    fn test_encode_decode() {
+        let headers = [("X-Message", "caf%C3%A9 au lait"), ("X-Passphrase", "pw")];
+        let reply = post("/encode?chunk_type=ruSt", &headers, &png_bytes());
+        assert_eq!(reply.status, 200);
+        assert_eq!(reply.content_type, "image/png");
+
+        let headers = [("X-Passphrase", "pw")];
+        let decoded = post("/decode?chunk_type=ruSt", &headers, &reply.body);
+        assert_eq!(decoded.status, 200);
+        let payload = json(&decoded)["payload"].as_str().unwrap().to_string();
+        let payload = BASE64_STANDARD.decode(payload).unwrap();
+        assert_eq!(String::from_utf8(payload).unwrap(), "café au lait");
+
+        // A payload costing more to derive a key for than the server allows is refused
+        let limit = KdfParams {
+            iterations: 1,
+            ..KdfParams::DEFAULT
+        };
+        let url = "/decode?chunk_type=ruSt";
+        let headers = [Header::from_bytes("X-Passphrase", "pw").unwrap()];
+        let refused = route(
+            &Method::Post,
+            url,
+            &headers,
+            &reply.body[..],
+            None,
+            1024,
+            limit,
+        );
+        assert_eq!(refused.status, 400);
+        assert!(json(&refused)["error"]
+            .as_str()
+            .unwrap()
+            .contains("iterations"));
+
+        let reply = post("/encode?chunk_type=ruSt", &[], &png_bytes());
+        assert_eq!(reply.status, 400);
+        assert!(json(&reply)["error"]
+            .as_str()
+            .unwrap()
+            .contains("X-Message"));
+    }
+}
@@ -0,0 +1,248 @@
+use crate::args::{ShellCommand, ShellLine};
+use clap::Parser;
+use pngmi::chunk_type::ChunkType;
+use pngmi::message;
+use pngmi::output::{self, WriteOptions};
+use pngmi::png::Png;
+use pngmi::PngResult;
+use rustyline::error::ReadlineError;
+use rustyline::DefaultEditor;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+const PROMPT: &str = "pngmi> ";
+
+/// A file loaded once and edited in memory by the commands typed at the prompt
+struct Shell<'a> {
+    path: &'a Path,
+    png: Png,
+    options: WriteOptions<'a>,
+    modified: bool,
+    /// Whether quitting with unsaved changes has already been warned about
+    warned: bool,
+}
+
+/// Read commands from the prompt, or from standard input if it isn't a terminal, until the
+/// user quits. Failed commands are reported and leave the file as it was.
+pub fn run(path: &Path, png: Png, options: WriteOptions) -> PngResult<()> {
+    let mut shell = Shell {
+        path,
+        png,
+        options,
+        modified: false,
+        warned: false,
+    };
+    let mut editor = DefaultEditor::new()?;
+    loop {
+        let line = match editor.readline(PROMPT) {
+            Ok(line) => line,
+            // Ctrl-C abandons the line being typed, as in other shells
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) if shell.quit() => return Ok(()),
+            Err(ReadlineError::Eof) => continue,
+            Err(e) => return Err(e.into()),
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let _ = editor.add_history_entry(&line);
+        let Some(words) = shlex::split(&line) else {
+            eprintln!("Error: Unbalanced quotes");
+            continue;
+        };
+        let command = match ShellLine::try_parse_from(words) {
+            Ok(parsed) => parsed.command,
+            Err(e) => {
+                let _ = e.print();
+                continue;
+            }
+        };
+        if matches!(command, ShellCommand::Quit) {
+            if shell.quit() {
+                return Ok(());
+            }
+            continue;
+        }
+        shell.warned = false;
+        if let Err(e) = shell.execute(command) {
+            eprintln!("Error: {}", e);
+        }
+    }
+}
+
+impl Shell<'_> {
+    /// Returns whether to leave the shell, which the first attempt with unsaved changes doesn't
+    fn quit(&mut self) -> bool {
+        if self.modified && !self.warned {
+            eprintln!("Unsaved changes: quit again to discard them, or save first");
+            self.warned = true;
+            return false;
+        }
+        true
+    }
+
+    fn execute(&mut self, command: ShellCommand) -> PngResult<()> {
+        match command {
+            ShellCommand::Print => {
+                for (index, chunk) in self.png.chunks().iter().enumerate() {
+                    println!(
+                        "{:>4} {} {:<12} {:>10} bytes",
+                        index,
+                        chunk.chunk_type(),
+                        chunk.chunk_type().category(),
+                        chunk.length()
+                    );
+                }
+            }
+            ShellCommand::Remove { chunk_type, nth } => {
+                if ChunkType::from_str(&chunk_type)?.is_critical() {
+                    let message =
+                        format!("{} is a critical chunk and can't be removed", chunk_type);
+                    return Err(message.into());
+                }
+                let index = self
+                    .png
+                    .chunks()
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, chunk)| chunk.chunk_type().to_string() == chunk_type)
+                    .nth(nth - 1)
+                    .map(|(index, _)| index)
+                    .ok_or_else(|| format!("There is no {} chunk {}", chunk_type, nth))?;
+                self.png.remove_chunk_at(index);
+                self.modified = true;
+                println!("Removed {} chunk {} (index {})", chunk_type, nth, index);
+            }
+            ShellCommand::Encode {
+                chunk_type,
+                message,
+                passphrase,
+            } => {
+                let chunk_type = ChunkType::from_str(&chunk_type)?;
+                let added =
+                    message::embed(&mut self.png, chunk_type, &message, passphrase.as_deref())?;
+                self.modified = true;
+                println!("Added {} {} chunk(s)", added, chunk_type);
+            }
+            ShellCommand::Decode {
+                chunk_type,
+                passphrase,
+                out,
+            } => {
+                let envelope = message::read(&self.png, &chunk_type, passphrase.as_deref())?;
+                match out {
+                    Some(out) => fs::write(out, envelope.body())?,
+                    None if envelope.is_text() => {
+                        println!("{}", String::from_utf8(envelope.into_body())?)
+                    }
+                    None => {
+                        let message = format!(
+                            "Payload is binary ({}); pass --out to write it to a file",
+                            envelope.mime_type()
+                        );
+                        return Err(message.into());
+                    }
+                }
+            }
+            ShellCommand::Save { path } => {
+                let target = path.as_deref().unwrap_or(self.path);
+                output::write_atomic(target, &self.png.as_bytes(), &self.options)?;
+                if target == self.path {
+                    self.modified = false;
+                }
+                println!("Saved {}", target.display());
+            }
+            ShellCommand::Quit => {}
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pngmi::chunk::Chunk;
+
+    fn shell(path: &Path) -> Shell<'_> {
+        let chunk = |chunk_type: &str, data: &[u8]| {
+            Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec())
+        };
+        let png = Png::from_chunks(vec![
+            chunk("IHDR", &[0; 13]),
+            chunk("tEXt", b"a"),
+            chunk("tEXt", b"b"),
+            chunk("IEND", &[]),
+        ]);
+        Shell {
+            path,
+            png,
+            options: WriteOptions::default(),
+            modified: false,
+            warned: false,
+        }
+    }
+
+    fn line(words: &[&str]) -> ShellCommand {
+        ShellLine::try_parse_from(words).unwrap().command
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut shell = shell(Path::new("a.png"));
+        shell.execute(line(&["remove", "tEXt", "2"])).unwrap();
+        assert!(shell.modified);
+        let data: Vec<_> = shell.png.chunks().iter().map(Chunk::data).collect();
+        assert_eq!(data, [&[0; 13][..], b"a", b""]);
+        assert!(shell.execute(line(&["remove", "tEXt", "2"])).is_err());
+
+        let error = shell.execute(line(&["remove", "IHDR"])).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "IHDR is a critical chunk and can't be removed"
+        );
+        assert_eq!(shell.png.chunks().len(), 3);
+        assert!(ShellLine::try_parse_from(["remove", "tEXt", "0"]).is_err());
+    }
+
+    #[test]
+    fn test_encode_save_decode() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a.png");
+        let mut shell = shell(&path);
+        shell
+            .execute(line(&["encode", "ruSt", "hello", "--passphrase", "pw"]))
+            .unwrap();
+        assert!(shell.modified);
+        shell.execute(line(&["save"])).unwrap();
+        assert!(!shell.modified);
+
+        let saved = Png::try_from(&fs::read(&path).unwrap()[..]).unwrap();
+        let envelope = message::read(&saved, "ruSt", Some("pw")).unwrap();
+        assert_eq!(envelope.body(), b"hello");
+
+        let out = dir.path().join("message.txt");
+        let out = out.to_str().unwrap();
+        shell
+            .execute(line(&[
+                "decode",
+                "ruSt",
+                "--passphrase",
+                "pw",
+                "--out",
+                out,
+            ]))
+            .unwrap();
+        assert_eq!(fs::read(out).unwrap(), b"hello");
+        assert!(shell.execute(line(&["decode", "ruSt"])).is_err());
+    }
+
+    #[test]
+    fn test_quit_warns_once() {
+        let mut shell = shell(Path::new("a.png"));
+        assert!(shell.quit());
+        shell.modified = true;
+        assert!(!shell.quit());
+        assert!(shell.quit());
+    }
+}
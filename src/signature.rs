@@ -0,0 +1,540 @@
+use crate::chunk::ChunkRef;
+use crate::decoder::{self, Pixels};
+use crate::envelope::Envelope;
+use crate::ihdr::ColorType;
+use crate::lsb;
+use crate::png::{Png, PngRef};
+use crate::polyglot;
+use crate::segment;
+use crate::trailer;
+use std::borrow::Cow;
+use std::fmt::{Display, Formatter};
+
+/// The stamp OpenStego writes at the start of the header ahead of every payload
+const OPENSTEGO_STAMP: &[u8] = b"OPENSTEGO";
+
+/// The chunk type pngme, the Rust tutorial project, hides its messages under in every example
+const PNGME_CHUNK_TYPE: &str = "ruSt";
+
+/// How many bytes to read from the start of each bit plane: enough to recognise a file
+/// header, a length prefix or a run of text
+const LSB_PREFIX_LEN: usize = 64;
+
+/// A run of text this long at the start of a bit plane is very unlikely to be there by chance
+const MIN_TEXT_LEN: usize = 16;
+
+/// The tools whose output `detect` recognises
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tool {
+    /// The pngme tutorial project, which appends plain messages in `ruSt` chunks after IEND
+    Pngme,
+    /// This crate, whose payloads start with an envelope, segment or trailer header
+    Pngmi,
+    /// OpenStego, whose payload header starts with an `OPENSTEGO` stamp
+    OpenStego,
+    /// The Stegano Python library, whose LSB messages are prefixed with their length and a colon
+    Stegano,
+    /// Any tool writing its payload straight into one bit plane, as zsteg finds them
+    SequentialLsb,
+}
+
+impl Tool {
+    /// A stable ID for the tool, for machine-readable output
+    pub fn id(&self) -> &'static str {
+        match self {
+            Tool::Pngme => "pngme",
+            Tool::Pngmi => "pngmi",
+            Tool::OpenStego => "openstego",
+            Tool::Stegano => "stegano",
+            Tool::SequentialLsb => "sequential-lsb",
+        }
+    }
+}
+
+impl Display for Tool {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Tool::Pngme => write!(f, "pngme"),
+            Tool::Pngmi => write!(f, "pngmi"),
+            Tool::OpenStego => write!(f, "OpenStego"),
+            Tool::Stegano => write!(f, "Stegano"),
+            Tool::SequentialLsb => write!(f, "a sequential LSB tool"),
+        }
+    }
+}
+
+/// The lowest bit of every sample in some of the channels, read a row at a time and packed
+/// into bytes in the given bit order. Displayed the way zsteg names them, e.g. `b1,rgb,lsb,xy`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BitPlane {
+    /// The channels read from each pixel in order, one letter each
+    pub channels: &'static str,
+    /// Whether the first bit read is the least significant bit of each byte
+    pub lsb_first: bool,
+}
+
+impl BitPlane {
+    /// The bit planes worth reading for an image of the given colour type
+    fn all(color_type: ColorType) -> Vec<BitPlane> {
+        let channels: &[&str] = match color_type {
+            ColorType::Grayscale => &["y"],
+            ColorType::GrayscaleAlpha => &["y", "ya", "a"],
+            ColorType::Truecolor => &["rgb", "bgr", "r", "g", "b"],
+            ColorType::TruecolorAlpha => &["rgb", "bgr", "rgba", "r", "g", "b", "a"],
+            ColorType::Indexed => &[],
+        };
+        channels
+            .iter()
+            .flat_map(|channels| {
+                [true, false].map(|lsb_first| BitPlane {
+                    channels,
+                    lsb_first,
+                })
+            })
+            .collect()
+    }
+
+    /// The first `len` bytes of this bit plane
+    fn read(&self, pixels: &Pixels, len: usize) -> Vec<u8> {
+        let bytes_per_sample = pixels.bit_depth as usize / 8;
+        let channels = pixels.color_type.channels() as usize;
+        let indices: Vec<usize> = self
+            .channels
+            .chars()
+            .map(|channel| match (pixels.color_type, channel) {
+                (ColorType::GrayscaleAlpha, 'a') => 1,
+                (_, 'g') => 1,
+                (_, 'b') => 2,
+                (_, 'a') => 3,
+                _ => 0,
+            })
+            .collect();
+        let data = pixels.data();
+        let mut bits = (0..pixels.width * pixels.height).flat_map(|pixel| {
+            indices.iter().map(move |channel| {
+                data[(pixel * channels + channel + 1) * bytes_per_sample - 1] & 1
+            })
+        });
+        let mut bytes = vec![];
+        while bytes.len() < len {
+            let mut byte = 0u8;
+            for i in 0..8 {
+                let Some(bit) = bits.next() else {
+                    return bytes;
+                };
+                byte |= match self.lsb_first {
+                    true => bit << i,
+                    false => bit << (7 - i),
+                };
+            }
+            bytes.push(byte);
+        }
+        bytes
+    }
+}
+
+impl Display for BitPlane {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let order = if self.lsb_first { "lsb" } else { "msb" };
+        write!(f, "b1,{},{},xy", self.channels, order)
+    }
+}
+
+/// Where in the file a signature was found
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Location {
+    /// The chunk at this position in the PNG
+    Chunk { index: usize, chunk_type: String },
+    /// A well-formed chunk among the data after IEND
+    TrailingChunk { chunk_type: String },
+    /// The data after IEND
+    TrailingData,
+    /// A bit plane of the decoded pixels
+    Pixels(BitPlane),
+}
+
+impl Display for Location {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Location::Chunk { index, chunk_type } => write!(f, "{} chunk {}", chunk_type, index),
+            Location::TrailingChunk { chunk_type } => {
+                write!(f, "{} chunk after IEND", chunk_type)
+            }
+            Location::TrailingData => write!(f, "data after IEND"),
+            Location::Pixels(plane) => write!(f, "pixel LSBs ({})", plane),
+        }
+    }
+}
+
+/// The fingerprint of a particular steganography tool
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signature {
+    /// The tool which most likely wrote the data
+    pub tool: Tool,
+    /// What gave the tool away, e.g. "ZIP archive"
+    pub marker: &'static str,
+    pub location: Location,
+}
+
+impl Display for Signature {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} in {}, likely from {}",
+            self.marker, self.location, self.tool
+        )
+    }
+}
+
+/// Returns whether `data` contains `needle` anywhere
+fn contains(data: &[u8], needle: &[u8]) -> bool {
+    data.windows(needle.len()).any(|window| window == needle)
+}
+
+/// The tool behind a chunk's data, if it has a recognisable header. Chunks after IEND with no
+/// header of their own are what pngme leaves behind.
+fn chunk_signature(chunk: &ChunkRef, after_iend: bool) -> Option<(Tool, &'static str)> {
+    let data = chunk.data();
+    if data.starts_with(&Envelope::MAGIC) {
+        Some((Tool::Pngmi, "payload envelope"))
+    } else if segment::is_segment(data) {
+        Some((Tool::Pngmi, "payload segment"))
+    } else if contains(data, OPENSTEGO_STAMP) {
+        Some((Tool::OpenStego, "OPENSTEGO stamp"))
+    } else if chunk.chunk_type().to_string() == PNGME_CHUNK_TYPE
+        || (after_iend && !chunk.chunk_type().is_standard())
+    {
+        Some((Tool::Pngme, "message chunk"))
+    } else {
+        None
+    }
+}
+
+/// Returns whether the bytes start with a run of text varied enough not to be a pattern in
+/// the image. Flat or striped areas read as one or two characters over and over, where no
+/// character makes up more than a quarter of real text.
+fn starts_with_text(bytes: &[u8]) -> bool {
+    let len = bytes
+        .iter()
+        .take_while(|byte| byte.is_ascii_graphic() || matches!(byte, b' ' | b'\t' | b'\r' | b'\n'))
+        .count();
+    let mut counts = [0; 256];
+    for byte in &bytes[..len] {
+        counts[*byte as usize] += 1;
+    }
+    len >= MIN_TEXT_LEN && counts.iter().all(|count| count * 4 <= len)
+}
+
+/// Returns whether the bytes look like a Stegano message: a decimal length, a colon and then
+/// that much text
+fn is_stegano_message(bytes: &[u8]) -> bool {
+    let digits = bytes
+        .iter()
+        .take_while(|byte| byte.is_ascii_digit())
+        .count();
+    (1..=10).contains(&digits)
+        && bytes.get(digits) == Some(&b':')
+        && bytes[digits + 1..]
+            .iter()
+            .take(MIN_TEXT_LEN)
+            .all(|byte| byte.is_ascii_graphic() || *byte == b' ')
+}
+
+/// The tool behind the start of a bit plane, if it's recognisable
+fn plane_signature(bytes: &[u8]) -> Option<(Tool, &'static str)> {
+    if bytes.starts_with(OPENSTEGO_STAMP) {
+        Some((Tool::OpenStego, "OPENSTEGO stamp"))
+    } else if is_stegano_message(bytes) {
+        Some((Tool::Stegano, "length-prefixed message"))
+    } else if let Some(format) = polyglot::identify(bytes) {
+        Some((Tool::SequentialLsb, format.name()))
+    } else if starts_with_text(bytes) {
+        Some((Tool::SequentialLsb, "text"))
+    } else {
+        None
+    }
+}
+
+/// Decode the pixels of an image whose samples can carry a payload in their low bits, or
+/// `None` if the image can't be decoded or has palette indices or samples under 8 bits
+fn lsb_pixels(png: &PngRef) -> Option<Pixels> {
+    let png = Png::from_chunks(
+        png.chunks()
+            .iter()
+            .filter(|chunk| matches!(chunk.chunk_type().to_string().as_str(), "IHDR" | "IDAT"))
+            .map(ChunkRef::to_chunk)
+            .collect(),
+    );
+    let pixels = decoder::decode_png(&png).ok()?;
+    match pixels.color_type != ColorType::Indexed && pixels.bit_depth >= 8 {
+        true => Some(pixels),
+        false => None,
+    }
+}
+
+/// Look for the fingerprints of known steganography tools in the chunks, the data after IEND
+/// and, if `pixels` is set, the low bits of the decoded image. Each signature comes with the
+/// data it was found in.
+pub fn detect<'a>(png: &PngRef<'a>, pixels: bool) -> Vec<(Signature, Cow<'a, [u8]>)> {
+    let mut signatures = vec![];
+    for (index, chunk) in png.chunks().iter().enumerate() {
+        if chunk.chunk_type().is_critical() {
+            continue;
+        }
+        if let Some((tool, marker)) = chunk_signature(chunk, false) {
+            let location = Location::Chunk {
+                index,
+                chunk_type: chunk.chunk_type().to_string(),
+            };
+            let signature = Signature {
+                tool,
+                marker,
+                location,
+            };
+            signatures.push((signature, Cow::Borrowed(chunk.data())));
+        }
+    }
+
+    let trailing_data = png.trailing_data();
+    let trailing = png
+        .trailing_chunks()
+        .iter()
+        .find_map(|chunk| {
+            chunk_signature(chunk, true).map(|(tool, marker)| {
+                let chunk_type = chunk.chunk_type().to_string();
+                (tool, marker, Location::TrailingChunk { chunk_type })
+            })
+        })
+        .or_else(|| {
+            trailer::find(trailing_data)
+                .map(|_| (Tool::Pngmi, "payload trailer", Location::TrailingData))
+        })
+        .or_else(|| {
+            contains(trailing_data, OPENSTEGO_STAMP).then_some((
+                Tool::OpenStego,
+                "OPENSTEGO stamp",
+                Location::TrailingData,
+            ))
+        });
+    if let Some((tool, marker, location)) = trailing {
+        let signature = Signature {
+            tool,
+            marker,
+            location,
+        };
+        signatures.push((signature, Cow::Borrowed(trailing_data)));
+    }
+
+    if let Some(pixels) = pixels.then(|| lsb_pixels(png)).flatten() {
+        signatures.extend(pixel_signature(&pixels));
+    }
+    signatures
+}
+
+/// The first signature found in the bit planes of the pixels, with the start of its plane.
+/// A payload hidden by `lsb::embed` also shows up as text or a file in one of the planes, so
+/// that is looked for first.
+fn pixel_signature<'a>(pixels: &Pixels) -> Option<(Signature, Cow<'a, [u8]>)> {
+    let found = match lsb::read_payload(pixels, LSB_PREFIX_LEN) {
+        Some(payload) if payload.starts_with(&Envelope::MAGIC) => {
+            let plane = BitPlane {
+                channels: match lsb::colour_channels(pixels.color_type) {
+                    1 => "y",
+                    _ => "rgb",
+                },
+                lsb_first: false,
+            };
+            Some((Tool::Pngmi, "payload envelope", plane, payload))
+        }
+        _ => BitPlane::all(pixels.color_type)
+            .into_iter()
+            .find_map(|plane| {
+                let bytes = plane.read(pixels, LSB_PREFIX_LEN);
+                plane_signature(&bytes).map(|(tool, marker)| (tool, marker, plane, bytes))
+            }),
+    };
+    found.map(|(tool, marker, plane, bytes)| {
+        let signature = Signature {
+            tool,
+            marker,
+            location: Location::Pixels(plane),
+        };
+        (signature, Cow::Owned(bytes))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ihdr::Ihdr;
+    use crate::test_util::chunk;
+
+    /// An RGB image whose low bits in the given plane spell out `hidden`, over noise
+    fn pixels_hiding(hidden: &[u8], plane: BitPlane) -> Pixels {
+        let ihdr = Ihdr {
+            width: 64,
+            height: 64,
+            bit_depth: 8,
+            color_type: ColorType::Truecolor,
+            compression_method: 0,
+            filter_method: 0,
+            interlaced: false,
+        };
+        let mut seed = 0x9e37_79b9_u32;
+        let mut raw = vec![];
+        for _ in 0..ihdr.height {
+            raw.push(0);
+            raw.extend((0..ihdr.width * 3).map(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                (seed >> 16) as u8
+            }));
+        }
+        let mut pixels = decoder::decode(&ihdr, &raw).unwrap();
+        let channels: Vec<usize> = plane
+            .channels
+            .chars()
+            .map(|channel| "rgb".find(channel).unwrap())
+            .collect();
+        let bits = hidden.iter().flat_map(|byte| {
+            (0..8).map(move |i| match plane.lsb_first {
+                true => (byte >> i) & 1,
+                false => (byte >> (7 - i)) & 1,
+            })
+        });
+        let offsets = (0..).flat_map(|pixel| channels.iter().map(move |c| pixel * 3 + c));
+        let data = pixels.data_mut();
+        for (offset, bit) in offsets.zip(bits) {
+            data[offset] = (data[offset] & !1) | bit;
+        }
+        pixels
+    }
+
+    #[test]
+    fn test_chunk_signatures() {
+        let mut envelope = Envelope::MAGIC.to_vec();
+        envelope.extend_from_slice(b"rest of envelope");
+        let png = Png::from_chunks(vec![
+            chunk("IHDR", &[0; 13]),
+            chunk("tEXt", b"Comment\0Made with OPENSTEGO"),
+            chunk("prVt", &envelope),
+            chunk("ruSt", b"This is a secret message!"),
+            chunk("IEND", &[]),
+        ]);
+        let mut bytes = png.as_bytes();
+        bytes.extend_from_slice(&chunk("seCr", b"after the end").as_bytes());
+        let png = PngRef::try_from(bytes.as_slice()).unwrap();
+
+        let signatures: Vec<String> = detect(&png, false)
+            .iter()
+            .map(|(signature, _)| signature.to_string())
+            .collect();
+        assert_eq!(
+            signatures,
+            vec![
+                "OPENSTEGO stamp in tEXt chunk 1, likely from OpenStego",
+                "payload envelope in prVt chunk 2, likely from pngmi",
+                "message chunk in ruSt chunk 3, likely from pngme",
+                "message chunk in seCr chunk after IEND, likely from pngme",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_trailer_signature() {
+        let mut png = Png::from_chunks(vec![chunk("IHDR", &[0; 13]), chunk("IEND", &[])]);
+        trailer::embed(&mut png, b"payload").unwrap();
+        let signatures = detect(&png.borrowed(), false);
+        assert_eq!(signatures.len(), 1);
+        assert_eq!(
+            signatures[0].0.to_string(),
+            "payload trailer in data after IEND, likely from pngmi"
+        );
+    }
+
+    #[test]
+    fn test_clean_chunks() {
+        let png = Png::from_chunks(vec![
+            chunk("IHDR", &[0; 13]),
+            chunk("tEXt", b"Comment\0Nothing to see here"),
+            chunk("IEND", &[]),
+        ]);
+        assert!(detect(&png.borrowed(), false).is_empty());
+    }
+
+    #[test]
+    fn test_plane_signatures() {
+        let plane = |channels, lsb_first| BitPlane {
+            channels,
+            lsb_first,
+        };
+        let cases: &[(&[u8], BitPlane, Tool, &str)] = &[
+            (
+                b"OPENSTEGO\x02payload",
+                plane("rgb", true),
+                Tool::OpenStego,
+                "OPENSTEGO stamp in pixel LSBs (b1,rgb,lsb,xy), likely from OpenStego",
+            ),
+            (
+                b"24:Meet me at the docks at ten",
+                plane("rgb", false),
+                Tool::Stegano,
+                "length-prefixed message in pixel LSBs (b1,rgb,msb,xy), likely from Stegano",
+            ),
+            (
+                b"PK\x03\x04\x14\x00\x00\x00",
+                plane("bgr", false),
+                Tool::SequentialLsb,
+                "ZIP archive in pixel LSBs (b1,bgr,msb,xy), likely from a sequential LSB tool",
+            ),
+            (
+                b"the flag is hidden in the blue channel",
+                plane("b", true),
+                Tool::SequentialLsb,
+                "text in pixel LSBs (b1,b,lsb,xy), likely from a sequential LSB tool",
+            ),
+        ];
+        for (hidden, hidden_plane, tool, message) in cases {
+            let (signature, bytes) =
+                pixel_signature(&pixels_hiding(hidden, *hidden_plane)).unwrap();
+            assert_eq!(signature.tool, *tool);
+            assert_eq!(signature.to_string(), *message);
+            assert!(bytes.starts_with(hidden));
+        }
+        assert_eq!(
+            pixel_signature(&pixels_hiding(b"", plane("rgb", true))),
+            None
+        );
+    }
+
+    #[test]
+    fn test_lsb_embed_signature() {
+        let raw: Vec<u8> = (0..32 * 33).map(|i| (i * 7 % 251) as u8).collect();
+        let ihdr = Ihdr {
+            width: 32,
+            height: 32,
+            bit_depth: 8,
+            color_type: ColorType::Grayscale,
+            compression_method: 0,
+            filter_method: 0,
+            interlaced: false,
+        };
+        let mut png = Png::from_chunks(vec![chunk("IHDR", &ihdr.as_bytes()), chunk("IEND", &[])]);
+        png.insert_chunk(1, chunk("IDAT", &[]));
+        png.set_idat_data(
+            &raw.chunks(33)
+                .flat_map(|row| [&[0], &row[1..]].concat())
+                .collect::<Vec<u8>>(),
+            6,
+            1024,
+        )
+        .unwrap();
+        lsb::embed(&mut png, &Envelope::text("hi").as_bytes().unwrap(), 2).unwrap();
+
+        let signatures = detect(&png.borrowed(), true);
+        assert_eq!(signatures.len(), 1);
+        assert_eq!(
+            signatures[0].0.to_string(),
+            "payload envelope in pixel LSBs (b1,y,msb,xy), likely from pngmi"
+        );
+    }
+}
@@ -0,0 +1,101 @@
+use crate::envelope::{Envelope, Flags};
+use crate::error::{error, ErrorKind};
+use crate::PngResult;
+use ed25519_dalek::pkcs8::{DecodePrivateKey, DecodePublicKey};
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use std::fs;
+use std::path::Path;
+
+/// Load an Ed25519 private key from a PKCS#8 PEM file, e.g. as written by
+/// `openssl genpkey -algorithm ed25519`
+pub fn load_signing_key(path: &Path) -> PngResult<SigningKey> {
+    let pem = fs::read_to_string(path)?;
+    SigningKey::from_pkcs8_pem(&pem).map_err(|e| {
+        error(
+            ErrorKind::Crypto,
+            format!("Invalid Ed25519 private key {}: {}", path.display(), e),
+        )
+    })
+}
+
+/// Load an Ed25519 public key from an SPKI PEM file, e.g. as written by `openssl pkey -pubout`
+pub fn load_verifying_key(path: &Path) -> PngResult<VerifyingKey> {
+    let pem = fs::read_to_string(path)?;
+    VerifyingKey::from_public_key_pem(&pem).map_err(|e| {
+        error(
+            ErrorKind::Crypto,
+            format!("Invalid Ed25519 public key {}: {}", path.display(), e),
+        )
+    })
+}
+
+/// Sign the envelope as it currently stands. This should be the last transformation applied
+/// before embedding, so that the signature covers the metadata and the final body.
+pub fn sign(envelope: &mut Envelope, key: &SigningKey) -> PngResult<()> {
+    envelope.flags_mut().set(Flags::SIGNED, true);
+    let signature = key.sign(&envelope.signed_bytes()?);
+    envelope.set_signature(Some(signature.to_bytes()));
+    Ok(())
+}
+
+/// Check the signature of an envelope against the given public key.
+/// This must happen before the body is decrypted or decompressed.
+pub fn verify(envelope: &Envelope, key: &VerifyingKey) -> PngResult<()> {
+    let signature = envelope
+        .signature()
+        .ok_or_else(|| error(ErrorKind::Crypto, "Payload is not signed"))?;
+    key.verify_strict(&envelope.signed_bytes()?, &Signature::from_bytes(signature))
+        .map_err(|_| {
+            error(
+                ErrorKind::Crypto,
+                "Payload signature does not match the given public key",
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signing_key(seed: u8) -> SigningKey {
+        SigningKey::from_bytes(&[seed; 32])
+    }
+
+    #[test]
+    fn test_sign_and_verify() {
+        let key = signing_key(7);
+        let mut envelope = Envelope::text("signed message");
+        sign(&mut envelope, &key).unwrap();
+        assert!(envelope.flags().contains(Flags::SIGNED));
+
+        let bytes = envelope.as_bytes().unwrap();
+        let parsed = Envelope::from_payload(&bytes).unwrap();
+        assert!(verify(&parsed, &key.verifying_key()).is_ok());
+    }
+
+    #[test]
+    fn test_verify_with_wrong_key() {
+        let mut envelope = Envelope::text("signed message");
+        sign(&mut envelope, &signing_key(7)).unwrap();
+        assert!(verify(&envelope, &signing_key(8).verifying_key()).is_err());
+    }
+
+    #[test]
+    fn test_verify_tampered_envelope() {
+        let key = signing_key(7);
+        let mut envelope = Envelope::text("signed message");
+        sign(&mut envelope, &key).unwrap();
+        let mut bytes = envelope.as_bytes().unwrap();
+        let last_body_byte = bytes.len() - Envelope::SIGNATURE_LEN - 1;
+        bytes[last_body_byte] ^= 1;
+
+        let parsed = Envelope::from_payload(&bytes).unwrap();
+        assert!(verify(&parsed, &key.verifying_key()).is_err());
+    }
+
+    #[test]
+    fn test_verify_unsigned_envelope() {
+        let envelope = Envelope::text("unsigned message");
+        assert!(verify(&envelope, &signing_key(7).verifying_key()).is_err());
+    }
+}
@@ -0,0 +1,188 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::error::{error, ErrorKind};
+use crate::png::Png;
+use crate::PngResult;
+use serde_json::{json, Value};
+use std::fs;
+use std::path::{Component, Path};
+use std::str::FromStr;
+
+/// The version of the manifest format written by `split`
+pub const MANIFEST_VERSION: u64 = 1;
+
+/// The name of the manifest `split` writes alongside the chunk files
+pub const MANIFEST_FILE_NAME: &str = "manifest.json";
+
+/// The name of the file holding any data after the IEND chunk
+const TRAILING_DATA_FILE_NAME: &str = "trailing.bin";
+
+/// One chunk of a split PNG: its type, and the file holding its data
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManifestEntry {
+    pub chunk_type: ChunkType,
+    /// The path of the data file, relative to the manifest
+    pub file: String,
+}
+
+/// The chunks of a split PNG in file order, each of whose data is kept in a file of its own
+/// so that it can be edited with any tool before the PNG is put back together
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Manifest {
+    pub chunks: Vec<ManifestEntry>,
+    /// The file holding the data after the IEND chunk, if there is any
+    pub trailing_data: Option<String>,
+}
+
+impl Manifest {
+    pub fn to_json(&self) -> Value {
+        let chunks: Vec<Value> = self
+            .chunks
+            .iter()
+            .map(|entry| json!({ "type": entry.chunk_type.to_string(), "file": entry.file }))
+            .collect();
+        let mut value = json!({ "version": MANIFEST_VERSION, "chunks": chunks });
+        if let Some(file) = &self.trailing_data {
+            value["trailing_data"] = json!(file);
+        }
+        value
+    }
+
+    pub fn from_json(value: &Value) -> PngResult<Self> {
+        match value["version"].as_u64() {
+            Some(MANIFEST_VERSION) => {}
+            Some(version) => {
+                return Err(format!("Unsupported manifest version {}", version).into());
+            }
+            None => return Err("Manifest is missing its version".into()),
+        }
+        let file = |value: &Value| -> PngResult<String> {
+            let file = value.as_str().ok_or("Manifest entry is missing its file")?;
+            check_relative(file)?;
+            Ok(file.to_string())
+        };
+        let chunks = value["chunks"]
+            .as_array()
+            .ok_or("Manifest is missing its chunks")?
+            .iter()
+            .map(|entry| {
+                let chunk_type = entry["type"]
+                    .as_str()
+                    .ok_or("Manifest entry is missing its type")?;
+                Ok(ManifestEntry {
+                    chunk_type: ChunkType::from_str(chunk_type)?,
+                    file: file(&entry["file"])?,
+                })
+            })
+            .collect::<PngResult<_>>()?;
+        let trailing_data = match &value["trailing_data"] {
+            Value::Null => None,
+            trailing_data => Some(file(trailing_data)?),
+        };
+        Ok(Manifest {
+            chunks,
+            trailing_data,
+        })
+    }
+}
+
+/// Manifests only refer to files beside or beneath them, so that assembling an untrusted one
+/// can't read files from elsewhere into the image
+fn check_relative(file: &str) -> PngResult<()> {
+    let normal = Path::new(file)
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)));
+    match normal && !file.is_empty() {
+        true => Ok(()),
+        false => Err(error(
+            ErrorKind::Parse,
+            format!(
+                "Manifest file {} must be a path relative to the manifest",
+                file
+            ),
+        )),
+    }
+}
+
+/// Write each chunk's data to a numbered file in the directory, e.g. `0003-tEXt.bin`, along
+/// with a manifest listing them in order. The directory is created if need be.
+pub fn split(png: &Png, dir: &Path) -> PngResult<Manifest> {
+    fs::create_dir_all(dir)?;
+    let mut manifest = Manifest::default();
+    for (index, chunk) in png.chunks().iter().enumerate() {
+        let file = format!("{:04}-{}.bin", index, chunk.chunk_type());
+        fs::write(dir.join(&file), chunk.data())?;
+        manifest.chunks.push(ManifestEntry {
+            chunk_type: *chunk.chunk_type(),
+            file,
+        });
+    }
+    if !png.trailing_data().is_empty() {
+        fs::write(dir.join(TRAILING_DATA_FILE_NAME), png.trailing_data())?;
+        manifest.trailing_data = Some(TRAILING_DATA_FILE_NAME.to_string());
+    }
+    let json = serde_json::to_string_pretty(&manifest.to_json())?;
+    fs::write(dir.join(MANIFEST_FILE_NAME), json + "\n")?;
+    Ok(manifest)
+}
+
+/// Rebuild a PNG from the manifest at the given path and the files it lists, computing every
+/// chunk's length and CRC afresh
+pub fn assemble(manifest_path: &Path) -> PngResult<Png> {
+    let json: Value = serde_json::from_slice(&fs::read(manifest_path)?)
+        .map_err(|e| error(ErrorKind::Parse, format!("Invalid manifest: {}", e)))?;
+    let manifest = Manifest::from_json(&json)?;
+    let dir = manifest_path.parent().unwrap_or(Path::new(""));
+    let read =
+        |file: &str| fs::read(dir.join(file)).map_err(|e| format!("Couldn't read {}: {}", file, e));
+    let chunks = manifest
+        .chunks
+        .iter()
+        .map(|entry| Ok(Chunk::new(entry.chunk_type, read(&entry.file)?)))
+        .collect::<PngResult<_>>()?;
+    let mut png = Png::from_chunks(chunks);
+    if let Some(file) = &manifest.trailing_data {
+        png.set_trailing_data(read(file)?);
+    }
+    Ok(png)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::chunk;
+
+    #[test]
+    fn test_split_assemble() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut png = Png::from_chunks(vec![
+            chunk("IHDR", &[0; 13]),
+            chunk("tEXt", b"Title\0Old"),
+            chunk("IDAT", &[1, 2, 3]),
+            chunk("IEND", &[]),
+        ]);
+        png.set_trailing_data(b"extra".to_vec());
+
+        let manifest = split(&png, dir.path()).unwrap();
+        assert_eq!(manifest.chunks[1].file, "0001-tEXt.bin");
+        assert_eq!(manifest.trailing_data.as_deref(), Some("trailing.bin"));
+        let manifest_path = dir.path().join(MANIFEST_FILE_NAME);
+        assert_eq!(assemble(&manifest_path).unwrap().as_bytes(), png.as_bytes());
+
+        fs::write(dir.path().join("0001-tEXt.bin"), b"Title\0New").unwrap();
+        let assembled = assemble(&manifest_path).unwrap();
+        assert_eq!(assembled.chunks()[1].data(), b"Title\0New");
+        assert!(Png::try_from(assembled.as_bytes().as_slice()).is_ok());
+    }
+
+    #[test]
+    fn test_manifest_paths_must_be_relative() {
+        for file in ["/etc/passwd", "../secret.bin", ""] {
+            let json = json!({
+                "version": MANIFEST_VERSION,
+                "chunks": [{ "type": "IHDR", "file": file }],
+            });
+            assert!(Manifest::from_json(&json).is_err());
+        }
+    }
+}
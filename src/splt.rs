@@ -0,0 +1,198 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+use crate::text::{latin1_decode, latin1_encode, validate_keyword};
+use crate::{PngError, PngResult};
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// One colour of a suggested palette, with samples of the palette's sample depth
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpltEntry {
+    pub red: u16,
+    pub green: u16,
+    pub blue: u16,
+    pub alpha: u16,
+    /// How often the colour is used relative to the others, or 0 if unknown
+    pub frequency: u16,
+}
+
+/// A palette suggested for displaying a truecolour image on a limited device, as stored in an
+/// sPLT chunk
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SuggestedPalette {
+    name: String,
+    sample_depth: u8,
+    entries: Vec<SpltEntry>,
+}
+
+impl SuggestedPalette {
+    pub const CHUNK_TYPE: &'static str = "sPLT";
+
+    pub fn new(name: &str, sample_depth: u8, entries: Vec<SpltEntry>) -> PngResult<Self> {
+        validate_keyword(name)?;
+        if sample_depth != 8 && sample_depth != 16 {
+            return Err(format!("sPLT sample depth must be 8 or 16, not {}", sample_depth).into());
+        }
+        if sample_depth == 8 {
+            let too_large = entries.iter().any(|entry| {
+                [entry.red, entry.green, entry.blue, entry.alpha]
+                    .iter()
+                    .any(|sample| *sample > u8::MAX as u16)
+            });
+            if too_large {
+                return Err("sPLT sample is too large for a sample depth of 8".into());
+            }
+        }
+        Ok(SuggestedPalette {
+            name: name.to_string(),
+            sample_depth,
+            entries,
+        })
+    }
+
+    /// The palette name, which tells palettes in the same file apart
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Bits per sample of each entry: 8 or 16
+    pub fn sample_depth(&self) -> u8 {
+        self.sample_depth
+    }
+
+    pub fn entries(&self) -> &[SpltEntry] {
+        &self.entries
+    }
+
+    /// The length of each encoded entry, which depends on the sample depth
+    fn entry_len(sample_depth: u8) -> usize {
+        if sample_depth == 8 {
+            6
+        } else {
+            10
+        }
+    }
+
+    pub fn as_bytes(&self) -> PngResult<Vec<u8>> {
+        let mut bytes = latin1_encode(&self.name)?;
+        bytes.extend_from_slice(&[0, self.sample_depth]);
+        for entry in &self.entries {
+            for sample in [entry.red, entry.green, entry.blue, entry.alpha] {
+                if self.sample_depth == 8 {
+                    bytes.push(sample as u8);
+                } else {
+                    bytes.extend_from_slice(&sample.to_be_bytes());
+                }
+            }
+            bytes.extend_from_slice(&entry.frequency.to_be_bytes());
+        }
+        Ok(bytes)
+    }
+
+    pub fn to_chunk(&self) -> PngResult<Chunk> {
+        Ok(Chunk::new(
+            ChunkType::from_str(Self::CHUNK_TYPE)?,
+            self.as_bytes()?,
+        ))
+    }
+}
+
+impl TryFrom<&[u8]> for SuggestedPalette {
+    type Error = PngError;
+
+    /// Decode sPLT chunk data
+    fn try_from(value: &[u8]) -> PngResult<Self> {
+        let name_len = value
+            .iter()
+            .position(|b| *b == 0)
+            .ok_or("sPLT chunk has no palette name terminator")?;
+        let sample_depth = *value
+            .get(name_len + 1)
+            .ok_or("sPLT chunk has no sample depth")?;
+        let entries = &value[name_len + 2..];
+        let entry_len = Self::entry_len(sample_depth);
+        if !entries.len().is_multiple_of(entry_len) {
+            return Err(format!(
+                "sPLT entries are {} bytes long, which is not a multiple of {}",
+                entries.len(),
+                entry_len
+            )
+            .into());
+        }
+        let entries = entries
+            .chunks_exact(entry_len)
+            .map(|entry| {
+                let sample = |index: usize| match sample_depth {
+                    8 => entry[index] as u16,
+                    _ => u16::from_be_bytes([entry[index * 2], entry[index * 2 + 1]]),
+                };
+                SpltEntry {
+                    red: sample(0),
+                    green: sample(1),
+                    blue: sample(2),
+                    alpha: sample(3),
+                    frequency: u16::from_be_bytes([entry[entry_len - 2], entry[entry_len - 1]]),
+                }
+            })
+            .collect();
+        SuggestedPalette::new(&latin1_decode(&value[..name_len]), sample_depth, entries)
+    }
+}
+
+impl Display for SuggestedPalette {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "\"{}\" ({}-bit, {} entries)",
+            self.name,
+            self.sample_depth,
+            self.entries.len()
+        )
+    }
+}
+
+/// Decode every sPLT chunk of the PNG, in file order
+pub fn suggested_palettes(png: &Png) -> impl Iterator<Item = PngResult<SuggestedPalette>> + '_ {
+    png.chunks_by_type(SuggestedPalette::CHUNK_TYPE)
+        .map(|chunk| SuggestedPalette::try_from(chunk.data()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(red: u16, frequency: u16) -> SpltEntry {
+        SpltEntry {
+            red,
+            green: 2,
+            blue: 3,
+            alpha: 255,
+            frequency,
+        }
+    }
+
+    #[test]
+    fn test_splt_round_trip() {
+        let palette = SuggestedPalette::new("web safe", 8, vec![entry(1, 10), entry(4, 0)]);
+        let palette = palette.unwrap();
+        let bytes = palette.as_bytes().unwrap();
+        assert_eq!(bytes.len(), 8 + 2 + 2 * 6);
+        assert_eq!(&bytes[10..16], &[1, 2, 3, 255, 0, 10]);
+        assert_eq!(SuggestedPalette::try_from(&bytes[..]).unwrap(), palette);
+        assert_eq!(palette.to_string(), "\"web safe\" (8-bit, 2 entries)");
+
+        let deep = SuggestedPalette::new("deep", 16, vec![entry(1000, 1)]).unwrap();
+        let bytes = deep.as_bytes().unwrap();
+        assert_eq!(bytes.len(), 4 + 2 + 10);
+        assert_eq!(SuggestedPalette::try_from(&bytes[..]).unwrap(), deep);
+    }
+
+    #[test]
+    fn test_invalid_splt() {
+        assert!(SuggestedPalette::new("deep", 8, vec![entry(1000, 1)]).is_err());
+        assert!(SuggestedPalette::new("odd", 4, vec![]).is_err());
+        assert!(SuggestedPalette::try_from(&b"name\0\x08\x01\x02"[..]).is_err());
+        assert!(SuggestedPalette::try_from(&b"name"[..]).is_err());
+    }
+}
@@ -0,0 +1,181 @@
+use crate::chunk_type::ChunkType;
+use crate::png::{Png, PngRef};
+use std::cmp::Reverse;
+
+/// How many of the largest chunks `Stats` lists
+pub const LARGEST_CHUNKS: usize = 5;
+
+/// The sizes of a group of chunks, added up
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Totals {
+    pub count: usize,
+    /// The bytes of chunk data
+    pub data_bytes: usize,
+    /// The bytes the chunks take up in the file, including their length, type and CRC
+    pub bytes: usize,
+}
+
+impl Totals {
+    /// The bytes spent on the length, type and CRC of each chunk rather than its data
+    pub fn framing_bytes(&self) -> usize {
+        self.bytes - self.data_bytes
+    }
+
+    fn add(&mut self, other: &Totals) {
+        self.count += other.count;
+        self.data_bytes += other.data_bytes;
+        self.bytes += other.bytes;
+    }
+}
+
+/// One of the largest chunks in a file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LargeChunk {
+    pub index: usize,
+    pub chunk_type: ChunkType,
+    pub length: u32,
+}
+
+/// A breakdown of where the bytes of a PNG file go
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stats {
+    /// The size of the file as parsed: its signature, chunks and trailing data
+    pub file_bytes: usize,
+    /// The totals for each chunk type, largest first
+    pub types: Vec<(ChunkType, Totals)>,
+    /// The largest chunks, largest first
+    pub largest: Vec<LargeChunk>,
+    /// The bytes following the IEND chunk
+    pub trailing_bytes: usize,
+}
+
+impl Stats {
+    pub fn new(png: &PngRef) -> Self {
+        let mut types: Vec<(ChunkType, Totals)> = vec![];
+        for chunk in png.chunks() {
+            let chunk_totals = Totals {
+                count: 1,
+                data_bytes: chunk.data().len(),
+                bytes: chunk.overall_length() as usize,
+            };
+            match types
+                .iter_mut()
+                .find(|(chunk_type, _)| chunk_type == chunk.chunk_type())
+            {
+                Some((_, totals)) => totals.add(&chunk_totals),
+                None => types.push((*chunk.chunk_type(), chunk_totals)),
+            }
+        }
+        // Stable, so that types of the same size stay in file order
+        types.sort_by_key(|(_, totals)| Reverse(totals.bytes));
+
+        let mut largest: Vec<LargeChunk> = png
+            .chunks()
+            .iter()
+            .enumerate()
+            .map(|(index, chunk)| LargeChunk {
+                index,
+                chunk_type: *chunk.chunk_type(),
+                length: chunk.length(),
+            })
+            .collect();
+        largest.sort_by_key(|chunk| Reverse(chunk.length));
+        largest.truncate(LARGEST_CHUNKS);
+
+        let trailing_bytes = png.trailing_data().len();
+        let chunk_bytes: usize = types.iter().map(|(_, totals)| totals.bytes).sum();
+        Stats {
+            file_bytes: Png::HEADER_BYTES_LEN + chunk_bytes + trailing_bytes,
+            types,
+            largest,
+            trailing_bytes,
+        }
+    }
+
+    /// The totals over the chunk types for which `include` returns true
+    fn total(&self, include: impl Fn(&ChunkType) -> bool) -> Totals {
+        let mut total = Totals::default();
+        for (_, totals) in self
+            .types
+            .iter()
+            .filter(|(chunk_type, _)| include(chunk_type))
+        {
+            total.add(totals);
+        }
+        total
+    }
+
+    /// The image data, split across the IDAT chunks
+    pub fn image_data(&self) -> Totals {
+        self.total(|chunk_type| *chunk_type == ChunkType::IDAT)
+    }
+
+    /// The critical chunks other than IDAT, such as IHDR, PLTE and IEND
+    pub fn other_critical(&self) -> Totals {
+        self.total(|chunk_type| chunk_type.is_critical() && *chunk_type != ChunkType::IDAT)
+    }
+
+    /// The ancillary chunks, which a decoder can do without, and so the bytes the file could
+    /// lose without changing the image
+    pub fn ancillary(&self) -> Totals {
+        self.total(|chunk_type| !chunk_type.is_critical())
+    }
+
+    /// What share of the file the given number of bytes is, as a percentage
+    pub fn percent(&self, bytes: usize) -> f64 {
+        bytes as f64 * 100.0 / self.file_bytes as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_stats() {
+        let chunk =
+            |chunk_type, len| Chunk::new(ChunkType::from_str(chunk_type).unwrap(), vec![0; len]);
+        let png = Png::from_chunks(vec![
+            chunk("IHDR", 13),
+            chunk("tEXt", 20),
+            chunk("IDAT", 100),
+            chunk("IDAT", 50),
+            chunk("tEXt", 10),
+            chunk("IEND", 0),
+        ]);
+        let stats = Stats::new(&png.borrowed());
+        assert_eq!(stats.file_bytes, png.as_bytes().len());
+        assert_eq!(stats.file_bytes, 8 + 6 * 12 + 193);
+
+        let types: Vec<(String, usize)> = stats
+            .types
+            .iter()
+            .map(|(chunk_type, totals)| (chunk_type.to_string(), totals.count))
+            .collect();
+        assert_eq!(
+            types,
+            [
+                ("IDAT".to_string(), 2),
+                ("tEXt".to_string(), 2),
+                ("IHDR".to_string(), 1),
+                ("IEND".to_string(), 1)
+            ]
+        );
+        assert_eq!(stats.image_data().bytes, 174);
+        assert_eq!(
+            stats.ancillary(),
+            Totals {
+                count: 2,
+                data_bytes: 30,
+                bytes: 54
+            }
+        );
+        assert_eq!(stats.ancillary().framing_bytes(), 24);
+        assert_eq!(stats.other_critical().bytes, 37);
+        assert_eq!(stats.largest.len(), LARGEST_CHUNKS);
+        assert_eq!(stats.largest[0].index, 2);
+        assert!((stats.percent(stats.file_bytes) - 100.0).abs() < f64::EPSILON);
+    }
+}
@@ -0,0 +1,238 @@
+use crate::chunk::Chunk;
+use crate::error::{error, ErrorKind};
+use crate::png::{ParseOptions, Png};
+use crate::{PngError, PngResult};
+use std::io;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+/// Reads the chunks of a PNG one at a time from an async reader, such as a socket or a tokio
+/// file, so that a large upload can be inspected without holding all of it in memory. Applies
+/// the same checks and limits as parsing a whole file does.
+pub struct ChunkReader<R> {
+    reader: R,
+    options: ParseOptions,
+    /// How many bytes have been read so far
+    offset: usize,
+    chunks: usize,
+    started: bool,
+    finished: bool,
+}
+
+impl<R: AsyncRead + Unpin> ChunkReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self::with_options(reader, ParseOptions::default())
+    }
+
+    pub fn with_options(reader: R, options: ParseOptions) -> Self {
+        ChunkReader {
+            reader,
+            options,
+            offset: 0,
+            chunks: 0,
+            started: false,
+            finished: false,
+        }
+    }
+
+    /// The next chunk, or `None` once IEND has been read or the stream ends after a whole chunk.
+    /// The signature is checked before the first chunk is read.
+    pub async fn next_chunk(&mut self) -> PngResult<Option<Chunk>> {
+        if self.finished {
+            return Ok(None);
+        }
+        if !self.started {
+            let mut header = [0; Png::HEADER_BYTES_LEN];
+            self.read(&mut header).await?;
+            if header != Png::STANDARD_HEADER {
+                return Err(error(ErrorKind::Parse, "Invalid header found"));
+            }
+            self.started = true;
+        }
+
+        let start = self.offset;
+        let mut length = [0; 4];
+        match self.reader.read(&mut length).await? {
+            0 if self.chunks > 0 => {
+                self.finished = true;
+                return Ok(None);
+            }
+            read => self.offset += read,
+        }
+        self.read(&mut length[self.offset - start..]).await?;
+        let data_len = u32::from_be_bytes(length) as usize;
+        if data_len > self.options.max_chunk_len {
+            let message = format!(
+                "Chunk at offset {:#010x} declares {} bytes, more than the limit of {}",
+                start, data_len, self.options.max_chunk_len
+            );
+            return Err(error(ErrorKind::Parse, message));
+        }
+        if self.chunks >= self.options.max_chunks {
+            let message = format!(
+                "More than {} chunks; stopped at offset {:#010x}",
+                self.options.max_chunks, start
+            );
+            return Err(error(ErrorKind::Parse, message));
+        }
+        self.check_size(start + 12 + data_len)?;
+
+        // Length, type, data and CRC, which `Chunk::try_from` checks as a whole. The buffer
+        // grows as the bytes arrive, so a length field alone can't reserve the memory.
+        let mut bytes = length.to_vec();
+        let rest = 8 + data_len;
+        let read = (&mut self.reader)
+            .take(rest as u64)
+            .read_to_end(&mut bytes)
+            .await?;
+        self.offset += read;
+        if read < rest {
+            return Err(self.truncated());
+        }
+        let chunk = Chunk::try_from(&bytes[..])?;
+        self.chunks += 1;
+        self.finished = chunk.chunk_type().bytes() == *b"IEND";
+        Ok(Some(chunk))
+    }
+
+    /// Read every remaining chunk and whatever follows IEND, as `Png::try_from` would parse the
+    /// whole stream
+    pub async fn read_png(mut self) -> PngResult<Png> {
+        let mut chunks = vec![];
+        while let Some(chunk) = self.next_chunk().await? {
+            chunks.push(chunk);
+        }
+        let mut trailing_data = vec![];
+        let limit = self.options.max_total_size.saturating_sub(self.offset) as u64;
+        (&mut self.reader)
+            .take(limit.saturating_add(1))
+            .read_to_end(&mut trailing_data)
+            .await?;
+        self.check_size(self.offset + trailing_data.len())?;
+        let mut png = Png::from_chunks(chunks);
+        png.set_trailing_data(trailing_data);
+        Ok(png)
+    }
+
+    /// The underlying reader, positioned after the last chunk read
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Fill the buffer, failing with a parse error if the stream ends first
+    async fn read(&mut self, buffer: &mut [u8]) -> PngResult<()> {
+        match self.reader.read_exact(buffer).await {
+            Ok(_) => {
+                self.offset += buffer.len();
+                Ok(())
+            }
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Err(self.truncated()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn truncated(&self) -> PngError {
+        let message = format!("Stream ended part way through the chunk at {}", self.offset);
+        error(ErrorKind::Parse, message)
+    }
+
+    fn check_size(&self, size: usize) -> PngResult<()> {
+        if size > self.options.max_total_size {
+            let message = format!(
+                "Input is more than the limit of {} bytes",
+                self.options.max_total_size
+            );
+            return Err(error(ErrorKind::Parse, message));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::chunk;
+
+    fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+
+    fn testing_png() -> Png {
+        Png::from_chunks(vec![
+            chunk("FrSt", b"I am the first chunk"),
+            chunk("miDl", b"I am another chunk"),
+            chunk("IEND", b""),
+        ])
+    }
+
+    #[test]
+    fn test_next_chunk() {
+        let bytes = testing_png().as_bytes();
+        block_on(async {
+            let mut reader = ChunkReader::new(&bytes[..]);
+            let mut types = vec![];
+            while let Some(chunk) = reader.next_chunk().await.unwrap() {
+                types.push(chunk.chunk_type().to_string());
+            }
+            assert_eq!(types, ["FrSt", "miDl", "IEND"]);
+            assert!(reader.next_chunk().await.unwrap().is_none());
+        });
+    }
+
+    #[test]
+    fn test_read_png() {
+        let mut bytes = testing_png().as_bytes();
+        bytes.extend_from_slice(b"trailer");
+        let png = block_on(Png::from_async_reader(&bytes[..])).unwrap();
+        let expected = Png::try_from(&bytes[..]).unwrap();
+        assert_eq!(png.as_bytes(), expected.as_bytes());
+        assert_eq!(png.trailing_data(), b"trailer");
+    }
+
+    #[test]
+    fn test_bad_streams() {
+        let bytes = testing_png().as_bytes();
+        let read = |bytes: &[u8], options: ParseOptions| {
+            block_on(ChunkReader::with_options(bytes, options).read_png())
+        };
+        let defaults = ParseOptions::default();
+        assert!(read(&bytes, defaults).is_ok());
+        assert!(read(&bytes[1..], defaults).is_err());
+        assert!(read(&bytes[..Png::HEADER_BYTES_LEN], defaults).is_err());
+        assert!(read(&bytes[..bytes.len() - 3], defaults).is_err());
+
+        let mut corrupt = bytes.clone();
+        corrupt[Png::HEADER_BYTES_LEN + 10] ^= 1;
+        assert!(read(&corrupt, defaults).is_err());
+
+        let small = ParseOptions {
+            max_chunk_len: 19,
+            ..defaults
+        };
+        assert!(read(&bytes, small).is_err());
+        let few = ParseOptions {
+            max_chunks: 2,
+            ..defaults
+        };
+        assert!(read(&bytes, few).is_err());
+        let short = ParseOptions {
+            max_total_size: bytes.len() - 1,
+            ..defaults
+        };
+        assert!(read(&bytes, short).is_err());
+
+        // A length field claiming far more than follows it doesn't reserve that much memory
+        let mut huge = bytes[..Png::HEADER_BYTES_LEN].to_vec();
+        huge.extend_from_slice(&0x4000_0000u32.to_be_bytes());
+        huge.extend_from_slice(b"ruSt data");
+        let roomy = ParseOptions {
+            max_chunk_len: usize::MAX,
+            max_total_size: usize::MAX,
+            ..defaults
+        };
+        let error = read(&huge, roomy).err().unwrap();
+        assert!(error.to_string().starts_with("Stream ended part way"));
+    }
+}
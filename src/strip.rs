@@ -0,0 +1,202 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::exif::{self, EXIF_CHUNK_TYPE};
+use crate::png::Png;
+use crate::PngResult;
+use std::str::FromStr;
+
+/// Which chunks `strip` removes. Critical chunks are never removed.
+#[derive(Debug, Clone, Default)]
+pub struct StripOptions {
+    /// Ancillary chunk types to keep while everything else ancillary is removed
+    pub keep: Vec<String>,
+    /// When not empty, remove only these chunk types and leave other ancillary chunks alone
+    pub remove: Vec<String>,
+    /// Leave any data after IEND in place
+    pub keep_trailing_data: bool,
+    /// Only blank out the GPS tags of EXIF data, leaving every chunk in place
+    pub gps_only: bool,
+}
+
+impl StripOptions {
+    /// Check that every listed chunk type is well formed and that no critical chunk is
+    /// listed for removal
+    fn check(&self) -> PngResult<()> {
+        if self.gps_only && !(self.keep.is_empty() && self.remove.is_empty()) {
+            return Err("Removing only GPS data can't be combined with chunk lists".into());
+        }
+        for chunk_type in &self.keep {
+            ChunkType::from_str(chunk_type)?;
+        }
+        for chunk_type in &self.remove {
+            if ChunkType::from_str(chunk_type)?.is_critical() {
+                return Err(format!("Refusing to remove critical {} chunks", chunk_type).into());
+            }
+            if self.keep.contains(chunk_type) {
+                return Err(format!("{} is listed both to keep and to remove", chunk_type).into());
+            }
+        }
+        Ok(())
+    }
+
+    fn should_remove(&self, chunk: &Chunk) -> bool {
+        let chunk_type = chunk.chunk_type().to_string();
+        if !self.remove.is_empty() {
+            return self.remove.contains(&chunk_type);
+        }
+        !chunk.chunk_type().is_critical() && !self.keep.contains(&chunk_type)
+    }
+}
+
+/// What `strip` took out of a PNG
+#[derive(Debug, Default)]
+pub struct StripReport {
+    /// The removed chunks, in file order
+    pub removed: Vec<Chunk>,
+    /// The number of bytes removed from after IEND
+    pub trailing_data_len: usize,
+    /// Whether GPS tags were removed from EXIF data
+    pub gps_removed: bool,
+}
+
+impl StripReport {
+    /// Returns whether anything was removed
+    pub fn is_empty(&self) -> bool {
+        self.removed.is_empty() && self.trailing_data_len == 0 && !self.gps_removed
+    }
+}
+
+/// Blank out the GPS tags in every eXIf chunk, returning whether there were any
+pub fn strip_gps(png: &mut Png) -> PngResult<bool> {
+    let mut removed = false;
+    for chunk in png.chunks_mut() {
+        if chunk.chunk_type().to_string() != EXIF_CHUNK_TYPE {
+            continue;
+        }
+        let mut data = chunk.data().to_vec();
+        if exif::remove_gps(&mut data)? {
+            *chunk = Chunk::new(ChunkType::from_str(EXIF_CHUNK_TYPE)?, data);
+            removed = true;
+        }
+    }
+    Ok(removed)
+}
+
+/// Remove metadata from the PNG: by default every ancillary chunk and any data after IEND
+pub fn strip(png: &mut Png, options: &StripOptions) -> PngResult<StripReport> {
+    options.check()?;
+    if options.gps_only {
+        return Ok(StripReport {
+            gps_removed: strip_gps(png)?,
+            ..Default::default()
+        });
+    }
+    let removed = png.retain_chunks(|chunk| !options.should_remove(chunk));
+    let trailing_data_len = if options.keep_trailing_data {
+        0
+    } else {
+        png.take_trailing_data().len()
+    };
+    Ok(StripReport {
+        removed,
+        trailing_data_len,
+        gps_removed: false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testing_png() -> Png {
+        let chunks = ["IHDR", "tEXt", "pHYs", "IDAT", "tIME", "IEND"]
+            .iter()
+            .map(|t| Chunk::new(ChunkType::from_str(t).unwrap(), vec![]))
+            .collect();
+        let mut bytes = Png::from_chunks(chunks).as_bytes();
+        bytes.extend_from_slice(b"trailer");
+        Png::try_from(bytes.as_slice()).unwrap()
+    }
+
+    fn chunk_types(png: &Png) -> Vec<String> {
+        png.chunks()
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_strip_all_ancillary() {
+        let mut png = testing_png();
+        let report = strip(&mut png, &StripOptions::default()).unwrap();
+        assert_eq!(chunk_types(&png), vec!["IHDR", "IDAT", "IEND"]);
+        assert_eq!(report.removed.len(), 3);
+        assert_eq!(report.trailing_data_len, 7);
+        assert!(png.trailing_data().is_empty());
+    }
+
+    #[test]
+    fn test_strip_keep_and_remove_lists() {
+        let mut png = testing_png();
+        let options = StripOptions {
+            keep: vec!["pHYs".to_string()],
+            keep_trailing_data: true,
+            ..Default::default()
+        };
+        strip(&mut png, &options).unwrap();
+        assert_eq!(chunk_types(&png), vec!["IHDR", "pHYs", "IDAT", "IEND"]);
+        assert_eq!(png.trailing_data(), b"trailer");
+
+        let mut png = testing_png();
+        let options = StripOptions {
+            remove: vec!["tEXt".to_string()],
+            ..Default::default()
+        };
+        strip(&mut png, &options).unwrap();
+        assert_eq!(
+            chunk_types(&png),
+            vec!["IHDR", "pHYs", "IDAT", "tIME", "IEND"]
+        );
+    }
+
+    #[test]
+    fn test_strip_rejects_bad_lists() {
+        for options in [
+            StripOptions {
+                remove: vec!["IDAT".to_string()],
+                ..Default::default()
+            },
+            StripOptions {
+                keep: vec!["pHY".to_string()],
+                ..Default::default()
+            },
+            StripOptions {
+                keep: vec!["tEXt".to_string()],
+                remove: vec!["tEXt".to_string()],
+                ..Default::default()
+            },
+        ] {
+            assert!(strip(&mut testing_png(), &options).is_err());
+        }
+    }
+
+    #[test]
+    fn test_strip_gps_only() {
+        let mut png = testing_png();
+        let options = StripOptions {
+            gps_only: true,
+            ..Default::default()
+        };
+        let report = strip(&mut png, &options).unwrap();
+        assert!(report.is_empty());
+        assert_eq!(png.chunks().len(), 6);
+        assert_eq!(png.trailing_data(), b"trailer");
+
+        let options = StripOptions {
+            gps_only: true,
+            keep: vec!["pHYs".to_string()],
+            ..Default::default()
+        };
+        assert!(strip(&mut png, &options).is_err());
+    }
+}
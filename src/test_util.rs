@@ -0,0 +1,48 @@
+//! Fixtures shared by the unit tests of several modules
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::ihdr::{ColorType, Ihdr};
+use crate::png::Png;
+use std::str::FromStr;
+
+/// A chunk of the given type holding `data`, with its CRC computed
+pub(crate) fn chunk(chunk_type: &str, data: &[u8]) -> Chunk {
+    Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec())
+}
+
+/// A file made of chunks given as their type and text data
+pub(crate) fn png(chunks: &[(&str, &str)]) -> Png {
+    Png::from_chunks(
+        chunks
+            .iter()
+            .map(|(chunk_type, data)| chunk(chunk_type, data.as_bytes()))
+            .collect(),
+    )
+}
+
+/// The smallest file which parses: an all-zero IHDR chunk then IEND
+pub(crate) fn minimal_png() -> Png {
+    Png::from_chunks(vec![chunk("IHDR", &[0; 13]), chunk("IEND", &[])])
+}
+
+/// The chunk types of the file, in order
+pub(crate) fn types(png: &Png) -> Vec<String> {
+    png.chunks()
+        .iter()
+        .map(|chunk| chunk.chunk_type().to_string())
+        .collect()
+}
+
+/// The header of a 1x1 image with the given bit depth and colour type
+pub(crate) fn ihdr(bit_depth: u8, color_type: ColorType) -> Ihdr {
+    Ihdr {
+        width: 1,
+        height: 1,
+        bit_depth,
+        color_type,
+        compression_method: 0,
+        filter_method: 0,
+        interlaced: false,
+    }
+}
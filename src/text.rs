@@ -0,0 +1,590 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::compression::Compression;
+use crate::png::Png;
+use crate::{PngError, PngResult};
+use std::str::FromStr;
+
+/// The longest keyword the spec allows, in characters
+pub const MAX_KEYWORD_LEN: usize = 79;
+
+/// Check a keyword against the spec: 1 to 79 printable Latin-1 characters, with no leading,
+/// trailing or consecutive spaces
+pub fn validate_keyword(keyword: &str) -> PngResult<()> {
+    let len = keyword.chars().count();
+    if len == 0 || len > MAX_KEYWORD_LEN {
+        return Err(format!(
+            "Keyword must be 1 to {} characters long, not {}",
+            MAX_KEYWORD_LEN, len
+        )
+        .into());
+    }
+    if let Some(c) = keyword
+        .chars()
+        .find(|c| !matches!(*c as u32, 32..=126 | 161..=255))
+    {
+        return Err(format!("Keyword contains {:?}, which isn't printable Latin-1", c).into());
+    }
+    if keyword.starts_with(' ') || keyword.ends_with(' ') || keyword.contains("  ") {
+        return Err("Keyword must not have leading, trailing or consecutive spaces".into());
+    }
+    Ok(())
+}
+
+/// Check that text holds no NUL characters, which can't be stored in a text chunk.
+///
+/// NUL separates the keyword from the text in tEXt, zTXt and iTXt, so one inside the text
+/// would end it early when read back. This is checked before a chunk is written; validating
+/// an existing file reports NULs in the text as a finding of their own instead.
+pub fn validate_text(text: &str) -> PngResult<()> {
+    if text.contains('\0') {
+        return Err("Text must not contain NUL characters".into());
+    }
+    Ok(())
+}
+
+/// Whether a byte of tEXt or zTXt text is a control character other than linefeed, which
+/// has no defined meaning in PNG text.
+///
+/// The spec limits this text to Latin-1 printable characters plus linefeed, so the C0 codes
+/// other than linefeed, DEL and the C1 codes are all undefined. NUL matches too, but callers
+/// report it separately since it also ends the text.
+pub fn is_undefined_control(byte: u8) -> bool {
+    matches!(byte, 0..=9 | 11..=31 | 127..=159)
+}
+
+/// Decode Latin-1 bytes, each of which maps straight to the Unicode code point of its value
+pub fn latin1_decode(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| *b as char).collect()
+}
+
+/// Encode text as Latin-1, failing on characters outside it
+pub fn latin1_encode(text: &str) -> PngResult<Vec<u8>> {
+    text.chars()
+        .map(|c| u8::try_from(c).map_err(|_| format!("{:?} can't be encoded as Latin-1", c)))
+        .collect::<Result<_, _>>()
+        .map_err(Into::into)
+}
+
+/// Split chunk data at the NUL which ends the keyword, returning the keyword and the rest
+fn split_keyword(data: &[u8]) -> PngResult<(String, &[u8])> {
+    let end = data
+        .iter()
+        .position(|b| *b == 0)
+        .ok_or("Text chunk has no keyword terminator")?;
+    let keyword = latin1_decode(&data[..end]);
+    validate_keyword(&keyword)?;
+    Ok((keyword, &data[end + 1..]))
+}
+
+/// The only compression method defined for zTXt chunks: zlib deflate
+const COMPRESSION_METHOD_DEFLATE: u8 = 0;
+
+/// A Latin-1 text chunk, stored either as is (tEXt) or zlib compressed (zTXt)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextChunk {
+    keyword: String,
+    text: String,
+    compressed: bool,
+}
+
+impl TextChunk {
+    pub const CHUNK_TYPE: &'static str = "tEXt";
+    pub const COMPRESSED_CHUNK_TYPE: &'static str = "zTXt";
+
+    /// Create a text chunk, checking the keyword and that the text is NUL-free Latin-1
+    pub fn new(keyword: &str, text: &str) -> PngResult<Self> {
+        validate_keyword(keyword)?;
+        validate_text(text)?;
+        latin1_encode(text)?;
+        Ok(TextChunk {
+            keyword: keyword.to_string(),
+            text: text.to_string(),
+            compressed: false,
+        })
+    }
+
+    pub fn keyword(&self) -> &str {
+        &self.keyword
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Returns whether the text is stored compressed, in a zTXt chunk
+    pub fn is_compressed(&self) -> bool {
+        self.compressed
+    }
+
+    pub fn set_compressed(&mut self, compressed: bool) {
+        self.compressed = compressed
+    }
+
+    /// The chunk type this text is stored under
+    pub fn chunk_type(&self) -> &'static str {
+        if self.compressed {
+            Self::COMPRESSED_CHUNK_TYPE
+        } else {
+            Self::CHUNK_TYPE
+        }
+    }
+
+    /// Encode as a tEXt chunk, or a zTXt chunk if compressed
+    pub fn to_chunk(&self) -> PngResult<Chunk> {
+        let mut data = latin1_encode(&self.keyword)?;
+        data.push(0);
+        let text = latin1_encode(&self.text)?;
+        if self.compressed {
+            data.push(COMPRESSION_METHOD_DEFLATE);
+            data.extend(Compression::Zlib.compress(&text, None)?);
+        } else {
+            data.extend(text);
+        }
+        Ok(Chunk::new(ChunkType::from_str(self.chunk_type())?, data))
+    }
+}
+
+impl TryFrom<&Chunk> for TextChunk {
+    type Error = PngError;
+
+    /// Decode a tEXt or zTXt chunk
+    fn try_from(chunk: &Chunk) -> PngResult<Self> {
+        let compressed = match chunk.chunk_type().to_string().as_str() {
+            Self::CHUNK_TYPE => false,
+            Self::COMPRESSED_CHUNK_TYPE => true,
+            other => return Err(format!("Expected a tEXt or zTXt chunk, found {}", other).into()),
+        };
+        let (keyword, text) = split_keyword(chunk.data())?;
+        let text = if compressed {
+            match text.split_first() {
+                Some((&COMPRESSION_METHOD_DEFLATE, compressed_text)) => {
+                    latin1_decode(&Compression::Zlib.decompress(compressed_text)?)
+                }
+                Some((method, _)) => {
+                    return Err(format!("Unknown zTXt compression method {}", method).into())
+                }
+                None => return Err("zTXt chunk has no compression method".into()),
+            }
+        } else {
+            latin1_decode(text)
+        };
+        Ok(TextChunk {
+            keyword,
+            text,
+            compressed,
+        })
+    }
+}
+
+/// Check an iTXt language tag: empty, or hyphen-separated words of 1 to 8 ASCII letters and
+/// digits such as `en` or `x-klingon`
+pub fn validate_language_tag(tag: &str) -> PngResult<()> {
+    if tag.is_empty() {
+        return Ok(());
+    }
+    let valid = tag.split('-').all(|word| {
+        (1..=8).contains(&word.len()) && word.chars().all(|c| c.is_ascii_alphanumeric())
+    });
+    if !valid {
+        return Err(format!("Invalid language tag {:?}", tag).into());
+    }
+    Ok(())
+}
+
+/// Split off the next NUL-terminated field
+fn split_field<'a>(data: &'a [u8], name: &str) -> PngResult<(&'a [u8], &'a [u8])> {
+    let end = data
+        .iter()
+        .position(|b| *b == 0)
+        .ok_or_else(|| format!("iTXt chunk has no {} terminator", name))?;
+    Ok((&data[..end], &data[end + 1..]))
+}
+
+/// Decode a UTF-8 field, naming it in the error
+fn decode_utf8(bytes: &[u8], name: &str) -> PngResult<String> {
+    String::from_utf8(bytes.to_vec())
+        .map_err(|e| format!("iTXt {} is not UTF-8: {}", name, e).into())
+}
+
+/// An international text chunk (iTXt): UTF-8 text with an optional language tag and a
+/// translation of the keyword, optionally zlib compressed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ItxtChunk {
+    keyword: String,
+    language_tag: String,
+    translated_keyword: String,
+    text: String,
+    compressed: bool,
+}
+
+impl ItxtChunk {
+    pub const CHUNK_TYPE: &'static str = "iTXt";
+
+    /// Create an uncompressed iTXt chunk with no language tag or translated keyword, checking
+    /// the keyword and that the text is NUL-free
+    pub fn new(keyword: &str, text: &str) -> PngResult<Self> {
+        validate_keyword(keyword)?;
+        validate_text(text)?;
+        Ok(ItxtChunk {
+            keyword: keyword.to_string(),
+            language_tag: String::new(),
+            translated_keyword: String::new(),
+            text: text.to_string(),
+            compressed: false,
+        })
+    }
+
+    pub fn keyword(&self) -> &str {
+        &self.keyword
+    }
+
+    pub fn language_tag(&self) -> &str {
+        &self.language_tag
+    }
+
+    pub fn set_language_tag(&mut self, tag: &str) -> PngResult<()> {
+        validate_language_tag(tag)?;
+        self.language_tag = tag.to_string();
+        Ok(())
+    }
+
+    pub fn translated_keyword(&self) -> &str {
+        &self.translated_keyword
+    }
+
+    pub fn set_translated_keyword(&mut self, translated_keyword: &str) -> PngResult<()> {
+        if translated_keyword.contains('\0') {
+            return Err("Translated keyword must not contain NUL characters".into());
+        }
+        self.translated_keyword = translated_keyword.to_string();
+        Ok(())
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn is_compressed(&self) -> bool {
+        self.compressed
+    }
+
+    pub fn set_compressed(&mut self, compressed: bool) {
+        self.compressed = compressed
+    }
+
+    /// Encode as an iTXt chunk
+    pub fn to_chunk(&self) -> PngResult<Chunk> {
+        let mut data = latin1_encode(&self.keyword)?;
+        data.extend_from_slice(&[0, self.compressed as u8, COMPRESSION_METHOD_DEFLATE]);
+        data.extend_from_slice(self.language_tag.as_bytes());
+        data.push(0);
+        data.extend_from_slice(self.translated_keyword.as_bytes());
+        data.push(0);
+        if self.compressed {
+            data.extend(Compression::Zlib.compress(self.text.as_bytes(), None)?);
+        } else {
+            data.extend_from_slice(self.text.as_bytes());
+        }
+        Ok(Chunk::new(ChunkType::from_str(Self::CHUNK_TYPE)?, data))
+    }
+}
+
+impl TryFrom<&Chunk> for ItxtChunk {
+    type Error = PngError;
+
+    /// Decode an iTXt chunk, checking the compression fields and that the text is UTF-8
+    fn try_from(chunk: &Chunk) -> PngResult<Self> {
+        if chunk.chunk_type().to_string() != Self::CHUNK_TYPE {
+            return Err(format!("Expected an iTXt chunk, found {}", chunk.chunk_type()).into());
+        }
+        let (keyword, rest) = split_keyword(chunk.data())?;
+        let (compressed, rest) = match rest {
+            [0, _, rest @ ..] => (false, rest),
+            [1, COMPRESSION_METHOD_DEFLATE, rest @ ..] => (true, rest),
+            [1, method, ..] => {
+                return Err(format!("Unknown iTXt compression method {}", method).into())
+            }
+            [flag, _, ..] => return Err(format!("Invalid iTXt compression flag {}", flag).into()),
+            _ => return Err("iTXt chunk is missing its compression fields".into()),
+        };
+        let (language_tag, rest) = split_field(rest, "language tag")?;
+        let language_tag = decode_utf8(language_tag, "language tag")?;
+        validate_language_tag(&language_tag)?;
+        let (translated_keyword, text) = split_field(rest, "translated keyword")?;
+        let translated_keyword = decode_utf8(translated_keyword, "translated keyword")?;
+        let text = if compressed {
+            decode_utf8(&Compression::Zlib.decompress(text)?, "text")?
+        } else {
+            decode_utf8(text, "text")?
+        };
+        Ok(ItxtChunk {
+            keyword,
+            language_tag,
+            translated_keyword,
+            text,
+            compressed,
+        })
+    }
+}
+
+/// Any of the textual chunks: tEXt, zTXt or iTXt
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TextualChunk {
+    Latin1(TextChunk),
+    International(ItxtChunk),
+}
+
+impl TextualChunk {
+    /// The chunk types which hold text
+    pub const CHUNK_TYPES: [&'static str; 3] = [
+        TextChunk::CHUNK_TYPE,
+        TextChunk::COMPRESSED_CHUNK_TYPE,
+        ItxtChunk::CHUNK_TYPE,
+    ];
+
+    pub fn keyword(&self) -> &str {
+        match self {
+            TextualChunk::Latin1(text) => text.keyword(),
+            TextualChunk::International(text) => text.keyword(),
+        }
+    }
+
+    /// The language tag, which is always empty for Latin-1 text
+    pub fn language_tag(&self) -> &str {
+        match self {
+            TextualChunk::Latin1(_) => "",
+            TextualChunk::International(text) => text.language_tag(),
+        }
+    }
+
+    pub fn text(&self) -> &str {
+        match self {
+            TextualChunk::Latin1(text) => text.text(),
+            TextualChunk::International(text) => text.text(),
+        }
+    }
+
+    pub fn to_chunk(&self) -> PngResult<Chunk> {
+        match self {
+            TextualChunk::Latin1(text) => text.to_chunk(),
+            TextualChunk::International(text) => text.to_chunk(),
+        }
+    }
+}
+
+impl From<TextChunk> for TextualChunk {
+    fn from(text: TextChunk) -> Self {
+        TextualChunk::Latin1(text)
+    }
+}
+
+impl From<ItxtChunk> for TextualChunk {
+    fn from(text: ItxtChunk) -> Self {
+        TextualChunk::International(text)
+    }
+}
+
+impl TryFrom<&Chunk> for TextualChunk {
+    type Error = PngError;
+
+    fn try_from(chunk: &Chunk) -> PngResult<Self> {
+        if chunk.chunk_type().to_string() == ItxtChunk::CHUNK_TYPE {
+            Ok(ItxtChunk::try_from(chunk)?.into())
+        } else {
+            Ok(TextChunk::try_from(chunk)?.into())
+        }
+    }
+}
+
+/// Decode every tEXt, zTXt and iTXt chunk in the PNG, in file order. Chunks which don't
+/// decode, e.g. because another tool misused the chunk type, are returned as errors so
+/// callers can skip them.
+pub fn text_chunks(png: &Png) -> impl Iterator<Item = PngResult<TextualChunk>> + '_ {
+    png.chunks()
+        .iter()
+        .filter(|chunk| {
+            TextualChunk::CHUNK_TYPES.contains(&chunk.chunk_type().to_string().as_str())
+        })
+        .map(TextualChunk::try_from)
+}
+
+/// Set the text stored under a keyword and language tag. The first existing textual chunk
+/// with the same keyword and language is replaced in place and any others are removed;
+/// otherwise a new chunk is appended. Latin-1 text counts as having no language tag.
+pub fn set_text(png: &mut Png, text: impl Into<TextualChunk>) -> PngResult<()> {
+    let text = text.into();
+    let matches = |chunk: &Chunk| {
+        TextualChunk::CHUNK_TYPES.contains(&chunk.chunk_type().to_string().as_str())
+            && TextualChunk::try_from(chunk).is_ok_and(|existing| {
+                existing.keyword() == text.keyword()
+                    && existing.language_tag() == text.language_tag()
+            })
+    };
+    let chunk = text.to_chunk()?;
+    match png.chunks().iter().position(matches) {
+        Some(index) => {
+            png.chunks_mut()[index] = chunk;
+            let mut seen = false;
+            png.retain_chunks(|chunk| {
+                if !matches(chunk) {
+                    return true;
+                }
+                let first = !seen;
+                seen = true;
+                first
+            });
+        }
+        None => png.append_chunk(chunk),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testing_png() -> Png {
+        let chunks = vec![
+            Chunk::new(ChunkType::from_str("IHDR").unwrap(), vec![]),
+            TextChunk::new("Title", "Dice").unwrap().to_chunk().unwrap(),
+            Chunk::new(ChunkType::from_str("IDAT").unwrap(), vec![]),
+            TextChunk::new("Title", "Old").unwrap().to_chunk().unwrap(),
+            Chunk::new(ChunkType::from_str("IEND").unwrap(), vec![]),
+        ];
+        Png::from_chunks(chunks)
+    }
+
+    #[test]
+    fn test_keyword_validation() {
+        assert!(validate_keyword("Title").is_ok());
+        assert!(validate_keyword("Création time").is_ok());
+        assert!(validate_keyword(&"k".repeat(79)).is_ok());
+        for keyword in ["", " Title", "Title ", "Ti  tle", "Ti\ttle", "名前"] {
+            assert!(validate_keyword(keyword).is_err(), "{:?}", keyword);
+        }
+        assert!(validate_keyword(&"k".repeat(80)).is_err());
+    }
+
+    #[test]
+    fn test_text_chunk_round_trip() {
+        let text = TextChunk::new("Comment", "café").unwrap();
+        let chunk = text.to_chunk().unwrap();
+        assert_eq!(chunk.data(), b"Comment\0caf\xe9");
+        assert_eq!(TextChunk::try_from(&chunk).unwrap(), text);
+
+        assert!(TextChunk::new("Comment", "nul\0").is_err());
+        assert!(TextChunk::new("Comment", "€").is_err());
+        assert!(ItxtChunk::new("Comment", "nul\0").is_err());
+        assert!(is_undefined_control(b'\r'));
+        assert!(!is_undefined_control(b'\n'));
+        assert!(is_undefined_control(0x85));
+        assert!(!is_undefined_control(0xe9));
+    }
+
+    #[test]
+    fn test_set_text() {
+        let mut png = testing_png();
+        set_text(&mut png, TextChunk::new("Title", "New").unwrap()).unwrap();
+        set_text(&mut png, TextChunk::new("Author", "Me").unwrap()).unwrap();
+        let texts: Vec<(String, String)> = text_chunks(&png)
+            .map(|text| text.unwrap())
+            .map(|text| (text.keyword().to_string(), text.text().to_string()))
+            .collect();
+        assert_eq!(
+            texts,
+            vec![
+                ("Title".to_string(), "New".to_string()),
+                ("Author".to_string(), "Me".to_string())
+            ]
+        );
+        // The replacement stays where the original was, before IDAT
+        assert_eq!(png.chunks()[1].chunk_type().to_string(), "tEXt");
+        assert_eq!(
+            png.chunks().last().unwrap().chunk_type().to_string(),
+            "IEND"
+        );
+    }
+
+    #[test]
+    fn test_compressed_text_round_trip() {
+        let mut text = TextChunk::new("Comment", &"repetitive ".repeat(100)).unwrap();
+        text.set_compressed(true);
+        let chunk = text.to_chunk().unwrap();
+        assert_eq!(chunk.chunk_type().to_string(), "zTXt");
+        assert!(chunk.data().starts_with(b"Comment\0\0"));
+        assert!(chunk.data().len() < 100);
+        assert_eq!(TextChunk::try_from(&chunk).unwrap(), text);
+
+        // Replacing the text under a keyword works across tEXt and zTXt
+        let mut png = testing_png();
+        text.keyword = "Title".to_string();
+        set_text(&mut png, text.clone()).unwrap();
+        let texts: Vec<TextualChunk> = text_chunks(&png).map(|text| text.unwrap()).collect();
+        assert_eq!(texts, vec![text.into()]);
+    }
+
+    #[test]
+    fn test_unknown_compression_method() {
+        let mut data = b"Comment\0\x01".to_vec();
+        data.extend(Compression::Zlib.compress(b"text", None).unwrap());
+        let chunk = Chunk::new(ChunkType::from_str("zTXt").unwrap(), data);
+        assert!(TextChunk::try_from(&chunk).is_err());
+    }
+
+    #[test]
+    fn test_itxt_round_trip() {
+        for compressed in [false, true] {
+            let mut text = ItxtChunk::new("Title", "Würfel 🎲").unwrap();
+            text.set_language_tag("de-DE").unwrap();
+            text.set_translated_keyword("Titel").unwrap();
+            text.set_compressed(compressed);
+            let chunk = text.to_chunk().unwrap();
+            assert_eq!(
+                chunk.data().starts_with(b"Title\0\0\0de-DE\0Titel\0"),
+                !compressed
+            );
+            assert_eq!(ItxtChunk::try_from(&chunk).unwrap(), text);
+        }
+        assert!(ItxtChunk::new("Title", "")
+            .unwrap()
+            .set_language_tag("de_DE")
+            .is_err());
+    }
+
+    #[test]
+    fn test_invalid_itxt() {
+        let itxt = |data: &[u8]| Chunk::new(ChunkType::from_str("iTXt").unwrap(), data.to_vec());
+        for data in [
+            &b"Title\0\x02\0\0\0text"[..], // compression flag
+            b"Title\0\x01\x01\0\0text",    // compression method
+            b"Title\0\0\0\0\0\xff",        // text isn't UTF-8
+            b"Title\0\0\0en\0",            // no translated keyword terminator
+            b"Title\0\0",
+        ] {
+            assert!(ItxtChunk::try_from(&itxt(data)).is_err(), "{:?}", data);
+        }
+    }
+
+    #[test]
+    fn test_set_text_per_language() {
+        let mut png = testing_png();
+        let mut german = ItxtChunk::new("Title", "Würfel").unwrap();
+        german.set_language_tag("de").unwrap();
+        set_text(&mut png, german.clone()).unwrap();
+        german.set_compressed(true);
+        set_text(&mut png, german).unwrap();
+        let texts: Vec<(String, String)> = text_chunks(&png)
+            .map(|text| text.unwrap())
+            .map(|text| (text.language_tag().to_string(), text.text().to_string()))
+            .collect();
+        assert_eq!(
+            texts,
+            vec![
+                ("".to_string(), "Dice".to_string()),
+                ("".to_string(), "Old".to_string()),
+                ("de".to_string(), "Würfel".to_string())
+            ]
+        );
+    }
+}
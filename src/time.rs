@@ -0,0 +1,236 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+use crate::{PngError, PngResult};
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The time of the last modification of the image, as stored in a tIME chunk. Always UTC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TimeChunk {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    /// Up to 60 to allow for leap seconds
+    pub second: u8,
+}
+
+impl TimeChunk {
+    pub const CHUNK_TYPE: &'static str = "tIME";
+
+    /// The length of the tIME chunk data
+    pub const LEN: usize = 7;
+
+    /// The current time
+    pub fn now() -> PngResult<Self> {
+        let seconds = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        Self::from_unix_seconds(seconds)
+    }
+
+    /// Convert seconds since the Unix epoch to a calendar date and time
+    pub fn from_unix_seconds(seconds: u64) -> PngResult<Self> {
+        let days = (seconds / 86400) as i64;
+        let time_of_day = seconds % 86400;
+        // Howard Hinnant's days-to-civil algorithm, with eras of 400 years starting in March
+        let z = days + 719468;
+        let era = z.div_euclid(146097);
+        let day_of_era = z.rem_euclid(146097);
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let shifted_month = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+        let month = if shifted_month < 10 {
+            shifted_month + 3
+        } else {
+            shifted_month - 9
+        };
+        let year = year_of_era + era * 400 + (month <= 2) as i64;
+        Ok(TimeChunk {
+            year: u16::try_from(year).map_err(|_| format!("Year {} is out of range", year))?,
+            month: month as u8,
+            day: day as u8,
+            hour: (time_of_day / 3600) as u8,
+            minute: (time_of_day / 60 % 60) as u8,
+            second: (time_of_day % 60) as u8,
+        })
+    }
+
+    /// Check that each field is within the range the spec allows
+    fn check(&self) -> PngResult<()> {
+        let fields = [
+            ("month", self.month, 1, 12),
+            ("day", self.day, 1, 31),
+            ("hour", self.hour, 0, 23),
+            ("minute", self.minute, 0, 59),
+            ("second", self.second, 0, 60),
+        ];
+        for (name, value, min, max) in fields {
+            if !(min..=max).contains(&value) {
+                return Err(format!("The {} {} is out of range", name, value).into());
+            }
+        }
+        Ok(())
+    }
+
+    pub fn as_bytes(&self) -> [u8; Self::LEN] {
+        let [year_high, year_low] = self.year.to_be_bytes();
+        [
+            year_high,
+            year_low,
+            self.month,
+            self.day,
+            self.hour,
+            self.minute,
+            self.second,
+        ]
+    }
+
+    pub fn to_chunk(&self) -> PngResult<Chunk> {
+        Ok(Chunk::new(
+            ChunkType::from_str(Self::CHUNK_TYPE)?,
+            self.as_bytes().to_vec(),
+        ))
+    }
+}
+
+impl TryFrom<&[u8]> for TimeChunk {
+    type Error = PngError;
+
+    /// Decode tIME chunk data
+    fn try_from(value: &[u8]) -> PngResult<Self> {
+        let [year_high, year_low, month, day, hour, minute, second] =
+            <[u8; Self::LEN]>::try_from(value).map_err(|_| {
+                format!(
+                    "tIME data is {} bytes long but should be {}",
+                    value.len(),
+                    Self::LEN
+                )
+            })?;
+        let time = TimeChunk {
+            year: u16::from_be_bytes([year_high, year_low]),
+            month,
+            day,
+            hour,
+            minute,
+            second,
+        };
+        time.check()?;
+        Ok(time)
+    }
+}
+
+impl FromStr for TimeChunk {
+    type Err = PngError;
+
+    /// Parse a UTC time written as `YYYY-MM-DDTHH:MM:SS`, optionally with a space instead of
+    /// the `T` and a trailing `Z`
+    fn from_str(s: &str) -> PngResult<Self> {
+        let invalid = || format!("Invalid time {:?}; expected YYYY-MM-DDTHH:MM:SS", s);
+        let s = s.strip_suffix('Z').unwrap_or(s);
+        let (date, time) = s.split_once(['T', ' ']).ok_or_else(invalid)?;
+        let mut date = date.split('-');
+        let mut time = time.split(':');
+        let next = |parts: &mut std::str::Split<'_, char>| -> PngResult<u16> {
+            let part = parts.next().ok_or_else(invalid)?;
+            Ok(part.parse().map_err(|_| invalid())?)
+        };
+        let year = next(&mut date)?;
+        let (month, day) = (next(&mut date)?, next(&mut date)?);
+        let (hour, minute, second) = (next(&mut time)?, next(&mut time)?, next(&mut time)?);
+        if date.next().is_some() || time.next().is_some() {
+            return Err(invalid().into());
+        }
+        let narrow = |value: u16| u8::try_from(value).map_err(|_| invalid());
+        let parsed = TimeChunk {
+            year,
+            month: narrow(month)?,
+            day: narrow(day)?,
+            hour: narrow(hour)?,
+            minute: narrow(minute)?,
+            second: narrow(second)?,
+        };
+        parsed.check()?;
+        Ok(parsed)
+    }
+}
+
+impl Display for TimeChunk {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+/// Decode the tIME chunk of the PNG, if it has one
+pub fn get_time(png: &Png) -> PngResult<Option<TimeChunk>> {
+    png.chunk_by_type(TimeChunk::CHUNK_TYPE)
+        .map(|chunk| TimeChunk::try_from(chunk.data()))
+        .transpose()
+}
+
+/// Store the modification time, replacing any existing tIME chunk
+pub fn set_time(png: &mut Png, time: TimeChunk) -> PngResult<()> {
+    let chunk = time.to_chunk()?;
+    png.retain_chunks(|existing| existing.chunk_type().to_string() != TimeChunk::CHUNK_TYPE);
+    png.append_chunk(chunk);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_unix_seconds() {
+        let epoch = TimeChunk::from_unix_seconds(0).unwrap();
+        assert_eq!(epoch.to_string(), "1970-01-01T00:00:00Z");
+        // A leap day, and the last second of the century
+        let leap_day = TimeChunk::from_unix_seconds(1709210096).unwrap();
+        assert_eq!(leap_day.to_string(), "2024-02-29T12:34:56Z");
+        let new_years_eve = TimeChunk::from_unix_seconds(4102444799).unwrap();
+        assert_eq!(new_years_eve.to_string(), "2099-12-31T23:59:59Z");
+    }
+
+    #[test]
+    fn test_time_round_trip() {
+        let time = TimeChunk::from_str("2024-02-29T12:34:56Z").unwrap();
+        assert_eq!(time, TimeChunk::from_str("2024-02-29 12:34:56").unwrap());
+        assert_eq!(time.as_bytes(), [7, 232, 2, 29, 12, 34, 56]);
+        assert_eq!(TimeChunk::try_from(&time.as_bytes()[..]).unwrap(), time);
+
+        for invalid in [
+            "2024-02-29",
+            "2024-13-01T00:00:00",
+            "2024-01-01T24:00:00",
+            "2024-1-1T0:0:0:0",
+        ] {
+            assert!(TimeChunk::from_str(invalid).is_err(), "{}", invalid);
+        }
+        assert!(TimeChunk::try_from(&[7, 232, 0, 1, 0, 0, 0][..]).is_err());
+        assert!(TimeChunk::try_from(&[7, 232, 1, 1, 0, 0][..]).is_err());
+    }
+
+    #[test]
+    fn test_set_time() {
+        let chunks = ["IHDR", "IDAT", "IEND"]
+            .iter()
+            .map(|t| Chunk::new(ChunkType::from_str(t).unwrap(), vec![]))
+            .collect();
+        let mut png = Png::from_chunks(chunks);
+        assert_eq!(get_time(&png).unwrap(), None);
+
+        let time = TimeChunk::from_unix_seconds(0).unwrap();
+        set_time(&mut png, TimeChunk::now().unwrap()).unwrap();
+        set_time(&mut png, time).unwrap();
+        assert_eq!(get_time(&png).unwrap(), Some(time));
+        assert_eq!(png.chunks_by_type("tIME").count(), 1);
+        assert_eq!(png.chunks()[2].chunk_type().to_string(), "tIME");
+    }
+}
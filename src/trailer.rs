@@ -0,0 +1,111 @@
+use crate::error::{error, ErrorKind};
+use crate::png::Png;
+use crate::PngResult;
+use std::ops::Range;
+
+/// Marks the start of a payload appended after the IEND chunk
+pub const TRAILER_MAGIC: [u8; 4] = *b"pmiT";
+
+/// Magic, payload length and the CRC-32 of the payload
+const HEADER_LEN: usize = TRAILER_MAGIC.len() + 4 + 4;
+
+/// The payload with the header `find` looks for in front of it
+fn frame(payload: &[u8]) -> PngResult<Vec<u8>> {
+    let len = u32::try_from(payload.len()).map_err(|_| "Payload is too large for a trailer")?;
+    let mut bytes = TRAILER_MAGIC.to_vec();
+    bytes.extend_from_slice(&len.to_be_bytes());
+    bytes.extend_from_slice(&crc32fast::hash(payload).to_be_bytes());
+    bytes.extend_from_slice(payload);
+    Ok(bytes)
+}
+
+/// Where the payload of the trailer in `data` lies. Other data may come before it, so every
+/// occurrence of the magic with a length which fits is a candidate. Only the best of them is
+/// checksummed: the one ending with the data, as `embed` leaves it, or else the first.
+pub fn find(data: &[u8]) -> Option<Range<usize>> {
+    let candidates: Vec<(Range<usize>, u32)> = (0..data.len().saturating_sub(HEADER_LEN - 1))
+        .filter_map(|start| {
+            let header = &data[start..start + HEADER_LEN];
+            if header[..4] != TRAILER_MAGIC {
+                return None;
+            }
+            let len = u32::from_be_bytes([header[4], header[5], header[6], header[7]]) as usize;
+            let crc = u32::from_be_bytes([header[8], header[9], header[10], header[11]]);
+            let payload = start + HEADER_LEN..(start + HEADER_LEN).checked_add(len)?;
+            (payload.end <= data.len()).then_some((payload, crc))
+        })
+        .collect();
+    let (payload, crc) = candidates
+        .iter()
+        .find(|(payload, _)| payload.end == data.len())
+        .or(candidates.first())?;
+    (crc32fast::hash(&data[payload.clone()]) == *crc).then(|| payload.clone())
+}
+
+/// Append the payload after the IEND chunk, following any data already there. Decoders stop
+/// at IEND so the image is untouched, but anything which rewrites the file may drop it.
+pub fn embed(png: &mut Png, payload: &[u8]) -> PngResult<()> {
+    let mut trailing_data = png.take_trailing_data();
+    if find(&trailing_data).is_some() {
+        png.set_trailing_data(trailing_data);
+        return Err("The PNG already has a payload after IEND".into());
+    }
+    trailing_data.extend_from_slice(&frame(payload)?);
+    png.set_trailing_data(trailing_data);
+    Ok(())
+}
+
+/// Recover a payload appended by `embed` from the data after IEND
+pub fn extract(trailing_data: &[u8]) -> PngResult<Vec<u8>> {
+    find(trailing_data)
+        .map(|payload| trailing_data[payload].to_vec())
+        .ok_or_else(|| error(ErrorKind::Parse, "No payload found after IEND"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::minimal_png;
+
+    #[test]
+    fn test_round_trip() {
+        let mut png = minimal_png();
+        png.set_trailing_data(b"someone else's data pmiT".to_vec());
+        embed(&mut png, b"hidden").unwrap();
+
+        let png = Png::try_from(png.as_bytes().as_slice()).unwrap();
+        assert!(png.trailing_data().starts_with(b"someone else's data"));
+        assert_eq!(extract(png.trailing_data()).unwrap(), b"hidden");
+    }
+
+    #[test]
+    fn test_embed_twice() {
+        let mut png = minimal_png();
+        embed(&mut png, b"first").unwrap();
+        assert!(embed(&mut png, b"second").is_err());
+        assert_eq!(extract(png.trailing_data()).unwrap(), b"first");
+    }
+
+    #[test]
+    fn test_damaged_trailer() {
+        let mut bytes = frame(b"hidden").unwrap();
+        assert_eq!(find(&bytes), Some(HEADER_LEN..HEADER_LEN + 6));
+        bytes.pop();
+        assert_eq!(find(&bytes), None);
+        bytes.push(b'N');
+        assert!(extract(&bytes).is_err());
+        assert!(extract(b"").is_err());
+    }
+
+    #[test]
+    fn test_find_prefers_trailer_at_end() {
+        // A stray magic whose length happens to fit doesn't hide the real trailer
+        let mut bytes = b"pmiT\0\0\0\x01\0\0\0\0x".to_vec();
+        let start = bytes.len() + HEADER_LEN;
+        bytes.extend(frame(b"hidden").unwrap());
+        assert_eq!(find(&bytes), Some(start..start + 6));
+        // Without one at the end, the first candidate is the only one checked
+        bytes.push(0);
+        assert_eq!(find(&bytes), None);
+    }
+}
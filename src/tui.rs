@@ -0,0 +1,393 @@
+use pngmi::apng::{AnimationControl, FrameControl};
+use pngmi::chunk::Chunk;
+use pngmi::codec::{ChunkRegistry, DecodeContext, TypedChunk};
+use pngmi::exif::{self, EXIF_CHUNK_TYPE};
+use pngmi::hexdump::{self, BYTES_PER_LINE};
+use pngmi::output::{self, WriteOptions};
+use pngmi::palette::Transparency;
+use pngmi::png::Png;
+use pngmi::PngResult;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Style, Stylize};
+use ratatui::widgets::{Block, List, ListState, Paragraph, Wrap};
+use ratatui::{DefaultTerminal, Frame};
+use std::fs;
+use std::io::{self, IsTerminal};
+use std::path::Path;
+
+/// The keys shown at the bottom of the screen when there is nothing else to say
+const HELP: &str = "↑↓ select  PgUp/PgDn scroll  d delete  x export  w save  q quit";
+
+/// What keys currently do
+enum Mode {
+    Browse,
+    /// Editing the path to export the selected chunk's data to
+    Export(String),
+    /// Quitting was asked for with unsaved changes, and has to be asked for again
+    ConfirmQuit,
+}
+
+/// A chunk browser over one file, which edits a copy in memory until asked to save
+struct App<'a> {
+    path: &'a Path,
+    png: Png,
+    options: WriteOptions<'a>,
+    list: ListState,
+    /// How many lines the hex dump is scrolled down
+    scroll: usize,
+    /// How many lines of the hex dump were visible when it was last drawn
+    page: usize,
+    mode: Mode,
+    status: String,
+    modified: bool,
+}
+
+/// Browse the chunks of a PNG in the terminal until the user quits, saving edits to `path`
+/// when asked to
+pub fn run(path: &Path, png: Png, options: WriteOptions) -> PngResult<()> {
+    if !io::stdin().is_terminal() || !io::stdout().is_terminal() {
+        return Err("The chunk browser needs a terminal".into());
+    }
+    let mut app = App {
+        path,
+        png,
+        options,
+        list: ListState::default().with_selected(Some(0)),
+        scroll: 0,
+        page: 0,
+        mode: Mode::Browse,
+        status: String::new(),
+        modified: false,
+    };
+    let mut terminal = ratatui::init();
+    let result = app.run(&mut terminal);
+    ratatui::restore();
+    result
+}
+
+impl App<'_> {
+    fn run(&mut self, terminal: &mut DefaultTerminal) -> PngResult<()> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            self.status.clear();
+            let mode = std::mem::replace(&mut self.mode, Mode::Browse);
+            match mode {
+                Mode::Export(mut target) => match key.code {
+                    KeyCode::Enter => self.export(Path::new(&target)),
+                    KeyCode::Esc => {}
+                    KeyCode::Backspace => {
+                        target.pop();
+                        self.mode = Mode::Export(target);
+                    }
+                    KeyCode::Char(c) => {
+                        target.push(c);
+                        self.mode = Mode::Export(target);
+                    }
+                    _ => self.mode = Mode::Export(target),
+                },
+                Mode::ConfirmQuit if matches!(key.code, KeyCode::Char('q')) => return Ok(()),
+                Mode::Browse | Mode::ConfirmQuit => {
+                    if self.browse(key.code) {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+    }
+
+    /// Act on a key pressed while browsing, returning whether to quit
+    fn browse(&mut self, code: KeyCode) -> bool {
+        let last = self.png.chunks().len().saturating_sub(1);
+        let selected = self.selected();
+        match code {
+            KeyCode::Char('q') | KeyCode::Esc if self.modified => {
+                self.status =
+                    "Unsaved changes: press q again to quit without saving, or w to save".into();
+                self.mode = Mode::ConfirmQuit;
+            }
+            KeyCode::Char('q') | KeyCode::Esc => return true,
+            KeyCode::Up | KeyCode::Char('k') => self.select(selected.saturating_sub(1)),
+            KeyCode::Down | KeyCode::Char('j') => self.select((selected + 1).min(last)),
+            KeyCode::Home | KeyCode::Char('g') => self.select(0),
+            KeyCode::End | KeyCode::Char('G') => self.select(last),
+            KeyCode::PageDown | KeyCode::Char(' ') => self.scroll += self.page.max(1),
+            KeyCode::PageUp | KeyCode::Char('b') => {
+                self.scroll = self.scroll.saturating_sub(self.page.max(1))
+            }
+            KeyCode::Char('d') => self.delete(),
+            KeyCode::Char('x') => {
+                if let Some(chunk) = self.chunk() {
+                    self.mode = Mode::Export(self.export_name(selected, chunk));
+                }
+            }
+            KeyCode::Char('w') => self.save(),
+            _ => {}
+        }
+        false
+    }
+
+    fn selected(&self) -> usize {
+        self.list.selected().unwrap_or(0)
+    }
+
+    fn select(&mut self, index: usize) {
+        self.list.select(Some(index));
+        self.scroll = 0;
+    }
+
+    fn chunk(&self) -> Option<&Chunk> {
+        self.png.chunks().get(self.selected())
+    }
+
+    /// The default file name to export a chunk to, e.g. `image.3.tEXt`
+    fn export_name(&self, index: usize, chunk: &Chunk) -> String {
+        let stem = self.path.file_stem().unwrap_or_default().to_string_lossy();
+        format!("{}.{}.{}", stem, index, chunk.chunk_type())
+    }
+
+    fn delete(&mut self) {
+        let index = self.selected();
+        let Some(chunk) = self.chunk() else {
+            return;
+        };
+        if chunk.chunk_type().is_critical() {
+            self.status = format!(
+                "{} is a critical chunk and can't be deleted",
+                chunk.chunk_type()
+            );
+            return;
+        }
+        let removed = self.png.remove_chunk_at(index);
+        self.status = format!("Deleted {} chunk {}", removed.chunk_type(), index);
+        self.modified = true;
+        self.select(index.min(self.png.chunks().len().saturating_sub(1)));
+    }
+
+    fn export(&mut self, target: &Path) {
+        let Some(chunk) = self.chunk() else {
+            return;
+        };
+        self.status = match fs::write(target, chunk.data()) {
+            Ok(()) => format!("Wrote {} bytes to {}", chunk.length(), target.display()),
+            Err(e) => format!("Couldn't write {}: {}", target.display(), e),
+        };
+    }
+
+    fn save(&mut self) {
+        self.status = match output::write_atomic(self.path, &self.png.as_bytes(), &self.options) {
+            Ok(()) => {
+                self.modified = false;
+                format!("Saved {}", self.path.display())
+            }
+            Err(e) => format!("Couldn't save {}: {}", self.path.display(), e),
+        };
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [main, status] =
+            Layout::vertical([Constraint::Min(1), Constraint::Length(1)]).areas(frame.area());
+        let [list, detail] =
+            Layout::horizontal([Constraint::Length(36), Constraint::Min(1)]).areas(main);
+        self.draw_list(frame, list);
+        if let Some(chunk) = self.chunk() {
+            let decoded = describe(&self.png, self.selected());
+            let height = (decoded.len() as u16 + 2).min(detail.height / 2);
+            let [decoded_area, hex_area] =
+                Layout::vertical([Constraint::Length(height), Constraint::Min(1)]).areas(detail);
+            let title = format!(" {} ", flags(chunk));
+            frame.render_widget(
+                Paragraph::new(decoded.join("\n"))
+                    .block(Block::bordered().title(title))
+                    .wrap(Wrap { trim: false }),
+                decoded_area,
+            );
+            self.draw_hex(frame, hex_area);
+        }
+        let line = match &self.mode {
+            Mode::Export(target) => format!("Export to: {}█", target),
+            _ if !self.status.is_empty() => self.status.clone(),
+            _ => HELP.to_string(),
+        };
+        frame.render_widget(Paragraph::new(line).reversed(), status);
+    }
+
+    fn draw_list(&mut self, frame: &mut Frame, area: Rect) {
+        let rows: Vec<String> = self
+            .png
+            .chunks()
+            .iter()
+            .enumerate()
+            .map(|(index, chunk)| {
+                let chunk_type = chunk.chunk_type();
+                format!(
+                    "{:>3} {} {:>10} {}{}{}",
+                    index,
+                    chunk_type,
+                    chunk.length(),
+                    if chunk_type.is_critical() { 'C' } else { 'a' },
+                    if chunk_type.is_public() { 'P' } else { 'p' },
+                    if chunk_type.is_safe_to_copy() {
+                        'S'
+                    } else {
+                        's'
+                    },
+                )
+            })
+            .collect();
+        let modified = if self.modified { " [modified]" } else { "" };
+        let title = format!(" {}{} ", self.path.display(), modified);
+        let list = List::new(rows)
+            .block(Block::bordered().title(title))
+            .highlight_style(Style::new().reversed());
+        frame.render_stateful_widget(list, area, &mut self.list);
+    }
+
+    /// Dump only the lines of the selected chunk's data which fit, so huge IDAT chunks stay
+    /// quick to draw
+    fn draw_hex(&mut self, frame: &mut Frame, area: Rect) {
+        self.page = usize::from(area.height.saturating_sub(2));
+        let Some(data) = self.png.chunks().get(self.selected()).map(Chunk::data) else {
+            return;
+        };
+        let lines = data.len().div_ceil(BYTES_PER_LINE);
+        self.scroll = self.scroll.min(lines.saturating_sub(1));
+        let start = (self.scroll * BYTES_PER_LINE).min(data.len());
+        let end = (start + self.page * BYTES_PER_LINE).min(data.len());
+        let mut dump = vec![];
+        // Writing to a Vec can't fail
+        let _ = hexdump::write_hexdump(&mut dump, &data[start..end], start);
+        let title = format!(" {} bytes ", data.len());
+        frame.render_widget(
+            Paragraph::new(String::from_utf8_lossy(&dump).into_owned())
+                .block(Block::bordered().title(title)),
+            area,
+        );
+    }
+}
+
+/// The properties encoded in the case of a chunk's type, spelled out
+fn flags(chunk: &Chunk) -> String {
+    let chunk_type = chunk.chunk_type();
+    format!(
+        "{}: {}, {}, {}",
+        chunk_type,
+        if chunk_type.is_critical() {
+            "critical"
+        } else {
+            "ancillary"
+        },
+        if chunk_type.is_public() {
+            "public"
+        } else {
+            "private"
+        },
+        if chunk_type.is_safe_to_copy() {
+            "safe to copy"
+        } else {
+            "unsafe to copy"
+        },
+    )
+}
+
+/// Lines describing the chunk at `index`, with its contents decoded for the types there is a
+/// decoder for
+fn describe(png: &Png, index: usize) -> Vec<String> {
+    let chunk = &png.chunks()[index];
+    let offset: usize = png.header().len()
+        + png.chunks()[..index]
+            .iter()
+            .map(|chunk| chunk.overall_length() as usize)
+            .sum::<usize>();
+    let mut lines = vec![format!(
+        "Offset {:#010x}, {} bytes, CRC {:#010x}",
+        offset,
+        chunk.length(),
+        chunk.crc()
+    )];
+    let details = ChunkRegistry::default()
+        .decode(chunk, &DecodeContext::from_png(png))
+        .and_then(describe_typed);
+    match details {
+        Ok(details) => lines.extend(
+            details
+                .iter()
+                .flat_map(|line| line.lines())
+                .map(String::from),
+        ),
+        Err(e) => lines.push(format!("Can't decode: {}", e)),
+    }
+    lines
+}
+
+fn describe_typed(typed: TypedChunk) -> PngResult<Vec<String>> {
+    Ok(match typed {
+        TypedChunk::Ihdr(ihdr) => vec![
+            format!("{}x{} pixels", ihdr.width, ihdr.height),
+            format!("{}, bit depth {}", ihdr.color_type, ihdr.bit_depth),
+            format!(
+                "Interlaced: {}",
+                if ihdr.interlaced { "Adam7" } else { "no" }
+            ),
+        ],
+        TypedChunk::Palette(palette) => {
+            let entries = palette.entries().iter().enumerate();
+            let mut lines = vec![format!("{} entries", palette.entries().len())];
+            lines.extend(entries.map(|(index, rgb)| format!("{:>3}: {}", index, rgb)));
+            lines
+        }
+        TypedChunk::Transparency(Transparency::Gray(level)) => {
+            vec![format!("Transparent grey level {}", level)]
+        }
+        TypedChunk::Transparency(Transparency::Rgb(r, g, b)) => {
+            vec![format!("Transparent colour ({}, {}, {})", r, g, b)]
+        }
+        TypedChunk::Transparency(Transparency::Alpha(alphas)) => alphas
+            .iter()
+            .enumerate()
+            .map(|(index, alpha)| format!("{:>3}: alpha {}", index, alpha))
+            .collect(),
+        TypedChunk::Background(background) => vec![background.to_string()],
+        TypedChunk::SignificantBits(bits) => vec![bits.to_string()],
+        TypedChunk::Histogram(histogram) => histogram
+            .frequencies()
+            .iter()
+            .enumerate()
+            .map(|(index, frequency)| format!("{:>3}: {}", index, frequency))
+            .collect(),
+        TypedChunk::Gamma(gamma) => vec![gamma.to_string()],
+        TypedChunk::Iccp(iccp) => vec![format!(
+            "Profile '{}', {} bytes",
+            iccp.name(),
+            iccp.profile().len()
+        )],
+        TypedChunk::Phys(phys) => vec![phys.to_string()],
+        TypedChunk::Time(time) => vec![time.to_string()],
+        TypedChunk::Text(text) => {
+            let mut lines = vec![format!("Keyword: {}", text.keyword())];
+            if !text.language_tag().is_empty() {
+                lines.push(format!("Language: {}", text.language_tag()));
+            }
+            lines.push(text.text().to_string());
+            lines
+        }
+        TypedChunk::SuggestedPalette(palette) => vec![palette.to_string()],
+        TypedChunk::Unknown(chunk) => match chunk.chunk_type().to_string().as_str() {
+            AnimationControl::CHUNK_TYPE => {
+                vec![AnimationControl::try_from(chunk.data())?.to_string()]
+            }
+            FrameControl::CHUNK_TYPE => vec![FrameControl::try_from(chunk.data())?.to_string()],
+            EXIF_CHUNK_TYPE => exif::read_exif(chunk.data())?
+                .iter()
+                .map(ToString::to_string)
+                .collect(),
+            _ => vec![],
+        },
+    })
+}
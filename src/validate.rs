@@ -0,0 +1,1389 @@
+use crate::apng;
+use crate::background::Background;
+use crate::chunk_type::ChunkType;
+use crate::dedupe;
+use crate::error::{error, ErrorKind};
+use crate::gamma::{GamaChunk, SRGB_GAMMA};
+use crate::ihdr::{ColorType, Ihdr};
+use crate::palette::Histogram;
+use crate::plugin::ChunkHandlers;
+use crate::png::{self, ParseOptions, Png};
+use crate::polyglot;
+use crate::raw::{self, RawChunk, RawChunks};
+use crate::sbit::SignificantBits;
+use crate::text;
+use crate::zlib::{self, Inflated, ZlibProblem};
+use crate::PngResult;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use tracing::debug;
+
+/// How serious a spec violation is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// Worth knowing about, but not a problem
+    Info,
+    /// Decoders may cope, but the file doesn't follow the spec
+    Warning,
+    /// Conforming decoders are expected to reject the file
+    Error,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Info => write!(f, "info"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// The rule a violation breaks, with an ID which stays the same across releases so that
+/// tools consuming the output can key on it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Rule {
+    InvalidSignature,
+    MalformedChunk,
+    InvalidChunkType,
+    ReservedBitSet,
+    CrcMismatch,
+    IhdrNotFirst,
+    IhdrLength,
+    IhdrDimensions,
+    IhdrColorType,
+    IhdrBitDepth,
+    IhdrCompressionMethod,
+    IhdrFilterMethod,
+    IhdrInterlaceMethod,
+    DuplicateChunk,
+    DuplicateAncillaryChunk,
+    MissingIdat,
+    PlteAfterIdat,
+    PlteNotAllowed,
+    MisplacedChunk,
+    IdatNotConsecutive,
+    InvalidColorChunk,
+    SrgbWithIccp,
+    SrgbGammaMismatch,
+    InvalidPrivateChunk,
+    MissingIend,
+    TrailingData,
+    ChunkAfterIend,
+    EmbeddedFile,
+    ApngSequence,
+    ZlibHeader,
+    ZlibStream,
+    ZlibChecksum,
+    IdatSize,
+    TextKeyword,
+    TextEncoding,
+    TextNul,
+    TextControlCharacter,
+}
+
+impl Rule {
+    pub const ALL: &'static [Rule] = &[
+        Rule::InvalidSignature,
+        Rule::MalformedChunk,
+        Rule::InvalidChunkType,
+        Rule::ReservedBitSet,
+        Rule::CrcMismatch,
+        Rule::IhdrNotFirst,
+        Rule::IhdrLength,
+        Rule::IhdrDimensions,
+        Rule::IhdrColorType,
+        Rule::IhdrBitDepth,
+        Rule::IhdrCompressionMethod,
+        Rule::IhdrFilterMethod,
+        Rule::IhdrInterlaceMethod,
+        Rule::DuplicateChunk,
+        Rule::DuplicateAncillaryChunk,
+        Rule::MissingIdat,
+        Rule::PlteAfterIdat,
+        Rule::PlteNotAllowed,
+        Rule::MisplacedChunk,
+        Rule::IdatNotConsecutive,
+        Rule::InvalidColorChunk,
+        Rule::SrgbWithIccp,
+        Rule::SrgbGammaMismatch,
+        Rule::InvalidPrivateChunk,
+        Rule::MissingIend,
+        Rule::TrailingData,
+        Rule::ChunkAfterIend,
+        Rule::EmbeddedFile,
+        Rule::ApngSequence,
+        Rule::ZlibHeader,
+        Rule::ZlibStream,
+        Rule::ZlibChecksum,
+        Rule::IdatSize,
+        Rule::TextKeyword,
+        Rule::TextEncoding,
+        Rule::TextNul,
+        Rule::TextControlCharacter,
+    ];
+
+    /// The stable ID of this rule
+    pub fn id(&self) -> &'static str {
+        match self {
+            Rule::InvalidSignature => "invalid-signature",
+            Rule::MalformedChunk => "malformed-chunk",
+            Rule::InvalidChunkType => "invalid-chunk-type",
+            Rule::ReservedBitSet => "reserved-bit-set",
+            Rule::CrcMismatch => "crc-mismatch",
+            Rule::IhdrNotFirst => "ihdr-not-first",
+            Rule::IhdrLength => "ihdr-length",
+            Rule::IhdrDimensions => "ihdr-dimensions",
+            Rule::IhdrColorType => "ihdr-color-type",
+            Rule::IhdrBitDepth => "ihdr-bit-depth",
+            Rule::IhdrCompressionMethod => "ihdr-compression-method",
+            Rule::IhdrFilterMethod => "ihdr-filter-method",
+            Rule::IhdrInterlaceMethod => "ihdr-interlace-method",
+            Rule::DuplicateChunk => "duplicate-chunk",
+            Rule::DuplicateAncillaryChunk => "duplicate-ancillary-chunk",
+            Rule::MissingIdat => "missing-idat",
+            Rule::PlteAfterIdat => "plte-after-idat",
+            Rule::PlteNotAllowed => "plte-not-allowed",
+            Rule::MisplacedChunk => "misplaced-chunk",
+            Rule::IdatNotConsecutive => "idat-not-consecutive",
+            Rule::InvalidColorChunk => "invalid-color-chunk",
+            Rule::SrgbWithIccp => "srgb-with-iccp",
+            Rule::SrgbGammaMismatch => "srgb-gamma-mismatch",
+            Rule::InvalidPrivateChunk => "invalid-private-chunk",
+            Rule::MissingIend => "missing-iend",
+            Rule::TrailingData => "trailing-data",
+            Rule::ChunkAfterIend => "chunk-after-iend",
+            Rule::EmbeddedFile => "embedded-file",
+            Rule::ApngSequence => "apng-sequence",
+            Rule::ZlibHeader => "zlib-header",
+            Rule::ZlibStream => "zlib-stream",
+            Rule::ZlibChecksum => "zlib-checksum",
+            Rule::IdatSize => "idat-size",
+            Rule::TextKeyword => "text-keyword",
+            Rule::TextEncoding => "text-encoding",
+            Rule::TextNul => "text-nul",
+            Rule::TextControlCharacter => "text-control-character",
+        }
+    }
+
+    /// How serious a violation of this rule is unless reclassified
+    pub fn default_severity(&self) -> Severity {
+        match self {
+            Rule::ReservedBitSet
+            | Rule::DuplicateAncillaryChunk
+            | Rule::SrgbWithIccp
+            | Rule::SrgbGammaMismatch
+            | Rule::TrailingData
+            | Rule::ChunkAfterIend
+            | Rule::EmbeddedFile
+            | Rule::TextControlCharacter => Severity::Warning,
+            _ => Severity::Error,
+        }
+    }
+
+    /// A one-line description of what the rule checks
+    pub fn description(&self) -> &'static str {
+        match self {
+            Rule::InvalidSignature => "The file must start with the 8-byte PNG signature",
+            Rule::MalformedChunk => "Every chunk must fit within the file and be well formed",
+            Rule::InvalidChunkType => "Chunk type codes must be four ASCII letters",
+            Rule::ReservedBitSet => "The reserved bit of a chunk type must not be set",
+            Rule::CrcMismatch => "A chunk's stored CRC must match its type and data",
+            Rule::IhdrNotFirst => "IHDR must be the first chunk",
+            Rule::IhdrLength => "IHDR data must be 13 bytes long",
+            Rule::IhdrDimensions => "Image width and height must be from 1 to 2^31-1",
+            Rule::IhdrColorType => "The colour type must be 0, 2, 3, 4 or 6",
+            Rule::IhdrBitDepth => "The bit depth must be one the colour type allows",
+            Rule::IhdrCompressionMethod => "The compression method must be 0 (deflate)",
+            Rule::IhdrFilterMethod => "The filter method must be 0 (adaptive)",
+            Rule::IhdrInterlaceMethod => "The interlace method must be 0 (none) or 1 (Adam7)",
+            Rule::DuplicateChunk => "IHDR, PLTE and IEND must each appear only once",
+            Rule::DuplicateAncillaryChunk => {
+                "Standard ancillary chunks such as gAMA and pHYs must each appear only once"
+            }
+            Rule::MissingIdat => "The image must have at least one IDAT chunk",
+            Rule::PlteAfterIdat => "PLTE must come before the first IDAT chunk",
+            Rule::PlteNotAllowed => "Grayscale images must not have a PLTE chunk",
+            Rule::MisplacedChunk => {
+                "Ancillary chunks must come before the PLTE or IDAT chunks the spec puts after them"
+            }
+            Rule::IdatNotConsecutive => "IDAT chunks must be consecutive",
+            Rule::InvalidColorChunk => "Colour chunks must be consistent with the IHDR",
+            Rule::SrgbWithIccp => "sRGB and iCCP chunks must not both be present",
+            Rule::SrgbGammaMismatch => "A gAMA chunk alongside sRGB should hold the sRGB gamma",
+            Rule::InvalidPrivateChunk => {
+                "Private chunks must be accepted by their registered handler"
+            }
+            Rule::MissingIend => "The file must end with an IEND chunk",
+            Rule::TrailingData => "No data should follow the IEND chunk",
+            Rule::ChunkAfterIend => "No chunks should follow IEND, as decoders ignore them",
+            Rule::EmbeddedFile => {
+                "Chunks and trailing data should not hold other file formats, making a polyglot"
+            }
+            Rule::ApngSequence => {
+                "fcTL and fdAT sequence numbers must count up from 0 without gaps or repeats"
+            }
+            Rule::ZlibHeader => {
+                "Compressed data must start with a zlib header for deflate with no dictionary"
+            }
+            Rule::ZlibStream => "Compressed data must inflate without errors to its end",
+            Rule::ZlibChecksum => "A zlib stream's Adler-32 checksum must match its data",
+            Rule::IdatSize => "Image data must inflate to the size the IHDR implies",
+            Rule::TextKeyword => {
+                "Text keywords must be 1 to 79 Latin-1 characters with no extra spaces"
+            }
+            Rule::TextEncoding => "iTXt text and translated keywords must be UTF-8",
+            Rule::TextNul => "Text must not contain NUL characters",
+            Rule::TextControlCharacter => {
+                "tEXt and zTXt text should hold no control characters other than linefeed"
+            }
+        }
+    }
+}
+
+impl Display for Rule {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.pad(self.id())
+    }
+}
+
+impl FromStr for Rule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Rule::ALL
+            .iter()
+            .find(|rule| rule.id() == s)
+            .copied()
+            .ok_or_else(|| format!("Unknown rule '{}'", s))
+    }
+}
+
+/// A single way in which a file breaks the PNG spec
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    pub rule: Rule,
+    pub severity: Severity,
+    /// The byte offset of the offending chunk or data
+    pub offset: usize,
+    pub message: String,
+}
+
+impl Display for Violation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:#010x}: {}: {}",
+            self.offset, self.severity, self.message
+        )
+    }
+}
+
+/// Collects violations as the chunks are walked
+#[derive(Default)]
+struct Validator {
+    violations: Vec<Violation>,
+}
+
+impl Validator {
+    fn report(&mut self, rule: Rule, offset: usize, message: String) {
+        self.violations.push(Violation {
+            rule,
+            severity: rule.default_severity(),
+            offset,
+            message,
+        })
+    }
+
+    /// Checks which only need to look at a single chunk
+    fn check_chunk(&mut self, chunk: &RawChunk) {
+        match ChunkType::try_from(chunk.chunk_type) {
+            Err(_) => self.report(
+                Rule::InvalidChunkType,
+                chunk.offset,
+                format!("Invalid chunk type bytes {:?}", chunk.chunk_type),
+            ),
+            Ok(chunk_type) if !chunk_type.is_reserved_bit_valid() => self.report(
+                Rule::ReservedBitSet,
+                chunk.offset,
+                format!("{} chunk has its reserved bit set", chunk.type_name()),
+            ),
+            Ok(_) => {}
+        }
+    }
+
+    /// Checks every chunk's CRC, which is the bulk of the work on large files
+    fn check_crcs(&mut self, chunks: &[RawChunk]) {
+        for (chunk, computed) in raw::crc_mismatches(chunks) {
+            self.report(
+                Rule::CrcMismatch,
+                chunk.offset,
+                format!(
+                    "{} chunk CRC is {:#010x} but should be {:#010x}",
+                    chunk.type_name(),
+                    chunk.stored_crc,
+                    computed
+                ),
+            );
+        }
+    }
+
+    /// Checks that each IHDR field holds a value the spec allows, reporting every bad field
+    /// at its own offset
+    fn check_ihdr(&mut self, chunks: &[RawChunk]) {
+        // A missing IHDR is reported by the ordering checks
+        let Some(ihdr) = chunks.iter().find(|chunk| chunk.is(b"IHDR")) else {
+            return;
+        };
+        let data = ihdr.data;
+        if data.len() != Ihdr::LEN {
+            self.report(
+                Rule::IhdrLength,
+                ihdr.offset,
+                format!(
+                    "IHDR data is {} bytes long but should be {}",
+                    data.len(),
+                    Ihdr::LEN
+                ),
+            );
+            return;
+        }
+        // The data follows the length and type fields
+        let field = |index: usize| ihdr.offset + 8 + index;
+        for (name, index) in [("width", 0), ("height", 4)] {
+            let value = u32::from_be_bytes([
+                data[index],
+                data[index + 1],
+                data[index + 2],
+                data[index + 3],
+            ]);
+            if value == 0 || value > i32::MAX as u32 {
+                self.report(
+                    Rule::IhdrDimensions,
+                    field(index),
+                    format!("Image {} {} is out of range", name, value),
+                );
+            }
+        }
+        match ColorType::try_from(data[9]) {
+            Err(_) => self.report(
+                Rule::IhdrColorType,
+                field(9),
+                format!("Invalid colour type {}", data[9]),
+            ),
+            Ok(color_type) if !color_type.allowed_bit_depths().contains(&data[8]) => self.report(
+                Rule::IhdrBitDepth,
+                field(8),
+                format!(
+                    "Bit depth {} is not allowed for {} images",
+                    data[8], color_type
+                ),
+            ),
+            Ok(_) => {}
+        }
+        let methods = [
+            (Rule::IhdrCompressionMethod, "compression", 10, 0),
+            (Rule::IhdrFilterMethod, "filter", 11, 0),
+            (Rule::IhdrInterlaceMethod, "interlace", 12, 1),
+        ];
+        for (rule, name, index, max) in methods {
+            if data[index] > max {
+                self.report(
+                    rule,
+                    field(index),
+                    format!("Unknown {} method {}", name, data[index]),
+                );
+            }
+        }
+    }
+
+    /// Checks on where chunks appear relative to each other
+    fn check_ordering(&mut self, chunks: &[RawChunk]) {
+        match chunks.first() {
+            Some(first) if !first.is(b"IHDR") => self.report(
+                Rule::IhdrNotFirst,
+                first.offset,
+                format!("First chunk is {} rather than IHDR", first.type_name()),
+            ),
+            _ => {}
+        }
+
+        for unique in [b"IHDR", b"PLTE", b"IEND"] {
+            for duplicate in chunks.iter().filter(|chunk| chunk.is(unique)).skip(1) {
+                self.report(
+                    Rule::DuplicateChunk,
+                    duplicate.offset,
+                    format!("Duplicate {} chunk", duplicate.type_name()),
+                );
+            }
+        }
+
+        for once_only in dedupe::ONCE_ONLY_TYPES {
+            for duplicate in chunks
+                .iter()
+                .filter(|chunk| chunk.is(&once_only.bytes()))
+                .skip(1)
+            {
+                self.report(
+                    Rule::DuplicateAncillaryChunk,
+                    duplicate.offset,
+                    format!("Duplicate {} chunk", duplicate.type_name()),
+                );
+            }
+        }
+
+        for (index, chunk) in chunks.iter().enumerate() {
+            // IHDR and PLTE have rules of their own, and IDAT always comes before IEND
+            let Ok(chunk_type) = ChunkType::try_from(chunk.chunk_type) else {
+                continue;
+            };
+            if [b"IHDR", b"PLTE", b"IDAT"].iter().any(|t| chunk.is(t)) {
+                continue;
+            }
+            let earlier = png::required_before(&chunk_type).iter().find_map(|before| {
+                chunks[..index]
+                    .iter()
+                    .find(|earlier| earlier.chunk_type.as_slice() == before.as_bytes())
+            });
+            if let Some(earlier) = earlier {
+                self.report(
+                    Rule::MisplacedChunk,
+                    chunk.offset,
+                    format!(
+                        "{} chunk appears after the {} chunk at {:#010x}",
+                        chunk_type,
+                        earlier.type_name(),
+                        earlier.offset
+                    ),
+                );
+            }
+        }
+
+        let first_idat = chunks.iter().position(|chunk| chunk.is(b"IDAT"));
+        match first_idat {
+            None => self.report(
+                Rule::MissingIdat,
+                chunks.last().map_or(Png::HEADER_BYTES_LEN, RawChunk::end),
+                "No IDAT chunk found".to_string(),
+            ),
+            Some(first_idat) => {
+                for plte in chunks[first_idat..]
+                    .iter()
+                    .filter(|chunk| chunk.is(b"PLTE"))
+                {
+                    self.report(
+                        Rule::PlteAfterIdat,
+                        plte.offset,
+                        "PLTE chunk appears after IDAT".to_string(),
+                    );
+                }
+                let run_len = chunks[first_idat..]
+                    .iter()
+                    .take_while(|chunk| chunk.is(b"IDAT"))
+                    .count();
+                // Report where each later run of IDAT chunks starts
+                let rest = &chunks[first_idat + run_len..];
+                for (index, stray) in rest.iter().enumerate() {
+                    if stray.is(b"IDAT") && (index == 0 || !rest[index - 1].is(b"IDAT")) {
+                        self.report(
+                            Rule::IdatNotConsecutive,
+                            stray.offset,
+                            "IDAT chunks are not consecutive".to_string(),
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    /// Checks that the bKGD, sBIT and hIST chunks suit the image's colour type and palette
+    fn check_color_chunks(&mut self, chunks: &[RawChunk]) {
+        // A missing or broken IHDR has already been reported
+        let Some(Ok(ihdr)) = chunks
+            .iter()
+            .find(|chunk| chunk.is(b"IHDR"))
+            .map(|chunk| Ihdr::try_from(chunk.data))
+        else {
+            return;
+        };
+        let plte = chunks.iter().find(|chunk| chunk.is(b"PLTE"));
+        if let Some(plte) = plte {
+            if matches!(
+                ihdr.color_type,
+                ColorType::Grayscale | ColorType::GrayscaleAlpha
+            ) {
+                self.report(
+                    Rule::PlteNotAllowed,
+                    plte.offset,
+                    format!("PLTE chunk in a {} image", ihdr.color_type),
+                );
+            }
+        }
+        let palette_len = plte.map(|chunk| chunk.data.len() / 3);
+        for chunk in chunks {
+            let result = match &chunk.chunk_type {
+                b"bKGD" => Background::decode(chunk.data, &ihdr, palette_len).map(|_| ()),
+                b"sBIT" => SignificantBits::new(chunk.data.to_vec(), &ihdr).map(|_| ()),
+                b"hIST" => Histogram::decode(chunk.data, palette_len).map(|_| ()),
+                _ => Ok(()),
+            };
+            if let Err(e) = result {
+                self.report(Rule::InvalidColorChunk, chunk.offset, e.to_string());
+            }
+        }
+    }
+
+    /// Checks that the colour space chunks don't contradict each other
+    fn check_color_space(&mut self, chunks: &[RawChunk]) {
+        let Some(srgb) = chunks.iter().find(|chunk| chunk.is(b"sRGB")) else {
+            return;
+        };
+        if let Some(iccp) = chunks.iter().find(|chunk| chunk.is(b"iCCP")) {
+            self.report(
+                Rule::SrgbWithIccp,
+                srgb.offset.max(iccp.offset),
+                "Both sRGB and iCCP chunks are present".to_string(),
+            );
+        }
+        let gama = chunks.iter().find(|chunk| chunk.is(b"gAMA"));
+        if let Some(gama) = gama.filter(|gama| gama.data.len() == GamaChunk::LEN) {
+            let gamma = GamaChunk::try_from(gama.data).map_or(0, |gamma| gamma.value);
+            if gamma != SRGB_GAMMA {
+                self.report(
+                    Rule::SrgbGammaMismatch,
+                    gama.offset,
+                    format!(
+                        "gAMA value {} doesn't match the {} implied by the sRGB chunk",
+                        gamma, SRGB_GAMMA
+                    ),
+                );
+            }
+        }
+    }
+
+    /// Checks for other file formats hidden in the chunks
+    fn check_embedded_files(&mut self, chunks: &[RawChunk]) {
+        for chunk in chunks {
+            for embedded in polyglot::find_in_chunk(&chunk.chunk_type, chunk.data) {
+                self.report(
+                    Rule::EmbeddedFile,
+                    // The data follows the length and type fields
+                    chunk.offset + 8 + embedded.offset,
+                    format!(
+                        "{} found in {} chunk data",
+                        embedded.format,
+                        chunk.type_name()
+                    ),
+                );
+            }
+        }
+    }
+
+    /// Checks that the animation chunks of an APNG are numbered in order, so that decoders can
+    /// tell if any were lost or reordered
+    fn check_animation_sequence(&mut self, chunks: &[RawChunk]) {
+        let mut expected = 0u32;
+        for chunk in chunks {
+            if !chunk.is(b"fcTL") && !chunk.is(b"fdAT") {
+                continue;
+            }
+            let Ok((sequence_number, _)) = apng::split_frame_data(chunk.data) else {
+                self.report(
+                    Rule::ApngSequence,
+                    chunk.offset,
+                    format!(
+                        "{} chunk is too short to hold a sequence number",
+                        chunk.type_name()
+                    ),
+                );
+                continue;
+            };
+            if sequence_number != expected {
+                self.report(
+                    Rule::ApngSequence,
+                    chunk.offset,
+                    format!(
+                        "{} chunk has sequence number {} but should be {}",
+                        chunk.type_name(),
+                        sequence_number,
+                        expected
+                    ),
+                );
+            }
+            // Carry on from this chunk so that one gap is reported once
+            expected = sequence_number.wrapping_add(1);
+        }
+    }
+
+    /// Inflates the image data and the compressed text and profile chunks, checking that each
+    /// zlib stream is intact and that the image data is the size the IHDR implies
+    /// Checks the zlib stream of every chunk which has one and of the image data, returning
+    /// the text the zTXt and compressed iTXt chunks inflate to, by chunk offset, so that it is
+    /// only inflated once
+    fn check_zlib_streams(&mut self, chunks: &[RawChunk]) -> HashMap<usize, Vec<u8>> {
+        let mut texts = HashMap::new();
+        for chunk in chunks {
+            let Some(stream) = zlib::compressed_stream(&chunk.chunk_type, chunk.data) else {
+                continue;
+            };
+            let name = format!("{} chunk", chunk.type_name());
+            if chunk.is(b"iCCP") {
+                self.check_zlib_stream(chunk.offset, &name, stream, zlib::MAX_INFLATED_LEN, None);
+                continue;
+            }
+            let mut text = vec![];
+            let limit = zlib::MAX_INFLATED_LEN;
+            self.check_zlib_stream(chunk.offset, &name, stream, limit, Some(&mut text));
+            texts.insert(chunk.offset, text);
+        }
+
+        // A missing IDAT chunk has already been reported
+        let Some(first) = chunks.iter().find(|chunk| chunk.is(b"IDAT")) else {
+            return texts;
+        };
+        let stream: Vec<u8> = chunks
+            .iter()
+            .filter(|chunk| chunk.is(b"IDAT"))
+            .flat_map(|chunk| chunk.data)
+            .copied()
+            .collect();
+        let expected = chunks
+            .iter()
+            .find(|chunk| chunk.is(b"IHDR"))
+            .and_then(|chunk| Ihdr::try_from(chunk.data).ok())
+            .map(|ihdr| ihdr.raw_data_len() as u64);
+        // The inflated data is thrown away as it goes, so the size the IHDR implies bounds the
+        // work rather than the memory used
+        let limit = expected.unwrap_or(zlib::MAX_INFLATED_LEN);
+        let inflated = self.check_zlib_stream(first.offset, "Image data", &stream, limit, None);
+        let message = match (inflated, expected) {
+            (Some(Inflated::Complete(len)), Some(expected)) if len != expected => format!(
+                "Image data inflates to {} bytes but the IHDR implies {}",
+                len, expected
+            ),
+            (Some(Inflated::OverLimit), Some(expected)) => format!(
+                "Image data inflates to more than the {} bytes the IHDR implies",
+                expected
+            ),
+            _ => return texts,
+        };
+        self.report(Rule::IdatSize, first.offset, message);
+        texts
+    }
+
+    /// Checks a single zlib stream, reporting any problem at `offset`, and returns how much it
+    /// inflated to if it's intact. What it inflates to is kept in `output` if given.
+    fn check_zlib_stream(
+        &mut self,
+        offset: usize,
+        name: &str,
+        stream: &[u8],
+        limit: u64,
+        output: Option<&mut Vec<u8>>,
+    ) -> Option<Inflated> {
+        let checked = match output {
+            Some(output) => zlib::check_into(stream, limit, output),
+            None => zlib::check(stream, limit),
+        };
+        let problem = match checked {
+            Ok(inflated) => return Some(inflated),
+            Err(problem) => problem,
+        };
+        let rule = match problem {
+            ZlibProblem::Header(_) => Rule::ZlibHeader,
+            ZlibProblem::Stream(_) => Rule::ZlibStream,
+            ZlibProblem::Checksum { .. } => Rule::ZlibChecksum,
+        };
+        self.report(rule, offset, format!("{}: {}", name, problem));
+        None
+    }
+
+    /// Checks the keyword and text of each tEXt, zTXt and iTXt chunk, given the text that
+    /// `check_zlib_streams` inflated
+    fn check_text_chunks(&mut self, chunks: &[RawChunk], texts: &HashMap<usize, Vec<u8>>) {
+        for chunk in chunks {
+            if !chunk.is(b"tEXt") && !chunk.is(b"zTXt") && !chunk.is(b"iTXt") {
+                continue;
+            }
+            let name = chunk.type_name();
+            let Some(end) = chunk.data.iter().position(|&byte| byte == 0) else {
+                let message = format!("{} chunk has no keyword terminator", name);
+                self.report(Rule::MalformedChunk, chunk.offset, message);
+                continue;
+            };
+            if let Err(e) = text::validate_keyword(&text::latin1_decode(&chunk.data[..end])) {
+                self.report(
+                    Rule::TextKeyword,
+                    chunk.offset,
+                    format!("{} chunk: {}", name, e),
+                );
+            }
+            let rest = &chunk.data[end + 1..];
+            let inflated = texts.get(&chunk.offset).map(Vec::as_slice);
+            if chunk.is(b"iTXt") {
+                self.check_international_text(chunk, rest, inflated);
+                continue;
+            }
+            // A broken zlib stream has already been reported, so check whatever inflated
+            let body = match inflated {
+                Some(text) => text,
+                None if chunk.is(b"tEXt") => rest,
+                None => continue,
+            };
+            if body.contains(&0) {
+                let message = format!("{} chunk text contains a NUL character", name);
+                self.report(Rule::TextNul, chunk.offset, message);
+            }
+            if let Some(&byte) = body
+                .iter()
+                .find(|&&byte| byte != 0 && text::is_undefined_control(byte))
+            {
+                self.report(
+                    Rule::TextControlCharacter,
+                    chunk.offset,
+                    format!(
+                        "{} chunk text contains control character {:#04x}",
+                        name, byte
+                    ),
+                );
+            }
+        }
+    }
+
+    /// Checks the fields of an iTXt chunk after its keyword
+    fn check_international_text(&mut self, chunk: &RawChunk, rest: &[u8], inflated: Option<&[u8]>) {
+        let fields = match rest {
+            [flag, _, fields @ ..] if *flag <= 1 => fields,
+            // A bad compression flag leaves the text unreadable
+            [_, _, ..] => return,
+            _ => {
+                let message = "iTXt chunk is missing its compression fields".to_string();
+                return self.report(Rule::MalformedChunk, chunk.offset, message);
+            }
+        };
+        let mut fields = fields.splitn(3, |&byte| byte == 0);
+        let (Some(_), Some(translated_keyword), Some(body)) =
+            (fields.next(), fields.next(), fields.next())
+        else {
+            let message =
+                "iTXt chunk is missing a language tag or translated keyword terminator".to_string();
+            return self.report(Rule::MalformedChunk, chunk.offset, message);
+        };
+        let body = inflated.unwrap_or(body);
+        for (field, bytes) in [("translated keyword", translated_keyword), ("text", body)] {
+            if let Err(e) = std::str::from_utf8(bytes) {
+                self.report(
+                    Rule::TextEncoding,
+                    chunk.offset,
+                    format!("iTXt chunk {} is not UTF-8: {}", field, e),
+                );
+            }
+        }
+        if body.contains(&0) {
+            let message = "iTXt chunk text contains a NUL character".to_string();
+            self.report(Rule::TextNul, chunk.offset, message);
+        }
+    }
+
+    /// Checks on private chunks which have a registered handler
+    fn check_private_chunks(&mut self, chunks: &[RawChunk], handlers: &ChunkHandlers) {
+        for chunk in chunks {
+            let Ok(chunk_type) = ChunkType::try_from(chunk.chunk_type) else {
+                continue;
+            };
+            if let Some(handler) = handlers.get(&chunk_type) {
+                for problem in handler.validate(chunk.data) {
+                    self.report(
+                        Rule::InvalidPrivateChunk,
+                        chunk.offset,
+                        format!("{} chunk: {}", chunk_type, problem),
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Check the raw bytes of a file against the structural rules of the PNG spec, returning
+/// every violation found in file order
+pub fn validate(bytes: &[u8]) -> Vec<Violation> {
+    validate_with(bytes, &ChunkHandlers::default())
+}
+
+/// Like `validate_with`, but refusing input beyond the limits in `options` as parsing it would,
+/// rather than reporting on it
+pub fn validate_within(
+    bytes: &[u8],
+    handlers: &ChunkHandlers,
+    options: &ParseOptions,
+) -> PngResult<Vec<Violation>> {
+    options.check_size(bytes)?;
+    for (count, chunk) in RawChunks::new(bytes).enumerate() {
+        let offset = match chunk {
+            Ok(chunk) => chunk.offset,
+            Err(e) => e.offset,
+        };
+        options.check_count(count, offset)?;
+        if let Some(message) = options.check_length(bytes, offset) {
+            let message = format!("Chunk at offset {:#010x} {}", offset, message);
+            return Err(error(ErrorKind::Parse, message));
+        }
+    }
+    Ok(validate_with(bytes, handlers))
+}
+
+/// Like `validate`, but also check private chunks using the given handlers
+pub fn validate_with(bytes: &[u8], handlers: &ChunkHandlers) -> Vec<Violation> {
+    let mut validator = Validator::default();
+    if !bytes.starts_with(&Png::STANDARD_HEADER) {
+        validator.report(
+            Rule::InvalidSignature,
+            0,
+            "Missing or invalid PNG signature".to_string(),
+        );
+        return validator.violations;
+    }
+
+    let mut chunks = vec![];
+    let mut walker = RawChunks::new(bytes);
+    for chunk in walker.by_ref() {
+        match chunk {
+            Ok(chunk) => {
+                debug!(
+                    offset = chunk.offset,
+                    chunk_type = %chunk.type_name(),
+                    length = chunk.data.len(),
+                    "Parsed chunk"
+                );
+                validator.check_chunk(&chunk);
+                chunks.push(chunk);
+            }
+            Err(e) => validator.report(Rule::MalformedChunk, e.offset, e.message),
+        }
+    }
+    validator.check_crcs(&chunks);
+    validator.check_ihdr(&chunks);
+    validator.check_ordering(&chunks);
+    validator.check_color_space(&chunks);
+    validator.check_color_chunks(&chunks);
+    validator.check_private_chunks(&chunks, handlers);
+    validator.check_embedded_files(&chunks);
+    validator.check_animation_sequence(&chunks);
+    let texts = validator.check_zlib_streams(&chunks);
+    validator.check_text_chunks(&chunks, &texts);
+
+    match chunks.last() {
+        Some(last) if last.is(b"IEND") => {
+            if walker.offset() < bytes.len() {
+                validator.report(
+                    Rule::TrailingData,
+                    walker.offset(),
+                    format!("{} bytes of data after IEND", bytes.len() - walker.offset()),
+                );
+                for chunk in RawChunks::at(bytes, walker.offset()).map_while(Result::ok) {
+                    if ChunkType::try_from(chunk.chunk_type).is_err() {
+                        break;
+                    }
+                    validator.report(
+                        Rule::ChunkAfterIend,
+                        chunk.offset,
+                        format!("{} chunk after IEND", chunk.type_name()),
+                    );
+                }
+                for embedded in polyglot::find(&bytes[walker.offset()..]) {
+                    validator.report(
+                        Rule::EmbeddedFile,
+                        walker.offset() + embedded.offset,
+                        format!("{} found after IEND", embedded.format),
+                    );
+                }
+            }
+        }
+        _ => validator.report(
+            Rule::MissingIend,
+            walker.offset(),
+            "Missing IEND chunk".to_string(),
+        ),
+    }
+
+    validator
+        .violations
+        .sort_by_key(|violation| violation.offset);
+    validator.violations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::chunk;
+
+    /// A 1x1 8-bit indexed image
+    const IHDR_DATA: [u8; Ihdr::LEN] = [0, 0, 0, 1, 0, 0, 0, 1, 8, 3, 0, 0, 0];
+
+    /// The compressed image data of a 1x1 8-bit indexed or grayscale image
+    const IDAT_DATA: [u8; 10] = [0x78, 0xda, 0x63, 0x60, 0, 0, 0, 2, 0, 1];
+
+    /// A PNG of chunks with no data, other than the IHDR, the first IDAT and tEXt keywords
+    fn png_bytes(chunk_types: &[&str]) -> Vec<u8> {
+        let first_idat = chunk_types.iter().position(|&t| t == "IDAT");
+        let chunks = chunk_types
+            .iter()
+            .enumerate()
+            .map(|(index, &t)| {
+                let data = match t {
+                    "IHDR" => IHDR_DATA.to_vec(),
+                    "IDAT" if Some(index) == first_idat => IDAT_DATA.to_vec(),
+                    "tEXt" => b"Comment\0".to_vec(),
+                    _ => vec![],
+                };
+                chunk(t, &data)
+            })
+            .collect();
+        Png::from_chunks(chunks).as_bytes()
+    }
+
+    fn messages(bytes: &[u8]) -> Vec<String> {
+        validate(bytes)
+            .into_iter()
+            .map(|violation| violation.message)
+            .collect()
+    }
+
+    #[test]
+    fn test_validate_within_limits() {
+        let bytes = png_bytes(&["IHDR", "IDAT", "IEND"]);
+        let handlers = ChunkHandlers::default();
+        let options = ParseOptions::default();
+        let violations = validate_within(&bytes, &handlers, &options).unwrap();
+        assert_eq!(violations, validate(&bytes));
+
+        let limits = [
+            ParseOptions {
+                max_chunks: 2,
+                ..options
+            },
+            ParseOptions {
+                max_chunk_len: Ihdr::LEN - 1,
+                ..options
+            },
+            ParseOptions {
+                max_total_size: bytes.len() - 1,
+                ..options
+            },
+        ];
+        for options in limits {
+            let error = validate_within(&bytes, &handlers, &options).unwrap_err();
+            assert_eq!(crate::error::kind_of(error.as_ref()), ErrorKind::Parse);
+        }
+    }
+
+    #[test]
+    fn test_valid_png() {
+        assert!(validate(&png_bytes(&["IHDR", "PLTE", "IDAT", "IDAT", "IEND"])).is_empty());
+    }
+
+    #[test]
+    fn test_ihdr_fields() {
+        let ihdr = |data: &[u8]| {
+            let png = Png::from_chunks(vec![
+                chunk("IHDR", data),
+                chunk("IDAT", &IDAT_DATA),
+                chunk("IEND", &[]),
+            ]);
+            validate(&png.as_bytes())
+                .into_iter()
+                .map(|violation| (violation.rule.id(), violation.offset))
+                .collect::<Vec<_>>()
+        };
+        let data_offset = Png::HEADER_BYTES_LEN + 8;
+        assert!(ihdr(&[0, 0, 0, 1, 0, 0, 0, 1, 8, 0, 0, 0, 1]).is_empty());
+        assert_eq!(
+            ihdr(&[0, 0, 0, 0, 0x80, 0, 0, 0, 16, 3, 1, 1, 2]),
+            vec![
+                ("ihdr-dimensions", data_offset),
+                ("ihdr-dimensions", data_offset + 4),
+                ("ihdr-bit-depth", data_offset + 8),
+                ("ihdr-compression-method", data_offset + 10),
+                ("ihdr-filter-method", data_offset + 11),
+                ("ihdr-interlace-method", data_offset + 12),
+            ]
+        );
+        assert_eq!(
+            ihdr(&[0, 0, 0, 1, 0, 0, 0, 1, 8, 5, 0, 0, 0]),
+            vec![("ihdr-color-type", data_offset + 9)]
+        );
+        assert_eq!(ihdr(&[0; 12]), vec![("ihdr-length", Png::HEADER_BYTES_LEN)]);
+    }
+
+    #[test]
+    fn test_animation_sequence() {
+        let sequenced = |chunk_type: &str, sequence_number: u32| {
+            chunk(chunk_type, &sequence_number.to_be_bytes())
+        };
+        let png = Png::from_chunks(vec![
+            chunk("IHDR", &[]),
+            sequenced("fcTL", 0),
+            chunk("IDAT", &[]),
+            sequenced("fcTL", 1),
+            sequenced("fdAT", 3),
+            sequenced("fcTL", 4),
+            chunk("fdAT", &[0; 2]),
+            chunk("IEND", &[]),
+        ]);
+        let violations: Vec<_> = validate(&png.as_bytes())
+            .into_iter()
+            .filter(|violation| violation.rule == Rule::ApngSequence)
+            .map(|violation| violation.message)
+            .collect();
+        assert_eq!(
+            violations,
+            vec![
+                "fdAT chunk has sequence number 3 but should be 2",
+                "fdAT chunk is too short to hold a sequence number",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_embedded_files() {
+        let png = Png::from_chunks(vec![
+            chunk("IHDR", &[]),
+            chunk("IDAT", b"%PDF-1.4"),
+            chunk("zIPs", b"xxPK\x03\x04"),
+            chunk("IEND", &[]),
+        ]);
+        let mut bytes = png.as_bytes();
+        let iend_end = bytes.len();
+        bytes.extend_from_slice(b"%PDF-1.7");
+
+        let violations = validate(&bytes);
+        let embedded: Vec<_> = violations
+            .iter()
+            .filter(|violation| violation.rule == Rule::EmbeddedFile)
+            .map(|violation| (violation.offset, violation.message.as_str()))
+            .collect();
+        assert_eq!(
+            embedded,
+            vec![
+                (
+                    Png::HEADER_BYTES_LEN + 12 + 20 + 8 + 2,
+                    "ZIP archive found in zIPs chunk data"
+                ),
+                (iend_end, "PDF document found after IEND"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rule_ids() {
+        for rule in Rule::ALL {
+            assert_eq!(Rule::from_str(rule.id()), Ok(*rule));
+        }
+        assert!(Rule::from_str("no-such-rule").is_err());
+        assert_eq!(Rule::CrcMismatch.default_severity(), Severity::Error);
+        assert_eq!(Rule::TrailingData.default_severity(), Severity::Warning);
+    }
+
+    #[test]
+    fn test_bad_signature() {
+        let mut bytes = png_bytes(&["IHDR", "IDAT", "IEND"]);
+        bytes[0] = 0;
+        assert_eq!(messages(&bytes), vec!["Missing or invalid PNG signature"]);
+    }
+
+    #[test]
+    fn test_ordering_violations() {
+        let bytes = png_bytes(&["tEXt", "IHDR", "IDAT", "PLTE", "IDAT", "IHDR", "IEND"]);
+        assert_eq!(
+            messages(&bytes),
+            vec![
+                "First chunk is tEXt rather than IHDR",
+                "PLTE chunk appears after IDAT",
+                "IDAT chunks are not consecutive",
+                "Duplicate IHDR chunk",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_misplaced_chunks() {
+        let mut bytes = png_bytes(&[
+            "IHDR", "PLTE", "gAMA", "IDAT", "pHYs", "IDAT", "tEXt", "IDAT", "gAMA", "IEND",
+        ]);
+        let trailing = bytes.len();
+        let text = chunk("tEXt", b"a\0b");
+        bytes.extend_from_slice(&text.as_bytes());
+        bytes.extend_from_slice(b"junk");
+        let violations: Vec<_> = validate(&bytes)
+            .into_iter()
+            .map(|violation| (violation.rule.id(), violation.message))
+            .collect();
+        let rules: Vec<_> = violations.iter().map(|(rule, _)| *rule).collect();
+        assert_eq!(
+            rules,
+            vec![
+                "misplaced-chunk",
+                "misplaced-chunk",
+                "idat-not-consecutive",
+                "idat-not-consecutive",
+                "duplicate-ancillary-chunk",
+                "misplaced-chunk",
+                "trailing-data",
+                "chunk-after-iend",
+            ]
+        );
+        assert_eq!(
+            violations[0].1,
+            format!(
+                "gAMA chunk appears after the PLTE chunk at {:#010x}",
+                8 + 12 + Ihdr::LEN
+            )
+        );
+        assert_eq!(
+            validate(&bytes)
+                .iter()
+                .filter(|violation| violation.rule == Rule::ChunkAfterIend)
+                .map(|violation| violation.offset)
+                .collect::<Vec<_>>(),
+            vec![trailing]
+        );
+    }
+
+    #[test]
+    fn test_plte_in_grayscale_image() {
+        let chunks = [
+            ("IHDR", vec![0, 0, 0, 1, 0, 0, 0, 1, 8, 0, 0, 0, 0]),
+            ("PLTE", vec![0; 3]),
+            ("IDAT", IDAT_DATA.to_vec()),
+            ("IEND", vec![]),
+        ]
+        .into_iter()
+        .map(|(t, data)| chunk(t, &data))
+        .collect();
+        assert_eq!(
+            messages(&Png::from_chunks(chunks).as_bytes()),
+            vec!["PLTE chunk in a grayscale image"]
+        );
+    }
+
+    #[test]
+    fn test_crc_and_offsets() {
+        let mut bytes = png_bytes(&["IHDR", "IDAT", "IEND"]);
+        bytes[8 + 8 + Ihdr::LEN] ^= 0xff; // IHDR CRC
+        bytes.extend_from_slice(b"extra");
+        let violations = validate(&bytes);
+        assert_eq!(violations.len(), 2);
+        assert_eq!(violations[0].offset, 8);
+        assert_eq!(violations[0].severity, Severity::Error);
+        assert_eq!(violations[0].rule, Rule::CrcMismatch);
+        assert_eq!(violations[1].offset, 8 + 36 + Ihdr::LEN + IDAT_DATA.len());
+        assert_eq!(violations[1].severity, Severity::Warning);
+        assert_eq!(violations[1].rule.id(), "trailing-data");
+    }
+
+    #[test]
+    fn test_zlib_streams() {
+        let violations = |chunks: Vec<(&str, Vec<u8>)>| {
+            let chunks = chunks
+                .into_iter()
+                .map(|(t, data)| chunk(t, &data))
+                .collect();
+            validate(&Png::from_chunks(chunks).as_bytes())
+                .into_iter()
+                .map(|violation| (violation.rule.id(), violation.message))
+                .collect::<Vec<_>>()
+        };
+        let image = |idat: &[u8]| {
+            vec![
+                ("IHDR", IHDR_DATA.to_vec()),
+                ("PLTE", vec![0; 3]),
+                ("IDAT", idat.to_vec()),
+                ("IEND", vec![]),
+            ]
+        };
+
+        let mut idat = IDAT_DATA;
+        idat[9] ^= 1;
+        assert_eq!(
+            violations(image(&idat)),
+            vec![(
+                "zlib-checksum",
+                "Image data: Adler-32 checksum is 0x00020000 but the data sums to 0x00020001"
+                    .to_string()
+            )]
+        );
+        let mut idat = IDAT_DATA;
+        idat[0] = 0x79;
+        assert_eq!(violations(image(&idat))[0].0, "zlib-header");
+        assert_eq!(violations(image(&IDAT_DATA[..6]))[0].0, "zlib-stream");
+        // Three bytes where the IHDR implies two
+        let idat = [0x78, 0xda, 0x63, 0x60, 0x60, 0, 0, 0, 3, 0, 1];
+        assert_eq!(
+            violations(image(&idat)),
+            vec![(
+                "idat-size",
+                "Image data inflates to more than the 2 bytes the IHDR implies".to_string()
+            )]
+        );
+        let idat = [0x78, 0xda, 0x63, 0, 0, 0, 1, 0, 1];
+        assert_eq!(
+            violations(image(&idat)),
+            vec![(
+                "idat-size",
+                "Image data inflates to 1 bytes but the IHDR implies 2".to_string()
+            )]
+        );
+
+        let mut chunks = image(&IDAT_DATA);
+        chunks.insert(2, ("zTXt", b"Comment\0\0\x78\x02junk".to_vec()));
+        chunks.insert(
+            2,
+            (
+                "iTXt",
+                b"Comment\0\x01\0\0\0\x78\xda\x03\0\0\0\0\x01".to_vec(),
+            ),
+        );
+        let found = violations(chunks);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].0, "zlib-header");
+        assert!(found[0].1.starts_with("zTXt chunk: "));
+    }
+
+    #[test]
+    fn test_text_chunks() {
+        let violations = |chunk_type: &str, data: &[u8]| {
+            let chunks = [
+                ("IHDR", IHDR_DATA.to_vec()),
+                ("PLTE", vec![0; 3]),
+                (chunk_type, data.to_vec()),
+                ("IDAT", IDAT_DATA.to_vec()),
+                ("IEND", vec![]),
+            ]
+            .into_iter()
+            .map(|(t, data)| chunk(t, &data))
+            .collect();
+            validate(&Png::from_chunks(chunks).as_bytes())
+                .into_iter()
+                .map(|violation| (violation.rule.id(), violation.message))
+                .collect::<Vec<_>>()
+        };
+        let rules = |chunk_type: &str, data: &[u8]| {
+            violations(chunk_type, data)
+                .into_iter()
+                .map(|(rule, _)| rule)
+                .collect::<Vec<_>>()
+        };
+
+        assert!(violations("tEXt", b"Comment\0caf\xe9\nau lait").is_empty());
+        assert!(violations("iTXt", "Title\0\0\0de\0Titel\0Würfel".as_bytes()).is_empty());
+        assert_eq!(
+            violations("tEXt", b" Comment\0text"),
+            vec![(
+                "text-keyword",
+                "tEXt chunk: Keyword must not have leading, trailing or consecutive spaces"
+                    .to_string()
+            )]
+        );
+        assert_eq!(rules("tEXt", b"Comment"), vec!["malformed-chunk"]);
+        assert_eq!(rules("tEXt", b"\0text"), vec!["text-keyword"]);
+        assert_eq!(rules("tEXt", b"Comment\0a\0b"), vec!["text-nul"]);
+        assert_eq!(
+            violations("tEXt", b"Comment\0line\r\n"),
+            vec![(
+                "text-control-character",
+                "tEXt chunk text contains control character 0x0d".to_string()
+            )]
+        );
+        // zTXt text is checked once inflated: "a\0b"
+        let ztxt = b"Comment\0\0\x78\xda\x4b\x64\x48\x02\x00\x01\x88\x00\xc4";
+        assert_eq!(rules("zTXt", ztxt), vec!["text-nul"]);
+
+        assert_eq!(
+            violations("iTXt", b"Title\0\0\0en\0Title\0\xff"),
+            vec![(
+                "text-encoding",
+                "iTXt chunk text is not UTF-8: invalid utf-8 sequence of 1 bytes from index 0"
+                    .to_string()
+            )]
+        );
+        assert_eq!(
+            rules("iTXt", b"Title\0\0\0en\0\xc3\0text\0"),
+            vec!["text-encoding", "text-nul"]
+        );
+        assert_eq!(rules("iTXt", b"Title\0\0\0en"), vec!["malformed-chunk"]);
+        assert_eq!(rules("iTXt", b"Title\0\0"), vec!["malformed-chunk"]);
+    }
+
+    #[test]
+    fn test_color_space_conflicts() {
+        let chunks = [
+            ("IHDR", IHDR_DATA.to_vec()),
+            ("sRGB", vec![0]),
+            ("gAMA", vec![0, 1, 134, 160]),
+        ]
+        .into_iter()
+        .chain([
+            ("iCCP", vec![]),
+            ("IDAT", IDAT_DATA.to_vec()),
+            ("IEND", vec![]),
+        ])
+        .map(|(t, data)| chunk(t, &data))
+        .collect();
+        assert_eq!(
+            messages(&Png::from_chunks(chunks).as_bytes()),
+            vec![
+                "gAMA value 100000 doesn't match the 45455 implied by the sRGB chunk",
+                "Both sRGB and iCCP chunks are present",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_color_chunk_violations() {
+        // An 8-bit indexed image with a two entry palette
+        let ihdr = vec![0, 0, 0, 1, 0, 0, 0, 1, 8, 3, 0, 0, 0];
+        let chunks = [("IHDR", ihdr), ("sBIT", vec![8, 8]), ("PLTE", vec![0; 6])]
+            .into_iter()
+            .chain([("hIST", vec![0; 6]), ("bKGD", vec![2])])
+            .chain([("IDAT", IDAT_DATA.to_vec())])
+            .chain([("IEND", vec![])])
+            .map(|(t, data)| chunk(t, &data))
+            .collect();
+        assert_eq!(
+            messages(&Png::from_chunks(chunks).as_bytes()),
+            vec![
+                "sBIT has 2 values but indexed images need 3",
+                "hIST data is 6 bytes long but should be 4 for a 2 entry palette",
+                "bKGD palette index 2 is past the end of the 2 entry palette",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_private_chunk_handlers() {
+        let mut handlers = ChunkHandlers::default();
+        handlers
+            .register(crate::plugin::builtin_handler("envelope", "ruSt").unwrap())
+            .unwrap();
+        let mut bytes = png_bytes(&["IHDR", "IDAT", "IEND"]);
+        let chunk = chunk("ruSt", b"pmiE");
+        // After the IHDR chunk
+        let after_ihdr = 8 + 12 + Ihdr::LEN;
+        bytes.splice(after_ihdr..after_ihdr, chunk.as_bytes());
+        assert!(validate(&bytes).is_empty());
+        let violations = validate_with(&bytes, &handlers);
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].offset, after_ihdr);
+        assert!(violations[0].message.starts_with("ruSt chunk: "));
+    }
+
+    #[test]
+    fn test_missing_chunks() {
+        let bytes = png_bytes(&["IHDR"]);
+        assert_eq!(
+            messages(&bytes),
+            vec!["No IDAT chunk found", "Missing IEND chunk"]
+        );
+    }
+}
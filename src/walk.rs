@@ -0,0 +1,313 @@
+use crate::args::{FileListArgs, WalkArgs};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use ignore::WalkBuilder;
+use pngmi::input;
+use pngmi::PngResult;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+/// The name of the files, in gitignore syntax, listing what to leave out of the directory
+/// holding them and the directories beneath it
+pub const IGNORE_FILE_NAME: &str = ".pngmiignore";
+
+/// The characters which make a path a glob pattern rather than a literal path
+const GLOB_CHARS: &[char] = &['*', '?', '[', '{'];
+
+/// Compile glob patterns of entries to leave out of directory walks, as `WalkArgs::ignore`
+/// takes them. A pattern matches an entry's name or its path beneath the directory being
+/// walked, and an ignored directory isn't descended into.
+pub fn ignore_set(patterns: &[String]) -> PngResult<GlobSet> {
+    let mut set = GlobSetBuilder::new();
+    for pattern in patterns {
+        set.add(GlobBuilder::new(pattern).literal_separator(true).build()?);
+    }
+    Ok(set.build()?)
+}
+
+/// Returns whether an entry found walking `root` matches one of the `ignore` patterns
+fn is_ignored(ignore: &GlobSet, root: &Path, path: &Path) -> bool {
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    ignore.is_match(relative) || path.file_name().is_some_and(|name| ignore.is_match(name))
+}
+
+/// Returns whether a walk error is a symlink leading back up to a directory being walked,
+/// which is skipped rather than followed forever
+fn is_loop(error: &ignore::Error) -> bool {
+    match error {
+        ignore::Error::Loop { .. } => true,
+        ignore::Error::WithPath { err, .. } | ignore::Error::WithDepth { err, .. } => is_loop(err),
+        _ => false,
+    }
+}
+
+/// Returns whether the path is a glob pattern to be expanded, rather than a path to a file,
+/// directory or URL. Patterns are expanded here rather than by the shell so that they behave
+/// the same everywhere, including on Windows.
+pub fn is_glob(path: &Path) -> bool {
+    input::url(path).is_none() && !path.exists() && path.to_string_lossy().contains(GLOB_CHARS)
+}
+
+/// Collect the PNG files at the given path in a stable order: the path itself if it is a file,
+/// every `*.png` file beneath it if it is a directory, or every file matching it if it is a
+/// glob pattern
+pub fn find_pngs(path: &Path, walk: &WalkArgs) -> PngResult<Vec<PathBuf>> {
+    if is_glob(path) {
+        return expand_glob(path, walk);
+    }
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    walk_files(path, walk, |path| {
+        path.extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("png"))
+    })
+}
+
+/// Collect the files to process: those listed by `--files-from` if given, otherwise those
+/// found at the path
+pub fn select_files(
+    path: Option<&Path>,
+    list: &FileListArgs,
+    walk: &WalkArgs,
+) -> PngResult<Vec<PathBuf>> {
+    match (&list.files_from, path) {
+        (Some(_), Some(_)) => Err("Pass either a path or --files-from, not both".into()),
+        (Some(source), None) => read_file_list(source, list.null),
+        (None, Some(path)) => find_pngs(path, walk),
+        (None, None) => Err("No input files given".into()),
+    }
+}
+
+/// Read a list of paths from the file, or from standard input if it is "-", one per line or
+/// NUL-delimited. Empty entries are skipped.
+fn read_file_list(source: &Path, null: bool) -> PngResult<Vec<PathBuf>> {
+    let mut bytes = vec![];
+    if source == Path::new("-") {
+        io::stdin().lock().read_to_end(&mut bytes)?;
+    } else {
+        bytes = fs::read(source)?;
+    }
+    let delimiter = if null { b'\0' } else { b'\n' };
+    bytes
+        .split(|byte| *byte == delimiter)
+        .map(|entry| match null {
+            true => entry,
+            false => entry.strip_suffix(b"\r").unwrap_or(entry),
+        })
+        .filter(|entry| !entry.is_empty())
+        .map(path_from_bytes)
+        .collect()
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: &[u8]) -> PngResult<PathBuf> {
+    use std::os::unix::ffi::OsStrExt;
+    Ok(PathBuf::from(std::ffi::OsStr::from_bytes(bytes)))
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: &[u8]) -> PngResult<PathBuf> {
+    Ok(PathBuf::from(String::from_utf8(bytes.to_vec())?))
+}
+
+/// Find every file matching the pattern, walking only the directory named by its literal
+/// leading components
+fn expand_glob(pattern: &Path, walk: &WalkArgs) -> PngResult<Vec<PathBuf>> {
+    let text = pattern.to_string_lossy();
+    let matcher = GlobBuilder::new(&text)
+        .literal_separator(true)
+        .build()?
+        .compile_matcher();
+    let root: PathBuf = pattern
+        .components()
+        .take_while(|component| !component.as_os_str().to_string_lossy().contains(GLOB_CHARS))
+        .collect();
+    let files = if root.as_os_str().is_empty() || root.is_dir() {
+        walk_files(&root, walk, |path| matcher.is_match(path))?
+    } else {
+        vec![]
+    };
+    if files.is_empty() {
+        return Err(format!("No files match {}", text).into());
+    }
+    Ok(files)
+}
+
+/// Recursively collect the files beneath `root` for which `keep` returns true, sorted. Hidden
+/// entries, ignored ones and those listed in `.pngmiignore` files are skipped and symlinked
+/// directories aren't followed unless `walk` says otherwise. A deeper `.pngmiignore` takes
+/// precedence over a shallower one, so it can re-include with `!` what the other ignores.
+fn walk_files(
+    root: &Path,
+    walk: &WalkArgs,
+    keep: impl Fn(&Path) -> bool,
+) -> PngResult<Vec<PathBuf>> {
+    // An empty root is the current directory, but paths beneath it are kept relative
+    let read_from = if root.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        root
+    };
+    let mut builder = WalkBuilder::new(read_from);
+    builder
+        .standard_filters(false)
+        .hidden(!walk.hidden)
+        .follow_links(walk.follow_symlinks);
+    if !walk.no_ignore {
+        builder.add_custom_ignore_filename(IGNORE_FILE_NAME);
+    }
+    let (ignore, base) = (walk.ignore.clone(), read_from.to_path_buf());
+    builder.filter_entry(move |entry| !is_ignored(&ignore, &base, entry.path()));
+    let mut files = vec![];
+    for entry in builder.build() {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) if is_loop(&e) => continue,
+            Err(e) => return Err(e.into()),
+        };
+        let path = match root.as_os_str().is_empty() {
+            true => entry.path().strip_prefix(read_from).unwrap_or(entry.path()),
+            false => entry.path(),
+        };
+        if !path.is_dir() && keep(path) {
+            files.push(path.to_path_buf());
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn walk_args() -> WalkArgs {
+        WalkArgs {
+            hidden: false,
+            follow_symlinks: false,
+            no_ignore: false,
+            ignore: GlobSet::empty(),
+        }
+    }
+
+    /// A directory holding the given files, each empty unless given contents
+    fn tree(files: &[(&str, &str)]) -> TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        for (path, contents) in files {
+            let path = dir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+        dir
+    }
+
+    /// The files found beneath the directory, relative to it
+    fn found(dir: &TempDir, walk: &WalkArgs) -> Vec<String> {
+        find_pngs(dir.path(), walk)
+            .unwrap()
+            .iter()
+            .map(|path| {
+                let path = path.strip_prefix(dir.path()).unwrap();
+                path.to_string_lossy().replace('\\', "/")
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_find_pngs() {
+        let dir = tree(&[
+            ("a.png", ""),
+            ("notes.txt", ""),
+            ("sub/b.PNG", ""),
+            (".cache/c.png", ""),
+        ]);
+        assert_eq!(found(&dir, &walk_args()), ["a.png", "sub/b.PNG"]);
+        let walk = WalkArgs {
+            hidden: true,
+            ..walk_args()
+        };
+        assert_eq!(found(&dir, &walk), [".cache/c.png", "a.png", "sub/b.PNG"]);
+    }
+
+    #[test]
+    fn test_ignore_files() {
+        let dir = tree(&[
+            (".pngmiignore", "build/\nscratch-*.png\n"),
+            ("build/a.png", ""),
+            ("scratch-1.png", ""),
+            ("keep.png", ""),
+            ("sub/.pngmiignore", "!scratch-2.png\n"),
+            ("sub/scratch-2.png", ""),
+            ("sub/scratch-3.png", ""),
+        ]);
+        assert_eq!(found(&dir, &walk_args()), ["keep.png", "sub/scratch-2.png"]);
+    }
+
+    #[test]
+    fn test_nested_ignore_files() {
+        let dir = tree(&[
+            (".pngmiignore", "*.png\n"),
+            ("a.png", ""),
+            ("sub/.pngmiignore", "!*.png\n"),
+            ("sub/b.png", ""),
+            ("sub/deeper/.pngmiignore", "c-*.png\n"),
+            ("sub/deeper/c-1.png", ""),
+            ("sub/deeper/d.png", ""),
+        ]);
+        assert_eq!(found(&dir, &walk_args()), ["sub/b.png", "sub/deeper/d.png"]);
+    }
+
+    #[test]
+    fn test_no_ignore() {
+        let dir = tree(&[
+            (".pngmiignore", "*.png\n"),
+            ("a.png", ""),
+            ("sub/.pngmiignore", "!b.png\nc.png\n"),
+            ("sub/b.png", ""),
+            ("sub/c.png", ""),
+        ]);
+        assert_eq!(found(&dir, &walk_args()), ["sub/b.png"]);
+        let walk = WalkArgs {
+            no_ignore: true,
+            ..walk_args()
+        };
+        assert_eq!(found(&dir, &walk), ["a.png", "sub/b.png", "sub/c.png"]);
+    }
+
+    #[test]
+    fn test_ignore_patterns() {
+        let dir = tree(&[("a.png", ""), ("vendor/b.png", ""), ("sub/thumb.png", "")]);
+        let walk = WalkArgs {
+            ignore: ignore_set(&["vendor".to_string(), "thumb.png".to_string()]).unwrap(),
+            ..walk_args()
+        };
+        assert_eq!(found(&dir, &walk), ["a.png"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlink_loop() {
+        let dir = tree(&[("a.png", ""), ("sub/b.png", "")]);
+        std::os::unix::fs::symlink(dir.path(), dir.path().join("sub/up")).unwrap();
+        assert_eq!(found(&dir, &walk_args()), ["a.png", "sub/b.png"]);
+        let walk = WalkArgs {
+            follow_symlinks: true,
+            ..walk_args()
+        };
+        assert_eq!(found(&dir, &walk), ["a.png", "sub/b.png"]);
+    }
+
+    #[test]
+    fn test_read_file_list() {
+        let dir = tree(&[
+            ("list", "a.png\r\n\nsub/b c.png\n"),
+            ("list0", "a.png\0b\nc.png\0"),
+        ]);
+        let list = read_file_list(&dir.path().join("list"), false).unwrap();
+        assert_eq!(list, [Path::new("a.png"), Path::new("sub/b c.png")]);
+        let list = read_file_list(&dir.path().join("list0"), true).unwrap();
+        assert_eq!(list, [Path::new("a.png"), Path::new("b\nc.png")]);
+    }
+}
@@ -0,0 +1,116 @@
+use crate::chunk_type::ChunkType;
+use crate::message;
+use crate::png::Png;
+use crate::PngError;
+use serde_json::{json, Value};
+use std::str::FromStr;
+use wasm_bindgen::prelude::*;
+
+/// A PNG parsed from bytes and edited in memory, exposed to JavaScript as `Png`
+#[wasm_bindgen(js_name = Png)]
+pub struct WasmPng {
+    png: Png,
+}
+
+#[wasm_bindgen(js_class = Png)]
+impl WasmPng {
+    /// Parse the bytes of a PNG file, e.g. `new Png(new Uint8Array(await file.arrayBuffer()))`
+    #[wasm_bindgen(constructor)]
+    pub fn new(bytes: &[u8]) -> Result<WasmPng, JsError> {
+        let png = Png::try_from(bytes).map_err(js_error)?;
+        Ok(WasmPng { png })
+    }
+
+    /// The chunks, as an array of `{type, category, length, crc}` objects
+    pub fn chunks(&self) -> Result<JsValue, JsError> {
+        to_js(&chunks_json(&self.png))
+    }
+
+    /// Encode a text message into new chunks of the given type, encrypted if given a
+    /// passphrase, returning how many chunks it took
+    #[wasm_bindgen(js_name = encodeMessage)]
+    pub fn encode_message(
+        &mut self,
+        chunk_type: &str,
+        message: &str,
+        passphrase: Option<String>,
+    ) -> Result<usize, JsError> {
+        let chunk_type = ChunkType::from_str(chunk_type).map_err(js_error)?;
+        message::embed(&mut self.png, chunk_type, message, passphrase.as_deref()).map_err(js_error)
+    }
+
+    /// The text message stored in chunks of the given type
+    #[wasm_bindgen(js_name = decodeMessage)]
+    pub fn decode_message(
+        &self,
+        chunk_type: &str,
+        passphrase: Option<String>,
+    ) -> Result<String, JsError> {
+        decode_text(&self.png, chunk_type, passphrase.as_deref()).map_err(js_error)
+    }
+
+    /// The bytes of the PNG with any changes made to it
+    #[wasm_bindgen(js_name = toBytes)]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.png.as_bytes()
+    }
+}
+
+/// The chunks of a PNG file, as `Png.chunks` lists them, without keeping the parsed file
+#[wasm_bindgen(js_name = listChunks)]
+pub fn list_chunks(bytes: &[u8]) -> Result<JsValue, JsError> {
+    let png = Png::try_from(bytes).map_err(js_error)?;
+    to_js(&chunks_json(&png))
+}
+
+fn chunks_json(png: &Png) -> Value {
+    let chunks = png.chunks().iter().map(|chunk| {
+        json!({
+            "type": chunk.chunk_type().to_string(),
+            "category": chunk.chunk_type().category().to_string(),
+            "length": chunk.length(),
+            "crc": chunk.crc(),
+        })
+    });
+    Value::Array(chunks.collect())
+}
+
+fn decode_text(png: &Png, chunk_type: &str, passphrase: Option<&str>) -> Result<String, PngError> {
+    let envelope = message::read(png, chunk_type, passphrase)?;
+    if !envelope.is_text() {
+        return Err(format!("Payload is binary ({})", envelope.mime_type()).into());
+    }
+    Ok(String::from_utf8(envelope.into_body())?)
+}
+
+fn to_js(value: &Value) -> Result<JsValue, JsError> {
+    js_sys::JSON::parse(&value.to_string()).map_err(|_| JsError::new("Couldn't build the result"))
+}
+
+fn js_error(e: PngError) -> JsError {
+    JsError::new(&e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::minimal_png;
+
+    #[test]
+    fn test_chunks_json() {
+        let chunks = chunks_json(&minimal_png());
+        assert_eq!(chunks[0]["type"], "IHDR");
+        assert_eq!(chunks[0]["category"], "critical");
+        assert_eq!(chunks[0]["length"], 13);
+        assert_eq!(chunks[1]["type"], "IEND");
+    }
+
+    #[test]
+    fn test_decode_text() {
+        let mut png = minimal_png();
+        let chunk_type = ChunkType::from_str("ruSt").unwrap();
+        message::embed(&mut png, chunk_type, "Hello there", None).unwrap();
+        assert_eq!(decode_text(&png, "ruSt", None).unwrap(), "Hello there");
+        assert!(decode_text(&png, "noPe", None).is_err());
+    }
+}
@@ -0,0 +1,169 @@
+use crate::args::{Cli, WatchArgs};
+use clap::CommandFactory;
+use notify::event::EventKind;
+use notify::{RecursiveMode, Watcher};
+use pngmi::PngResult;
+use std::collections::HashMap;
+use std::env;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::{Duration, Instant, SystemTime};
+use tracing::{info, warn};
+
+/// How often to look for files which have gone quiet long enough to process
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// What a file looked like when the command last finished with it
+type Stamp = (SystemTime, u64);
+
+/// Run the command given by `--on-add` on every PNG added to or changed in the directory,
+/// until interrupted. Each file waits until it has gone unchanged for `--delay`, so exports
+/// still being written aren't picked up half finished.
+pub fn watch(args: WatchArgs) -> PngResult<()> {
+    let words = command_words(&args.on_add)?;
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender)?;
+    let mode = match args.recursive {
+        true => RecursiveMode::Recursive,
+        false => RecursiveMode::NonRecursive,
+    };
+    watcher.watch(&args.dir, mode)?;
+    info!(
+        "Watching {} for PNG files; press Ctrl-C to stop",
+        args.dir.display()
+    );
+
+    let delay = Duration::from_millis(args.delay);
+    let mut pending: HashMap<PathBuf, Instant> = HashMap::new();
+    // The files the command has already handled, so that files it rewrites aren't handled again
+    let mut handled: HashMap<PathBuf, Stamp> = HashMap::new();
+    loop {
+        match receiver.recv_timeout(POLL_INTERVAL) {
+            Ok(Ok(event)) if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) => {
+                for path in event.paths.into_iter().filter(|path| is_png(path)) {
+                    pending.insert(path, Instant::now());
+                }
+            }
+            Ok(Ok(_)) | Err(RecvTimeoutError::Timeout) => {}
+            Ok(Err(e)) => warn!("Watch error: {}", e),
+            Err(RecvTimeoutError::Disconnected) => return Ok(()),
+        }
+        let ready: Vec<PathBuf> = pending
+            .iter()
+            .filter(|(_, changed)| changed.elapsed() >= delay)
+            .map(|(path, _)| path.clone())
+            .collect();
+        for path in ready {
+            pending.remove(&path);
+            if !path.is_file() || stamp(&path) == handled.get(&path).copied() {
+                continue;
+            }
+            // One file the command can't be run on shouldn't stop the rest being handled
+            if let Err(e) = run_command(&words, &path) {
+                warn!("{}: couldn't run {}: {}", path.display(), words[0], e);
+                continue;
+            }
+            if let Some(stamp) = stamp(&path) {
+                handled.insert(path, stamp);
+            }
+        }
+    }
+}
+
+/// Split the command into words and check that it names a pngmi command to run
+fn command_words(command: &str) -> PngResult<Vec<String>> {
+    let words = shlex::split(command).ok_or("Unbalanced quotes in --on-add")?;
+    let Some(name) = words.first() else {
+        return Err("--on-add needs a command to run".into());
+    };
+    if name == "watch" || Cli::command().find_subcommand(name).is_none() {
+        return Err(format!("--on-add: '{}' isn't a command watch can run", name).into());
+    }
+    Ok(words)
+}
+
+/// Run pngmi on the file
+fn run_command(words: &[String], path: &Path) -> PngResult<()> {
+    info!("Running {} on {}", words[0], path.display());
+    let status = process::Command::new(env::current_exe()?)
+        .args(command_args(words, path))
+        .status()?;
+    match status.code() {
+        Some(0) => info!("{}: {} succeeded", path.display(), words[0]),
+        Some(code) => warn!(
+            "{}: {} failed with exit status {}",
+            path.display(),
+            words[0],
+            code
+        ),
+        None => warn!("{}: {} was killed", path.display(), words[0]),
+    }
+    Ok(())
+}
+
+/// The arguments to run pngmi with, where the file replaces any `{}` among the words or else
+/// follows them
+fn command_args<'a>(words: &'a [String], path: &'a Path) -> Vec<&'a OsStr> {
+    let path_arg = path.as_os_str();
+    let mut args: Vec<&OsStr> = words
+        .iter()
+        .map(|word| match word.as_str() {
+            "{}" => path_arg,
+            word => word.as_ref(),
+        })
+        .collect();
+    if !words.iter().any(|word| word == "{}") {
+        args.push(path_arg);
+    }
+    args
+}
+
+fn is_png(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("png"))
+}
+
+fn stamp(path: &Path) -> Option<Stamp> {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_command_words() {
+        let words = command_words("strip --keep 'tEXt iTXt'").unwrap();
+        assert_eq!(words, ["strip", "--keep", "tEXt iTXt"]);
+        for bad in ["", "strip 'unbalanced", "watch .", "nonsense {}"] {
+            assert!(command_words(bad).is_err(), "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn test_command_args() {
+        let path = Path::new("in dir/a.png");
+        let words = command_words("copy-meta {} {}.bak").unwrap();
+        // Only a word which is exactly {} is replaced
+        assert_eq!(
+            command_args(&words, path),
+            ["copy-meta", "in dir/a.png", "{}.bak"]
+        );
+        let words = command_words("validate --strict").unwrap();
+        assert_eq!(
+            command_args(&words, path),
+            ["validate", "--strict", "in dir/a.png"]
+        );
+    }
+
+    #[test]
+    fn test_is_png() {
+        assert!(is_png(Path::new("a.PNG")));
+        assert!(!is_png(Path::new("a.png.tmp")));
+        assert!(!is_png(Path::new("png")));
+    }
+}
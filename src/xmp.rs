@@ -0,0 +1,78 @@
+use crate::png::Png;
+use crate::text::{self, ItxtChunk, TextualChunk};
+use crate::PngResult;
+
+/// The iTXt keyword XMP packets are stored under, as used by Adobe tools and exiftool
+pub const XMP_KEYWORD: &str = "XML:com.adobe.xmp";
+
+fn is_xmp(text: &TextualChunk) -> bool {
+    matches!(text, TextualChunk::International(itxt) if itxt.keyword() == XMP_KEYWORD)
+}
+
+/// Return the XMP packet stored in the PNG, if there is one
+pub fn get_xmp(png: &Png) -> PngResult<Option<String>> {
+    for text in text::text_chunks(png) {
+        let text = text?;
+        if is_xmp(&text) {
+            return Ok(Some(text.text().to_string()));
+        }
+    }
+    Ok(None)
+}
+
+/// Store an XMP packet, replacing any existing one. The packet is left uncompressed, as the
+/// XMP spec recommends, so that tools which scan files for packets can still find it.
+pub fn set_xmp(png: &mut Png, packet: &str) -> PngResult<()> {
+    if !packet.contains("<x:xmpmeta") {
+        return Err("XMP packet has no x:xmpmeta element".into());
+    }
+    text::set_text(png, ItxtChunk::new(XMP_KEYWORD, packet)?)
+}
+
+/// Remove every XMP packet from the PNG, returning how many were removed
+pub fn remove_xmp(png: &mut Png) -> usize {
+    png.retain_chunks(|chunk| {
+        chunk.chunk_type().to_string() != ItxtChunk::CHUNK_TYPE
+            || !TextualChunk::try_from(chunk).is_ok_and(|text| is_xmp(&text))
+    })
+    .len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+    use crate::chunk_type::ChunkType;
+    use std::str::FromStr;
+
+    const PACKET: &str = r#"<?xpacket begin="" id="W5M0MpCehiHzreSzNTczkc9d"?>
+<x:xmpmeta xmlns:x="adobe:ns:meta/"><rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#"/></x:xmpmeta>
+<?xpacket end="w"?>"#;
+
+    fn testing_png() -> Png {
+        let chunks = ["IHDR", "IDAT", "IEND"]
+            .iter()
+            .map(|t| Chunk::new(ChunkType::from_str(t).unwrap(), vec![]))
+            .collect();
+        Png::from_chunks(chunks)
+    }
+
+    #[test]
+    fn test_xmp_round_trip() {
+        let mut png = testing_png();
+        assert_eq!(get_xmp(&png).unwrap(), None);
+        set_xmp(&mut png, PACKET).unwrap();
+        set_xmp(&mut png, PACKET).unwrap();
+        assert_eq!(get_xmp(&png).unwrap().as_deref(), Some(PACKET));
+        assert_eq!(png.chunks_by_type("iTXt").count(), 1);
+
+        assert_eq!(remove_xmp(&mut png), 1);
+        assert_eq!(get_xmp(&png).unwrap(), None);
+        assert_eq!(png.chunks().len(), 3);
+    }
+
+    #[test]
+    fn test_not_xmp() {
+        assert!(set_xmp(&mut testing_png(), "<html/>").is_err());
+    }
+}
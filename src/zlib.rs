@@ -0,0 +1,299 @@
+use crate::error::{error, ErrorKind};
+use crate::PngResult;
+use flate2::read::ZlibDecoder;
+use flate2::{Decompress, FlushDecompress, Status};
+use std::fmt::{Display, Formatter};
+use std::io::Read;
+
+/// The most data inflated from a stream whose size isn't otherwise known, so that a small
+/// chunk can't keep the tool busy inflating gigabytes or make it allocate them
+pub const MAX_INFLATED_LEN: u64 = 256 * 1024 * 1024;
+
+/// The largest prime below 2^16, which both Adler-32 sums are taken modulo
+const ADLER_MODULUS: u32 = 65521;
+
+/// The most bytes which can be summed before the Adler-32 sums could overflow a u32
+const ADLER_BLOCK_LEN: usize = 5552;
+
+/// A way in which a zlib stream is broken
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ZlibProblem {
+    /// The two-byte header is missing or holds values PNG doesn't allow
+    Header(String),
+    /// The deflate data is corrupt or stops before its final block
+    Stream(String),
+    /// The Adler-32 checksum after the deflate data doesn't match what it inflates to
+    Checksum { stored: u32, computed: u32 },
+}
+
+impl Display for ZlibProblem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ZlibProblem::Header(message) | ZlibProblem::Stream(message) => f.write_str(message),
+            ZlibProblem::Checksum { stored, computed } => write!(
+                f,
+                "Adler-32 checksum is {:#010x} but the data sums to {:#010x}",
+                stored, computed
+            ),
+        }
+    }
+}
+
+/// How far `check` got through a stream with nothing wrong in it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Inflated {
+    /// The whole stream inflated to this many bytes
+    Complete(u64),
+    /// The stream inflates to more than the limit, so checking stopped there
+    OverLimit,
+}
+
+/// Inflate a zlib stream without keeping the output, checking its header, its deflate data
+/// and its Adler-32 checksum. At most `limit` bytes are inflated.
+pub fn check(data: &[u8], limit: u64) -> Result<Inflated, ZlibProblem> {
+    check_with(data, limit, None)
+}
+
+/// Like `check`, also appending what the stream inflates to onto `output`, up to `limit`
+/// bytes. Whatever inflated before a problem was found is kept.
+pub fn check_into(data: &[u8], limit: u64, output: &mut Vec<u8>) -> Result<Inflated, ZlibProblem> {
+    check_with(data, limit, Some(output))
+}
+
+fn check_with(
+    data: &[u8],
+    limit: u64,
+    mut output: Option<&mut Vec<u8>>,
+) -> Result<Inflated, ZlibProblem> {
+    let (header, deflate) = match data {
+        [cmf, flg, rest @ ..] => ([*cmf, *flg], rest),
+        _ => return Err(ZlibProblem::Header("zlib stream is too short".to_string())),
+    };
+    check_header(header)?;
+
+    let mut inflater = Decompress::new(false);
+    let mut buffer = vec![0; 32 * 1024];
+    let mut adler = 1;
+    loop {
+        let (before_in, before_out) = (inflater.total_in(), inflater.total_out());
+        let status = inflater
+            .decompress(
+                &deflate[before_in as usize..],
+                &mut buffer,
+                FlushDecompress::None,
+            )
+            .map_err(|e| ZlibProblem::Stream(format!("Deflate data is corrupt: {}", e)))?;
+        let produced = (inflater.total_out() - before_out) as usize;
+        adler = update_adler32(adler, &buffer[..produced]);
+        if let Some(output) = output.as_deref_mut() {
+            let room = limit.saturating_sub(before_out) as usize;
+            output.extend_from_slice(&buffer[..produced.min(room)]);
+        }
+        if inflater.total_out() > limit {
+            return Ok(Inflated::OverLimit);
+        }
+        match status {
+            Status::StreamEnd => break,
+            _ if produced == 0 && inflater.total_in() == before_in => {
+                let message = "Deflate data ends before its final block".to_string();
+                return Err(ZlibProblem::Stream(message));
+            }
+            _ => {}
+        }
+    }
+
+    let rest = &deflate[inflater.total_in() as usize..];
+    let Some(stored) = rest.get(..4) else {
+        let message = "zlib stream ends before its Adler-32 checksum".to_string();
+        return Err(ZlibProblem::Stream(message));
+    };
+    let stored = u32::from_be_bytes([stored[0], stored[1], stored[2], stored[3]]);
+    if stored != adler {
+        return Err(ZlibProblem::Checksum {
+            stored,
+            computed: adler,
+        });
+    }
+    Ok(Inflated::Complete(inflater.total_out()))
+}
+
+/// Check the CMF and FLG bytes: deflate with a window of at most 32K, a valid check value
+/// and no preset dictionary, which PNG doesn't allow
+fn check_header([cmf, flg]: [u8; 2]) -> Result<(), ZlibProblem> {
+    let problem = if cmf & 0x0f != 8 {
+        format!("zlib compression method is {} rather than 8", cmf & 0x0f)
+    } else if cmf >> 4 > 7 {
+        format!(
+            "zlib window size {} is larger than 32K",
+            1u64 << ((cmf >> 4) + 8)
+        )
+    } else if (u16::from(cmf) << 8 | u16::from(flg)) % 31 != 0 {
+        format!(
+            "zlib header check bits are wrong in {:#04x} {:#04x}",
+            cmf, flg
+        )
+    } else if flg & 0x20 != 0 {
+        "zlib stream asks for a preset dictionary".to_string()
+    } else {
+        return Ok(());
+    };
+    Err(ZlibProblem::Header(problem))
+}
+
+/// Add `data` to a running Adler-32 checksum, which starts at 1
+fn update_adler32(adler: u32, data: &[u8]) -> u32 {
+    let (mut a, mut b) = (adler & 0xffff, adler >> 16);
+    for block in data.chunks(ADLER_BLOCK_LEN) {
+        for &byte in block {
+            a += u32::from(byte);
+            b += a;
+        }
+        a %= ADLER_MODULUS;
+        b %= ADLER_MODULUS;
+    }
+    b << 16 | a
+}
+
+/// Inflate a whole zlib stream, failing if it's corrupt or inflates to more than `limit`
+/// bytes. Payloads, text and ICC profiles are all inflated through here.
+pub fn inflate(data: &[u8], limit: u64) -> PngResult<Vec<u8>> {
+    inflate_into(data, limit, vec![])
+}
+
+/// `inflate` into `buffer`, for callers which know how much to reserve up front
+pub(crate) fn inflate_into(data: &[u8], limit: u64, mut buffer: Vec<u8>) -> PngResult<Vec<u8>> {
+    ZlibDecoder::new(data)
+        .take(limit.saturating_add(1))
+        .read_to_end(&mut buffer)
+        .map_err(|e| error(ErrorKind::Parse, format!("zlib stream is corrupt: {}", e)))?;
+    if buffer.len() as u64 > limit {
+        return Err(error(
+            ErrorKind::Validation,
+            format!("zlib stream inflates to more than {} bytes", limit),
+        ));
+    }
+    Ok(buffer)
+}
+
+/// Inflate a zlib stream, keeping at most `limit` bytes and whatever could be read of a
+/// corrupt one
+pub fn inflate_lossy(data: &[u8], limit: u64) -> Vec<u8> {
+    let mut inflated = vec![];
+    let _ = ZlibDecoder::new(data)
+        .take(limit)
+        .read_to_end(&mut inflated);
+    inflated
+}
+
+/// The zlib stream inside a zTXt, compressed iTXt or iCCP chunk, after the keyword or profile
+/// name and the compression fields
+pub fn compressed_stream<'a>(chunk_type: &[u8; 4], data: &'a [u8]) -> Option<&'a [u8]> {
+    let rest = &data[data.iter().position(|&byte| byte == 0)? + 1..];
+    match chunk_type {
+        b"zTXt" | b"iCCP" => rest.get(1..),
+        b"iTXt" => match rest {
+            [1, _, rest @ ..] => {
+                let language_end = rest.iter().position(|&byte| byte == 0)?;
+                let rest = &rest[language_end + 1..];
+                Some(&rest[rest.iter().position(|&byte| byte == 0)? + 1..])
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::ZlibEncoder;
+    use std::io::Write;
+
+    fn compress(data: &[u8]) -> Vec<u8> {
+        let mut encoder = ZlibEncoder::new(vec![], flate2::Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn test_adler32() {
+        assert_eq!(update_adler32(1, b"Wikipedia"), 0x11e60398);
+        let data = vec![0xff; 100_000];
+        let split = update_adler32(update_adler32(1, &data[..7]), &data[7..]);
+        assert_eq!(split, update_adler32(1, &data));
+    }
+
+    #[test]
+    fn test_check() {
+        let data = b"the quick brown fox ".repeat(1000);
+        let stream = compress(&data);
+        let len = data.len() as u64;
+        assert_eq!(check(&stream, len), Ok(Inflated::Complete(len)));
+        assert_eq!(check(&stream, len - 1), Ok(Inflated::OverLimit));
+
+        let mut bad = stream.clone();
+        bad[1] ^= 1;
+        assert!(matches!(check(&bad, len), Err(ZlibProblem::Header(_))));
+        assert!(matches!(check(&[0x78], len), Err(ZlibProblem::Header(_))));
+
+        let mut bad = stream.clone();
+        let last = bad.len() - 1;
+        bad[last] ^= 1;
+        assert!(matches!(
+            check(&bad, len),
+            Err(ZlibProblem::Checksum { .. })
+        ));
+        let truncated = &stream[..stream.len() - 2];
+        assert!(matches!(check(truncated, len), Err(ZlibProblem::Stream(_))));
+        let truncated = &stream[..stream.len() / 2];
+        assert!(matches!(check(truncated, len), Err(ZlibProblem::Stream(_))));
+    }
+
+    #[test]
+    fn test_check_into() {
+        let data = b"the quick brown fox ".repeat(1000);
+        let stream = compress(&data);
+        let len = data.len() as u64;
+        let mut output = vec![];
+        assert_eq!(
+            check_into(&stream, len, &mut output),
+            Ok(Inflated::Complete(len))
+        );
+        assert_eq!(output, data);
+
+        let mut output = vec![];
+        assert_eq!(
+            check_into(&stream, 10, &mut output),
+            Ok(Inflated::OverLimit)
+        );
+        assert_eq!(output, data[..10]);
+        let mut bad = stream.clone();
+        let last = bad.len() - 1;
+        bad[last] ^= 1;
+        let mut output = vec![];
+        assert!(check_into(&bad, len, &mut output).is_err());
+        assert_eq!(output, data);
+    }
+
+    #[test]
+    fn test_inflate() {
+        let data = vec![0; 100_000];
+        let stream = compress(&data);
+        assert_eq!(inflate(&stream, 100_000).unwrap(), data);
+        let e = inflate(&stream, 99_999).unwrap_err();
+        assert_eq!(crate::error::kind_of(&*e), ErrorKind::Validation);
+        assert!(inflate(&stream[..stream.len() / 2], 100_000).is_err());
+    }
+
+    #[test]
+    fn test_compressed_stream() {
+        assert_eq!(compressed_stream(b"zTXt", b"key\0\0zz"), Some(&b"zz"[..]));
+        assert_eq!(compressed_stream(b"iCCP", b"sRGB\0\0zz"), Some(&b"zz"[..]));
+        assert_eq!(
+            compressed_stream(b"iTXt", b"key\0\x01\0en\0Key\0zz"),
+            Some(&b"zz"[..])
+        );
+        assert_eq!(compressed_stream(b"iTXt", b"key\0\0\0en\0Key\0text"), None);
+        assert_eq!(compressed_stream(b"tEXt", b"key\0text"), None);
+    }
+}